@@ -0,0 +1,7 @@
+// Compiles the gRPC API's proto files. protox parses them, so protoc need not be installed.
+fn main() -> Result<(), Box<dyn std::error::Error>> {
+    println!("cargo:rerun-if-changed=proto");
+    let files = protox::compile(["alya/v1/alya.proto"], ["proto"])?;
+    tonic_prost_build::configure().build_client(false).compile_fds(files)?;
+    Ok(())
+}
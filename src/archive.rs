@@ -0,0 +1,174 @@
+use crate::knowledge::{Knowledge, SCHEMA_VERSION};
+use crate::{CharacterConfig, KnowledgeSources};
+use chrono::{DateTime, Utc};
+use flate2::read::GzDecoder;
+use flate2::write::GzEncoder;
+use flate2::Compression;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::fs;
+use std::io::{Read, Write};
+use std::path::Path;
+
+const FORMAT: &str = "alya-character-archive";
+const FORMAT_VERSION: u32 = 1;
+
+/// The files an archive holds; anything else in it is refused.
+const FILES: [&str; 5] = ["manifest.json", "character.json", "knowledge_sources.json", "knowledge.json", "sessions.json"];
+/// Largest file read from an archive, so a crafted one cannot fill the memory.
+const MAX_FILE_BYTES: u64 = 512 * 1024 * 1024;
+/// Largest amount read from an archive across its files.
+const MAX_TOTAL_BYTES: u64 = 1024 * 1024 * 1024;
+
+/// Describes an archive; stored as `manifest.json`.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct Manifest {
+    pub format: String,
+    pub format_version: u32,
+    pub exported_at: DateTime<Utc>,
+    pub character_name: String,
+    pub schema_version: u32,
+    pub fact_count: usize,
+}
+
+/// A trained character packed into a single `.tar.gz` file: its configuration, learned
+/// knowledge including embeddings, and conversation sessions.
+pub struct CharacterArchive {
+    pub manifest: Manifest,
+    pub character: CharacterConfig,
+    pub knowledge_sources: KnowledgeSources,
+    pub knowledge: Knowledge,
+    pub sessions: HashMap<String, Vec<String>>,
+}
+
+impl CharacterArchive {
+    pub fn new(
+        character: CharacterConfig,
+        knowledge_sources: KnowledgeSources,
+        knowledge: Knowledge,
+        sessions: HashMap<String, Vec<String>>,
+    ) -> Self {
+        CharacterArchive {
+            manifest: Manifest {
+                format: FORMAT.to_string(),
+                format_version: FORMAT_VERSION,
+                exported_at: Utc::now(),
+                character_name: character.name.clone(),
+                schema_version: SCHEMA_VERSION,
+                fact_count: knowledge.facts.len(),
+            },
+            character,
+            knowledge_sources,
+            knowledge,
+            sessions,
+        }
+    }
+
+    pub fn write(&self, path: &Path) -> Result<(), Box<dyn std::error::Error>> {
+        fs::write(path, self.to_bytes()?)?;
+        Ok(())
+    }
+
+    pub fn read(path: &Path) -> Result<Self, Box<dyn std::error::Error>> {
+        Self::from_bytes(&fs::read(path)?)
+    }
+
+    pub fn to_bytes(&self) -> Result<Vec<u8>, Box<dyn std::error::Error>> {
+        let mut builder = tar::Builder::new(GzEncoder::new(Vec::new(), Compression::default()));
+        append_json(&mut builder, "manifest.json", &self.manifest)?;
+        append_json(&mut builder, "character.json", &self.character)?;
+        append_json(&mut builder, "knowledge_sources.json", &self.knowledge_sources)?;
+        append_json(&mut builder, "knowledge.json", &self.knowledge)?;
+        append_json(&mut builder, "sessions.json", &self.sessions)?;
+        Ok(builder.into_inner()?.finish()?)
+    }
+
+    pub fn from_bytes(bytes: &[u8]) -> Result<Self, Box<dyn std::error::Error>> {
+        let mut archive = tar::Archive::new(GzDecoder::new(bytes));
+        let mut files = HashMap::new();
+        let mut total = 0;
+        for entry in archive.entries()? {
+            let entry = entry?;
+            let name = entry.path()?.to_string_lossy().to_string();
+            if !FILES.contains(&name.as_str()) || files.contains_key(&name) {
+                return Err(format!("Archive has an unexpected file {}", name).into());
+            }
+            // The header's size can't be trusted, so the read itself is bounded
+            let limit = MAX_FILE_BYTES.min(MAX_TOTAL_BYTES - total);
+            let mut contents = String::new();
+            entry.take(limit + 1).read_to_string(&mut contents)?;
+            if contents.len() as u64 > limit {
+                return Err(format!("Archive file {} is too large; the limit is {} MiB per file and {} MiB in all", name, MAX_FILE_BYTES >> 20, MAX_TOTAL_BYTES >> 20).into());
+            }
+            total += contents.len() as u64;
+            files.insert(name, contents);
+        }
+        let mut file = |name: &str| files.remove(name).ok_or_else(|| format!("Archive is missing {}", name));
+
+        let manifest: Manifest = serde_json::from_str(&file("manifest.json")?)?;
+        if manifest.format != FORMAT || manifest.format_version > FORMAT_VERSION {
+            return Err(format!(
+                "Unsupported archive format {} version {}",
+                manifest.format, manifest.format_version
+            )
+            .into());
+        }
+
+        Ok(CharacterArchive {
+            character: serde_json::from_str(&file("character.json")?)?,
+            knowledge_sources: serde_json::from_str(&file("knowledge_sources.json")?)?,
+            // Archives from older versions go through the usual schema upgrades
+            knowledge: Knowledge::from_json(serde_json::from_str(&file("knowledge.json")?)?)?,
+            sessions: serde_json::from_str(&file("sessions.json")?)?,
+            manifest,
+        })
+    }
+}
+
+fn append_json<W: Write, T: Serialize>(
+    builder: &mut tar::Builder<W>,
+    name: &str,
+    value: &T,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let data = serde_json::to_vec_pretty(value)?;
+    let mut header = tar::Header::new_gnu();
+    header.set_size(data.len() as u64);
+    header.set_mode(0o644);
+    header.set_mtime(Utc::now().timestamp().max(0) as u64);
+    header.set_cksum();
+    builder.append_data(&mut header, name, data.as_slice())?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn archive_of(files: &[(&str, serde_json::Value)]) -> Vec<u8> {
+        let mut builder = tar::Builder::new(GzEncoder::new(Vec::new(), Compression::default()));
+        for (name, value) in files {
+            append_json(&mut builder, name, value).unwrap();
+        }
+        builder.into_inner().unwrap().finish().unwrap()
+    }
+
+    fn error_of(bytes: &[u8]) -> String {
+        match CharacterArchive::from_bytes(bytes) {
+            Ok(_) => panic!("the archive was read"),
+            Err(e) => e.to_string(),
+        }
+    }
+
+    #[test]
+    fn unexpected_files_are_refused() {
+        let bytes = archive_of(&[("manifest.json", serde_json::json!({})), ("notes.json", serde_json::json!("hello"))]);
+        assert_eq!(error_of(&bytes), "Archive has an unexpected file notes.json");
+        let bytes = archive_of(&[("sessions.json", serde_json::json!({})), ("sessions.json", serde_json::json!({}))]);
+        assert_eq!(error_of(&bytes), "Archive has an unexpected file sessions.json");
+    }
+
+    #[test]
+    fn missing_files_are_named() {
+        assert_eq!(error_of(&archive_of(&[("sessions.json", serde_json::json!({}))])), "Archive is missing manifest.json");
+    }
+}
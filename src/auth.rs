@@ -0,0 +1,281 @@
+use base64::engine::general_purpose::URL_SAFE_NO_PAD;
+use base64::Engine;
+use chrono::{DateTime, Utc};
+use hmac::{Hmac, Mac};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use sha2::{Digest, Sha256};
+use std::fs;
+use std::path::{Path, PathBuf};
+
+const KEYS_FILE: &str = "api_keys.json";
+/// What API keys start with, so they are told apart from JWTs and other secrets.
+const KEY_PREFIX: &str = "alya_";
+
+/// The secret JWTs for the API are signed with (HS256).
+pub const JWT_SECRET_NAME: &str = "ALYA_JWT_SECRET";
+
+/// What an API caller may do.
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum Scope {
+    /// Chat and read the character.
+    Chat,
+    /// Everything, including managing the knowledge and learning.
+    Admin,
+}
+
+impl Scope {
+    pub fn parse(s: &str) -> Option<Scope> {
+        match s.trim().to_lowercase().as_str() {
+            "chat" => Some(Scope::Chat),
+            "admin" => Some(Scope::Admin),
+            _ => None,
+        }
+    }
+
+    pub fn allows(self, needed: Scope) -> bool {
+        self == Scope::Admin || needed == Scope::Chat
+    }
+}
+
+/// An API key, of which only the hash is kept.
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct ApiKey {
+    pub name: String,
+    hash: String,
+    pub scope: Scope,
+    pub created_at: DateTime<Utc>,
+}
+
+/// Who made an API request, by the name of their key or the subject of their JWT.
+#[derive(Debug, Clone)]
+pub struct Caller {
+    pub name: String,
+    pub scope: Scope,
+}
+
+/// The API keys of the HTTP API, kept in `api_keys.json` in the data directory.
+pub struct ApiKeys {
+    path: PathBuf,
+    keys: Vec<ApiKey>,
+}
+
+impl ApiKeys {
+    pub fn load(dir: &Path) -> Result<Self, Box<dyn std::error::Error>> {
+        let path = dir.join(KEYS_FILE);
+        let keys = match fs::read_to_string(&path) {
+            Ok(text) => serde_json::from_str(&text)?,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => Vec::new(),
+            Err(e) => return Err(e.into()),
+        };
+        Ok(ApiKeys { path, keys })
+    }
+
+    pub fn keys(&self) -> &[ApiKey] {
+        &self.keys
+    }
+
+    /// Creates a key and returns it; it cannot be shown again, since only its hash is saved.
+    pub fn create(&mut self, name: &str, scope: Scope) -> Result<String, Box<dyn std::error::Error>> {
+        if self.keys.iter().any(|key| key.name == name) {
+            return Err(format!("There already is a key called {}", name).into());
+        }
+        let key = format!("{}{}", KEY_PREFIX, crate::storage::generate_key());
+        self.keys.push(ApiKey {
+            name: name.to_string(),
+            hash: hash(&key),
+            scope,
+            created_at: Utc::now(),
+        });
+        self.save()?;
+        Ok(key)
+    }
+
+    /// Deletes a key. Returns false if there is none by that name.
+    pub fn revoke(&mut self, name: &str) -> Result<bool, Box<dyn std::error::Error>> {
+        let count = self.keys.len();
+        self.keys.retain(|key| key.name != name);
+        if self.keys.len() == count {
+            return Ok(false);
+        }
+        self.save()?;
+        Ok(true)
+    }
+
+    fn save(&self) -> Result<(), Box<dyn std::error::Error>> {
+        if let Some(dir) = self.path.parent() {
+            fs::create_dir_all(dir)?;
+        }
+        fs::write(&self.path, serde_json::to_string_pretty(&self.keys)?)?;
+        Ok(())
+    }
+}
+
+/// Checks the credentials of API requests: the API keys, and JWTs when a secret for them
+/// is set.
+pub struct Auth {
+    keys: Vec<ApiKey>,
+    jwt_secret: Option<String>,
+}
+
+impl Auth {
+    pub fn new(keys: &ApiKeys, jwt_secret: Option<String>) -> Self {
+        Auth {
+            keys: keys.keys.clone(),
+            jwt_secret,
+        }
+    }
+
+    /// Whether any credentials could pass.
+    pub fn is_configured(&self) -> bool {
+        !self.keys.is_empty() || self.jwt_secret.is_some()
+    }
+
+    /// Who `token`, an API key or a JWT, belongs to, if it is valid.
+    pub fn authenticate(&self, token: &str) -> Option<Caller> {
+        if token.starts_with(KEY_PREFIX) {
+            let hash = hash(token);
+            return self.keys.iter().find(|key| key.hash == hash).map(|key| Caller {
+                name: key.name.clone(),
+                scope: key.scope,
+            });
+        }
+        verify_jwt(token, self.jwt_secret.as_deref()?)
+    }
+}
+
+/// Checks an HS256 JWT and reads the caller from its `sub` and `scope` claims. The scope
+/// defaults to chat; a token past its `exp` is refused.
+fn verify_jwt(token: &str, secret: &str) -> Option<Caller> {
+    let mut parts = token.split('.');
+    let (header, payload, signature) = (parts.next()?, parts.next()?, parts.next()?);
+    if parts.next().is_some() {
+        return None;
+    }
+    let header: Value = serde_json::from_slice(&URL_SAFE_NO_PAD.decode(header).ok()?).ok()?;
+    if header["alg"] != "HS256" {
+        return None;
+    }
+    let mut mac = Hmac::<Sha256>::new_from_slice(secret.as_bytes()).ok()?;
+    // The header and payload are what is signed
+    mac.update(&token.as_bytes()[..token.len() - signature.len() - 1]);
+    mac.verify_slice(&URL_SAFE_NO_PAD.decode(signature).ok()?).ok()?;
+
+    let claims: Value = serde_json::from_slice(&URL_SAFE_NO_PAD.decode(payload).ok()?).ok()?;
+    if claims["exp"].as_i64().is_some_and(|exp| exp <= Utc::now().timestamp()) {
+        return None;
+    }
+    let scope = match claims["scope"].as_str() {
+        Some(scope) => Scope::parse(scope)?,
+        None => Scope::Chat,
+    };
+    Some(Caller {
+        name: claims["sub"].as_str()?.to_string(),
+        scope,
+    })
+}
+
+fn hash(key: &str) -> String {
+    format!("{:x}", Sha256::digest(key.as_bytes()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    const SECRET: &str = "test-secret";
+
+    fn jwt(header: Value, claims: Value, secret: &str) -> String {
+        let signed = format!(
+            "{}.{}",
+            URL_SAFE_NO_PAD.encode(header.to_string()),
+            URL_SAFE_NO_PAD.encode(claims.to_string())
+        );
+        let mut mac = Hmac::<Sha256>::new_from_slice(secret.as_bytes()).unwrap();
+        mac.update(signed.as_bytes());
+        format!("{}.{}", signed, URL_SAFE_NO_PAD.encode(mac.finalize().into_bytes()))
+    }
+
+    fn hs256() -> Value {
+        json!({ "alg": "HS256", "typ": "JWT" })
+    }
+
+    #[test]
+    fn jwt_with_valid_signature_is_accepted() {
+        let token = jwt(hs256(), json!({ "sub": "frontend", "scope": "admin" }), SECRET);
+        let caller = verify_jwt(&token, SECRET).unwrap();
+        assert_eq!(caller.name, "frontend");
+        assert_eq!(caller.scope, Scope::Admin);
+    }
+
+    #[test]
+    fn jwt_scope_defaults_to_chat() {
+        let token = jwt(hs256(), json!({ "sub": "frontend" }), SECRET);
+        assert_eq!(verify_jwt(&token, SECRET).unwrap().scope, Scope::Chat);
+    }
+
+    #[test]
+    fn jwt_with_unknown_scope_is_refused() {
+        let token = jwt(hs256(), json!({ "sub": "frontend", "scope": "root" }), SECRET);
+        assert!(verify_jwt(&token, SECRET).is_none());
+    }
+
+    #[test]
+    fn jwt_signed_with_another_secret_is_refused() {
+        let token = jwt(hs256(), json!({ "sub": "frontend" }), "another-secret");
+        assert!(verify_jwt(&token, SECRET).is_none());
+    }
+
+    #[test]
+    fn jwt_with_changed_claims_is_refused() {
+        let token = jwt(hs256(), json!({ "sub": "frontend" }), SECRET);
+        let parts: Vec<&str> = token.split('.').collect();
+        let forged = URL_SAFE_NO_PAD.encode(json!({ "sub": "frontend", "scope": "admin" }).to_string());
+        let token = format!("{}.{}.{}", parts[0], forged, parts[2]);
+        assert!(verify_jwt(&token, SECRET).is_none());
+    }
+
+    #[test]
+    fn jwt_with_other_algorithm_is_refused() {
+        let none = jwt(json!({ "alg": "none" }), json!({ "sub": "frontend" }), SECRET);
+        assert!(verify_jwt(&none, SECRET).is_none());
+        let unsigned = format!("{}.", none.rsplit_once('.').unwrap().0);
+        assert!(verify_jwt(&unsigned, SECRET).is_none());
+        let hs512 = jwt(json!({ "alg": "HS512" }), json!({ "sub": "frontend" }), SECRET);
+        assert!(verify_jwt(&hs512, SECRET).is_none());
+    }
+
+    #[test]
+    fn jwt_past_its_expiry_is_refused() {
+        let expired = jwt(hs256(), json!({ "sub": "frontend", "exp": Utc::now().timestamp() - 60 }), SECRET);
+        assert!(verify_jwt(&expired, SECRET).is_none());
+        let valid = jwt(hs256(), json!({ "sub": "frontend", "exp": Utc::now().timestamp() + 60 }), SECRET);
+        assert!(verify_jwt(&valid, SECRET).is_some());
+    }
+
+    #[test]
+    fn malformed_jwt_is_refused() {
+        for token in ["", "a.b", "a.b.c.d", "not base64.at.all"] {
+            assert!(verify_jwt(token, SECRET).is_none(), "{}", token);
+        }
+        let without_subject = jwt(hs256(), json!({ "scope": "chat" }), SECRET);
+        assert!(verify_jwt(&without_subject, SECRET).is_none());
+    }
+
+    #[test]
+    fn admin_scope_allows_everything_and_chat_only_chat() {
+        assert!(Scope::Admin.allows(Scope::Admin));
+        assert!(Scope::Admin.allows(Scope::Chat));
+        assert!(Scope::Chat.allows(Scope::Chat));
+        assert!(!Scope::Chat.allows(Scope::Admin));
+    }
+
+    #[test]
+    fn scopes_parse_in_any_case() {
+        assert_eq!(Scope::parse(" Admin "), Some(Scope::Admin));
+        assert_eq!(Scope::parse("chat"), Some(Scope::Chat));
+        assert_eq!(Scope::parse("read"), None);
+    }
+}
@@ -0,0 +1,389 @@
+use crate::ingest::LoreEntry;
+use crate::{CharacterConfig, Example};
+use base64::Engine;
+use flate2::write::ZlibEncoder;
+use flate2::{Compression, Crc};
+use serde_json::{json, Value};
+use std::fs;
+use std::io::Write;
+use std::path::Path;
+
+/// The first bytes of every PNG file.
+const PNG_SIGNATURE: &[u8] = b"\x89PNG\r\n\x1a\n";
+/// What `{{user}}` becomes in the card's descriptions, and in its dialogue.
+const USER_DESCRIBED: &str = "the user";
+const USER_ADDRESSED: &str = "you";
+/// The size and color of the picture of a PNG card made without one.
+const PLAIN_IMAGE: (u32, u32, [u8; 3]) = (400, 600, [0xe8, 0xe4, 0xf0]);
+
+/// A SillyTavern or TavernAI character card: a JSON file, or a PNG image carrying the JSON
+/// in a `chara` (or, for version 3, `ccv3`) text chunk. Version 1 cards have their fields at
+/// the top level; version 2 and 3 cards have them under `data`.
+pub struct Card {
+    pub character: CharacterConfig,
+    /// The situation the chats take place in.
+    pub scenario: String,
+    /// The entries of the card's embedded lorebook (`character_book`).
+    pub lore: Vec<LoreEntry>,
+}
+
+impl Card {
+    pub fn read(path: &Path) -> Result<Self, Box<dyn std::error::Error>> {
+        Self::from_bytes(&fs::read(path)?)
+    }
+
+    /// The card in the contents of a JSON or PNG file.
+    pub fn from_bytes(bytes: &[u8]) -> Result<Self, Box<dyn std::error::Error>> {
+        let json = match is_png(bytes) {
+            true => png_card(bytes)?,
+            false => bytes.to_vec(),
+        };
+        let card: Value = serde_json::from_slice(&json).map_err(|e| format!("Not a character card: {}", e))?;
+        Self::parse(&card)
+    }
+
+    pub fn parse(card: &Value) -> Result<Self, Box<dyn std::error::Error>> {
+        let data = if card["data"].is_object() { &card["data"] } else { card };
+        let field = |key: &str| data[key].as_str().unwrap_or_default().trim().to_string();
+        let name = field("name");
+        if name.is_empty() {
+            return Err("The card has no character name".into());
+        }
+        let described = |key: &str| fill(&field(key), &name, USER_DESCRIBED);
+
+        let personality = described("personality");
+        // A personality given as a list of short words doubles as the traits
+        let items: Vec<String> = personality.split(',').map(|item| item.trim().to_string()).filter(|item| !item.is_empty()).collect();
+        let traits = match items.len() > 1 && items.iter().all(|item| item.split_whitespace().count() <= 3) {
+            true => items,
+            false => Vec::new(),
+        };
+        let greeting = fill(&field("first_mes"), &name, USER_ADDRESSED);
+        let character = CharacterConfig {
+            description: described("description"),
+            personality,
+            traits,
+            interests: Vec::new(),
+            examples: examples(&field("mes_example"), &name),
+            greeting: (!greeting.is_empty()).then_some(greeting),
+            name: name.clone(),
+        };
+
+        let entries = data["character_book"]["entries"].as_array().into_iter().flatten();
+        let lore = entries
+            .filter(|entry| entry["enabled"] != false)
+            .enumerate()
+            .filter_map(|(index, entry)| {
+                let text = fill(entry["content"].as_str().unwrap_or_default().trim(), &name, USER_DESCRIBED);
+                let keywords: Vec<String> = entry["keys"].as_array().into_iter().flatten().filter_map(Value::as_str).map(str::to_string).collect();
+                let title = [&entry["name"], &entry["comment"]]
+                    .into_iter()
+                    .filter_map(Value::as_str)
+                    .find(|title| !title.trim().is_empty())
+                    .map(str::to_string)
+                    .or_else(|| keywords.first().cloned())
+                    .unwrap_or_else(|| format!("{} {}", name, index + 1));
+                (!text.is_empty()).then(|| LoreEntry { title: title.trim().to_string(), tags: Vec::new(), keywords, text })
+            })
+            .collect();
+        Ok(Card {
+            character,
+            scenario: described("scenario"),
+            lore,
+        })
+    }
+}
+
+impl Card {
+    /// The card in the version 2 format (`chara_card_v2`), which SillyTavern and most other
+    /// tools read.
+    pub fn to_json(&self) -> Value {
+        let character = &self.character;
+        // Traits the personality already lists, as an imported card's do, are not repeated
+        let listed = character.traits.iter().all(|t| character.personality.to_lowercase().contains(&t.to_lowercase()));
+        let personality = match (character.personality.is_empty(), character.traits.is_empty() || listed) {
+            (_, true) => character.personality.clone(),
+            (true, false) => character.traits.join(", "),
+            (false, false) => format!("{}. Traits: {}", character.personality.trim_end_matches('.'), character.traits.join(", ")),
+        };
+        let mut description = character.description.clone();
+        if !character.interests.is_empty() {
+            description.push_str(&format!("\n\nInterests: {}", character.interests.join(", ")));
+        }
+        let examples: String = character
+            .examples
+            .iter()
+            .map(|example| format!("<START>\n{{{{user}}}}: {}\n{{{{char}}}}: {}\n", example.user, example.reply))
+            .collect();
+        let entries: Vec<Value> = self
+            .lore
+            .iter()
+            .enumerate()
+            .map(|(index, entry)| {
+                json!({
+                    "id": index,
+                    "keys": entry.keywords,
+                    "content": entry.text,
+                    "name": entry.title,
+                    "comment": entry.title,
+                    "enabled": true,
+                    "insertion_order": index,
+                    "case_sensitive": false,
+                    "extensions": {},
+                })
+            })
+            .collect();
+        let mut data = json!({
+            "name": character.name,
+            "description": description.trim(),
+            "personality": personality,
+            "scenario": self.scenario,
+            "first_mes": character.greeting.clone().unwrap_or_default(),
+            "mes_example": examples.trim_end(),
+            "creator_notes": "",
+            "system_prompt": "",
+            "post_history_instructions": "",
+            "alternate_greetings": [],
+            "tags": [],
+            "creator": "",
+            "character_version": "",
+            "extensions": {},
+        });
+        if !entries.is_empty() {
+            data["character_book"] = json!({ "name": format!("{}'s lorebook", character.name), "entries": entries, "extensions": {} });
+        }
+        json!({ "spec": "chara_card_v2", "spec_version": "2.0", "data": data })
+    }
+
+    /// Writes the card as a PNG image carrying it, in `image` or in a plain picture.
+    pub fn write_png(&self, path: &Path, image: Option<&[u8]>) -> Result<(), Box<dyn std::error::Error>> {
+        let image = match image {
+            Some(image) if is_png(image) => image.to_vec(),
+            Some(_) => return Err("The card's image must be a PNG".into()),
+            None => plain_png()?,
+        };
+        let mut text = b"chara\0".to_vec();
+        text.extend(base64::engine::general_purpose::STANDARD.encode(self.to_json().to_string()).into_bytes());
+
+        // The image's own card, if it has one, is left out, and the new one goes before the end
+        let mut png = PNG_SIGNATURE.to_vec();
+        for (kind, data) in chunks(&image) {
+            let keyword = data.split(|&byte| byte == 0).next().unwrap_or_default().to_ascii_lowercase();
+            if kind == b"tEXt" && (keyword == b"chara" || keyword == b"ccv3") {
+                continue;
+            }
+            if kind == b"IEND" {
+                push_chunk(&mut png, b"tEXt", &text);
+            }
+            push_chunk(&mut png, kind, data);
+        }
+        fs::write(path, png)?;
+        Ok(())
+    }
+}
+
+pub fn is_png(bytes: &[u8]) -> bool {
+    bytes.starts_with(PNG_SIGNATURE)
+}
+
+/// The chunks of a PNG, by type and data.
+fn chunks(png: &[u8]) -> Vec<(&[u8], &[u8])> {
+    let mut chunks = Vec::new();
+    let mut rest = &png[PNG_SIGNATURE.len()..];
+    // Each chunk is its length, its type, its data and a checksum
+    while rest.len() >= 12 {
+        let length = u32::from_be_bytes([rest[0], rest[1], rest[2], rest[3]]) as usize;
+        let Some(data) = rest.get(8..8 + length) else {
+            break;
+        };
+        chunks.push((&rest[4..8], data));
+        rest = &rest[(12 + length).min(rest.len())..];
+    }
+    chunks
+}
+
+fn push_chunk(png: &mut Vec<u8>, kind: &[u8], data: &[u8]) {
+    let mut crc = Crc::new();
+    crc.update(kind);
+    crc.update(data);
+    png.extend((data.len() as u32).to_be_bytes());
+    png.extend(kind);
+    png.extend(data);
+    png.extend(crc.sum().to_be_bytes());
+}
+
+/// A picture of one color, for a card without one.
+fn plain_png() -> Result<Vec<u8>, Box<dyn std::error::Error>> {
+    let (width, height, color) = PLAIN_IMAGE;
+    // Each row of pixels starts with its filter, none
+    let mut row = vec![0];
+    for _ in 0..width {
+        row.extend(color);
+    }
+    let mut pixels = ZlibEncoder::new(Vec::new(), Compression::default());
+    for _ in 0..height {
+        pixels.write_all(&row)?;
+    }
+    let mut header = Vec::new();
+    header.extend(width.to_be_bytes());
+    header.extend(height.to_be_bytes());
+    // 8 bits per channel, RGB, then the default compression, filtering and no interlacing
+    header.extend([8, 2, 0, 0, 0]);
+    let mut png = PNG_SIGNATURE.to_vec();
+    push_chunk(&mut png, b"IHDR", &header);
+    push_chunk(&mut png, b"IDAT", &pixels.finish()?);
+    push_chunk(&mut png, b"IEND", &[]);
+    Ok(png)
+}
+
+/// The card in a PNG's text chunks, preferring the version 3 one.
+fn png_card(png: &[u8]) -> Result<Vec<u8>, Box<dyn std::error::Error>> {
+    let (mut chara, mut ccv3) = (None, None);
+    for (kind, data) in chunks(png) {
+        if kind != b"tEXt" {
+            continue;
+        }
+        if let Some((keyword, text)) = data.iter().position(|&byte| byte == 0).map(|at| (&data[..at], &data[at + 1..])) {
+            match keyword.to_ascii_lowercase().as_slice() {
+                b"chara" => chara = Some(text),
+                b"ccv3" => ccv3 = Some(text),
+                _ => {}
+            }
+        }
+    }
+    let encoded = ccv3.or(chara).ok_or("The image has no character card in it")?;
+    Ok(base64::engine::general_purpose::STANDARD.decode(encoded.trim_ascii())?)
+}
+
+/// Replaces the card's placeholders for the character and the user, in any case.
+fn fill(text: &str, name: &str, user: &str) -> String {
+    let placeholders = [("{{char}}", name), ("<bot>", name), ("{{user}}", user), ("<user>", user)];
+    let mut filled = String::new();
+    let mut rest = text;
+    'text: while let Some(next) = rest.chars().next() {
+        for (placeholder, value) in placeholders {
+            if rest.get(..placeholder.len()).is_some_and(|head| head.eq_ignore_ascii_case(placeholder)) {
+                filled.push_str(value);
+                rest = &rest[placeholder.len()..];
+                continue 'text;
+            }
+        }
+        filled.push(next);
+        rest = &rest[next.len_utf8()..];
+    }
+    filled
+}
+
+/// The exchanges of a card's example dialogue: blocks started by `<START>`, of lines such as
+/// `{{user}}: Hi` and `{{char}}: Hello`. A line without a speaker goes on the one before it.
+/// Each user line is paired with the character's reply to it.
+fn examples(dialogue: &str, name: &str) -> Vec<Example> {
+    // Who spoke and what they said; `None` between blocks, so no exchange spans two
+    let mut turns: Vec<Option<(bool, String)>> = Vec::new();
+    for line in dialogue.lines() {
+        let trimmed = line.trim();
+        if trimmed.eq_ignore_ascii_case("<start>") {
+            turns.push(None);
+            continue;
+        }
+        let speaker = trimmed.split_once(':').and_then(|(speaker, text)| {
+            let is = |placeholders: &[&str]| placeholders.iter().any(|placeholder| speaker.trim().eq_ignore_ascii_case(placeholder));
+            if is(&["{{user}}", "<user>"]) {
+                Some((true, text.trim().to_string()))
+            } else if is(&["{{char}}", "<bot>", name]) {
+                Some((false, text.trim().to_string()))
+            } else {
+                None
+            }
+        });
+        match speaker {
+            Some(turn) => turns.push(Some(turn)),
+            None if !trimmed.is_empty() => {
+                if let Some(Some((_, text))) = turns.last_mut() {
+                    text.push('\n');
+                    text.push_str(trimmed);
+                }
+            }
+            None => {}
+        }
+    }
+    turns
+        .windows(2)
+        .filter_map(|pair| match pair {
+            [Some((true, user)), Some((false, reply))] if !user.is_empty() && !reply.is_empty() => Some(Example {
+                user: fill(user, name, USER_ADDRESSED),
+                reply: fill(reply, name, USER_ADDRESSED),
+            }),
+            _ => None,
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn png_with(text_chunks: &[&[u8]]) -> Vec<u8> {
+        let mut png = PNG_SIGNATURE.to_vec();
+        push_chunk(&mut png, b"IHDR", &[0; 13]);
+        for text in text_chunks {
+            push_chunk(&mut png, b"tEXt", text);
+        }
+        push_chunk(&mut png, b"IEND", &[]);
+        png
+    }
+
+    fn text_chunk(keyword: &str, card: &Value) -> Vec<u8> {
+        let mut text = format!("{}\0", keyword).into_bytes();
+        text.extend(base64::engine::general_purpose::STANDARD.encode(card.to_string()).into_bytes());
+        text
+    }
+
+    #[test]
+    fn chunks_are_read_in_order() {
+        let png = plain_png().unwrap();
+        let kinds: Vec<&[u8]> = chunks(&png).into_iter().map(|(kind, _)| kind).collect();
+        assert_eq!(kinds, vec![&b"IHDR"[..], b"IDAT", b"IEND"]);
+        assert_eq!(chunks(&png)[0].1.len(), 13);
+    }
+
+    #[test]
+    fn chunks_stop_at_a_truncated_chunk() {
+        let mut png = png_with(&[b"Comment\0hello"]);
+        png.truncate(png.len() - 20);
+        let kinds: Vec<&[u8]> = chunks(&png).into_iter().map(|(kind, _)| kind).collect();
+        assert_eq!(kinds, vec![&b"IHDR"[..]]);
+    }
+
+    #[test]
+    fn chunks_stop_at_an_impossible_length() {
+        let mut png = PNG_SIGNATURE.to_vec();
+        png.extend(u32::MAX.to_be_bytes());
+        png.extend(b"tEXt");
+        png.extend([0; 8]);
+        assert!(chunks(&png).is_empty());
+        assert!(chunks(PNG_SIGNATURE).is_empty());
+    }
+
+    #[test]
+    fn card_is_read_from_its_text_chunk() {
+        let card = json!({ "spec": "chara_card_v2", "data": { "name": "Alya", "personality": "proud, kind" } });
+        let png = png_with(&[b"Comment\0made by hand", &text_chunk("chara", &card)]);
+        let card = Card::from_bytes(&png).unwrap();
+        assert_eq!(card.character.name, "Alya");
+        assert_eq!(card.character.traits, vec!["proud", "kind"]);
+    }
+
+    #[test]
+    fn version_3_card_is_preferred() {
+        let v2 = json!({ "data": { "name": "Old" } });
+        let v3 = json!({ "data": { "name": "New" } });
+        let png = png_with(&[&text_chunk("ccv3", &v3), &text_chunk("chara", &v2)]);
+        assert_eq!(Card::from_bytes(&png).unwrap().character.name, "New");
+    }
+
+    #[test]
+    fn image_without_a_card_is_an_error() {
+        assert!(Card::from_bytes(&plain_png().unwrap()).is_err());
+    }
+}
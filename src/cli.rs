@@ -0,0 +1,244 @@
+use clap::{Parser, Subcommand, ValueEnum};
+use std::path::PathBuf;
+
+/// A self-learning character chatbot.
+#[derive(Debug, Parser)]
+#[command(name = "alya", version)]
+pub struct Cli {
+    /// Keep the configuration and all the data next to the executable, in its config and
+    /// data directories, as on a USB stick; also on when a file named `portable` is there
+    #[arg(long, global = true)]
+    pub portable: bool,
+    /// Where the configuration, installed characters and profiles are kept [default: config
+    /// in the working directory if it is there, else the platform's, such as ~/.config/alya]
+    #[arg(long, global = true, value_name = "PATH")]
+    pub config_dir: Option<PathBuf>,
+    /// The character configuration file, JSON, TOML or YAML [default: chatbot_config.json in
+    /// the config directory]
+    #[arg(long, global = true, value_name = "PATH")]
+    pub config: Option<PathBuf>,
+    /// Where the knowledge, sessions and other state are kept [default: data in the working
+    /// directory if it is there, else the platform's, such as ~/.local/share/alya]
+    #[arg(long, global = true, value_name = "PATH")]
+    pub data_dir: Option<PathBuf>,
+    /// Use the character installed as NAME with `character install`, with its own
+    /// configuration and data
+    #[arg(long, global = true, value_name = "NAME")]
+    pub character: Option<String>,
+    /// Use the profile NAME made with `profile create`, such as dev, with its own
+    /// configuration and data apart from the main ones
+    #[arg(long, global = true, value_name = "NAME", conflicts_with = "character")]
+    pub profile: Option<String>,
+    /// Don't learn when the chat starts, nor on the learning schedule during it
+    #[arg(long, global = true)]
+    pub no_initial_learn: bool,
+    /// Read messages from stdin and write only the replies to stdout, without prompts or
+    /// banners; progress goes to stderr
+    #[arg(long, short, global = true)]
+    pub quiet: bool,
+    /// How replies are written; json writes one object per reply, implying --quiet
+    #[arg(long, global = true, value_enum, default_value_t = OutputFormat::Text)]
+    pub output: OutputFormat,
+    /// Instead of chatting, speak JSON-RPC on stdin and stdout, for editors and apps that
+    /// run alya as a subprocess
+    #[arg(long)]
+    pub stdio: bool,
+    #[command(subcommand)]
+    pub command: Option<Command>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum OutputFormat {
+    Text,
+    Json,
+}
+
+#[derive(Debug, Subcommand)]
+pub enum Command {
+    /// Chat with the character (the default)
+    Chat,
+    /// Answer one question and exit, for use from scripts
+    Ask {
+        #[arg(required = true)]
+        question: Vec<String>,
+    },
+    /// Keep the character loaded and answer the messages sent with `alya send`
+    Daemon,
+    /// Have the running daemon answer a message
+    Send {
+        #[arg(required = true)]
+        message: Vec<String>,
+    },
+    /// Answer an HTTP API for chatting, managing the knowledge and learning
+    Serve {
+        /// The address and port to listen on
+        #[arg(long, default_value = "127.0.0.1:8080")]
+        bind: String,
+        /// Let anyone who can reach the API use it, without a key
+        #[arg(long)]
+        no_auth: bool,
+        /// Also answer the gRPC API on this address and port, such as 127.0.0.1:50051
+        #[arg(long, value_name = "ADDRESS")]
+        grpc: Option<std::net::SocketAddr>,
+    },
+    /// Speak the Model Context Protocol on stdin and stdout, for MCP clients that run alya
+    /// as a subprocess
+    Mcp,
+    /// Answer on several chat platforms at once, with one chatbot
+    Gateway {
+        /// The platforms, such as discord or irc; every one set up in the config if none are
+        /// given
+        names: Vec<String>,
+    },
+    /// Answer mentions and direct messages as a Discord bot
+    Discord,
+    /// Answer the allowed chats as a Telegram bot
+    Telegram,
+    /// Answer on Matrix, in encrypted rooms too, as a bot account
+    Matrix,
+    /// Answer mentions and direct messages in a Slack workspace, over Socket Mode
+    Slack,
+    /// Answer private messages and the messages addressed to it in IRC channels
+    Irc,
+    /// Receive WhatsApp messages on a webhook and answer them with the WhatsApp Cloud API
+    Whatsapp {
+        /// The address and port to listen on, behind a reverse proxy with HTTPS, instead of
+        /// whatsapp.bind
+        #[arg(long)]
+        bind: Option<String>,
+    },
+    /// Answer the emails sent to a mailbox, checking it over IMAP and replying over SMTP
+    Email,
+    /// Answer direct messages and the messages addressed to it in XMPP group chats
+    Xmpp,
+    /// Answer the mentions of a Mastodon account
+    Mastodon,
+    /// Manage the keys of the HTTP API
+    ApiKeys {
+        #[arg(value_parser = ["list", "create", "revoke"])]
+        action: String,
+        name: Option<String>,
+        /// What a new key may do: chat only, or everything
+        #[arg(long, value_parser = ["chat", "admin"], default_value = "chat")]
+        scope: String,
+    },
+    /// Search and learn about the character, then exit
+    Learn {
+        /// Only show what would be fetched and learned
+        #[arg(long)]
+        dry_run: bool,
+    },
+    /// Train with a book or document, or with the new and changed files in a directory
+    Train { path: PathBuf },
+    /// List, roll back or benchmark the saved versions of the knowledge
+    Knowledge {
+        #[arg(value_parser = ["history", "rollback", "bench"])]
+        action: String,
+        /// The snapshot to roll back to
+        id: Option<String>,
+    },
+    /// Import data/learned_knowledge.json into the configured storage backend
+    Migrate {
+        #[arg(long)]
+        dry_run: bool,
+    },
+    /// Pack the character, its knowledge and sessions into an archive
+    Export { file: String },
+    /// Add an exported character's knowledge and sessions
+    Import {
+        file: String,
+        /// Keep the current character configuration
+        #[arg(long)]
+        keep_character: bool,
+        #[arg(long)]
+        dry_run: bool,
+    },
+    /// Make the character the one of a SillyTavern or TavernAI character card, from its JSON
+    /// or PNG file
+    Card {
+        #[arg(value_parser = ["import"])]
+        action: String,
+        file: String,
+    },
+    /// Install a character shared as a character card or configuration from its URL, or list
+    /// the installed characters
+    Character {
+        #[arg(value_parser = ["install", "list"])]
+        action: String,
+        url: Option<String>,
+        /// What to install it as, for --character; from the character's name if not given
+        #[arg(long)]
+        name: Option<String>,
+        /// Install without asking
+        #[arg(long)]
+        yes: bool,
+    },
+    /// Make a profile, starting from a copy of the current configuration and with no data,
+    /// or list the profiles
+    Profile {
+        #[arg(value_parser = ["create", "list"])]
+        action: String,
+        name: Option<String>,
+    },
+    /// Have several characters talk with each other, and with you if you join in
+    Group {
+        /// The characters: installed ones by name, and the current one by its name
+        #[arg(required = true, num_args = 2..)]
+        characters: Vec<String>,
+        /// What they talk about
+        #[arg(long)]
+        topic: Option<String>,
+        /// How many times each speaks; 3, or until you end it with --join
+        #[arg(long)]
+        rounds: Option<usize>,
+        /// Take a turn after each round
+        #[arg(long)]
+        join: bool,
+        /// Where to write the transcript [default: data/transcripts/group-<time>.md]
+        #[arg(long, value_name = "PATH")]
+        transcript: Option<PathBuf>,
+    },
+    /// Write the character as a character card for SillyTavern and other tools: a PNG image
+    /// carrying it, or a JSON file
+    ExportCharacter {
+        /// Where to write it; a .png file is an image, anything else JSON
+        file: String,
+        /// The picture of a PNG card; a plain one if not given
+        #[arg(long, value_name = "PNG")]
+        image: Option<String>,
+        /// Add a summary of the learned knowledge, written by the model, to the description
+        #[arg(long)]
+        with_knowledge: bool,
+    },
+    /// Push or pull the knowledge to the configured S3 or WebDAV copy
+    Sync {
+        #[arg(value_parser = ["status", "push", "pull"])]
+        action: String,
+        /// Overwrite the other side's changes
+        #[arg(long)]
+        force: bool,
+        /// Combine both sides when pulling
+        #[arg(long)]
+        merge: bool,
+    },
+    /// Create the key for storage.encryption
+    Encryption {
+        #[arg(value_parser = ["init"])]
+        action: String,
+    },
+    /// Walk through the character, its knowledge sources, the API keys and search, and the
+    /// conversation settings, to set them up or change them
+    Setup,
+    /// Manage the API keys and passwords kept in the OS keyring
+    Keys {
+        #[arg(value_parser = ["list", "set", "delete"])]
+        action: String,
+        name: Option<String>,
+    },
+    /// Manage the cookies sent to sites behind a login
+    Cookies {
+        #[arg(value_parser = ["list", "set", "clear", "login"])]
+        action: String,
+        domain: Option<String>,
+    },
+}
@@ -0,0 +1,489 @@
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+use serde_json::{Map, Value};
+use std::fs;
+use std::path::{Path, PathBuf};
+use toml_edit::ser::ValueSerializer;
+use toml_edit::{DocumentMut, Item, Table, TableLike};
+
+/// Starts the environment variables that override settings, such as
+/// `ALYA__CONVERSATION_SETTINGS__MAX_HISTORY`.
+pub const ENV_PREFIX: &str = "ALYA__";
+/// Separates the sections of a setting in the name of its environment variable.
+const ENV_SEPARATOR: &str = "__";
+
+/// The extensions a configuration file may have, in the order they are looked for.
+const EXTENSIONS: [&str; 4] = ["json", "toml", "yaml", "yml"];
+
+/// How a configuration file is written, going by its extension.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Format {
+    Json,
+    Toml,
+    Yaml,
+}
+
+impl Format {
+    /// The format of `path`; JSON unless it ends in `.toml`, `.yaml` or `.yml`.
+    pub fn of(path: &Path) -> Format {
+        match path.extension().and_then(|extension| extension.to_str()).map(str::to_lowercase).as_deref() {
+            Some("toml") => Format::Toml,
+            Some("yaml" | "yml") => Format::Yaml,
+            _ => Format::Json,
+        }
+    }
+
+    /// The extension of a file in this format.
+    pub fn extension(self) -> &'static str {
+        match self {
+            Format::Json => "json",
+            Format::Toml => "toml",
+            Format::Yaml => "yaml",
+        }
+    }
+
+    /// Parses `text`, naming the setting that is wrong when it does not fit, such as
+    /// `conversation_settings.max_history: invalid type: string "5", expected usize`.
+    pub fn parse<T: DeserializeOwned>(self, text: &str) -> Result<T, Box<dyn std::error::Error>> {
+        Ok(match self {
+            Format::Json => serde_path_to_error::deserialize(&mut serde_json::Deserializer::from_str(text)).map_err(located)?,
+            Format::Toml => serde_path_to_error::deserialize(toml::Deserializer::new(text)).map_err(located)?,
+            Format::Yaml => serde_path_to_error::deserialize(serde_yaml::Deserializer::from_str(text)).map_err(located)?,
+        })
+    }
+
+    pub fn to_string<T: Serialize>(self, value: &T) -> Result<String, Box<dyn std::error::Error>> {
+        Ok(match self {
+            Format::Json => serde_json::to_string_pretty(value)?,
+            Format::Toml => toml::to_string_pretty(value)?,
+            Format::Yaml => serde_yaml::to_string(value)?,
+        })
+    }
+}
+
+/// The error with the setting it is about in front, unless it is about the whole file,
+/// such as a syntax error. A missing setting is named by the section it is missing from.
+fn located<E: std::fmt::Display>(error: serde_path_to_error::Error<E>) -> String {
+    let path = error.path().to_string();
+    let hint = if error.inner().to_string().starts_with("missing field") { "; add it" } else { "" };
+    match path.as_str() {
+        "." | "" => format!("{}{}", error.inner(), hint),
+        _ => format!("{}: {}{}", path, error.inner(), hint),
+    }
+}
+
+/// Reads a configuration file in the format its extension gives.
+pub fn read<T: DeserializeOwned>(path: &Path) -> Result<T, Box<dyn std::error::Error>> {
+    let text = fs::read_to_string(path)?;
+    Format::of(path).parse(&text).map_err(|e| format!("{}: {}", path.display(), e).into())
+}
+
+/// Reads a configuration file like `read`, with the settings the `ENV_PREFIX` environment
+/// variables give in place of the file's.
+pub fn read_with_env<T: DeserializeOwned>(path: &Path) -> Result<T, Box<dyn std::error::Error>> {
+    let mut value: Value = read(path)?;
+    apply_env(&mut value)?;
+    serde_path_to_error::deserialize(value).map_err(|e| format!("{}: {}", path.display(), located(e)).into())
+}
+
+/// Writes `config` to `path` in the format its extension gives. In a file that is already
+/// there only the settings that differ from what it holds are changed, as it reads with
+/// the `ENV_PREFIX` environment variables applied, so their overrides stay out of the file
+/// and settings left out of it, to have their defaults, stay out too. A TOML file keeps its
+/// comments and layout; a YAML file with comments is left as it is, with a warning, as
+/// they would be lost.
+pub fn write<T: Serialize + DeserializeOwned>(path: &Path, config: &T) -> Result<(), Box<dyn std::error::Error>> {
+    let format = Format::of(path);
+    let text = match path.exists() {
+        true => match patched(path, config) {
+            Ok(Some(text)) => text,
+            Ok(None) => return Ok(()),
+            // A file that does not read as a configuration any more is replaced
+            Err(_) => format.to_string(config)?,
+        },
+        false => format.to_string(config)?,
+    };
+    if let Some(dir) = path.parent().filter(|dir| !dir.as_os_str().is_empty()) {
+        fs::create_dir_all(dir)?;
+    }
+    fs::write(path, text)?;
+    Ok(())
+}
+
+/// A setting that changed: its path, and its new value or `None` when it is to be left out.
+type Change = (Vec<String>, Option<Value>);
+
+/// The text of the file at `path` with the settings `config` changed set in it, or `None`
+/// when it is to be left as it is.
+fn patched<T: Serialize + DeserializeOwned>(path: &Path, config: &T) -> Result<Option<String>, Box<dyn std::error::Error>> {
+    let format = Format::of(path);
+    let text = fs::read_to_string(path)?;
+    let mut file: Value = format.parse(&text)?;
+    let mut as_read = file.clone();
+    apply_env(&mut as_read)?;
+    let before = serde_json::to_value(serde_json::from_value::<T>(as_read)?)?;
+    let mut found = Vec::new();
+    changes(&before, &serde_json::to_value(config)?, &mut Vec::new(), &mut found);
+    if found.is_empty() {
+        return Ok(None);
+    }
+    match format {
+        Format::Toml => {
+            let mut document: DocumentMut = text.parse()?;
+            for (path, value) in found {
+                set_toml(&mut document, &path, value.as_ref().map(toml_item).transpose()?);
+            }
+            Ok(Some(document.to_string()))
+        }
+        Format::Yaml if has_comments(&text) => {
+            let settings: Vec<String> = found.iter().map(|(path, _)| path.join(".")).collect();
+            status!(
+                "Warning: {} was not saved, as its comments would be lost; set {} in it by hand",
+                path.display(),
+                settings.join(", ")
+            );
+            Ok(None)
+        }
+        _ => {
+            for (path, value) in found {
+                set(&mut file, &path, value);
+            }
+            format.to_string(&file).map(Some)
+        }
+    }
+}
+
+/// Whether a YAML file has a comment, counting any ` #` in case it is not in a string.
+fn has_comments(text: &str) -> bool {
+    text.lines().any(|line| line.trim_start().starts_with('#') || line.contains(" #"))
+}
+
+/// Adds the settings that differ between `before` and `after` to `found`, by section.
+fn changes(before: &Value, after: &Value, path: &mut Vec<String>, found: &mut Vec<Change>) {
+    if before == after {
+        return;
+    }
+    match (before, after) {
+        (Value::Object(before), Value::Object(after)) => {
+            for (key, value) in after {
+                path.push(key.clone());
+                changes(before.get(key).unwrap_or(&Value::Null), value, path, found);
+                path.pop();
+            }
+            for key in before.keys().filter(|key| !after.contains_key(*key)) {
+                path.push(key.clone());
+                found.push((path.clone(), None));
+                path.pop();
+            }
+        }
+        // A setting that is not set is left out, as TOML has no null
+        (_, Value::Null) => found.push((path.clone(), None)),
+        _ => found.push((path.clone(), Some(without_nulls(after.clone())))),
+    }
+}
+
+fn without_nulls(value: Value) -> Value {
+    match value {
+        Value::Object(map) => Value::Object(map.into_iter().filter(|(_, value)| !value.is_null()).map(|(key, value)| (key, without_nulls(value))).collect()),
+        Value::Array(elements) => Value::Array(elements.into_iter().map(without_nulls).collect()),
+        value => value,
+    }
+}
+
+/// Sets the setting at `path` in `file`, adding the sections it is in, or takes it out.
+fn set(file: &mut Value, path: &[String], value: Option<Value>) {
+    let Some((last, sections)) = path.split_last() else {
+        return;
+    };
+    let mut section = file;
+    for key in sections {
+        if value.is_none() && !section.get(key).is_some_and(Value::is_object) {
+            return;
+        }
+        if !section.is_object() {
+            *section = Value::Object(Map::new());
+        }
+        section = section.as_object_mut().unwrap().entry(key.clone()).or_insert_with(|| Value::Object(Map::new()));
+    }
+    if !section.is_object() {
+        *section = Value::Object(Map::new());
+    }
+    let section = section.as_object_mut().unwrap();
+    match value {
+        Some(value) => {
+            section.insert(last.clone(), value);
+        }
+        None => {
+            section.shift_remove(last);
+        }
+    }
+}
+
+/// `value` as TOML, a table for a section and a value for a setting.
+fn toml_item(value: &Value) -> Result<Item, Box<dyn std::error::Error>> {
+    Ok(match value.serialize(ValueSerializer::new())? {
+        toml_edit::Value::InlineTable(table) => Item::Table(table.into_table()),
+        value => Item::Value(value),
+    })
+}
+
+/// Like `set`, in a TOML document, keeping the comments and layout around the setting.
+fn set_toml(document: &mut DocumentMut, path: &[String], value: Option<Item>) {
+    let Some((last, sections)) = path.split_last() else {
+        return;
+    };
+    let mut section: &mut dyn TableLike = document.as_table_mut();
+    for key in sections {
+        if !section.get(key).is_some_and(Item::is_table_like) {
+            if value.is_none() {
+                return;
+            }
+            let mut table = Table::new();
+            table.set_implicit(true);
+            section.insert(key, Item::Table(table));
+        }
+        section = section.get_mut(key).and_then(Item::as_table_like_mut).unwrap();
+    }
+    let Some(new) = value else {
+        section.remove(last);
+        return;
+    };
+    if !section.contains_key(last) {
+        section.insert(last, new);
+        return;
+    }
+    match (section.get_mut(last).unwrap(), new) {
+        // A setting keeps the comment at the end of its line
+        (Item::Value(old), Item::Value(mut new)) => {
+            *new.decor_mut() = old.decor().clone();
+            *old = new;
+        }
+        (old, new) => *old = new,
+    }
+}
+
+/// `value` with the settings the `ENV_PREFIX` environment variables give in place of its
+/// own, for a configuration that has no file.
+pub fn with_env<T: Serialize + DeserializeOwned>(value: T) -> Result<T, Box<dyn std::error::Error>> {
+    let mut overridden = serde_json::to_value(&value)?;
+    if !apply_env(&mut overridden)? {
+        return Ok(value);
+    }
+    serde_path_to_error::deserialize(overridden).map_err(|e| format!("environment: {}", located(e)).into())
+}
+
+/// Sets the setting of each `ENV_PREFIX` environment variable in `config`, in the order of
+/// their names so a section and a setting in it are set in a fixed order. The name is the
+/// path of the setting in upper case, with `__` between the sections, and an array
+/// element is named by its index, as in `ALYA__WEBHOOKS__0__URL`. A value is taken as
+/// JSON when it is, such as `5`, `true` or `["a", "b"]`, and as text otherwise, or always
+/// as text for a setting that is text, so a name can be `123`. Whether any were set.
+fn apply_env(config: &mut Value) -> Result<bool, Box<dyn std::error::Error>> {
+    let mut overrides: Vec<(String, String)> = std::env::vars().filter(|(name, _)| name.starts_with(ENV_PREFIX)).collect();
+    overrides.sort();
+    for (name, text) in &overrides {
+        let keys: Vec<String> = name[ENV_PREFIX.len()..].split(ENV_SEPARATOR).map(str::to_lowercase).collect();
+        if keys.iter().any(String::is_empty) {
+            return Err(format!("{}: is not a setting; name one like {}CHARACTER__NAME", name, ENV_PREFIX).into());
+        }
+        let mut value = &mut *config;
+        for key in &keys {
+            if let Value::Array(elements) = value {
+                let length = elements.len();
+                let index = key.parse::<usize>().ok().filter(|index| *index < length);
+                let index = index.ok_or_else(|| format!("{}: the list has {} element(s); name one by its index, from 0", name, length))?;
+                value = &mut elements[index];
+                continue;
+            }
+            if !value.is_object() {
+                *value = Value::Object(serde_json::Map::new());
+            }
+            value = value.as_object_mut().unwrap().entry(key.clone()).or_insert(Value::Null);
+        }
+        *value = match value {
+            Value::String(_) => Value::String(text.clone()),
+            _ => serde_json::from_str(text).unwrap_or_else(|_| Value::String(text.clone())),
+        };
+    }
+    Ok(!overrides.is_empty())
+}
+
+/// The configuration file `path` is, or one next to it with the same name in another
+/// format, such as `chatbot_config.toml` for `chatbot_config.json`. `path` when there is
+/// none yet.
+pub fn find(path: &Path) -> PathBuf {
+    if path.exists() {
+        return path.to_path_buf();
+    }
+    EXTENSIONS.iter().map(|extension| path.with_extension(extension)).find(|other| other.exists()).unwrap_or_else(|| path.to_path_buf())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    /// Held by the tests that read the environment, as it is shared by the tests running at
+    /// once; every case setting variables is in one test.
+    static ENV: std::sync::Mutex<()> = std::sync::Mutex::new(());
+
+    #[test]
+    fn environment_overrides_settings() {
+        let _env = ENV.lock().unwrap();
+        let mut config = json!({
+            "character": { "name": "Alya", "age": 16 },
+            "conversation_settings": { "max_history": 20, "citations": false },
+            "webhooks": [{ "url": "https://old.example.com" }],
+        });
+        let overrides = [
+            ("ALYA__CHARACTER__NAME", "123"),
+            ("ALYA__CONVERSATION_SETTINGS__MAX_HISTORY", "5"),
+            ("ALYA__CONVERSATION_SETTINGS__CITATIONS", "true"),
+            ("ALYA__WEBHOOKS__0__URL", "https://new.example.com"),
+            ("ALYA__KNOWLEDGE_SOURCES__FEEDS", r#"["https://example.com/feed.xml"]"#),
+            ("ALYA__SEARCH__PROVIDER", "brave"),
+        ];
+        for (name, value) in overrides {
+            std::env::set_var(name, value);
+        }
+        let applied = apply_env(&mut config);
+        for (name, _) in overrides {
+            std::env::remove_var(name);
+        }
+
+        assert!(applied.unwrap());
+        // Text settings stay text even when the value looks like JSON
+        assert_eq!(config["character"]["name"], json!("123"));
+        assert_eq!(config["character"]["age"], json!(16));
+        assert_eq!(config["conversation_settings"]["max_history"], json!(5));
+        assert_eq!(config["conversation_settings"]["citations"], json!(true));
+        assert_eq!(config["webhooks"][0]["url"], json!("https://new.example.com"));
+        assert_eq!(config["knowledge_sources"]["feeds"], json!(["https://example.com/feed.xml"]));
+        assert_eq!(config["search"]["provider"], json!("brave"));
+
+        std::env::set_var("ALYA__WEBHOOKS__3__URL", "https://example.com");
+        let error = apply_env(&mut config).unwrap_err().to_string();
+        std::env::remove_var("ALYA__WEBHOOKS__3__URL");
+        assert!(error.contains("the list has 1 element(s)"), "{}", error);
+
+        std::env::set_var("ALYA__CHARACTER____NAME", "Alya");
+        let error = apply_env(&mut config).unwrap_err().to_string();
+        std::env::remove_var("ALYA__CHARACTER____NAME");
+        assert!(error.contains("is not a setting"), "{}", error);
+    }
+
+    fn patch(file: Value, before: Value, after: Value) -> Value {
+        let mut found = Vec::new();
+        changes(&before, &after, &mut Vec::new(), &mut found);
+        let mut file = file;
+        for (path, value) in found {
+            set(&mut file, &path, value);
+        }
+        file
+    }
+
+    #[test]
+    fn saving_changes_only_what_changed() {
+        // The name comes from the environment, the age is left to its default
+        let file = json!({ "character": { "name": "Alya", "personality": "proud" } });
+        let before = json!({ "character": { "name": "Masha", "personality": "proud", "age": 16 } });
+        let after = json!({ "character": { "name": "Masha", "personality": "kind", "age": 16 } });
+        assert_eq!(patch(file, before, after), json!({ "character": { "name": "Alya", "personality": "kind" } }));
+    }
+
+    #[test]
+    fn saving_adds_new_sections_and_leaves_out_unset_ones() {
+        let file = json!({ "character": { "name": "Alya" }, "sync": { "url": "https://example.com" } });
+        let before = json!({ "character": { "name": "Alya" }, "sync": { "url": "https://example.com" } });
+        let after = json!({ "character": { "name": "Alya" }, "sync": null, "discord": { "token": null, "prefix": "!" } });
+        assert_eq!(patch(file, before, after), json!({ "character": { "name": "Alya" }, "discord": { "prefix": "!" } }));
+    }
+
+    #[test]
+    fn saving_keeps_the_order_of_the_file() {
+        let file = json!({ "storage": { "backend": "sqlite" }, "character": { "name": "Alya" } });
+        let before = file.clone();
+        let after = json!({ "character": { "name": "Masha" }, "storage": { "backend": "sqlite" } });
+        let patched = patch(file, before, after);
+        let keys: Vec<&String> = patched.as_object().unwrap().keys().collect();
+        assert_eq!(keys, ["storage", "character"]);
+    }
+
+    #[derive(Debug, serde::Serialize, serde::Deserialize)]
+    struct Saved {
+        character: SavedCharacter,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        discord: Option<SavedDiscord>,
+    }
+
+    #[derive(Debug, serde::Serialize, serde::Deserialize)]
+    struct SavedCharacter {
+        name: String,
+        personality: String,
+    }
+
+    #[derive(Debug, serde::Serialize, serde::Deserialize)]
+    struct SavedDiscord {
+        prefix: String,
+    }
+
+    fn saved(name: &str, text: &str, config: &Saved) -> String {
+        let _env = ENV.lock().unwrap();
+        let path = std::env::temp_dir().join(format!("alya-config-{}-{}", std::process::id(), name));
+        fs::write(&path, text).unwrap();
+        write(&path, config).unwrap();
+        let saved = fs::read_to_string(&path).unwrap();
+        fs::remove_file(&path).unwrap();
+        saved
+    }
+
+    #[test]
+    fn saving_toml_keeps_its_comments() {
+        let text = "# Who she is\n[character]\nname = \"Alya\" # her short name\n# How she talks\npersonality = \"proud\"\n";
+        let config = Saved {
+            character: SavedCharacter { name: "Alya".to_string(), personality: "kind".to_string() },
+            discord: Some(SavedDiscord { prefix: "!".to_string() }),
+        };
+        assert_eq!(
+            saved("comments.toml", text, &config),
+            "# Who she is\n[character]\nname = \"Alya\" # her short name\n# How she talks\npersonality = \"kind\"\n\n[discord]\nprefix = \"!\"\n"
+        );
+    }
+
+    #[test]
+    fn saving_yaml_with_comments_leaves_it_alone() {
+        let text = "# Who she is\ncharacter:\n  name: Alya\n  personality: proud\n";
+        let config = Saved {
+            character: SavedCharacter { name: "Alya".to_string(), personality: "kind".to_string() },
+            discord: None,
+        };
+        assert_eq!(saved("comments.yaml", text, &config), text);
+        let text = "character:\n  name: Alya\n  personality: proud\n";
+        assert_eq!(saved("plain.yaml", text, &config), "character:\n  name: Alya\n  personality: kind\n");
+    }
+
+    #[test]
+    fn errors_name_the_setting() {
+        #[derive(Debug, serde::Deserialize)]
+        #[allow(dead_code)]
+        struct Config {
+            conversation_settings: Settings,
+        }
+        #[derive(Debug, serde::Deserialize)]
+        #[allow(dead_code)]
+        struct Settings {
+            max_history: usize,
+        }
+        let error = Format::Json.parse::<Config>(r#"{ "conversation_settings": { "max_history": "5" } }"#).unwrap_err();
+        assert!(error.to_string().starts_with("conversation_settings.max_history: invalid type"), "{}", error);
+        let error = Format::Toml.parse::<Config>("[conversation_settings]\n").unwrap_err();
+        assert!(error.to_string().ends_with("; add it"), "{}", error);
+    }
+
+    #[test]
+    fn format_goes_by_the_extension() {
+        assert_eq!(Format::of(Path::new("config/chatbot_config.TOML")), Format::Toml);
+        assert_eq!(Format::of(Path::new("chatbot_config.yml")), Format::Yaml);
+        assert_eq!(Format::of(Path::new("chatbot_config")), Format::Json);
+    }
+}
@@ -0,0 +1,113 @@
+use serde::{Deserialize, Serialize};
+use std::io;
+use tokio::io::{AsyncRead, AsyncWrite};
+
+#[cfg(unix)]
+const SOCKET_FILE: &str = "alya.sock";
+
+/// A message sent to the daemon, as one line of JSON. The daemon answers each with one
+/// line of JSON: the reply, or an object with an `error`.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct Request {
+    pub message: String,
+}
+
+/// A connection between the daemon and a client.
+pub trait Stream: AsyncRead + AsyncWrite + Unpin + Send {}
+
+impl<T: AsyncRead + AsyncWrite + Unpin + Send> Stream for T {}
+
+/// Where the daemon of the current data directory listens: a Unix socket in the data
+/// directory, or on Windows a named pipe named after it.
+pub fn address() -> String {
+    #[cfg(unix)]
+    {
+        crate::paths::data_path(SOCKET_FILE).display().to_string()
+    }
+    #[cfg(windows)]
+    {
+        use sha2::{Digest, Sha256};
+        let dir = crate::paths::data_dir();
+        let dir = dir.canonicalize().unwrap_or_else(|_| dir.to_path_buf());
+        let hash = Sha256::digest(dir.display().to_string().as_bytes());
+        format!(r"\\.\pipe\alya-{}", &format!("{:x}", hash)[..16])
+    }
+}
+
+/// The daemon's end of the socket, which lets clients connect one after another.
+pub struct Listener {
+    #[cfg(unix)]
+    listener: tokio::net::UnixListener,
+    #[cfg(windows)]
+    server: tokio::net::windows::named_pipe::NamedPipeServer,
+    address: String,
+}
+
+impl Listener {
+    /// Starts listening, unless a daemon already is. A socket left behind by a daemon that
+    /// did not shut down cleanly is replaced.
+    pub async fn bind() -> Result<Self, Box<dyn std::error::Error>> {
+        let address = address();
+        if connect().await.is_ok() {
+            return Err(format!("A daemon is already listening on {}", address).into());
+        }
+        #[cfg(unix)]
+        {
+            if let Some(dir) = std::path::Path::new(&address).parent() {
+                std::fs::create_dir_all(dir)?;
+            }
+            match std::fs::remove_file(&address) {
+                Err(e) if e.kind() != io::ErrorKind::NotFound => return Err(e.into()),
+                _ => {}
+            }
+            let listener = tokio::net::UnixListener::bind(&address)?;
+            Ok(Listener { listener, address })
+        }
+        #[cfg(windows)]
+        {
+            let server = tokio::net::windows::named_pipe::ServerOptions::new()
+                .first_pipe_instance(true)
+                .create(&address)?;
+            Ok(Listener { server, address })
+        }
+    }
+
+    pub fn address(&self) -> &str {
+        &self.address
+    }
+
+    /// Waits for the next client.
+    pub async fn accept(&mut self) -> io::Result<Box<dyn Stream>> {
+        #[cfg(unix)]
+        {
+            let (stream, _) = self.listener.accept().await?;
+            Ok(Box::new(stream))
+        }
+        #[cfg(windows)]
+        {
+            self.server.connect().await?;
+            // A pipe instance serves one client; the next client connects to a new one
+            let next = tokio::net::windows::named_pipe::ServerOptions::new().create(&self.address)?;
+            Ok(Box::new(std::mem::replace(&mut self.server, next)))
+        }
+    }
+}
+
+#[cfg(unix)]
+impl Drop for Listener {
+    fn drop(&mut self) {
+        let _ = std::fs::remove_file(&self.address);
+    }
+}
+
+/// Connects to the daemon of the current data directory.
+pub async fn connect() -> io::Result<Box<dyn Stream>> {
+    #[cfg(unix)]
+    {
+        Ok(Box::new(tokio::net::UnixStream::connect(address()).await?))
+    }
+    #[cfg(windows)]
+    {
+        Ok(Box::new(tokio::net::windows::named_pipe::ClientOptions::new().open(address())?))
+    }
+}
@@ -0,0 +1,264 @@
+use crate::gateway::{split_message, Connector, Incoming};
+use crate::server::{self, ApiCall, Calls};
+use async_trait::async_trait;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use serenity::all::{
+    ChannelId, Command, CommandInteraction, CommandOptionType, Context, CreateCommand, CreateCommandOption, CreateInteractionResponseFollowup,
+    EditInteractionResponse, EventHandler, GatewayIntents, Http, Interaction, Message, Ready, ResolvedValue, UserId,
+};
+use std::sync::{Arc, OnceLock};
+use std::time::Duration;
+use tokio::sync::mpsc;
+
+/// The secret holding the bot's token.
+pub const TOKEN_NAME: &str = "DISCORD_BOT_TOKEN";
+/// The most characters Discord allows in a message.
+const MESSAGE_LIMIT: usize = 2000;
+/// How many facts `/knowledge search` lists.
+const SEARCH_LIMIT: usize = 10;
+
+/// Who may manage the bot from Discord.
+#[derive(Debug, Serialize, Deserialize, Clone, Default)]
+pub struct DiscordSettings {
+    /// The IDs of the roles whose members may use the slash commands; nobody may without
+    /// any.
+    #[serde(default)]
+    pub admin_roles: Vec<String>,
+}
+
+/// The Discord bot. It answers mentions and direct messages in character, with a
+/// conversation for each channel, and takes slash commands from the admins.
+pub struct Bot {
+    token: String,
+    settings: DiscordSettings,
+    /// The client of the REST API, once connected.
+    http: OnceLock<Arc<Http>>,
+}
+
+impl Bot {
+    pub fn new(token: String, settings: DiscordSettings) -> Self {
+        Bot { token, settings, http: OnceLock::new() }
+    }
+}
+
+#[async_trait]
+impl Connector for Bot {
+    type ReplyTo = ChannelId;
+
+    const NAME: &'static str = "discord";
+    const TITLE: &'static str = "Discord";
+    const PER_USER: bool = false;
+    /// Discord shows "typing" for 10 seconds.
+    const TYPING_EVERY: Duration = Duration::from_secs(8);
+
+    /// Connects to Discord and passes on the messages until the connection fails.
+    async fn receive(
+        self: Arc<Self>,
+        incoming: mpsc::Sender<Incoming<ChannelId>>,
+        calls: Calls,
+    ) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+        let intents = GatewayIntents::GUILD_MESSAGES | GatewayIntents::DIRECT_MESSAGES | GatewayIntents::MESSAGE_CONTENT;
+        let handler = Handler {
+            calls,
+            incoming,
+            settings: self.settings.clone(),
+            me: OnceLock::new(),
+        };
+        let mut client = serenity::Client::builder(&self.token, intents).event_handler(handler).await?;
+        let _ = self.http.set(client.http.clone());
+        client.start().await?;
+        Ok(())
+    }
+
+    async fn typing(&self, message: &Incoming<ChannelId>) {
+        if let Some(http) = self.http.get() {
+            let _ = message.reply_to.broadcast_typing(http).await;
+        }
+    }
+
+    async fn send(&self, message: &Incoming<ChannelId>, reply: &str) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+        let http = self.http.get().ok_or("Not connected to Discord")?;
+        for part in split_message(reply, MESSAGE_LIMIT) {
+            message.reply_to.say(http, part).await?;
+        }
+        Ok(())
+    }
+}
+
+struct Handler {
+    calls: Calls,
+    incoming: mpsc::Sender<Incoming<ChannelId>>,
+    settings: DiscordSettings,
+    /// The bot's own user, known once the gateway is ready.
+    me: OnceLock<UserId>,
+}
+
+#[serenity::async_trait]
+impl EventHandler for Handler {
+    async fn ready(&self, ctx: Context, ready: Ready) {
+        status!("Connected to Discord as {}", ready.user.name);
+        let _ = self.me.set(ready.user.id);
+        if let Err(e) = Command::set_global_commands(&ctx.http, commands()).await {
+            status!("Could not register the Discord slash commands: {}", e);
+        }
+    }
+
+    async fn interaction_create(&self, ctx: Context, interaction: Interaction) {
+        let Interaction::Command(command) = interaction else {
+            return;
+        };
+        // Answers are only shown to whoever used the command, and may take a while
+        if let Err(e) = command.defer_ephemeral(&ctx.http).await {
+            status!("Could not answer a Discord command: {}", e);
+            return;
+        }
+        let text = if self.is_admin(&command) {
+            self.run_command(&command).await.unwrap_or_else(|e| format!("That failed: {}", e))
+        } else {
+            "Only the bot's admins may use that.".to_string()
+        };
+        let mut parts = split_message(&text, MESSAGE_LIMIT).into_iter();
+        let first = parts.next().unwrap_or_default();
+        if let Err(e) = command.edit_response(&ctx.http, EditInteractionResponse::new().content(first)).await {
+            status!("Could not answer a Discord command: {}", e);
+            return;
+        }
+        for part in parts {
+            let followup = CreateInteractionResponseFollowup::new().content(part).ephemeral(true);
+            if let Err(e) = command.create_followup(&ctx.http, followup).await {
+                status!("Could not answer a Discord command: {}", e);
+                break;
+            }
+        }
+    }
+
+    async fn message(&self, _ctx: Context, message: Message) {
+        let Some(&me) = self.me.get() else {
+            return;
+        };
+        if message.author.bot || (message.guild_id.is_some() && !message.mentions_user_id(me)) {
+            return;
+        }
+        let text = message
+            .content
+            .replace(&format!("<@{}>", me), "")
+            .replace(&format!("<@!{}>", me), "")
+            .trim()
+            .to_string();
+        if text.is_empty() {
+            return;
+        }
+        let message = Incoming {
+            user: message.author.id.to_string(),
+            channel: message.channel_id.to_string(),
+            text,
+            reply_to: message.channel_id,
+        };
+        let _ = self.incoming.send(message).await;
+    }
+}
+
+impl Handler {
+    /// Whether the user of a command has one of the admin roles. Commands sent in direct
+    /// messages have no roles, so they are refused.
+    fn is_admin(&self, command: &CommandInteraction) -> bool {
+        command
+            .member
+            .as_ref()
+            .is_some_and(|member| member.roles.iter().any(|role| self.settings.admin_roles.contains(&role.to_string())))
+    }
+
+    async fn run_command(&self, command: &CommandInteraction) -> Result<String, server::ApiError> {
+        let options = command.data.options();
+        let string = |options: &[serenity::all::ResolvedOption], name: &str| {
+            options.iter().find(|option| option.name == name).and_then(|option| match option.value {
+                ResolvedValue::String(value) => Some(value.to_string()),
+                _ => None,
+            })
+        };
+        match command.data.name.as_str() {
+            "learn" => {
+                let report = server::call(&self.calls, ApiCall::Learn).await?;
+                Ok(format!(
+                    "Learned {} new facts from {} pages.",
+                    report["facts_added"].as_u64().unwrap_or_default(),
+                    report["pages_fetched"].as_u64().unwrap_or_default()
+                ))
+            }
+            "knowledge" => {
+                let query = options
+                    .iter()
+                    .find_map(|option| match &option.value {
+                        ResolvedValue::SubCommand(options) if option.name == "search" => string(options, "query"),
+                        _ => None,
+                    })
+                    .unwrap_or_default();
+                let facts = server::call(&self.calls, ApiCall::Facts { filter: query, limit: Some(SEARCH_LIMIT) }).await?;
+                let facts: Vec<String> = facts
+                    .as_array()
+                    .into_iter()
+                    .flatten()
+                    .map(|fact| {
+                        let text = fact["text"].as_str().unwrap_or_default();
+                        match fact["source_url"].as_str() {
+                            Some(url) => format!("- {} (<{}>)", text, url),
+                            None => format!("- {}", text),
+                        }
+                    })
+                    .collect();
+                Ok(if facts.is_empty() { "Nothing known about that.".to_string() } else { facts.join("\n") })
+            }
+            "persona" => {
+                let personality = string(&options, "personality");
+                let description = string(&options, "description");
+                let character = if personality.is_some() || description.is_some() {
+                    server::call(&self.calls, ApiCall::UpdateCharacter { personality, description }).await?
+                } else {
+                    server::call(&self.calls, ApiCall::Character).await?
+                };
+                Ok(persona(&character))
+            }
+            "forget" => {
+                let forgotten = server::call(&self.calls, ApiCall::Forget(session(command.channel_id))).await?;
+                Ok(format!(
+                    "Forgot this channel's conversation and {} memories from it.",
+                    forgotten["forgotten"].as_u64().unwrap_or_default()
+                ))
+            }
+            name => Err(server::ApiError::NotFound(format!("There is no command {}", name))),
+        }
+    }
+}
+
+/// The conversation of a channel.
+fn session(channel: ChannelId) -> String {
+    format!("{}/{}", Bot::NAME, channel)
+}
+
+fn persona(character: &Value) -> String {
+    let list = |key: &str| character[key].as_array().into_iter().flatten().filter_map(Value::as_str).collect::<Vec<_>>().join(", ");
+    format!(
+        "**{}**\nPersonality: {}\nDescription: {}\nTraits: {}\nInterests: {}",
+        character["name"].as_str().unwrap_or_default(),
+        character["personality"].as_str().unwrap_or_default(),
+        character["description"].as_str().unwrap_or_default(),
+        list("traits"),
+        list("interests")
+    )
+}
+
+fn commands() -> Vec<CreateCommand> {
+    vec![
+        CreateCommand::new("learn").description("Search and learn about the character now"),
+        CreateCommand::new("knowledge").description("Look through what the character has learned").add_option(
+            CreateCommandOption::new(CommandOptionType::SubCommand, "search", "List the learned facts with a tag or containing some text")
+                .add_sub_option(CreateCommandOption::new(CommandOptionType::String, "query", "A tag name, or text to search for").required(true)),
+        ),
+        CreateCommand::new("persona")
+            .description("Show the character, or change their personality or description")
+            .add_option(CreateCommandOption::new(CommandOptionType::String, "personality", "The new personality"))
+            .add_option(CreateCommandOption::new(CommandOptionType::String, "description", "The new description")),
+        CreateCommand::new("forget").description("Forget this channel's conversation and what was remembered from it"),
+    ]
+}
@@ -0,0 +1,292 @@
+use crate::gateway::{Connector, Incoming};
+use crate::server::Calls;
+use async_trait::async_trait;
+use futures::StreamExt;
+use lettre::message::header::{ContentType, Header, HeaderName, HeaderValue};
+use lettre::message::Mailbox;
+use lettre::transport::smtp::authentication::Credentials;
+use lettre::{AsyncSmtpTransport, AsyncTransport, Tokio1Executor};
+use mail_parser::MessageParser;
+use serde::{Deserialize, Serialize};
+use std::collections::HashSet;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use tokio::net::TcpStream;
+use tokio::sync::mpsc;
+
+/// The secret holding the mailbox's password, for both IMAP and SMTP.
+pub const PASSWORD_NAME: &str = "EMAIL_PASSWORD";
+/// SMTP's port for connections that are TLS from the start; others use STARTTLS.
+const IMPLICIT_TLS_PORT: u16 = 465;
+
+type ImapSession = async_imap::Session<tokio_native_tls::TlsStream<TcpStream>>;
+
+/// The mailbox the character answers.
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct EmailSettings {
+    /// The address the character writes from, such as `alya@example.com`.
+    pub address: String,
+    /// The login for both servers; the address if not given.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub username: Option<String>,
+    pub imap_server: String,
+    #[serde(default = "default_imap_port")]
+    pub imap_port: u16,
+    pub smtp_server: String,
+    #[serde(default = "default_smtp_port")]
+    pub smtp_port: u16,
+    /// The folder the emails to answer arrive in.
+    #[serde(default = "default_mailbox")]
+    pub mailbox: String,
+    /// How often to check for new emails, in seconds.
+    #[serde(default = "default_poll_seconds")]
+    pub poll_seconds: u64,
+}
+
+fn default_imap_port() -> u16 {
+    993
+}
+
+fn default_smtp_port() -> u16 {
+    IMPLICIT_TLS_PORT
+}
+
+fn default_mailbox() -> String {
+    "INBOX".to_string()
+}
+
+fn default_poll_seconds() -> u64 {
+    60
+}
+
+impl EmailSettings {
+    fn username(&self) -> &str {
+        self.username.as_deref().unwrap_or(&self.address)
+    }
+}
+
+/// Marks a reply as sent automatically (RFC 3834), so that other auto-responders leave it
+/// alone rather than answering it.
+#[derive(Clone)]
+struct AutoSubmitted;
+
+impl Header for AutoSubmitted {
+    fn name() -> HeaderName {
+        HeaderName::new_from_ascii_str("Auto-Submitted")
+    }
+
+    fn parse(_: &str) -> Result<Self, Box<dyn std::error::Error + Send + Sync>> {
+        Ok(AutoSubmitted)
+    }
+
+    fn display(&self) -> HeaderValue {
+        HeaderValue::new(Self::name(), "auto-replied".to_string())
+    }
+}
+
+/// An email to answer.
+struct Email {
+    from: Mailbox,
+    subject: String,
+    message_id: Option<String>,
+    references: Vec<String>,
+    date: String,
+    text: String,
+}
+
+/// The mailbox. It is checked every `poll_seconds`, and its unread emails are answered in
+/// character, quoting them, with a conversation for each email thread.
+pub struct Bot {
+    settings: EmailSettings,
+    password: String,
+    from: Mailbox,
+    mailer: AsyncSmtpTransport<Tokio1Executor>,
+    /// The UIDs of the emails being answered, which the next checks leave alone.
+    pending: Mutex<HashSet<u32>>,
+}
+
+impl Bot {
+    /// Writes as `name`.
+    pub fn new(settings: EmailSettings, password: String, name: &str) -> Result<Self, Box<dyn std::error::Error + Send + Sync>> {
+        let from: Mailbox = format!("{} <{}>", name, settings.address).parse()?;
+        let relay = match settings.smtp_port {
+            IMPLICIT_TLS_PORT => AsyncSmtpTransport::<Tokio1Executor>::relay(&settings.smtp_server)?,
+            _ => AsyncSmtpTransport::<Tokio1Executor>::starttls_relay(&settings.smtp_server)?,
+        };
+        let mailer = relay
+            .port(settings.smtp_port)
+            .credentials(Credentials::new(settings.username().to_string(), password.clone()))
+            .build();
+        Ok(Bot { settings, password, from, mailer, pending: Mutex::new(HashSet::new()) })
+    }
+}
+
+/// The email to answer, and its UID in the mailbox.
+pub struct ReplyTo {
+    uid: u32,
+    email: Email,
+}
+
+#[async_trait]
+impl Connector for Bot {
+    type ReplyTo = ReplyTo;
+
+    const NAME: &'static str = "email";
+    const TITLE: &'static str = "email";
+    const PER_USER: bool = false;
+
+    /// Fails only if the first check does.
+    async fn receive(self: Arc<Self>, incoming: mpsc::Sender<Incoming<ReplyTo>>, _calls: Calls) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+        self.mailer.test_connection().await?;
+        let mut first = true;
+        loop {
+            match self.poll(&incoming).await {
+                Ok(()) => {}
+                Err(e) if first => return Err(e),
+                Err(e) => status!("Could not check {}: {}", self.settings.address, e),
+            }
+            if first {
+                status!("Answering the emails to {}", self.settings.address);
+                first = false;
+            }
+            tokio::time::sleep(Duration::from_secs(self.settings.poll_seconds)).await;
+        }
+    }
+
+    async fn send(&self, message: &Incoming<ReplyTo>, reply: &str) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+        let ReplyTo { uid, email } = &message.reply_to;
+        let result = self.reply(*uid, email, reply).await;
+        self.pending.lock().unwrap().remove(uid);
+        result
+    }
+
+    /// Leaves the email unread, so that it is answered on a later check.
+    async fn failed(&self, message: &Incoming<ReplyTo>) {
+        self.pending.lock().unwrap().remove(&message.reply_to.uid);
+    }
+}
+
+async fn login(settings: &EmailSettings, password: &str) -> Result<ImapSession, Box<dyn std::error::Error + Send + Sync>> {
+    let tcp = TcpStream::connect((settings.imap_server.as_str(), settings.imap_port)).await?;
+    let tls = tokio_native_tls::TlsConnector::from(tokio_native_tls::native_tls::TlsConnector::new()?);
+    let stream = tls.connect(&settings.imap_server, tcp).await?;
+    let client = async_imap::Client::new(stream);
+    let mut session = client.login(settings.username(), password).await.map_err(|(e, _)| e)?;
+    session.select(&settings.mailbox).await?;
+    Ok(session)
+}
+
+impl Bot {
+    /// Passes on the unread emails. An email is marked as read once it is answered or found
+    /// to need no answer; one whose reply failed is tried again on a later check.
+    async fn poll(&self, incoming: &mpsc::Sender<Incoming<ReplyTo>>) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+        let mut session = login(&self.settings, &self.password).await?;
+        let unread = session.uid_search("UNSEEN").await?;
+        for uid in unread {
+            if self.pending.lock().unwrap().contains(&uid) {
+                continue;
+            }
+            // PEEK leaves the email unread until it is answered
+            let fetched: Vec<_> = session.uid_fetch(uid.to_string(), "BODY.PEEK[]").await?.collect().await;
+            let Some(raw) = fetched.into_iter().filter_map(Result::ok).find_map(|fetch| fetch.body().map(<[u8]>::to_vec)) else {
+                continue;
+            };
+            let text = parse(&raw, &self.settings.address).map(|email| (new_text(&email.text), email));
+            let Some((text, email)) = text.filter(|(text, _)| !text.is_empty()) else {
+                session.uid_store(uid.to_string(), "+FLAGS (\\Seen)").await?.collect::<Vec<_>>().await;
+                continue;
+            };
+            // The first email of the thread names its conversation
+            let thread = email.references.first().or(email.message_id.as_ref()).cloned().unwrap_or_else(|| email.from.email.to_string());
+            self.pending.lock().unwrap().insert(uid);
+            let message = Incoming {
+                user: email.from.email.to_string(),
+                channel: thread.trim_matches(['<', '>']).replace('/', "_"),
+                text,
+                reply_to: ReplyTo { uid, email },
+            };
+            let _ = incoming.send(message).await;
+        }
+        session.logout().await?;
+        Ok(())
+    }
+
+    /// Sends the reply, quoting the email, and marks the email as answered.
+    async fn reply(&self, uid: u32, email: &Email, reply: &str) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+        let quoted: Vec<String> = email.text.lines().map(|line| format!("> {}", line)).collect();
+        let body = format!("{}\n\nOn {}, {} wrote:\n{}\n", reply, email.date, email.from, quoted.join("\n"));
+        let subject = if email.subject.to_lowercase().starts_with("re:") { email.subject.clone() } else { format!("Re: {}", email.subject) };
+        let mut builder = lettre::Message::builder()
+            .from(self.from.clone())
+            .to(email.from.clone())
+            .subject(subject)
+            .header(ContentType::TEXT_PLAIN)
+            .header(AutoSubmitted);
+        if let Some(id) = &email.message_id {
+            let mut references = email.references.clone();
+            references.push(id.clone());
+            let references: Vec<String> = references.iter().map(|id| format!("<{}>", id)).collect();
+            builder = builder.in_reply_to(format!("<{}>", id)).references(references.join(" "));
+        }
+        self.mailer.send(builder.body(body)?).await?;
+        let mut session = login(&self.settings, &self.password).await?;
+        session.uid_store(uid.to_string(), "+FLAGS (\\Seen \\Answered)").await?.collect::<Vec<_>>().await;
+        session.logout().await?;
+        Ok(())
+    }
+}
+
+/// The email in `raw`, unless it should not be answered: those sent by the character itself,
+/// by mailing lists, and by other auto-responders, so two of them cannot answer each other
+/// forever.
+fn parse(raw: &[u8], own_address: &str) -> Option<Email> {
+    let message = MessageParser::default().parse(raw)?;
+    let sender = message.from()?.first()?;
+    let address = sender.address()?;
+    let automatic = message.header_raw("Auto-Submitted").is_some_and(|value| !value.trim().eq_ignore_ascii_case("no"))
+        || message
+            .header_raw("Precedence")
+            .is_some_and(|value| ["bulk", "list", "junk"].contains(&value.trim().to_lowercase().as_str()))
+        || message.header_raw("List-Id").is_some();
+    if automatic || address.eq_ignore_ascii_case(own_address) {
+        return None;
+    }
+    let from = match sender.name() {
+        Some(name) => format!("{} <{}>", name, address),
+        None => address.to_string(),
+    };
+    let ids = |value: &mail_parser::HeaderValue| -> Vec<String> {
+        match value.as_text_list() {
+            Some(ids) => ids.iter().map(|id| id.to_string()).collect(),
+            None => value.as_text().map(|id| vec![id.to_string()]).unwrap_or_default(),
+        }
+    };
+    let mut references = ids(message.references());
+    if references.is_empty() {
+        references = ids(message.in_reply_to());
+    }
+    Some(Email {
+        from: from.parse().ok()?,
+        subject: message.subject().unwrap_or_default().to_string(),
+        message_id: message.message_id().map(str::to_string),
+        references,
+        date: message.header_raw("Date").unwrap_or_default().trim().to_string(),
+        text: message.body_text(0).unwrap_or_default().trim().to_string(),
+    })
+}
+
+/// The new part of an email's text, without what it quotes.
+fn new_text(text: &str) -> String {
+    let mut lines = Vec::new();
+    for line in text.lines() {
+        if line.starts_with('>') {
+            continue;
+        }
+        // The "On ..., ... wrote:" line most clients put above the quote ends the new part
+        if line.trim_end().ends_with("wrote:") {
+            break;
+        }
+        lines.push(line);
+    }
+    lines.join("\n").trim().to_string()
+}
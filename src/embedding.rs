@@ -0,0 +1,187 @@
+use crate::secrets;
+use async_trait::async_trait;
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+
+/// Gemini accepts at most this many texts per batch request.
+const GEMINI_MAX_BATCH: usize = 100;
+
+/// Which embedder implementation to use.
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Eq, Default)]
+#[serde(rename_all = "lowercase")]
+pub enum EmbedderKind {
+    #[default]
+    Gemini,
+    /// Runs a MiniLM model in-process; requires the `local-embeddings` feature.
+    Local,
+}
+
+/// Creates the configured embedder, falling back to Gemini when the local model is unavailable.
+pub fn create_embedder(kind: EmbedderKind, model: Option<&str>) -> Box<dyn Embedder> {
+    match kind {
+        EmbedderKind::Gemini => Box::new(GeminiEmbedder::new(model.unwrap_or(GeminiEmbedder::DEFAULT_MODEL))),
+        #[cfg(feature = "local-embeddings")]
+        EmbedderKind::Local => match LocalEmbedder::new(model.unwrap_or(LocalEmbedder::DEFAULT_MODEL)) {
+            Ok(embedder) => Box::new(embedder),
+            Err(e) => {
+                status!("Could not load local embedding model ({}), using Gemini embeddings", e);
+                Box::new(GeminiEmbedder::new(GeminiEmbedder::DEFAULT_MODEL))
+            }
+        },
+        #[cfg(not(feature = "local-embeddings"))]
+        EmbedderKind::Local => {
+            status!("Local embeddings need a build with `--features local-embeddings`, using Gemini embeddings");
+            Box::new(GeminiEmbedder::new(GeminiEmbedder::DEFAULT_MODEL))
+        }
+    }
+}
+
+/// Turns text into vectors for fact indexing, duplicate detection and retrieval.
+#[async_trait]
+pub trait Embedder: Send + Sync {
+    /// Identifies the vector space; embeddings from different models are not comparable.
+    fn model(&self) -> &str;
+
+    async fn embed(&self, text: &str) -> Result<Vec<f32>, Box<dyn std::error::Error>> {
+        let mut embeddings = self.embed_batch(&[text.to_string()]).await?;
+        embeddings.pop().ok_or_else(|| "Embedder returned no embedding".into())
+    }
+
+    async fn embed_batch(&self, texts: &[String]) -> Result<Vec<Vec<f32>>, Box<dyn std::error::Error>>;
+}
+
+pub struct GeminiEmbedder {
+    model: String,
+    client: reqwest::Client,
+}
+
+impl GeminiEmbedder {
+    pub const DEFAULT_MODEL: &'static str = "text-embedding-004";
+
+    pub fn new(model: &str) -> Self {
+        GeminiEmbedder {
+            model: model.to_string(),
+            client: reqwest::Client::builder()
+                .timeout(std::time::Duration::from_secs(30))
+                .build()
+                .unwrap_or_default(),
+        }
+    }
+}
+
+#[async_trait]
+impl Embedder for GeminiEmbedder {
+    fn model(&self) -> &str {
+        &self.model
+    }
+
+    async fn embed_batch(&self, texts: &[String]) -> Result<Vec<Vec<f32>>, Box<dyn std::error::Error>> {
+        let api_key = secrets::require("GEMINI_API_KEY")?;
+        let mut embeddings = Vec::with_capacity(texts.len());
+
+        for batch in texts.chunks(GEMINI_MAX_BATCH) {
+            let requests: Vec<Value> = batch
+                .iter()
+                .map(|text| {
+                    json!({
+                        "model": format!("models/{}", self.model),
+                        "content": {
+                            "parts": [{
+                                "text": text
+                            }]
+                        }
+                    })
+                })
+                .collect();
+
+            let response = self
+                .client
+                .post(format!(
+                    "https://generativelanguage.googleapis.com/v1beta/models/{}:batchEmbedContents",
+                    self.model
+                ))
+                .header("x-goog-api-key", &api_key)
+                .json(&json!({ "requests": requests }))
+                .send()
+                .await?;
+
+            let response_json: Value = response.json().await?;
+            let batch_embeddings = response_json
+                .get("embeddings")
+                .and_then(|embeddings| embeddings.as_array())
+                .ok_or("Embedding response did not contain any embeddings")?;
+            if batch_embeddings.len() != batch.len() {
+                return Err("Embedding response size does not match the request".into());
+            }
+
+            for embedding in batch_embeddings {
+                let values = embedding
+                    .get("values")
+                    .and_then(|values| values.as_array())
+                    .ok_or("Embedding without values")?;
+                embeddings.push(values.iter().filter_map(|v| v.as_f64()).map(|v| v as f32).collect());
+            }
+        }
+
+        Ok(embeddings)
+    }
+}
+
+#[cfg(feature = "local-embeddings")]
+pub use local::LocalEmbedder;
+
+#[cfg(feature = "local-embeddings")]
+mod local {
+    use super::Embedder;
+    use async_trait::async_trait;
+    use fastembed::{EmbeddingModel, InitOptions, TextEmbedding};
+    use std::sync::Arc;
+
+    /// Offline embedder running an ONNX sentence-transformer through fastembed.
+    pub struct LocalEmbedder {
+        model_name: String,
+        model: Arc<TextEmbedding>,
+    }
+
+    impl LocalEmbedder {
+        pub const DEFAULT_MODEL: &'static str = "all-MiniLM-L6-v2";
+
+        pub fn new(model_name: &str) -> Result<Self, Box<dyn std::error::Error>> {
+            let model = match model_name {
+                "all-MiniLM-L6-v2" => EmbeddingModel::AllMiniLML6V2,
+                "all-MiniLM-L12-v2" => EmbeddingModel::AllMiniLML12V2,
+                "bge-small-en-v1.5" => EmbeddingModel::BGESmallENV15,
+                "multilingual-e5-small" => EmbeddingModel::MultilingualE5Small,
+                other => return Err(format!("Unsupported local embedding model: {}", other).into()),
+            };
+            status!("Loading local embedding model {}...", model_name);
+            let model = TextEmbedding::try_new(
+                InitOptions::new(model)
+                    .with_cache_dir(crate::paths::data_path("models"))
+                    .with_show_download_progress(true),
+            )
+            .map_err(|e| e.to_string())?;
+            Ok(LocalEmbedder {
+                model_name: model_name.to_string(),
+                model: Arc::new(model),
+            })
+        }
+    }
+
+    #[async_trait]
+    impl Embedder for LocalEmbedder {
+        fn model(&self) -> &str {
+            &self.model_name
+        }
+
+        async fn embed_batch(&self, texts: &[String]) -> Result<Vec<Vec<f32>>, Box<dyn std::error::Error>> {
+            let model = Arc::clone(&self.model);
+            let texts = texts.to_vec();
+            // Inference is CPU-bound; keep it off the async worker threads
+            let embeddings = tokio::task::spawn_blocking(move || model.embed(texts, None))
+                .await?
+                .map_err(|e| e.to_string())?;
+            Ok(embeddings)
+        }
+    }
+}
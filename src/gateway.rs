@@ -0,0 +1,246 @@
+use crate::server::{self, ApiCall, Calls, Limiter};
+use async_trait::async_trait;
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::sync::mpsc;
+use tokio::task::JoinSet;
+
+/// What the character says when it could not answer.
+pub const APOLOGY: &str = "Sorry, I couldn't answer that just now.";
+
+/// A message for the character to answer.
+pub struct Incoming<R> {
+    /// Who wrote it, in the platform's terms: a user ID, an address or a nick.
+    pub user: String,
+    /// Where it was written: a channel, room, chat or email thread, or the user themselves
+    /// for a direct message.
+    pub channel: String,
+    pub text: String,
+    /// What else the connector needs to answer it, such as the platform's ID of the message.
+    pub reply_to: R,
+}
+
+/// A chat platform the character talks on. The connector receives the messages and sends
+/// the replies; the gateway has the chatbot answer them.
+#[async_trait]
+pub trait Connector: Send + Sync + 'static {
+    type ReplyTo: Send + Sync + 'static;
+
+    /// The platform in lowercase, which starts the sessions of its conversations and names
+    /// its config section, such as "telegram".
+    const NAME: &'static str;
+    /// The platform as shown in messages, such as "Telegram".
+    const TITLE: &'static str;
+    /// Whether each user has a conversation of their own wherever they write, rather than
+    /// each channel having one.
+    const PER_USER: bool;
+    /// How long the platform shows that the character is typing; it is shown again this
+    /// often until the reply is sent.
+    const TYPING_EVERY: Duration = Duration::from_secs(3);
+
+    /// Connects and passes on the messages to answer until the connection fails for good;
+    /// dropped connections are the connector's to restore. `calls` is for what the platform
+    /// offers besides the chat, such as Discord's slash commands.
+    async fn receive(
+        self: Arc<Self>,
+        incoming: mpsc::Sender<Incoming<Self::ReplyTo>>,
+        calls: Calls,
+    ) -> Result<(), Box<dyn std::error::Error + Send + Sync>>;
+
+    /// Shows that the character is writing a reply to `message`, where the platform can.
+    async fn typing(&self, _message: &Incoming<Self::ReplyTo>) {}
+
+    /// Sends the character's reply to `message`.
+    async fn send(&self, message: &Incoming<Self::ReplyTo>, reply: &str) -> Result<(), Box<dyn std::error::Error + Send + Sync>>;
+
+    /// Called when the chatbot could not answer `message`: sends an apology, unless the
+    /// connector can do better, such as trying again later.
+    async fn failed(&self, message: &Incoming<Self::ReplyTo>) {
+        if let Err(e) = self.send(message, APOLOGY).await {
+            status!("Could not send a reply on {}: {}", Self::TITLE, e);
+        }
+    }
+}
+
+/// How a connector ended: its title and why.
+pub type Stopped = (&'static str, Result<(), Box<dyn std::error::Error + Send + Sync>>);
+
+/// Starts a connector, whatever its type.
+trait Start: Send {
+    fn title(&self) -> &'static str;
+
+    fn start(self: Box<Self>, calls: Calls, limits: Limits, running: &mut JoinSet<Stopped>);
+}
+
+/// The rate limiter the connectors share with the APIs, and the character's name for its
+/// throttle message.
+#[derive(Clone)]
+pub struct Limits {
+    pub limiter: Limiter,
+    pub name: String,
+}
+
+impl<C: Connector> Start for C {
+    fn title(&self) -> &'static str {
+        C::TITLE
+    }
+
+    fn start(self: Box<Self>, calls: Calls, limits: Limits, running: &mut JoinSet<Stopped>) {
+        running.spawn(serve::<C>(Arc::from(self), calls, limits));
+    }
+}
+
+/// Runs several connectors side by side against one chatbot.
+#[derive(Default)]
+pub struct Gateway {
+    connectors: Vec<Box<dyn Start>>,
+}
+
+impl Gateway {
+    pub fn add<C: Connector>(&mut self, connector: C) {
+        self.connectors.push(Box::new(connector));
+    }
+
+    /// The platforms, as shown in messages.
+    pub fn titles(&self) -> Vec<&'static str> {
+        self.connectors.iter().map(|connector| connector.title()).collect()
+    }
+
+    /// Connects every connector, each sending its chats over `calls` within `limits`. Each
+    /// ends in the set when it stops.
+    pub fn start(self, calls: Calls, limits: Limits) -> JoinSet<Stopped> {
+        let mut running = JoinSet::new();
+        for connector in self.connectors {
+            connector.start(calls.clone(), limits.clone(), &mut running);
+        }
+        running
+    }
+}
+
+/// Answers the messages a connector receives until it stops. Each is answered in the
+/// background, so the connector keeps receiving meanwhile.
+async fn serve<C: Connector>(connector: Arc<C>, calls: Calls, limits: Limits) -> Stopped {
+    let (sender, mut incoming) = mpsc::channel(16);
+    let mut receiving = tokio::spawn(connector.clone().receive(sender, calls.clone()));
+    let result = loop {
+        tokio::select! {
+            Some(message) = incoming.recv() => {
+                let (connector, calls, limits) = (connector.clone(), calls.clone(), limits.clone());
+                tokio::spawn(async move { answer(&*connector, &calls, &limits, message).await });
+            }
+            result = &mut receiving => break result.map_err(Into::into).and_then(|result| result),
+        }
+    };
+    (C::TITLE, result)
+}
+
+/// Has the chatbot answer `message` and sends the reply, or the throttle message when the
+/// conversation is over a rate limit.
+async fn answer<C: Connector>(connector: &C, calls: &Calls, limits: &Limits, message: Incoming<C::ReplyTo>) {
+    let conversation = if C::PER_USER { &message.user } else { &message.channel };
+    let session = format!("{}/{}", C::NAME, conversation);
+    let throttled = {
+        let mut limiter = limits.limiter.lock().unwrap();
+        limiter.admit(&session).err().map(|wait| limiter.throttle_message(&limits.name, wait))
+    };
+    if let Some(text) = throttled {
+        if let Err(e) = connector.send(&message, &text).await {
+            status!("Could not send a reply on {}: {}", C::TITLE, e);
+        }
+        return;
+    }
+    let chat = ApiCall::Chat {
+        session,
+        message: message.text.clone(),
+        stream: None,
+    };
+    let reply = server::call(calls, chat);
+    tokio::pin!(reply);
+    let mut typing = tokio::time::interval(C::TYPING_EVERY);
+    let reply = loop {
+        tokio::select! {
+            reply = &mut reply => break reply,
+            _ = typing.tick() => connector.typing(&message).await,
+        }
+    };
+    match reply {
+        Ok(reply) => {
+            limits.limiter.lock().unwrap().record_tokens(reply["tokens"].as_u64().unwrap_or_default());
+            if let Err(e) = connector.send(&message, reply["text"].as_str().unwrap_or_default()).await {
+                status!("Could not send a reply on {}: {}", C::TITLE, e);
+            }
+        }
+        Err(e) => {
+            status!("Could not answer a message on {}: {}", C::TITLE, e);
+            connector.failed(&message).await;
+        }
+    }
+}
+
+/// Splits `text` into messages of at most `limit` characters, at line breaks where it can,
+/// then at spaces, and mid-word only for words longer than a message.
+pub fn split_message(text: &str, limit: usize) -> Vec<String> {
+    let mut parts = Vec::new();
+    let mut rest = text.trim();
+    while rest.chars().count() > limit {
+        let end = rest.char_indices().nth(limit).map_or(rest.len(), |(index, _)| index);
+        let head = &rest[..end];
+        let cut = head.rfind('\n').or_else(|| head.rfind(' ')).filter(|&cut| cut > 0).unwrap_or(end);
+        parts.push(rest[..cut].trim_end().to_string());
+        rest = rest[cut..].trim_start();
+    }
+    if !rest.is_empty() {
+        parts.push(rest.to_string());
+    }
+    parts
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn short_text_is_one_message() {
+        assert_eq!(split_message("  Hello there!\n", 2000), vec!["Hello there!"]);
+    }
+
+    #[test]
+    fn empty_text_is_no_message() {
+        assert!(split_message("", 10).is_empty());
+        assert!(split_message(" \n ", 10).is_empty());
+    }
+
+    #[test]
+    fn splits_at_line_breaks_first() {
+        let parts = split_message("first line\nsecond line here", 20);
+        assert_eq!(parts, vec!["first line", "second line here"]);
+    }
+
+    #[test]
+    fn splits_at_spaces_without_line_breaks() {
+        let parts = split_message("one two three four five", 11);
+        assert_eq!(parts, vec!["one two", "three four", "five"]);
+        assert!(parts.iter().all(|part| part.chars().count() <= 11));
+    }
+
+    #[test]
+    fn splits_long_words_mid_word() {
+        assert_eq!(split_message("abcdefghij", 4), vec!["abcd", "efgh", "ij"]);
+    }
+
+    #[test]
+    fn counts_characters_rather_than_bytes() {
+        let text = "привет мир";
+        assert_eq!(split_message(text, 10), vec![text]);
+        let parts = split_message("ありがとうございます", 4);
+        assert_eq!(parts, vec!["ありがと", "うござい", "ます"]);
+    }
+
+    #[test]
+    fn keeps_every_word() {
+        let text = "Alya looks away, her cheeks red. ".repeat(20);
+        let parts = split_message(&text, 50);
+        assert!(parts.iter().all(|part| part.chars().count() <= 50));
+        assert_eq!(parts.join(" ").split_whitespace().collect::<Vec<_>>(), text.split_whitespace().collect::<Vec<_>>());
+    }
+}
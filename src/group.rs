@@ -0,0 +1,108 @@
+use crate::config_format::ENV_PREFIX;
+use crate::paths;
+use serde_json::{json, Value};
+use std::path::PathBuf;
+use std::process::Stdio;
+use std::time::Duration;
+use tokio::io::{AsyncBufReadExt, AsyncWriteExt, BufReader};
+use tokio::process::{Child, ChildStdin, ChildStdout, Command};
+
+/// How long a character has to answer, its model's requests included.
+const TIMEOUT: Duration = Duration::from_secs(180);
+
+/// Where a character of a group conversation is configured.
+pub enum Source {
+    /// A character installed with `character install`, by name.
+    Installed(String),
+    /// A configuration file and data directory, such as the current character's.
+    Files { config: PathBuf, data_dir: PathBuf },
+}
+
+/// A character taking part in a group conversation. Each runs as an `alya --stdio` process
+/// of its own, with its own configuration, knowledge and memory of the conversation.
+pub struct Member {
+    pub name: String,
+    // Kept so the process is killed with the member
+    _child: Child,
+    stdin: ChildStdin,
+    stdout: BufReader<ChildStdout>,
+    next_id: u64,
+}
+
+impl Member {
+    /// Starts the character, which does not learn at startup, so the conversation begins
+    /// right away. Its progress messages go to stderr.
+    pub async fn start(source: &Source) -> Result<Self, Box<dyn std::error::Error>> {
+        let mut command = Command::new(std::env::current_exe()?);
+        // The directories are given in full, as they may not be the child's defaults
+        command.arg("--config-dir").arg(paths::config_dir());
+        match source {
+            Source::Installed(name) => {
+                // The overrides are the current character's settings, not the others'
+                for (variable, _) in std::env::vars().filter(|(variable, _)| variable.starts_with(ENV_PREFIX)) {
+                    command.env_remove(variable);
+                }
+                command.arg("--data-dir").arg(paths::character_data_dir(name)).args(["--character", name])
+            }
+            Source::Files { config, data_dir } => command.arg("--config").arg(config).arg("--data-dir").arg(data_dir),
+        };
+        let mut child = command
+            .args(["--stdio", "--no-initial-learn"])
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::inherit())
+            .kill_on_drop(true)
+            .spawn()?;
+        let stdin = child.stdin.take().ok_or("The character has no stdin")?;
+        let stdout = child.stdout.take().ok_or("The character has no stdout")?;
+        let mut member = Member {
+            name: String::new(),
+            _child: child,
+            stdin,
+            stdout: BufReader::new(stdout),
+            next_id: 1,
+        };
+        let initialized = member.request("initialize", json!({})).await?;
+        member.name = initialized["character"]["name"].as_str().unwrap_or_default().to_string();
+        Ok(member)
+    }
+
+    /// Has the character answer `message` in its conversation `session`.
+    pub async fn chat(&mut self, session: &str, message: &str) -> Result<String, Box<dyn std::error::Error>> {
+        let reply = self.request("chat", json!({ "message": message, "session": session })).await?;
+        Ok(reply["text"].as_str().unwrap_or_default().trim().to_string())
+    }
+
+    /// Stops the character's process.
+    pub async fn shutdown(mut self) {
+        let _ = self.request("shutdown", Value::Null).await;
+    }
+
+    async fn request(&mut self, method: &str, params: Value) -> Result<Value, Box<dyn std::error::Error>> {
+        let id = self.next_id;
+        self.next_id += 1;
+        let request = json!({ "jsonrpc": "2.0", "id": id, "method": method, "params": params });
+        self.stdin.write_all(format!("{}\n", request).as_bytes()).await?;
+        self.stdin.flush().await?;
+        let name = if self.name.is_empty() { "The character" } else { &self.name };
+        let answer = tokio::time::timeout(TIMEOUT, async {
+            loop {
+                let mut line = String::new();
+                if self.stdout.read_line(&mut line).await? == 0 {
+                    return Err(format!("{} stopped", name).into());
+                }
+                // Notifications, such as the chunks of a streamed reply, are skipped
+                match serde_json::from_str::<Value>(&line) {
+                    Ok(message) if message["id"] == id => return Ok::<Value, Box<dyn std::error::Error>>(message),
+                    _ => continue,
+                }
+            }
+        })
+        .await
+        .map_err(|_| format!("{} did not answer in time", name))??;
+        if let Some(error) = answer.get("error") {
+            return Err(format!("{} could not answer: {}", name, error["message"].as_str().unwrap_or_default()).into());
+        }
+        Ok(answer["result"].clone())
+    }
+}
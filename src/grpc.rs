@@ -0,0 +1,212 @@
+use crate::auth::{Auth, Caller, Scope};
+use crate::server::{self, ApiCall, ApiError, Calls, Limiter};
+use futures::stream::{self, Stream, StreamExt};
+use serde_json::Value;
+use std::net::SocketAddr;
+use std::pin::Pin;
+use std::sync::Arc;
+use tokio::sync::{mpsc, oneshot};
+use tonic::{Request, Response, Status};
+
+pub mod proto {
+    tonic::include_proto!("alya.v1");
+}
+
+use proto::alya_server::{Alya, AlyaServer};
+use proto::chat_event::Event;
+
+impl From<ApiError> for Status {
+    fn from(error: ApiError) -> Status {
+        match error {
+            ApiError::BadRequest(message) => Status::invalid_argument(message),
+            ApiError::Unauthorized(message) => Status::unauthenticated(message),
+            ApiError::Forbidden(message) => Status::permission_denied(message),
+            ApiError::NotFound(message) => Status::not_found(message),
+            ApiError::Throttled(text, retry_after) => {
+                let mut status = Status::resource_exhausted(text);
+                status.metadata_mut().insert("retry-after", retry_after.into());
+                status
+            }
+            ApiError::Internal(message) => Status::internal(message),
+        }
+    }
+}
+
+/// The gRPC API, which sends its calls to the chatbot as the HTTP API does.
+struct Service {
+    calls: Calls,
+    auth: Option<Arc<Auth>>,
+    limiter: Limiter,
+    /// The character's name, for the throttle message.
+    name: String,
+}
+
+/// Serves the gRPC API on `address` until the server fails. It shares the chatbot, the
+/// credentials and the rate limits with the HTTP API.
+pub async fn serve(
+    address: SocketAddr,
+    calls: Calls,
+    auth: Option<Arc<Auth>>,
+    limiter: Limiter,
+    name: String,
+) -> Result<(), tonic::transport::Error> {
+    let service = Service { calls, auth, limiter, name };
+    tonic::transport::Server::builder()
+        .add_service(AlyaServer::new(service))
+        .serve(address)
+        .await
+}
+
+impl Service {
+    /// Who made a request, if its credentials allow `needed`; `None` without authentication.
+    fn caller<T>(&self, request: &Request<T>, needed: Scope) -> Result<Option<Caller>, Status> {
+        match &self.auth {
+            Some(auth) => Ok(Some(server::authorize(auth, request.metadata().as_ref(), needed)?)),
+            None => Ok(None),
+        }
+    }
+
+    /// The session of a chat request, counted against the rate limits.
+    fn admit(&self, request: &Request<proto::ChatRequest>) -> Result<String, Status> {
+        let caller = self.caller(request, Scope::Chat)?;
+        let chat = request.get_ref();
+        if chat.message.trim().is_empty() {
+            return Err(Status::invalid_argument("The message is empty"));
+        }
+        let session = server::session(caller.as_ref(), chat.user.as_deref())?;
+        let mut limiter = self.limiter.lock().unwrap();
+        if let Err(wait) = limiter.admit(&session) {
+            return Err(limiter.throttled(&self.name, wait).into());
+        }
+        Ok(session)
+    }
+
+    async fn call(&self, call: ApiCall) -> Result<Value, Status> {
+        Ok(server::call(&self.calls, call).await?)
+    }
+}
+
+fn reply(reply: &Value) -> proto::ChatReply {
+    proto::ChatReply {
+        text: reply["text"].as_str().unwrap_or_default().to_string(),
+        sources: strings(&reply["sources"]),
+        fact_keys: strings(&reply["fact_keys"]),
+        model_requests: reply["model_requests"].as_u64().unwrap_or_default(),
+        tokens: reply["tokens"].as_u64().unwrap_or_default(),
+        latency_ms: reply["latency_ms"].as_u64().unwrap_or_default(),
+    }
+}
+
+/// A fact, from the JSON the HTTP API shows it as.
+fn fact(fact: &Value) -> proto::Fact {
+    proto::Fact {
+        key: fact["key"].as_str().unwrap_or_default().to_string(),
+        text: fact["text"].as_str().unwrap_or_default().to_string(),
+        source_url: fact["source_url"].as_str().map(str::to_string),
+        method: fact["method"].as_str().unwrap_or_default().to_string(),
+        tags: strings(&fact["tags"]),
+        learned_at: fact["learned_at"].as_str().unwrap_or_default().to_string(),
+        verified_at: fact["verified_at"].as_str().unwrap_or_default().to_string(),
+        confidence: fact["confidence"].as_f64().unwrap_or_default(),
+    }
+}
+
+fn strings(value: &Value) -> Vec<String> {
+    value.as_array().into_iter().flatten().filter_map(|item| item.as_str()).map(str::to_string).collect()
+}
+
+type ChatEvents = Pin<Box<dyn Stream<Item = Result<proto::ChatEvent, Status>> + Send>>;
+
+#[tonic::async_trait]
+impl Alya for Service {
+    async fn chat(&self, request: Request<proto::ChatRequest>) -> Result<Response<proto::ChatReply>, Status> {
+        let session = self.admit(&request)?;
+        let message = request.into_inner().message;
+        let answer = self.call(ApiCall::Chat { session, message, stream: None }).await?;
+        self.limiter.lock().unwrap().record_tokens(answer["tokens"].as_u64().unwrap_or_default());
+        Ok(Response::new(reply(&answer)))
+    }
+
+    type ChatStreamStream = ChatEvents;
+
+    async fn chat_stream(&self, request: Request<proto::ChatRequest>) -> Result<Response<ChatEvents>, Status> {
+        let session = self.admit(&request)?;
+        let message = request.into_inner().message;
+        let (chunks, written) = mpsc::unbounded_channel();
+        let (answer, answered) = oneshot::channel();
+        let chat = ApiCall::Chat { session, message, stream: Some(chunks) };
+        self.calls
+            .send((chat, answer))
+            .await
+            .map_err(|_| Status::internal("The chatbot has stopped"))?;
+
+        // The chunks end when the chatbot is done with the call and drops its sender
+        let chunks = stream::unfold(written, |mut written| async move {
+            let chunk = written.recv().await?;
+            Some((Ok(proto::ChatEvent { event: Some(Event::Chunk(chunk)) }), written))
+        });
+        let limiter = self.limiter.clone();
+        let end = stream::once(async move {
+            let answer = answered.await.map_err(|_| Status::internal("The chatbot has stopped"))??;
+            limiter.lock().unwrap().record_tokens(answer["tokens"].as_u64().unwrap_or_default());
+            Ok(proto::ChatEvent { event: Some(Event::Reply(reply(&answer))) })
+        });
+        Ok(Response::new(Box::pin(chunks.chain(end))))
+    }
+
+    async fn get_character(&self, request: Request<proto::GetCharacterRequest>) -> Result<Response<proto::Character>, Status> {
+        self.caller(&request, Scope::Chat)?;
+        let character = self.call(ApiCall::Character).await?;
+        Ok(Response::new(proto::Character {
+            name: character["name"].as_str().unwrap_or_default().to_string(),
+            personality: character["personality"].as_str().unwrap_or_default().to_string(),
+            description: character["description"].as_str().unwrap_or_default().to_string(),
+            traits: strings(&character["traits"]),
+            interests: strings(&character["interests"]),
+        }))
+    }
+
+    async fn list_facts(&self, request: Request<proto::ListFactsRequest>) -> Result<Response<proto::ListFactsResponse>, Status> {
+        self.caller(&request, Scope::Chat)?;
+        let request = request.into_inner();
+        let limit = request.limit.map(|limit| limit as usize);
+        let facts = self.call(ApiCall::Facts { filter: request.query, limit }).await?;
+        let facts = facts.as_array().into_iter().flatten().map(fact).collect();
+        Ok(Response::new(proto::ListFactsResponse { facts }))
+    }
+
+    async fn get_fact(&self, request: Request<proto::GetFactRequest>) -> Result<Response<proto::Fact>, Status> {
+        self.caller(&request, Scope::Chat)?;
+        let found = self.call(ApiCall::Fact(request.into_inner().key)).await?;
+        Ok(Response::new(fact(&found)))
+    }
+
+    async fn create_facts(&self, request: Request<proto::CreateFactsRequest>) -> Result<Response<proto::CreateFactsResponse>, Status> {
+        self.caller(&request, Scope::Admin)?;
+        let text = request.into_inner().text;
+        if text.trim().is_empty() {
+            return Err(Status::invalid_argument("The text is empty"));
+        }
+        let trained = self.call(ApiCall::Train(text)).await?;
+        Ok(Response::new(proto::CreateFactsResponse {
+            facts_added: trained["facts_added"].as_u64().unwrap_or_default(),
+        }))
+    }
+
+    async fn update_fact(&self, request: Request<proto::UpdateFactRequest>) -> Result<Response<proto::Fact>, Status> {
+        self.caller(&request, Scope::Admin)?;
+        let proto::UpdateFactRequest { key, text } = request.into_inner();
+        if text.trim().is_empty() {
+            return Err(Status::invalid_argument("The text is empty"));
+        }
+        let updated = self.call(ApiCall::UpdateFact { key, text }).await?;
+        Ok(Response::new(fact(&updated)))
+    }
+
+    async fn delete_fact(&self, request: Request<proto::DeleteFactRequest>) -> Result<Response<proto::DeleteFactResponse>, Status> {
+        self.caller(&request, Scope::Admin)?;
+        let key = request.into_inner().key;
+        self.call(ApiCall::DeleteFact(key.clone())).await?;
+        Ok(Response::new(proto::DeleteFactResponse { key }))
+    }
+}
@@ -0,0 +1,127 @@
+use crate::knowledge::Knowledge;
+use crate::storage::Codec;
+use chrono::{DateTime, Utc};
+use flate2::read::GzDecoder;
+use flate2::write::GzEncoder;
+use flate2::Compression;
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::io::Read;
+use std::path::{Path, PathBuf};
+
+const LOG_FILE: &str = "log.json";
+
+/// One saved version of the knowledge, listed by `knowledge history`.
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct Snapshot {
+    pub id: u64,
+    pub taken_at: DateTime<Utc>,
+    pub fact_count: usize,
+    /// Facts added, changed and removed since the previous snapshot.
+    pub added: usize,
+    pub changed: usize,
+    pub removed: usize,
+    pub fingerprint: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub note: Option<String>,
+}
+
+/// Snapshot log of every knowledge save, kept as gzipped JSON files next to a
+/// `log.json` index so a bad learning run can be rolled back. Independent of the
+/// storage backend, but encrypted along with it.
+pub struct KnowledgeHistory {
+    dir: PathBuf,
+    /// Number of snapshots to keep; 0 turns the history off.
+    keep: usize,
+    codec: Codec,
+}
+
+impl KnowledgeHistory {
+    pub fn new(dir: &Path, keep: usize, codec: Codec) -> Self {
+        KnowledgeHistory {
+            dir: dir.to_path_buf(),
+            keep,
+            codec,
+        }
+    }
+
+    /// Whether snapshots are kept at all.
+    pub fn is_enabled(&self) -> bool {
+        self.keep > 0
+    }
+
+    /// Snapshots from oldest to newest.
+    pub fn list(&self) -> Result<Vec<Snapshot>, Box<dyn std::error::Error>> {
+        let path = self.dir.join(LOG_FILE);
+        if !path.exists() {
+            return Ok(Vec::new());
+        }
+        Ok(serde_json::from_str(&fs::read_to_string(path)?)?)
+    }
+
+    /// Records `knowledge` unless it is identical to the newest snapshot, pruning the
+    /// oldest ones beyond the limit. Returns the id of the new snapshot.
+    pub fn record(&self, knowledge: &Knowledge, note: Option<String>) -> Result<Option<u64>, Box<dyn std::error::Error>> {
+        if self.keep == 0 {
+            return Ok(None);
+        }
+        let mut log = self.list()?;
+        let fingerprint = knowledge.fingerprint();
+        if log.last().is_some_and(|last| last.fingerprint == fingerprint) {
+            return Ok(None);
+        }
+
+        let previous = log.last().and_then(|last| self.load(last.id).ok()).unwrap_or_default();
+        let (added, changed, removed) = diff(&previous, knowledge);
+        let id = log.last().map_or(1, |last| last.id + 1);
+
+        fs::create_dir_all(&self.dir)?;
+        let mut encoder = GzEncoder::new(Vec::new(), Compression::default());
+        serde_json::to_writer(&mut encoder, knowledge)?;
+        fs::write(self.snapshot_path(id), self.codec.seal(encoder.finish()?)?)?;
+
+        log.push(Snapshot {
+            id,
+            taken_at: Utc::now(),
+            fact_count: knowledge.facts.len(),
+            added,
+            changed,
+            removed,
+            fingerprint,
+            note,
+        });
+        let excess = log.len().saturating_sub(self.keep);
+        for old in log.drain(..excess) {
+            let _ = fs::remove_file(self.snapshot_path(old.id));
+        }
+        fs::write(self.dir.join(LOG_FILE), serde_json::to_string_pretty(&log)?)?;
+        Ok(Some(id))
+    }
+
+    /// Reads a snapshot back, upgrading it if it predates the current schema.
+    pub fn load(&self, id: u64) -> Result<Knowledge, Box<dyn std::error::Error>> {
+        let bytes = fs::read(self.snapshot_path(id))
+            .map_err(|_| format!("No snapshot #{} in {}", id, self.dir.display()))?;
+        let mut json = String::new();
+        GzDecoder::new(self.codec.unseal(&bytes)?.as_slice()).read_to_string(&mut json)?;
+        Knowledge::from_json(serde_json::from_str(&json)?)
+    }
+
+    fn snapshot_path(&self, id: u64) -> PathBuf {
+        self.dir.join(format!("{:06}.json.gz", id))
+    }
+}
+
+fn diff(before: &Knowledge, after: &Knowledge) -> (usize, usize, usize) {
+    let mut added = 0;
+    let mut changed = 0;
+    for (key, fact) in &after.facts {
+        match before.facts.get(key) {
+            None => added += 1,
+            Some(old) if serde_json::to_value(old).ok() != serde_json::to_value(fact).ok() => changed += 1,
+            Some(_) => {}
+        }
+    }
+    let removed = before.facts.keys().filter(|key| !after.facts.contains_key(*key)).count();
+    (added, changed, removed)
+}
@@ -0,0 +1,251 @@
+use scraper::Html;
+use serde_json::{json, Value};
+use url::Url;
+
+const ENDPOINT: &str = "https://graphql.anilist.co";
+/// Characters of the character's best-known work listed as related to them.
+const MAX_RELATED: usize = 12;
+
+const CHARACTER_QUERY: &str = r#"
+query ($search: String) {
+  Character(search: $search) {
+    id
+    siteUrl
+    name { full native alternative }
+    description
+    gender
+    age
+    bloodType
+    dateOfBirth { year month day }
+    media(sort: POPULARITY_DESC, perPage: 10) {
+      edges {
+        characterRole
+        japanese: voiceActors(language: JAPANESE) { name { full } }
+        english: voiceActors(language: ENGLISH) { name { full } }
+        node {
+          type
+          format
+          startDate { year }
+          title { romaji english }
+          characters(sort: [ROLE, RELEVANCE, ID], perPage: 15) {
+            edges { role node { id name { full } } }
+          }
+        }
+      }
+    }
+  }
+}
+"#;
+
+/// A character's entry on AniList.
+#[derive(Debug)]
+pub struct AniListCharacter {
+    pub name: String,
+    pub native_name: Option<String>,
+    pub alternative_names: Vec<String>,
+    /// The description without its spoilers, as Markdown.
+    pub description: String,
+    /// The parts of the description AniList hides as spoilers.
+    pub spoilers: Vec<String>,
+    pub gender: Option<String>,
+    pub age: Option<String>,
+    pub birthday: Option<String>,
+    pub blood_type: Option<String>,
+    /// Anime, manga and novels the character is in, most popular first.
+    pub appearances: Vec<Appearance>,
+    /// Voice actors with the language they voice the character in.
+    pub voice_actors: Vec<(String, String)>,
+    /// Other characters of the most popular work, with their role in it.
+    pub related: Vec<(String, String)>,
+    pub url: String,
+}
+
+#[derive(Debug)]
+pub struct Appearance {
+    pub title: String,
+    /// Such as `TV` or `NOVEL`.
+    pub format: String,
+    pub year: Option<i64>,
+    /// `MAIN`, `SUPPORTING` or `BACKGROUND`.
+    pub role: String,
+}
+
+impl AniListCharacter {
+    /// Names, age, birthday and the like, exactly as AniList gives them.
+    pub fn profile(&self) -> String {
+        let mut fields = Vec::new();
+        if let Some(native) = &self.native_name {
+            fields.push(format!("Native name: {}", native));
+        }
+        if !self.alternative_names.is_empty() {
+            fields.push(format!("Also known as: {}", self.alternative_names.join(", ")));
+        }
+        for (label, value) in [
+            ("Gender", &self.gender),
+            ("Age", &self.age),
+            ("Birthday", &self.birthday),
+            ("Blood type", &self.blood_type),
+        ] {
+            if let Some(value) = value {
+                fields.push(format!("{}: {}", label, value));
+            }
+        }
+        if !self.voice_actors.is_empty() {
+            let actors: Vec<String> = self.voice_actors.iter().map(|(name, language)| format!("{} ({})", name, language)).collect();
+            fields.push(format!("Voice actors: {}", actors.join(", ")));
+        }
+        let fields: Vec<String> = fields.into_iter().map(|field| format!("- {}", field)).collect();
+        format!("Profile of {}, from AniList:\n{}", self.name, fields.join("\n"))
+    }
+
+    /// The works the character appears in, as a list.
+    pub fn appearances_text(&self) -> Option<String> {
+        if self.appearances.is_empty() {
+            return None;
+        }
+        let lines: Vec<String> = self
+            .appearances
+            .iter()
+            .map(|appearance| {
+                let year = appearance.year.map(|year| format!(", {}", year)).unwrap_or_default();
+                format!("- {} ({}{}), as a {} character", appearance.title, appearance.format, year, appearance.role.to_lowercase())
+            })
+            .collect();
+        Some(format!("{} appears in:\n{}", self.name, lines.join("\n")))
+    }
+
+    /// The other characters of the character's best-known work, as a list.
+    pub fn related_text(&self) -> Option<String> {
+        let work = &self.appearances.first()?.title;
+        if self.related.is_empty() {
+            return None;
+        }
+        let lines: Vec<String> = self.related.iter().map(|(name, role)| format!("- {} ({} character)", name, role.to_lowercase())).collect();
+        Some(format!("Characters alongside {} in {}:\n{}", self.name, work, lines.join("\n")))
+    }
+}
+
+/// The AniList search page for a character name, used as the source of what is learned.
+pub fn anilist_search_url(name: &str) -> Result<String, url::ParseError> {
+    Ok(Url::parse_with_params("https://anilist.co/search/characters", &[("search", name.trim())])?.to_string())
+}
+
+/// Looks up the character best matching `name` through the AniList GraphQL API.
+pub async fn fetch_anilist_character(client: &reqwest::Client, name: &str) -> Result<AniListCharacter, Box<dyn std::error::Error>> {
+    let response = client
+        .post(ENDPOINT)
+        .header("Accept", "application/json")
+        .json(&json!({ "query": CHARACTER_QUERY, "variables": { "search": name.trim() } }))
+        .send()
+        .await?;
+    let status = response.status();
+    let body: Value = response.json().await?;
+    let character = &body["data"]["Character"];
+    if character.is_null() {
+        if status == reqwest::StatusCode::NOT_FOUND {
+            return Err(format!("AniList has no character named {}", name).into());
+        }
+        let message = body["errors"][0]["message"].as_str().unwrap_or("no data returned");
+        return Err(format!("AniList refused the lookup of {} (Status: {}): {}", name, status, message).into());
+    }
+    Ok(parse_character(character))
+}
+
+fn parse_character(character: &Value) -> AniListCharacter {
+    let id = character["id"].as_u64().unwrap_or_default();
+    let name = string(&character["name"]["full"]).unwrap_or_default();
+    let (description, spoilers) = split_spoilers(character["description"].as_str().unwrap_or_default());
+
+    let birthday = &character["dateOfBirth"];
+    let birthday = match (birthday["year"].as_i64(), birthday["month"].as_u64(), birthday["day"].as_u64()) {
+        (year, Some(month), Some(day)) => chrono::NaiveDate::from_ymd_opt(2000, month as u32, day as u32).map(|date| match year {
+            Some(year) => format!("{} {}", date.format("%B %-d,"), year),
+            None => date.format("%B %-d").to_string(),
+        }),
+        _ => None,
+    };
+
+    let mut appearances = Vec::new();
+    let mut voice_actors: Vec<(String, String)> = Vec::new();
+    let edges = character["media"]["edges"].as_array().cloned().unwrap_or_default();
+    for edge in &edges {
+        let media = &edge["node"];
+        let title = string(&media["title"]["english"]).or_else(|| string(&media["title"]["romaji"]));
+        if let Some(title) = title {
+            appearances.push(Appearance {
+                title,
+                format: string(&media["format"]).or_else(|| string(&media["type"])).unwrap_or_default(),
+                year: media["startDate"]["year"].as_i64(),
+                role: string(&edge["characterRole"]).unwrap_or_else(|| "BACKGROUND".to_string()),
+            });
+        }
+        for (field, language) in [("japanese", "Japanese"), ("english", "English")] {
+            for actor in edge[field].as_array().into_iter().flatten() {
+                let Some(actor) = string(&actor["name"]["full"]) else {
+                    continue;
+                };
+                if !voice_actors.iter().any(|(name, _)| *name == actor) {
+                    voice_actors.push((actor, language.to_string()));
+                }
+            }
+        }
+    }
+    let related = edges
+        .first()
+        .and_then(|edge| edge["node"]["characters"]["edges"].as_array())
+        .into_iter()
+        .flatten()
+        .filter(|other| other["node"]["id"].as_u64() != Some(id))
+        .filter_map(|other| Some((string(&other["node"]["name"]["full"])?, string(&other["role"]).unwrap_or_default())))
+        .take(MAX_RELATED)
+        .collect();
+
+    AniListCharacter {
+        native_name: string(&character["name"]["native"]),
+        alternative_names: character["name"]["alternative"]
+            .as_array()
+            .into_iter()
+            .flatten()
+            .filter_map(string)
+            .collect(),
+        description,
+        spoilers,
+        gender: string(&character["gender"]),
+        age: string(&character["age"]),
+        birthday,
+        blood_type: string(&character["bloodType"]),
+        appearances,
+        voice_actors,
+        related,
+        url: string(&character["siteUrl"]).unwrap_or_else(|| format!("https://anilist.co/character/{}", id)),
+        name,
+    }
+}
+
+/// A non-empty string field.
+fn string(value: &Value) -> Option<String> {
+    value.as_str().map(str::trim).filter(|value| !value.is_empty()).map(str::to_string)
+}
+
+/// Splits AniList's `~!spoiler!~` markup out of a description, turning its `<br>` tags
+/// into line breaks and dropping other HTML tags.
+fn split_spoilers(description: &str) -> (String, Vec<String>) {
+    let description = description.replace("<br>", "\n").replace("<br />", "\n");
+    let description: String = Html::parse_fragment(&description).root_element().text().collect();
+    let mut visible = String::new();
+    let mut spoilers = Vec::new();
+    let mut rest = description.as_str();
+    while let Some(start) = rest.find("~!") {
+        visible.push_str(&rest[..start]);
+        let after = &rest[start + 2..];
+        let end = after.find("!~").unwrap_or(after.len());
+        let spoiler = after[..end].trim();
+        if !spoiler.is_empty() {
+            spoilers.push(spoiler.to_string());
+        }
+        rest = after.get(end + 2..).unwrap_or_default();
+    }
+    visible.push_str(rest);
+    let visible = visible.lines().map(str::trim_end).collect::<Vec<_>>().join("\n");
+    (visible.trim().to_string(), spoilers)
+}
@@ -0,0 +1,79 @@
+use super::url_matches;
+use serde::{Deserialize, Serialize};
+
+/// Pages whose static HTML converts to less text than this are treated as empty, which is
+/// what a page rendered by JavaScript looks like before its scripts run.
+const MIN_STATIC_TEXT: usize = 200;
+
+/// When pages are rendered in a headless Chrome instead of being read as served.
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct BrowserSettings {
+    /// URL patterns, as in the domain filter, of pages that are always rendered.
+    #[serde(default)]
+    pub render: Vec<String>,
+    /// Render pages whose static HTML has next to no text.
+    #[serde(default = "default_when_empty")]
+    pub when_empty: bool,
+    /// The Chrome or Chromium executable; found on the PATH when not set.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub chrome: Option<String>,
+}
+
+fn default_when_empty() -> bool {
+    true
+}
+
+impl Default for BrowserSettings {
+    fn default() -> Self {
+        BrowserSettings {
+            render: Vec::new(),
+            when_empty: default_when_empty(),
+            chrome: None,
+        }
+    }
+}
+
+impl BrowserSettings {
+    /// Whether the page at `url`, served as `html`, should be rendered. Pages are only
+    /// rendered because they look empty when the browser is built in.
+    pub fn should_render(&self, url: &str, html: &str) -> bool {
+        url_matches(&self.render, url)
+            || (cfg!(feature = "headless")
+                && self.when_empty
+                && super::html_to_markdown(html).chars().count() < MIN_STATIC_TEXT)
+    }
+}
+
+/// Loads `url` in a headless Chrome and returns the HTML once its scripts have run.
+#[cfg(feature = "headless")]
+pub async fn render_page(url: &str, settings: &BrowserSettings) -> Result<String, Box<dyn std::error::Error>> {
+    use chromiumoxide::browser::{Browser, BrowserConfig};
+    use futures::StreamExt;
+    use std::time::Duration;
+
+    let mut config = BrowserConfig::builder().request_timeout(Duration::from_secs(30));
+    if let Some(chrome) = &settings.chrome {
+        config = config.chrome_executable(chrome);
+    }
+    let (mut browser, mut handler) = Browser::launch(config.build()?).await?;
+    let events = tokio::spawn(async move { while handler.next().await.is_some_and(|event| event.is_ok()) {} });
+
+    let rendered = async {
+        let page = browser.new_page(url).await?;
+        page.wait_for_navigation().await?;
+        // Scripts often fetch the content after the load event
+        tokio::time::sleep(Duration::from_secs(2)).await;
+        page.content().await
+    }
+    .await;
+
+    let _ = browser.close().await;
+    let _ = browser.wait().await;
+    events.abort();
+    Ok(rendered?)
+}
+
+#[cfg(not(feature = "headless"))]
+pub async fn render_page(_url: &str, _settings: &BrowserSettings) -> Result<String, Box<dyn std::error::Error>> {
+    Err("rendering JavaScript needs a build with `--features headless`".into())
+}
@@ -0,0 +1,85 @@
+use sha2::{Digest, Sha256};
+
+/// Preferred places to split, best first: paragraphs, sentences, lines, then words.
+const SEPARATORS: [&str; 6] = ["\n\n", ". ", "! ", "? ", "\n", " "];
+
+/// Splits `text` into chunks of at most `size` characters, each starting with the last
+/// `overlap` characters of the previous one so nothing is cut off without context. Chunks
+/// end at a paragraph, sentence or word break in their second half where there is one.
+pub fn chunk_text(text: &str, size: usize, overlap: usize) -> Vec<String> {
+    let text = text.trim();
+    // Byte offset of every character, plus the end of the text.
+    let bounds: Vec<usize> = text.char_indices().map(|(at, _)| at).chain(std::iter::once(text.len())).collect();
+    let total = bounds.len() - 1;
+    let size = size.max(1);
+    let overlap = overlap.min(size / 2);
+
+    let mut chunks = Vec::new();
+    let mut start = 0;
+    loop {
+        let limit = (start + size).min(total);
+        let end = match limit == total {
+            true => total,
+            false => break_point(text, &bounds, start, limit),
+        };
+        chunks.push(text[bounds[start]..bounds[end]].trim().to_string());
+        if end == total {
+            return chunks;
+        }
+        // Back up by the overlap, then forward to the start of a word.
+        let mut next = end.saturating_sub(overlap).max(start + 1);
+        while next < end && !text[bounds[next - 1]..bounds[next]].trim().is_empty() {
+            next += 1;
+        }
+        start = next;
+    }
+}
+
+/// Index of the character after the best separator in the second half of
+/// `start..limit`, or `limit` if there is none.
+fn break_point(text: &str, bounds: &[usize], start: usize, limit: usize) -> usize {
+    let min = start + (limit - start) / 2;
+    let window = &text[bounds[min]..bounds[limit]];
+    SEPARATORS
+        .iter()
+        .find_map(|separator| window.rfind(separator).map(|at| at + separator.len()))
+        .and_then(|at| bounds.binary_search(&(bounds[min] + at)).ok())
+        .unwrap_or(limit)
+}
+
+/// Splits Markdown into runs of whole sections, a section being a heading and the text up
+/// to the next heading, of up to `size` characters unless a single section is longer.
+/// Where a run ends depends on the text of its sections, so an edit to one section changes
+/// only its own run and not every run after it.
+pub fn split_sections(text: &str, size: usize) -> Vec<String> {
+    let mut sections: Vec<String> = Vec::new();
+    for line in text.trim().lines() {
+        if line.starts_with('#') || sections.is_empty() {
+            sections.push(String::new());
+        }
+        if let Some(section) = sections.last_mut() {
+            section.push_str(line);
+            section.push('\n');
+        }
+    }
+
+    let mut runs = Vec::new();
+    let mut run = String::new();
+    for section in sections {
+        if !run.is_empty() && run.chars().count() + section.chars().count() > size {
+            runs.push(std::mem::take(&mut run));
+        }
+        run.push_str(&section);
+        if ends_run(&section) {
+            runs.push(std::mem::take(&mut run));
+        }
+    }
+    runs.push(run);
+    runs.into_iter().map(|run| run.trim().to_string()).filter(|run| !run.is_empty()).collect()
+}
+
+/// Whether a run ends after this section, about one section in four, decided by its text
+/// alone so runs line up again right after an edited section.
+fn ends_run(section: &str) -> bool {
+    Sha256::digest(section.trim().as_bytes())[0] % 4 == 0
+}
@@ -0,0 +1,128 @@
+use crate::storage::secret_codec;
+use reqwest::header::{HeaderMap, SET_COOKIE};
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+use url::Url;
+
+const COOKIES_FILE: &str = "cookies.enc";
+
+/// Cookies for the sites the chatbot logs in to, by domain and then name. They are kept
+/// in `data/cookies.enc`, always encrypted with the storage encryption key, and sent with
+/// every fetch from the domain and its subdomains.
+#[derive(Debug)]
+pub struct CookieJar {
+    path: PathBuf,
+    sites: BTreeMap<String, BTreeMap<String, String>>,
+}
+
+impl CookieJar {
+    /// Reads the saved cookies; no file means no cookies, and needs no key.
+    pub fn load(data_dir: &Path) -> Result<Self, Box<dyn std::error::Error>> {
+        let mut jar = CookieJar::empty(data_dir);
+        if jar.path.exists() {
+            jar.sites = serde_json::from_str(&secret_codec()?.decode(&fs::read(&jar.path)?)?.0)?;
+        }
+        Ok(jar)
+    }
+
+    /// A jar without cookies that saves to the usual place.
+    pub fn empty(data_dir: &Path) -> Self {
+        CookieJar {
+            path: data_dir.join(COOKIES_FILE),
+            sites: BTreeMap::new(),
+        }
+    }
+
+    pub fn save(&self) -> Result<(), Box<dyn std::error::Error>> {
+        if let Some(dir) = self.path.parent() {
+            fs::create_dir_all(dir)?;
+        }
+        fs::write(&self.path, secret_codec()?.encode(&serde_json::to_string(&self.sites)?)?)?;
+        Ok(())
+    }
+
+    /// The `Cookie` header to send to `url`, if the jar has any cookies for it.
+    pub fn header(&self, url: &str) -> Option<String> {
+        let host = Url::parse(url).ok()?.host_str()?.to_ascii_lowercase();
+        let cookies: Vec<String> = self
+            .sites
+            .iter()
+            .filter(|(domain, _)| within(&host, domain))
+            .flat_map(|(_, cookies)| cookies.iter().map(|(name, value)| format!("{}={}", name, value)))
+            .collect();
+        (!cookies.is_empty()).then(|| cookies.join("; "))
+    }
+
+    pub fn has_cookies(&self, domain: &str) -> bool {
+        self.sites.get(&normalize(domain)).is_some_and(|cookies| !cookies.is_empty())
+    }
+
+    /// Keeps the cookies a response from `url` sets, and forgets those it expires. Returns
+    /// whether anything changed.
+    pub fn update(&mut self, url: &str, headers: &HeaderMap) -> bool {
+        let Some(host) = Url::parse(url).ok().and_then(|url| url.host_str().map(str::to_ascii_lowercase)) else {
+            return false;
+        };
+        let mut changed = false;
+        for header in headers.get_all(SET_COOKIE).iter().filter_map(|header| header.to_str().ok()) {
+            let mut parts = header.split(';');
+            let Some((name, value)) = parts.next().and_then(|pair| pair.split_once('=')) else {
+                continue;
+            };
+            let mut domain = host.clone();
+            let mut expired = false;
+            for attribute in parts {
+                let (key, attribute_value) = attribute.split_once('=').unwrap_or((attribute, ""));
+                match key.trim().to_ascii_lowercase().as_str() {
+                    // A cookie may be widened to a parent domain, never to another site
+                    "domain" if within(&host, &normalize(attribute_value)) => domain = normalize(attribute_value),
+                    "max-age" => expired = attribute_value.trim().parse::<i64>().is_ok_and(|age| age <= 0),
+                    _ => {}
+                }
+            }
+            let cookies = self.sites.entry(domain).or_default();
+            let (name, value) = (name.trim().to_string(), value.trim().to_string());
+            changed |= match expired {
+                true => cookies.remove(&name).is_some(),
+                false => cookies.insert(name, value.clone()).as_ref() != Some(&value),
+            };
+        }
+        self.sites.retain(|_, cookies| !cookies.is_empty());
+        changed
+    }
+
+    /// Replaces the cookies of `domain` with those in `cookies`, written as in a `Cookie`
+    /// header (`name=value; other=value`), such as copied from a logged-in browser.
+    pub fn set(&mut self, domain: &str, cookies: &str) -> usize {
+        let parsed: BTreeMap<String, String> = cookies
+            .split(';')
+            .filter_map(|pair| pair.split_once('='))
+            .map(|(name, value)| (name.trim().to_string(), value.trim().to_string()))
+            .filter(|(name, _)| !name.is_empty())
+            .collect();
+        let count = parsed.len();
+        self.sites.insert(normalize(domain), parsed);
+        self.sites.retain(|_, cookies| !cookies.is_empty());
+        count
+    }
+
+    /// Forgets the cookies of `domain`. Returns false if there were none.
+    pub fn clear(&mut self, domain: &str) -> bool {
+        self.sites.remove(&normalize(domain)).is_some()
+    }
+
+    /// The domains with cookies and how many each has.
+    pub fn domains(&self) -> Vec<(&str, usize)> {
+        self.sites.iter().map(|(domain, cookies)| (domain.as_str(), cookies.len())).collect()
+    }
+}
+
+fn normalize(domain: &str) -> String {
+    domain.trim().trim_start_matches('.').to_ascii_lowercase()
+}
+
+/// Whether `host` is `domain` or one of its subdomains.
+pub(super) fn within(host: &str, domain: &str) -> bool {
+    host == domain || host.ends_with(&format!(".{}", domain))
+}
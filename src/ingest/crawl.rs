@@ -0,0 +1,34 @@
+use super::markdown::content_root;
+use scraper::{Html, Selector};
+use url::Url;
+
+/// Links to these are files rather than pages.
+const SKIPPED_EXTENSIONS: [&str; 9] = [".jpg", ".jpeg", ".png", ".gif", ".webp", ".svg", ".pdf", ".zip", ".mp4"];
+
+/// The pages on the same host that the main content of a page links to, without
+/// fragments, in the order they appear. Wiki namespace pages (`Special:`, `File:`,
+/// `Talk:`, ...) and edit or history views are left out.
+pub fn same_site_links(html: &str, base: &Url) -> Vec<Url> {
+    let document = Html::parse_document(html);
+    let Ok(anchors) = Selector::parse("a[href]") else {
+        return Vec::new();
+    };
+    let mut links: Vec<Url> = Vec::new();
+    for anchor in content_root(&document).select(&anchors) {
+        let Some(mut link) = anchor.value().attr("href").and_then(|href| base.join(href).ok()) else {
+            continue;
+        };
+        link.set_fragment(None);
+        let path = link.path().to_ascii_lowercase();
+        let wanted = matches!(link.scheme(), "http" | "https")
+            && link.host_str() == base.host_str()
+            && !path.contains(':')
+            && !path.contains("%3a")
+            && !link.query().is_some_and(|query| query.contains("action="))
+            && !SKIPPED_EXTENSIONS.iter().any(|extension| path.ends_with(extension));
+        if wanted && link != *base && !links.contains(&link) {
+            links.push(link);
+        }
+    }
+    links
+}
@@ -0,0 +1,298 @@
+use super::html_to_markdown;
+use roxmltree::{Document as Xml, Node, ParsingOptions};
+use scraper::{Html, Selector};
+use std::fs::{self, File};
+use std::io::Read;
+use std::io;
+use std::path::{Path, PathBuf};
+use zip::ZipArchive;
+
+/// File extensions `read_document` understands.
+const EXTENSIONS: [&str; 5] = ["epub", "docx", "txt", "md", "markdown"];
+/// Chapters with less text than this, such as covers and copyright pages, are skipped.
+const MIN_CHAPTER_TEXT: usize = 100;
+
+/// A book or document read from a file, split into chapters.
+#[derive(Debug)]
+pub struct Document {
+    pub title: Option<String>,
+    pub chapters: Vec<Chapter>,
+}
+
+#[derive(Debug)]
+pub struct Chapter {
+    pub title: String,
+    /// The chapter as Markdown.
+    pub text: String,
+}
+
+impl Document {
+    /// Characters of text in all chapters.
+    pub fn text_length(&self) -> usize {
+        self.chapters.iter().map(|chapter| chapter.text.chars().count()).sum()
+    }
+}
+
+/// Reads an EPUB, DOCX, Markdown or plain text file. EPUBs are split into the chapters of
+/// their reading order and DOCX files at their top-level headings; text files are one
+/// chapter.
+pub fn read_document(path: &Path) -> Result<Document, Box<dyn std::error::Error>> {
+    let mut document = match extension(path).unwrap_or_default().as_str() {
+        "epub" => read_epub(path)?,
+        "docx" => read_docx(path)?,
+        "txt" | "md" | "markdown" => Document {
+            title: None,
+            chapters: vec![Chapter {
+                title: file_title(path),
+                text: fs::read_to_string(path)?,
+            }],
+        },
+        _ => return Err(format!("Can't read {}; supported files are .epub, .docx, .txt and .md", path.display()).into()),
+    };
+    document.chapters.retain(|chapter| chapter.text.trim().chars().count() >= MIN_CHAPTER_TEXT);
+    if document.chapters.is_empty() {
+        return Err(format!("No text found in {}", path.display()).into());
+    }
+    Ok(document)
+}
+
+/// The files under `dir` that `read_document` can read, in its subdirectories too, sorted
+/// by path. Hidden files and directories are skipped.
+pub fn document_files(dir: &Path) -> io::Result<Vec<PathBuf>> {
+    let mut files = Vec::new();
+    let mut pending = vec![dir.to_path_buf()];
+    while let Some(dir) = pending.pop() {
+        for entry in fs::read_dir(&dir)? {
+            let path = entry?.path();
+            if path.file_name().is_some_and(|name| name.to_string_lossy().starts_with('.')) {
+                continue;
+            }
+            if path.is_dir() {
+                pending.push(path);
+            } else if extension(&path).is_some_and(|ext| EXTENSIONS.contains(&ext.as_str())) {
+                files.push(path);
+            }
+        }
+    }
+    files.sort();
+    Ok(files)
+}
+
+fn extension(path: &Path) -> Option<String> {
+    path.extension().and_then(|ext| ext.to_str()).map(str::to_ascii_lowercase)
+}
+
+fn file_title(path: &Path) -> String {
+    path.file_stem().map(|stem| stem.to_string_lossy().into_owned()).unwrap_or_default()
+}
+
+fn read_epub(path: &Path) -> Result<Document, Box<dyn std::error::Error>> {
+    let mut archive = ZipArchive::new(File::open(path)?)?;
+    let container = read_entry(&mut archive, "META-INF/container.xml")?;
+    let container = parse_xml(&container)?;
+    let package_path = container
+        .descendants()
+        .find(|node| node.has_tag_name("rootfile"))
+        .and_then(|node| node.attribute("full-path"))
+        .ok_or("The EPUB has no package document")?
+        .to_string();
+
+    let package = read_entry(&mut archive, &package_path)?;
+    let package = parse_xml(&package)?;
+    let title = package
+        .descendants()
+        .find(|node| node.has_tag_name("title"))
+        .and_then(|node| node.text())
+        .map(|title| title.trim().to_string());
+    // Manifest hrefs are relative to the package document
+    let base = package_path.rfind('/').map_or("", |at| &package_path[..=at]);
+    let items: Vec<(&str, String, bool)> = package
+        .descendants()
+        .filter(|node| node.has_tag_name("item"))
+        .filter_map(|node| {
+            let nav = node.attribute("properties").is_some_and(|properties| properties.contains("nav"));
+            Some((node.attribute("id")?, format!("{}{}", base, node.attribute("href")?), nav))
+        })
+        .collect();
+
+    let mut chapters = Vec::new();
+    let spine = package.descendants().filter(|node| node.has_tag_name("itemref"));
+    for idref in spine.filter_map(|node| node.attribute("idref")) {
+        let Some((_, href, false)) = items.iter().find(|(id, _, _)| *id == idref) else {
+            continue;
+        };
+        let Ok(html) = read_entry(&mut archive, &resolve(href)) else {
+            continue;
+        };
+        let text = html_to_markdown(&html);
+        let number = chapters.len() + 1;
+        chapters.push(Chapter {
+            title: html_title(&html).unwrap_or_else(|| format!("Chapter {}", number)),
+            text,
+        });
+    }
+    Ok(Document {
+        title: title.or_else(|| Some(file_title(path))),
+        chapters,
+    })
+}
+
+/// The first heading of a chapter, or else its `<title>`.
+fn html_title(html: &str) -> Option<String> {
+    let document = Html::parse_document(html);
+    ["h1", "h2", "h3", "title"]
+        .iter()
+        .filter_map(|selector| Selector::parse(selector).ok())
+        .find_map(|selector| {
+            let text = document.select(&selector).next()?.text().collect::<String>();
+            let text = text.split_whitespace().collect::<Vec<_>>().join(" ");
+            (!text.is_empty()).then_some(text)
+        })
+}
+
+fn read_docx(path: &Path) -> Result<Document, Box<dyn std::error::Error>> {
+    let mut archive = ZipArchive::new(File::open(path)?)?;
+    let body = read_entry(&mut archive, "word/document.xml")?;
+    let body = parse_xml(&body)?;
+    let title = read_entry(&mut archive, "docProps/core.xml").ok().and_then(|core| {
+        let core = parse_xml(&core).ok()?;
+        let title = core.descendants().find(|node| node.has_tag_name("title"))?.text()?.trim().to_string();
+        (!title.is_empty()).then_some(title)
+    });
+
+    let mut chapters: Vec<Chapter> = Vec::new();
+    let Some(root) = body.descendants().find(|node| node.has_tag_name("body")) else {
+        return Err("The DOCX has no document body".into());
+    };
+    for block in root.children().filter(Node::is_element) {
+        let (text, level) = match block.tag_name().name() {
+            "p" => paragraph(block),
+            "tbl" => (table(block), None),
+            _ => continue,
+        };
+        if text.trim().is_empty() {
+            continue;
+        }
+        // Top-level headings start a new chapter
+        if level == Some(1) || chapters.is_empty() {
+            chapters.push(Chapter {
+                title: match level {
+                    Some(1) => text.clone(),
+                    _ => title.clone().unwrap_or_else(|| file_title(path)),
+                },
+                text: String::new(),
+            });
+        }
+        if let Some(chapter) = chapters.last_mut() {
+            let line = match level {
+                Some(level) => format!("{} {}", "#".repeat(level), text),
+                None => text,
+            };
+            chapter.text.push_str(&line);
+            chapter.text.push_str("\n\n");
+        }
+    }
+    Ok(Document {
+        title: title.or_else(|| Some(file_title(path))),
+        chapters,
+    })
+}
+
+/// The text of a DOCX paragraph, and its heading level if it is a heading. List items
+/// get a Markdown bullet.
+fn paragraph(node: Node) -> (String, Option<usize>) {
+    let mut text = String::new();
+    for child in node.descendants() {
+        match child.tag_name().name() {
+            "t" => text.push_str(child.text().unwrap_or("")),
+            "tab" => text.push('\t'),
+            "br" | "cr" => text.push('\n'),
+            _ => {}
+        }
+    }
+    let style = node
+        .descendants()
+        .find(|child| child.has_tag_name("pStyle"))
+        .and_then(|style| style.attributes().find(|attribute| attribute.name() == "val"))
+        .map(|style| style.value().to_ascii_lowercase().replace(' ', ""));
+    let level = match style.as_deref() {
+        Some("title") => Some(1),
+        Some(style) => style.strip_prefix("heading").and_then(|level| level.parse::<usize>().ok()),
+        None => None,
+    };
+    if level.is_none() && node.descendants().any(|child| child.has_tag_name("numPr")) {
+        text = format!("- {}", text);
+    }
+    (text.trim_end().to_string(), level.map(|level| level.clamp(1, 6)))
+}
+
+/// A DOCX table as a Markdown table.
+fn table(node: Node) -> String {
+    let mut lines = Vec::new();
+    for (index, row) in node.children().filter(|child| child.has_tag_name("tr")).enumerate() {
+        let cells: Vec<String> = row
+            .children()
+            .filter(|child| child.has_tag_name("tc"))
+            .map(|cell| {
+                let texts: Vec<String> = cell.children().filter(|child| child.has_tag_name("p")).map(|p| paragraph(p).0).collect();
+                texts.join(" ").replace('|', "\\|")
+            })
+            .collect();
+        lines.push(format!("| {} |", cells.join(" | ")));
+        if index == 0 {
+            lines.push(format!("|{}", " --- |".repeat(cells.len())));
+        }
+    }
+    lines.join("\n")
+}
+
+fn read_entry(archive: &mut ZipArchive<File>, name: &str) -> Result<String, Box<dyn std::error::Error>> {
+    let mut entry = archive.by_name(name)?;
+    let mut text = String::new();
+    entry.read_to_string(&mut text)?;
+    Ok(text)
+}
+
+fn parse_xml(text: &str) -> Result<Xml<'_>, roxmltree::Error> {
+    let options = ParsingOptions {
+        allow_dtd: true,
+        ..ParsingOptions::default()
+    };
+    Xml::parse_with_options(text, options)
+}
+
+/// Resolves `..` segments and percent-escapes in a path inside the archive.
+fn resolve(href: &str) -> String {
+    let href = href.split('#').next().unwrap_or(href);
+    let mut segments: Vec<&str> = Vec::new();
+    for segment in href.split('/') {
+        match segment {
+            ".." => {
+                segments.pop();
+            }
+            "." | "" => {}
+            _ => segments.push(segment),
+        }
+    }
+    let path = segments.join("/");
+    let bytes = path.as_bytes();
+    let mut decoded = Vec::with_capacity(bytes.len());
+    let mut index = 0;
+    while index < bytes.len() {
+        let escaped = (bytes[index] == b'%')
+            .then(|| path.get(index + 1..index + 3))
+            .flatten()
+            .and_then(|hex| u8::from_str_radix(hex, 16).ok());
+        match escaped {
+            Some(byte) => {
+                decoded.push(byte);
+                index += 3;
+            }
+            None => {
+                decoded.push(bytes[index]);
+                index += 1;
+            }
+        }
+    }
+    String::from_utf8_lossy(&decoded).into_owned()
+}
@@ -0,0 +1,63 @@
+use serde::{Deserialize, Serialize};
+use url::Url;
+
+/// Which sites the chatbot may learn from. Patterns are host names, matching the host and
+/// its subdomains, optionally followed by a path prefix: `fandom.com`, `*.fandom.com` (only
+/// subdomains) or `en.wikipedia.org/wiki/`.
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct DomainFilter {
+    /// When not empty, only URLs matching one of these are learned from.
+    #[serde(default)]
+    pub allow: Vec<String>,
+    /// URLs matching any of these are never learned from, even if allowed.
+    #[serde(default = "default_deny")]
+    pub deny: Vec<String>,
+}
+
+fn default_deny() -> Vec<String> {
+    vec!["pinterest.com".to_string()]
+}
+
+impl Default for DomainFilter {
+    fn default() -> Self {
+        DomainFilter {
+            allow: Vec::new(),
+            deny: default_deny(),
+        }
+    }
+}
+
+impl DomainFilter {
+    pub fn allows(&self, url: &str) -> bool {
+        let Ok(url) = Url::parse(url) else {
+            return false;
+        };
+        let Some(host) = url.host_str().map(str::to_ascii_lowercase) else {
+            return false;
+        };
+        let matching = |pattern: &String| matches(pattern, &host, url.path());
+        (self.allow.is_empty() || self.allow.iter().any(matching)) && !self.deny.iter().any(matching)
+    }
+}
+
+/// Whether `url` matches any of `patterns`, which are written as in `DomainFilter`.
+pub fn url_matches(patterns: &[String], url: &str) -> bool {
+    let Ok(url) = Url::parse(url) else {
+        return false;
+    };
+    let host = url.host_str().unwrap_or_default().to_ascii_lowercase();
+    patterns.iter().any(|pattern| matches(pattern, &host, url.path()))
+}
+
+fn matches(pattern: &str, host: &str, path: &str) -> bool {
+    let pattern = pattern.trim().to_ascii_lowercase();
+    let (domain, prefix) = match pattern.find('/') {
+        Some(at) => pattern.split_at(at),
+        None => (pattern.as_str(), ""),
+    };
+    let host_matches = match domain.strip_prefix("*.") {
+        Some(parent) => host.ends_with(&format!(".{}", parent)),
+        None => host == domain || host.ends_with(&format!(".{}", domain)),
+    };
+    host_matches && path.to_ascii_lowercase().starts_with(prefix)
+}
@@ -0,0 +1,112 @@
+use super::html_to_markdown;
+use chrono::{DateTime, Utc};
+use roxmltree::{Document as Xml, Node, ParsingOptions};
+
+/// An RSS or Atom feed.
+#[derive(Debug)]
+pub struct Feed {
+    pub title: String,
+    /// The entries in the order the feed lists them, usually newest first.
+    pub entries: Vec<FeedEntry>,
+}
+
+#[derive(Debug)]
+pub struct FeedEntry {
+    /// The entry's GUID or Atom ID, or else its link.
+    pub id: String,
+    pub title: String,
+    pub link: Option<String>,
+    pub published: Option<DateTime<Utc>>,
+    /// The entry's content or summary as Markdown; often only a teaser of the article.
+    pub content: String,
+}
+
+/// Downloads and reads the feed at `url`.
+pub async fn fetch_feed(client: &reqwest::Client, url: &str) -> Result<Feed, Box<dyn std::error::Error>> {
+    let response = client
+        .get(url)
+        .header("Accept", "application/rss+xml, application/atom+xml, application/xml;q=0.9, */*;q=0.8")
+        .send()
+        .await?;
+    if !response.status().is_success() {
+        return Err(format!("Failed to fetch feed {} (Status: {})", url, response.status()).into());
+    }
+    parse_feed(&response.text().await?)
+}
+
+/// Reads an RSS 2.0, RSS 1.0 or Atom feed.
+pub fn parse_feed(xml: &str) -> Result<Feed, Box<dyn std::error::Error>> {
+    let options = ParsingOptions {
+        allow_dtd: true,
+        ..ParsingOptions::default()
+    };
+    let document = Xml::parse_with_options(xml, options)?;
+    let root = document.root_element();
+    let (container, entry_tag) = match root.tag_name().name() {
+        "feed" => (root, "entry"),
+        "rss" => (child(root, "channel").ok_or("The RSS feed has no channel")?, "item"),
+        // RSS 1.0 lists its items next to the channel
+        "RDF" => (root, "item"),
+        other => return Err(format!("Not an RSS or Atom feed (root element `{}`)", other).into()),
+    };
+    let title = child(container, "title")
+        .or_else(|| child(root, "channel").and_then(|channel| child(channel, "title")))
+        .map(text)
+        .unwrap_or_default();
+    let entries = container
+        .children()
+        .filter(|node| node.has_tag_name(entry_tag))
+        .filter_map(|node| entry(node, xml))
+        .collect();
+    Ok(Feed { title, entries })
+}
+
+fn entry(node: Node, xml: &str) -> Option<FeedEntry> {
+    let link = node
+        .children()
+        .filter(|child| child.has_tag_name("link"))
+        .find_map(|link| match link.attribute("href") {
+            // Atom: the alternate link is the article
+            Some(href) => matches!(link.attribute("rel"), None | Some("alternate")).then(|| href.to_string()),
+            None => Some(text(link)),
+        })
+        .filter(|link| !link.is_empty());
+    let id = ["guid", "id"]
+        .iter()
+        .find_map(|name| child(node, name).map(text))
+        .filter(|id| !id.is_empty())
+        .or_else(|| link.clone())?;
+    let published = ["pubDate", "published", "updated", "date"].iter().find_map(|name| {
+        let date = text(child(node, name)?);
+        let parsed = DateTime::parse_from_rfc2822(&date).or_else(|_| DateTime::parse_from_rfc3339(&date));
+        parsed.ok().map(|date| date.with_timezone(&Utc))
+    });
+    // The full content if the feed has it, else the summary
+    let content = ["encoded", "content", "description", "summary"]
+        .iter()
+        .filter_map(|name| child(node, name))
+        .map(|content| match content.attribute("type") {
+            Some("xhtml") => html_to_markdown(&xml[content.range()]),
+            Some("text") => text(content),
+            _ => html_to_markdown(&text(content)),
+        })
+        .find(|content| !content.trim().is_empty())
+        .unwrap_or_default();
+    Some(FeedEntry {
+        id,
+        title: child(node, "title").map(text).unwrap_or_default(),
+        link,
+        published,
+        content,
+    })
+}
+
+fn child<'a, 'input>(node: Node<'a, 'input>, name: &str) -> Option<Node<'a, 'input>> {
+    node.children().find(|child| child.has_tag_name(name))
+}
+
+/// All text inside an element, CDATA sections included, trimmed.
+fn text(node: Node) -> String {
+    let text: String = node.descendants().filter(|node| node.is_text()).filter_map(|node| node.text()).collect();
+    text.trim().to_string()
+}
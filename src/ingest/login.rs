@@ -0,0 +1,133 @@
+use super::cookies::{within, CookieJar};
+use crate::secrets;
+use reqwest::header::{COOKIE, LOCATION};
+use reqwest::redirect::Policy;
+use scraper::{Html, Selector};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::sync::Mutex;
+use std::time::Duration;
+use url::Url;
+
+/// Redirects followed after submitting the login form.
+const MAX_REDIRECTS: usize = 5;
+
+/// A login form to submit for a site that hides its pages behind a login, such as a
+/// private wiki or forum. The session cookies it sets are kept in the cookie jar.
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct SiteLogin {
+    /// The site the login is for, subdomains included.
+    pub domain: String,
+    /// The page with the login form.
+    pub url: String,
+    /// Form fields to fill in besides the password, such as the user name.
+    #[serde(default)]
+    pub fields: BTreeMap<String, String>,
+    /// Name of the form's password field.
+    #[serde(default = "default_password_field")]
+    pub password_field: String,
+    /// Name of the secret, in the OS keyring or the environment, holding the password.
+    pub password_key: String,
+}
+
+fn default_password_field() -> String {
+    "password".to_string()
+}
+
+impl SiteLogin {
+    /// Whether pages at `url` are behind this login.
+    pub fn covers(&self, url: &str) -> bool {
+        let host = Url::parse(url).ok().and_then(|url| url.host_str().map(str::to_ascii_lowercase));
+        host.is_some_and(|host| within(&host, self.domain.trim().trim_start_matches('.').to_ascii_lowercase().as_str()))
+    }
+
+    /// Submits the login form, keeping hidden fields such as CSRF tokens, and stores the
+    /// session cookies in `jar`.
+    pub async fn log_in(&self, jar: &Mutex<CookieJar>) -> Result<(), Box<dyn std::error::Error>> {
+        let password = secrets::require(&self.password_key)?;
+        let client = reqwest::Client::builder()
+            .timeout(Duration::from_secs(20))
+            // Redirects are followed by hand, so the cookies set along the way are kept
+            .redirect(Policy::none())
+            .build()?;
+
+        let page = get(&client, &self.url, jar).await?;
+        let form = LoginForm::find(&page.text().await?, &self.url, &self.password_field)
+            .ok_or_else(|| format!("No login form with a `{}` field at {}", self.password_field, self.url))?;
+        let mut fields = form.fields;
+        fields.extend(self.fields.clone());
+        fields.insert(self.password_field.clone(), password);
+
+        let mut request = client.post(form.action.as_str()).form(&fields);
+        if let Some(cookies) = jar.lock().unwrap().header(form.action.as_str()) {
+            request = request.header(COOKIE, cookies);
+        }
+        let mut response = request.send().await?;
+        let mut url = form.action;
+        jar.lock().unwrap().update(url.as_str(), response.headers());
+        for _ in 0..MAX_REDIRECTS {
+            let Some(next) = response
+                .headers()
+                .get(LOCATION)
+                .and_then(|location| location.to_str().ok())
+                .and_then(|location| url.join(location).ok())
+            else {
+                break;
+            };
+            url = next;
+            response = get(&client, url.as_str(), jar).await?;
+        }
+
+        if !response.status().is_success() {
+            return Err(format!("Logging in to {} failed (Status: {})", self.domain, response.status()).into());
+        }
+        if LoginForm::find(&response.text().await?, url.as_str(), &self.password_field).is_some() {
+            return Err(format!("Logging in to {} failed; check the fields and {}", self.domain, self.password_key).into());
+        }
+        jar.lock().unwrap().save()
+    }
+}
+
+/// GETs `url` with the jar's cookies and keeps those the response sets.
+async fn get(client: &reqwest::Client, url: &str, jar: &Mutex<CookieJar>) -> Result<reqwest::Response, reqwest::Error> {
+    let mut request = client.get(url);
+    if let Some(cookies) = jar.lock().unwrap().header(url) {
+        request = request.header(COOKIE, cookies);
+    }
+    let response = request.send().await?;
+    jar.lock().unwrap().update(url, response.headers());
+    Ok(response)
+}
+
+/// The login form of a page: where it posts to and the values already filled in.
+struct LoginForm {
+    action: Url,
+    fields: BTreeMap<String, String>,
+}
+
+impl LoginForm {
+    /// The form on the page that has a `password_field`.
+    fn find(html: &str, page_url: &str, password_field: &str) -> Option<Self> {
+        let document = Html::parse_document(html);
+        let forms = Selector::parse("form").ok()?;
+        let inputs = Selector::parse("input[name]").ok()?;
+        let form = document
+            .select(&forms)
+            .find(|form| form.select(&inputs).any(|input| input.value().attr("name") == Some(password_field)))?;
+
+        let page_url = Url::parse(page_url).ok()?;
+        let action = match form.value().attr("action").filter(|action| !action.is_empty()) {
+            Some(action) => page_url.join(action).ok()?,
+            None => page_url,
+        };
+        let fields = form
+            .select(&inputs)
+            .filter(|input| !matches!(input.value().attr("type"), Some("submit" | "button" | "checkbox" | "radio")))
+            .filter_map(|input| {
+                let name = input.value().attr("name")?;
+                Some((name.to_string(), input.value().attr("value").unwrap_or_default().to_string()))
+            })
+            .collect();
+        Some(LoginForm { action, fields })
+    }
+}
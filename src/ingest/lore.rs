@@ -0,0 +1,119 @@
+use std::fs;
+use std::path::Path;
+
+/// One lorebook entry: a Markdown file whose front matter gives its title, tags and
+/// keywords, e.g.
+///
+/// ```text
+/// ---
+/// title: Alya's family
+/// tags: [relationships]
+/// keywords: [Masha, Maria, sister]
+/// ---
+/// Alya's older sister Maria (Masha) ...
+/// ```
+#[derive(Debug, Clone, PartialEq)]
+pub struct LoreEntry {
+    pub title: String,
+    pub tags: Vec<String>,
+    pub keywords: Vec<String>,
+    pub text: String,
+}
+
+impl LoreEntry {
+    /// Reads an entry; without front matter the file name is the title and the whole file
+    /// is the text.
+    pub fn read(path: &Path) -> Result<Self, Box<dyn std::error::Error>> {
+        let content = fs::read_to_string(path)?;
+        let content = content.trim_start_matches('\u{feff}');
+        let mut entry = LoreEntry {
+            title: path.file_stem().map(|stem| stem.to_string_lossy().into_owned()).unwrap_or_default(),
+            tags: Vec::new(),
+            keywords: Vec::new(),
+            text: content.trim().to_string(),
+        };
+        let Some((front_matter, body)) = split_front_matter(content) else {
+            return Ok(entry);
+        };
+        entry.text = body.trim().to_string();
+
+        // `key: value`, `key: [a, b]`, or `key:` followed by `- a` lines
+        let mut list: Option<&mut Vec<String>> = None;
+        for line in front_matter.lines() {
+            let trimmed = line.trim();
+            if trimmed.is_empty() || trimmed.starts_with('#') {
+                continue;
+            }
+            if let (Some(item), Some(list)) = (trimmed.strip_prefix("- "), list.as_deref_mut()) {
+                list.push(unquote(item));
+                continue;
+            }
+            let Some((key, value)) = trimmed.split_once(':') else {
+                return Err(format!("Can't read front matter line `{}` in {}", trimmed, path.display()).into());
+            };
+            let value = value.trim();
+            list = None;
+            match key.trim().to_ascii_lowercase().as_str() {
+                "title" => entry.title = unquote(value),
+                "tags" => {
+                    entry.tags = parse_list(value);
+                    list = Some(&mut entry.tags);
+                }
+                "keywords" | "keys" => {
+                    entry.keywords = parse_list(value);
+                    list = Some(&mut entry.keywords);
+                }
+                _ => {}
+            }
+        }
+        Ok(entry)
+    }
+
+    /// The text as it is learned, headed by the title.
+    pub fn fact_text(&self) -> String {
+        format!("# {}\n\n{}", self.title, self.text)
+    }
+
+    /// The entry as a file of the lore directory, which `read` reads back.
+    pub fn markdown(&self) -> String {
+        let mut front_matter = format!("title: \"{}\"\n", self.title);
+        for (key, list) in [("tags", &self.tags), ("keywords", &self.keywords)] {
+            if !list.is_empty() {
+                front_matter.push_str(&format!("{}:\n", key));
+                for item in list {
+                    front_matter.push_str(&format!("  - \"{}\"\n", item));
+                }
+            }
+        }
+        format!("---\n{}---\n{}\n", front_matter, self.text)
+    }
+}
+
+/// The front matter between the leading `---` lines, and the rest of the file.
+fn split_front_matter(content: &str) -> Option<(&str, &str)> {
+    let rest = content.strip_prefix("---")?.trim_start_matches([' ', '\t']);
+    let rest = rest.strip_prefix("\r\n").or_else(|| rest.strip_prefix('\n'))?;
+    let mut offset = 0;
+    for line in rest.split_inclusive('\n') {
+        if line.trim_end() == "---" {
+            return Some((&rest[..offset], &rest[offset + line.len()..]));
+        }
+        offset += line.len();
+    }
+    None
+}
+
+fn parse_list(value: &str) -> Vec<String> {
+    let value = value.strip_prefix('[').and_then(|value| value.strip_suffix(']')).unwrap_or(value);
+    value.split(',').map(unquote).filter(|item| !item.is_empty()).collect()
+}
+
+fn unquote(value: &str) -> String {
+    let value = value.trim();
+    value
+        .strip_prefix('"')
+        .and_then(|value| value.strip_suffix('"'))
+        .or_else(|| value.strip_prefix('\'').and_then(|value| value.strip_suffix('\'')))
+        .unwrap_or(value)
+        .to_string()
+}
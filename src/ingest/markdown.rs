@@ -0,0 +1,288 @@
+use scraper::{ElementRef, Html, Node, Selector};
+
+/// Where the content of a page usually is, most specific first.
+const CONTENT_SELECTORS: [&str; 5] = [".mw-parser-output", "article", "main", "#content", "body"];
+/// Elements that never hold content worth learning.
+const SKIPPED_TAGS: [&str; 12] = [
+    "head", "script", "style", "noscript", "template", "svg", "nav", "footer", "form", "button", "iframe", "select",
+];
+/// Wiki navigation boxes, edit links, footnote markers and tables of contents.
+const SKIPPED_CLASSES: [&str; 7] = ["navbox", "mw-editsection", "noprint", "toc", "reference", "mw-jump-link", "metadata"];
+const BLOCK_TAGS: [&str; 14] = [
+    "p", "div", "section", "article", "aside", "main", "header", "figure", "figcaption", "dl", "center", "details",
+    "summary", "address",
+];
+/// Tables with cells longer than this are page layout rather than data.
+const LAYOUT_CELL_LENGTH: usize = 300;
+
+/// Converts a web page to Markdown, keeping headings, lists, emphasis and tables so that
+/// the structure of infoboxes and relationship tables survives into the learned facts.
+/// Only the main content of the page is converted; navigation, scripts and the like are
+/// dropped.
+pub fn html_to_markdown(html: &str) -> String {
+    let document = Html::parse_document(html);
+    let root = content_root(&document);
+
+    let mut writer = Writer::default();
+    let has_title = Selector::parse("h1").is_ok_and(|h1| root.select(&h1).next().is_some());
+    let title = Selector::parse("title").ok().and_then(|title| document.select(&title).next());
+    if let (false, Some(title)) = (has_title, title) {
+        writer.heading(1, &inline_text(title));
+    }
+    writer.children(root);
+    writer.finish()
+}
+
+/// The element holding the main content of the page.
+pub(super) fn content_root(document: &Html) -> ElementRef<'_> {
+    CONTENT_SELECTORS
+        .iter()
+        .filter_map(|selector| Selector::parse(selector).ok())
+        .find_map(|selector| document.select(&selector).next())
+        .unwrap_or_else(|| document.root_element())
+}
+
+/// The collapsed text of an element on one line, with emphasis kept.
+fn inline_text(element: ElementRef) -> String {
+    let mut writer = Writer::default();
+    writer.children(element);
+    writer.finish().split_whitespace().collect::<Vec<_>>().join(" ")
+}
+
+#[derive(Default)]
+struct Writer {
+    out: String,
+    /// Open lists, innermost last: the next number of a numbered list, or None.
+    lists: Vec<Option<usize>>,
+    /// Whitespace was seen since the last text.
+    space: bool,
+}
+
+impl Writer {
+    fn finish(self) -> String {
+        self.out.trim().to_string()
+    }
+
+    fn children(&mut self, element: ElementRef) {
+        for child in element.children() {
+            match child.value() {
+                Node::Text(text) => self.text(text),
+                Node::Element(_) => {
+                    if let Some(child) = ElementRef::wrap(child) {
+                        self.element(child);
+                    }
+                }
+                _ => {}
+            }
+        }
+    }
+
+    fn element(&mut self, element: ElementRef) {
+        let value = element.value();
+        let name = value.name();
+        let hidden = value.attr("hidden").is_some()
+            || value.attr("style").is_some_and(|style| style.replace(' ', "").contains("display:none"));
+        if hidden || SKIPPED_TAGS.contains(&name) || value.classes().any(|class| SKIPPED_CLASSES.contains(&class)) {
+            return;
+        }
+
+        match name {
+            "h1" | "h2" | "h3" | "h4" | "h5" | "h6" => {
+                let level = name[1..].parse().unwrap_or(1);
+                self.heading(level, &inline_text(element));
+            }
+            "br" => self.line_break(),
+            "hr" => {
+                self.paragraph_break();
+                self.out.push_str("---");
+                self.paragraph_break();
+            }
+            "ul" | "ol" => {
+                self.paragraph_break();
+                self.lists.push((name == "ol").then_some(1));
+                self.children(element);
+                self.lists.pop();
+                self.paragraph_break();
+            }
+            "li" => self.list_item(element),
+            "table" => self.table(element),
+            "strong" | "b" => self.wrapped(element, "**"),
+            "em" | "i" => self.wrapped(element, "*"),
+            "code" => self.wrapped(element, "`"),
+            "pre" => {
+                self.paragraph_break();
+                self.out.push_str("```\n");
+                self.out.push_str(element.text().collect::<String>().trim_end());
+                self.out.push_str("\n```");
+                self.paragraph_break();
+            }
+            "blockquote" => {
+                let mut quote = Writer::default();
+                quote.children(element);
+                self.paragraph_break();
+                for line in quote.finish().lines() {
+                    self.out.push_str(format!("> {}", line).trim_end());
+                    self.out.push('\n');
+                }
+                self.paragraph_break();
+            }
+            "dt" => {
+                self.line_break();
+                self.wrapped(element, "**");
+            }
+            "dd" => {
+                self.line_break();
+                self.out.push_str(": ");
+                self.children(element);
+                self.line_break();
+            }
+            _ if BLOCK_TAGS.contains(&name) => {
+                self.paragraph_break();
+                self.children(element);
+                self.paragraph_break();
+            }
+            _ => self.children(element),
+        }
+    }
+
+    fn text(&mut self, text: &str) {
+        if text.starts_with(char::is_whitespace) {
+            self.space = true;
+        }
+        let collapsed = text.split_whitespace().collect::<Vec<_>>().join(" ");
+        if !collapsed.is_empty() {
+            self.inline(&collapsed);
+        }
+        if text.ends_with(char::is_whitespace) {
+            self.space = true;
+        }
+    }
+
+    fn inline(&mut self, text: &str) {
+        if self.space && !self.out.is_empty() && !self.out.ends_with(char::is_whitespace) {
+            self.out.push(' ');
+        }
+        self.space = false;
+        self.out.push_str(text);
+    }
+
+    fn wrapped(&mut self, element: ElementRef, mark: &str) {
+        let text = inline_text(element);
+        if text.is_empty() {
+            return;
+        }
+        let raw: String = element.text().collect();
+        if raw.starts_with(char::is_whitespace) {
+            self.space = true;
+        }
+        self.inline(&format!("{}{}{}", mark, text, mark));
+        if raw.ends_with(char::is_whitespace) {
+            self.space = true;
+        }
+    }
+
+    fn heading(&mut self, level: usize, text: &str) {
+        if text.is_empty() {
+            return;
+        }
+        self.paragraph_break();
+        self.out.push_str(&"#".repeat(level.clamp(1, 6)));
+        self.out.push(' ');
+        self.out.push_str(text);
+        self.paragraph_break();
+    }
+
+    fn list_item(&mut self, element: ElementRef) {
+        self.line_break();
+        let start = self.out.len();
+        let marker = match self.lists.last_mut() {
+            Some(Some(number)) => {
+                *number += 1;
+                format!("{}. ", *number - 1)
+            }
+            _ => "- ".to_string(),
+        };
+        self.out.push_str(&"  ".repeat(self.lists.len().saturating_sub(1)));
+        self.out.push_str(&marker);
+        let marker_end = self.out.len();
+        self.children(element);
+        if self.out.trim_end().len() < marker_end {
+            self.out.truncate(start);
+        }
+        self.line_break();
+    }
+
+    /// Ends the current line. Inside lists, where a blank line would end the list, this is
+    /// also used between paragraphs.
+    fn line_break(&mut self) {
+        let trimmed = self.out.trim_end_matches([' ', '\t']).len();
+        self.out.truncate(trimmed);
+        if !self.out.is_empty() && !self.out.ends_with('\n') {
+            self.out.push('\n');
+        }
+        self.space = false;
+    }
+
+    fn paragraph_break(&mut self) {
+        self.line_break();
+        if self.lists.is_empty() && !self.out.is_empty() && !self.out.ends_with("\n\n") {
+            self.out.push('\n');
+        }
+    }
+
+    fn table(&mut self, table: ElementRef) {
+        let elements = table_rows(table);
+        let mut rows: Vec<Vec<String>> = Vec::new();
+        for row in &elements {
+            let mut cells = Vec::new();
+            for cell in row.children().filter_map(ElementRef::wrap) {
+                if !matches!(cell.value().name(), "th" | "td") {
+                    continue;
+                }
+                cells.push(inline_text(cell).replace('|', "\\|"));
+                let span: usize = cell.value().attr("colspan").and_then(|span| span.parse().ok()).unwrap_or(1);
+                cells.extend(std::iter::repeat_n(String::new(), span.clamp(1, 10) - 1));
+            }
+            if cells.iter().any(|cell| !cell.is_empty()) {
+                rows.push(cells);
+            }
+        }
+
+        let width = rows.iter().map(Vec::len).max().unwrap_or(0);
+        let layout = rows.iter().flatten().any(|cell| cell.chars().count() > LAYOUT_CELL_LENGTH);
+        if layout || width < 2 || rows.len() < 2 {
+            // Not tabular data; keep the text of the cells as paragraphs.
+            for cell in elements.iter().flat_map(|row| row.children().filter_map(ElementRef::wrap)) {
+                self.paragraph_break();
+                self.children(cell);
+                self.paragraph_break();
+            }
+            return;
+        }
+
+        self.paragraph_break();
+        for (index, row) in rows.iter_mut().enumerate() {
+            row.resize(width, String::new());
+            self.out.push_str(&format!("| {} |\n", row.join(" | ")));
+            if index == 0 {
+                self.out.push_str(&format!("|{}\n", " --- |".repeat(width)));
+            }
+        }
+        self.paragraph_break();
+    }
+}
+
+/// The rows of `table`, without those of tables nested in it.
+fn table_rows(table: ElementRef) -> Vec<ElementRef> {
+    let Ok(selector) = Selector::parse("tr") else {
+        return Vec::new();
+    };
+    table
+        .select(&selector)
+        .filter(|row| {
+            row.ancestors()
+                .find(|node| node.value().as_element().is_some_and(|element| element.name() == "table"))
+                .is_some_and(|closest| closest.id() == table.id())
+        })
+        .collect()
+}
@@ -0,0 +1,391 @@
+use super::html_to_markdown;
+use scraper::{Html, Selector};
+use serde_json::Value;
+use std::collections::HashMap;
+use std::sync::Mutex;
+use std::time::Duration;
+use url::Url;
+
+/// Rendered infoboxes, replaced by the fields read from the wikitext.
+const INFOBOX_SELECTOR: &str = "aside.portable-infobox, table.infobox";
+/// Infobox fields that hold pictures and layout rather than facts.
+const SKIPPED_FIELDS: [&str; 6] = ["image", "imagecaption", "caption", "image_size", "imagesize", "title"];
+/// A template with this many named parameters at the top of an article is taken for its
+/// infobox, whatever it is called.
+const MIN_INFOBOX_FIELDS: usize = 3;
+
+/// An article read through the MediaWiki API.
+#[derive(Debug)]
+pub struct WikiArticle {
+    pub title: String,
+    /// The rendered article, for following its links.
+    pub html: String,
+    pub infobox: Option<Infobox>,
+    /// The article text as Markdown, without the infobox.
+    pub text: String,
+}
+
+impl WikiArticle {
+    /// The article as it is learned from: the infobox as a list, then the text.
+    pub fn markdown(&self) -> String {
+        let mut markdown = format!("# {}\n\n", self.title);
+        if let Some(infobox) = &self.infobox {
+            markdown.push_str("## Infobox\n\n");
+            for (field, value) in &infobox.fields {
+                markdown.push_str(&format!("- {}: {}\n", field, value));
+            }
+            markdown.push('\n');
+        }
+        markdown.push_str(&self.text);
+        markdown
+    }
+}
+
+/// The data fields of an article's infobox, such as a character's age, birthday,
+/// affiliations and voice actors.
+#[derive(Debug, Clone)]
+pub struct Infobox {
+    /// The article the infobox is in.
+    pub subject: String,
+    /// The fields in the order the infobox lists them.
+    pub fields: Vec<(String, String)>,
+}
+
+impl Infobox {
+    /// The fields as a list headed by what they describe.
+    pub fn text(&self) -> String {
+        let mut text = format!("Profile of {}:\n", self.subject);
+        for (field, value) in &self.fields {
+            text.push_str(&format!("- {}: {}\n", field, value));
+        }
+        text
+    }
+}
+
+/// Finds out which sites run MediaWiki and reads their articles through its API, which
+/// gives the article without the site's skin and the infobox as data.
+pub struct MediaWiki {
+    /// The API endpoint of each wiki probed so far, by the URL prefix of its articles;
+    /// None for sites that turned out not to be wikis.
+    apis: Mutex<HashMap<String, Option<String>>>,
+    client: reqwest::Client,
+}
+
+impl MediaWiki {
+    pub fn new() -> Self {
+        MediaWiki {
+            apis: Mutex::new(HashMap::new()),
+            client: reqwest::Client::builder()
+                .timeout(Duration::from_secs(20))
+                .build()
+                .unwrap_or_default(),
+        }
+    }
+
+    /// The article at `url` if it is on a MediaWiki site, or None if it is not a wiki
+    /// article, such as a page of any other site.
+    pub async fn article(&self, url: &str) -> Option<Result<WikiArticle, Box<dyn std::error::Error>>> {
+        let (root, title) = article_location(url)?;
+        let api = self.api(&root).await?;
+        Some(self.fetch_article(&api, &title).await)
+    }
+
+    /// The wiki's API endpoint, probing the usual places the first time.
+    async fn api(&self, root: &str) -> Option<String> {
+        if let Some(api) = self.apis.lock().unwrap().get(root) {
+            return api.clone();
+        }
+        let mut found = None;
+        for api in [format!("{}/api.php", root), format!("{}/w/api.php", root)] {
+            let probe = self
+                .client
+                .get(&api)
+                .query(&[("action", "query"), ("meta", "siteinfo"), ("format", "json")])
+                .send()
+                .await;
+            let Ok(response) = probe else {
+                continue;
+            };
+            let info: Option<Value> = response.json().await.ok();
+            let generator = info.as_ref().and_then(|info| info["query"]["general"]["generator"].as_str());
+            if generator.is_some_and(|generator| generator.starts_with("MediaWiki")) {
+                found = Some(api);
+                break;
+            }
+        }
+        // A wiki that could not be reached is probed again on the next page
+        self.apis.lock().unwrap().insert(root.to_string(), found.clone());
+        found
+    }
+
+    async fn fetch_article(&self, api: &str, title: &str) -> Result<WikiArticle, Box<dyn std::error::Error>> {
+        let response: Value = self
+            .client
+            .get(api)
+            .query(&[
+                ("action", "parse"),
+                ("page", title),
+                ("prop", "text|wikitext"),
+                ("redirects", "1"),
+                ("disableeditsection", "1"),
+                ("disabletoc", "1"),
+                ("format", "json"),
+                ("formatversion", "2"),
+            ])
+            .send()
+            .await?
+            .error_for_status()?
+            .json()
+            .await?;
+        if let Some(error) = response["error"]["info"].as_str() {
+            return Err(format!("The wiki could not return {}: {}", title, error).into());
+        }
+        let parse = &response["parse"];
+        let html = parse["text"].as_str().ok_or("The wiki returned no article text")?.to_string();
+        // Fandom's portable infoboxes label their fields; other infoboxes are read from
+        // the template in the source
+        let mut fields = portable_infobox(&html);
+        if fields.is_empty() {
+            fields = infobox(parse["wikitext"].as_str().unwrap_or_default());
+        }
+        let text = match fields.is_empty() {
+            true => html_to_markdown(&html),
+            false => html_to_markdown(&without_infobox(&html)),
+        };
+        let title = parse["title"].as_str().unwrap_or(title).to_string();
+        Ok(WikiArticle {
+            infobox: (!fields.is_empty()).then(|| Infobox {
+                subject: title.clone(),
+                fields,
+            }),
+            title,
+            html,
+            text,
+        })
+    }
+}
+
+/// The URL of an article on a Fandom wiki, such as `roshidere.fandom.com`, in one of its
+/// other language editions when `language` is set.
+pub fn fandom_url(wiki: &str, page: &str, language: Option<&str>) -> Result<String, url::ParseError> {
+    let language = language.map(|language| format!("/{}", language.trim_matches('/'))).unwrap_or_default();
+    let base = Url::parse(&format!("https://{}.fandom.com{}/wiki/", wiki.trim().to_ascii_lowercase(), language))?;
+    Ok(base.join(&page.trim().replace(' ', "_"))?.to_string())
+}
+
+/// The URL prefix of the wiki's articles and the article's title, for `/wiki/Title` and
+/// `index.php?title=Title` URLs.
+fn article_location(url: &str) -> Option<(String, String)> {
+    let url = Url::parse(url).ok()?;
+    let origin = url.origin().ascii_serialization();
+    let path = url.path();
+    if let Some(at) = path.find("/wiki/") {
+        let title = &path[at + "/wiki/".len()..];
+        let title = percent_decode(title).replace('_', " ");
+        return (!title.is_empty()).then(|| (format!("{}{}", origin, &path[..at]), title));
+    }
+    let directory = path.strip_suffix("/index.php")?;
+    let title = url.query_pairs().find(|(key, _)| key == "title")?.1.replace('_', " ");
+    Some((format!("{}{}", origin, directory), title))
+}
+
+fn percent_decode(text: &str) -> String {
+    url::form_urlencoded::parse(format!("x={}", text.replace('+', "%2B")).as_bytes())
+        .next()
+        .map(|(_, value)| value.into_owned())
+        .unwrap_or_else(|| text.to_string())
+}
+
+/// The rendered article with its infoboxes taken out.
+fn without_infobox(html: &str) -> String {
+    let mut document = Html::parse_document(html);
+    let Ok(selector) = Selector::parse(INFOBOX_SELECTOR) else {
+        return html.to_string();
+    };
+    let ids: Vec<_> = document.select(&selector).map(|element| element.id()).collect();
+    for id in ids {
+        if let Some(mut node) = document.tree.get_mut(id) {
+            node.detach();
+        }
+    }
+    document.html()
+}
+
+/// The labelled fields of the first portable infobox in a rendered Fandom article.
+/// Values on several lines or in lists are joined with commas.
+fn portable_infobox(html: &str) -> Vec<(String, String)> {
+    let document = Html::parse_document(html);
+    let (Ok(infobox), Ok(data), Ok(label), Ok(value)) = (
+        Selector::parse("aside.portable-infobox"),
+        Selector::parse(".pi-data"),
+        Selector::parse(".pi-data-label"),
+        Selector::parse(".pi-data-value"),
+    ) else {
+        return Vec::new();
+    };
+    let Some(infobox) = document.select(&infobox).next() else {
+        return Vec::new();
+    };
+    let collapse = |text: String| text.split_whitespace().collect::<Vec<_>>().join(" ");
+    infobox
+        .select(&data)
+        .filter_map(|item| {
+            let label = item
+                .select(&label)
+                .next()
+                .map(|label| collapse(label.text().collect()))
+                .or_else(|| item.value().attr("data-source").map(field_label))?;
+            let value = item.select(&value).next()?.inner_html();
+            let value = value.replace("<br>", ", ").replace("</li>", ", ");
+            let value = collapse(Html::parse_fragment(&value).root_element().text().collect());
+            let value = value.trim_matches([',', ' ']).replace(" ,", ",");
+            let skipped = SKIPPED_FIELDS.contains(&label.to_ascii_lowercase().as_str());
+            (!label.is_empty() && !value.is_empty() && !skipped).then_some((label, value))
+        })
+        .collect()
+}
+
+/// The fields of the article's infobox: the first template named like an infobox, or else
+/// the first with several named parameters, before the first section heading.
+pub(super) fn infobox(wikitext: &str) -> Vec<(String, String)> {
+    let lead = wikitext.find("\n==").map_or(wikitext, |at| &wikitext[..at]);
+    let templates: Vec<(String, Vec<(String, String)>)> = top_level_templates(lead)
+        .into_iter()
+        .map(|template| {
+            let mut parts = split_top_level(template, '|').into_iter();
+            let name = parts.next().unwrap_or_default().trim().to_string();
+            let fields = parts
+                .filter_map(|part| {
+                    let (key, value) = part.split_once('=')?;
+                    Some((key.trim().to_string(), clean_wikitext(value)))
+                })
+                .collect();
+            (name, fields)
+        })
+        .collect();
+    let chosen = templates
+        .iter()
+        .find(|(name, _)| name.to_ascii_lowercase().contains("infobox"))
+        .or_else(|| templates.iter().find(|(_, fields)| fields.len() >= MIN_INFOBOX_FIELDS));
+    let Some((_, fields)) = chosen else {
+        return Vec::new();
+    };
+    fields
+        .iter()
+        .filter(|(key, value)| !key.is_empty() && !value.is_empty() && !SKIPPED_FIELDS.contains(&key.to_ascii_lowercase().as_str()))
+        .map(|(key, value)| (field_label(key), value.clone()))
+        .collect()
+}
+
+/// `voice_actor` → `Voice actor`.
+fn field_label(key: &str) -> String {
+    let label = key.replace('_', " ");
+    let mut chars = label.chars();
+    match chars.next() {
+        Some(first) => first.to_uppercase().chain(chars).collect(),
+        None => label,
+    }
+}
+
+/// The inner text of the `{{...}}` templates that are not inside another template.
+fn top_level_templates(text: &str) -> Vec<&str> {
+    let bytes = text.as_bytes();
+    let mut templates = Vec::new();
+    let (mut depth, mut start, mut index) = (0, 0, 0);
+    while index + 1 < bytes.len() {
+        match &bytes[index..index + 2] {
+            b"{{" => {
+                if depth == 0 {
+                    start = index + 2;
+                }
+                depth += 1;
+                index += 2;
+            }
+            b"}}" if depth > 0 => {
+                depth -= 1;
+                if depth == 0 {
+                    templates.push(&text[start..index]);
+                }
+                index += 2;
+            }
+            _ => index += 1,
+        }
+    }
+    templates
+}
+
+/// Splits at `separator`s outside nested templates and links.
+fn split_top_level(text: &str, separator: char) -> Vec<&str> {
+    let mut parts = Vec::new();
+    let (mut depth, mut start) = (0i32, 0);
+    let mut previous = ' ';
+    for (index, c) in text.char_indices() {
+        match c {
+            '{' | '[' if previous == c => depth += 1,
+            '}' | ']' if previous == c => depth -= 1,
+            c if c == separator && depth <= 0 => {
+                parts.push(&text[start..index]);
+                start = index + c.len_utf8();
+            }
+            _ => {}
+        }
+        // `{{{` must not count as two openings of a pair
+        previous = if previous == c { ' ' } else { c };
+    }
+    parts.push(&text[start..]);
+    parts
+}
+
+/// A wikitext value as plain text: links become their labels, and references, comments,
+/// nested templates, files, categories and formatting are dropped.
+fn clean_wikitext(value: &str) -> String {
+    let mut text = value.to_string();
+    while let Some(start) = text.find("<!--") {
+        let end = text[start..].find("-->").map_or(text.len(), |end| start + end + "-->".len());
+        text.replace_range(start..end, "");
+    }
+    while let Some(start) = text.find("<ref") {
+        let Some(tag_end) = text[start..].find('>').map(|end| start + end) else {
+            break;
+        };
+        let end = match text[..tag_end].ends_with('/') {
+            true => tag_end + 1,
+            false => text[tag_end..].find("</ref>").map_or(text.len(), |end| tag_end + end + "</ref>".len()),
+        };
+        text.replace_range(start..end, "");
+    }
+    for template in top_level_templates(&text.clone()) {
+        text = text.replacen(&format!("{{{{{}}}}}", template), "", 1);
+    }
+    let text = text.replace("<br>", ", ").replace("<br/>", ", ").replace("<br />", ", ");
+
+    let mut plain = String::new();
+    let mut rest = text.as_str();
+    while let Some(start) = rest.find('[') {
+        plain.push_str(&rest[..start]);
+        rest = &rest[start..];
+        if let Some(link) = rest.strip_prefix("[[") {
+            let Some(end) = link.find("]]") else {
+                break;
+            };
+            let mut parts = link[..end].split('|');
+            let target = parts.next().unwrap_or_default();
+            // Files, categories and links to other languages
+            if !target.contains(':') {
+                plain.push_str(parts.next_back().unwrap_or(target));
+            }
+            rest = &link[end + 2..];
+        } else {
+            // External links: `[url label]`
+            let Some(end) = rest.find(']') else {
+                break;
+            };
+            plain.push_str(rest[1..end].split_once(' ').map_or("", |(_, label)| label));
+            rest = &rest[end + 1..];
+        }
+    }
+    plain.push_str(rest);
+    let plain = plain.replace("'''", "").replace("''", "");
+    let plain: String = Html::parse_fragment(&plain).root_element().text().collect();
+    plain.split_whitespace().collect::<Vec<_>>().join(" ").trim_matches([',', ' ']).to_string()
+}
@@ -0,0 +1,43 @@
+//! Turning fetched pages and documents into text the model can learn from.
+
+mod anilist;
+mod browser;
+mod chunk;
+mod cookies;
+mod crawl;
+mod document;
+mod domains;
+mod feed;
+mod login;
+mod lore;
+mod markdown;
+mod mediawiki;
+mod ocr;
+mod qa;
+mod reddit;
+mod robots;
+mod sitemap;
+mod vndb;
+mod wikipedia;
+mod youtube;
+
+pub use anilist::{anilist_search_url, fetch_anilist_character};
+pub use browser::{render_page, BrowserSettings};
+pub use chunk::{chunk_text, split_sections};
+pub use cookies::CookieJar;
+pub use crawl::same_site_links;
+pub use document::{document_files, read_document};
+pub use domains::{url_matches, DomainFilter};
+pub use feed::{fetch_feed, FeedEntry};
+pub use login::SiteLogin;
+pub use lore::LoreEntry;
+pub use markdown::html_to_markdown;
+pub use mediawiki::{fandom_url, Infobox, MediaWiki};
+pub use ocr::{download_image, read_image, transcribe_image};
+pub use qa::read_qa_pairs;
+pub use reddit::{fetch_reddit_thread, reddit_thread_id, RedditSettings};
+pub use robots::RobotsCache;
+pub use sitemap::sitemap_urls;
+pub use vndb::{fetch_vndb_character, vndb_search_url, VndbSettings};
+pub use wikipedia::{fetch_wikipedia, wikipedia_url};
+pub use youtube::{fetch_transcript, youtube_video_id};
@@ -0,0 +1,105 @@
+use crate::secrets;
+use base64::Engine;
+use serde_json::{json, Value};
+use std::fs;
+use std::path::Path;
+
+/// Gemini's limit for images sent inline with a request.
+const MAX_IMAGE_BYTES: usize = 20 * 1024 * 1024;
+const VISION_MODEL: &str = "gemini-2.0-flash";
+
+const TRANSCRIBE_PROMPT: &str = "Transcribe all the text in this image, such as an official character profile, a manga page \
+    or a scanned artbook page. Keep the reading order (right to left for manga panels), write speech bubbles as \
+    \"Speaker: line\" where the speaker is clear, and keep tables and labelled fields as lists. Add one sentence on what \
+    the image shows if it helps to understand the text. Answer only with the transcription, or with NO TEXT if there is none.";
+
+/// An image file or download to read text from.
+pub struct Image {
+    pub data: Vec<u8>,
+    pub mime_type: String,
+}
+
+/// The image type of a file name or URL path, from its extension.
+fn mime_type(name: &str) -> Option<&'static str> {
+    let extension = name.rsplit_once('.')?.1.to_ascii_lowercase();
+    match extension.as_str() {
+        "png" => Some("image/png"),
+        "jpg" | "jpeg" => Some("image/jpeg"),
+        "webp" => Some("image/webp"),
+        "gif" => Some("image/gif"),
+        "heic" => Some("image/heic"),
+        "heif" => Some("image/heif"),
+        _ => None,
+    }
+}
+
+/// Reads a PNG, JPEG, WebP, GIF or HEIC image from disk.
+pub fn read_image(path: &Path) -> Result<Image, Box<dyn std::error::Error>> {
+    let mime_type = mime_type(&path.to_string_lossy())
+        .ok_or_else(|| format!("Can't read {}; supported images are .png, .jpg, .webp, .gif and .heic", path.display()))?;
+    let data = fs::read(path)?;
+    check_size(data.len())?;
+    Ok(Image {
+        data,
+        mime_type: mime_type.to_string(),
+    })
+}
+
+/// Downloads an image, taking its type from the response or else from the URL.
+pub async fn download_image(client: &reqwest::Client, url: &str) -> Result<Image, Box<dyn std::error::Error>> {
+    let response = client.get(url).send().await?;
+    if !response.status().is_success() {
+        return Err(format!("Failed to download {} (Status: {})", url, response.status()).into());
+    }
+    let served = response
+        .headers()
+        .get("Content-Type")
+        .and_then(|value| value.to_str().ok())
+        .and_then(|value| value.split(';').next())
+        .map(|value| value.trim().to_ascii_lowercase())
+        .filter(|value| value.starts_with("image/"));
+    let path = url::Url::parse(url)?.path().to_string();
+    let mime_type = served
+        .or_else(|| mime_type(&path).map(str::to_string))
+        .ok_or_else(|| format!("{} is not an image", url))?;
+    let data = response.bytes().await?.to_vec();
+    check_size(data.len())?;
+    Ok(Image { data, mime_type })
+}
+
+fn check_size(bytes: usize) -> Result<(), Box<dyn std::error::Error>> {
+    if bytes > MAX_IMAGE_BYTES {
+        return Err(format!("The image is {} MB; images over {} MB can't be read", bytes / 1024 / 1024, MAX_IMAGE_BYTES / 1024 / 1024).into());
+    }
+    Ok(())
+}
+
+/// Reads the text in an image with Gemini's vision model. Returns an empty string when the
+/// image has no text.
+pub async fn transcribe_image(client: &reqwest::Client, image: &Image) -> Result<String, Box<dyn std::error::Error>> {
+    let api_key = secrets::require("GEMINI_API_KEY")?;
+    let response = client
+        .post(format!(
+            "https://generativelanguage.googleapis.com/v1beta/models/{}:generateContent",
+            VISION_MODEL
+        ))
+        .header("x-goog-api-key", api_key)
+        .json(&json!({
+            "contents": [{
+                "parts": [
+                    { "inline_data": { "mime_type": image.mime_type, "data": base64::engine::general_purpose::STANDARD.encode(&image.data) } },
+                    { "text": TRANSCRIBE_PROMPT }
+                ]
+            }]
+        }))
+        .send()
+        .await?;
+    let status = response.status();
+    let body: Value = response.json().await?;
+    if !status.is_success() {
+        let message = body["error"]["message"].as_str().unwrap_or("no details given");
+        return Err(format!("Gemini could not read the image (Status: {}): {}", status, message).into());
+    }
+    let text = body["candidates"][0]["content"]["parts"][0]["text"].as_str().unwrap_or_default().trim();
+    Ok(if text.eq_ignore_ascii_case("NO TEXT") { String::new() } else { text.to_string() })
+}
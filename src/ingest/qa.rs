@@ -0,0 +1,89 @@
+use serde_json::Value;
+use sha2::{Digest, Sha256};
+use std::fs;
+use std::path::Path;
+
+/// Field names accepted for the question and the answer, in order of preference.
+const QUESTION_FIELDS: [&str; 4] = ["question", "q", "prompt", "instruction"];
+const ANSWER_FIELDS: [&str; 5] = ["answer", "a", "response", "completion", "output"];
+
+/// A curated question about the character and the answer it should give.
+#[derive(Debug, Clone, PartialEq)]
+pub struct QaPair {
+    pub question: String,
+    pub answer: String,
+}
+
+impl QaPair {
+    /// The fact key for the pair, from its question alone, so importing a dataset again
+    /// replaces the answers that changed instead of adding them twice.
+    pub fn key(&self) -> String {
+        let question = self.question.split_whitespace().collect::<Vec<_>>().join(" ").to_lowercase();
+        let hash = format!("{:x}", Sha256::digest(question.as_bytes()));
+        format!("qa_{}", &hash[..16])
+    }
+
+    /// The text as it is learned.
+    pub fn fact_text(&self) -> String {
+        format!("Q: {}\nA: {}", self.question, self.answer)
+    }
+}
+
+/// Reads question-answer pairs from a JSONL file (one `{"question": ..., "answer": ...}`
+/// object per line) or a CSV file with `question` and `answer` columns. Common
+/// alternative names such as `prompt`/`response` are accepted too.
+pub fn read_qa_pairs(path: &Path) -> Result<Vec<QaPair>, Box<dyn std::error::Error>> {
+    let extension = path.extension().and_then(|ext| ext.to_str()).unwrap_or("").to_ascii_lowercase();
+    let pairs = match extension.as_str() {
+        "jsonl" | "ndjson" => read_jsonl(path)?,
+        "csv" => read_csv(path)?,
+        _ => return Err(format!("Can't read {}; Q&A datasets must be .jsonl or .csv", path.display()).into()),
+    };
+    if pairs.is_empty() {
+        return Err(format!("No question-answer pairs found in {}", path.display()).into());
+    }
+    Ok(pairs)
+}
+
+fn read_jsonl(path: &Path) -> Result<Vec<QaPair>, Box<dyn std::error::Error>> {
+    let mut pairs = Vec::new();
+    for (number, line) in fs::read_to_string(path)?.lines().enumerate() {
+        if line.trim().is_empty() {
+            continue;
+        }
+        let record: Value =
+            serde_json::from_str(line).map_err(|e| format!("Line {} of {} is not JSON: {}", number + 1, path.display(), e))?;
+        let field = |names: &[&str]| names.iter().find_map(|name| record.get(*name)?.as_str()).map(str::trim);
+        match (field(&QUESTION_FIELDS), field(&ANSWER_FIELDS)) {
+            (Some(question), Some(answer)) if !question.is_empty() && !answer.is_empty() => pairs.push(QaPair {
+                question: question.to_string(),
+                answer: answer.to_string(),
+            }),
+            _ => status!("Skipping line {} of {}: no question and answer", number + 1, path.display()),
+        }
+    }
+    Ok(pairs)
+}
+
+fn read_csv(path: &Path) -> Result<Vec<QaPair>, Box<dyn std::error::Error>> {
+    let mut reader = csv::ReaderBuilder::new().flexible(true).from_path(path)?;
+    let headers: Vec<String> = reader.headers()?.iter().map(|header| header.trim().to_ascii_lowercase()).collect();
+    let column = |names: &[&str]| names.iter().find_map(|name| headers.iter().position(|header| header == name));
+    let (Some(question), Some(answer)) = (column(&QUESTION_FIELDS), column(&ANSWER_FIELDS)) else {
+        return Err(format!("{} needs `question` and `answer` columns", path.display()).into());
+    };
+
+    let mut pairs = Vec::new();
+    for record in reader.records() {
+        let record = record?;
+        let line = record.position().map_or(0, |position| position.line());
+        match (record.get(question).map(str::trim), record.get(answer).map(str::trim)) {
+            (Some(question), Some(answer)) if !question.is_empty() && !answer.is_empty() => pairs.push(QaPair {
+                question: question.to_string(),
+                answer: answer.to_string(),
+            }),
+            _ => status!("Skipping line {} of {}: no question and answer", line, path.display()),
+        }
+    }
+    Ok(pairs)
+}
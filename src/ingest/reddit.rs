@@ -0,0 +1,157 @@
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use url::Url;
+
+/// Reddit asks API clients to identify themselves; generic browser agents get throttled.
+const USER_AGENT: &str = "alya-chatbot/0.1 (character knowledge learner)";
+/// Replies this deep below a top-level comment are left out.
+const MAX_REPLY_DEPTH: u64 = 2;
+
+/// Which comments of a Reddit thread are learned from along with the post.
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct RedditSettings {
+    /// Comments scored lower than this are skipped.
+    #[serde(default = "default_min_score")]
+    pub min_score: i64,
+    /// Most comments learned from per thread, highest scored first, not counting the
+    /// comments they reply to.
+    #[serde(default = "default_max_comments")]
+    pub max_comments: usize,
+}
+
+fn default_min_score() -> i64 {
+    5
+}
+
+fn default_max_comments() -> usize {
+    30
+}
+
+impl Default for RedditSettings {
+    fn default() -> Self {
+        RedditSettings {
+            min_score: default_min_score(),
+            max_comments: default_max_comments(),
+        }
+    }
+}
+
+/// The ID of the thread at a Reddit comments URL or `redd.it` short link.
+pub fn reddit_thread_id(url: &str) -> Option<String> {
+    let url = Url::parse(url).ok()?;
+    let host = url.host_str()?.to_ascii_lowercase();
+    let segments: Vec<&str> = url.path_segments()?.filter(|segment| !segment.is_empty()).collect();
+    let id = match host.as_str() {
+        "redd.it" => segments.first()?,
+        "reddit.com" | "www.reddit.com" | "old.reddit.com" | "new.reddit.com" | "np.reddit.com" | "m.reddit.com" => {
+            let at = segments.iter().position(|segment| *segment == "comments")?;
+            segments.get(at + 1)?
+        }
+        _ => return None,
+    };
+    id.chars().all(|c| c.is_ascii_alphanumeric()).then(|| id.to_ascii_lowercase())
+}
+
+/// Downloads a thread through Reddit's public JSON endpoint and writes the post and its
+/// best comments as Markdown.
+pub async fn fetch_reddit_thread(
+    client: &reqwest::Client,
+    thread_id: &str,
+    settings: &RedditSettings,
+) -> Result<String, Box<dyn std::error::Error>> {
+    let url = format!("https://www.reddit.com/comments/{}.json?sort=top&raw_json=1&limit=500", thread_id);
+    let response = client.get(&url).header("User-Agent", USER_AGENT).send().await?;
+    if !response.status().is_success() {
+        return Err(format!("Reddit refused thread {} (Status: {})", thread_id, response.status()).into());
+    }
+    let listings: Value = response.json().await?;
+    let post = &listings[0]["data"]["children"][0]["data"];
+    let title = post["title"].as_str().ok_or_else(|| format!("Reddit thread {} has no post", thread_id))?;
+
+    let mut markdown = format!(
+        "# {}\n\nA discussion thread in r/{}, posted by u/{} (score {}).\n\n",
+        title,
+        post["subreddit"].as_str().unwrap_or("?"),
+        post["author"].as_str().unwrap_or("[deleted]"),
+        post["score"].as_i64().unwrap_or(0)
+    );
+    match post["selftext"].as_str().map(str::trim).filter(|text| !text.is_empty() && !is_removed(text)) {
+        Some(text) => markdown.push_str(&format!("{}\n\n", text)),
+        None => {
+            if let Some(link) = post["url"].as_str().filter(|link| !link.contains("reddit.com")) {
+                markdown.push_str(&format!("The post links to {}\n\n", link));
+            }
+        }
+    }
+
+    let mut comments = Vec::new();
+    collect_comments(&listings[1]["data"]["children"], None, settings, &mut comments);
+    // The best comments and those they reply to, in thread order
+    let mut best: Vec<usize> = (0..comments.len()).collect();
+    best.sort_by_key(|index| std::cmp::Reverse(comments[*index].score));
+    best.truncate(settings.max_comments);
+    for index in best.clone() {
+        let mut parent = comments[index].parent;
+        while let Some(index) = parent.filter(|index| !best.contains(index)) {
+            best.push(index);
+            parent = comments[index].parent;
+        }
+    }
+    best.sort();
+    if !best.is_empty() {
+        markdown.push_str("## Top comments\n\n");
+        for comment in best.iter().map(|index| &comments[*index]) {
+            markdown.push_str(&format!(
+                "{}- u/{} (score {}): {}\n",
+                "  ".repeat(comment.depth as usize),
+                comment.author,
+                comment.score,
+                comment.body
+            ));
+        }
+    }
+    Ok(markdown)
+}
+
+struct Comment {
+    author: String,
+    score: i64,
+    depth: u64,
+    /// Index of the comment this one replies to.
+    parent: Option<usize>,
+    /// On one line, so it stays one list item.
+    body: String,
+}
+
+/// The comments of a listing and their replies that score at least `min_score`, in
+/// thread order. Replies to skipped comments are skipped too.
+fn collect_comments(children: &Value, parent: Option<usize>, settings: &RedditSettings, comments: &mut Vec<Comment>) {
+    for child in children.as_array().into_iter().flatten() {
+        // "more" entries are comments that would need another request
+        if child["kind"] != "t1" {
+            continue;
+        }
+        let data = &child["data"];
+        let body = data["body"].as_str().unwrap_or_default().trim();
+        let author = data["author"].as_str().unwrap_or("[deleted]");
+        let score = data["score"].as_i64().unwrap_or(0);
+        let depth = data["depth"].as_u64().unwrap_or(0);
+        let moderator = author == "AutoModerator" || data["stickied"].as_bool().unwrap_or(false);
+        if body.is_empty() || is_removed(body) || moderator || score < settings.min_score || depth > MAX_REPLY_DEPTH {
+            continue;
+        }
+        comments.push(Comment {
+            author: author.to_string(),
+            score,
+            depth,
+            parent,
+            body: body.split_whitespace().collect::<Vec<_>>().join(" "),
+        });
+        let index = comments.len() - 1;
+        collect_comments(&data["replies"]["data"]["children"], Some(index), settings, comments);
+    }
+}
+
+fn is_removed(text: &str) -> bool {
+    matches!(text, "[deleted]" | "[removed]")
+}
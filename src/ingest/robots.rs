@@ -0,0 +1,191 @@
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+use url::Url;
+
+/// The name the chatbot looks for in `User-agent` lines.
+const AGENT: &str = "alya-chatbot";
+/// How long a fetched robots.txt is trusted before it is fetched again.
+const CACHE_TTL: Duration = Duration::from_secs(24 * 60 * 60);
+
+/// Whether a path pattern is allowed or disallowed.
+type Rule = (bool, String);
+
+/// The rules of one robots.txt that apply to this chatbot.
+#[derive(Debug, Default)]
+struct Rules {
+    rules: Vec<Rule>,
+}
+
+impl Rules {
+    fn allow_all() -> Self {
+        Rules::default()
+    }
+
+    fn disallow_all() -> Self {
+        Rules {
+            rules: vec![(false, "/".to_string())],
+        }
+    }
+
+    /// Picks the group for this chatbot's name, or else the `*` group, following RFC 9309.
+    fn parse(robots: &str) -> Self {
+        let mut groups: Vec<(Vec<String>, Vec<Rule>)> = Vec::new();
+        let mut in_agents = false;
+        for line in robots.lines() {
+            let line = line.split('#').next().unwrap_or("").trim();
+            let Some((field, value)) = line.split_once(':') else {
+                continue;
+            };
+            let value = value.trim();
+            match field.trim().to_ascii_lowercase().as_str() {
+                "user-agent" => {
+                    if !in_agents {
+                        groups.push((Vec::new(), Vec::new()));
+                    }
+                    in_agents = true;
+                    if let Some((agents, _)) = groups.last_mut() {
+                        agents.push(value.to_ascii_lowercase());
+                    }
+                }
+                field @ ("allow" | "disallow") => {
+                    in_agents = false;
+                    // An empty Disallow allows everything, which is the same as no rule.
+                    if let (Some((_, rules)), false) = (groups.last_mut(), value.is_empty()) {
+                        rules.push((field == "allow", value.to_string()));
+                    }
+                }
+                _ => in_agents = false,
+            }
+        }
+
+        let rules_for = |matches: &dyn Fn(&str) -> bool| {
+            let mut found = false;
+            let mut rules = Vec::new();
+            for (agents, group) in &groups {
+                if agents.iter().any(|agent| matches(agent)) {
+                    found = true;
+                    rules.extend(group.iter().cloned());
+                }
+            }
+            found.then_some(rules)
+        };
+        let rules = rules_for(&|agent| agent != "*" && AGENT.contains(agent))
+            .or_else(|| rules_for(&|agent| agent == "*"))
+            .unwrap_or_default();
+        Rules { rules }
+    }
+
+    /// The longest matching rule decides; on a tie Allow wins.
+    fn allows(&self, path: &str) -> bool {
+        self.rules
+            .iter()
+            .filter(|(_, pattern)| matches(pattern, path))
+            .max_by_key(|(allow, pattern)| (pattern.len(), *allow))
+            .is_none_or(|(allow, _)| *allow)
+    }
+}
+
+/// Whether `path` matches a robots.txt path pattern, where `*` matches any characters and a
+/// trailing `$` anchors the pattern to the end of the path.
+fn matches(pattern: &str, path: &str) -> bool {
+    let (pattern, anchored) = match pattern.strip_suffix('$') {
+        Some(pattern) => (pattern, true),
+        None => (pattern, false),
+    };
+    let mut pieces = pattern.split('*');
+    let Some(first) = pieces.next() else {
+        return true;
+    };
+    let Some(mut rest) = path.strip_prefix(first) else {
+        return false;
+    };
+    let pieces: Vec<&str> = pieces.collect();
+    for (index, piece) in pieces.iter().enumerate() {
+        let last = index == pieces.len() - 1;
+        if last && anchored {
+            return rest.ends_with(piece);
+        }
+        match rest.find(piece) {
+            Some(at) => rest = &rest[at + piece.len()..],
+            None => return false,
+        }
+    }
+    !anchored || rest.is_empty()
+}
+
+/// Fetched robots.txt rules per site, so each site's robots.txt is only requested once a
+/// day.
+pub struct RobotsCache {
+    sites: Mutex<HashMap<String, (Instant, Arc<Rules>)>>,
+    /// Hosts whose robots.txt is not checked.
+    exempt: Vec<String>,
+    client: reqwest::Client,
+}
+
+impl RobotsCache {
+    pub fn new(exempt: &[String]) -> Self {
+        RobotsCache {
+            sites: Mutex::new(HashMap::new()),
+            exempt: exempt.iter().map(|host| host.to_ascii_lowercase()).collect(),
+            client: reqwest::Client::builder()
+                .timeout(Duration::from_secs(10))
+                .build()
+                .unwrap_or_default(),
+        }
+    }
+
+    /// Whether the site's robots.txt lets the chatbot fetch `url`. A missing robots.txt
+    /// allows everything; one that cannot be fetched because of a server or network error
+    /// allows nothing until it can.
+    pub async fn allows(&self, url: &str) -> bool {
+        let Ok(url) = Url::parse(url) else {
+            return false;
+        };
+        let Some(host) = url.host_str().map(str::to_ascii_lowercase) else {
+            return false;
+        };
+        if self.exempt.iter().any(|exempt| host == *exempt || host.ends_with(&format!(".{}", exempt))) {
+            return true;
+        }
+        let mut path = url.path().to_string();
+        if let Some(query) = url.query() {
+            path = format!("{}?{}", path, query);
+        }
+
+        let origin = url.origin().ascii_serialization();
+        let cached = self
+            .sites
+            .lock()
+            .unwrap()
+            .get(&origin)
+            .filter(|(fetched, _)| fetched.elapsed() < CACHE_TTL)
+            .map(|(_, rules)| Arc::clone(rules));
+        let rules = match cached {
+            Some(rules) => rules,
+            // Failures are not cached, so the next URL of the site tries again
+            None => match self.fetch(&origin).await {
+                Some(rules) => {
+                    let rules = Arc::new(rules);
+                    self.sites.lock().unwrap().insert(origin, (Instant::now(), Arc::clone(&rules)));
+                    rules
+                }
+                None => Arc::new(Rules::disallow_all()),
+            },
+        };
+        rules.allows(&path)
+    }
+
+    /// The site's rules, or None if its robots.txt could not be fetched.
+    async fn fetch(&self, origin: &str) -> Option<Rules> {
+        let response = self.client.get(format!("{}/robots.txt", origin)).send().await.ok()?;
+        let status = response.status();
+        if status.is_client_error() {
+            return Some(Rules::allow_all());
+        }
+        if !status.is_success() {
+            return None;
+        }
+        response.text().await.ok().map(|robots| Rules::parse(&robots))
+    }
+}
@@ -0,0 +1,91 @@
+use flate2::read::GzDecoder;
+use std::collections::HashSet;
+use std::io::Read;
+
+/// Sitemap indexes can nest; stop after reading this many sitemaps.
+const MAX_SITEMAPS: usize = 50;
+
+/// The page URLs listed in the sitemap at `url`, following sitemap indexes and reading
+/// gzipped sitemaps. With a `pattern`, only URLs containing it are returned; `*` in the
+/// pattern matches any characters.
+pub async fn sitemap_urls(
+    client: &reqwest::Client,
+    url: &str,
+    pattern: Option<&str>,
+) -> Result<Vec<String>, Box<dyn std::error::Error>> {
+    let mut pending = vec![url.to_string()];
+    let mut read = 0;
+    let mut urls = Vec::new();
+    while let Some(sitemap) = pending.pop() {
+        if read == MAX_SITEMAPS {
+            status!("Stopped after reading {} sitemaps", MAX_SITEMAPS);
+            break;
+        }
+        read += 1;
+
+        let response = client.get(&sitemap).send().await?;
+        if !response.status().is_success() {
+            return Err(format!("Failed to fetch sitemap {} (Status: {})", sitemap, response.status()).into());
+        }
+        let bytes = response.bytes().await?;
+        let xml = match bytes.starts_with(&[0x1f, 0x8b]) {
+            true => {
+                let mut xml = String::new();
+                GzDecoder::new(bytes.as_ref()).read_to_string(&mut xml)?;
+                xml
+            }
+            false => String::from_utf8_lossy(&bytes).into_owned(),
+        };
+
+        let locations = locations(&xml);
+        if xml.contains("<sitemapindex") {
+            // Read the nested sitemaps in the order they are listed
+            pending.extend(locations.into_iter().rev());
+        } else {
+            urls.extend(locations.into_iter().filter(|url| pattern.is_none_or(|pattern| matches(pattern, url))));
+        }
+    }
+    let mut seen = HashSet::new();
+    urls.retain(|url| seen.insert(url.clone()));
+    Ok(urls)
+}
+
+/// The contents of every `<loc>` element.
+fn locations(xml: &str) -> Vec<String> {
+    let mut locations = Vec::new();
+    let mut rest = xml;
+    while let Some(start) = rest.find("<loc>") {
+        rest = &rest[start + "<loc>".len()..];
+        let Some(end) = rest.find("</loc>") else {
+            break;
+        };
+        let location = rest[..end].trim();
+        let location = location
+            .strip_prefix("<![CDATA[")
+            .and_then(|location| location.strip_suffix("]]>"))
+            .unwrap_or(location);
+        locations.push(unescape(location.trim()));
+        rest = &rest[end..];
+    }
+    locations
+}
+
+fn unescape(text: &str) -> String {
+    text.replace("&lt;", "<")
+        .replace("&gt;", ">")
+        .replace("&quot;", "\"")
+        .replace("&apos;", "'")
+        .replace("&amp;", "&")
+}
+
+/// Whether the pieces of `pattern` between its `*`s occur in `url` in order.
+fn matches(pattern: &str, url: &str) -> bool {
+    let mut rest = url;
+    for piece in pattern.split('*') {
+        match rest.find(piece) {
+            Some(at) => rest = &rest[at + piece.len()..],
+            None => return false,
+        }
+    }
+    true
+}
@@ -0,0 +1,294 @@
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+use url::Url;
+
+const ENDPOINT: &str = "https://api.vndb.org/kana/character";
+/// Visual novel descriptions are cut off after this many characters, so a long synopsis
+/// does not crowd out everything else in the prompt.
+const MAX_VN_TEXT: usize = 3_000;
+/// BBCode tags VNDB uses in descriptions, removed with their brackets.
+const FORMATTING_TAGS: [&str; 7] = ["url", "b", "i", "u", "s", "raw", "quote"];
+
+const FIELDS: &str = "id, name, original, aliases, description, blood_type, height, weight, bust, waist, hips, cup, age, \
+    birthday, sex, traits.name, traits.group_name, traits.spoiler, vns.id, vns.title, vns.role, vns.spoiler, vns.description";
+
+/// How much of the story a VNDB lookup may reveal, following VNDB's own spoiler levels.
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Default)]
+#[serde(rename_all = "lowercase")]
+pub enum SpoilerLevel {
+    /// Nothing marked as a spoiler is stored.
+    #[default]
+    None,
+    /// Minor spoilers, such as traits revealed early in a route.
+    Minor,
+    /// Everything, endings and twists included.
+    Major,
+}
+
+impl SpoilerLevel {
+    fn allows(&self, spoiler: u64) -> bool {
+        spoiler <= *self as u64
+    }
+}
+
+/// What VNDB lookups store.
+#[derive(Debug, Serialize, Deserialize, Clone, Default)]
+pub struct VndbSettings {
+    #[serde(default)]
+    pub spoiler_level: SpoilerLevel,
+}
+
+/// A character's entry on VNDB, with what the spoiler level leaves out already removed.
+#[derive(Debug)]
+pub struct VndbCharacter {
+    pub name: String,
+    pub original_name: Option<String>,
+    pub aliases: Vec<String>,
+    /// The description without its spoilers.
+    pub description: String,
+    /// The parts of the description VNDB marks as spoilers, when the level allows them.
+    pub spoilers: Vec<String>,
+    /// Profile fields such as age, height and measurements, labelled.
+    pub fields: Vec<(String, String)>,
+    /// Trait names by group, such as `Personality` or `Hair`, in VNDB's order.
+    pub traits: Vec<(String, Vec<String>)>,
+    pub routes: Vec<Route>,
+    pub url: String,
+}
+
+/// A visual novel the character is in.
+#[derive(Debug)]
+pub struct Route {
+    pub title: String,
+    /// `main`, `primary`, `side` or `appears`.
+    pub role: String,
+    /// The visual novel's description, spoilers removed as the level asks.
+    pub summary: String,
+}
+
+impl VndbCharacter {
+    /// Names, measurements and the like, exactly as VNDB gives them.
+    pub fn profile(&self) -> String {
+        let mut fields = Vec::new();
+        if let Some(original) = &self.original_name {
+            fields.push(format!("Original name: {}", original));
+        }
+        if !self.aliases.is_empty() {
+            fields.push(format!("Also known as: {}", self.aliases.join(", ")));
+        }
+        fields.extend(self.fields.iter().map(|(label, value)| format!("{}: {}", label, value)));
+        let fields: Vec<String> = fields.into_iter().map(|field| format!("- {}", field)).collect();
+        format!("Profile of {}, from VNDB:\n{}", self.name, fields.join("\n"))
+    }
+
+    /// The character's traits by group.
+    pub fn traits_text(&self) -> Option<String> {
+        if self.traits.is_empty() {
+            return None;
+        }
+        let lines: Vec<String> = self.traits.iter().map(|(group, names)| format!("- {}: {}", group, names.join(", "))).collect();
+        Some(format!("Traits of {}, from VNDB:\n{}", self.name, lines.join("\n")))
+    }
+
+    /// The visual novels the character is in, with their summaries.
+    pub fn routes_text(&self) -> Option<String> {
+        if self.routes.is_empty() {
+            return None;
+        }
+        let mut text = format!("Visual novels {} appears in, from VNDB:\n", self.name);
+        for route in &self.routes {
+            text.push_str(&format!("\n## {} ({} character)\n", route.title, route.role));
+            if !route.summary.is_empty() {
+                text.push_str(&format!("\n{}\n", route.summary));
+            }
+        }
+        Some(text)
+    }
+}
+
+/// The VNDB character search for a name, used to check the domain filter before a lookup.
+pub fn vndb_search_url(name: &str) -> Result<String, url::ParseError> {
+    Ok(Url::parse_with_params("https://vndb.org/c", &[("q", name.trim())])?.to_string())
+}
+
+/// Looks up the character best matching `name` through the VNDB API, keeping only the
+/// traits, visual novels and description parts `spoiler_level` allows.
+pub async fn fetch_vndb_character(
+    client: &reqwest::Client,
+    name: &str,
+    spoiler_level: SpoilerLevel,
+) -> Result<VndbCharacter, Box<dyn std::error::Error>> {
+    let response = client
+        .post(ENDPOINT)
+        .json(&json!({
+            "filters": ["search", "=", name.trim()],
+            "fields": FIELDS,
+            "sort": "searchrank",
+            "results": 1,
+        }))
+        .send()
+        .await?;
+    if !response.status().is_success() {
+        let status = response.status();
+        let message = response.text().await.unwrap_or_default();
+        return Err(format!("VNDB refused the lookup of {} (Status: {}): {}", name, status, message.trim()).into());
+    }
+    let body: Value = response.json().await?;
+    match body["results"].as_array().and_then(|results| results.first()) {
+        Some(character) => Ok(parse_character(character, spoiler_level)),
+        None => Err(format!("VNDB has no character named {}", name).into()),
+    }
+}
+
+fn parse_character(character: &Value, spoiler_level: SpoilerLevel) -> VndbCharacter {
+    let id = string(&character["id"]).unwrap_or_default();
+    let (description, mut spoilers) = split_spoilers(character["description"].as_str().unwrap_or_default());
+    if spoiler_level == SpoilerLevel::None {
+        spoilers.clear();
+    }
+
+    let mut fields = Vec::new();
+    let sex = |index: usize| match character["sex"][index].as_str() {
+        Some("m") => Some("male"),
+        Some("f") => Some("female"),
+        Some("b") => Some("both"),
+        Some("n") => Some("sexless"),
+        _ => None,
+    };
+    // The real sex is a spoiler whenever it differs from the apparent one
+    match (sex(0), sex(1)) {
+        (Some(apparent), Some(real)) if apparent != real && spoiler_level == SpoilerLevel::Major => {
+            fields.push(("Sex".to_string(), format!("{} (actually {})", apparent, real)));
+        }
+        (Some(apparent), _) => fields.push(("Sex".to_string(), apparent.to_string())),
+        _ => {}
+    }
+    if let Some(age) = character["age"].as_u64() {
+        fields.push(("Age".to_string(), age.to_string()));
+    }
+    if let (Some(month), Some(day)) = (character["birthday"][0].as_u64(), character["birthday"][1].as_u64()) {
+        if let Some(date) = chrono::NaiveDate::from_ymd_opt(2000, month as u32, day as u32) {
+            fields.push(("Birthday".to_string(), date.format("%B %-d").to_string()));
+        }
+    }
+    if let Some(blood_type) = string(&character["blood_type"]) {
+        fields.push(("Blood type".to_string(), blood_type.to_uppercase()));
+    }
+    for (label, field, unit) in [("Height", "height", "cm"), ("Weight", "weight", "kg")] {
+        if let Some(value) = character[field].as_u64().filter(|value| *value > 0) {
+            fields.push((label.to_string(), format!("{} {}", value, unit)));
+        }
+    }
+    let measurements: Vec<String> = ["bust", "waist", "hips"]
+        .iter()
+        .filter_map(|field| character[*field].as_u64().filter(|value| *value > 0))
+        .map(|value| value.to_string())
+        .collect();
+    if measurements.len() == 3 {
+        fields.push(("Measurements".to_string(), format!("{} cm (bust-waist-hips)", measurements.join("-"))));
+    }
+    if let Some(cup) = string(&character["cup"]) {
+        fields.push(("Cup size".to_string(), cup));
+    }
+
+    let mut traits: Vec<(String, Vec<String>)> = Vec::new();
+    for item in character["traits"].as_array().into_iter().flatten() {
+        if !spoiler_level.allows(item["spoiler"].as_u64().unwrap_or_default()) {
+            continue;
+        }
+        let (Some(group), Some(name)) = (string(&item["group_name"]), string(&item["name"])) else {
+            continue;
+        };
+        match traits.iter_mut().find(|(existing, _)| *existing == group) {
+            Some((_, names)) => names.push(name),
+            None => traits.push((group, vec![name])),
+        }
+    }
+
+    let mut routes = Vec::new();
+    for vn in character["vns"].as_array().into_iter().flatten() {
+        if !spoiler_level.allows(vn["spoiler"].as_u64().unwrap_or_default()) {
+            continue;
+        }
+        let Some(title) = string(&vn["title"]) else {
+            continue;
+        };
+        // A character can be listed once per release of the same visual novel
+        if routes.iter().any(|route: &Route| route.title == title) {
+            continue;
+        }
+        let (mut summary, vn_spoilers) = split_spoilers(vn["description"].as_str().unwrap_or_default());
+        if spoiler_level == SpoilerLevel::Major {
+            for spoiler in vn_spoilers {
+                summary.push_str("\n\n");
+                summary.push_str(&spoiler);
+            }
+        }
+        if summary.chars().count() > MAX_VN_TEXT {
+            summary = summary.chars().take(MAX_VN_TEXT).collect::<String>() + "...";
+        }
+        routes.push(Route {
+            title,
+            role: string(&vn["role"]).unwrap_or_else(|| "appears".to_string()),
+            summary: summary.trim().to_string(),
+        });
+    }
+
+    VndbCharacter {
+        original_name: string(&character["original"]),
+        aliases: character["aliases"].as_array().into_iter().flatten().filter_map(string).collect(),
+        description,
+        spoilers,
+        fields,
+        traits,
+        routes,
+        url: format!("https://vndb.org/{}", id),
+        name: string(&character["name"]).unwrap_or_default(),
+    }
+}
+
+/// A non-empty string field.
+fn string(value: &Value) -> Option<String> {
+    value.as_str().map(str::trim).filter(|value| !value.is_empty()).map(str::to_string)
+}
+
+/// Splits the `[spoiler]` blocks out of a VNDB description and removes its other BBCode.
+fn split_spoilers(description: &str) -> (String, Vec<String>) {
+    let mut visible = String::new();
+    let mut spoilers = Vec::new();
+    let mut rest = description;
+    while let Some(start) = rest.find("[spoiler]") {
+        visible.push_str(&rest[..start]);
+        let after = &rest[start + "[spoiler]".len()..];
+        let end = after.find("[/spoiler]").unwrap_or(after.len());
+        let spoiler = strip_formatting(&after[..end]);
+        if !spoiler.is_empty() {
+            spoilers.push(spoiler);
+        }
+        rest = after.get(end + "[/spoiler]".len()..).unwrap_or_default();
+    }
+    visible.push_str(rest);
+    (strip_formatting(&visible), spoilers)
+}
+
+/// Removes `[url=...]`, `[b]` and the like, keeping the text between them.
+fn strip_formatting(text: &str) -> String {
+    let mut stripped = String::new();
+    let mut rest = text;
+    while let Some(start) = rest.find('[') {
+        stripped.push_str(&rest[..start]);
+        let after = &rest[start..];
+        let tag = after.find(']').map(|end| &after[1..end]);
+        let name = tag.map(|tag| tag.trim_start_matches('/').split('=').next().unwrap_or_default().to_ascii_lowercase());
+        match (tag, name) {
+            (Some(tag), Some(name)) if FORMATTING_TAGS.contains(&name.as_str()) => rest = &after[tag.len() + 2..],
+            _ => {
+                stripped.push('[');
+                rest = &after[1..];
+            }
+        }
+    }
+    stripped.push_str(rest);
+    let lines: Vec<&str> = stripped.lines().map(str::trim_end).collect();
+    lines.join("\n").trim().to_string()
+}
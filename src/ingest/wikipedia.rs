@@ -0,0 +1,133 @@
+use super::html_to_markdown;
+use scraper::{ElementRef, Html, Selector};
+use serde_json::Value;
+use url::Url;
+
+/// Wikimedia asks API clients to say who they are.
+const USER_AGENT: &str = "alya-chatbot/0.1 (https://github.com/idMJA/alya-chatbot)";
+/// Sections are cut off after this many characters, so one article does not crowd out
+/// everything else in the prompt.
+const MAX_SECTION_TEXT: usize = 4_000;
+
+/// The lead summary of a Wikipedia article and the sections asked for.
+#[derive(Debug)]
+pub struct WikipediaArticle {
+    pub title: String,
+    /// The short description under the title, such as "Private school in Tokyo, Japan".
+    pub description: Option<String>,
+    pub summary: String,
+    /// Sections by heading, as Markdown.
+    pub sections: Vec<(String, String)>,
+    pub url: String,
+}
+
+impl WikipediaArticle {
+    /// The article as it is learned.
+    pub fn text(&self) -> String {
+        let mut text = match &self.description {
+            Some(description) => format!("{} ({}), from Wikipedia:\n\n{}\n", self.title, description, self.summary),
+            None => format!("{}, from Wikipedia:\n\n{}\n", self.title, self.summary),
+        };
+        for (heading, section) in &self.sections {
+            text.push_str(&format!("\n## {}\n\n{}\n", heading, section));
+        }
+        text
+    }
+}
+
+/// The address of an article on the Wikipedia in `language`.
+pub fn wikipedia_url(language: &str, title: &str) -> Result<String, url::ParseError> {
+    let base = Url::parse(&format!("https://{}.wikipedia.org/wiki/", language.trim().to_ascii_lowercase()))?;
+    Ok(base.join(&title.trim().replace(' ', "_"))?.to_string())
+}
+
+/// Looks up `topic` on the Wikipedia in `language` through its REST API, searching for the
+/// closest title when there is no article of that exact name. `sections` are the headings
+/// of sections to read besides the summary, matched case-insensitively.
+pub async fn fetch_wikipedia(
+    client: &reqwest::Client,
+    language: &str,
+    topic: &str,
+    sections: &[String],
+) -> Result<WikipediaArticle, Box<dyn std::error::Error>> {
+    let host = format!("https://{}.wikipedia.org", language.trim().to_ascii_lowercase());
+    let mut summary = get(client, &format!("{}/api/rest_v1/page/summary/{}", host, path_title(topic))).await?;
+    if summary.is_none() {
+        let search = get(client, &format!("{}/w/rest.php/v1/search/title?limit=1&q={}", host, path_title(topic))).await?;
+        let key = search.as_ref().and_then(|search| search["pages"][0]["key"].as_str());
+        let Some(key) = key else {
+            return Err(format!("Wikipedia ({}) has no article about {}", language, topic).into());
+        };
+        summary = get(client, &format!("{}/api/rest_v1/page/summary/{}", host, path_title(key))).await?;
+    }
+    let summary = summary.ok_or_else(|| format!("Wikipedia ({}) has no article about {}", language, topic))?;
+    let title = summary["title"].as_str().unwrap_or(topic).to_string();
+    if summary["type"] == "disambiguation" {
+        return Err(format!("\"{}\" has several meanings on Wikipedia; be more specific", title).into());
+    }
+
+    let mut article = WikipediaArticle {
+        description: summary["description"].as_str().map(str::to_string),
+        summary: summary["extract"].as_str().unwrap_or_default().trim().to_string(),
+        sections: Vec::new(),
+        url: summary["content_urls"]["desktop"]["page"]
+            .as_str()
+            .map(str::to_string)
+            .unwrap_or_else(|| format!("{}/wiki/{}", host, path_title(&title))),
+        title,
+    };
+    if !sections.is_empty() {
+        let url = format!("{}/api/rest_v1/page/html/{}", host, path_title(&article.title));
+        let response = client.get(&url).header("User-Agent", USER_AGENT).send().await?.error_for_status()?;
+        article.sections = find_sections(&response.text().await?, sections);
+        for wanted in sections {
+            if !article.sections.iter().any(|(heading, _)| heading.eq_ignore_ascii_case(wanted)) {
+                status!("\"{}\" has no section \"{}\"", article.title, wanted);
+            }
+        }
+    }
+    Ok(article)
+}
+
+/// GETs a JSON document; a 404 is None.
+async fn get(client: &reqwest::Client, url: &str) -> Result<Option<Value>, Box<dyn std::error::Error>> {
+    let response = client.get(url).header("User-Agent", USER_AGENT).send().await?;
+    if response.status() == reqwest::StatusCode::NOT_FOUND {
+        return Ok(None);
+    }
+    Ok(Some(response.error_for_status()?.json().await?))
+}
+
+fn path_title(title: &str) -> String {
+    url::form_urlencoded::byte_serialize(title.trim().replace(' ', "_").as_bytes()).collect()
+}
+
+/// The sections of an article's HTML whose headings are among `wanted`, in article order.
+fn find_sections(html: &str, wanted: &[String]) -> Vec<(String, String)> {
+    let document = Html::parse_document(html);
+    let Ok(section) = Selector::parse("section") else {
+        return Vec::new();
+    };
+    let mut found = Vec::new();
+    for element in document.select(&section) {
+        let Some(heading) = element
+            .children()
+            .filter_map(ElementRef::wrap)
+            .find(|child| matches!(child.value().name(), "h2" | "h3" | "h4")) else {
+            continue;
+        };
+        let heading = heading.text().collect::<String>().trim().to_string();
+        if !wanted.iter().any(|wanted| wanted.trim().eq_ignore_ascii_case(&heading)) {
+            continue;
+        }
+        let text = html_to_markdown(&element.html());
+        // The heading itself is written by `WikipediaArticle::text`
+        let text = text.trim().trim_start_matches('#').trim_start().strip_prefix(heading.as_str()).unwrap_or(&text).trim();
+        let text = match text.char_indices().nth(MAX_SECTION_TEXT) {
+            Some((end, _)) => format!("{}…", &text[..end]),
+            None => text.to_string(),
+        };
+        found.push((heading, text));
+    }
+    found
+}
@@ -0,0 +1,177 @@
+use roxmltree::Document as Xml;
+use scraper::Html;
+use serde_json::{json, Value};
+use url::Url;
+
+/// Client the captions are requested as; the web client's caption URLs need tokens that
+/// only a browser can produce.
+const PLAYER_CLIENT: (&str, &str) = ("ANDROID", "20.10.38");
+/// A pause between captions this long, in seconds, starts a new paragraph.
+const PARAGRAPH_PAUSE: f64 = 2.0;
+/// Paragraphs are also broken after this many characters, so long talks chunk cleanly.
+const MAX_PARAGRAPH: usize = 1_500;
+
+/// The captions of a YouTube video as one text.
+#[derive(Debug)]
+pub struct Transcript {
+    pub title: String,
+    pub author: String,
+    pub language: String,
+    /// Whether the captions were generated by speech recognition rather than written.
+    pub generated: bool,
+    pub text: String,
+}
+
+impl Transcript {
+    /// The transcript as it is learned from, headed by the video's title.
+    pub fn markdown(&self) -> String {
+        format!("# {}\n\nTranscript of a video by {}.\n\n{}", self.title, self.author, self.text)
+    }
+}
+
+/// The ID of the video at a YouTube watch, short, embed or `youtu.be` URL.
+pub fn youtube_video_id(url: &str) -> Option<String> {
+    let url = Url::parse(url).ok()?;
+    let host = url.host_str()?.to_ascii_lowercase();
+    let host = host.strip_prefix("www.").or_else(|| host.strip_prefix("m.")).unwrap_or(&host);
+    let mut segments = url.path_segments()?.filter(|segment| !segment.is_empty());
+    let id = match host {
+        "youtu.be" => segments.next()?.to_string(),
+        "youtube.com" | "music.youtube.com" | "youtube-nocookie.com" => match segments.next()? {
+            "watch" => url.query_pairs().find(|(key, _)| key == "v")?.1.into_owned(),
+            "shorts" | "embed" | "live" | "v" => segments.next()?.to_string(),
+            _ => return None,
+        },
+        _ => return None,
+    };
+    let valid = id.len() == 11 && id.chars().all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_');
+    valid.then_some(id)
+}
+
+/// Downloads the captions of a video, preferring written captions in the first of
+/// `languages` that has any, then generated ones, then any language at all.
+pub async fn fetch_transcript(
+    client: &reqwest::Client,
+    video_id: &str,
+    languages: &[String],
+) -> Result<Transcript, Box<dyn std::error::Error>> {
+    let watch_url = format!("https://www.youtube.com/watch?v={}", video_id);
+    let page = client
+        .get(&watch_url)
+        .header("Accept-Language", "en-US,en;q=0.5")
+        // Skips the cookie consent page shown in the EU
+        .header("Cookie", "CONSENT=YES+cb")
+        .send()
+        .await?
+        .text()
+        .await?;
+    let api_key = between(&page, "\"INNERTUBE_API_KEY\":\"", "\"").ok_or("YouTube did not return the video page")?;
+
+    let (client_name, client_version) = PLAYER_CLIENT;
+    let player: Value = client
+        .post(format!("https://www.youtube.com/youtubei/v1/player?key={}", api_key))
+        .json(&json!({
+            "context": { "client": { "clientName": client_name, "clientVersion": client_version } },
+            "videoId": video_id,
+        }))
+        .send()
+        .await?
+        .json()
+        .await?;
+    let status = player["playabilityStatus"]["status"].as_str().unwrap_or("UNKNOWN");
+    if status != "OK" {
+        let reason = player["playabilityStatus"]["reason"].as_str().unwrap_or(status);
+        return Err(format!("YouTube video {} is unavailable: {}", video_id, reason).into());
+    }
+
+    let tracks = player["captions"]["playerCaptionsTracklistRenderer"]["captionTracks"]
+        .as_array()
+        .filter(|tracks| !tracks.is_empty())
+        .ok_or_else(|| format!("YouTube video {} has no captions", video_id))?;
+    let track = pick_track(tracks, languages).ok_or_else(|| format!("YouTube video {} has no usable captions", video_id))?;
+    let caption_url = track["baseUrl"].as_str().ok_or("The caption track has no URL")?;
+    // The plain timed-text format is the same for every client
+    let mut caption_url = Url::parse(caption_url)?;
+    let query: Vec<(String, String)> = caption_url
+        .query_pairs()
+        .filter(|(key, _)| key != "fmt")
+        .map(|(key, value)| (key.into_owned(), value.into_owned()))
+        .collect();
+    caption_url.query_pairs_mut().clear().extend_pairs(query);
+
+    let xml = client.get(caption_url).send().await?.error_for_status()?.text().await?;
+    let text = caption_text(&xml)?;
+    if text.trim().is_empty() {
+        return Err(format!("The captions of YouTube video {} are empty", video_id).into());
+    }
+    Ok(Transcript {
+        title: player["videoDetails"]["title"].as_str().unwrap_or(video_id).to_string(),
+        author: player["videoDetails"]["author"].as_str().unwrap_or("an unknown channel").to_string(),
+        language: track["languageCode"].as_str().unwrap_or_default().to_string(),
+        generated: track["kind"].as_str() == Some("asr"),
+        text,
+    })
+}
+
+fn pick_track<'a>(tracks: &'a [Value], languages: &[String]) -> Option<&'a Value> {
+    let language = |track: &Value| track["languageCode"].as_str().unwrap_or_default().to_ascii_lowercase();
+    let generated = |track: &Value| track["kind"].as_str() == Some("asr");
+    for wanted in languages.iter().map(|language| language.to_ascii_lowercase()) {
+        let matches = |track: &&Value| {
+            let language = language(track);
+            language == wanted || language.starts_with(&format!("{}-", wanted))
+        };
+        let found = tracks.iter().filter(matches).min_by_key(|track| generated(track));
+        if found.is_some() {
+            return found;
+        }
+    }
+    tracks.iter().min_by_key(|track| generated(track))
+}
+
+/// The text of a timed-text caption file, in paragraphs split at pauses. Handles both
+/// the `<text start dur>` format and the `<p t d>` one with times in milliseconds.
+fn caption_text(xml: &str) -> Result<String, roxmltree::Error> {
+    let xml = Xml::parse(xml)?;
+    let mut paragraphs: Vec<String> = Vec::new();
+    let mut current = String::new();
+    let mut last_end: Option<f64> = None;
+    for node in xml.descendants().filter(|node| node.has_tag_name("text") || node.has_tag_name("p")) {
+        let seconds = |attribute: &str, scale: f64| {
+            let value = node.attribute(attribute)?.parse::<f64>().ok()?;
+            Some(value / scale)
+        };
+        let (start, duration) = match node.tag_name().name() {
+            "text" => (seconds("start", 1.0), seconds("dur", 1.0)),
+            _ => (seconds("t", 1000.0), seconds("d", 1000.0)),
+        };
+        let raw: String = node.descendants().filter(|child| child.is_text()).filter_map(|child| child.text()).collect();
+        // Caption text is HTML-escaped a second time inside the XML
+        let text: String = Html::parse_fragment(&raw).root_element().text().collect();
+        let text = text.split_whitespace().collect::<Vec<_>>().join(" ");
+        if text.is_empty() {
+            continue;
+        }
+
+        let pause = matches!((start, last_end), (Some(start), Some(end)) if start - end >= PARAGRAPH_PAUSE);
+        if !current.is_empty() && (pause || current.len() >= MAX_PARAGRAPH) {
+            paragraphs.push(std::mem::take(&mut current));
+        }
+        if !current.is_empty() {
+            current.push(' ');
+        }
+        current.push_str(&text);
+        if let Some(start) = start {
+            last_end = Some(start + duration.unwrap_or(0.0));
+        }
+    }
+    if !current.is_empty() {
+        paragraphs.push(current);
+    }
+    Ok(paragraphs.join("\n\n"))
+}
+
+fn between<'a>(text: &'a str, start: &str, end: &str) -> Option<&'a str> {
+    let rest = &text[text.find(start)? + start.len()..];
+    Some(&rest[..rest.find(end)?])
+}
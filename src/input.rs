@@ -0,0 +1,177 @@
+use crate::paths;
+use rustyline::completion::Completer;
+use rustyline::error::ReadlineError;
+use rustyline::highlight::Highlighter;
+use rustyline::hint::Hinter;
+use rustyline::history::FileHistory;
+use rustyline::validate::{ValidationContext, ValidationResult, Validator};
+use rustyline::{Cmd, Config, Editor, ExternalPrinter, Helper, KeyCode, KeyEvent, Modifiers};
+use std::io::{self, BufRead, IsTerminal};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{mpsc as std_mpsc, OnceLock};
+use tokio::sync::{mpsc, Mutex};
+
+/// The file in the data directory the chat messages typed are remembered in, for the
+/// arrow keys and Ctrl+R.
+const HISTORY_FILE: &str = "prompt_history.txt";
+/// How many typed messages are remembered.
+const HISTORY_SIZE: usize = 1000;
+
+/// The thread that reads stdin, and what it was asked to read.
+struct Lines {
+    /// Asks for a line; true for a chat message, which is remembered in the history.
+    requests: std_mpsc::Sender<bool>,
+    lines: mpsc::UnboundedReceiver<io::Result<Option<String>>>,
+    /// A line was asked for that has not been taken yet, as when waiting for it was
+    /// given up.
+    pending: bool,
+}
+
+static LINES: OnceLock<Mutex<Lines>> = OnceLock::new();
+
+/// Prints above the line being edited, which the editor then draws again below.
+static PRINTER: std::sync::Mutex<Option<Box<dyn ExternalPrinter + Send>>> = std::sync::Mutex::new(None);
+/// Whether the editor has the terminal, waiting for a line.
+static EDITING: AtomicBool = AtomicBool::new(false);
+
+/// Whether a line is being edited, so what is printed goes through `println`, and prompts
+/// need not be printed again: the editor keeps the line on screen below the output.
+pub fn is_editing() -> bool {
+    EDITING.load(Ordering::Relaxed)
+}
+
+/// Prints `text` and a line ending to stdout. While a line is being edited, the text goes
+/// above it rather than into it.
+pub fn println(text: String) {
+    if is_editing() {
+        if let Some(printer) = PRINTER.lock().unwrap().as_mut() {
+            if printer.print(text.clone()).is_ok() {
+                return;
+            }
+        }
+    }
+    println!("{}", text);
+}
+
+/// Reads the next line typed, with its line ending, or None at the end of input. Stdin is
+/// read on a thread of its own, started by the first call, so the runtime goes on with
+/// scheduled jobs and other work while waiting. Waiting can be given up, as in a
+/// `select!`, without losing the line: the next call gets it.
+///
+/// In a terminal the line can be edited, and Alt+Enter or Shift+Enter, where the terminal
+/// tells it apart, or a `\` at the end of a line starts a new line in it; a line made of
+/// several ends with a line ending like any other. Everything the user types should be
+/// read with this once it has been called.
+pub async fn read_line() -> io::Result<Option<String>> {
+    read(false).await
+}
+
+/// Reads a chat message like `read_line`; in a terminal it is remembered, so the arrow
+/// keys and Ctrl+R find it again, in this run and the next ones.
+pub async fn read_message() -> io::Result<Option<String>> {
+    read(true).await
+}
+
+async fn read(remember: bool) -> io::Result<Option<String>> {
+    let mut lines = LINES.get_or_init(start).lock().await;
+    if !lines.pending {
+        if lines.requests.send(remember).is_err() {
+            // The thread ended with the input
+            return Ok(None);
+        }
+        lines.pending = true;
+    }
+    let line = lines.lines.recv().await;
+    lines.pending = false;
+    line.unwrap_or(Ok(None))
+}
+
+/// Starts the thread, which reads a line each time it is asked to, rather than ahead, so
+/// the editor only shows up when something is read.
+fn start() -> Mutex<Lines> {
+    let (requests, asked) = std_mpsc::channel();
+    let (sender, lines) = mpsc::unbounded_channel();
+    std::thread::spawn(move || {
+        let mut editor = if io::stdin().is_terminal() { editor() } else { None };
+        for remember in asked {
+            let line = match editor.as_mut() {
+                Some(editor) => read_edited(editor, remember),
+                None => read_plain(),
+            };
+            let ended = !matches!(line, Ok(Some(_)));
+            if sender.send(line).is_err() || ended {
+                break;
+            }
+        }
+    });
+    Mutex::new(Lines { requests, lines, pending: false })
+}
+
+fn read_plain() -> io::Result<Option<String>> {
+    let mut line = String::new();
+    Ok(match io::stdin().lock().read_line(&mut line)? {
+        0 => None,
+        _ => Some(line),
+    })
+}
+
+/// The line editor, with the history of earlier runs; None where the terminal can't have
+/// one.
+fn editor() -> Option<Editor<Continuation, FileHistory>> {
+    let config = Config::builder().auto_add_history(false).max_history_size(HISTORY_SIZE).ok()?.build();
+    let mut editor = Editor::with_config(config).ok()?;
+    editor.set_helper(Some(Continuation));
+    // Without one, output printed while editing runs into the line
+    if let Ok(printer) = editor.create_external_printer() {
+        *PRINTER.lock().unwrap() = Some(Box::new(printer));
+    }
+    editor.bind_sequence(KeyEvent(KeyCode::Enter, Modifiers::ALT), Cmd::Newline);
+    editor.bind_sequence(KeyEvent(KeyCode::Enter, Modifiers::SHIFT), Cmd::Newline);
+    // There is no history yet on the first run
+    let _ = editor.load_history(&paths::data_path(HISTORY_FILE));
+    Some(editor)
+}
+
+/// Reads a line with the editor. Ctrl+C and Ctrl+D end the input, as they do without it.
+fn read_edited(editor: &mut Editor<Continuation, FileHistory>, remember: bool) -> io::Result<Option<String>> {
+    EDITING.store(true, Ordering::Relaxed);
+    let line = editor.readline("");
+    EDITING.store(false, Ordering::Relaxed);
+    let line = match line {
+        Ok(line) => line.replace("\\\n", "\n"),
+        Err(ReadlineError::Interrupted | ReadlineError::Eof) => return Ok(None),
+        Err(ReadlineError::Io(e)) => return Err(e),
+        Err(e) => return Err(io::Error::other(e)),
+    };
+    if remember && !line.trim().is_empty() {
+        let _ = editor.add_history_entry(line.as_str());
+        // Forgetting the history is no reason to stop the chat
+        let _ = std::fs::create_dir_all(paths::data_dir());
+        let _ = editor.save_history(&paths::data_path(HISTORY_FILE));
+    }
+    Ok(Some(line + "\n"))
+}
+
+/// Goes on to a new line of the same input when a line ends with `\`.
+struct Continuation;
+
+impl Validator for Continuation {
+    fn validate(&self, context: &mut ValidationContext) -> rustyline::Result<ValidationResult> {
+        Ok(match context.input().ends_with('\\') {
+            true => ValidationResult::Incomplete,
+            false => ValidationResult::Valid(None),
+        })
+    }
+}
+
+impl Completer for Continuation {
+    type Candidate = String;
+}
+
+impl Hinter for Continuation {
+    type Hint = String;
+}
+
+impl Highlighter for Continuation {}
+
+impl Helper for Continuation {}
@@ -0,0 +1,273 @@
+use crate::gateway::{Connector, Incoming};
+use crate::server::Calls;
+use async_trait::async_trait;
+use base64::Engine;
+use serde::{Deserialize, Serialize};
+use std::io;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use tokio::io::{AsyncBufReadExt, AsyncRead, AsyncWrite, AsyncWriteExt, BufReader};
+use tokio::net::TcpStream;
+use tokio::sync::mpsc;
+use tokio::time::Instant;
+
+/// The secret holding the password of the SASL account.
+pub const PASSWORD_NAME: &str = "IRC_PASSWORD";
+/// How many bytes of text go in one line, leaving room for the command and the prefix the
+/// server adds within IRC's 512.
+const LINE_BYTES: usize = 400;
+/// Replies are cut off after this many lines, so a long one does not flood the channel.
+const MAX_LINES: usize = 6;
+/// Flood protection: after a burst of this many lines, one line is sent every
+/// `LINE_INTERVAL`, which keeps servers from disconnecting the bot for flooding.
+const BURST: u32 = 4;
+const LINE_INTERVAL: Duration = Duration::from_secs(2);
+/// How long to wait before connecting again after the connection was lost.
+const RETRY_AFTER: Duration = Duration::from_secs(30);
+
+/// The IRC network and channels the bot joins.
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct IrcSettings {
+    /// Such as `irc.libera.chat`.
+    pub server: String,
+    #[serde(default = "default_port")]
+    pub port: u16,
+    #[serde(default = "default_tls")]
+    pub tls: bool,
+    pub nick: String,
+    #[serde(default)]
+    pub channels: Vec<String>,
+    /// The account to log in to with SASL, with its password in `IRC_PASSWORD`.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub sasl_account: Option<String>,
+}
+
+fn default_port() -> u16 {
+    6697
+}
+
+fn default_tls() -> bool {
+    true
+}
+
+trait Connection: AsyncRead + AsyncWrite + Unpin + Send {}
+
+impl<T: AsyncRead + AsyncWrite + Unpin + Send> Connection for T {}
+
+/// The bot on the IRC network. It stays on the network, reconnecting when the connection is
+/// lost, and answers private messages and the channel messages addressed to its nick, with a
+/// conversation for each nick.
+pub struct Bot {
+    settings: IrcSettings,
+    password: Option<String>,
+    /// The lines to send on the current connection, once the bot is registered on it.
+    lines: Mutex<Option<mpsc::UnboundedSender<String>>>,
+}
+
+/// Who to answer: the channel or nick, and in a channel, the nick to address the reply to.
+pub struct ReplyTo {
+    to: String,
+    sender: Option<String>,
+}
+
+#[async_trait]
+impl Connector for Bot {
+    type ReplyTo = ReplyTo;
+
+    const NAME: &'static str = "irc";
+    const TITLE: &'static str = "IRC";
+    const PER_USER: bool = true;
+
+    /// Fails only if the SASL login is refused.
+    async fn receive(self: Arc<Self>, incoming: mpsc::Sender<Incoming<ReplyTo>>, _calls: Calls) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+        let server = &self.settings.server;
+        loop {
+            let result = self.connect(&incoming).await;
+            *self.lines.lock().unwrap() = None;
+            match result {
+                Ok(()) => status!("Disconnected from {}, reconnecting", server),
+                Err(e) if e.kind() == io::ErrorKind::PermissionDenied => return Err(e.into()),
+                Err(e) => status!("Lost the connection to {}, reconnecting: {}", server, e),
+            }
+            tokio::time::sleep(RETRY_AFTER).await;
+        }
+    }
+
+    async fn send(&self, message: &Incoming<ReplyTo>, reply: &str) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+        let lines = self.lines.lock().unwrap().clone().ok_or("Not connected to IRC")?;
+        let mut parts: Vec<String> = reply.lines().filter(|line| !line.trim().is_empty()).flat_map(split_line).collect();
+        if parts.len() > MAX_LINES {
+            parts.truncate(MAX_LINES);
+            parts[MAX_LINES - 1].push_str(" […]");
+        }
+        for (index, part) in parts.iter().enumerate() {
+            // In a channel, the first line says who it answers
+            let text = match (index, &message.reply_to.sender) {
+                (0, Some(sender)) => format!("{}: {}", sender, part),
+                _ => part.clone(),
+            };
+            lines.send(format!("PRIVMSG {} :{}", message.reply_to.to, text))?;
+        }
+        Ok(())
+    }
+}
+
+impl Bot {
+    pub fn new(settings: IrcSettings, password: Option<String>) -> Self {
+        Bot { settings, password, lines: Mutex::new(None) }
+    }
+
+    /// Talks to the network until it closes the connection.
+    async fn connect(&self, incoming: &mpsc::Sender<Incoming<ReplyTo>>) -> io::Result<()> {
+        let settings = &self.settings;
+        let tcp = TcpStream::connect((settings.server.as_str(), settings.port)).await?;
+        let connection: Box<dyn Connection> = if settings.tls {
+            let tls = tokio_native_tls::native_tls::TlsConnector::new().map_err(io::Error::other)?;
+            let tls = tokio_native_tls::TlsConnector::from(tls);
+            Box::new(tls.connect(&settings.server, tcp).await.map_err(io::Error::other)?)
+        } else {
+            Box::new(tcp)
+        };
+        let (reader, writer) = tokio::io::split(connection);
+        let mut reader = BufReader::new(reader);
+        let (lines, queued) = mpsc::unbounded_channel();
+        let writer = tokio::spawn(write_lines(writer, queued));
+
+        if settings.sasl_account.is_some() {
+            let _ = lines.send("CAP REQ :sasl".to_string());
+        }
+        let mut nick = settings.nick.clone();
+        let _ = lines.send(format!("NICK {}", nick));
+        let _ = lines.send(format!("USER {} 0 * :{}", nick, nick));
+
+        let mut line = String::new();
+        let result = loop {
+            line.clear();
+            match reader.read_line(&mut line).await {
+                Ok(0) => break Ok(()),
+                Ok(_) => {}
+                Err(e) => break Err(e),
+            }
+            let (prefix, command, params) = parse(line.trim_end());
+            match (command, params.as_slice()) {
+                ("PING", [token, ..]) => {
+                    let _ = lines.send(format!("PONG :{}", token));
+                }
+                ("CAP", [_, "ACK", ..]) => {
+                    let _ = lines.send("AUTHENTICATE PLAIN".to_string());
+                }
+                ("CAP", [_, "NAK", ..]) => break Err(refused("The server does not support SASL")),
+                ("AUTHENTICATE", ["+"]) => {
+                    let account = settings.sasl_account.as_deref().unwrap_or_default();
+                    let login = format!("{}\0{}\0{}", account, account, self.password.as_deref().unwrap_or_default());
+                    let _ = lines.send(format!("AUTHENTICATE {}", base64::engine::general_purpose::STANDARD.encode(login)));
+                }
+                ("903", _) => {
+                    let _ = lines.send("CAP END".to_string());
+                }
+                ("902" | "904" | "905", _) => break Err(refused("The SASL login was refused; check the account and IRC_PASSWORD")),
+                ("001", [me, ..]) => {
+                    nick = me.to_string();
+                    status!("Connected to {} as {}", settings.server, nick);
+                    for channel in &settings.channels {
+                        let _ = lines.send(format!("JOIN {}", channel));
+                    }
+                    *self.lines.lock().unwrap() = Some(lines.clone());
+                }
+                ("433", _) => {
+                    nick.push('_');
+                    let _ = lines.send(format!("NICK {}", nick));
+                }
+                ("PRIVMSG", [target, text]) => {
+                    let Some(sender) = prefix.and_then(|prefix| prefix.split('!').next()) else {
+                        continue;
+                    };
+                    // CTCP requests, such as VERSION, are not chat
+                    if text.starts_with('\u{1}') {
+                        continue;
+                    }
+                    let in_channel = target.starts_with('#') || target.starts_with('&');
+                    let message = if in_channel { addressed(text, &nick) } else { Some(text.to_string()) };
+                    let Some(text) = message.filter(|message| !message.is_empty()) else {
+                        continue;
+                    };
+                    let (to, addressee) = if in_channel { (target.to_string(), Some(sender.to_string())) } else { (sender.to_string(), None) };
+                    let message = Incoming {
+                        user: sender.to_lowercase(),
+                        channel: to.clone(),
+                        text,
+                        reply_to: ReplyTo { to, sender: addressee },
+                    };
+                    let _ = incoming.send(message).await;
+                }
+                ("ERROR", _) => break Ok(()),
+                _ => {}
+            }
+        };
+        writer.abort();
+        result
+    }
+}
+
+fn refused(message: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::PermissionDenied, message)
+}
+
+/// Sends the queued lines, holding them back so as not to flood the server.
+async fn write_lines<W: AsyncWrite + Unpin>(mut writer: W, mut queued: mpsc::UnboundedReceiver<String>) -> io::Result<()> {
+    let mut next = Instant::now();
+    while let Some(line) = queued.recv().await {
+        let now = Instant::now();
+        next = next.max(now.checked_sub(LINE_INTERVAL * BURST).unwrap_or(now));
+        tokio::time::sleep_until(next).await;
+        next += LINE_INTERVAL;
+        writer.write_all(format!("{}\r\n", line).as_bytes()).await?;
+    }
+    Ok(())
+}
+
+/// A message's prefix, command and parameters, the last of which may contain spaces.
+fn parse(line: &str) -> (Option<&str>, &str, Vec<&str>) {
+    let (prefix, rest) = match line.strip_prefix(':') {
+        Some(rest) => match rest.split_once(' ') {
+            Some((prefix, rest)) => (Some(prefix), rest),
+            None => (Some(rest), ""),
+        },
+        None => (None, line),
+    };
+    let (middle, trailing) = match rest.split_once(" :") {
+        Some((middle, trailing)) => (middle, Some(trailing)),
+        None => (rest, None),
+    };
+    let mut words = middle.split(' ').filter(|word| !word.is_empty());
+    let command = words.next().unwrap_or_default();
+    let mut params: Vec<&str> = words.collect();
+    params.extend(trailing);
+    (prefix, command, params)
+}
+
+/// The text of a channel message addressed to `nick`, as in "alya: hi" or "alya, hi".
+fn addressed(text: &str, nick: &str) -> Option<String> {
+    let head = text.get(..nick.len())?;
+    let rest = text[nick.len()..].strip_prefix([':', ','])?;
+    head.eq_ignore_ascii_case(nick).then(|| rest.trim().to_string())
+}
+
+/// Splits a line of text into pieces of at most `LINE_BYTES` bytes, at spaces where it can.
+fn split_line(line: &str) -> Vec<String> {
+    let mut parts = Vec::new();
+    let mut rest = line.trim();
+    while rest.len() > LINE_BYTES {
+        let mut end = LINE_BYTES;
+        while !rest.is_char_boundary(end) {
+            end -= 1;
+        }
+        let cut = rest[..end].rfind(' ').filter(|&cut| cut > 0).unwrap_or(end);
+        parts.push(rest[..cut].trim_end().to_string());
+        rest = rest[cut..].trim_start();
+    }
+    if !rest.is_empty() {
+        parts.push(rest.to_string());
+    }
+    parts
+}
@@ -0,0 +1,154 @@
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+
+const STATE_FILE: &str = "jobs.json";
+
+/// When a job runs.
+#[derive(Debug, Clone)]
+pub enum Schedule {
+    /// This long after the job last ran, and right away if it never did.
+    Every(chrono::Duration),
+    /// At the times of a cron expression.
+    Cron(Box<cron::Schedule>),
+}
+
+impl Schedule {
+    /// Parses `every 30m`, `every 6h`, `every 2d` or `every 1w`, `@hourly`, `@daily` and the
+    /// like, or a cron expression of five fields (minute to weekday) or six (with seconds).
+    pub fn parse(s: &str) -> Result<Schedule, String> {
+        let s = s.trim();
+        if let Some(interval) = s.strip_prefix("every ") {
+            let interval = interval.trim();
+            let split = interval.find(|c: char| !c.is_ascii_digit()).unwrap_or(interval.len());
+            let (count, unit) = interval.split_at(split);
+            let count: i64 = count.parse().map_err(|_| format!("\"{}\" does not start with a number", interval))?;
+            let duration = match unit.trim() {
+                "m" | "min" | "minutes" => chrono::Duration::minutes(count),
+                "h" | "hours" => chrono::Duration::hours(count),
+                "d" | "days" => chrono::Duration::days(count),
+                "w" | "weeks" => chrono::Duration::weeks(count),
+                unit => return Err(format!("unknown unit \"{}\"; use m, h, d or w", unit)),
+            };
+            if count <= 0 {
+                return Err("the interval has to be longer than zero".to_string());
+            }
+            return Ok(Schedule::Every(duration));
+        }
+        // The cron crate counts seconds too; classic five-field expressions start at the minute
+        let expression = if s.split_whitespace().count() == 5 { format!("0 {}", s) } else { s.to_string() };
+        cron::Schedule::from_str(&expression)
+            .map(|schedule| Schedule::Cron(Box::new(schedule)))
+            .map_err(|e| format!("\"{}\" is not a schedule: {}", s, e))
+    }
+
+    /// When a job that last ran at `last_run` runs next. A cron job that never ran waits
+    /// for its next time.
+    pub fn next_run(&self, last_run: Option<DateTime<Utc>>, now: DateTime<Utc>) -> Option<DateTime<Utc>> {
+        match self {
+            Schedule::Every(interval) => Some(last_run.map_or(now, |last| last + *interval)),
+            Schedule::Cron(schedule) => schedule.after(&last_run.unwrap_or(now)).next(),
+        }
+    }
+}
+
+/// A task run on a schedule, such as refreshing the knowledge or making a backup.
+#[derive(Debug, Clone)]
+pub struct Job {
+    pub name: String,
+    pub description: String,
+    pub schedule: Schedule,
+    /// The schedule as written in the config.
+    pub spec: String,
+}
+
+/// The outcome of a job's last run.
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct JobRun {
+    pub last_run: DateTime<Utc>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+}
+
+/// The registered jobs and when each last ran, persisted in `jobs.json` so schedules carry
+/// over restarts. Running the jobs is up to the caller, which asks `due` what to run.
+pub struct Scheduler {
+    path: PathBuf,
+    jobs: Vec<Job>,
+    runs: HashMap<String, JobRun>,
+}
+
+impl Scheduler {
+    /// Loads when the jobs last ran from `dir`, with no jobs registered yet.
+    pub fn load(dir: &Path) -> Result<Self, Box<dyn std::error::Error>> {
+        let path = dir.join(STATE_FILE);
+        let runs = match fs::read_to_string(&path) {
+            Ok(text) => serde_json::from_str(&text)?,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => HashMap::new(),
+            Err(e) => return Err(e.into()),
+        };
+        Ok(Scheduler { path, jobs: Vec::new(), runs })
+    }
+
+    /// Adds a job, replacing any registered under the same name.
+    pub fn register(&mut self, name: &str, description: &str, spec: &str) -> Result<(), String> {
+        let schedule = Schedule::parse(spec)?;
+        self.jobs.retain(|job| job.name != name);
+        self.jobs.push(Job {
+            name: name.to_string(),
+            description: description.to_string(),
+            schedule,
+            spec: spec.trim().to_string(),
+        });
+        Ok(())
+    }
+
+    /// Takes a job off the schedule for this run.
+    pub fn unregister(&mut self, name: &str) {
+        self.jobs.retain(|job| job.name != name);
+    }
+
+    pub fn jobs(&self) -> &[Job] {
+        &self.jobs
+    }
+
+    pub fn job(&self, name: &str) -> Option<&Job> {
+        self.jobs.iter().find(|job| job.name == name)
+    }
+
+    pub fn last_run(&self, name: &str) -> Option<&JobRun> {
+        self.runs.get(name)
+    }
+
+    pub fn next_run(&self, job: &Job) -> Option<DateTime<Utc>> {
+        job.schedule.next_run(self.runs.get(&job.name).map(|run| run.last_run), Utc::now())
+    }
+
+    /// When the next job is due, if any job is scheduled.
+    pub fn next_due(&self) -> Option<DateTime<Utc>> {
+        self.jobs.iter().filter_map(|job| self.next_run(job)).min()
+    }
+
+    /// The names of the jobs due now, in the order they were registered.
+    pub fn due(&self) -> Vec<String> {
+        let now = Utc::now();
+        self.jobs
+            .iter()
+            .filter(|job| self.next_run(job).is_some_and(|next| next <= now))
+            .map(|job| job.name.clone())
+            .collect()
+    }
+
+    /// Records that a job just ran, with the error it failed with, and saves the state.
+    pub fn record(&mut self, name: &str, error: Option<String>) -> Result<(), Box<dyn std::error::Error>> {
+        self.runs.insert(name.to_string(), JobRun { last_run: Utc::now(), error });
+        if let Some(dir) = self.path.parent() {
+            fs::create_dir_all(dir)?;
+        }
+        fs::write(&self.path, serde_json::to_string_pretty(&self.runs)?)?;
+        Ok(())
+    }
+}
@@ -0,0 +1,674 @@
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use sha2::{Digest, Sha256};
+use std::collections::{HashMap, HashSet};
+
+/// Version of the persisted knowledge format. Bump it together with a new step in
+/// `SCHEMA_UPGRADES` whenever the stored shape of `Knowledge` changes.
+pub const SCHEMA_VERSION: u32 = 1;
+
+/// Number of consecutive words per shingle used for near-duplicate detection.
+const SHINGLE_SIZE: usize = 5;
+/// Jaccard similarity above which two facts are considered the same content.
+const NEAR_DUPLICATE_THRESHOLD: f64 = 0.8;
+/// Cosine similarity above which two embedded facts are considered the same content.
+const EMBEDDING_DUPLICATE_THRESHOLD: f32 = 0.95;
+
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Eq, Hash)]
+#[serde(rename_all = "kebab-case")]
+pub enum FactTag {
+    Personality,
+    Relationships,
+    Plot,
+    UserInfo,
+    Trained,
+    /// Real-world places, institutions and references rather than the characters.
+    Background,
+}
+
+impl FactTag {
+    pub const ALL: [FactTag; 6] = [
+        FactTag::Personality,
+        FactTag::Relationships,
+        FactTag::Plot,
+        FactTag::UserInfo,
+        FactTag::Trained,
+        FactTag::Background,
+    ];
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            FactTag::Personality => "personality",
+            FactTag::Relationships => "relationships",
+            FactTag::Plot => "plot",
+            FactTag::UserInfo => "user-info",
+            FactTag::Trained => "trained",
+            FactTag::Background => "background",
+        }
+    }
+
+    pub fn parse(s: &str) -> Option<FactTag> {
+        let s = s.trim().to_lowercase();
+        FactTag::ALL.into_iter().find(|tag| tag.as_str() == s)
+    }
+}
+
+/// How a fact entered the knowledge base.
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "kebab-case")]
+pub enum LearnMethod {
+    WebSearch,
+    Url,
+    Training,
+    /// Entries of the hand-written lorebook in `lore/`.
+    Lorebook,
+    /// Curated question-answer pairs imported with `import_qa`.
+    Dataset,
+    /// Entries of subscribed RSS and Atom feeds.
+    Feed,
+    /// Encyclopedia articles on background topics, looked up with `wiki`, and character
+    /// database entries.
+    Reference,
+    /// Memories about a person chatting with the character, from what they said.
+    Conversation,
+    /// Facts carried over from knowledge files written before provenance was tracked.
+    Unknown,
+}
+
+impl LearnMethod {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            LearnMethod::WebSearch => "web search",
+            LearnMethod::Url => "url",
+            LearnMethod::Training => "training",
+            LearnMethod::Lorebook => "lorebook",
+            LearnMethod::Dataset => "dataset",
+            LearnMethod::Feed => "feed",
+            LearnMethod::Reference => "reference",
+            LearnMethod::Conversation => "conversation",
+            LearnMethod::Unknown => "unknown",
+        }
+    }
+
+    /// How much a fact is trusted when it is first learned.
+    pub fn initial_confidence(&self) -> f64 {
+        match self {
+            LearnMethod::Training | LearnMethod::Lorebook | LearnMethod::Dataset => 1.0,
+            LearnMethod::Url | LearnMethod::Feed | LearnMethod::Reference | LearnMethod::Conversation => 0.9,
+            LearnMethod::WebSearch => 0.7,
+            LearnMethod::Unknown => 0.5,
+        }
+    }
+
+    /// Web content goes stale; hand-fed training text does not, and neither do feed entries,
+    /// which report what was news on their date, or encyclopedia background.
+    pub fn decays(&self) -> bool {
+        matches!(self, LearnMethod::Url | LearnMethod::WebSearch | LearnMethod::Unknown)
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct Fact {
+    pub text: String,
+    pub source_url: Option<String>,
+    pub learned_at: DateTime<Utc>,
+    pub method: LearnMethod,
+    #[serde(default)]
+    pub tags: Vec<FactTag>,
+    /// Words that put the fact into the prompt whenever a message mentions one of them,
+    /// however similar the rest of the message is.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub keywords: Vec<String>,
+    /// Confidence at `verified_at`, before any decay.
+    pub confidence: f64,
+    /// When the fact was last confirmed against its source.
+    pub verified_at: DateTime<Utc>,
+    /// Embedding of `text`, used to retrieve the fact for relevant messages.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub embedding: Option<Vec<f32>>,
+    /// Model that produced `embedding`.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub embedding_model: Option<String>,
+    /// For memories about a person chatting with the character, the session of that
+    /// person; only their conversations see the fact.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub user: Option<String>,
+    /// Set on facts loaded without their text and embedding; the full fact stays in the
+    /// store until it is fetched with `KnowledgeStore::load_fact`.
+    #[serde(skip)]
+    pub stub: Option<FactStub>,
+}
+
+/// What a fact loaded without its body still knows about it.
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct FactStub {
+    pub content_hash: String,
+    /// Whether the stored fact has an embedding from `embedding_model`.
+    pub embedded: bool,
+}
+
+impl Fact {
+    pub fn new(text: String, source_url: Option<String>, method: LearnMethod, tags: Vec<FactTag>) -> Self {
+        let now = Utc::now();
+        Fact {
+            text,
+            source_url,
+            learned_at: now,
+            method,
+            tags,
+            keywords: Vec::new(),
+            confidence: method.initial_confidence(),
+            verified_at: now,
+            embedding: None,
+            embedding_model: None,
+            user: None,
+            stub: None,
+        }
+    }
+
+    /// What a stub of this fact keeps of its text and embedding.
+    pub fn stub_info(&self) -> FactStub {
+        match &self.stub {
+            Some(stub) => stub.clone(),
+            None => FactStub {
+                content_hash: self.content_hash(),
+                embedded: self.embedding.is_some(),
+            },
+        }
+    }
+
+    /// This fact without its text and embedding.
+    pub fn to_stub(&self) -> Fact {
+        Fact {
+            text: String::new(),
+            source_url: self.source_url.clone(),
+            learned_at: self.learned_at,
+            method: self.method,
+            tags: self.tags.clone(),
+            keywords: self.keywords.clone(),
+            confidence: self.confidence,
+            verified_at: self.verified_at,
+            embedding: None,
+            embedding_model: self.embedding_model.clone(),
+            user: self.user.clone(),
+            stub: Some(self.stub_info()),
+        }
+    }
+
+    pub fn is_stub(&self) -> bool {
+        self.stub.is_some()
+    }
+
+    /// Whether the fact has an embedding from `model`, loaded or not.
+    pub fn is_embedded_with(&self, model: &str) -> bool {
+        let embedded = self.embedding.is_some() || self.stub.as_ref().is_some_and(|stub| stub.embedded);
+        embedded && self.embedding_model.as_deref() == Some(model)
+    }
+
+    /// Confidence after exponential decay since the last verification.
+    pub fn current_confidence(&self, half_life_days: f64) -> f64 {
+        if !self.method.decays() || half_life_days <= 0.0 {
+            return self.confidence;
+        }
+        let age_days = (Utc::now() - self.verified_at).num_seconds().max(0) as f64 / 86_400.0;
+        self.confidence * 0.5_f64.powf(age_days / half_life_days)
+    }
+
+    /// Hash of the whitespace- and case-normalised text.
+    pub fn content_hash(&self) -> String {
+        if let Some(stub) = &self.stub {
+            return stub.content_hash.clone();
+        }
+        let normalized = normalized_words(&self.text).join(" ");
+        format!("{:x}", Sha256::digest(normalized.as_bytes()))
+    }
+
+    fn shingles(&self) -> HashSet<String> {
+        let words = normalized_words(&self.text);
+        if words.len() <= SHINGLE_SIZE {
+            return HashSet::from([words.join(" ")]);
+        }
+        words.windows(SHINGLE_SIZE).map(|window| window.join(" ")).collect()
+    }
+
+    /// Whether `message` mentions one of the fact's keywords as a whole word, ignoring case.
+    pub fn is_triggered_by(&self, message: &str) -> bool {
+        let message = message.to_lowercase();
+        self.keywords.iter().any(|keyword| {
+            let keyword = keyword.trim().to_lowercase();
+            !keyword.is_empty()
+                && message.match_indices(&keyword).any(|(at, _)| {
+                    let before = message[..at].chars().next_back();
+                    let after = message[at + keyword.len()..].chars().next();
+                    !before.is_some_and(char::is_alphanumeric) && !after.is_some_and(char::is_alphanumeric)
+                })
+        })
+    }
+
+    /// Whether both facts say essentially the same thing, either byte-for-byte after
+    /// normalisation, by near-identical embeddings, or by overlapping word shingles.
+    pub fn is_duplicate_of(&self, other: &Fact) -> bool {
+        if self.content_hash() == other.content_hash() {
+            return true;
+        }
+        // Without the text only exact duplicates can be recognised
+        if self.is_stub() || other.is_stub() {
+            return false;
+        }
+        if let (Some(ours), Some(theirs)) = (&self.embedding, &other.embedding) {
+            if self.embedding_model == other.embedding_model
+                && cosine_similarity(ours, theirs) >= EMBEDDING_DUPLICATE_THRESHOLD
+            {
+                return true;
+            }
+        }
+        let ours = self.shingles();
+        let theirs = other.shingles();
+        let union = ours.union(&theirs).count();
+        union > 0 && ours.intersection(&theirs).count() as f64 / union as f64 >= NEAR_DUPLICATE_THRESHOLD
+    }
+}
+
+pub fn cosine_similarity(a: &[f32], b: &[f32]) -> f32 {
+    if a.len() != b.len() || a.is_empty() {
+        return 0.0;
+    }
+    let dot: f32 = a.iter().zip(b).map(|(x, y)| x * y).sum();
+    let norm_a = a.iter().map(|x| x * x).sum::<f32>().sqrt();
+    let norm_b = b.iter().map(|x| x * x).sum::<f32>().sqrt();
+    if norm_a == 0.0 || norm_b == 0.0 {
+        return 0.0;
+    }
+    dot / (norm_a * norm_b)
+}
+
+fn normalized_words(text: &str) -> Vec<String> {
+    text.split_whitespace()
+        .map(|word| word.chars().filter(|c| c.is_alphanumeric()).collect::<String>().to_lowercase())
+        .filter(|word| !word.is_empty())
+        .collect()
+}
+
+/// Which side of a contradiction survives.
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "kebab-case")]
+pub enum Verdict {
+    Existing,
+    New,
+    Both,
+}
+
+impl Verdict {
+    pub fn parse(s: &str) -> Option<Verdict> {
+        match s.trim().trim_matches(|c: char| !c.is_alphabetic()).to_lowercase().as_str() {
+            "existing" => Some(Verdict::Existing),
+            "new" => Some(Verdict::New),
+            "both" => Some(Verdict::Both),
+            _ => None,
+        }
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct Resolution {
+    pub verdict: Verdict,
+    /// "model" or "user".
+    pub resolved_by: String,
+    pub explanation: String,
+    pub resolved_at: DateTime<Utc>,
+    /// Text of the fact that was dropped, kept so the decision can be reviewed later.
+    pub discarded_text: Option<String>,
+}
+
+/// A pair of facts that disagree with each other, plus how the conflict was settled.
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct Contradiction {
+    pub new_key: String,
+    pub existing_key: String,
+    pub description: String,
+    pub detected_at: DateTime<Utc>,
+    pub resolution: Option<Resolution>,
+}
+
+/// What was seen the last time a learned page was fetched, to tell whether it changed.
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+pub struct PageState {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub etag: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub last_modified: Option<String>,
+    /// Hash of the page content as converted for learning; empty if never fetched.
+    #[serde(default)]
+    pub content_hash: String,
+    pub checked_at: DateTime<Utc>,
+    /// Set once the page answers 404 Not Found or 410 Gone.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub gone: Option<Gone>,
+    /// What was written from each run of sections, so a changed page only has its changed
+    /// sections processed again.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub sections: Vec<SectionState>,
+}
+
+/// What the model wrote from a run of sections of a learned page.
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+pub struct SectionState {
+    /// Hash of the sections' text.
+    pub hash: String,
+    pub processed: String,
+}
+
+impl SectionState {
+    pub fn hash(text: &str) -> String {
+        format!("{:x}", Sha256::digest(text.as_bytes()))
+    }
+}
+
+/// A learned page that no longer exists.
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+pub struct Gone {
+    pub status: u16,
+    pub since: DateTime<Utc>,
+}
+
+impl PageState {
+    pub fn new(content: &str, etag: Option<String>, last_modified: Option<String>) -> Self {
+        PageState {
+            etag,
+            last_modified,
+            content_hash: format!("{:x}", Sha256::digest(content.as_bytes())),
+            checked_at: Utc::now(),
+            gone: None,
+            sections: Vec::new(),
+        }
+    }
+}
+
+/// A local file trained with, to tell whether it changed since.
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+pub struct FileState {
+    pub modified: DateTime<Utc>,
+    /// Hash of the file's bytes.
+    pub content_hash: String,
+    pub trained_at: DateTime<Utc>,
+}
+
+impl FileState {
+    pub fn new(bytes: &[u8], modified: DateTime<Utc>) -> Self {
+        FileState {
+            modified,
+            content_hash: format!("{:x}", Sha256::digest(bytes)),
+            trained_at: Utc::now(),
+        }
+    }
+}
+
+/// A subscribed feed: when it was last checked and the entries already seen in it.
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+pub struct FeedState {
+    pub checked_at: DateTime<Utc>,
+    /// IDs of the entries learned from or passed over, newest first.
+    pub seen: Vec<String>,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct Knowledge {
+    /// Format version the knowledge was written with; see `Knowledge::from_json`.
+    #[serde(default)]
+    pub schema_version: u32,
+    pub facts: HashMap<String, Fact>,
+    pub search_history: Vec<String>,
+    pub learned_urls: Vec<String>,
+    pub external_url_count: usize,
+    pub cached_content: HashMap<String, String>,
+    #[serde(default)]
+    pub contradictions: Vec<Contradiction>,
+    /// Validators and content hashes of learned pages, keyed by URL, for `refresh`.
+    #[serde(default)]
+    pub pages: HashMap<String, PageState>,
+    /// Local files trained with, keyed by their `file://` URL, for `train_dir`.
+    #[serde(default)]
+    pub files: HashMap<String, FileState>,
+    /// Subscribed RSS and Atom feeds, keyed by URL.
+    #[serde(default)]
+    pub feeds: HashMap<String, FeedState>,
+}
+
+impl Default for Knowledge {
+    fn default() -> Self {
+        Knowledge {
+            schema_version: SCHEMA_VERSION,
+            facts: HashMap::new(),
+            search_history: Vec::new(),
+            learned_urls: Vec::new(),
+            external_url_count: 0,
+            cached_content: HashMap::new(),
+            contradictions: Vec::new(),
+            pages: HashMap::new(),
+            files: HashMap::new(),
+            feeds: HashMap::new(),
+        }
+    }
+}
+
+impl Knowledge {
+    /// Reads persisted knowledge of any schema version, upgrading older formats first.
+    pub fn from_json(mut value: Value) -> Result<Knowledge, Box<dyn std::error::Error>> {
+        let version = value.get("schema_version").and_then(Value::as_u64).unwrap_or(0) as u32;
+        if version > SCHEMA_VERSION {
+            return Err(format!(
+                "Knowledge uses schema version {}, but this build only understands up to {}; please update the chatbot",
+                version, SCHEMA_VERSION
+            )
+            .into());
+        }
+        for upgrade in &SCHEMA_UPGRADES[version as usize..] {
+            upgrade(&mut value);
+        }
+        if let Some(knowledge) = value.as_object_mut() {
+            knowledge.insert("schema_version".to_string(), SCHEMA_VERSION.into());
+        }
+        Ok(serde_json::from_value(value)?)
+    }
+
+    /// Stable hash of the whole knowledge; `serde_json::Value` keeps object keys sorted.
+    pub fn fingerprint(&self) -> String {
+        let canonical = serde_json::to_value(self).map(|value| value.to_string()).unwrap_or_default();
+        format!("{:x}", Sha256::digest(canonical.as_bytes()))
+    }
+
+    pub fn merge(&mut self, other: Knowledge) {
+        let mut facts: Vec<(String, Fact)> = other.facts.into_iter().collect();
+        facts.sort_by(|a, b| a.0.cmp(&b.0));
+        for (key, fact) in facts {
+            self.insert_fact(key, fact);
+        }
+        for query in other.search_history {
+            if !self.search_history.contains(&query) {
+                self.search_history.push(query);
+            }
+        }
+        for url in other.learned_urls {
+            if !self.learned_urls.contains(&url) {
+                self.learned_urls.push(url);
+            }
+        }
+        self.cached_content.extend(other.cached_content);
+        self.pages.extend(other.pages);
+        self.files.extend(other.files);
+        self.feeds.extend(other.feeds);
+        self.external_url_count = other.external_url_count;
+        for contradiction in other.contradictions {
+            let known = self.contradictions.iter().any(|c| {
+                c.new_key == contradiction.new_key
+                    && c.existing_key == contradiction.existing_key
+                    && c.detected_at == contradiction.detected_at
+            });
+            if !known {
+                self.contradictions.push(contradiction);
+            }
+        }
+    }
+
+    /// Source URLs of facts whose decayed confidence fell below `threshold`.
+    pub fn urls_needing_reverification(&self, half_life_days: f64, threshold: f64) -> Vec<String> {
+        let mut urls: Vec<String> = self
+            .facts
+            .values()
+            .filter(|fact| fact.current_confidence(half_life_days) < threshold)
+            .filter_map(|fact| fact.source_url.clone())
+            .collect();
+        urls.sort();
+        urls.dedup();
+        urls
+    }
+
+    /// Records that a learned page answered `status`; the first time is kept.
+    pub fn mark_gone(&mut self, url: &str, status: u16) {
+        let page = self.pages.entry(url.to_string()).or_insert_with(|| PageState {
+            etag: None,
+            last_modified: None,
+            content_hash: String::new(),
+            checked_at: Utc::now(),
+            gone: None,
+            sections: Vec::new(),
+        });
+        page.checked_at = Utc::now();
+        page.gone.get_or_insert(Gone {
+            status,
+            since: Utc::now(),
+        });
+    }
+
+    /// Learned pages that no longer exist, in the order they were learned.
+    pub fn gone_sources(&self) -> Vec<String> {
+        self.learned_urls
+            .iter()
+            .filter(|url| self.pages.get(*url).is_some_and(|page| page.gone.is_some()))
+            .cloned()
+            .collect()
+    }
+
+    /// Keys of the facts learned from `url`.
+    pub fn facts_from(&self, url: &str) -> Vec<String> {
+        let mut keys: Vec<String> = self
+            .facts
+            .iter()
+            .filter(|(_, fact)| fact.source_url.as_deref() == Some(url))
+            .map(|(key, _)| key.clone())
+            .collect();
+        keys.sort();
+        keys
+    }
+
+    /// Indices of contradictions still waiting for a verdict.
+    pub fn pending_contradictions(&self) -> Vec<usize> {
+        (0..self.contradictions.len())
+            .filter(|&i| self.contradictions[i].resolution.is_none())
+            .collect()
+    }
+
+    /// Applies a verdict to a flagged contradiction, dropping the losing fact.
+    /// Returns the key of the dropped fact, if any.
+    pub fn resolve_contradiction(
+        &mut self,
+        index: usize,
+        verdict: Verdict,
+        resolved_by: &str,
+        explanation: String,
+    ) -> Option<String> {
+        let contradiction = self.contradictions.get(index)?;
+        let discarded_key = match verdict {
+            Verdict::Existing => Some(contradiction.new_key.clone()),
+            Verdict::New => Some(contradiction.existing_key.clone()),
+            Verdict::Both => None,
+        };
+        let discarded_text = discarded_key
+            .as_ref()
+            .and_then(|key| self.facts.remove(key))
+            .map(|fact| fact.text);
+        self.contradictions[index].resolution = Some(Resolution {
+            verdict,
+            resolved_by: resolved_by.to_string(),
+            explanation,
+            resolved_at: Utc::now(),
+            discarded_text,
+        });
+        discarded_key
+    }
+
+    /// Stores a fact unless another key already holds the same content. Re-learning under an
+    /// existing key replaces that fact. Returns the key of the duplicate when the fact is skipped.
+    pub fn insert_fact(&mut self, key: String, fact: Fact) -> Option<String> {
+        let duplicate = self
+            .facts
+            .iter()
+            .find(|(existing_key, existing)| **existing_key != key && fact.is_duplicate_of(existing))
+            .map(|(existing_key, _)| existing_key.clone());
+        if duplicate.is_none() {
+            self.facts.insert(key, fact);
+        }
+        duplicate
+    }
+}
+
+/// `SCHEMA_UPGRADES[n]` turns knowledge of schema version `n` into version `n + 1`.
+const SCHEMA_UPGRADES: [fn(&mut Value); SCHEMA_VERSION as usize] = [upgrade_v0_to_v1];
+
+/// Version 0 stored facts as plain strings with their tags in a separate `fact_tags` map;
+/// later unversioned files had structured facts without confidence tracking.
+fn upgrade_v0_to_v1(knowledge: &mut Value) {
+    let Some(knowledge) = knowledge.as_object_mut() else {
+        return;
+    };
+    let fact_tags = knowledge.remove("fact_tags").unwrap_or(Value::Null);
+    let Some(facts) = knowledge.get_mut("facts").and_then(Value::as_object_mut) else {
+        return;
+    };
+
+    for (key, fact) in facts.iter_mut() {
+        if let Value::String(text) = fact {
+            *fact = serde_json::to_value(legacy_fact(key, text.clone())).unwrap_or(Value::Null);
+        }
+        let Some(fact) = fact.as_object_mut() else {
+            continue;
+        };
+
+        let has_tags = fact.get("tags").and_then(Value::as_array).is_some_and(|tags| !tags.is_empty());
+        if let Some(tags) = fact_tags.get(key).filter(|_| !has_tags) {
+            fact.insert("tags".to_string(), tags.clone());
+        }
+        if !fact.contains_key("confidence") {
+            let method = fact
+                .get("method")
+                .and_then(|method| serde_json::from_value::<LearnMethod>(method.clone()).ok())
+                .unwrap_or(LearnMethod::Unknown);
+            fact.insert("confidence".to_string(), method.initial_confidence().into());
+        }
+        if !fact.contains_key("verified_at") {
+            let learned_at = fact.get("learned_at").cloned().unwrap_or(Value::Null);
+            fact.insert("verified_at".to_string(), learned_at);
+        }
+    }
+}
+
+/// Legacy facts recorded their origin only in the key.
+fn legacy_fact(key: &str, text: String) -> Fact {
+    let mut fact = Fact::new(text, None, LearnMethod::Unknown, Vec::new());
+    fact.learned_at = DateTime::<Utc>::UNIX_EPOCH;
+    if let Some(url) = key.strip_prefix("personal_knowledge_") {
+        fact.method = LearnMethod::Url;
+        fact.source_url = Some(url.to_string());
+    } else if let Some(timestamp) = key.strip_prefix("trained_knowledge_") {
+        fact.method = LearnMethod::Training;
+        if let Some(learned_at) = timestamp.parse().ok().and_then(|ts| DateTime::from_timestamp(ts, 0)) {
+            fact.learned_at = learned_at;
+        }
+    } else if key == "self_understanding" {
+        fact.method = LearnMethod::WebSearch;
+    }
+    fact.verified_at = fact.learned_at;
+    fact.confidence = fact.method.initial_confidence();
+    fact
+}
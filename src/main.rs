@@ -1,34 +1,426 @@
-use reqwest;
+// The Matrix client's futures are too deeply nested for the default to prove them Send
+#![recursion_limit = "256"]
+
 use serde_json::{json, Value};
-use std::env;
-use std::collections::{VecDeque, HashMap};
-use std::sync::{Arc, RwLock};
+use std::collections::{HashMap, HashSet, VecDeque};
+use std::sync::{Arc, Mutex, RwLock};
 use std::fs;
-use std::path::Path;
+use std::path::{Path, PathBuf};
 use dotenv::dotenv;
-use scraper::{Html, Selector};
+use futures::StreamExt;
 use serde::{Deserialize, Serialize};
-use chrono;
+use tokio::sync::{mpsc, RwLock as AsyncRwLock};
 
-#[derive(Debug, Serialize, Deserialize)]
+#[macro_use]
+mod output;
+mod archive;
+mod auth;
+mod card;
+mod cli;
+mod config_format;
+mod daemon;
+mod discord;
+mod email;
+mod embedding;
+mod gateway;
+mod group;
+mod grpc;
+mod history;
+mod ingest;
+mod input;
+mod irc;
+mod jobs;
+mod knowledge;
+mod mastodon;
+mod matrix;
+mod mcp;
+mod mcp_client;
+mod paths;
+mod queue;
+mod reload;
+mod report;
+mod search;
+mod secrets;
+mod server;
+mod slack;
+mod stdio;
+mod storage;
+mod sync;
+mod telegram;
+mod vector_index;
+mod webhooks;
+mod whatsapp;
+mod xmpp;
+
+use archive::CharacterArchive;
+use auth::{ApiKeys, Auth, Scope, JWT_SECRET_NAME};
+use card::Card;
+use clap::Parser;
+use cli::{Cli, Command, OutputFormat};
+use discord::DiscordSettings;
+use email::EmailSettings;
+use embedding::{create_embedder, Embedder, EmbedderKind};
+use gateway::{split_message, Gateway, Limits};
+use history::KnowledgeHistory;
+use ingest::{
+    anilist_search_url, chunk_text, document_files, download_image, fandom_url, fetch_anilist_character, fetch_feed, fetch_reddit_thread, fetch_transcript, fetch_wikipedia, html_to_markdown, read_document, read_image, read_qa_pairs,
+    fetch_vndb_character, reddit_thread_id, render_page, same_site_links, sitemap_urls, split_sections, transcribe_image, vndb_search_url, wikipedia_url, youtube_video_id, BrowserSettings,
+    CookieJar, DomainFilter, FeedEntry, Infobox, LoreEntry, MediaWiki, RedditSettings, RobotsCache, SiteLogin, VndbSettings,
+};
+use irc::IrcSettings;
+use jobs::Scheduler;
+use knowledge::{Contradiction, Fact, FactTag, FeedState, FileState, Knowledge, LearnMethod, PageState, SectionState, Verdict};
+use mastodon::MastodonSettings;
+use matrix::MatrixSettings;
+use mcp_client::{gemini_schema, McpClient, McpServerSettings};
+use queue::{LearningQueue, MAX_ATTEMPTS};
+use report::LearnReport;
+use server::{ApiCall, ApiError, ApiResult, RateLimiter, ServerSettings};
+use search::{create_search_provider, SearchProvider, SearchProviderKind, SearchSettings};
+use storage::{compare_backends, create_codec, create_store, generate_key, CachedStore, import_into, migrate_from_json, FactQuery, KnowledgeStore, StorageBackend, StorageSettings, ENCRYPTION_KEY_NAME};
+use sync::{create_remote, Expect, SyncSettings, SyncState};
+use telegram::TelegramSettings;
+use vector_index::{create_vector_index, QdrantSettings, TagFilter, VectorIndex, VectorIndexKind};
+use webhooks::{EventKind, Webhook, Webhooks};
+use whatsapp::WhatsAppSettings;
+use xmpp::XmppSettings;
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
 struct CharacterConfig {
     name: String,
     personality: String,
     description: String,
     traits: Vec<String>,
     interests: Vec<String>,
+    /// Sample exchanges that show the model how the character talks.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    examples: Vec<Example>,
+    /// What the character says to open a chat that has no history yet.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    greeting: Option<String>,
 }
 
-#[derive(Debug, Serialize, Deserialize)]
+/// One few-shot example: a user message and the character's reply to it.
+#[derive(Debug, Serialize, Deserialize, Clone)]
+struct Example {
+    user: String,
+    reply: String,
+}
+
+/// Longer histories than this make every prompt long and slow; more is taken for a typo.
+const MAX_HISTORY_LIMIT: usize = 100;
+
+/// Few-shot examples beyond this many make every prompt longer for little gain.
+const MAX_USEFUL_EXAMPLES: usize = 10;
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
 struct KnowledgeSources {
-    self_learning_urls: Vec<String>,
+    self_learning_urls: Vec<LearningSource>,
     additional_context: String,
+    /// Directory of hand-written Markdown lorebook entries.
+    #[serde(default = "default_lore_dir")]
+    lore_dir: String,
+    /// RSS and Atom feeds whose new entries are learned from on every `learn`.
+    #[serde(default)]
+    feeds: Vec<String>,
+}
+
+fn default_lore_dir() -> String {
+    "lore".to_string()
+}
+
+/// A page to learn from: a plain URL, `{ "url": ..., "depth": 2 }` to also learn from
+/// the same-site pages it links to, up to `depth` links away, or
+/// `{ "fandom": "roshidere", "page": "Alisa Mikhailovna Kujou" }` for a Fandom article.
+#[derive(Debug, Serialize, Deserialize, Clone)]
+#[serde(untagged)]
+enum LearningSource {
+    Url(String),
+    Crawl {
+        url: String,
+        #[serde(default)]
+        depth: usize,
+        /// Most pages learned from in one crawl, the starting page included.
+        #[serde(default = "default_max_pages")]
+        max_pages: usize,
+    },
+    Fandom {
+        /// The wiki's name, as in `<name>.fandom.com`.
+        fandom: String,
+        /// The article's title.
+        page: String,
+        /// The language edition of the wiki, such as `ru`; the English one when not set.
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        language: Option<String>,
+    },
+    /// A background topic looked up on Wikipedia, such as a school or a city in the story:
+    /// the article's summary and the `sections` named.
+    Wikipedia {
+        wikipedia: String,
+        /// The Wikipedia edition, such as `ja`; the English one when not set.
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        language: Option<String>,
+        #[serde(default, skip_serializing_if = "Vec::is_empty")]
+        sections: Vec<String>,
+    },
+    /// A character's AniList entry, looked up by name: profile, description, appearances,
+    /// voice actors and the characters around them.
+    AniList { anilist: String },
+    /// A visual-novel character's VNDB entry, looked up by name: profile and measurements,
+    /// traits, and the visual novels they are in, as far as `learning.vndb` allows spoilers.
+    Vndb { vndb: String },
+}
+
+fn default_max_pages() -> usize {
+    20
+}
+
+/// The Wikipedia edition background topics are looked up in unless another is named.
+const DEFAULT_WIKIPEDIA: &str = "en";
+
+impl LearningSource {
+    fn url(&self) -> String {
+        match self {
+            LearningSource::Url(url) | LearningSource::Crawl { url, .. } => url.clone(),
+            LearningSource::Fandom { fandom, page, language } => {
+                fandom_url(fandom, page, language.as_deref()).unwrap_or_else(|_| format!("https://{}.fandom.com/wiki/{}", fandom, page))
+            }
+            LearningSource::Wikipedia { wikipedia, language, .. } => {
+                let language = language.as_deref().unwrap_or(DEFAULT_WIKIPEDIA);
+                wikipedia_url(language, wikipedia).unwrap_or_else(|_| format!("https://{}.wikipedia.org/wiki/{}", language, wikipedia))
+            }
+            LearningSource::AniList { anilist } => {
+                anilist_search_url(anilist).unwrap_or_else(|_| format!("https://anilist.co/search/characters?search={}", anilist))
+            }
+            LearningSource::Vndb { vndb } => vndb_search_url(vndb).unwrap_or_else(|_| format!("https://vndb.org/c?q={}", vndb)),
+        }
+    }
+}
+
+/// When learning runs on its own, set by `conversation_settings.learning_frequency`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum LearningFrequency {
+    /// Every time the chatbot starts.
+    Startup,
+    /// Once a day, at startup or while the chat is idle.
+    Daily,
+    /// Once a week, at startup or while the chat is idle.
+    Weekly,
+    /// Only with `learn`.
+    Manual,
+}
+
+impl LearningFrequency {
+    fn parse(s: &str) -> Option<LearningFrequency> {
+        match s.trim().to_lowercase().as_str() {
+            "startup" => Some(LearningFrequency::Startup),
+            "daily" => Some(LearningFrequency::Daily),
+            "weekly" => Some(LearningFrequency::Weekly),
+            "manual" | "never" => Some(LearningFrequency::Manual),
+            _ => None,
+        }
+    }
+
+    /// The schedule of the `learn` job, when learning runs on one.
+    fn schedule(&self) -> Option<&'static str> {
+        match self {
+            LearningFrequency::Daily => Some("every 1d"),
+            LearningFrequency::Weekly => Some("every 1w"),
+            LearningFrequency::Startup | LearningFrequency::Manual => None,
+        }
+    }
 }
 
 #[derive(Debug, Serialize, Deserialize)]
 struct ConversationSettings {
     max_history: usize,
+    /// "startup", "daily", "weekly" or "manual"; see `LearningFrequency`.
     learning_frequency: String,
+    /// Only facts carrying one of these tags are used in chat (empty = all).
+    #[serde(default)]
+    include_tags: Vec<FactTag>,
+    /// Facts carrying any of these tags are never used in chat.
+    #[serde(default)]
+    exclude_tags: Vec<FactTag>,
+    /// Append the sources of the facts behind factual replies.
+    #[serde(default)]
+    citations: bool,
+    /// How many of the newest feed entries from the last `RECENT_NEWS_DAYS` go into every
+    /// prompt, so recent developments can come up in chat.
+    #[serde(default = "default_recent_news")]
+    recent_news: usize,
+    /// Search the web in the middle of a chat when the learned knowledge does not answer
+    /// a question, and answer from what was found.
+    #[serde(default)]
+    live_search: bool,
+    /// Remember what people chatting tell about themselves, separately for each session.
+    #[serde(default = "default_user_memory")]
+    user_memory: bool,
+}
+
+fn default_user_memory() -> bool {
+    true
+}
+
+fn default_recent_news() -> usize {
+    3
+}
+
+/// Who settles contradictions between learned facts.
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Eq, Default)]
+#[serde(rename_all = "lowercase")]
+enum ContradictionPolicy {
+    /// Ask the model to pick the correct fact right away.
+    #[default]
+    Model,
+    /// Keep both facts and wait for a `resolve` command.
+    User,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+struct LearningSettings {
+    #[serde(default)]
+    contradiction_resolution: ContradictionPolicy,
+    /// Days after which the confidence in web-sourced facts has halved.
+    #[serde(default = "default_confidence_half_life_days")]
+    confidence_half_life_days: f64,
+    /// Facts whose confidence decayed below this are re-fetched on the next `learn`.
+    #[serde(default = "default_reverify_threshold")]
+    reverify_threshold: f64,
+    /// Pages longer than this many characters are processed in chunks and merged.
+    #[serde(default = "default_chunk_size")]
+    chunk_size: usize,
+    /// Characters each chunk repeats from the end of the previous one.
+    #[serde(default = "default_chunk_overlap")]
+    chunk_overlap: usize,
+    /// Days between checks of the learned pages for dead links during `learn`; 0 turns
+    /// the check off.
+    #[serde(default = "default_check_sources_days")]
+    check_sources_days: u32,
+    /// Hosts, such as your own sites, that are fetched without checking their robots.txt.
+    /// Subdomains are included.
+    #[serde(default)]
+    ignore_robots_txt: Vec<String>,
+    /// Sites that may and may not be learned from, whether added by hand or found.
+    #[serde(default)]
+    domains: DomainFilter,
+    /// Which pages are rendered in a headless browser so their JavaScript runs.
+    #[serde(default)]
+    browser: BrowserSettings,
+    /// Sites to log in to before learning from their pages.
+    #[serde(default)]
+    logins: Vec<SiteLogin>,
+    /// Caption languages to learn YouTube videos from, most preferred first. Videos
+    /// without captions in any of them are learned from in whatever language they have.
+    #[serde(default = "default_transcript_languages")]
+    transcript_languages: Vec<String>,
+    /// Which comments of Reddit threads are learned from.
+    #[serde(default)]
+    reddit: RedditSettings,
+    /// Read articles of MediaWiki sites through their API rather than their pages.
+    #[serde(default = "default_mediawiki_api")]
+    mediawiki_api: bool,
+    /// How many spoilers VNDB lookups store.
+    #[serde(default)]
+    vndb: VndbSettings,
+}
+
+fn default_confidence_half_life_days() -> f64 {
+    90.0
+}
+
+fn default_reverify_threshold() -> f64 {
+    0.5
+}
+
+fn default_check_sources_days() -> u32 {
+    7
+}
+
+fn default_chunk_size() -> usize {
+    20_000
+}
+
+fn default_chunk_overlap() -> usize {
+    1_000
+}
+
+fn default_mediawiki_api() -> bool {
+    true
+}
+
+fn default_transcript_languages() -> Vec<String> {
+    vec!["en".to_string()]
+}
+
+impl Default for LearningSettings {
+    fn default() -> Self {
+        LearningSettings {
+            contradiction_resolution: ContradictionPolicy::default(),
+            confidence_half_life_days: default_confidence_half_life_days(),
+            reverify_threshold: default_reverify_threshold(),
+            chunk_size: default_chunk_size(),
+            chunk_overlap: default_chunk_overlap(),
+            check_sources_days: default_check_sources_days(),
+            ignore_robots_txt: Vec::new(),
+            domains: DomainFilter::default(),
+            browser: BrowserSettings::default(),
+            logins: Vec::new(),
+            transcript_languages: default_transcript_languages(),
+            reddit: RedditSettings::default(),
+            mediawiki_api: default_mediawiki_api(),
+            vndb: VndbSettings::default(),
+        }
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+struct RetrievalSettings {
+    /// When disabled every allowed fact goes into every prompt.
+    #[serde(default = "default_retrieval_enabled")]
+    enabled: bool,
+    /// Maximum number of facts added to a prompt.
+    #[serde(default = "default_top_k")]
+    top_k: usize,
+    /// Minimum cosine similarity between a fact and the message.
+    #[serde(default = "default_similarity_threshold")]
+    similarity_threshold: f32,
+    #[serde(default)]
+    embedder: EmbedderKind,
+    /// Embedding model name; each embedder has its own default.
+    #[serde(default)]
+    embedding_model: Option<String>,
+    #[serde(default)]
+    vector_index: VectorIndexKind,
+    /// Connection settings used when `vector_index` is "qdrant".
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    qdrant: Option<QdrantSettings>,
+}
+
+fn default_retrieval_enabled() -> bool {
+    true
+}
+
+fn default_top_k() -> usize {
+    5
+}
+
+fn default_similarity_threshold() -> f32 {
+    0.3
+}
+
+impl Default for RetrievalSettings {
+    fn default() -> Self {
+        RetrievalSettings {
+            enabled: default_retrieval_enabled(),
+            top_k: default_top_k(),
+            similarity_threshold: default_similarity_threshold(),
+            embedder: EmbedderKind::default(),
+            embedding_model: None,
+            vector_index: VectorIndexKind::default(),
+            qdrant: None,
+        }
+    }
 }
 
 #[derive(Debug, Serialize, Deserialize)]
@@ -36,90 +428,630 @@ struct ChatbotConfig {
     character: CharacterConfig,
     knowledge_sources: KnowledgeSources,
     conversation_settings: ConversationSettings,
+    #[serde(default)]
+    learning: LearningSettings,
+    #[serde(default)]
+    retrieval: RetrievalSettings,
+    #[serde(default)]
+    storage: StorageSettings,
+    /// The search engine `learn` finds pages with.
+    #[serde(default)]
+    search: SearchSettings,
+    /// Remote copy of the knowledge kept in sync with `sync push` / `sync pull`.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    sync: Option<SyncSettings>,
+    /// Schedules of the background jobs in `JOBS`, by name, such as "every 6h" or a cron
+    /// expression; "off" turns a job off.
+    #[serde(default, skip_serializing_if = "HashMap::is_empty")]
+    jobs: HashMap<String, String>,
+    /// Rate limits of `alya serve`.
+    #[serde(default)]
+    server: ServerSettings,
+    /// URLs that are sent the chatbot's events.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    webhooks: Vec<Webhook>,
+    /// External MCP servers whose tools the model may use in the chat, by name.
+    #[serde(default, skip_serializing_if = "HashMap::is_empty")]
+    mcp_servers: HashMap<String, McpServerSettings>,
+    /// Who may manage `alya discord` with its slash commands.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    discord: Option<DiscordSettings>,
+    /// The chats `alya telegram` answers.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    telegram: Option<TelegramSettings>,
+    /// The homeserver of `alya matrix`.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    matrix: Option<MatrixSettings>,
+    /// The network and channels of `alya irc`.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    irc: Option<IrcSettings>,
+    /// The phone number of `alya whatsapp`.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    whatsapp: Option<WhatsAppSettings>,
+    /// The mailbox of `alya email`.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    email: Option<EmailSettings>,
+    /// The account and rooms of `alya xmpp`.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    xmpp: Option<XmppSettings>,
+    /// The account of `alya mastodon` and the `post` job.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    mastodon: Option<MastodonSettings>,
 }
 
-#[derive(Debug, Serialize, Deserialize, Clone)]
-struct Knowledge {
-    facts: HashMap<String, String>,
-    search_history: Vec<String>,
-    learned_urls: Vec<String>,
-    external_url_count: usize,
-    cached_content: HashMap<String, String>,
+impl ChatbotConfig {
+    fn save(&self) -> Result<(), Box<dyn std::error::Error>> {
+        self.save_to(paths::config_file())
+    }
+
+    /// Writes the configuration to `path`, changing only what differs from the file there, so
+    /// the environment's overrides are not saved with it.
+    fn save_to(&self, path: &Path) -> Result<(), Box<dyn std::error::Error>> {
+        config_format::write(path, self)
+    }
+
+    /// Finds the settings that would fail later or leave the bot silently broken, each as
+    /// the field, what is wrong and how to fix it. A character without a name is fine as
+    /// long as nothing else of it is set either: the chat sets it up.
+    fn problems(&self) -> Vec<String> {
+        let mut problems = Vec::new();
+        let character = &self.character;
+        if character.name.trim().is_empty() && !(character.personality.is_empty() && character.description.is_empty()) {
+            problems.push("character.name: is empty; give the character a name, or clear the character to set it up in the chat".to_string());
+        }
+        for (index, example) in character.examples.iter().enumerate() {
+            if example.user.trim().is_empty() || example.reply.trim().is_empty() {
+                problems.push(format!("character.examples[{}]: needs both a user message and a reply", index));
+            }
+        }
+
+        let settings = &self.conversation_settings;
+        if !(1..=MAX_HISTORY_LIMIT).contains(&settings.max_history) {
+            problems.push(format!(
+                "conversation_settings.max_history: is {}; use a number from 1 to {}, such as 5",
+                settings.max_history, MAX_HISTORY_LIMIT
+            ));
+        }
+        if LearningFrequency::parse(&settings.learning_frequency).is_none() {
+            problems.push(format!(
+                "conversation_settings.learning_frequency: \"{}\" is not known; use startup, daily, weekly or manual",
+                settings.learning_frequency
+            ));
+        }
+        if let Some(tag) = settings.include_tags.iter().find(|tag| settings.exclude_tags.contains(tag)) {
+            problems.push(format!("conversation_settings.exclude_tags: \"{}\" is included and excluded at once; remove it from one of them", tag.as_str()));
+        }
+
+        let mut urls: Vec<(String, &str)> = Vec::new();
+        for (index, source) in self.knowledge_sources.self_learning_urls.iter().enumerate() {
+            if let LearningSource::Url(url) | LearningSource::Crawl { url, .. } = source {
+                urls.push((format!("knowledge_sources.self_learning_urls[{}]", index), url));
+            }
+        }
+        for (index, feed) in self.knowledge_sources.feeds.iter().enumerate() {
+            urls.push((format!("knowledge_sources.feeds[{}]", index), feed));
+        }
+        for (index, webhook) in self.webhooks.iter().enumerate() {
+            urls.push((format!("webhooks[{}].url", index), &webhook.url));
+        }
+        for (name, server) in &self.mcp_servers {
+            if let Some(url) = &server.url {
+                urls.push((format!("mcp_servers.{}.url", name), url));
+            }
+        }
+        if let Some(url) = &self.search.searxng_url {
+            urls.push(("search.searxng_url".to_string(), url));
+        }
+        if let Some(qdrant) = &self.retrieval.qdrant {
+            urls.push(("retrieval.qdrant.url".to_string(), &qdrant.url));
+        }
+        if let Some(sync) = &self.sync {
+            urls.push(("sync.url".to_string(), &sync.url));
+        }
+        if let Some(matrix) = &self.matrix {
+            urls.push(("matrix.homeserver".to_string(), &matrix.homeserver));
+        }
+        if let Some(mastodon) = &self.mastodon {
+            urls.push(("mastodon.instance".to_string(), &mastodon.instance));
+        }
+        for (field, url) in urls {
+            match url::Url::parse(url.trim()) {
+                Ok(parsed) if matches!(parsed.scheme(), "http" | "https") && parsed.host().is_some() => {}
+                Ok(_) => problems.push(format!("{}: \"{}\" is not a web address; use an http:// or https:// URL", field, url)),
+                Err(url::ParseError::RelativeUrlWithoutBase) => {
+                    problems.push(format!("{}: \"{}\" has no scheme; write it as https://{}", field, url, url.trim()))
+                }
+                Err(e) => problems.push(format!("{}: \"{}\" is not a valid URL ({})", field, url, e)),
+            }
+        }
+
+        let learning = &self.learning;
+        if learning.chunk_size == 0 {
+            problems.push(format!("learning.chunk_size: must be more than 0, such as {}", default_chunk_size()));
+        } else if learning.chunk_overlap >= learning.chunk_size {
+            problems.push(format!(
+                "learning.chunk_overlap: is {}, not less than chunk_size ({}); make it a small part of it, such as {}",
+                learning.chunk_overlap,
+                learning.chunk_size,
+                learning.chunk_size / 20
+            ));
+        }
+        if learning.confidence_half_life_days <= 0.0 {
+            problems.push(format!("learning.confidence_half_life_days: must be more than 0, such as {}", default_confidence_half_life_days()));
+        }
+        if !(0.0..=1.0).contains(&learning.reverify_threshold) {
+            problems.push(format!("learning.reverify_threshold: is {}; use a number from 0 to 1, such as {}", learning.reverify_threshold, default_reverify_threshold()));
+        }
+        if self.retrieval.top_k == 0 {
+            problems.push(format!("retrieval.top_k: must be at least 1, such as {}; set retrieval.enabled to false to use every fact", default_top_k()));
+        }
+        if !(-1.0..=1.0).contains(&self.retrieval.similarity_threshold) {
+            problems.push(format!(
+                "retrieval.similarity_threshold: is {}; use a number from -1 to 1, such as {}",
+                self.retrieval.similarity_threshold,
+                default_similarity_threshold()
+            ));
+        }
+        problems
+    }
+
+    /// Fails with every problem of the configuration read from `path`, one per line.
+    fn validate(&self, path: &Path) -> Result<(), Box<dyn std::error::Error>> {
+        let problems = self.problems();
+        if problems.is_empty() {
+            return Ok(());
+        }
+        let mut message = format!("{} has {} problem(s):", path.display(), problems.len());
+        for problem in problems {
+            message.push_str("\n  ");
+            message.push_str(&problem);
+        }
+        Err(message.into())
+    }
+}
+
+const CITATION_MARKER: &str = "SOURCES:";
+
+/// Starts the reply the model gives instead of an answer when it wants to search first.
+const SEARCH_MARKER: &str = "SEARCH:";
+
+/// Starts a line of the reply with something to remember about the user.
+const MEMORY_MARKER: &str = "REMEMBER:";
+
+/// Session used by the interactive chat loop.
+const DEFAULT_SESSION: &str = "default";
+/// How many times the model may call tools of MCP servers before it has to answer.
+const MAX_TOOL_ROUNDS: usize = 5;
+/// The session of `alya mcp`.
+const MCP_SESSION: &str = "mcp";
+
+/// Where knowledge snapshots for `knowledge history` / `knowledge rollback` are kept.
+const HISTORY_DIR: &str = "history";
+
+/// With lazy loading, a snapshot has to read every fact back from the store, so one is
+/// only taken every this many saves.
+const LAZY_SNAPSHOT_EVERY: usize = 10;
+
+/// Pause between two pages of a crawl or refresh.
+const CRAWL_DELAY: std::time::Duration = std::time::Duration::from_secs(1);
+
+/// Searches ask for this many times the results learned from, so that results dropped by
+/// `search.rules` can be made up for.
+const SEARCH_OVERFETCH: usize = 3;
+
+/// Feed entries learned within this many days count as recent news.
+const RECENT_NEWS_DAYS: i64 = 30;
+
+/// The background jobs that can be scheduled, with what they do.
+const JOBS: [(&str, &str); 6] = [
+    ("learn", "searches and learns about the character, then refreshes changed pages"),
+    ("refresh", "learns again from learned pages that changed"),
+    ("feeds", "checks the subscribed feeds for new entries"),
+    ("backup", "saves an archive of the character and its knowledge in data/backups"),
+    ("consolidate", "removes facts that repeat another fact"),
+    ("post", "posts an in-character status on Mastodon"),
+];
+/// Model requests a typical page costs when learning from it: rewriting, tagging and
+/// checking it against the known facts. Only used to estimate the cost of `learn`.
+const REQUESTS_PER_PAGE: usize = 3;
+/// Starts the learning queue ID of a configured source, followed by its URL.
+const SOURCE_STEP: &str = "source:";
+/// How long the daemon waits for an idle client before hanging up on it.
+const CLIENT_TIMEOUT: std::time::Duration = std::time::Duration::from_secs(30);
+const BACKUP_DIR: &str = "backups";
+/// Older backups than the newest this many are deleted.
+const BACKUPS_KEPT: usize = 7;
+
+/// Feeds are not checked again within this time, however often `learn` runs.
+const FEED_CHECK_INTERVAL: chrono::Duration = chrono::Duration::hours(1);
+
+/// Entries learned from one feed per check; a feed checked for the first time only has
+/// its newest entries learned, the older ones are passed over.
+const MAX_FEED_ENTRIES: usize = 10;
+const FIRST_FEED_ENTRIES: usize = 3;
+
+/// Entry IDs remembered per feed to tell new entries from seen ones.
+const MAX_SEEN_ENTRIES: usize = 500;
+
+/// Feed entries with less text than this are teasers; the linked article is fetched.
+const MIN_ENTRY_TEXT: usize = 500;
+/// How many characters of facts the knowledge summary of an exported card is written from.
+const SUMMARY_INPUT: usize = 30_000;
+/// The largest character card or configuration `character install` downloads.
+const MAX_CHARACTER_DOWNLOAD: usize = 20 * 1024 * 1024;
+/// How many times each character of a group conversation speaks, unless the user joins in.
+const GROUP_ROUNDS: usize = 3;
+/// The directory in `data/` group conversations are written down in.
+const TRANSCRIPT_DIR: &str = "transcripts";
+/// The config sections a running chat takes up when the file changes; the others need a
+/// restart.
+const LIVE_SETTINGS: [&str; 6] = ["character", "knowledge_sources", "conversation_settings", "learning", "search", "jobs"];
+
+/// The character's reply to a chat message, with what making it took.
+#[derive(Debug, Serialize, Deserialize)]
+struct Reply {
+    text: String,
+    /// The source URLs the reply cites.
+    sources: Vec<String>,
+    /// The facts retrieved for the message and given to the model.
+    fact_keys: Vec<String>,
+    model_requests: usize,
+    tokens: u64,
+    latency_ms: u128,
+}
+
+/// A fetched web page, with the validators to fetch it conditionally next time.
+struct FetchedPage {
+    html: String,
+    /// The text to learn from when the source is not an HTML page, such as the
+    /// transcript of a video.
+    text: Option<String>,
+    /// The infobox of a wiki article, learned as written besides the article.
+    infobox: Option<Infobox>,
+    etag: Option<String>,
+    last_modified: Option<String>,
+}
+
+impl FetchedPage {
+    /// The content to learn from, as Markdown.
+    fn content(&self) -> String {
+        self.text.clone().unwrap_or_else(|| html_to_markdown(&self.html))
+    }
+}
+
+enum Fetched {
+    Page(FetchedPage),
+    /// The server answered 304 Not Modified.
+    NotModified,
+    /// The server answered with this error status.
+    Failed(u16),
+    /// Disallowed by the domain filter or robots.txt.
+    Skipped,
+}
+
+/// Statuses that mean a page is gone for good rather than temporarily unavailable.
+fn is_gone(status: u16) -> bool {
+    matches!(status, 404 | 410)
 }
 
 struct Chatbot {
     config: ChatbotConfig,
-    conversation_history: VecDeque<String>,
+    /// The conversations in progress, by session: the last `max_history` messages of each.
+    conversations: HashMap<String, VecDeque<String>>,
     knowledge: Arc<RwLock<Knowledge>>,
+    store: Box<dyn KnowledgeStore>,
+    history: KnowledgeHistory,
+    /// Saves since the last snapshot, for `LAZY_SNAPSHOT_EVERY`.
+    saves_since_snapshot: std::sync::atomic::AtomicUsize,
+    embedder: Box<dyn Embedder>,
+    vector_index: AsyncRwLock<Box<dyn VectorIndex>>,
+    /// The facts the vector index holds as far as this run knows, kept current as facts are
+    /// indexed and removed; the index itself is only asked when the knowledge changed
+    /// otherwise, as on startup or a rollback, since asking Qdrant means reading it all.
+    indexed: Mutex<Option<HashSet<String>>>,
+    robots: RobotsCache,
+    wikis: MediaWiki,
+    search: Box<dyn SearchProvider>,
+    cookies: Mutex<CookieJar>,
+    /// Sites whose login failed during this run.
+    failed_logins: Mutex<HashSet<String>>,
+    scheduler: Mutex<Scheduler>,
+    /// What the current or last learning run did.
+    report: Mutex<LearnReport>,
+    queue: Mutex<LearningQueue>,
+    webhooks: Webhooks,
+    /// The MCP servers of the config, connected to when the chat first needs them.
+    mcp_clients: tokio::sync::OnceCell<Vec<McpClient>>,
 }
 
 impl Chatbot {
-    fn new(config: ChatbotConfig) -> Self {
-        Chatbot {
+    async fn new(config: ChatbotConfig) -> Result<Self, Box<dyn std::error::Error>> {
+        let mut store = create_store(&config.storage, &config.character.name).await?;
+        if config.storage.lazy_loading {
+            store = Box::new(CachedStore::new(store, config.storage.fact_cache_size));
+        }
+        let embedder = create_embedder(config.retrieval.embedder, config.retrieval.embedding_model.as_deref());
+        let vector_index = match (config.retrieval.vector_index, store.vector_index(embedder.model())) {
+            (VectorIndexKind::Postgres, Some(index)) => index,
+            (kind, _) => create_vector_index(
+                kind,
+                config.retrieval.qdrant.as_ref(),
+                &config.character.name,
+                embedder.model(),
+            ),
+        };
+        let conversations = HashMap::from([(DEFAULT_SESSION.to_string(), store.load_session(DEFAULT_SESSION).await?.into_iter().collect())]);
+        let cookies = CookieJar::load(paths::data_dir()).unwrap_or_else(|e| {
+            status!("Could not read the saved cookies, logins will be repeated: {}", e);
+            CookieJar::empty(paths::data_dir())
+        });
+        Ok(Chatbot {
+            embedder,
+            vector_index: AsyncRwLock::new(vector_index),
+            indexed: Mutex::new(None),
+            store,
+            history: knowledge_history(&config)?,
+            saves_since_snapshot: std::sync::atomic::AtomicUsize::new(0),
+            robots: RobotsCache::new(&config.learning.ignore_robots_txt),
+            wikis: MediaWiki::new(),
+            search: create_search_provider(&config.search),
+            cookies: Mutex::new(cookies),
+            failed_logins: Mutex::new(HashSet::new()),
+            scheduler: Mutex::new(build_scheduler(&config)?),
+            report: Mutex::new(LearnReport::new()),
+            queue: Mutex::new(LearningQueue::load(paths::data_dir())?),
+            webhooks: Webhooks::new(config.webhooks.clone(), &config.character.name),
+            mcp_clients: tokio::sync::OnceCell::new(),
             config,
-            conversation_history: VecDeque::new(),
-            knowledge: Arc::new(RwLock::new(Knowledge {
-                facts: HashMap::new(),
-                search_history: Vec::new(),
-                learned_urls: Vec::new(),
-                external_url_count: 0,
-                cached_content: HashMap::new(),
-            })),
+            conversations,
+            knowledge: Arc::new(RwLock::new(Knowledge::default())),
+        })
+    }
+
+    /// Loads a session's conversation, unless it is in progress already.
+    async fn open_session(&mut self, session: &str) -> Result<(), Box<dyn std::error::Error>> {
+        if !self.conversations.contains_key(session) {
+            let history = self.store.load_session(session).await?.into_iter().collect();
+            self.conversations.insert(session.to_string(), history);
         }
+        Ok(())
+    }
+
+    /// The messages of a session's conversation, oldest first.
+    fn conversation(&self, session: &str) -> impl Iterator<Item = &String> {
+        self.conversations.get(session).into_iter().flatten()
     }
 
-    fn add_to_history(&mut self, message: &str) {
-        if self.conversation_history.len() >= self.config.conversation_settings.max_history {
-            self.conversation_history.pop_front();
+    fn add_to_history(&mut self, session: &str, message: &str) {
+        let max_history = self.config.conversation_settings.max_history;
+        let history = self.conversations.entry(session.to_string()).or_default();
+        if history.len() >= max_history {
+            history.pop_front();
         }
-        self.conversation_history.push_back(message.to_string());
+        history.push_back(message.to_string());
     }
 
-    fn load_knowledge(&self) -> Result<(), Box<dyn std::error::Error>> {
-        let knowledge_path = Path::new("data/learned_knowledge.json");
-        if knowledge_path.exists() {
-            let knowledge_str = fs::read_to_string(knowledge_path)?;
-            let loaded_knowledge: Knowledge = serde_json::from_str(&knowledge_str)?;
-            
+    async fn load_knowledge(&self) -> Result<(), Box<dyn std::error::Error>> {
+        let lazy = self.config.storage.lazy_loading;
+        let loaded = if lazy { self.store.load_metadata().await? } else { self.store.load().await? };
+        if let Some(loaded_knowledge) = loaded {
             if let Ok(mut current_knowledge) = self.knowledge.write() {
-                current_knowledge.merge(loaded_knowledge);
+                // Merging compares every fact with every other one, which is what lazy
+                // loading is meant to avoid on startup
+                if lazy && current_knowledge.facts.is_empty() {
+                    *current_knowledge = loaded_knowledge;
+                } else {
+                    current_knowledge.merge(loaded_knowledge);
+                }
             }
         }
         Ok(())
     }
 
-    fn save_knowledge(&self) -> Result<(), Box<dyn std::error::Error>> {
-        if let Ok(knowledge) = self.knowledge.read() {
-            let knowledge_str = serde_json::to_string_pretty(&*knowledge)?;
-            
-            // Ensure the data directory exists
-            fs::create_dir_all("data")?;
-            fs::write("data/learned_knowledge.json", knowledge_str)?;
-            println!("Knowledge saved successfully");
+    /// The facts under `keys` with their text and embedding, fetching the ones loaded as
+    /// stubs from the store. Unknown keys are left out.
+    async fn load_facts(&self, keys: &[String]) -> Result<HashMap<String, Fact>, Box<dyn std::error::Error>> {
+        let (mut facts, stubs) = {
+            let knowledge = self.knowledge.read().unwrap();
+            let mut facts = HashMap::new();
+            let mut stubs = Vec::new();
+            for key in keys {
+                match knowledge.facts.get(key) {
+                    Some(fact) if fact.is_stub() => stubs.push(key.clone()),
+                    Some(fact) => {
+                        facts.insert(key.clone(), fact.clone());
+                    }
+                    None => {}
+                }
+            }
+            (facts, stubs)
+        };
+        for key in stubs {
+            if let Some(fact) = self.store.load_fact(&key).await? {
+                facts.insert(key, fact);
+            }
+        }
+        Ok(facts)
+    }
+
+    /// The knowledge with every fact complete, as needed for snapshots and exports.
+    async fn full_knowledge(&self) -> Result<Knowledge, Box<dyn std::error::Error>> {
+        let mut snapshot = self.knowledge.read().unwrap().clone();
+        if !snapshot.facts.values().any(Fact::is_stub) {
+            return Ok(snapshot);
+        }
+        let mut stored = self.store.load().await?.unwrap_or_default().facts;
+        for (key, fact) in snapshot.facts.iter_mut().filter(|(_, fact)| fact.is_stub()) {
+            if let Some(full) = stored.remove(key) {
+                *fact = full;
+            }
         }
+        Ok(snapshot)
+    }
+
+    /// Whether a save of `knowledge` is also kept as a snapshot: never with the history off,
+    /// every `LAZY_SNAPSHOT_EVERY` saves while facts are stubs, and on every save otherwise.
+    fn snapshot_due(&self, knowledge: &Knowledge) -> bool {
+        use std::sync::atomic::Ordering;
+        if !self.history.is_enabled() {
+            return false;
+        }
+        if !knowledge.facts.values().any(Fact::is_stub) {
+            return true;
+        }
+        let saves = self.saves_since_snapshot.fetch_add(1, Ordering::Relaxed) + 1;
+        if saves < LAZY_SNAPSHOT_EVERY {
+            return false;
+        }
+        self.saves_since_snapshot.store(0, Ordering::Relaxed);
+        true
+    }
+
+    async fn save_knowledge(&self) -> Result<(), Box<dyn std::error::Error>> {
+        // Snapshot first; the lock cannot be held while the store awaits
+        let snapshot = self.knowledge.read().unwrap().clone();
+        self.store.save(&snapshot).await?;
+        if self.snapshot_due(&snapshot) {
+            let recorded = self
+                .full_knowledge()
+                .await
+                .and_then(|knowledge| self.history.record(&knowledge, None));
+            if let Err(e) = recorded {
+                status!("Error recording knowledge history: {}", e);
+            }
+        }
+        status!("Knowledge saved successfully");
+        Ok(())
+    }
+
+    /// Replaces the knowledge with snapshot `id`. The rollback is itself recorded, so it
+    /// can be undone the same way.
+    async fn rollback_knowledge(&self, id: u64) -> Result<(), Box<dyn std::error::Error>> {
+        let snapshot = self.history.load(id)?;
+        self.store.save(&snapshot).await?;
+        self.history.record(&snapshot, Some(format!("rollback to #{}", id)))?;
+        *self.knowledge.write().unwrap() = snapshot;
         Ok(())
     }
 
+    async fn save_session(&self, session: &str) -> Result<(), Box<dyn std::error::Error>> {
+        let history: Vec<String> = self.conversation(session).cloned().collect();
+        self.store.save_session(session, &history).await
+    }
+
+    /// Rewrites raw content in the character's voice. Content over `chunk_size` is
+    /// processed in overlapping chunks whose results are then merged into one text.
     async fn process_with_ai(&self, content: &str) -> Result<String, Box<dyn std::error::Error>> {
+        let settings = &self.config.learning;
+        let chunks = chunk_text(content, settings.chunk_size, settings.chunk_overlap);
+        if chunks.len() > 1 {
+            status!("Content is long, processing it in {} chunks...", chunks.len());
+        }
+
+        let mut parts = Vec::new();
+        for chunk in &chunks {
+            // Prepare the prompt for Gemini
+            let prompt = format!(
+                "You are {}. Process this raw information about you and rewrite it in first person perspective, \
+                removing any HTML, scripts, or irrelevant content. Focus only on your personality, background, relationships, and characteristics. \
+                Keep details from headings, tables and lists attached to the people and things they describe. Make it natural and personal:\n\n{}",
+                self.config.character.name, chunk
+            );
+            // Chunks processed before a run was cut short are not sent again
+            let cached = self.queue.lock().unwrap().processed(chunk);
+            let part = match cached {
+                Some(part) => part,
+                None => {
+                    let part = self.generate(&prompt).await?;
+                    self.queue.lock().unwrap().remember(chunk, &part)?;
+                    part
+                }
+            };
+            if !part.trim().is_empty() {
+                parts.push(part);
+            }
+        }
+
+        self.consolidate(parts).await
+    }
+
+    /// Rewrites a page in the character's voice one run of sections at a time, reusing what
+    /// was written for the runs unchanged since `previous` so that only new and changed
+    /// sections reach the model. Returns the text and the runs to remember for next time.
+    async fn process_sections(
+        &self,
+        content: &str,
+        previous: &[SectionState],
+    ) -> Result<(String, Vec<SectionState>), Box<dyn std::error::Error>> {
+        let runs = split_sections(content, self.config.learning.chunk_size);
+        let mut sections = Vec::new();
+        let mut reused = 0;
+        for run in &runs {
+            let hash = SectionState::hash(run);
+            let processed = match previous.iter().find(|known| known.hash == hash) {
+                Some(known) => {
+                    reused += 1;
+                    known.processed.clone()
+                }
+                None => self.process_with_ai(run).await?,
+            };
+            sections.push(SectionState { hash, processed });
+        }
+        if reused > 0 {
+            status!("{} of {} part(s) of the page are unchanged, processed only the rest", reused, runs.len());
+        }
+        let parts: Vec<&str> = sections.iter().map(|section| section.processed.trim()).filter(|text| !text.is_empty()).collect();
+        Ok((parts.join("\n\n"), sections))
+    }
+
+    /// Merges texts written from consecutive chunks of one source, a few at a time so that
+    /// every merge prompt stays within `chunk_size`, until a single text is left.
+    async fn consolidate(&self, mut parts: Vec<String>) -> Result<String, Box<dyn std::error::Error>> {
+        while parts.len() > 1 {
+            let mut merged = Vec::new();
+            let mut remaining = parts.into_iter().peekable();
+            while let Some(first) = remaining.next() {
+                let mut group = vec![first];
+                let mut length = group[0].chars().count();
+                while let Some(next) = remaining.next_if(|next| group.len() < 2 || length + next.chars().count() <= self.config.learning.chunk_size) {
+                    length += next.chars().count();
+                    group.push(next);
+                }
+                if group.len() == 1 {
+                    merged.extend(group);
+                    continue;
+                }
+
+                status!("Merging {} processed chunks...", group.len());
+                let prompt = format!(
+                    "You are {}. The following notes about you were written from consecutive parts of the same source \
+                    and overlap in places. Merge them into one natural first-person text that keeps every distinct detail exactly once:\n\n{}",
+                    self.config.character.name,
+                    group.join("\n\n---\n\n")
+                );
+                let text = self.generate(&prompt).await?;
+                // Keep the unmerged notes rather than losing them if the merge came back empty
+                match text.trim().is_empty() {
+                    true => merged.push(group.join("\n\n")),
+                    false => merged.push(text),
+                }
+            }
+            parts = merged;
+        }
+        Ok(parts.pop().unwrap_or_default())
+    }
+
+    async fn generate(&self, prompt: &str) -> Result<String, Box<dyn std::error::Error>> {
         let client = reqwest::Client::builder()
             .timeout(std::time::Duration::from_secs(30))
             .build()?;
-            
-        // Prepare the prompt for Gemini
-        let prompt = format!(
-            "You are Alisa Mikhailovna Kujou. Process this raw information about you and rewrite it in first person perspective, \
-            removing any HTML, scripts, or irrelevant content. Focus only on your personality, background, relationships, and characteristics. \
-            Make it natural and personal:\n\n{}", 
-            content
-        );
 
         // Call Gemini API
-        let api_key = env::var("GEMINI_API_KEY").expect("GEMINI_API_KEY not set");
+        let api_key = secrets::require("GEMINI_API_KEY")?;
         let response = client
-            .post(format!(
-                "https://generativelanguage.googleapis.com/v1beta/models/gemini-2.0-flash:generateContent?key={}",
-                api_key
-            ))
+            .post("https://generativelanguage.googleapis.com/v1beta/models/gemini-2.0-flash:generateContent")
+            // In a header rather than the URL, which ends up in error messages shown to API clients
+            .header("x-goog-api-key", api_key)
             .json(&json!({
                 "contents": [{
                     "parts": [{
@@ -131,7 +1063,12 @@ impl Chatbot {
             .await?;
 
         let response_json: Value = response.json().await?;
-        
+        {
+            let mut report = self.report.lock().unwrap();
+            report.model_requests += 1;
+            report.tokens += response_json["usageMetadata"]["totalTokenCount"].as_u64().unwrap_or_default();
+        }
+
         // Extract the processed content
         if let Some(candidates) = response_json.get("candidates") {
             if let Some(first_candidate) = candidates[0].as_object() {
@@ -146,376 +1083,4831 @@ impl Chatbot {
                 }
             }
         }
-        
+
         Ok("".to_string())
     }
 
-    async fn learn_from_url(&self, url: &str) -> Result<(), Box<dyn std::error::Error>> {
-        let knowledge = self.knowledge.write().unwrap();
-        
-        // Check if we've already learned from this URL
-        if knowledge.learned_urls.contains(&url.to_string()) {
-            println!("Already learned from URL: {}", url);
-            return Ok(());
+    /// The connected MCP servers. Servers that fail to start are left out.
+    async fn mcp_clients(&self) -> &[McpClient] {
+        self.mcp_clients
+            .get_or_init(|| async {
+                let mut names: Vec<&String> = self.config.mcp_servers.keys().collect();
+                names.sort();
+                let mut clients = Vec::new();
+                for name in names {
+                    match McpClient::connect(name, &self.config.mcp_servers[name]).await {
+                        Ok(client) => {
+                            status!("Connected to the MCP server {} with {} tool(s)", name, client.tools.len());
+                            clients.push(client);
+                        }
+                        Err(e) => status!("Could not connect to the MCP server {}: {}", name, e),
+                    }
+                }
+                clients
+            })
+            .await
+    }
+
+    /// Like `generate`, but lets the model call the tools of the MCP servers, for up to
+    /// `MAX_TOOL_ROUNDS` rounds, before it answers.
+    async fn generate_with_tools(&self, prompt: &str, clients: &[McpClient]) -> Result<String, Box<dyn std::error::Error>> {
+        let mut tools = HashMap::new();
+        let mut declarations = Vec::new();
+        for client in clients {
+            for tool in &client.tools {
+                // Gemini allows letters, digits, '_', '.' and '-', up to 64 of them
+                let name: String = format!("{}_{}", client.name, tool.name)
+                    .chars()
+                    .map(|c| if c.is_ascii_alphanumeric() || "_.-".contains(c) { c } else { '_' })
+                    .take(64)
+                    .collect();
+                let mut declaration = json!({ "name": name, "description": tool.description });
+                let parameters = gemini_schema(&tool.input_schema);
+                if parameters.get("properties").is_some() {
+                    declaration["parameters"] = parameters;
+                }
+                declarations.push(declaration);
+                tools.insert(name, (client, tool.name.as_str()));
+            }
         }
 
-        println!("Fetching content from URL: {}", url);
         let client = reqwest::Client::builder()
-            .timeout(std::time::Duration::from_secs(10))
+            .timeout(std::time::Duration::from_secs(30))
             .build()?;
-        
-        let response = client
-            .get(url)
-            .header("User-Agent", "Mozilla/5.0 (Windows NT 10.0; Win64; x64) AppleWebKit/537.36 (KHTML, like Gecko) Chrome/91.0.4472.124 Safari/537.36")
-            .header("Accept", "text/html,application/xhtml+xml,application/xml;q=0.9,image/webp,*/*;q=0.8")
-            .header("Accept-Language", "en-US,en;q=0.5")
+        let api_key = secrets::require("GEMINI_API_KEY")?;
+        let mut contents = vec![json!({ "role": "user", "parts": [{ "text": prompt }] })];
+        for round in 0..=MAX_TOOL_ROUNDS {
+            let mut request = json!({ "contents": contents, "tools": [{ "functionDeclarations": declarations }] });
+            if round == MAX_TOOL_ROUNDS {
+                request["toolConfig"] = json!({ "functionCallingConfig": { "mode": "NONE" } });
+            }
+            let response: Value = client
+                .post("https://generativelanguage.googleapis.com/v1beta/models/gemini-2.0-flash:generateContent")
+                .header("x-goog-api-key", &api_key)
+                .json(&request)
+                .send()
+                .await?
+                .json()
+                .await?;
+            {
+                let mut report = self.report.lock().unwrap();
+                report.model_requests += 1;
+                report.tokens += response["usageMetadata"]["totalTokenCount"].as_u64().unwrap_or_default();
+            }
+
+            let content = &response["candidates"][0]["content"];
+            let parts = content["parts"].as_array().cloned().unwrap_or_default();
+            let calls: Vec<&Value> = parts.iter().filter_map(|part| part.get("functionCall")).collect();
+            if calls.is_empty() {
+                return Ok(parts.iter().filter_map(|part| part["text"].as_str()).collect());
+            }
+            let mut results = Vec::new();
+            for call in calls {
+                let name = call["name"].as_str().unwrap_or_default();
+                let result = match tools.get(name) {
+                    Some((client, tool)) => {
+                        status!("Using {}...", name);
+                        client.call_tool(tool, call["args"].clone()).await.unwrap_or_else(|e| format!("Error: {}", e))
+                    }
+                    None => format!("Error: there is no tool {}", name),
+                };
+                results.push(json!({ "functionResponse": { "name": name, "response": { "result": result } } }));
+            }
+            contents.push(content.clone());
+            contents.push(json!({ "role": "user", "parts": results }));
+        }
+        Ok(String::new())
+    }
+
+    /// Like `generate`, but hands the text to `on_text` piece by piece as the model writes it.
+    async fn generate_streaming(&self, prompt: &str, on_text: &mut dyn FnMut(&str)) -> Result<String, Box<dyn std::error::Error>> {
+        let client = reqwest::Client::builder()
+            .timeout(std::time::Duration::from_secs(60))
+            .build()?;
+        let api_key = secrets::require("GEMINI_API_KEY")?;
+        let mut response = client
+            .post("https://generativelanguage.googleapis.com/v1beta/models/gemini-2.0-flash:streamGenerateContent?alt=sse")
+            .header("x-goog-api-key", api_key)
+            .json(&json!({ "contents": [{ "parts": [{ "text": prompt }] }] }))
             .send()
-            .await?;
-            
-        if !response.status().is_success() {
-            println!("Failed to fetch URL: {} (Status: {})", url, response.status());
+            .await?
+            .error_for_status()?;
+
+        // Server-sent events, one `data:` line of JSON per piece; the last has the usage
+        let (mut buffer, mut text, mut tokens) = (Vec::new(), String::new(), 0);
+        while let Some(bytes) = response.chunk().await? {
+            buffer.extend_from_slice(&bytes);
+            while let Some(end) = buffer.iter().position(|&byte| byte == b'\n') {
+                let line: Vec<u8> = buffer.drain(..=end).collect();
+                let Some(data) = String::from_utf8_lossy(&line).trim().strip_prefix("data:").map(str::to_string) else {
+                    continue;
+                };
+                let event: Value = serde_json::from_str(&data)?;
+                if let Some(piece) = event["candidates"][0]["content"]["parts"][0]["text"].as_str() {
+                    text.push_str(piece);
+                    on_text(piece);
+                }
+                tokens = event["usageMetadata"]["totalTokenCount"].as_u64().unwrap_or(tokens);
+            }
+        }
+        let mut report = self.report.lock().unwrap();
+        report.model_requests += 1;
+        report.tokens += tokens;
+        Ok(text)
+    }
+
+    /// Embeds facts that have no embedding yet, or one from a different model.
+    async fn embed_missing_facts(&self) -> Result<(), Box<dyn std::error::Error>> {
+        let model = self.embedder.model();
+        let missing: Vec<String> = {
+            let knowledge = self.knowledge.read().unwrap();
+            knowledge
+                .facts
+                .iter()
+                .filter(|(_, fact)| !fact.is_embedded_with(model))
+                .map(|(key, _)| key.clone())
+                .collect()
+        };
+        if missing.is_empty() {
             return Ok(());
         }
-        
-        println!("Successfully fetched URL, parsing content...");
-        let webpage = response.text().await?;
-        let document = Html::parse_document(&webpage);
-        
-        // Try different selectors to get content
-        let selectors = [
-            "p",           // Paragraphs
-            "article",     // Article content
-            ".content",    // Content class
-            ".article",    // Article class
-            "main",        // Main content
-            "#content",    // Content ID
-            ".wiki-content", // Wiki content
-            ".mw-parser-output", // MediaWiki content
-            ".character-info", // Character info
-            ".profile-content", // Profile content
-        ];
-        
-        let mut content = String::new();
-        for selector_str in selectors.iter() {
-            if let Ok(selector) = Selector::parse(selector_str) {
-                for element in document.select(&selector) {
-                    let text = element.text().collect::<Vec<_>>().join(" ");
-                    if !text.trim().is_empty() {
-                        content.push_str(&text);
-                        content.push_str("\n\n");
+        let mut facts = self.load_facts(&missing).await?;
+        let (keys, texts): (Vec<(String, Vec<FactTag>)>, Vec<String>) = facts
+            .iter()
+            .map(|(key, fact)| ((key.clone(), fact.tags.clone()), fact.text.clone()))
+            .unzip();
+
+        status!("Indexing {} fact(s) for retrieval...", keys.len());
+        let embeddings = self.embedder.embed_batch(&texts).await?;
+        let mut fetched = Vec::new();
+        {
+            let mut knowledge = self.knowledge.write().unwrap();
+            for ((key, _), embedding) in keys.iter().zip(&embeddings) {
+                let Some(fact) = knowledge.facts.get_mut(key) else {
+                    continue;
+                };
+                fact.embedding_model = Some(model.to_string());
+                match fact.stub.as_mut() {
+                    // Stubs stay stubs; the embedding goes straight to the store
+                    Some(stub) => {
+                        stub.embedded = true;
+                        if let Some(mut full) = facts.remove(key) {
+                            full.embedding = Some(embedding.clone());
+                            full.embedding_model = Some(model.to_string());
+                            fetched.push((key.clone(), full));
+                        }
                     }
+                    None => fact.embedding = Some(embedding.clone()),
                 }
             }
         }
-        
-        if content.trim().is_empty() {
-            println!("No content found at URL: {}", url);
-            return Ok(());
+        for (key, fact) in &fetched {
+            self.store.save_fact(key, fact).await?;
         }
-        
-        // Process content with AI before saving
-        println!("Processing content with AI...");
-        drop(knowledge); // Release the lock while processing
-        let processed_content = self.process_with_ai(&content).await?;
-        let mut knowledge = self.knowledge.write().unwrap();
-        
-        if !processed_content.is_empty() {
-            println!("Successfully processed and personalized content");
-            knowledge.facts.insert(format!("personal_knowledge_{}", url), processed_content);
-            knowledge.learned_urls.push(url.to_string());
-            
-            // Save knowledge after successful learning
-            drop(knowledge); // Release the lock before saving
-            self.save_knowledge()?;
+        self.save_knowledge().await?;
+
+        let mut index = self.vector_index.write().await;
+        for ((key, tags), embedding) in keys.iter().zip(&embeddings) {
+            index.insert(key, embedding, tags).await?;
+            self.track_indexed(key, true);
         }
-        
+        index.flush().await?;
         Ok(())
     }
 
-    async fn learn_about_self(&self) -> Result<(), Box<dyn std::error::Error>> {
-        println!("Starting self-learning process...");
-        
-        // Load existing knowledge first
-        self.load_knowledge()?;
-        
-        // Learn from web search
-        println!("Searching web for information about {}...", self.config.character.name);
-        let search_query = format!("{} character personality traits background story", self.config.character.name);
-        let content = self.search_web(&search_query).await?;
-        
-        println!("Processing search results...");
-        {
-            let mut knowledge = self.knowledge.write().unwrap();
-            knowledge.facts.insert("self_understanding".to_string(), content);
-            knowledge.search_history.push(search_query);
-        }
-        
-        // Save after web search
-        self.save_knowledge()?;
-        println!("Saved initial search results");
-        
-        // Learn from configured URLs
-        println!("Learning from configured URLs...");
-        for url in &self.config.knowledge_sources.self_learning_urls {
-            println!("Processing URL: {}", url);
-            match self.learn_from_url(url).await {
-                Ok(_) => println!("Successfully learned from URL: {}", url),
-                Err(e) => println!("Error learning from URL {}: {}", url, e),
-            }
-            // Save after each URL
-            self.save_knowledge()?;
-        }
-        
-        println!("Self-learning process completed!");
-        println!("\nI've learned about myself and I'm ready to chat!");
-        println!("You can ask me questions about:");
-        println!("1. My personality and traits");
-        println!("2. My background and story");
-        println!("3. My interests and hobbies");
-        println!("4. Or anything else you'd like to know!");
-        
-        Ok(())
-    }
+    /// Makes sure the vector index holds exactly the embedded facts, rebuilding it from the
+    /// embeddings stored with the facts when it is missing, stale or corrupted.
+    async fn sync_vector_index(&self) -> Result<(), Box<dyn std::error::Error>> {
+        let model = self.embedder.model();
+        let ids: HashSet<String> = {
+            let knowledge = self.knowledge.read().unwrap();
+            knowledge
+                .facts
+                .iter()
+                .filter(|(_, fact)| fact.is_embedded_with(model))
+                .map(|(key, _)| key.clone())
+                .collect()
+        };
 
-    async fn search_web(&self, query: &str) -> Result<String, Box<dyn std::error::Error>> {
-        println!("Executing web search for: {}", query);
+        if self.indexed.lock().unwrap().as_ref() == Some(&ids) {
+            return Ok(());
+        }
+        let mut index = self.vector_index.write().await;
+        if index.is_consistent(model, &ids).await? {
+            *self.indexed.lock().unwrap() = Some(ids);
+            return Ok(());
+        }
+
+        status!("Rebuilding vector index for {} fact(s)...", ids.len());
+        let (mut vectors, stubs) = {
+            let knowledge = self.knowledge.read().unwrap();
+            let mut vectors: Vec<(String, Vec<f32>, Vec<FactTag>)> = Vec::new();
+            let mut stubs = HashSet::new();
+            for (key, fact) in ids.iter().filter_map(|key| Some((key, knowledge.facts.get(key)?))) {
+                match &fact.embedding {
+                    Some(embedding) => vectors.push((key.clone(), embedding.clone(), fact.tags.clone())),
+                    None => {
+                        stubs.insert(key.clone());
+                    }
+                }
+            }
+            (vectors, stubs)
+        };
+        if !stubs.is_empty() {
+            self.store
+                .iterate(&mut |key, fact| {
+                    if let (true, Some(embedding)) = (stubs.contains(key), &fact.embedding) {
+                        vectors.push((key.to_string(), embedding.clone(), fact.tags.clone()));
+                    }
+                })
+                .await?;
+        }
+        index.reset(model).await?;
+        for (key, vector, tags) in &vectors {
+            index.insert(key, vector, tags).await?;
+        }
+        index.flush().await?;
+        *self.indexed.lock().unwrap() = Some(ids);
+        Ok(())
+    }
+
+    /// Records that the vector index now holds `key`, or no longer does.
+    fn track_indexed(&self, key: &str, held: bool) {
+        if let Some(indexed) = self.indexed.lock().unwrap().as_mut() {
+            match held {
+                true => indexed.insert(key.to_string()),
+                false => indexed.remove(key),
+            };
+        }
+    }
+
+    /// Picks the facts to put into the prompt for `query`: every fact allowed by the tag
+    /// filters, narrowed down to the most similar ones when retrieval is enabled. Facts with
+    /// a keyword the query mentions, and the newest feed entries, are always picked. Imported
+    /// Q&A answers come before the other facts they are picked with.
+    async fn select_facts(&self, session: &str, query: &str) -> Vec<String> {
+        let (mut allowed, mut triggered, curated): (Vec<String>, Vec<String>, HashSet<String>) = {
+            let knowledge = self.knowledge.read().unwrap();
+            let allowed = knowledge
+                .facts
+                .iter()
+                .filter(|(_, fact)| self.fact_allowed(&fact.tags) && fact.user.as_deref().is_none_or(|user| user == session));
+            let triggered = allowed.clone().filter(|(_, fact)| fact.is_triggered_by(query));
+            let curated = allowed.clone().filter(|(_, fact)| fact.method == LearnMethod::Dataset);
+            (
+                allowed.map(|(key, _)| key.clone()).collect(),
+                triggered.map(|(key, _)| key.clone()).collect(),
+                curated.map(|(key, _)| key.clone()).collect(),
+            )
+        };
+        allowed.sort();
+        allowed.sort_by_key(|key| !curated.contains(key));
+        triggered.sort();
+        for key in self.recent_news() {
+            if allowed.contains(&key) && !triggered.contains(&key) {
+                triggered.push(key);
+            }
+        }
+
+        let settings = &self.config.retrieval;
+        if !settings.enabled {
+            return allowed;
+        }
+
+        match self.search_index(query, settings.top_k).await {
+            Ok(results) => {
+                let similar = results
+                    .into_iter()
+                    .filter(|(key, score)| *score >= settings.similarity_threshold && allowed.contains(key))
+                    .take(settings.top_k)
+                    .map(|(key, _)| key);
+                let mut selected = triggered;
+                let mut similar: Vec<String> = similar.filter(|key| !selected.contains(key)).collect();
+                similar.sort_by_key(|key| !curated.contains(key));
+                selected.extend(similar);
+                selected
+            }
+            Err(e) => {
+                status!("Retrieval unavailable ({}), using all facts", e);
+                allowed
+            }
+        }
+    }
+
+    /// The keys of the newest facts learned from feeds within `RECENT_NEWS_DAYS`, newest first.
+    fn recent_news(&self) -> Vec<String> {
+        let since = chrono::Utc::now() - chrono::Duration::days(RECENT_NEWS_DAYS);
+        let knowledge = self.knowledge.read().unwrap();
+        let mut news: Vec<(&String, &Fact)> = knowledge
+            .facts
+            .iter()
+            .filter(|(_, fact)| fact.method == LearnMethod::Feed && fact.learned_at >= since)
+            .collect();
+        news.sort_by(|a, b| b.1.learned_at.cmp(&a.1.learned_at).then_with(|| a.0.cmp(b.0)));
+        news.into_iter().take(self.config.conversation_settings.recent_news).map(|(key, _)| key.clone()).collect()
+    }
+
+    async fn search_index(&self, query: &str, k: usize) -> Result<Vec<(String, f32)>, Box<dyn std::error::Error>> {
+        self.embed_missing_facts().await?;
+        self.sync_vector_index().await?;
+        let query_embedding = self.embedder.embed(query).await?;
+        self.vector_index
+            .read()
+            .await
+            .search(&query_embedding, k, &self.tag_filter())
+            .await
+    }
+
+    /// Asks the model which categories a piece of learned text belongs to.
+    async fn classify_fact(&self, text: &str) -> Vec<FactTag> {
+        let tag_list = FactTag::ALL
+            .iter()
+            .filter(|tag| **tag != FactTag::Trained)
+            .map(|tag| tag.as_str())
+            .collect::<Vec<_>>()
+            .join(", ");
+        let prompt = format!(
+            "Classify the following text about a fictional character. Answer only with a comma-separated list \
+            of the categories that apply, chosen from: {}. Use \"plot\" for story events and spoilers, \
+            \"user-info\" for information about the person chatting with the character, and \"background\" for \
+            real-world places, institutions and references rather than the characters.\n\n{}",
+            tag_list, text
+        );
+
+        let mut tags = Vec::new();
+        match self.generate(&prompt).await {
+            Ok(answer) => {
+                for tag in answer.split(',').filter_map(FactTag::parse) {
+                    if !tags.contains(&tag) {
+                        tags.push(tag);
+                    }
+                }
+            }
+            Err(e) => status!("Error classifying fact: {}", e),
+        }
+        if tags.is_empty() {
+            tags.push(FactTag::Personality);
+        }
+        tags
+    }
+
+    /// Whether a fact may be used in the chat context under the configured tag filters.
+    fn fact_allowed(&self, tags: &[FactTag]) -> bool {
+        self.tag_filter().matches(tags)
+    }
+
+    fn tag_filter(&self) -> TagFilter {
+        TagFilter {
+            include: self.config.conversation_settings.include_tags.clone(),
+            exclude: self.config.conversation_settings.exclude_tags.clone(),
+        }
+    }
+
+    /// Stores a newly learned fact, skipping duplicates and checking it against what is
+    /// already known. Returns whether the fact was stored.
+    async fn store_fact(&self, key: String, mut fact: Fact) -> Result<bool, Box<dyn std::error::Error>> {
+        self.embed_fact(&key, &mut fact).await;
+
+        // Only the most similar facts go into the contradiction prompt, so it stays short
+        let neighbours = self.nearest_facts(&key, fact.embedding.as_deref()).await?;
+        // Against stubs `insert_fact` only recognises exact duplicates
+        if self.config.storage.lazy_loading {
+            if let Some((existing, _)) = neighbours.iter().find(|(_, neighbour)| fact.is_duplicate_of(neighbour)) {
+                status!("Content duplicates already learned fact {}, skipping", existing);
+                self.report.lock().unwrap().duplicates += 1;
+                return Ok(false);
+            }
+        }
+        let existing_facts: Vec<(String, String)> = if neighbours.is_empty() && !self.config.storage.lazy_loading {
+            self.facts_sharing_tags(&key, &fact.tags)
+        } else {
+            neighbours
+                .into_iter()
+                .filter(|(_, existing)| existing.user.is_none())
+                .map(|(existing_key, existing)| (existing_key, existing.text))
+                .collect()
+        };
+
+        let text = fact.text.clone();
+        let embedding = fact.embedding.clone();
+        let tags = fact.tags.clone();
+        let stored = fact.clone();
+        if let Some(existing) = self.knowledge.write().unwrap().insert_fact(key.clone(), fact) {
+            status!("Content duplicates already learned fact {}, skipping", existing);
+            self.report.lock().unwrap().duplicates += 1;
+            return Ok(false);
+        }
+        self.store.save_fact(&key, &stored).await?;
+        self.report.lock().unwrap().facts_added += 1;
+        self.webhooks.emit(EventKind::FactLearned, fact_json(&key, &stored));
+
+        if let Some(embedding) = embedding {
+            let mut index = self.vector_index.write().await;
+            index.insert(&key, &embedding, &tags).await?;
+            index.flush().await?;
+            self.track_indexed(&key, true);
+        }
+
+        self.detect_contradictions(&key, &text, &existing_facts).await?;
+        Ok(true)
+    }
+
+    /// Stores a fact as it is, replacing whatever was stored under its key, without the
+    /// duplicate and contradiction checks of `store_fact`. For lorebook entries, which the
+    /// user wrote and which are canon, and for memories about a user, which are not the
+    /// character's knowledge to check against.
+    async fn put_fact(&self, key: String, mut fact: Fact) -> Result<(), Box<dyn std::error::Error>> {
+        self.embed_fact(&key, &mut fact).await;
+        self.store.save_fact(&key, &fact).await?;
+        if let Some(embedding) = &fact.embedding {
+            let mut index = self.vector_index.write().await;
+            index.insert(&key, embedding, &fact.tags).await?;
+            index.flush().await?;
+            self.track_indexed(&key, true);
+        }
+        self.webhooks.emit(EventKind::FactLearned, fact_json(&key, &fact));
+        self.knowledge.write().unwrap().facts.insert(key, fact);
+        self.report.lock().unwrap().facts_added += 1;
+        Ok(())
+    }
+
+    async fn embed_fact(&self, key: &str, fact: &mut Fact) {
+        match self.embedder.embed(&fact.text).await {
+            Ok(embedding) => {
+                fact.embedding = Some(embedding);
+                fact.embedding_model = Some(self.embedder.model().to_string());
+            }
+            Err(e) => status!("Error embedding fact {}: {}", key, e),
+        }
+    }
+
+    /// The facts most similar to a new one, most similar first. With lazy loading these are
+    /// all a new fact is checked against, instead of every stored fact.
+    async fn nearest_facts(&self, key: &str, embedding: Option<&[f32]>) -> Result<Vec<(String, Fact)>, Box<dyn std::error::Error>> {
+        let Some(embedding) = embedding else {
+            return Ok(Vec::new());
+        };
+        let search = self
+            .vector_index
+            .read()
+            .await
+            .search(embedding, self.config.retrieval.top_k, &TagFilter::default())
+            .await;
+        let keys: Vec<String> = match search {
+            Ok(results) => results.into_iter().map(|(found, _)| found).filter(|found| found != key).collect(),
+            Err(e) => {
+                status!("Error searching for similar facts: {}", e);
+                return Ok(Vec::new());
+            }
+        };
+        let mut facts = self.load_facts(&keys).await?;
+        Ok(keys.into_iter().filter_map(|key| Some((key.clone(), facts.remove(&key)?))).collect())
+    }
+
+    /// The `top_k` most recently learned facts sharing a tag with a new one, to check it
+    /// against when it has no embedding to find the most similar by.
+    fn facts_sharing_tags(&self, key: &str, tags: &[FactTag]) -> Vec<(String, String)> {
+        let knowledge = self.knowledge.read().unwrap();
+        let mut facts: Vec<(&String, &Fact)> = knowledge
+            .facts
+            .iter()
+            .filter(|(existing_key, existing)| {
+                *existing_key != key && existing.user.is_none() && existing.tags.iter().any(|tag| tags.contains(tag))
+            })
+            .collect();
+        facts.sort_by(|a, b| b.1.learned_at.cmp(&a.1.learned_at));
+        facts
+            .into_iter()
+            .take(self.config.retrieval.top_k)
+            .map(|(existing_key, existing)| (existing_key.clone(), existing.text.clone()))
+            .collect()
+    }
+
+    async fn detect_contradictions(
+        &self,
+        new_key: &str,
+        new_text: &str,
+        existing_facts: &[(String, String)],
+    ) -> Result<(), Box<dyn std::error::Error>> {
+        if existing_facts.is_empty() {
+            return Ok(());
+        }
+
+        let mut prompt = format!(
+            "Compare the NEW statement about {} with the numbered EXISTING statements. \
+            List only direct factual contradictions (different birthdays, ages, relationships, events). \
+            Answer with one line per contradiction in the form \"<number> | <short description>\", \
+            or with NONE if there are none.\n\nNEW:\n{}\n",
+            self.config.character.name, new_text
+        );
+        for (i, (_, text)) in existing_facts.iter().enumerate() {
+            prompt.push_str(&format!("\nEXISTING {}:\n{}\n", i + 1, text));
+        }
+
+        let answer = self.generate(&prompt).await?;
+        for line in answer.lines() {
+            let Some((number, description)) = line.split_once('|') else {
+                continue;
+            };
+            let Some((existing_key, existing_text)) = number
+                .trim()
+                .trim_start_matches("EXISTING")
+                .trim()
+                .parse::<usize>()
+                .ok()
+                .and_then(|n| n.checked_sub(1))
+                .and_then(|i| existing_facts.get(i))
+            else {
+                continue;
+            };
+
+            status!("Possible contradiction between {} and {}: {}", new_key, existing_key, description.trim());
+            let index = {
+                let mut knowledge = self.knowledge.write().unwrap();
+                knowledge.contradictions.push(Contradiction {
+                    new_key: new_key.to_string(),
+                    existing_key: existing_key.clone(),
+                    description: description.trim().to_string(),
+                    detected_at: chrono::Utc::now(),
+                    resolution: None,
+                });
+                knowledge.contradictions.len() - 1
+            };
+
+            match self.config.learning.contradiction_resolution {
+                ContradictionPolicy::Model => {
+                    self.adjudicate(index, existing_text, new_text, description.trim()).await?;
+                }
+                ContradictionPolicy::User => {
+                    status!("Flagged for review, use 'conflicts' and 'resolve {} existing|new|both'", index + 1);
+                }
+            }
+
+            // The new fact may have lost; nothing left to compare
+            if !self.knowledge.read().unwrap().facts.contains_key(new_key) {
+                break;
+            }
+        }
+        Ok(())
+    }
+
+    /// Lets the model decide which side of a contradiction is correct.
+    async fn adjudicate(
+        &self,
+        index: usize,
+        existing_text: &str,
+        new_text: &str,
+        description: &str,
+    ) -> Result<(), Box<dyn std::error::Error>> {
+        let prompt = format!(
+            "Two statements about {} contradict each other: {}\n\nEXISTING:\n{}\n\nNEW:\n{}\n\n\
+            Decide which one matches the official canon. Answer on the first line with exactly one word: \
+            EXISTING, NEW, or BOTH (if they can both be true). On the second line give a one-sentence explanation.",
+            self.config.character.name, description, existing_text, new_text
+        );
+        let answer = self.generate(&prompt).await?;
+        let mut lines = answer.lines().filter(|line| !line.trim().is_empty());
+        let verdict = lines.next().and_then(Verdict::parse);
+        let explanation = lines.collect::<Vec<_>>().join(" ");
+
+        match verdict {
+            Some(verdict) => {
+                status!("Resolved contradiction {}: {:?} ({})", index + 1, verdict, explanation);
+                self.resolve_contradiction(index, verdict, "model", explanation).await?;
+            }
+            None => status!("Could not resolve contradiction {}, use 'resolve {} existing|new|both'", index + 1, index + 1),
+        }
+        Ok(())
+    }
+
+    async fn resolve_contradiction(
+        &self,
+        index: usize,
+        verdict: Verdict,
+        resolved_by: &str,
+        explanation: String,
+    ) -> Result<(), Box<dyn std::error::Error>> {
+        // The discarded fact's text is kept with the resolution, so stubs need their body
+        let keys: Vec<String> = {
+            let knowledge = self.knowledge.read().unwrap();
+            knowledge
+                .contradictions
+                .get(index)
+                .map(|contradiction| vec![contradiction.new_key.clone(), contradiction.existing_key.clone()])
+                .unwrap_or_default()
+        };
+        let loaded = self.load_facts(&keys).await?;
+        {
+            let mut knowledge = self.knowledge.write().unwrap();
+            for (key, fact) in loaded {
+                if let Some(current) = knowledge.facts.get_mut(&key).filter(|current| current.is_stub()) {
+                    *current = fact;
+                }
+            }
+        }
+        let discarded = self
+            .knowledge
+            .write()
+            .unwrap()
+            .resolve_contradiction(index, verdict, resolved_by, explanation);
+        if let Some(key) = discarded {
+            self.store.delete_fact(&key).await?;
+            let mut index = self.vector_index.write().await;
+            index.remove(&key).await?;
+            index.flush().await?;
+            self.track_indexed(&key, false);
+        }
+        Ok(())
+    }
+
+    fn print_contradictions(&self) {
+        let knowledge = self.knowledge.read().unwrap();
+        let pending = knowledge.pending_contradictions();
+        if pending.is_empty() {
+            println!("No unresolved contradictions.");
+        }
+        for index in pending {
+            let contradiction = &knowledge.contradictions[index];
+            println!(
+                "\n{}. {} vs {}\n   {}",
+                index + 1,
+                contradiction.existing_key,
+                contradiction.new_key,
+                contradiction.description
+            );
+        }
+
+        let resolved = knowledge.contradictions.iter().filter(|c| c.resolution.is_some()).count();
+        if resolved > 0 {
+            println!("\n{} contradiction(s) already resolved.", resolved);
+        }
+    }
+
+    async fn learn_from_url(&self, url: &str) -> Result<(), Box<dyn std::error::Error>> {
+        // Check if we've already learned from this URL
+        if self.knowledge.read().unwrap().learned_urls.contains(&url.to_string()) {
+            status!("Already learned from URL: {}", url);
+            return Ok(());
+        }
+        if let Fetched::Page(page) = self.fetch_page(url, None).await? {
+            self.learn_from_page(url, &page).await?;
+        }
+        Ok(())
+    }
+
+    /// Learns from `url` and the same-site pages it links to, breadth first, up to `depth`
+    /// links away and `max_pages` pages in total. Pages learned from before are not
+    /// processed again, but their links are still followed.
+    async fn crawl(&self, url: &str, depth: usize, max_pages: usize) -> Result<(), Box<dyn std::error::Error>> {
+        let start = url::Url::parse(url)?;
+        let mut visited = HashSet::from([start.clone()]);
+        let mut queue = VecDeque::from([(start, 0)]);
+        let mut pages = 0;
+        while let Some((page, level)) = queue.pop_front() {
+            if pages == max_pages {
+                status!("Reached the limit of {} page(s), {} link(s) left unvisited", max_pages, queue.len() + 1);
+                break;
+            }
+            pages += 1;
+            if pages > 1 {
+                // Be gentle with the site
+                tokio::time::sleep(CRAWL_DELAY).await;
+            }
+
+            let webpage = match self.fetch_page(page.as_str(), None).await {
+                Ok(Fetched::Page(webpage)) => webpage,
+                Ok(_) => continue,
+                Err(e) => {
+                    status!("Error fetching {}: {}", page, e);
+                    continue;
+                }
+            };
+            if level < depth {
+                for link in same_site_links(&webpage.html, &page) {
+                    if self.config.learning.domains.allows(link.as_str()) && visited.insert(link.clone()) {
+                        queue.push_back((link, level + 1));
+                    }
+                }
+            }
+
+            if self.knowledge.read().unwrap().learned_urls.contains(&page.to_string()) {
+                status!("Already learned from URL: {}", page);
+                continue;
+            }
+            if let Err(e) = self.learn_from_page(page.as_str(), &webpage).await {
+                status!("Error learning from {}: {}", page, e);
+            }
+        }
+        Ok(())
+    }
+
+    /// Fetches a page, unless the domain filter or the site's robots.txt disallows it. With the `known` state
+    /// of an earlier fetch, the request is conditional. Pages listed for rendering, or that look empty as
+    /// served, are rendered in a headless browser. For YouTube videos the transcript is
+    /// fetched instead of the page, for Reddit threads the post and its best comments, and
+    /// for wiki articles the article through the MediaWiki API. The outcome is counted in the
+    /// learning report.
+    async fn fetch_page(&self, url: &str, known: Option<&PageState>) -> Result<Fetched, Box<dyn std::error::Error>> {
+        let fetched = self.fetch_source(url, known).await;
+        let mut report = self.report.lock().unwrap();
+        match &fetched {
+            Ok(Fetched::Page(_)) => report.pages_fetched += 1,
+            Ok(Fetched::Skipped) => report.pages_skipped += 1,
+            Ok(Fetched::Failed(status)) => report.fail(url, format!("status {}", status)),
+            Ok(Fetched::NotModified) => {}
+            Err(e) => report.fail(url, e),
+        }
+        drop(report);
+        fetched
+    }
+
+    /// The fetch itself, for `fetch_page`.
+    async fn fetch_source(&self, url: &str, known: Option<&PageState>) -> Result<Fetched, Box<dyn std::error::Error>> {
+        if !self.config.learning.domains.allows(url) {
+            status!("Skipping URL outside the allowed domains: {}", url);
+            return Ok(Fetched::Skipped);
+        }
+        // Reddit's robots.txt closes its pages to all crawlers; its JSON API is the way in
+        if let Some(thread_id) = reddit_thread_id(url) {
+            status!("Fetching Reddit thread: {}", url);
+            let client = reqwest::Client::builder()
+                .timeout(std::time::Duration::from_secs(20))
+                .build()?;
+            let thread = fetch_reddit_thread(&client, &thread_id, &self.config.learning.reddit).await?;
+            return Ok(Fetched::Page(FetchedPage {
+                html: String::new(),
+                text: Some(thread),
+                infobox: None,
+                etag: None,
+                last_modified: None,
+            }));
+        }
+        if !self.robots.allows(url).await {
+            status!("Skipping URL disallowed by the site's robots.txt: {}", url);
+            return Ok(Fetched::Skipped);
+        }
+        if let Some(video_id) = youtube_video_id(url) {
+            status!("Fetching the transcript of YouTube video: {}", url);
+            let client = reqwest::Client::builder()
+                .timeout(std::time::Duration::from_secs(20))
+                .build()?;
+            let transcript = fetch_transcript(&client, &video_id, &self.config.learning.transcript_languages).await?;
+            status!(
+                "Found {} captions in '{}' for \"{}\"",
+                if transcript.generated { "generated" } else { "written" },
+                transcript.language,
+                transcript.title
+            );
+            return Ok(Fetched::Page(FetchedPage {
+                html: String::new(),
+                text: Some(transcript.markdown()),
+                infobox: None,
+                etag: None,
+                last_modified: None,
+            }));
+        }
+
+        let login = self.config.learning.logins.iter().find(|login| login.covers(url));
+        // The API is asked without cookies, so wikis behind a login are read as pages
+        if self.config.learning.mediawiki_api && login.is_none() {
+            match self.wikis.article(url).await {
+                Some(Ok(article)) => {
+                    status!("Read \"{}\" through the wiki's API", article.title);
+                    return Ok(Fetched::Page(FetchedPage {
+                        text: Some(article.markdown()),
+                        html: article.html,
+                        infobox: article.infobox,
+                        etag: None,
+                        last_modified: None,
+                    }));
+                }
+                Some(Err(e)) => status!("Could not read {} through the wiki's API, fetching the page: {}", url, e),
+                None => {}
+            }
+        }
+        if let Some(login) = login.filter(|login| !self.cookies.lock().unwrap().has_cookies(&login.domain)) {
+            self.log_in(login).await;
+        }
+
+        status!("Fetching content from URL: {}", url);
         let client = reqwest::Client::builder()
             .timeout(std::time::Duration::from_secs(10))
             .build()?;
-        
-        // First try Google Custom Search API
-        let search_url = format!(
-            "https://www.googleapis.com/customsearch/v1?key={}&cx={}&q={}",
-            env::var("GOOGLE_SEARCH_API_KEY").expect("GOOGLE_SEARCH_API_KEY not set"),
-            env::var("GOOGLE_SEARCH_ENGINE_ID").expect("GOOGLE_SEARCH_ENGINE_ID not set"),
-            query
-        );
+        let mut response = self.send_fetch(&client, url, known).await?;
+        if let (Some(login), 401 | 403) = (login, response.status().as_u16()) {
+            // The saved session has probably expired
+            status!("{} refused the saved login, logging in again", url);
+            if self.log_in(login).await {
+                response = self.send_fetch(&client, url, known).await?;
+            }
+        }
 
-        println!("Sending request to Google Search API...");
-        let response = client.get(&search_url).send().await?;
-        
+        if response.status() == reqwest::StatusCode::NOT_MODIFIED {
+            status!("Not modified since the last fetch: {}", url);
+            return Ok(Fetched::NotModified);
+        }
         if !response.status().is_success() {
-            println!("Google Search API request failed: {}", response.status());
-            return Ok("".to_string());
+            status!("Failed to fetch URL: {} (Status: {})", url, response.status());
+            return Ok(Fetched::Failed(response.status().as_u16()));
+        }
+
+        status!("Successfully fetched URL, parsing content...");
+        let header = |name: &str| response.headers().get(name).and_then(|value| value.to_str().ok()).map(str::to_string);
+        let etag = header("ETag");
+        let last_modified = header("Last-Modified");
+        let mut html = response.text().await?;
+        if self.config.learning.browser.should_render(url, &html) {
+            status!("Rendering the page in a headless browser...");
+            match render_page(url, &self.config.learning.browser).await {
+                Ok(rendered) => html = rendered,
+                Err(e) => status!("Could not render {}, using the page as served: {}", url, e),
+            }
+        }
+        Ok(Fetched::Page(FetchedPage {
+            html,
+            text: None,
+            infobox: None,
+            etag,
+            last_modified,
+        }))
+    }
+
+    /// Sends the GET for `fetch_page` with the saved cookies of the site, keeping the cookies
+    /// the response sets.
+    async fn send_fetch(
+        &self,
+        client: &reqwest::Client,
+        url: &str,
+        known: Option<&PageState>,
+    ) -> Result<reqwest::Response, Box<dyn std::error::Error>> {
+        let mut request = client
+            .get(url)
+            .header("User-Agent", "Mozilla/5.0 (Windows NT 10.0; Win64; x64) AppleWebKit/537.36 (KHTML, like Gecko) Chrome/91.0.4472.124 Safari/537.36")
+            .header("Accept", "text/html,application/xhtml+xml,application/xml;q=0.9,image/webp,*/*;q=0.8")
+            .header("Accept-Language", "en-US,en;q=0.5");
+        if let Some(etag) = known.and_then(|known| known.etag.as_deref()) {
+            request = request.header("If-None-Match", etag);
+        }
+        if let Some(last_modified) = known.and_then(|known| known.last_modified.as_deref()) {
+            request = request.header("If-Modified-Since", last_modified);
+        }
+        let cookies = self.cookies.lock().unwrap().header(url);
+        if let Some(cookies) = cookies {
+            request = request.header("Cookie", cookies);
+        }
+        let response = request.send().await?;
+        let mut jar = self.cookies.lock().unwrap();
+        if jar.update(url, response.headers()) {
+            jar.save()?;
+        }
+        Ok(response)
+    }
+
+    /// Logs in to a site, once per run if it fails, so a wrong password is not tried
+    /// against every page of the site. Returns whether it succeeded.
+    async fn log_in(&self, login: &SiteLogin) -> bool {
+        if self.failed_logins.lock().unwrap().contains(&login.domain) {
+            return false;
+        }
+        status!("Logging in to {}...", login.domain);
+        match login.log_in(&self.cookies).await {
+            Ok(()) => true,
+            Err(e) => {
+                status!("Could not log in to {}: {}", login.domain, e);
+                self.failed_logins.lock().unwrap().insert(login.domain.clone());
+                false
+            }
+        }
+    }
+
+    /// Learns from a fetched page, replacing what was learned from it before.
+    async fn learn_from_page(&self, url: &str, page: &FetchedPage) -> Result<(), Box<dyn std::error::Error>> {
+        let content = page.content();
+
+        if content.trim().is_empty() {
+            status!("No content found at URL: {}", url);
+            return Ok(());
+        }
+
+        // Process content with AI before saving
+        status!("Processing content with AI...");
+        let previous = self.knowledge.read().unwrap().pages.get(url).map(|state| state.sections.clone()).unwrap_or_default();
+        let (processed_content, sections) = self.process_sections(&content, &previous).await?;
+
+        if !processed_content.is_empty() {
+            status!("Successfully processed and personalized content");
+            let tags = self.classify_fact(&processed_content).await;
+            let key = format!("personal_knowledge_{}", url);
+            let fact = Fact::new(processed_content, Some(url.to_string()), LearnMethod::Url, tags);
+            self.store_fact(key, fact).await?;
+            if let Some(infobox) = &page.infobox {
+                // Ages, birthdays and names are kept exactly as the wiki gives them
+                let tags = self.classify_fact(&infobox.text()).await;
+                let fact = Fact::new(infobox.text(), Some(url.to_string()), LearnMethod::Url, tags);
+                self.put_fact(format!("profile_{}", url), fact).await?;
+            }
+            {
+                let mut knowledge = self.knowledge.write().unwrap();
+                if !knowledge.learned_urls.iter().any(|learned| learned == url) {
+                    knowledge.learned_urls.push(url.to_string());
+                }
+                let mut state = PageState::new(&content, page.etag.clone(), page.last_modified.clone());
+                state.sections = sections;
+                knowledge.pages.insert(url.to_string(), state);
+            }
+
+            // Save knowledge after successful learning
+            self.save_knowledge().await?;
+            // Search results are learned from several at once, so only this page's go
+            let chunks: Vec<String> = split_sections(&content, self.config.learning.chunk_size)
+                .iter()
+                .flat_map(|run| chunk_text(run, self.config.learning.chunk_size, self.config.learning.chunk_overlap))
+                .collect();
+            self.queue.lock().unwrap().forget_chunks_of(&chunks)?;
+        }
+
+        Ok(())
+    }
+
+    /// Has the model write targeted search queries from the character config and the recent
+    /// conversation, skipping what was searched before, and learns from each of them.
+    async fn search_generated_queries(&self) -> Result<(), Box<dyn std::error::Error>> {
+        let queries = self.generate_search_queries().await;
+        if queries.is_empty() {
+            return Ok(());
+        }
+        status!("Searching {} more topic(s) about {}...", queries.len(), self.config.character.name);
+        for query in queries {
+            status!("Searching web for: {}", query);
+            match self.search_web(&query).await {
+                Ok(content) if !content.trim().is_empty() => {
+                    let tags = self.classify_fact(&content).await;
+                    let fact = Fact::new(content, None, LearnMethod::WebSearch, tags);
+                    self.store_fact(format!("search_{}", query.to_lowercase()), fact).await?;
+                }
+                Ok(_) => status!("Nothing found for: {}", query),
+                Err(e) => {
+                    status!("Skipping the remaining searches: {}", e);
+                    break;
+                }
+            }
+            self.knowledge.write().unwrap().search_history.push(query);
+            self.save_knowledge().await?;
+        }
+        Ok(())
+    }
+
+    /// Asks the model for up to `search.queries` web search queries about the character that
+    /// have not been searched before.
+    async fn generate_search_queries(&self) -> Vec<String> {
+        let character = &self.config.character;
+        let searched = self.knowledge.read().unwrap().search_history.clone();
+        let mut prompt = format!(
+            "You research the fictional character {} to learn everything about them. Write {} web search queries, one per \
+            line and nothing else, that would each find something different about the character: their relationships, \
+            story arcs and key events, memorable quotes, and recent news about their series.\n\n\
+            Description: {}\nPersonality: {}\nTraits: {}\nInterests: {}\n",
+            character.name,
+            self.config.search.queries,
+            character.description,
+            character.personality,
+            character.traits.join(", "),
+            character.interests.join(", ")
+        );
+        // The chat's conversation, not those of API users, which are private
+        if self.conversation(DEFAULT_SESSION).next().is_some() {
+            prompt.push_str("\nRecent conversation, which may show what users want to know about:\n");
+            for message in self.conversation(DEFAULT_SESSION) {
+                prompt.push_str(&format!("{}\n", message));
+            }
+        }
+        if !searched.is_empty() {
+            prompt.push_str("\nAlready searched, do not repeat these:\n");
+            for query in &searched {
+                prompt.push_str(&format!("- {}\n", query));
+            }
+        }
+        let reply = match self.generate(&prompt).await {
+            Ok(reply) => reply,
+            Err(e) => {
+                status!("Could not write search queries: {}", e);
+                return Vec::new();
+            }
+        };
+        let mut queries: Vec<String> = Vec::new();
+        for line in reply.lines() {
+            let query = line
+                .trim()
+                .trim_start_matches(|c: char| c.is_ascii_digit() || matches!(c, '-' | '*' | '.' | ')'))
+                .trim()
+                .trim_matches('"')
+                .to_string();
+            let seen = |existing: &String| existing.eq_ignore_ascii_case(&query);
+            if query.is_empty() || searched.iter().any(seen) || queries.iter().any(seen) {
+                continue;
+            }
+            queries.push(query);
+        }
+        queries.truncate(self.config.search.queries);
+        queries
+    }
+
+    /// Runs the steps of a learning run: the web searches, re-verifying stale facts,
+    /// checking for dead links, the configured sources and the feeds. The steps left are
+    /// kept in the learning queue, so a run cut short resumes where it stopped, and a step
+    /// that fails is tried again at the end of the run, up to `MAX_ATTEMPTS` times.
+    async fn learn_about_self(&self) -> Result<(), Box<dyn std::error::Error>> {
+        status!("Starting self-learning process...");
+        *self.report.lock().unwrap() = LearnReport::new();
+
+        // Load existing knowledge first
+        self.load_knowledge().await?;
+
+        let resumed = self.queue.lock().unwrap().start(self.learning_steps())?;
+        if resumed > 0 {
+            status!("Resuming the learning run that was cut short, {} step(s) left", resumed);
+        }
+        loop {
+            let Some(item) = self.queue.lock().unwrap().next()? else {
+                break;
+            };
+            if item.attempts > 1 {
+                status!("Trying again (attempt {} of {}): {}", item.attempts, MAX_ATTEMPTS, item.id);
+            }
+            match self.run_learning_step(&item.id).await {
+                Ok(()) => self.queue.lock().unwrap().complete(&item.id)?,
+                Err(e) => {
+                    let error = e.to_string();
+                    if self.queue.lock().unwrap().fail(&item.id, &error)? {
+                        status!("Will try again at the end of the run: {}", error);
+                        continue;
+                    }
+                    status!("Giving up after {} attempt(s): {}", item.attempts, error);
+                    let source = item.id.strip_prefix(SOURCE_STEP).unwrap_or(&item.id).to_string();
+                    let mut report = self.report.lock().unwrap();
+                    // A failed fetch is already in the report
+                    if !report.failures.iter().any(|(failed, _)| *failed == source) {
+                        report.fail(&source, error);
+                    }
+                }
+            }
+        }
+
+        status!("Self-learning process completed!");
+        self.report.lock().unwrap().print();
+
+        Ok(())
+    }
+
+    /// The steps of a learning run, in order, as queue item IDs.
+    fn learning_steps(&self) -> Vec<String> {
+        let mut steps = vec!["search".to_string()];
+        if self.config.search.queries > 0 {
+            steps.push("queries".to_string());
+        }
+        steps.push("reverify".to_string());
+        if self.config.learning.check_sources_days > 0 {
+            steps.push("check_sources".to_string());
+        }
+        for source in &self.config.knowledge_sources.self_learning_urls {
+            let step = format!("{}{}", SOURCE_STEP, source.url());
+            if !steps.contains(&step) {
+                steps.push(step);
+            }
+        }
+        if !self.config.knowledge_sources.feeds.is_empty() {
+            steps.push("feeds".to_string());
+        }
+        steps
+    }
+
+    async fn run_learning_step(&self, step: &str) -> Result<(), Box<dyn std::error::Error>> {
+        match step {
+            "search" => {
+                // Learn from web search
+                status!("Searching web for information about {}...", self.config.character.name);
+                let search_query = format!("{} character personality traits background story", self.config.character.name);
+                // Without a working search engine the configured sources are still learned from
+                match self.search_web(&search_query).await {
+                    Ok(content) => {
+                        status!("Processing search results...");
+                        let tags = self.classify_fact(&content).await;
+                        let fact = Fact::new(content, None, LearnMethod::WebSearch, tags);
+                        self.store_fact("self_understanding".to_string(), fact).await?;
+                        {
+                            let mut knowledge = self.knowledge.write().unwrap();
+                            if !knowledge.search_history.contains(&search_query) {
+                                knowledge.search_history.push(search_query);
+                            }
+                        }
+
+                        // Save after web search
+                        self.save_knowledge().await?;
+                        status!("Saved initial search results");
+                    }
+                    Err(e) => {
+                        status!("Skipping the web search: {}", e);
+                        self.report.lock().unwrap().fail("web search", e);
+                    }
+                }
+                Ok(())
+            }
+            "queries" => self.search_generated_queries().await,
+            "reverify" => self.reverify_stale_facts().await,
+            "check_sources" => {
+                let max_age = chrono::Duration::days(self.config.learning.check_sources_days.into());
+                let gone = self.check_sources(Some(max_age)).await?;
+                if gone > 0 {
+                    status!("{} learned source(s) no longer exist; type 'knowledge verify' to remove or replace them", gone);
+                }
+                Ok(())
+            }
+            "feeds" => {
+                status!("Checking feeds for new entries...");
+                self.learn_from_feeds(false).await
+            }
+            _ => {
+                let url = step.strip_prefix(SOURCE_STEP).ok_or_else(|| format!("Unknown learning step {}", step))?;
+                let source = self
+                    .config
+                    .knowledge_sources
+                    .self_learning_urls
+                    .iter()
+                    .find(|source| source.url() == url)
+                    .ok_or_else(|| format!("{} is no longer a learning source", url))?;
+                status!("Processing URL: {}", url);
+                match source {
+                    LearningSource::Crawl { depth, max_pages, .. } if *depth > 0 => self.crawl(url, *depth, *max_pages).await?,
+                    LearningSource::Wikipedia { wikipedia, language, sections } => {
+                        let language = language.as_deref().unwrap_or(DEFAULT_WIKIPEDIA);
+                        self.learn_wikipedia(language, wikipedia, sections, false).await?;
+                    }
+                    LearningSource::AniList { anilist } => {
+                        self.learn_anilist(anilist, false).await?;
+                    }
+                    LearningSource::Vndb { vndb } => {
+                        self.learn_vndb(vndb, false).await?;
+                    }
+                    _ => self.learn_from_url(url).await?,
+                }
+                status!("Successfully learned from URL: {}", url);
+                // Save after each URL
+                self.save_knowledge().await
+            }
+        }
+    }
+
+    /// Prints what `learn` would do without fetching or learning anything: the searches,
+    /// which configured sources would be fetched and which are already learned or left out,
+    /// the pages due for re-verification or a dead-link check, the feeds due, and about how
+    /// many model requests the run would take.
+    async fn print_learning_plan(&self) -> Result<(), Box<dyn std::error::Error>> {
+        self.load_knowledge().await?;
+        let knowledge = self.knowledge.read().unwrap();
+        let settings = &self.config.learning;
+        let search = &self.config.search;
+        let mut requests = 0;
+
+        println!("Dry run: nothing is fetched or learned.");
+        println!("\nWeb search with {}:", self.search.name());
+        println!("- \"{} character personality traits background story\"", self.config.character.name);
+        // Each search also merges what it found, then tags and checks the overview
+        let results = search.results * REQUESTS_PER_PAGE + 3;
+        requests += results;
+        if search.queries > 0 {
+            println!("- {} more queries the model writes, on topics not searched before", search.queries);
+            requests += 1 + search.queries * results;
+        }
+        println!("  Up to {} result page(s) per search; pages learned before are not fetched again", search.results);
+
+        println!("\nConfigured sources:");
+        if self.config.knowledge_sources.self_learning_urls.is_empty() {
+            println!("- none");
+        }
+        let looked_up = |key: String, what: &str| match knowledge.facts.contains_key(&key) {
+            true => format!("already learned from {}, skipped", what),
+            false => format!("looked up on {}", what),
+        };
+        for source in &self.config.knowledge_sources.self_learning_urls {
+            let url = source.url();
+            let status = match source {
+                _ if !settings.domains.allows(&url) => "outside learning.domains, skipped".to_string(),
+                LearningSource::Crawl { depth, max_pages, .. } if *depth > 0 => {
+                    requests += max_pages * REQUESTS_PER_PAGE;
+                    format!("crawled {} link(s) deep, up to {} page(s); pages learned before are not processed again", depth, max_pages)
+                }
+                LearningSource::Wikipedia { wikipedia, language, .. } => {
+                    let language = language.as_deref().unwrap_or(DEFAULT_WIKIPEDIA);
+                    requests += REQUESTS_PER_PAGE;
+                    looked_up(format!("wikipedia_{}_{}", language, wikipedia.trim().to_lowercase().replace(' ', "_")), "Wikipedia")
+                }
+                LearningSource::AniList { anilist } => {
+                    requests += REQUESTS_PER_PAGE;
+                    looked_up(format!("anilist_{}_profile", anilist.trim().to_lowercase().replace(' ', "_")), "AniList")
+                }
+                LearningSource::Vndb { vndb } => {
+                    requests += REQUESTS_PER_PAGE;
+                    looked_up(format!("vndb_{}_profile", vndb.trim().to_lowercase().replace(' ', "_")), "VNDB")
+                }
+                _ if knowledge.learned_urls.contains(&url) => "already learned, cached".to_string(),
+                _ => {
+                    requests += REQUESTS_PER_PAGE;
+                    "fetched and learned from, unless robots.txt disallows it".to_string()
+                }
+            };
+            println!("- {}: {}", url, status);
+        }
+
+        let stale = knowledge.urls_needing_reverification(settings.confidence_half_life_days, settings.reverify_threshold);
+        println!("\nPages fetched again because their facts lost confidence: {}", stale.len());
+        for url in &stale {
+            println!("- {}", url);
+        }
+        requests += stale.len() * REQUESTS_PER_PAGE;
+
+        if settings.check_sources_days > 0 {
+            let max_age = chrono::Duration::days(settings.check_sources_days.into());
+            let due = knowledge
+                .learned_urls
+                .iter()
+                .filter(|url| knowledge.pages.get(*url).is_none_or(|state| chrono::Utc::now() - state.checked_at > max_age))
+                .count();
+            println!("Pages checked for dead links: {}", due);
+        }
+
+        let feeds_due = self
+            .config
+            .knowledge_sources
+            .feeds
+            .iter()
+            .filter(|url| knowledge.feeds.get(*url).is_none_or(|state| chrono::Utc::now() - state.checked_at >= FEED_CHECK_INTERVAL))
+            .count();
+        println!("Feeds checked for new entries: {} of {}", feeds_due, self.config.knowledge_sources.feeds.len());
+
+        println!(
+            "\nAbout {} model request(s), more for long pages and new feed entries (about {} per page: rewriting, tagging and checking for contradictions)",
+            requests, REQUESTS_PER_PAGE
+        );
+        Ok(())
+    }
+
+    /// The configured learning frequency; unknown values learn at every start, as before
+    /// frequencies were honored.
+    fn learning_frequency(&self) -> LearningFrequency {
+        LearningFrequency::parse(&self.config.conversation_settings.learning_frequency).unwrap_or(LearningFrequency::Startup)
+    }
+
+    /// Gets ready to chat: learns at startup if the learning frequency says so, or else
+    /// loads what was learned, then runs the jobs that are due and reads the lorebook.
+    async fn start(&self, no_initial_learn: bool) -> Result<(), Box<dyn std::error::Error>> {
+        if LearningFrequency::parse(&self.config.conversation_settings.learning_frequency).is_none() {
+            status!(
+                "\nUnknown learning_frequency \"{}\"; use startup, daily, weekly or manual. Learning at every start.",
+                self.config.conversation_settings.learning_frequency
+            );
+        }
+
+        // Initial self-learning
+        if no_initial_learn {
+            self.scheduler.lock().unwrap().unregister("learn");
+        }
+        if self.learning_frequency() == LearningFrequency::Startup && !no_initial_learn {
+            status!("\nPerforming initial self-learning...");
+            self.learn_about_self().await?;
+        } else {
+            self.load_knowledge().await?;
+        }
+        self.run_due_jobs().await;
+        self.sync_lore().await?;
+        Ok(())
+    }
+
+    /// Runs the jobs that are due.
+    async fn run_due_jobs(&self) {
+        let due = self.scheduler.lock().unwrap().due();
+        for name in due {
+            status!("\nRunning the scheduled {} job...", name);
+            self.run_job(&name).await;
+        }
+    }
+
+    /// Runs a job and records the run. A failure is reported rather than returned, so it
+    /// stops neither the other jobs nor the chat.
+    async fn run_job(&self, name: &str) {
+        let result = match name {
+            "learn" => match self.learn_about_self().await {
+                Ok(()) => self.refresh_pages().await,
+                Err(e) => Err(e),
+            },
+            "refresh" => self.refresh_pages().await,
+            "feeds" => self.learn_from_feeds(true).await,
+            "backup" => self.backup().await.map(|path| status!("Saved a backup to {}", path.display())),
+            "consolidate" => self.merge_duplicate_facts().await.map(|removed| status!("Removed {} repeated fact(s)", removed)),
+            "post" => self.post_status().await.map(|url| status!("Posted {}", url)),
+            _ => Err(format!("There is no job called {}", name).into()),
+        };
+        let error = result.err().map(|e| e.to_string());
+        if let Some(e) = &error {
+            status!("The {} job failed: {}", name, e);
+            self.webhooks.emit(EventKind::Error, json!({ "job": name, "error": e }));
+        }
+        if let Err(e) = self.scheduler.lock().unwrap().record(name, error) {
+            status!("Could not record the run of the {} job: {}", name, e);
+        }
+    }
+
+    /// Writes a status in character and posts it on Mastodon, unlike the account's recent
+    /// ones. Returns its address.
+    async fn post_status(&self) -> Result<String, Box<dyn std::error::Error>> {
+        let Some(settings) = &self.config.mastodon else {
+            return Err("No Mastodon account is set; add a mastodon section to the config".into());
+        };
+        let account = mastodon::Account::new(settings, &secrets::require(mastodon::TOKEN_NAME)?).map_err(|e| e.to_string())?;
+        let recent = account.recent_statuses(5).await.map_err(|e| e.to_string())?;
+        let fact_keys = self.select_facts("mastodon", "what is on your mind lately").await;
+        let mut prompt = self.get_context("mastodon", &fact_keys, false).await;
+        prompt.push_str(&format!(
+            "\nWrite a new status for your Mastodon account, of at most {} characters, in your own voice: something on \
+            your mind, such as what you did today, what you think about or one of your interests. Reply with only the status.\n",
+            settings.max_characters
+        ));
+        if !recent.is_empty() {
+            prompt.push_str("Say something other than in your latest statuses:\n");
+            for status in &recent {
+                prompt.push_str(&format!("- {}\n", status));
+            }
+        }
+        let (status, _) = extract_memories(&self.generate(&prompt).await?);
+        let (status, _) = self.extract_citations(&status, &fact_keys);
+        let status = status.trim().trim_matches('"').trim();
+        let Some(status) = split_message(status, settings.max_characters).into_iter().next() else {
+            return Err("The model wrote no status".into());
+        };
+        Ok(account.post(settings, &status).await.map_err(|e| e.to_string())?)
+    }
+
+    /// A few paragraphs on the character from its learned knowledge, for those who know nothing
+    /// of it, such as the readers of an exported card. The most trusted facts go first.
+    async fn summarize_knowledge(&self) -> Result<String, Box<dyn std::error::Error>> {
+        let knowledge = self.full_knowledge().await?;
+        let half_life = self.config.learning.confidence_half_life_days;
+        let mut facts: Vec<&Fact> = knowledge.facts.values().collect();
+        facts.sort_by(|a, b| b.current_confidence(half_life).total_cmp(&a.current_confidence(half_life)));
+        let mut notes = String::new();
+        for fact in facts {
+            if notes.len() + fact.text.len() <= SUMMARY_INPUT {
+                notes.push_str(&format!("{}\n\n", fact.text.trim()));
+            }
+        }
+        if notes.is_empty() {
+            return Err("Nothing is learned yet; run `alya learn` first".into());
+        }
+        let name = &self.config.character.name;
+        let prompt = format!(
+            "The following notes were learned about {name}. Summarize them in a few paragraphs, in the third person, as the \
+            background of {name} for another chatbot that is to play them: their story, the people around them and what they \
+            are like. Keep to what the notes say, and reply with only the summary.\n\n{notes}"
+        );
+        let summary = self.generate(&prompt).await?;
+        match summary.trim() {
+            "" => Err("The model wrote no summary".into()),
+            summary => Ok(summary.to_string()),
+        }
+    }
+
+    /// Lists the scheduled jobs with their last and next runs.
+    fn print_jobs(&self) {
+        let scheduler = self.scheduler.lock().unwrap();
+        if scheduler.jobs().is_empty() {
+            println!("No jobs are scheduled; schedule them in the jobs section of the config");
+        }
+        let format = |time: chrono::DateTime<chrono::Utc>| time.with_timezone(&chrono::Local).format("%Y-%m-%d %H:%M").to_string();
+        for job in scheduler.jobs() {
+            println!("\n{} ({}): {}", job.name, job.spec, job.description);
+            match scheduler.last_run(&job.name) {
+                Some(run) => match &run.error {
+                    Some(e) => println!("  Last run: {}, failed: {}", format(run.last_run), e),
+                    None => println!("  Last run: {}", format(run.last_run)),
+                },
+                None => println!("  Last run: never"),
+            }
+            if let Some(next) = scheduler.next_run(job) {
+                println!("  Next run: {}", format(next));
+            }
+        }
+        let unscheduled: Vec<&str> = JOBS.iter().map(|(name, _)| *name).filter(|name| scheduler.job(name).is_none()).collect();
+        if !unscheduled.is_empty() {
+            println!("\nNot scheduled: {}", unscheduled.join(", "));
+        }
+    }
+
+    /// Saves an archive of the character, its knowledge and sessions in `BACKUP_DIR`,
+    /// deleting the oldest backups beyond `BACKUPS_KEPT`.
+    async fn backup(&self) -> Result<PathBuf, Box<dyn std::error::Error>> {
+        let archive = build_archive(&self.config, self.store.as_ref()).await?;
+        let dir = paths::data_path(BACKUP_DIR);
+        fs::create_dir_all(&dir)?;
+        let path = dir.join(format!("{}.tar.gz", chrono::Utc::now().format("%Y%m%d-%H%M%S")));
+        archive.write(&path)?;
+        let mut backups: Vec<PathBuf> = fs::read_dir(&dir)?
+            .filter_map(|entry| entry.ok().map(|entry| entry.path()))
+            .filter(|path| path.to_string_lossy().ends_with(".tar.gz"))
+            .collect();
+        // The names are timestamps, so they sort oldest first
+        backups.sort();
+        let excess = backups.len().saturating_sub(BACKUPS_KEPT);
+        for old in &backups[..excess] {
+            fs::remove_file(old)?;
+        }
+        Ok(path)
+    }
+
+    /// Removes the facts that say the same as another fact, keeping the most confident of
+    /// them, or the oldest when they are equally confident. Lorebook entries and imported
+    /// datasets are curated and left alone, and so are memories about users, which are each
+    /// about someone else. Returns how many facts were removed.
+    async fn merge_duplicate_facts(&self) -> Result<usize, Box<dyn std::error::Error>> {
+        let duplicates: Vec<String> = {
+            let knowledge = self.knowledge.read().unwrap();
+            let half_life = self.config.learning.confidence_half_life_days;
+            let mut facts: Vec<(&String, &Fact)> = knowledge
+                .facts
+                .iter()
+                .filter(|(_, fact)| !matches!(fact.method, LearnMethod::Lorebook | LearnMethod::Dataset | LearnMethod::Conversation))
+                .collect();
+            facts.sort_by(|(_, a), (_, b)| {
+                b.current_confidence(half_life)
+                    .total_cmp(&a.current_confidence(half_life))
+                    .then(a.learned_at.cmp(&b.learned_at))
+            });
+            let mut kept: Vec<&Fact> = Vec::new();
+            let mut duplicates = Vec::new();
+            for (key, fact) in facts {
+                if kept.iter().any(|other| fact.is_duplicate_of(other)) {
+                    duplicates.push(key.clone());
+                } else {
+                    kept.push(fact);
+                }
+            }
+            duplicates
+        };
+        if !duplicates.is_empty() {
+            self.remove_facts(&duplicates).await?;
+            self.save_knowledge().await?;
+        }
+        Ok(duplicates.len())
+    }
+
+    /// Checks whether the learned pages still exist, marking those that answer 404 or 410
+    /// as gone. With `max_age`, only pages not checked for that long are checked. Returns
+    /// how many sources are gone.
+    async fn check_sources(&self, max_age: Option<chrono::Duration>) -> Result<usize, Box<dyn std::error::Error>> {
+        let due: Vec<(String, Option<PageState>)> = {
+            let knowledge = self.knowledge.read().unwrap();
+            knowledge
+                .learned_urls
+                .iter()
+                .map(|url| (url.clone(), knowledge.pages.get(url).cloned()))
+                .filter(|(_, known)| match (max_age, known) {
+                    (Some(max_age), Some(known)) => chrono::Utc::now() - known.checked_at > max_age,
+                    _ => true,
+                })
+                .collect()
+        };
+        if due.is_empty() {
+            return Ok(self.knowledge.read().unwrap().gone_sources().len());
+        }
+
+        status!("Checking {} learned source(s) for dead links...", due.len());
+        for (index, (url, known)) in due.iter().enumerate() {
+            if index > 0 {
+                tokio::time::sleep(CRAWL_DELAY).await;
+            }
+            match self.fetch_page(url, known.as_ref()).await {
+                Ok(Fetched::Page(page)) => {
+                    let mut knowledge = self.knowledge.write().unwrap();
+                    match knowledge.pages.get_mut(url).filter(|state| !state.content_hash.is_empty()) {
+                        // Whether the content changed is for `refresh` to act on
+                        Some(state) => {
+                            state.checked_at = chrono::Utc::now();
+                            state.gone = None;
+                        }
+                        None => {
+                            let state = PageState::new(&page.content(), page.etag, page.last_modified);
+                            knowledge.pages.insert(url.clone(), state);
+                        }
+                    }
+                }
+                Ok(Fetched::NotModified) => {
+                    if let Some(state) = self.knowledge.write().unwrap().pages.get_mut(url) {
+                        state.checked_at = chrono::Utc::now();
+                        state.gone = None;
+                    }
+                }
+                Ok(Fetched::Failed(status)) if is_gone(status) => self.knowledge.write().unwrap().mark_gone(url, status),
+                Ok(_) => {}
+                Err(e) => status!("Error checking {}: {}", url, e),
+            }
+        }
+        self.save_knowledge().await?;
+        Ok(self.knowledge.read().unwrap().gone_sources().len())
+    }
+
+    /// Walks through the sources that are gone, asking whether to keep the facts learned
+    /// from each, remove them, or learn from a replacement URL instead.
+    async fn review_gone_sources(&mut self) -> Result<(), Box<dyn std::error::Error>> {
+        let gone: Vec<(String, u16, chrono::DateTime<chrono::Utc>, usize)> = {
+            let knowledge = self.knowledge.read().unwrap();
+            knowledge
+                .gone_sources()
+                .into_iter()
+                .filter_map(|url| {
+                    let gone = knowledge.pages.get(&url)?.gone.clone()?;
+                    let facts = knowledge.facts_from(&url).len();
+                    Some((url, gone.status, gone.since, facts))
+                })
+                .collect()
+        };
+        if gone.is_empty() {
+            status!("All learned sources are still there.");
+            return Ok(());
+        }
+
+        for (url, status, since, facts) in gone {
+            status!("\n{} is gone (HTTP {} since {}), {} fact(s) were learned from it.", url, status, since.format("%Y-%m-%d"), facts);
+            status!("Type 'remove' to forget them, a new URL to learn from instead, or press Enter to keep them:");
+            let answer = input::read_line().await?.unwrap_or_default();
+            match answer.trim() {
+                "remove" => {
+                    self.forget_source(&url).await?;
+                    status!("Removed {} fact(s) learned from {}", facts, url);
+                }
+                replacement if replacement.starts_with("http://") || replacement.starts_with("https://") => {
+                    self.learn_from_url(replacement).await?;
+                    if !self.knowledge.read().unwrap().learned_urls.iter().any(|learned| learned == replacement) {
+                        status!("Could not learn from {}, keeping the facts from {}", replacement, url);
+                        continue;
+                    }
+                    self.forget_source(&url).await?;
+                    for source in self.config.knowledge_sources.self_learning_urls.iter_mut() {
+                        if source.url() == url {
+                            *source = LearningSource::Url(replacement.to_string());
+                        }
+                    }
+                    self.save_config()?;
+                    status!("Replaced {} with {}", url, replacement);
+                }
+                _ => status!("Keeping the facts from {}", url),
+            }
+        }
+        Ok(())
+    }
+
+    /// Deletes facts from the store, the vector index and the loaded knowledge.
+    async fn remove_facts(&self, keys: &[String]) -> Result<(), Box<dyn std::error::Error>> {
+        if keys.is_empty() {
+            return Ok(());
+        }
+        for key in keys {
+            self.store.delete_fact(key).await?;
+            self.vector_index.write().await.remove(key).await?;
+            self.track_indexed(key, false);
+        }
+        self.vector_index.read().await.flush().await?;
+        let mut knowledge = self.knowledge.write().unwrap();
+        for key in keys {
+            knowledge.facts.remove(key);
+        }
+        Ok(())
+    }
+
+    /// Forgets a learned page: the facts learned from it, and the page as a learning source.
+    async fn forget_source(&mut self, url: &str) -> Result<(), Box<dyn std::error::Error>> {
+        let keys = self.knowledge.read().unwrap().facts_from(url);
+        self.remove_facts(&keys).await?;
+        {
+            let mut knowledge = self.knowledge.write().unwrap();
+            knowledge.learned_urls.retain(|learned| learned != url);
+            knowledge.pages.remove(url);
+            knowledge.cached_content.remove(url);
+        }
+        let sources = &mut self.config.knowledge_sources.self_learning_urls;
+        if sources.iter().any(|source| source.url() == url) {
+            sources.retain(|source| source.url() != url);
+            self.save_config()?;
+        }
+        self.save_knowledge().await
+    }
+
+    /// Re-fetches the learned pages and learns again from those whose content changed.
+    /// Requests are conditional on the stored ETag and Last-Modified, so unchanged pages
+    /// are usually not downloaded at all. Pages learned before their state was recorded
+    /// only get it recorded.
+    async fn refresh_pages(&self) -> Result<(), Box<dyn std::error::Error>> {
+        self.load_knowledge().await?;
+        let urls = self.knowledge.read().unwrap().learned_urls.clone();
+        let (mut updated, mut unchanged, mut failed) = (0, 0, 0);
+        for (index, url) in urls.iter().enumerate() {
+            if index > 0 {
+                tokio::time::sleep(CRAWL_DELAY).await;
+            }
+            let known = self.knowledge.read().unwrap().pages.get(url).cloned();
+            let page = match self.fetch_page(url, known.as_ref()).await {
+                Ok(Fetched::Page(page)) => page,
+                Ok(Fetched::NotModified) => {
+                    if let Some(state) = self.knowledge.write().unwrap().pages.get_mut(url) {
+                        state.checked_at = chrono::Utc::now();
+                        state.gone = None;
+                    }
+                    unchanged += 1;
+                    continue;
+                }
+                Ok(Fetched::Failed(status)) => {
+                    if is_gone(status) {
+                        self.knowledge.write().unwrap().mark_gone(url, status);
+                    }
+                    failed += 1;
+                    continue;
+                }
+                Ok(Fetched::Skipped) => {
+                    failed += 1;
+                    continue;
+                }
+                Err(e) => {
+                    status!("Error fetching {}: {}", url, e);
+                    failed += 1;
+                    continue;
+                }
+            };
+
+            let state = PageState::new(&page.content(), page.etag.clone(), page.last_modified.clone());
+            match known {
+                Some(known) if !known.content_hash.is_empty() && known.content_hash != state.content_hash => {
+                    status!("Page changed, learning from it again: {}", url);
+                    match self.learn_from_page(url, &page).await {
+                        Ok(()) => updated += 1,
+                        Err(e) => {
+                            status!("Error learning from {}: {}", url, e);
+                            failed += 1;
+                        }
+                    }
+                }
+                _ => {
+                    let mut state = state;
+                    state.sections = known.map(|known| known.sections).unwrap_or_default();
+                    self.knowledge.write().unwrap().pages.insert(url.clone(), state);
+                    unchanged += 1;
+                }
+            }
+        }
+
+        self.save_knowledge().await?;
+        status!("{} page(s) updated, {} unchanged, {} could not be checked", updated, unchanged, failed);
+        Ok(())
+    }
+
+    /// Adds the pages listed in a sitemap, optionally only those matching `pattern`, to the
+    /// learning sources. Returns how many pages matched and how many of them were new.
+    async fn add_sitemap(&mut self, url: &str, pattern: Option<&str>) -> Result<(usize, usize), Box<dyn std::error::Error>> {
+        let client = reqwest::Client::builder()
+            .timeout(std::time::Duration::from_secs(30))
+            .build()?;
+        let mut urls = sitemap_urls(&client, url, pattern).await?;
+        urls.retain(|url| self.config.learning.domains.allows(url));
+
+        let mut added = 0;
+        {
+            let knowledge = self.knowledge.read().unwrap();
+            let sources = &mut self.config.knowledge_sources.self_learning_urls;
+            for url in &urls {
+                if !sources.iter().any(|source| source.url() == *url) && !knowledge.learned_urls.contains(url) {
+                    sources.push(LearningSource::Url(url.clone()));
+                    added += 1;
+                }
+            }
+        }
+        self.save_config()?;
+        Ok((urls.len(), added))
+    }
+
+    /// Learns from the entries of the subscribed feeds that were not seen before. Feeds
+    /// checked within `FEED_CHECK_INTERVAL` are skipped unless `force` is set.
+    async fn learn_from_feeds(&self, force: bool) -> Result<(), Box<dyn std::error::Error>> {
+        let client = reqwest::Client::builder()
+            .timeout(std::time::Duration::from_secs(20))
+            .build()?;
+        for url in &self.config.knowledge_sources.feeds {
+            let known = self.knowledge.read().unwrap().feeds.get(url).cloned();
+            if !force && known.as_ref().is_some_and(|known| chrono::Utc::now() - known.checked_at < FEED_CHECK_INTERVAL) {
+                continue;
+            }
+            if !self.config.learning.domains.allows(url) {
+                status!("Skipping feed outside the allowed domains: {}", url);
+                continue;
+            }
+            if !self.robots.allows(url).await {
+                status!("Skipping feed disallowed by the site's robots.txt: {}", url);
+                continue;
+            }
+
+            status!("Checking feed: {}", url);
+            let feed = match fetch_feed(&client, url).await {
+                Ok(feed) => feed,
+                Err(e) => {
+                    status!("Error reading feed {}: {}", url, e);
+                    continue;
+                }
+            };
+            let mut seen = known.as_ref().map(|known| known.seen.clone()).unwrap_or_default();
+            let mut new: Vec<&FeedEntry> = feed.entries.iter().filter(|entry| !seen.contains(&entry.id)).collect();
+            new.sort_by_key(|entry| std::cmp::Reverse(entry.published));
+            let limit = if known.is_some() { MAX_FEED_ENTRIES } else { FIRST_FEED_ENTRIES };
+            if new.len() > limit {
+                status!("{} new entries, learning from the newest {}", new.len(), limit);
+            }
+
+            let mut learned = 0;
+            // Oldest first, so the newest entry is also the last learned
+            for (index, entry) in new.iter().enumerate().rev() {
+                if index < limit {
+                    match self.learn_from_feed_entry(url, &feed.title, entry).await {
+                        Ok(true) => learned += 1,
+                        Ok(false) => {}
+                        Err(e) => {
+                            status!("Error learning from feed entry {}: {}", entry.title, e);
+                            // Try again on the next check
+                            continue;
+                        }
+                    }
+                }
+                seen.insert(0, entry.id.clone());
+            }
+            seen.truncate(MAX_SEEN_ENTRIES);
+            let state = FeedState {
+                checked_at: chrono::Utc::now(),
+                seen,
+            };
+            self.knowledge.write().unwrap().feeds.insert(url.clone(), state);
+            self.save_knowledge().await?;
+            status!("Learned from {} new entr(ies) of {}", learned, if feed.title.is_empty() { url } else { &feed.title });
+        }
+        Ok(())
+    }
+
+    /// Summarizes a feed entry as news the character heard, fetching the linked article
+    /// when the feed only has a teaser. Returns false if the entry was not about the
+    /// character or their series.
+    async fn learn_from_feed_entry(&self, feed_url: &str, feed_title: &str, entry: &FeedEntry) -> Result<bool, Box<dyn std::error::Error>> {
+        let mut content = entry.content.clone();
+        if let Some(link) = entry.link.as_deref().filter(|_| content.chars().count() < MIN_ENTRY_TEXT) {
+            if let Ok(Fetched::Page(page)) = self.fetch_page(link, None).await {
+                content = page.content();
+            }
+        }
+        if content.trim().is_empty() && entry.title.is_empty() {
+            return Ok(false);
+        }
+
+        let published = entry.published.map(|date| date.format("%Y-%m-%d").to_string()).unwrap_or_else(|| "recently".to_string());
+        let prompt = format!(
+            "You are {}. The following entry of the feed \"{}\" was published {}. If it has nothing to do with you, \
+            the people around you or the series you are from, answer only with IRRELEVANT. Otherwise summarize what it reports \
+            in a few sentences, keeping names, dates and numbers, so you can bring it up in conversation as recent news:\n\n# {}\n\n{}",
+            self.config.character.name, feed_title, published, entry.title, content
+        );
+        let summary = self.generate(&prompt).await?;
+        if summary.trim().is_empty() || summary.trim().eq_ignore_ascii_case("IRRELEVANT") {
+            status!("Not relevant: {}", entry.title);
+            return Ok(false);
+        }
+
+        status!("Learned from feed entry: {}", entry.title);
+        let text = format!("News from {} ({}): {}\n\n{}", if feed_title.is_empty() { feed_url } else { feed_title }, published, entry.title, summary.trim());
+        let tags = self.classify_fact(&text).await;
+        let source = entry.link.clone().unwrap_or_else(|| feed_url.to_string());
+        let fact = Fact::new(text, Some(source), LearnMethod::Feed, tags);
+        self.store_fact(format!("feed_{}", entry.id), fact).await?;
+        Ok(true)
+    }
+
+    /// Learns the Wikipedia article about `topic` as background knowledge, stored as
+    /// written. Articles learned before are only looked up again with `force`. Returns
+    /// the text learned.
+    async fn learn_wikipedia(
+        &self,
+        language: &str,
+        topic: &str,
+        sections: &[String],
+        force: bool,
+    ) -> Result<String, Box<dyn std::error::Error>> {
+        let key = format!("wikipedia_{}_{}", language, topic.trim().to_lowercase().replace(' ', "_"));
+        if !force {
+            if let Some(fact) = self.knowledge.read().unwrap().facts.get(&key) {
+                status!("Already learned about {} from Wikipedia", topic);
+                return Ok(fact.text.clone());
+            }
+        }
+        let url = wikipedia_url(language, topic)?;
+        if !self.config.learning.domains.allows(&url) {
+            return Err(format!("{} is outside the allowed domains in learning.domains", url).into());
+        }
+
+        status!("Looking up {} on Wikipedia ({})...", topic, language);
+        let client = reqwest::Client::builder()
+            .timeout(std::time::Duration::from_secs(20))
+            .build()?;
+        let article = fetch_wikipedia(&client, language, topic, sections).await?;
+        let text = article.text();
+        let fact = Fact::new(text.clone(), Some(article.url), LearnMethod::Reference, vec![FactTag::Background]);
+        self.put_fact(key, fact).await?;
+        self.save_knowledge().await?;
+        Ok(text)
+    }
+
+    /// Learns the AniList entry of the character called `name` as facts kept as written:
+    /// the profile, the description with its spoilers apart and tagged as plot, the works
+    /// the character appears in, and the characters around them. Entries learned before
+    /// are only looked up again with `force`. Returns the profile.
+    async fn learn_anilist(&self, name: &str, force: bool) -> Result<String, Box<dyn std::error::Error>> {
+        let prefix = format!("anilist_{}", name.trim().to_lowercase().replace(' ', "_"));
+        if !force {
+            if let Some(fact) = self.knowledge.read().unwrap().facts.get(&format!("{}_profile", prefix)) {
+                status!("Already learned about {} from AniList", name);
+                return Ok(fact.text.clone());
+            }
+        }
+        let url = anilist_search_url(name)?;
+        if !self.config.learning.domains.allows(&url) {
+            return Err(format!("{} is outside the allowed domains in learning.domains", url).into());
+        }
+
+        status!("Looking up {} on AniList...", name);
+        let client = reqwest::Client::builder()
+            .timeout(std::time::Duration::from_secs(20))
+            .build()?;
+        let character = fetch_anilist_character(&client, name).await?;
+        let profile = character.profile();
+        let mut facts = vec![("profile", profile.clone(), self.classify_fact(&profile).await)];
+        if !character.description.is_empty() {
+            let description = format!("{}, as described on AniList:\n\n{}", character.name, character.description);
+            let tags = self.classify_fact(&description).await;
+            facts.push(("description", description, tags));
+        }
+        if !character.spoilers.is_empty() {
+            let spoilers = format!("Spoilers about {}, from AniList:\n\n{}", character.name, character.spoilers.join("\n\n"));
+            facts.push(("spoilers", spoilers, vec![FactTag::Plot]));
+        }
+        if let Some(appearances) = character.appearances_text() {
+            facts.push(("appearances", appearances, vec![FactTag::Background]));
+        }
+        if let Some(related) = character.related_text() {
+            facts.push(("related", related, vec![FactTag::Relationships]));
+        }
+        for (part, text, tags) in facts {
+            let fact = Fact::new(text, Some(character.url.clone()), LearnMethod::Reference, tags);
+            self.put_fact(format!("{}_{}", prefix, part), fact).await?;
+        }
+        self.save_knowledge().await?;
+        Ok(profile)
+    }
+
+    /// Learns the VNDB entry of the visual-novel character called `name` as facts kept as
+    /// written: the profile with its measurements, the traits, the description, and the
+    /// visual novels the character is in with their summaries. What VNDB marks as a spoiler
+    /// is only stored as far as `learning.vndb.spoiler_level` allows; parts a lower level
+    /// no longer allows are removed. Entries learned before are only looked up again with
+    /// `force`. Returns the profile.
+    async fn learn_vndb(&self, name: &str, force: bool) -> Result<String, Box<dyn std::error::Error>> {
+        let prefix = format!("vndb_{}", name.trim().to_lowercase().replace(' ', "_"));
+        if !force {
+            if let Some(fact) = self.knowledge.read().unwrap().facts.get(&format!("{}_profile", prefix)) {
+                status!("Already learned about {} from VNDB", name);
+                return Ok(fact.text.clone());
+            }
+        }
+        let url = vndb_search_url(name)?;
+        if !self.config.learning.domains.allows(&url) {
+            return Err(format!("{} is outside the allowed domains in learning.domains", url).into());
+        }
+
+        status!("Looking up {} on VNDB...", name);
+        let client = reqwest::Client::builder()
+            .timeout(std::time::Duration::from_secs(20))
+            .build()?;
+        let character = fetch_vndb_character(&client, name, self.config.learning.vndb.spoiler_level).await?;
+        let profile = character.profile();
+        let mut facts = vec![("profile", profile.clone(), self.classify_fact(&profile).await)];
+        if let Some(traits) = character.traits_text() {
+            facts.push(("traits", traits, vec![FactTag::Personality]));
+        }
+        if !character.description.is_empty() {
+            let description = format!("{}, as described on VNDB:\n\n{}", character.name, character.description);
+            let tags = self.classify_fact(&description).await;
+            facts.push(("description", description, tags));
+        }
+        if !character.spoilers.is_empty() {
+            let spoilers = format!("Spoilers about {}, from VNDB:\n\n{}", character.name, character.spoilers.join("\n\n"));
+            facts.push(("spoilers", spoilers, vec![FactTag::Plot]));
+        }
+        if let Some(routes) = character.routes_text() {
+            facts.push(("routes", routes, vec![FactTag::Plot]));
+        }
+
+        let keys: HashSet<String> = facts.iter().map(|(part, _, _)| format!("{}_{}", prefix, part)).collect();
+        let stale: Vec<String> = self
+            .knowledge
+            .read()
+            .unwrap()
+            .facts
+            .iter()
+            .filter(|(key, fact)| fact.method == LearnMethod::Reference && key.starts_with(&format!("{}_", prefix)) && !keys.contains(*key))
+            .map(|(key, _)| key.clone())
+            .collect();
+        self.remove_facts(&stale).await?;
+        for (part, text, tags) in facts {
+            let fact = Fact::new(text, Some(character.url.clone()), LearnMethod::Reference, tags);
+            self.put_fact(format!("{}_{}", prefix, part), fact).await?;
+        }
+        self.save_knowledge().await?;
+        Ok(profile)
+    }
+
+    /// Subscribes to the feed at `url` after checking that it can be read.
+    async fn add_feed(&mut self, url: &str) -> Result<(), Box<dyn std::error::Error>> {
+        if self.config.knowledge_sources.feeds.iter().any(|feed| feed == url) {
+            status!("Already subscribed to {}", url);
+            return Ok(());
+        }
+        if !self.config.learning.domains.allows(url) {
+            return Err(format!("{} is outside the allowed domains in learning.domains", url).into());
+        }
+        let client = reqwest::Client::builder()
+            .timeout(std::time::Duration::from_secs(20))
+            .build()?;
+        let feed = fetch_feed(&client, url).await?;
+        self.config.knowledge_sources.feeds.push(url.to_string());
+        self.save_config()?;
+        status!("Subscribed to {} ({} entries)", if feed.title.is_empty() { url } else { &feed.title }, feed.entries.len());
+        Ok(())
+    }
+
+    /// Re-fetches the sources of facts whose confidence has decayed below the threshold.
+    async fn reverify_stale_facts(&self) -> Result<(), Box<dyn std::error::Error>> {
+        let settings = &self.config.learning;
+        let stale_urls = self
+            .knowledge
+            .read()
+            .unwrap()
+            .urls_needing_reverification(settings.confidence_half_life_days, settings.reverify_threshold);
+        if stale_urls.is_empty() {
+            return Ok(());
+        }
+
+        status!("Re-verifying {} source(s) with low confidence...", stale_urls.len());
+        for url in stale_urls {
+            // Forget the URL so learn_from_url fetches it again and replaces the fact
+            self.knowledge.write().unwrap().learned_urls.retain(|learned| *learned != url);
+            if let Err(e) = self.learn_from_url(&url).await {
+                status!("Error re-verifying {}: {}", url, e);
+            }
+            // Learning from the page lists it again; when the page was skipped, unchanged or
+            // failed, the old fact is kept and retried on the next run
+            let mut knowledge = self.knowledge.write().unwrap();
+            if !knowledge.learned_urls.contains(&url) {
+                knowledge.learned_urls.push(url);
+            }
+        }
+        self.save_knowledge().await?;
+        Ok(())
+    }
+
+    /// Searches the web and learns from the first `search.results` results, several pages
+    /// at a time. Results learned from before are not fetched again, but what was learned
+    /// from them still counts. Returns the snippets and the knowledge of all results merged
+    /// into one text.
+    async fn search_web(&self, query: &str) -> Result<String, Box<dyn std::error::Error>> {
+        status!("Executing web search for: {}", query);
+        status!("Searching with {}...", self.search.name());
+        let settings = &self.config.search;
+        // Ask for more results than are learned from, to have some left after the rules
+        let found = self.search.search(query, settings.results * SEARCH_OVERFETCH).await?;
+        let total = found.len();
+        let mut seen = HashSet::new();
+        let mut results = settings.rules.apply(found);
+        results.retain(|result| seen.insert(result.url.clone()));
+        results.truncate(settings.results);
+        status!("Found {} search results, learning from {}", total, results.len());
+
+        let new: Vec<&str> = {
+            let knowledge = self.knowledge.read().unwrap();
+            results
+                .iter()
+                .map(|result| result.url.as_str())
+                .filter(|url| !knowledge.learned_urls.iter().any(|learned| learned == url))
+                .collect()
+        };
+        if new.len() < results.len() {
+            status!("{} result(s) already learned from", results.len() - new.len());
+        }
+        futures::stream::iter(new)
+            .for_each_concurrent(settings.concurrency.max(1), |url| async move {
+                status!("Processing URL: {}", url);
+                if let Err(e) = self.learn_from_url(url).await {
+                    status!("Error processing {}: {}", url, e);
+                }
+            })
+            .await;
+
+        let mut content = String::new();
+        for result in &results {
+            if !result.snippet.is_empty() {
+                content.push_str(&format!("{}: {}\n\n", result.title, result.snippet));
+            }
+        }
+        // Process search content with AI
+        status!("Processing search results with AI...");
+        let mut parts = vec![self.process_with_ai(&content).await?];
+        let keys: Vec<String> = results.iter().map(|result| format!("personal_knowledge_{}", result.url)).collect();
+        let mut learned = self.load_facts(&keys).await?;
+        parts.extend(keys.iter().filter_map(|key| learned.remove(key)).map(|fact| fact.text));
+        parts.retain(|part| !part.trim().is_empty());
+        self.consolidate(parts).await
+    }
+
+    /// Builds the prompt context from the given facts, numbered in the order given. With
+    /// `allow_search` the model may ask for a web search instead of answering.
+    async fn get_context(&self, session: &str, fact_keys: &[String], allow_search: bool) -> String {
+        let mut context = format!(
+            "You are a chatbot named {}. Your personality: {}. Description: {}. Traits: {}. Interests: {}.\n",
+            self.config.character.name,
+            self.config.character.personality,
+            self.config.character.description,
+            self.config.character.traits.join(", "),
+            self.config.character.interests.join(", ")
+        );
+
+        context.push_str(&format!("Additional context: {}\n", self.config.knowledge_sources.additional_context));
+
+        let facts = match self.load_facts(fact_keys).await {
+            Ok(facts) => facts,
+            Err(e) => {
+                status!("Error loading facts: {}", e);
+                HashMap::new()
+            }
+        };
+        // Add the learned facts selected for this message
+        for (i, key) in fact_keys.iter().enumerate() {
+            let Some(fact) = facts.get(key) else {
+                continue;
+            };
+            let reliability = if fact.method == LearnMethod::Dataset {
+                " (curated answer, prefer it to other knowledge)"
+            } else if fact.current_confidence(self.config.learning.confidence_half_life_days)
+                < self.config.learning.reverify_threshold
+            {
+                " (low confidence, may be outdated)"
+            } else {
+                ""
+            };
+            context.push_str(&format!("\nKnowledge [{}] from {}{}:\n{}\n", i + 1, key, reliability, fact.text));
+        }
+
+        if !self.config.character.examples.is_empty() {
+            context.push_str("\nExample exchanges showing how you talk:\n");
+            for example in &self.config.character.examples {
+                context.push_str(&format!("User: {}\n{}: {}\n", example.user, self.config.character.name, example.reply));
+            }
+        }
+
+        if self.conversation(session).next().is_some() {
+            context.push_str("\nPrevious conversation context:\n");
+            for msg in self.conversation(session) {
+                context.push_str(&format!("{}\n", msg));
+            }
+        }
+
+        // Add personality guidance
+        context.push_str("\nRemember to stay in character and respond according to your personality traits. ");
+        context.push_str("If you're asked about something you don't know, be honest about it. ");
+        context.push_str("Use your learned knowledge to provide detailed and accurate responses.\n");
+
+        if allow_search {
+            context.push_str(&format!(
+                "If the user asks about you, your story or the people and things in it, and neither your knowledge entries \
+                nor your description answer it, do not guess: reply with only one line, \"{} <a short web search query that \
+                would find the answer>\".\n",
+                SEARCH_MARKER
+            ));
+        }
+
+        if self.config.conversation_settings.user_memory {
+            context.push_str(&format!(
+                "If the user's message tells you something about them worth remembering in later conversations, \
+                such as their name, what they like or what they are going through, add a line after your reply \
+                starting with \"{}\" followed by it as a short statement about \"the user\". Leave it out otherwise.\n",
+                MEMORY_MARKER
+            ));
+        }
+
+        if self.config.conversation_settings.citations {
+            context.push_str(&format!(
+                "After your reply, add a final line starting with \"{}\" followed by the comma-separated numbers \
+                of the knowledge entries your reply relied on, or \"none\" if it did not rely on any.\n",
+                CITATION_MARKER
+            ));
+        }
+
+        context
+    }
+
+    /// Answers a chat message in character and keeps the exchange in the session's history.
+    /// When live search is on and the model finds the knowledge does not answer the message,
+    /// the web is searched, what is found is learned, and the message is answered again from
+    /// it. What the user tells about themselves is remembered for the session only.
+    /// Returns `None` if the model gave no reply.
+    /// With `stream`, the reply is also sent there as the model writes it.
+    async fn respond(
+        &mut self,
+        session: &str,
+        input: &str,
+        stream: Option<&mpsc::UnboundedSender<String>>,
+    ) -> Result<Option<Reply>, Box<dyn std::error::Error>> {
+        self.webhooks.emit(EventKind::MessageReceived, json!({ "session": session, "message": input }));
+        let result = self.compose_reply(session, input, stream).await;
+        match &result {
+            Ok(Some(reply)) => self.webhooks.emit(EventKind::ReplyGenerated, json!({ "session": session, "message": input, "reply": reply })),
+            Ok(None) => self.webhooks.emit(EventKind::Error, json!({ "session": session, "error": "The model gave no reply" })),
+            Err(e) => self.webhooks.emit(EventKind::Error, json!({ "session": session, "error": e.to_string() })),
+        }
+        result
+    }
+
+    /// `respond`, apart from the webhook events.
+    async fn compose_reply(
+        &mut self,
+        session: &str,
+        input: &str,
+        stream: Option<&mpsc::UnboundedSender<String>>,
+    ) -> Result<Option<Reply>, Box<dyn std::error::Error>> {
+        self.open_session(session).await?;
+        let started = std::time::Instant::now();
+        let (requests_before, tokens_before) = {
+            let report = self.report.lock().unwrap();
+            (report.model_requests, report.tokens)
+        };
+        self.add_to_history(session, &format!("User: {}", input));
+        let mut fact_keys = self.select_facts(session, input).await;
+        let live_search = self.config.conversation_settings.live_search;
+        let mut reply = self.generate_reply(session, input, &fact_keys, live_search, stream).await?;
+
+        if let Some(query) = reply.trim().strip_prefix(SEARCH_MARKER).map(str::trim).filter(|query| !query.is_empty()) {
+            status!("\n{}: Let me check...", self.config.character.name);
+            match self.search_web(query).await {
+                Ok(found) => {
+                    fact_keys = self.select_facts(session, input).await;
+                    if !found.trim().is_empty() {
+                        let key = format!("live_search_{}", query.to_lowercase());
+                        let tags = self.classify_fact(&found).await;
+                        let fact = Fact::new(found, None, LearnMethod::WebSearch, tags);
+                        self.store_fact(key.clone(), fact).await?;
+                        {
+                            let mut knowledge = self.knowledge.write().unwrap();
+                            if !knowledge.search_history.iter().any(|searched| searched == query) {
+                                knowledge.search_history.push(query.to_string());
+                            }
+                        }
+                        self.save_knowledge().await?;
+                        if self.knowledge.read().unwrap().facts.contains_key(&key) && !fact_keys.contains(&key) {
+                            fact_keys.insert(0, key);
+                        }
+                    }
+                }
+                Err(e) => status!("Could not search the web: {}", e),
+            }
+            reply = self.generate_reply(session, input, &fact_keys, false, stream).await?;
+        }
+
+        let (reply, memories) = extract_memories(&reply);
+        if reply.trim().is_empty() {
+            return Ok(None);
+        }
+        self.remember(session, memories).await?;
+        let (reply, sources) = self.extract_citations(&reply, &fact_keys);
+        self.add_to_history(session, &format!("{}: {}", self.config.character.name, reply));
+        let report = self.report.lock().unwrap();
+        Ok(Some(Reply {
+            text: reply,
+            sources,
+            fact_keys,
+            model_requests: report.model_requests - requests_before,
+            tokens: report.tokens - tokens_before,
+            latency_ms: started.elapsed().as_millis(),
+        }))
+    }
+
+    /// Asks the model for the character's reply to `input`, given the selected facts, and
+    /// streams it to `stream` without the lines meant for the chatbot.
+    async fn generate_reply(
+        &self,
+        session: &str,
+        input: &str,
+        fact_keys: &[String],
+        allow_search: bool,
+        stream: Option<&mpsc::UnboundedSender<String>>,
+    ) -> Result<String, Box<dyn std::error::Error>> {
+        let context = self.get_context(session, fact_keys, allow_search).await;
+        let prompt = format!("{}\n\nUser: {}\n{}: ", context, input, self.config.character.name);
+        let markers = [SEARCH_MARKER, MEMORY_MARKER, CITATION_MARKER];
+        let clients = self.mcp_clients().await;
+        if !clients.is_empty() {
+            // The tool calls come before the reply, which is streamed whole
+            let reply = self.generate_with_tools(&prompt, clients).await?;
+            if let Some(stream) = stream {
+                let mut filter = MarkerFilter::new(&markers, stream);
+                filter.push(&reply);
+                filter.finish();
+            }
+            return Ok(reply);
+        }
+        let Some(stream) = stream else {
+            return self.generate(&prompt).await;
+        };
+        let mut filter = MarkerFilter::new(&markers, stream);
+        let reply = self.generate_streaming(&prompt, &mut |piece| filter.push(piece)).await?;
+        filter.finish();
+        Ok(reply)
+    }
+
+    /// Does what an HTTP API request asks for and returns the response body.
+    async fn handle_api(&mut self, call: ApiCall) -> ApiResult {
+        let internal = |e: Box<dyn std::error::Error>| ApiError::Internal(e.to_string());
+        match call {
+            ApiCall::Chat { session, message, stream } => match self.respond(&session, &message, stream.as_ref()).await.map_err(internal)? {
+                Some(reply) => {
+                    self.save_session(&session).await.map_err(internal)?;
+                    Ok(json!(reply))
+                }
+                None => Err(ApiError::Internal("The model gave no reply".to_string())),
+            },
+            ApiCall::Character => Ok(json!(self.config.character)),
+            ApiCall::UpdateCharacter { personality, description } => {
+                if let Some(personality) = personality {
+                    self.config.character.personality = personality;
+                }
+                if let Some(description) = description {
+                    self.config.character.description = description;
+                }
+                self.save_config().map_err(internal)?;
+                Ok(json!(self.config.character))
+            }
+            ApiCall::Memories(session) => {
+                let knowledge = self.knowledge.read().unwrap();
+                let memories: Vec<Value> = knowledge
+                    .facts
+                    .iter()
+                    .filter(|(_, fact)| fact.user.as_deref() == Some(session.as_str()))
+                    .map(|(key, fact)| fact_json(key, fact))
+                    .collect();
+                Ok(json!(memories))
+            }
+            ApiCall::Forget(session) => {
+                let keys: Vec<String> = {
+                    let knowledge = self.knowledge.read().unwrap();
+                    knowledge.facts.iter().filter(|(_, fact)| fact.user.as_deref() == Some(session.as_str())).map(|(key, _)| key.clone()).collect()
+                };
+                self.remove_facts(&keys).await.map_err(internal)?;
+                self.save_knowledge().await.map_err(internal)?;
+                self.conversations.remove(&session);
+                self.store.save_session(&session, &[]).await.map_err(internal)?;
+                Ok(json!({ "forgotten": keys.len() }))
+            }
+            ApiCall::Facts { filter, limit } => {
+                // Memories about users are theirs alone, so they are left out of the knowledge
+                let facts = self.store.query(&fact_query(&filter)).await.map_err(internal)?;
+                let facts: Vec<Value> = facts
+                    .iter()
+                    .filter(|(_, fact)| fact.user.is_none())
+                    .take(limit.unwrap_or(usize::MAX))
+                    .map(|(key, fact)| fact_json(key, fact))
+                    .collect();
+                Ok(json!(facts))
+            }
+            ApiCall::Retrieve { session, query, limit } => {
+                let keys: Vec<String> = self.select_facts(&session, &query).await.into_iter().take(limit).collect();
+                let facts = self.load_facts(&keys).await.map_err(internal)?;
+                Ok(json!(keys.iter().filter_map(|key| facts.get(key).map(|fact| fact_json(key, fact))).collect::<Vec<_>>()))
+            }
+            ApiCall::Fact(key) => match self.store.load_fact(&key).await.map_err(internal)? {
+                Some(fact) if fact.user.is_none() => Ok(fact_json(&key, &fact)),
+                _ => Err(ApiError::NotFound(format!("There is no fact {}", key))),
+            },
+            ApiCall::DeleteFact(key) => {
+                if !self.knowledge.read().unwrap().facts.contains_key(&key) {
+                    return Err(ApiError::NotFound(format!("There is no fact {}", key)));
+                }
+                self.remove_facts(std::slice::from_ref(&key)).await.map_err(internal)?;
+                self.save_knowledge().await.map_err(internal)?;
+                Ok(json!({ "deleted": key }))
+            }
+            ApiCall::UpdateFact { key, text } => {
+                let Some(mut fact) = self.store.load_fact(&key).await.map_err(internal)?.filter(|fact| fact.user.is_none()) else {
+                    return Err(ApiError::NotFound(format!("There is no fact {}", key)));
+                };
+                // Whoever edits it vouches for the new text
+                fact.text = text;
+                fact.verified_at = chrono::Utc::now();
+                self.put_fact(key.clone(), fact.clone()).await.map_err(internal)?;
+                self.save_knowledge().await.map_err(internal)?;
+                Ok(fact_json(&key, &fact))
+            }
+            ApiCall::Train(text) => {
+                let before = self.knowledge.read().unwrap().facts.len();
+                self.train_with_text(&text).await.map_err(internal)?;
+                let facts_added = self.knowledge.read().unwrap().facts.len().saturating_sub(before);
+                Ok(json!({ "facts_added": facts_added }))
+            }
+            ApiCall::Learn => {
+                self.learn_about_self().await.map_err(internal)?;
+                self.scheduler.lock().unwrap().record("learn", None).map_err(internal)?;
+                Ok(json!(*self.report.lock().unwrap()))
+            }
+            ApiCall::RunJob(name) => {
+                if !JOBS.iter().any(|(job, _)| *job == name) {
+                    return Err(ApiError::NotFound(format!("There is no job called {}", name)));
+                }
+                self.run_job(&name).await;
+                let scheduler = self.scheduler.lock().unwrap();
+                match scheduler.last_run(&name) {
+                    Some(run) => match &run.error {
+                        Some(error) => Err(ApiError::Internal(error.clone())),
+                        None => Ok(json!({ "job": name, "ran_at": run.last_run })),
+                    },
+                    None => Err(ApiError::Internal(format!("{} did not run", name))),
+                }
+            }
+        }
+    }
+
+    /// Stores what the model noted about the user as memories only the session sees,
+    /// skipping any it already remembers.
+    async fn remember(&self, session: &str, memories: Vec<String>) -> Result<(), Box<dyn std::error::Error>> {
+        for (i, memory) in memories.into_iter().enumerate() {
+            let known = self
+                .knowledge
+                .read()
+                .unwrap()
+                .facts
+                .values()
+                .any(|fact| fact.user.as_deref() == Some(session) && fact.text.eq_ignore_ascii_case(&memory));
+            if known {
+                continue;
+            }
+            let key = format!("memory_{}_{}_{}", session, chrono::Utc::now().timestamp(), i);
+            let mut fact = Fact::new(memory, None, LearnMethod::Conversation, vec![FactTag::UserInfo]);
+            fact.user = Some(session.to_string());
+            self.put_fact(key, fact).await?;
+        }
+        Ok(())
+    }
+
+    /// Strips the citation line the model was asked to add and turns the referenced
+    /// knowledge entries into a list of source URLs.
+    fn extract_citations(&self, reply: &str, fact_keys: &[String]) -> (String, Vec<String>) {
+        let mut lines: Vec<&str> = reply.trim_end().lines().collect();
+        let citation_line = match lines.last() {
+            Some(line) if line.trim().to_uppercase().starts_with(CITATION_MARKER) => lines.pop().unwrap_or_default(),
+            _ => return (reply.trim().to_string(), Vec::new()),
+        };
+        let text = lines.join("\n");
+
+        let mut sources = Vec::new();
+        if let Ok(knowledge) = self.knowledge.read() {
+            let numbers = citation_line
+                .split(|c: char| !c.is_ascii_digit())
+                .filter_map(|n| n.parse::<usize>().ok());
+            for number in numbers {
+                let Some(key) = number.checked_sub(1).and_then(|i| fact_keys.get(i)) else {
+                    continue;
+                };
+                if let Some(url) = knowledge.facts.get(key).and_then(|fact| fact.source_url.clone()) {
+                    if !sources.contains(&url) {
+                        sources.push(url);
+                    }
+                }
+            }
+        }
+
+        (text.trim().to_string(), sources)
+    }
+
+    /// Lists stored facts; `filter` is a tag name or text to search for, empty for all facts.
+    async fn print_facts(&self, filter: &str) -> Result<(), Box<dyn std::error::Error>> {
+        let facts = self.store.query(&fact_query(filter)).await?;
+        if facts.is_empty() {
+            if filter.is_empty() {
+                println!("I haven't learned any facts yet.");
+            } else {
+                println!("No facts match '{}'.", filter);
+            }
+            return Ok(());
+        }
+        let knowledge = self.knowledge.read().unwrap();
+        for (key, fact) in &facts {
+            let tags = fact.tags.iter().map(|tag| tag.as_str()).collect::<Vec<_>>().join(", ");
+            let gone = fact
+                .source_url
+                .as_ref()
+                .and_then(|url| knowledge.pages.get(url)?.gone.as_ref())
+                .map(|gone| format!(" (stale: gone with HTTP {} since {})", gone.status, gone.since.format("%Y-%m-%d")))
+                .unwrap_or_default();
+            println!("\n{}", key);
+            println!("  Learned: {} via {}", fact.learned_at.format("%Y-%m-%d %H:%M UTC"), fact.method.as_str());
+            println!("  Source: {}{}", fact.source_url.as_deref().unwrap_or("-"), gone);
+            println!("  Tags: {}", if tags.is_empty() { "-" } else { &tags });
+            println!(
+                "  Confidence: {:.2} (last verified {})",
+                fact.current_confidence(self.config.learning.confidence_half_life_days),
+                fact.verified_at.format("%Y-%m-%d")
+            );
+        }
+        Ok(())
+    }
+
+    /// Writes the knowledge in the JSON file format, whatever the storage backend.
+    async fn export_knowledge(&self, path: &str) -> Result<(), Box<dyn std::error::Error>> {
+        let knowledge_str = serde_json::to_string_pretty(&self.full_knowledge().await?)?;
+        if let Some(parent) = Path::new(path).parent() {
+            fs::create_dir_all(parent)?;
+        }
+        fs::write(path, knowledge_str)?;
+        Ok(())
+    }
+
+    /// Reads the config file again and takes up the changes to the `LIVE_SETTINGS`, giving
+    /// the sections that changed and those whose changes wait for a restart. A new name
+    /// waits too, since the knowledge is stored under the character's name. A file that is
+    /// not valid changes nothing.
+    fn reload_config(&mut self) -> Result<(Vec<String>, Vec<String>), Box<dyn std::error::Error>> {
+        let mut config: ChatbotConfig = config_format::read_with_env(paths::config_file())?;
+        config.validate(paths::config_file())?;
+        let (old, new) = (serde_json::to_value(&self.config)?, serde_json::to_value(&config)?);
+        let keys: HashSet<&String> = old.as_object().into_iter().chain(new.as_object()).flat_map(|sections| sections.keys()).collect();
+        let (mut live, mut restart): (Vec<String>, Vec<String>) =
+            keys.into_iter().filter(|key| old.get(key.as_str()) != new.get(key.as_str())).cloned().partition(|key| LIVE_SETTINGS.contains(&key.as_str()));
+        if config.character.name != self.config.character.name {
+            config.character.name = self.config.character.name.clone();
+            restart.push("character.name".to_string());
+            live.retain(|key| key != "character" || serde_json::to_value(&config.character).ok() != old.get("character").cloned());
+        }
+        live.sort();
+        restart.sort();
+        if live.is_empty() {
+            return Ok((live, restart));
+        }
+
+        self.robots = RobotsCache::new(&config.learning.ignore_robots_txt);
+        self.search = create_search_provider(&config.search);
+        self.config.character = config.character;
+        self.config.knowledge_sources = config.knowledge_sources;
+        self.config.conversation_settings = config.conversation_settings;
+        self.config.learning = config.learning;
+        self.config.search = config.search;
+        if self.config.jobs != config.jobs {
+            self.config.jobs = config.jobs;
+            *self.scheduler.lock().unwrap() = build_scheduler(&self.config)?;
+        }
+        Ok((live, restart))
+    }
+
+    fn save_config(&self) -> Result<(), Box<dyn std::error::Error>> {
+        self.config.save()
+    }
+
+    async fn train_with_text(&self, text: &str) -> Result<(), Box<dyn std::error::Error>> {
+        status!("Training with provided text...");
+
+        // Process the text with AI to make it more personal and relevant
+        let processed_content = self.process_with_ai(text).await?;
+
+        if !processed_content.is_empty() {
+            let mut tags = vec![FactTag::Trained];
+            tags.extend(self.classify_fact(&processed_content).await);
+            let key = format!("trained_knowledge_{}", chrono::Utc::now().timestamp());
+            let fact = Fact::new(processed_content, None, LearnMethod::Training, tags);
+            if !self.store_fact(key, fact).await? {
+                return Ok(());
+            }
+
+            // Save the updated knowledge
+            self.save_knowledge().await?;
+            status!("Successfully trained with new text!");
+        }
+
+        Ok(())
+    }
+
+    /// Trains with a book or document, one chapter at a time so every chapter becomes its
+    /// own fact. The knowledge is saved after each chapter, so an interrupted run keeps what
+    /// it has learned. A file trained with before is skipped unless it changed, in which case
+    /// its facts are replaced. Returns whether the file was trained with.
+    async fn train_file(&self, path: &Path) -> Result<bool, Box<dyn std::error::Error>> {
+        let path = fs::canonicalize(path)?;
+        let source = url::Url::from_file_path(&path)
+            .map_err(|_| format!("Can't refer to {} as a source", path.display()))?
+            .to_string();
+        let modified: chrono::DateTime<chrono::Utc> = fs::metadata(&path)?.modified()?.into();
+        let known = self.knowledge.read().unwrap().files.get(&source).cloned();
+        if known.as_ref().is_some_and(|known| known.modified == modified) {
+            status!("Unchanged since it was trained with: {}", path.display());
+            return Ok(false);
+        }
+        let state = FileState::new(&fs::read(&path)?, modified);
+        if let Some(known) = known.filter(|known| known.content_hash == state.content_hash) {
+            // Only the modification time changed
+            status!("Unchanged since it was trained with: {}", path.display());
+            self.knowledge.write().unwrap().files.insert(source, FileState { trained_at: known.trained_at, ..state });
+            self.save_knowledge().await?;
+            return Ok(false);
+        }
+
+        let document = read_document(&path)?;
+        let total = document.text_length().max(1);
+        status!(
+            "Training with {} ({} chapter(s), {} characters)...",
+            document.title.as_deref().unwrap_or("the file"),
+            document.chapters.len(),
+            total
+        );
+
+        let mut keys = HashSet::new();
+        let mut done = 0;
+        for (index, chapter) in document.chapters.iter().enumerate() {
+            status!("[{}/{}, {}%] {}", index + 1, document.chapters.len(), done * 100 / total, chapter.title);
+            done += chapter.text.chars().count();
+            let key = format!("trained_file_{}#{}", source, index + 1);
+            keys.insert(key.clone());
+            let processed_content = self.process_with_ai(&chapter.text).await?;
+            if processed_content.is_empty() {
+                continue;
+            }
+            let mut tags = vec![FactTag::Trained];
+            tags.extend(self.classify_fact(&processed_content).await);
+            let fact = Fact::new(processed_content, Some(source.clone()), LearnMethod::Training, tags);
+            if self.store_fact(key, fact).await? {
+                self.save_knowledge().await?;
+            }
+        }
+
+        // Chapters the file no longer has
+        let stale: Vec<String> = self
+            .knowledge
+            .read()
+            .unwrap()
+            .facts_from(&source)
+            .into_iter()
+            .filter(|key| !keys.contains(key))
+            .collect();
+        self.remove_facts(&stale).await?;
+        self.knowledge.write().unwrap().files.insert(source, state);
+        self.save_knowledge().await?;
+        self.queue.lock().unwrap().forget_chunks()?;
+        status!("Finished training with {}", path.display());
+        Ok(true)
+    }
+
+    /// Reads the text in an image, such as a screenshot of an official profile, a manga page
+    /// or a scanned artbook page, and learns from it. `source` is a file path or an image URL.
+    /// Returns the text read.
+    async fn learn_from_image(&self, source: &str) -> Result<String, Box<dyn std::error::Error>> {
+        let client = reqwest::Client::builder()
+            .timeout(std::time::Duration::from_secs(60))
+            .build()?;
+        let (image, source_url, method) = if source.starts_with("http://") || source.starts_with("https://") {
+            if !self.config.learning.domains.allows(source) {
+                return Err(format!("{} is outside the allowed domains in learning.domains", source).into());
+            }
+            if !self.robots.allows(source).await {
+                return Err(format!("{} is disallowed by the site's robots.txt", source).into());
+            }
+            status!("Downloading image: {}", source);
+            (download_image(&client, source).await?, source.to_string(), LearnMethod::Url)
+        } else {
+            let path = fs::canonicalize(source)?;
+            let source_url = url::Url::from_file_path(&path)
+                .map_err(|_| format!("Can't refer to {} as a source", path.display()))?
+                .to_string();
+            (read_image(&path)?, source_url, LearnMethod::Training)
+        };
+
+        status!("Reading the text in the image...");
+        let text = transcribe_image(&client, &image).await?;
+        if text.is_empty() {
+            status!("No text found in {}", source);
+            return Ok(text);
+        }
+        status!("Processing content with AI...");
+        let processed_content = self.process_with_ai(&text).await?;
+        if !processed_content.is_empty() {
+            let tags = self.classify_fact(&processed_content).await;
+            let fact = Fact::new(processed_content, Some(source_url.clone()), method, tags);
+            if self.store_fact(format!("ocr_{}", source_url), fact).await? {
+                self.save_knowledge().await?;
+            }
+        }
+        Ok(text)
+    }
+
+    /// Trains with every supported file under `dir` and its subdirectories, skipping the
+    /// files that did not change since they were last trained with.
+    async fn train_dir(&self, dir: &Path) -> Result<(), Box<dyn std::error::Error>> {
+        let files = document_files(dir)?;
+        if files.is_empty() {
+            status!("No .txt, .md, .epub or .docx files in {}", dir.display());
+            return Ok(());
+        }
+        let (mut trained, mut unchanged, mut failed) = (0, 0, 0);
+        for (index, file) in files.iter().enumerate() {
+            status!("({}/{}) {}", index + 1, files.len(), file.display());
+            match self.train_file(file).await {
+                Ok(true) => trained += 1,
+                Ok(false) => unchanged += 1,
+                Err(e) => {
+                    status!("Error training with {}: {}", file.display(), e);
+                    failed += 1;
+                }
+            }
+        }
+        status!("Trained with {} file(s); {} unchanged, {} failed", trained, unchanged, failed);
+        Ok(())
+    }
+
+    /// Imports the question-answer pairs of a JSONL or CSV dataset as curated facts, which
+    /// are trusted as written and preferred to other knowledge. With `as_examples` the pairs
+    /// also become few-shot examples of the character's replies.
+    async fn import_qa(&mut self, path: &Path, as_examples: bool) -> Result<(), Box<dyn std::error::Error>> {
+        let pairs = read_qa_pairs(path)?;
+        let source = url::Url::from_file_path(fs::canonicalize(path)?).ok().map(String::from);
+        let (mut added, mut updated, mut unchanged) = (0, 0, 0);
+        for pair in &pairs {
+            let key = pair.key();
+            let text = pair.fact_text();
+            let existing = self.knowledge.read().unwrap().facts.get(&key).map(|fact| fact.text == text);
+            match existing {
+                Some(true) => {
+                    unchanged += 1;
+                    continue;
+                }
+                Some(false) => updated += 1,
+                None => added += 1,
+            }
+            let fact = Fact::new(text, source.clone(), LearnMethod::Dataset, vec![FactTag::Trained]);
+            self.put_fact(key, fact).await?;
+        }
+        if added + updated > 0 {
+            self.save_knowledge().await?;
+        }
+        status!("Imported {} Q&A pair(s): {} new, {} updated, {} unchanged", pairs.len(), added, updated, unchanged);
+
+        if as_examples {
+            let examples = &mut self.config.character.examples;
+            let before = examples.len();
+            for pair in pairs {
+                match examples.iter_mut().find(|example| example.user == pair.question) {
+                    Some(example) => example.reply = pair.answer,
+                    None => examples.push(Example {
+                        user: pair.question,
+                        reply: pair.answer,
+                    }),
+                }
+            }
+            status!("The character now has {} example exchange(s), {} new", examples.len(), examples.len() - before);
+            if examples.len() > MAX_USEFUL_EXAMPLES {
+                status!(
+                    "Every prompt includes all examples; consider keeping no more than {} in the config",
+                    MAX_USEFUL_EXAMPLES
+                );
+            }
+            self.save_config()?;
+        }
+        Ok(())
+    }
+
+    /// Brings the lorebook facts in line with the Markdown files in the lore directory. New
+    /// and changed entries are stored as written, with the tags and keywords of their front
+    /// matter; the facts of deleted entries are removed.
+    async fn sync_lore(&self) -> Result<(), Box<dyn std::error::Error>> {
+        let dir = &paths::resolve(&self.config.knowledge_sources.lore_dir);
+        let paths = match dir.is_dir() {
+            true => document_files(dir)?,
+            false => Vec::new(),
+        };
+        let markdown = |path: &Path| path.extension().is_some_and(|ext| ext == "md" || ext == "markdown");
+
+        let mut current = HashSet::new();
+        let mut updated = 0;
+        for path in paths.iter().filter(|path| markdown(path)) {
+            let path = fs::canonicalize(path)?;
+            let Ok(source) = url::Url::from_file_path(&path).map(String::from) else {
+                continue;
+            };
+            let key = format!("lore_{}", source);
+            current.insert(key.clone());
+            let modified: chrono::DateTime<chrono::Utc> = fs::metadata(&path)?.modified()?.into();
+            let unchanged = {
+                let knowledge = self.knowledge.read().unwrap();
+                knowledge.facts.contains_key(&key) && knowledge.files.get(&source).is_some_and(|file| file.modified == modified)
+            };
+            if unchanged {
+                continue;
+            }
+
+            let entry = match LoreEntry::read(&path) {
+                Ok(entry) => entry,
+                Err(e) => {
+                    status!("Skipping lorebook entry {}: {}", path.display(), e);
+                    continue;
+                }
+            };
+            let mut tags = Vec::new();
+            for name in &entry.tags {
+                match FactTag::parse(name) {
+                    Some(tag) if !tags.contains(&tag) => tags.push(tag),
+                    Some(_) => {}
+                    None => status!("Unknown tag '{}' in {}, ignoring it", name, path.display()),
+                }
+            }
+            let mut fact = Fact::new(entry.fact_text(), Some(source.clone()), LearnMethod::Lorebook, tags);
+            fact.keywords = entry.keywords;
+            self.put_fact(key, fact).await?;
+            let state = FileState::new(&fs::read(&path)?, modified);
+            self.knowledge.write().unwrap().files.insert(source, state);
+            updated += 1;
+        }
+
+        let removed: Vec<(String, Option<String>)> = self
+            .knowledge
+            .read()
+            .unwrap()
+            .facts
+            .iter()
+            .filter(|(key, fact)| fact.method == LearnMethod::Lorebook && !current.contains(*key))
+            .map(|(key, fact)| (key.clone(), fact.source_url.clone()))
+            .collect();
+        let keys: Vec<String> = removed.iter().map(|(key, _)| key.clone()).collect();
+        self.remove_facts(&keys).await?;
+        {
+            let mut knowledge = self.knowledge.write().unwrap();
+            for source in removed.iter().filter_map(|(_, source)| source.as_ref()) {
+                knowledge.files.remove(source);
+            }
+        }
+
+        if updated > 0 || !removed.is_empty() {
+            self.save_knowledge().await?;
+            status!("Lorebook: {} entr(ies) updated, {} removed", updated, removed.len());
+        }
+        Ok(())
+    }
+}
+
+fn print_history(history: &KnowledgeHistory) -> Result<(), Box<dyn std::error::Error>> {
+    let snapshots = history.list()?;
+    if snapshots.is_empty() {
+        println!("No knowledge snapshots yet.");
+        return Ok(());
+    }
+    for snapshot in snapshots.iter().rev() {
+        println!(
+            "#{} {}  {} fact(s) (+{} ~{} -{}){}",
+            snapshot.id,
+            snapshot.taken_at.format("%Y-%m-%d %H:%M UTC"),
+            snapshot.fact_count,
+            snapshot.added,
+            snapshot.changed,
+            snapshot.removed,
+            snapshot.note.as_deref().map(|note| format!("  {}", note)).unwrap_or_default()
+        );
+    }
+    Ok(())
+}
+
+/// Registers the jobs scheduled in the config. The `learn` job follows
+/// `learning_frequency` unless the config schedules it itself.
+fn build_scheduler(config: &ChatbotConfig) -> Result<Scheduler, Box<dyn std::error::Error>> {
+    let mut scheduler = Scheduler::load(paths::data_dir())?;
+    let frequency = LearningFrequency::parse(&config.conversation_settings.learning_frequency).unwrap_or(LearningFrequency::Startup);
+    for (name, description) in JOBS {
+        let spec = match config.jobs.get(name) {
+            Some(spec) => Some(spec.as_str()),
+            None if name == "learn" => frequency.schedule(),
+            None => None,
+        };
+        let Some(spec) = spec.filter(|spec| !spec.trim().eq_ignore_ascii_case("off")) else {
+            continue;
+        };
+        if let Err(e) = scheduler.register(name, description, spec) {
+            status!("Not scheduling the {} job: {}", name, e);
+        }
+    }
+    for name in config.jobs.keys() {
+        if !JOBS.iter().any(|(job, _)| job == name) {
+            let names: Vec<&str> = JOBS.iter().map(|(job, _)| *job).collect();
+            status!("There is no job called {}; the jobs are {}", name, names.join(", "));
+        }
+    }
+    Ok(scheduler)
+}
+
+/// The snapshot history, encrypted like the store when storage.encryption is on.
+fn knowledge_history(config: &ChatbotConfig) -> Result<KnowledgeHistory, Box<dyn std::error::Error>> {
+    Ok(KnowledgeHistory::new(
+        &paths::data_path(HISTORY_DIR),
+        config.storage.history_snapshots,
+        create_codec(&config.storage)?,
+    ))
+}
+
+/// `alya encryption init`: creates a random key for storage.encryption and files it in
+/// the OS keyring. Refuses to replace a key, since data encrypted with it would be lost.
+fn run_encryption(action: &str) -> Result<(), Box<dyn std::error::Error>> {
+    if action != "init" {
+        println!("Usage: alya encryption init");
+        return Ok(());
+    }
+    if secrets::get(ENCRYPTION_KEY_NAME).is_some() {
+        return Err(format!("An encryption key is already set in the keyring or {}", ENCRYPTION_KEY_NAME).into());
+    }
+    let key = generate_key();
+    secrets::set(ENCRYPTION_KEY_NAME, &key)?;
+    println!("Stored a new encryption key in the OS keyring.");
+    println!("Keep a copy somewhere safe, the data cannot be read without it:");
+    println!("{}", key);
+    println!("Set storage.encryption to true in the config to encrypt the knowledge with it; login cookies always are.");
+    Ok(())
+}
+
+/// `alya keys list|set <name>|delete <name>`: manages the API keys and passwords kept in
+/// the OS keyring. The value to set is read from stdin so it stays out of the shell history.
+async fn run_keys(action: &str, name: Option<&str>) -> Result<(), Box<dyn std::error::Error>> {
+    match (action, name) {
+        ("list", _) => {
+            for name in secrets::KNOWN {
+                let source = match secrets::lookup(name) {
+                    Some((_, secrets::Source::Keyring)) => "keyring",
+                    Some((_, secrets::Source::Environment)) => "environment",
+                    None => "not set",
+                };
+                println!("{:<24} {}", name, source);
+            }
+        }
+        ("set", Some(name)) => {
+            if !secrets::KNOWN.contains(&name) {
+                println!("Note: {} is not a key the chatbot reads", name);
+            }
+            println!("Enter the value for {}:", name);
+            let value = input::read_line().await?.unwrap_or_default();
+            let value = value.trim();
+            if value.is_empty() {
+                return Err("No value entered, nothing was stored".into());
+            }
+            secrets::set(name, value)?;
+            println!("Stored {} in the OS keyring", name);
+        }
+        ("delete", Some(name)) => match secrets::delete(name)? {
+            true => println!("Removed {} from the OS keyring", name),
+            false => println!("{} is not in the OS keyring", name),
+        },
+        _ => println!("Usage: alya keys list | alya keys set <name> | alya keys delete <name>"),
+    }
+    Ok(())
+}
+
+/// `alya setup`: walks through the whole configuration, or changes an existing one, and
+/// saves it.
+async fn run_setup(config: ChatbotConfig) -> Result<(), Box<dyn std::error::Error>> {
+    if output::is_quiet() {
+        return Err("setup asks questions; run it without --quiet".into());
+    }
+    let config = setup_wizard(config).await?;
+    config.save()?;
+    println!("\nSaved the configuration to {}", paths::config_file().display());
+    Ok(())
+}
+
+/// Asks for the character, its knowledge sources, the API keys and the search provider,
+/// and the conversation settings, offering the current value of each; Enter keeps it. An
+/// existing configuration can skip sections. The keys are checked with a request to their
+/// service before they are kept.
+async fn setup_wizard(mut config: ChatbotConfig) -> Result<ChatbotConfig, Box<dyn std::error::Error>> {
+    let new = config.character.name.is_empty();
+
+    if edit_section(new, "Character").await? {
+        let character = &mut config.character;
+        character.name = ask_parsed("Name", &character.name, |name| match name.trim().is_empty() {
+            true => Err("The character needs a name".to_string()),
+            false => Ok(name.trim().to_string()),
+        }).await?;
+        character.personality = ask("Personality", &character.personality).await?;
+        character.description = ask("Description", &character.description).await?;
+        character.traits = ask_list("Traits (comma-separated)", &character.traits).await?;
+        character.interests = ask_list("Interests (comma-separated)", &character.interests).await?;
+        let greeting = ask("Greeting when a chat starts ('-' for none)", character.greeting.as_deref().unwrap_or_default()).await?;
+        character.greeting = Some(greeting).filter(|greeting| !greeting.is_empty() && greeting != "-");
+    }
+
+    if edit_section(new, "Knowledge sources").await? {
+        let sources = &mut config.knowledge_sources;
+        let urls: Vec<String> = sources.self_learning_urls.iter().map(LearningSource::url).collect();
+        let kept = edit_urls("Pages to learn from", &urls).await?;
+        // The sources that stay keep their settings, such as a crawl's depth
+        sources.self_learning_urls.retain(|source| kept.contains(&source.url()));
+        let known: HashSet<String> = sources.self_learning_urls.iter().map(LearningSource::url).collect();
+        sources.self_learning_urls.extend(kept.into_iter().filter(|url| !known.contains(url)).map(LearningSource::Url));
+        sources.feeds = edit_urls("RSS and Atom feeds", &sources.feeds).await?;
+        sources.additional_context = ask("Background the model is always given ('-' for none)", &sources.additional_context).await?;
+        if sources.additional_context == "-" {
+            sources.additional_context.clear();
+        }
+    }
+
+    if edit_section(new, "API keys and search").await? {
+        while let Some(key) = ask_key("GEMINI_API_KEY", "The Gemini API key, from https://aistudio.google.com/apikey").await? {
+            match check_gemini_key(&key).await {
+                Ok(()) => {
+                    store_key("GEMINI_API_KEY", &key);
+                    break;
+                }
+                Err(e) => {
+                    println!("The key did not work: {}", e);
+                    if !ask_yes("Try another key?", true).await? {
+                        break;
+                    }
+                }
+            }
+        }
+        loop {
+            config.search.provider = ask_parsed("Search provider: google, duckduckgo, brave or searxng", &json!(config.search.provider).as_str().unwrap_or_default().to_string(), |provider| {
+                serde_json::from_value(Value::String(provider.trim().to_lowercase())).map_err(|_| "Use google, duckduckgo, brave or searxng".to_string())
+            }).await?;
+            match config.search.provider {
+                SearchProviderKind::Google => {
+                    for (name, description) in [("GOOGLE_SEARCH_API_KEY", "The Google Custom Search API key"), ("GOOGLE_SEARCH_ENGINE_ID", "The search engine ID")] {
+                        if let Some(key) = ask_key(name, description).await? {
+                            store_key(name, &key);
+                        }
+                    }
+                }
+                SearchProviderKind::Brave => {
+                    if let Some(key) = ask_key("BRAVE_SEARCH_API_KEY", "The Brave Search API key").await? {
+                        store_key("BRAVE_SEARCH_API_KEY", &key);
+                    }
+                }
+                SearchProviderKind::Searxng => {
+                    let url = ask_parsed("The SearxNG instance's address", config.search.searxng_url.as_deref().unwrap_or_default(), parse_web_url).await?;
+                    config.search.searxng_url = Some(url);
+                }
+                SearchProviderKind::DuckDuckGo => {}
+            }
+            println!("Trying a search...");
+            match create_search_provider(&config.search).search(&config.character.name, 1).await {
+                Ok(results) => {
+                    println!("The search works ({} result(s))", results.len());
+                    break;
+                }
+                Err(e) => {
+                    println!("The search failed: {}", e);
+                    if !ask_yes("Try other search settings?", true).await? {
+                        break;
+                    }
+                }
+            }
+        }
+    }
+
+    if edit_section(new, "Conversation settings").await? {
+        let settings = &mut config.conversation_settings;
+        settings.max_history = ask_parsed("Messages remembered in a conversation", &settings.max_history.to_string(), |text| {
+            text.trim().parse().ok().filter(|max| (1..=MAX_HISTORY_LIMIT).contains(max)).ok_or(format!("Use a number from 1 to {}", MAX_HISTORY_LIMIT))
+        }).await?;
+        settings.learning_frequency = ask_parsed("Learn on its own: startup, daily, weekly or manual", &settings.learning_frequency, |text| {
+            LearningFrequency::parse(text).map(|_| text.trim().to_lowercase()).ok_or("Use startup, daily, weekly or manual".to_string())
+        }).await?;
+        settings.citations = ask_yes("Show the sources of factual replies?", settings.citations).await?;
+        settings.live_search = ask_yes("Search the web for questions the knowledge doesn't answer?", settings.live_search).await?;
+        settings.user_memory = ask_yes("Remember what people tell about themselves?", settings.user_memory).await?;
+    }
+
+    config.validate(paths::config_file())?;
+    Ok(config)
+}
+
+/// Whether to go through a section: always for a new configuration, which shows its title.
+async fn edit_section(new: bool, section: &str) -> Result<bool, Box<dyn std::error::Error>> {
+    if new {
+        println!("\n== {} ==", section);
+        return Ok(true);
+    }
+    ask_yes(&format!("\nChange the {}?", section.to_lowercase()), false).await
+}
+
+/// A line of input, trimmed; the end of the input ends the setup.
+async fn read_answer() -> Result<String, Box<dyn std::error::Error>> {
+    let line = input::read_line().await?.ok_or("The setup ended before it was done; nothing was saved")?;
+    Ok(line.trim().to_string())
+}
+
+/// Asks for a value, showing the current one, which Enter keeps.
+async fn ask(question: &str, current: &str) -> Result<String, Box<dyn std::error::Error>> {
+    match current.char_indices().nth(60) {
+        _ if current.is_empty() => println!("{}:", question),
+        Some((end, _)) => println!("{} [{}...]:", question, &current[..end]),
+        None => println!("{} [{}]:", question, current),
+    }
+    let answer = read_answer().await?;
+    Ok(if answer.is_empty() { current.to_string() } else { answer })
+}
+
+/// Asks until the answer parses; the current value is offered like `ask` does.
+async fn ask_parsed<T>(question: &str, current: &str, parse: impl Fn(&str) -> Result<T, String>) -> Result<T, Box<dyn std::error::Error>> {
+    loop {
+        match parse(&ask(question, current).await?) {
+            Ok(value) => return Ok(value),
+            Err(e) => println!("{}", e),
+        }
+    }
+}
+
+/// Asks for a comma-separated list; '-' empties it.
+async fn ask_list(question: &str, current: &[String]) -> Result<Vec<String>, Box<dyn std::error::Error>> {
+    let answer = ask(question, &current.join(", ")).await?;
+    if answer == "-" {
+        return Ok(Vec::new());
+    }
+    Ok(answer.split(',').map(|item| item.trim().to_string()).filter(|item| !item.is_empty()).collect())
+}
+
+async fn ask_yes(question: &str, default: bool) -> Result<bool, Box<dyn std::error::Error>> {
+    println!("{} [{}]", question, if default { "Y/n" } else { "y/N" });
+    Ok(match read_answer().await?.to_lowercase().as_str() {
+        "" => default,
+        answer => matches!(answer, "y" | "yes"),
+    })
+}
+
+fn parse_web_url(text: &str) -> Result<String, String> {
+    match url::Url::parse(text.trim()) {
+        Ok(url) if matches!(url.scheme(), "http" | "https") => Ok(text.trim().to_string()),
+        _ => Err("Give an http:// or https:// address".to_string()),
+    }
+}
+
+/// Shows the URLs numbered and lets them be removed by number and new ones added, until an
+/// empty line.
+async fn edit_urls(title: &str, current: &[String]) -> Result<Vec<String>, Box<dyn std::error::Error>> {
+    let mut urls = current.to_vec();
+    loop {
+        println!("{}:", title);
+        if urls.is_empty() {
+            println!("  (none)");
+        }
+        for (index, url) in urls.iter().enumerate() {
+            println!("  {}. {}", index + 1, url);
+        }
+        println!("Add a URL, remove one by its number, or press Enter to go on:");
+        let answer = read_answer().await?;
+        if answer.is_empty() {
+            return Ok(urls);
+        }
+        if let Ok(number) = answer.parse::<usize>() {
+            match (1..=urls.len()).contains(&number) {
+                true => {
+                    urls.remove(number - 1);
+                }
+                false => println!("There is no number {}", number),
+            }
+            continue;
+        }
+        match parse_web_url(&answer) {
+            Ok(url) if urls.contains(&url) => println!("{} is already there", url),
+            Ok(url) => urls.push(url),
+            Err(e) => println!("{}", e),
+        }
+    }
+}
+
+/// Asks for a secret, offering to keep the one that is set. None when there is none and
+/// none was given.
+async fn ask_key(name: &str, description: &str) -> Result<Option<String>, Box<dyn std::error::Error>> {
+    let current = secrets::lookup(name);
+    match &current {
+        Some((_, secrets::Source::Keyring)) => println!("{} ({}) [in the keyring; Enter keeps it]:", description, name),
+        Some((_, secrets::Source::Environment)) => println!("{} ({}) [in the environment; Enter keeps it]:", description, name),
+        None => println!("{} ({}):", description, name),
+    }
+    let answer = read_answer().await?;
+    if !answer.is_empty() {
+        return Ok(Some(answer));
+    }
+    if current.is_none() {
+        println!("No {} was given; add it later with `alya keys set {}`", name, name);
+    }
+    Ok(current.map(|(key, _)| key))
+}
+
+/// Stores a secret in the OS keyring unless it is already the one in use. Where the
+/// keyring can't store it, it is used for this run and has to be added to `.env`.
+fn store_key(name: &str, key: &str) {
+    if secrets::get(name).as_deref() == Some(key) {
+        return;
+    }
+    match secrets::set(name, key) {
+        Ok(()) => println!("Stored {} in the OS keyring", name),
+        Err(e) => {
+            println!("Could not store {} in the OS keyring ({}); add it to .env to keep it", name, e);
+            secrets::set_for_run(name, key);
+        }
+    }
+}
+
+/// Checks a Gemini API key by listing the models it may use.
+async fn check_gemini_key(key: &str) -> Result<(), Box<dyn std::error::Error>> {
+    let response = reqwest::Client::builder()
+        .timeout(std::time::Duration::from_secs(30))
+        .build()?
+        .get("https://generativelanguage.googleapis.com/v1beta/models")
+        .header("x-goog-api-key", key)
+        .send()
+        .await?;
+    match response.status() {
+        status if status.is_success() => Ok(()),
+        status => Err(format!("Gemini answered {}", status).into()),
+    }
+}
+
+/// `alya api-keys list|create <name> [--scope chat|admin]|revoke <name>`: manages the
+/// keys of the HTTP API. A new key is shown once; only its hash is kept.
+fn run_api_keys(action: &str, name: Option<&str>, scope: &str) -> Result<(), Box<dyn std::error::Error>> {
+    let mut keys = ApiKeys::load(paths::data_dir())?;
+    match (action, name) {
+        ("list", _) => {
+            if keys.keys().is_empty() {
+                println!("No API keys yet");
+            }
+            for key in keys.keys() {
+                println!("{:<24} {:<6} created {}", key.name, format!("{:?}", key.scope).to_lowercase(), key.created_at.format("%Y-%m-%d"));
+            }
+        }
+        ("create", Some(name)) => {
+            let scope = Scope::parse(scope).ok_or("The scope is chat or admin")?;
+            let key = keys.create(name, scope)?;
+            println!("Created the API key {}; it is not shown again:", name);
+            println!("{}", key);
+            println!("A running `alya serve` takes it after a restart.");
+        }
+        ("revoke", Some(name)) => match keys.revoke(name)? {
+            true => println!("Revoked {}; a running `alya serve` stops taking it after a restart", name),
+            false => println!("There is no API key called {}", name),
+        },
+        _ => println!("Usage: alya api-keys list | alya api-keys create <name> [--scope chat|admin] | alya api-keys revoke <name>"),
+    }
+    Ok(())
+}
+
+/// `alya cookies list|set <domain>|clear <domain>|login <domain>`: manages the encrypted
+/// cookies sent to sites behind a login. `set` reads a `Cookie` header value, such as one
+/// copied from a logged-in browser, from stdin; `login` submits the configured login form.
+async fn run_cookies(config: &ChatbotConfig, action: &str, domain: Option<&str>) -> Result<(), Box<dyn std::error::Error>> {
+    let mut jar = CookieJar::load(paths::data_dir())?;
+    match (action, domain) {
+        ("list", _) => {
+            let domains = jar.domains();
+            if domains.is_empty() {
+                println!("No cookies saved");
+            }
+            for (domain, count) in domains {
+                println!("{:<32} {} cookie(s)", domain, count);
+            }
+        }
+        ("set", Some(domain)) => {
+            println!("Enter the cookies for {} (name=value; other=value):", domain);
+            let cookies = input::read_line().await?.unwrap_or_default();
+            let count = jar.set(domain, cookies.trim());
+            if count == 0 {
+                return Err("No cookies entered, nothing was stored".into());
+            }
+            jar.save()?;
+            println!("Stored {} cookie(s) for {}", count, domain);
+        }
+        ("clear", Some(domain)) => match jar.clear(domain) {
+            true => {
+                jar.save()?;
+                println!("Removed the cookies of {}", domain);
+            }
+            false => println!("No cookies saved for {}", domain),
+        },
+        ("login", Some(domain)) => {
+            let login = config
+                .learning
+                .logins
+                .iter()
+                .find(|login| login.domain.eq_ignore_ascii_case(domain))
+                .ok_or_else(|| format!("No login for {} in learning.logins", domain))?;
+            login.log_in(&Mutex::new(jar)).await?;
+            println!("Logged in to {}", domain);
+        }
+        _ => println!("Usage: alya cookies list | alya cookies set <domain> | alya cookies clear <domain> | alya cookies login <domain>"),
+    }
+    Ok(())
+}
+
+/// `alya knowledge history|rollback <n>|bench`: lists the snapshots taken on every save,
+/// restores one of them into the configured store, or compares how the local backends
+/// store the current knowledge.
+async fn run_knowledge(config: &ChatbotConfig, action: &str, id: Option<&str>) -> Result<(), Box<dyn std::error::Error>> {
+    let history = knowledge_history(config)?;
+    match (action, id.and_then(|id| id.trim_start_matches('#').parse::<u64>().ok())) {
+        ("history", _) => print_history(&history)?,
+        ("rollback", Some(id)) => {
+            let snapshot = history.load(id)?;
+            let store = create_store(&config.storage, &config.character.name).await?;
+            store.save(&snapshot).await?;
+            history.record(&snapshot, Some(format!("rollback to #{}", id)))?;
+            println!("Rolled back to snapshot #{} with {} fact(s)", id, snapshot.facts.len());
+        }
+        ("bench", _) => {
+            let store = create_store(&config.storage, &config.character.name).await?;
+            let knowledge = store.load().await?.unwrap_or_default();
+            println!("{} fact(s), {} cached page(s)", knowledge.facts.len(), knowledge.cached_content.len());
+            println!("{:<8} {:<6} {:>14} {:>12} {:>12}", "backend", "codec", "size", "save", "load");
+            for result in compare_backends(&knowledge, 5).await? {
+                println!("{}", result);
+            }
+        }
+        _ => println!("Usage: alya knowledge history | alya knowledge rollback <n> | alya knowledge bench"),
+    }
+    Ok(())
+}
+
+/// `alya migrate [--dry-run]`: imports `data/learned_knowledge.json` and
+/// `data/sessions.json` into the configured storage backend, after checking the
+/// configuration, which no backend stores and so stays in its file.
+async fn run_migration(config: &ChatbotConfig, dry_run: bool) -> Result<(), Box<dyn std::error::Error>> {
+    let config_path = paths::config_file();
+    config.validate(config_path)?;
+    match config_path.exists() {
+        true => println!("{} is valid and stays where it is; every backend reads it the same", config_path.display()),
+        false => println!("There is no {}; the defaults are used with every backend", config_path.display()),
+    }
+
+    if config.storage.backend == StorageBackend::Json {
+        println!("The json storage backend already reads data/learned_knowledge.json.");
+        println!("Set storage.backend in the config to the backend to migrate to.");
+        return Ok(());
+    }
+
+    let store = create_store(&config.storage, &config.character.name).await?;
+    let report = migrate_from_json(paths::data_dir(), create_codec(&config.storage)?, store.as_ref(), dry_run).await?;
+    println!("{}", report);
+    if dry_run {
+        println!("Dry run, nothing was written.");
+    }
+    Ok(())
+}
+
+/// Packs the character configuration and everything in `store` into an archive.
+async fn build_archive(
+    config: &ChatbotConfig,
+    store: &dyn KnowledgeStore,
+) -> Result<CharacterArchive, Box<dyn std::error::Error>> {
+    let knowledge = store.load().await?.unwrap_or_default();
+    let mut sessions = HashMap::new();
+    for session_id in store.list_sessions().await? {
+        let history = store.load_session(&session_id).await?;
+        sessions.insert(session_id, history);
+    }
+    Ok(CharacterArchive::new(config.character.clone(), config.knowledge_sources.clone(), knowledge, sessions))
+}
+
+/// `alya export <file>`: packs the character, its knowledge and sessions into one archive.
+async fn run_export(config: &ChatbotConfig, path: &str) -> Result<(), Box<dyn std::error::Error>> {
+    let store = create_store(&config.storage, &config.character.name).await?;
+    let archive = build_archive(config, store.as_ref()).await?;
+    archive.write(Path::new(path))?;
+    println!(
+        "Exported {} with {} fact(s) and {} session(s) to {}",
+        archive.manifest.character_name,
+        archive.manifest.fact_count,
+        archive.sessions.len(),
+        path
+    );
+    Ok(())
+}
+
+/// `alya import <file> [--keep-character] [--dry-run]`: adds an exported character's
+/// knowledge and sessions to the configured store and, unless asked not to, takes over its
+/// character configuration.
+async fn run_import(
+    mut config: ChatbotConfig,
+    path: &str,
+    keep_character: bool,
+    dry_run: bool,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let archive = CharacterArchive::read(Path::new(path))?;
+    println!(
+        "Archive of {} exported {} with {} fact(s)",
+        archive.manifest.character_name,
+        archive.manifest.exported_at.format("%Y-%m-%d %H:%M UTC"),
+        archive.manifest.fact_count
+    );
+
+    if !keep_character {
+        config.character = archive.character;
+        config.knowledge_sources = archive.knowledge_sources;
+    }
+    let store = create_store(&config.storage, &config.character.name).await?;
+    let report = import_into(store.as_ref(), Some(archive.knowledge), archive.sessions, dry_run).await?;
+    println!("{}", report);
+    if !dry_run {
+        let knowledge = store.load().await?.unwrap_or_default();
+        knowledge_history(&config)?
+            .record(&knowledge, Some(format!("import {}", path)))?;
+    }
+
+    if dry_run {
+        println!("Dry run, nothing was written.");
+    } else if !keep_character {
+        config.save()?;
+        println!("Character configuration replaced with {}", config.character.name);
+    }
+    Ok(())
+}
+
+/// `alya card import <file>`: replaces the character with the one of a SillyTavern or
+/// TavernAI character card. The card's scenario becomes the additional context, and the
+/// entries of its lorebook are added to the lore directory.
+fn run_card(mut config: ChatbotConfig, action: &str, file: &str) -> Result<(), Box<dyn std::error::Error>> {
+    if action != "import" {
+        println!("Usage: alya card import <file>");
+        return Ok(());
+    }
+    let card = Card::read(Path::new(file))?;
+    config.character = card.character;
+    if !card.scenario.is_empty() {
+        config.knowledge_sources.additional_context = card.scenario;
+    }
+    let added = write_lore(&paths::resolve(&config.knowledge_sources.lore_dir), &card.lore)?;
+    config.save()?;
+    println!("Character configuration replaced with {}", config.character.name);
+    println!(
+        "Imported {} example exchange(s){} and {} lorebook entr{}",
+        config.character.examples.len(),
+        if config.character.greeting.is_some() { ", a greeting" } else { "" },
+        added,
+        if added == 1 { "y" } else { "ies" }
+    );
+    if config.character.examples.len() > MAX_USEFUL_EXAMPLES {
+        status!(
+            "Every prompt includes all examples; consider keeping no more than {} in the config",
+            MAX_USEFUL_EXAMPLES
+        );
+    }
+    Ok(())
+}
+
+/// Writes lorebook entries as files of the lore directory `dir`, keeping the files that
+/// already exist, and gives how many were written.
+fn write_lore(dir: &Path, lore: &[LoreEntry]) -> Result<usize, Box<dyn std::error::Error>> {
+    let mut added = 0;
+    for entry in lore {
+        let stem: String = entry.title.chars().map(|c| if c.is_alphanumeric() || c == ' ' || c == '-' { c } else { '_' }).collect();
+        let path = dir.join(format!("{}.md", stem.trim()));
+        if path.exists() {
+            status!("Keeping the lorebook entry {}, which already exists", path.display());
+            continue;
+        }
+        fs::create_dir_all(dir)?;
+        fs::write(&path, entry.markdown())?;
+        added += 1;
+    }
+    Ok(added)
+}
+
+/// `alya character install <url>|list`: installs a character shared as a character card or
+/// as a character configuration, after showing it and asking, in a directory of its own
+/// under `config/characters`, to be used with `--character`. Only the character, its
+/// knowledge sources and its conversation settings are taken from a configuration; the
+/// rest, such as the model, storage and learning settings, are the current
+/// configuration's, without its chat platforms, webhooks, jobs and sync, which are the
+/// current character's own. Learning settings are never taken, as their logins name
+/// secrets to send and their browser is a program to run.
+async fn run_character(mut config: ChatbotConfig, action: &str, url: Option<&str>, name: Option<&str>, yes: bool) -> Result<(), Box<dyn std::error::Error>> {
+    let characters = paths::characters_dir();
+    if action == "list" {
+        let mut installed: Vec<(String, String)> = Vec::new();
+        if characters.is_dir() {
+            for entry in fs::read_dir(&characters)? {
+                let dir = entry?.path();
+                let file = config_format::find(&dir.join(paths::CHARACTER_CONFIG_FILE));
+                if !file.is_file() {
+                    continue;
+                }
+                let character = config_format::read::<Value>(&file).ok().and_then(|config| config["character"]["name"].as_str().map(str::to_string));
+                installed.push((dir.file_name().unwrap_or_default().to_string_lossy().to_string(), character.unwrap_or_default()));
+            }
+        }
+        if installed.is_empty() {
+            println!("No characters are installed; add one with `alya character install <url>`");
+        }
+        installed.sort();
+        for (name, character) in installed {
+            println!("{:<20} {}", name, character);
+        }
+        return Ok(());
+    }
+    let Some(url) = url.filter(|url| url.starts_with("http://") || url.starts_with("https://")) else {
+        println!("Usage: alya character install <url> [--name <name>] [--yes]");
+        return Ok(());
+    };
+
+    status!("Downloading {}...", url);
+    let client = reqwest::Client::builder().timeout(std::time::Duration::from_secs(60)).build()?;
+    let response = client.get(url).send().await?.error_for_status()?;
+    if response.content_length().is_some_and(|length| length as usize > MAX_CHARACTER_DOWNLOAD) {
+        return Err(format!("{} is larger than a character should be", url).into());
+    }
+    let bytes = response.bytes().await?;
+    if bytes.len() > MAX_CHARACTER_DOWNLOAD {
+        return Err(format!("{} is larger than a character should be", url).into());
+    }
+
+    // A configuration has a `character` section; anything else should be a card
+    let shared: Option<Value> = (!card::is_png(&bytes)).then(|| serde_json::from_slice(&bytes)).transpose().map_err(|e| format!("Neither a character card nor a configuration: {}", e))?;
+    // Whether conversation settings are taken from a configuration, to be shown before installing
+    let mut shared_settings = false;
+    let (card, sources) = match shared.filter(|shared| shared.get("character").is_some()) {
+        Some(shared) => {
+            let field = |key: &str| shared.get(key).cloned().unwrap_or(Value::Object(Default::default()));
+            let character: CharacterConfig = serde_json::from_value(shared["character"].clone()).map_err(|e| format!("The configuration's character section is not valid: {}", e))?;
+            if shared.get("conversation_settings").is_some() {
+                config.conversation_settings = serde_json::from_value(field("conversation_settings")).map_err(|e| format!("The configuration's conversation_settings are not valid: {}", e))?;
+                shared_settings = true;
+            }
+            if shared.get("learning").is_some() {
+                println!("The configuration's learning settings are left out; the current ones are kept");
+            }
+            let sources: KnowledgeSources = match shared.get("knowledge_sources") {
+                Some(sources) => serde_json::from_value(sources.clone()).map_err(|e| format!("The configuration's knowledge_sources are not valid: {}", e))?,
+                None => KnowledgeSources { self_learning_urls: Vec::new(), additional_context: String::new(), lore_dir: default_lore_dir(), feeds: Vec::new() },
+            };
+            let scenario = sources.additional_context.clone();
+            (Card { character, scenario, lore: Vec::new() }, sources)
+        }
+        None => {
+            let card = Card::from_bytes(&bytes)?;
+            let sources = KnowledgeSources { self_learning_urls: Vec::new(), additional_context: card.scenario.clone(), lore_dir: default_lore_dir(), feeds: Vec::new() };
+            (card, sources)
+        }
+    };
+    let character = &card.character;
+    if character.name.trim().is_empty() {
+        return Err("The character has no name".into());
+    }
+    let name = match name {
+        Some(name) => name.to_string(),
+        None => character_slug(&character.name),
+    };
+    if name.is_empty() || name.starts_with('.') || name.contains(['/', '\\']) {
+        return Err(format!("\"{}\" cannot name a character directory; give another with --name", name).into());
+    }
+    let dir = paths::character_dir(&name);
+    if dir.exists() {
+        return Err(format!("{} is already installed in {}; give another name with --name", name, dir.display()).into());
+    }
+
+    let shorten = |text: &str| match text.char_indices().nth(300) {
+        Some((end, _)) => format!("{}...", &text[..end]),
+        None => text.to_string(),
+    };
+    println!("\n{}", character.name);
+    for (label, text) in [("Description", &character.description), ("Personality", &character.personality), ("Scenario", &card.scenario)] {
+        if !text.is_empty() {
+            println!("{}: {}", label, shorten(text));
+        }
+    }
+    if !character.traits.is_empty() {
+        println!("Traits: {}", character.traits.join(", "));
+    }
+    if !character.interests.is_empty() {
+        println!("Interests: {}", character.interests.join(", "));
+    }
+    if let Some(greeting) = &character.greeting {
+        println!("Greeting: {}", shorten(greeting));
+    }
+    println!("{} example exchange(s), {} lorebook entr{}", character.examples.len(), card.lore.len(), if card.lore.len() == 1 { "y" } else { "ies" });
+    if shared_settings {
+        let settings = &config.conversation_settings;
+        let tags = |tags: &[FactTag]| tags.iter().map(FactTag::as_str).collect::<Vec<_>>().join(", ");
+        println!(
+            "Conversation settings: history of {} message(s), learns {}, citations {}, live search {}, user memory {}, {} recent news entr{}",
+            settings.max_history,
+            settings.learning_frequency,
+            if settings.citations { "on" } else { "off" },
+            if settings.live_search { "on" } else { "off" },
+            if settings.user_memory { "on" } else { "off" },
+            settings.recent_news,
+            if settings.recent_news == 1 { "y" } else { "ies" }
+        );
+        if !settings.include_tags.is_empty() || !settings.exclude_tags.is_empty() {
+            println!("Facts used: tagged {}; never tagged {}", tags(&settings.include_tags), tags(&settings.exclude_tags));
+        }
+    }
+    // The sources are fetched by `learn`, so they are shown in full
+    let urls: Vec<String> = sources.self_learning_urls.iter().map(LearningSource::url).chain(sources.feeds.iter().cloned()).collect();
+    if !urls.is_empty() {
+        println!("Learns from:");
+        for url in urls {
+            println!("  {}", url);
+        }
+    }
+    if !yes {
+        println!("\nInstall {} as {}? [y/N]", character.name, name);
+        let answer = input::read_line().await?.unwrap_or_default();
+        if !matches!(answer.trim().to_lowercase().as_str(), "y" | "yes") {
+            println!("Not installed");
+            return Ok(());
+        }
+    }
+
+    let lore_dir = dir.join("lore");
+    write_lore(&lore_dir, &card.lore)?;
+    config.character = card.character;
+    config.knowledge_sources = KnowledgeSources { lore_dir: lore_dir.to_string_lossy().to_string(), ..sources };
+    (config.discord, config.telegram, config.matrix, config.irc) = (None, None, None, None);
+    (config.whatsapp, config.email, config.xmpp, config.mastodon) = (None, None, None, None);
+    (config.sync, config.webhooks, config.jobs) = (None, Vec::new(), HashMap::new());
+    config.save_to(&dir.join(paths::CHARACTER_CONFIG_FILE))?;
+    println!("Installed {} in {}", config.character.name, dir.display());
+    println!("Chat with it with `alya --character {}`, and run `alya --character {} learn` to have it learn about itself", name, name);
+    Ok(())
+}
+
+/// The name a character is installed as by default, such as "masha-kujou".
+fn character_slug(name: &str) -> String {
+    name.to_lowercase().split(|c: char| !c.is_alphanumeric()).filter(|word| !word.is_empty()).collect::<Vec<_>>().join("-")
+}
+
+/// `alya profile create <name>` / `alya profile list`: profiles are configurations and data
+/// directories apart from the main ones, used with `--profile`, to try settings without
+/// touching the real character's knowledge and memory. A new profile starts from a copy of
+/// the current configuration file, as it is written, and with no data.
+fn run_profile(action: &str, name: Option<&str>) -> Result<(), Box<dyn std::error::Error>> {
+    let profiles = paths::profiles_dir();
+    if action == "list" {
+        let mut names: Vec<String> = Vec::new();
+        if profiles.is_dir() {
+            for entry in fs::read_dir(&profiles)? {
+                let entry = entry?;
+                if entry.path().is_dir() {
+                    names.push(entry.file_name().to_string_lossy().to_string());
+                }
+            }
+        }
+        if names.is_empty() {
+            println!("There are no profiles; make one with `alya profile create <name>`");
+        }
+        names.sort();
+        for name in names {
+            println!("{:<20} {}", name, paths::profile_data_dir(&name).display());
+        }
+        return Ok(());
+    }
+    let Some(name) = name else {
+        println!("Usage: alya profile create <name>");
+        return Ok(());
+    };
+    if name.is_empty() || name.starts_with('.') || name.contains(['/', '\\']) {
+        return Err(format!("\"{}\" cannot name a profile directory; give another name", name).into());
+    }
+    let dir = paths::profile_dir(name);
+    if dir.exists() {
+        return Err(format!("The profile {} already exists in {}", name, dir.display()).into());
+    }
+    fs::create_dir_all(&dir)?;
+    // The file is copied rather than saved so environment overrides are not written into it
+    let config = paths::config_file();
+    if config.exists() {
+        let copy = dir.join(paths::CHARACTER_CONFIG_FILE).with_extension(config_format::Format::of(config).extension());
+        fs::copy(config, &copy)?;
+        println!("Made the profile {} from {}", name, config.display());
+        println!("Change its settings in {}, and use it with `alya --profile {}`", copy.display(), name);
+    } else {
+        println!("Made the profile {}; `alya --profile {}` sets up its character", name, name);
+    }
+    Ok(())
+}
+
+/// `alya group <characters>...`: has installed characters, and the current one when named,
+/// talk with each other in turn, each running apart with its own persona and memory, for
+/// `rounds` rounds. With `join` the user takes a turn after each round, and the talk goes on
+/// until they end it. Every line is written to the transcript as it is said.
+async fn run_group(config: &ChatbotConfig, names: &[String], topic: Option<&str>, rounds: Option<usize>, join: bool, transcript: Option<PathBuf>) -> Result<(), Box<dyn std::error::Error>> {
+    use std::io::Write;
+    secrets::require("GEMINI_API_KEY")?;
+    let mut sources = Vec::new();
+    for (index, name) in names.iter().enumerate() {
+        if names[..index].contains(name) {
+            return Err(format!("{} is in the group twice", name).into());
+        }
+        let current = !config.character.name.is_empty() && (name.eq_ignore_ascii_case(&config.character.name) || *name == character_slug(&config.character.name));
+        let source = if paths::character_config(name).exists() {
+            group::Source::Installed(name.clone())
+        } else if current {
+            group::Source::Files { config: paths::config_file().to_path_buf(), data_dir: paths::data_dir().to_path_buf() }
+        } else {
+            return Err(format!("{} is neither an installed character nor the current one; see `alya character list`", name).into());
+        };
+        sources.push(source);
+    }
+    let mut members = Vec::new();
+    for (name, source) in names.iter().zip(&sources) {
+        status!("Starting {}...", name);
+        members.push(group::Member::start(source).await?);
+    }
+
+    // A session of its own keeps the talk out of each character's other conversations
+    let started = chrono::Utc::now();
+    let session = format!("group/{}", started.format("%Y%m%d-%H%M%S"));
+    let path = transcript.unwrap_or_else(|| paths::data_path(TRANSCRIPT_DIR).join(format!("group-{}.md", started.format("%Y%m%d-%H%M%S"))));
+    if let Some(dir) = path.parent().filter(|dir| !dir.as_os_str().is_empty()) {
+        fs::create_dir_all(dir)?;
+    }
+    let mut file = fs::File::create(&path)?;
+    let titles: Vec<String> = members.iter().map(|member| member.name.clone()).collect();
+    writeln!(file, "# {}\n", titles.join(", "))?;
+    if let Some(topic) = topic {
+        writeln!(file, "Topic: {}\n", topic)?;
+    }
+    writeln!(file, "Started {}\n", started.format("%Y-%m-%d %H:%M UTC"))?;
+
+    // What each character has yet to hear, as "Name: message" lines
+    let mut unheard: Vec<Vec<String>> = vec![Vec::new(); members.len()];
+    let mut introduced = vec![false; members.len()];
+    println!("\n{} are talking{}.\n", titles.join(", "), if join { "; press Enter after a round to let them go on, or type /quit to end" } else { "" });
+    'talk: for _ in 0..rounds.unwrap_or(if join { usize::MAX } else { GROUP_ROUNDS }) {
+        for index in 0..members.len() {
+            let name = titles[index].clone();
+            let mut message = String::new();
+            if !introduced[index] {
+                let others: Vec<&str> = titles.iter().filter(|other| **other != name).map(String::as_str).collect();
+                message = format!(
+                    "This is a group conversation between you, {}{}. {}Each message you get brings what the others said since you \
+                    last spoke, as \"Name: message\" lines. Speak only for yourself: answer them in a few sentences, in your own \
+                    voice, without writing their lines or putting your name before yours.\n\n",
+                    others.join(", "),
+                    if join { " and the user" } else { "" },
+                    topic.map(|topic| format!("The topic is {}. ", topic)).unwrap_or_default()
+                );
+                introduced[index] = true;
+            }
+            let heard = std::mem::take(&mut unheard[index]);
+            message.push_str(&if heard.is_empty() { "Begin the conversation.".to_string() } else { heard.join("\n\n") });
+
+            let reply = match members[index].chat(&session, &message).await {
+                Ok(reply) => reply,
+                Err(e) => {
+                    status!("{}", e);
+                    break 'talk;
+                }
+            };
+            let prefix = format!("{}:", name);
+            let reply = match reply.get(..prefix.len()) {
+                Some(head) if head.eq_ignore_ascii_case(&prefix) => reply[prefix.len()..].trim().to_string(),
+                _ => reply,
+            };
+            if reply.is_empty() {
+                continue;
+            }
+            println!("{}: {}\n", name, reply);
+            writeln!(file, "**{}:** {}\n", name, reply)?;
+            for (other, unheard) in unheard.iter_mut().enumerate() {
+                if other != index {
+                    unheard.push(format!("{}: {}", name, reply));
+                }
+            }
+        }
+        if join {
+            println!("You:");
+            let Some(line) = input::read_message().await?.filter(|line| line.trim() != "/quit") else {
+                break;
+            };
+            let line = line.trim();
+            if !line.is_empty() {
+                writeln!(file, "**You:** {}\n", line)?;
+                for unheard in &mut unheard {
+                    unheard.push(format!("User: {}", line));
+                }
+            }
+        }
+    }
+    for member in members {
+        member.shutdown().await;
+    }
+    println!("The transcript is in {}", path.display());
+    Ok(())
+}
+
+/// `alya export-character <file>`: writes the character as a version 2 character card, a
+/// PNG image carrying it or a JSON file, for SillyTavern and the other tools that read them.
+/// The lorebook goes in the card. With `with_knowledge` the model adds a summary of the
+/// learned knowledge to the description.
+async fn run_export_character(config: ChatbotConfig, file: &str, image: Option<&str>, with_knowledge: bool) -> Result<(), Box<dyn std::error::Error>> {
+    if config.character.name.is_empty() {
+        return Err("No character is set up yet; run `alya chat` first".into());
+    }
+    let png = Path::new(file).extension().is_some_and(|ext| ext.eq_ignore_ascii_case("png"));
+    if image.is_some() && !png {
+        return Err("--image is for PNG cards; give the card a .png file name".into());
+    }
+    let dir = &paths::resolve(&config.knowledge_sources.lore_dir);
+    let mut lore = Vec::new();
+    if dir.is_dir() {
+        for path in document_files(dir)?.iter().filter(|path| path.extension().is_some_and(|ext| ext == "md" || ext == "markdown")) {
+            match LoreEntry::read(path) {
+                Ok(entry) => lore.push(entry),
+                Err(e) => status!("Skipping lorebook entry {}: {}", path.display(), e),
+            }
+        }
+    }
+
+    let mut character = config.character.clone();
+    let scenario = config.knowledge_sources.additional_context.clone();
+    if with_knowledge {
+        secrets::require("GEMINI_API_KEY")?;
+        let chatbot = Chatbot::new(config).await?;
+        chatbot.load_knowledge().await?;
+        status!("Summarizing the learned knowledge...");
+        let summary = chatbot.summarize_knowledge().await?;
+        character.description = format!("{}\n\n{}", character.description, summary).trim().to_string();
+    }
+    let card = Card { character, scenario, lore };
+    if png {
+        let image = image.map(fs::read).transpose()?;
+        card.write_png(Path::new(file), image.as_deref())?;
+    } else {
+        fs::write(file, serde_json::to_string_pretty(&card.to_json())?)?;
+    }
+    println!(
+        "Exported {} to {}, with {} example exchange(s) and {} lorebook entr{}",
+        card.character.name,
+        file,
+        card.character.examples.len(),
+        card.lore.len(),
+        if card.lore.len() == 1 { "y" } else { "ies" }
+    );
+    Ok(())
+}
+
+/// `alya sync status|push|pull [--force] [--merge]`: keeps the knowledge in step with a
+/// copy on S3 or WebDAV. Pushing over someone else's push, or pulling over local changes,
+/// is refused unless `--force` is given; `pull --merge` combines both sides instead.
+async fn run_sync(
+    mut config: ChatbotConfig,
+    action: &str,
+    force: bool,
+    merge: bool,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let Some(settings) = config.sync.clone() else {
+        println!("No `sync` section in the config.");
+        return Ok(());
+    };
+    let remote = create_remote(&settings, &config.character.name)?;
+    let store = create_store(&config.storage, &config.character.name).await?;
+    let state = SyncState::load()?;
+    let knowledge = store.load().await?.unwrap_or_default();
+    let remote_version = remote.head().await?;
+    let local_changed = state.local_changed(&knowledge);
+    let remote_changed = state.remote_changed(remote_version.as_deref());
+
+    match action {
+        "status" => {
+            match state.synced_at {
+                Some(synced_at) => println!("Last synced {}", synced_at.format("%Y-%m-%d %H:%M UTC")),
+                None => println!("Never synced"),
+            }
+            println!("Local changes: {}", if local_changed { "yes" } else { "no" });
+            match remote_version {
+                Some(_) => println!("Remote changes: {}", if remote_changed { "yes" } else { "no" }),
+                None => println!("Nothing pushed yet"),
+            }
+        }
+        "push" => {
+            if remote_changed && !force {
+                return Err("The remote copy changed since the last sync; run `sync pull --merge` first \
+                    or `sync push --force` to overwrite it"
+                    .into());
+            }
+            let archive = build_archive(&config, store.as_ref()).await?;
+            // The server refuses the upload if someone pushed since the check above
+            let expect = match (force, remote_version.as_deref()) {
+                (true, _) => Expect::Anything,
+                (false, Some(version)) => Expect::Version(version),
+                (false, None) => Expect::Nothing,
+            };
+            let Some(version) = remote.put(archive.to_bytes()?, expect).await? else {
+                return Err("Another machine pushed while this one was; run `sync pull --merge` first \
+                    or `sync push --force` to overwrite it"
+                    .into());
+            };
+            SyncState::record(version, &archive.knowledge).save()?;
+            println!("Pushed {} fact(s)", archive.manifest.fact_count);
+        }
+        "pull" => {
+            let Some((data, version)) = remote.get().await? else {
+                println!("Nothing has been pushed yet.");
+                return Ok(());
+            };
+            if local_changed && !(force || merge) {
+                return Err("Local knowledge changed since the last sync; use `sync pull --merge` to combine \
+                    both sides or `sync pull --force` to replace the local copy"
+                    .into());
+            }
+            let archive = CharacterArchive::from_bytes(&data)?;
+            if merge {
+                let report = import_into(store.as_ref(), Some(archive.knowledge), archive.sessions, false).await?;
+                println!("{}", report);
+            } else {
+                store.save(&archive.knowledge).await?;
+                for (session_id, history) in &archive.sessions {
+                    store.save_session(session_id, history).await?;
+                }
+                println!("Replaced local knowledge with {} fact(s) from the remote copy", archive.manifest.fact_count);
+            }
+            config.character = archive.character;
+            config.knowledge_sources = archive.knowledge_sources;
+            config.save()?;
+
+            let knowledge = store.load().await?.unwrap_or_default();
+            SyncState::record(version, &knowledge).save()?;
+            knowledge_history(&config)?
+                .record(&knowledge, Some("sync pull".to_string()))?;
+        }
+        _ => println!("Usage: alya sync status|push|pull [--force] [--merge]"),
+    }
+    Ok(())
+}
+
+/// `alya learn [--dry-run]`: learns about the character once and exits, or only shows what
+/// that would do.
+async fn run_learn(config: ChatbotConfig, dry_run: bool) -> Result<(), Box<dyn std::error::Error>> {
+    if config.character.name.is_empty() {
+        return Err("No character is set up yet; run `alya chat` first".into());
+    }
+    let chatbot = Chatbot::new(config).await?;
+    if dry_run {
+        return chatbot.print_learning_plan().await;
+    }
+    secrets::require("GEMINI_API_KEY")?;
+    chatbot.learn_about_self().await?;
+    chatbot.sync_lore().await?;
+    chatbot.scheduler.lock().unwrap().record("learn", None)?;
+    Ok(())
+}
+
+/// `alya train <path>`: trains with a document, or with the new and changed files in a
+/// directory, and exits.
+async fn run_train(config: ChatbotConfig, path: &Path) -> Result<(), Box<dyn std::error::Error>> {
+    if config.character.name.is_empty() {
+        return Err("No character is set up yet; run `alya chat` first".into());
+    }
+    secrets::require("GEMINI_API_KEY")?;
+    let chatbot = Chatbot::new(config).await?;
+    chatbot.load_knowledge().await?;
+    if path.is_dir() {
+        chatbot.train_dir(path).await
+    } else {
+        chatbot.train_file(path).await.map(|_| ())
+    }
+}
+
+/// Passes on the text of a reply as the model writes it, holding back each line until it
+/// is clear it does not start with one of `markers`, and leaving out those that do.
+struct MarkerFilter<'a> {
+    markers: &'a [&'a str],
+    out: &'a mpsc::UnboundedSender<String>,
+    /// The start of the current line, while it could still be a marker line.
+    line: String,
+    /// Whether the current line is known to be part of the reply.
+    passing: bool,
+}
+
+impl<'a> MarkerFilter<'a> {
+    fn new(markers: &'a [&'a str], out: &'a mpsc::UnboundedSender<String>) -> Self {
+        MarkerFilter {
+            markers,
+            out,
+            line: String::new(),
+            passing: false,
+        }
+    }
+
+    fn push(&mut self, text: &str) {
+        for piece in text.split_inclusive('\n') {
+            if self.passing {
+                let _ = self.out.send(piece.to_string());
+            } else {
+                self.line.push_str(piece);
+                let start = self.line.trim_start().to_uppercase();
+                if !self.markers.iter().any(|marker| marker.starts_with(&start) || start.starts_with(marker)) {
+                    self.passing = true;
+                    let _ = self.out.send(std::mem::take(&mut self.line));
+                }
+            }
+            if piece.ends_with('\n') {
+                self.finish();
+                self.passing = false;
+            }
+        }
+    }
+
+    /// Sends what is held back of the last line, unless it is a marker line.
+    fn finish(&mut self) {
+        let line = std::mem::take(&mut self.line);
+        let start = line.trim_start().to_uppercase();
+        if !self.markers.iter().any(|marker| start.starts_with(marker)) && !line.is_empty() {
+            let _ = self.out.send(line);
+        }
+    }
+}
+
+/// Takes the lines the model started with `MEMORY_MARKER` out of a reply, returning the
+/// reply without them and what they note about the user.
+fn extract_memories(reply: &str) -> (String, Vec<String>) {
+    let mut memories = Vec::new();
+    let mut lines = Vec::new();
+    for line in reply.lines() {
+        let trimmed = line.trim();
+        match trimmed.get(..MEMORY_MARKER.len()) {
+            Some(marker) if marker.eq_ignore_ascii_case(MEMORY_MARKER) => {
+                let memory = trimmed[MEMORY_MARKER.len()..].trim();
+                if !memory.is_empty() {
+                    memories.push(memory.to_string());
+                }
+            }
+            _ => lines.push(line),
+        }
+    }
+    (lines.join("\n"), memories)
+}
+
+/// Finds the facts with a tag, when `filter` names one, or else the facts containing it.
+fn fact_query(filter: &str) -> FactQuery {
+    let mut query = FactQuery::default();
+    match FactTag::parse(filter) {
+        Some(tag) => query.tags.include.push(tag),
+        None if !filter.is_empty() => query.text = Some(filter.to_string()),
+        None => {}
+    }
+    query
+}
+
+/// A fact as the HTTP API shows it, without its embedding.
+fn fact_json(key: &str, fact: &Fact) -> Value {
+    json!({
+        "key": key,
+        "text": fact.text,
+        "source_url": fact.source_url,
+        "method": fact.method,
+        "tags": fact.tags,
+        "learned_at": fact.learned_at,
+        "confidence": fact.confidence,
+        "verified_at": fact.verified_at,
+    })
+}
+
+/// Prints a reply to a chat message, or that there was none, as text or as one line of JSON.
+fn print_reply(name: &str, reply: Option<&Reply>, format: OutputFormat) {
+    match (reply, format) {
+        (Some(reply), OutputFormat::Json) => println!("{}", json!(reply)),
+        (None, OutputFormat::Json) => println!("{}", json!({ "error": "no reply" })),
+        (Some(reply), OutputFormat::Text) => {
+            if output::is_quiet() {
+                println!("{}", reply.text);
+            } else {
+                println!("\n{}: {}", name, reply.text);
+            }
+            if !reply.sources.is_empty() {
+                println!("\nSources:");
+                for source in &reply.sources {
+                    println!("- {}", source);
+                }
+            }
+        }
+        (None, OutputFormat::Text) if output::is_quiet() => println!("Sorry, I couldn't process that request."),
+        (None, OutputFormat::Text) => println!("\n{}: Sorry, I couldn't process that request.", name),
+    }
+}
+
+/// `alya ask <question>`: answers one question from the character's knowledge and exits.
+/// Only the reply goes to stdout; the chat session is neither used nor saved.
+async fn run_ask(config: ChatbotConfig, question: &str, format: OutputFormat) -> Result<(), Box<dyn std::error::Error>> {
+    if config.character.name.is_empty() {
+        return Err("No character is set up yet; run `alya chat` first".into());
+    }
+    secrets::require("GEMINI_API_KEY")?;
+    // Progress goes to stderr so stdout holds only the answer
+    output::set_quiet(true);
+    let mut chatbot = Chatbot::new(config).await?;
+    chatbot.load_knowledge().await?;
+    chatbot.conversations.insert(DEFAULT_SESSION.to_string(), VecDeque::new());
+    let Some(reply) = chatbot.respond(DEFAULT_SESSION, question, None).await? else {
+        return Err("The model gave no reply".into());
+    };
+    print_reply(&chatbot.config.character.name, Some(&reply), format);
+    Ok(())
+}
+
+/// `alya daemon`: keeps the character and its knowledge loaded and answers the messages
+/// sent to its socket, running the scheduled jobs in between, until interrupted.
+async fn run_daemon(config: ChatbotConfig, no_initial_learn: bool) -> Result<(), Box<dyn std::error::Error>> {
+    if config.character.name.is_empty() {
+        return Err("No character is set up yet; run `alya chat` first".into());
+    }
+    secrets::require("GEMINI_API_KEY")?;
+    let mut listener = daemon::Listener::bind().await?;
+    let mut chatbot = Chatbot::new(config).await?;
+    chatbot.start(no_initial_learn).await?;
+    status!("Listening on {}", listener.address());
+
+    loop {
+        let next_due = chatbot.scheduler.lock().unwrap().next_due();
+        let wait = next_due.map(|due| (due - chrono::Utc::now()).to_std().unwrap_or_default());
+        tokio::select! {
+            stream = listener.accept() => {
+                if let Err(e) = serve_client(&mut chatbot, stream?).await {
+                    status!("Client error: {}", e);
+                }
+            }
+            _ = tokio::time::sleep(wait.unwrap_or_default()), if wait.is_some() => chatbot.run_due_jobs().await,
+            _ = tokio::signal::ctrl_c() => break,
+        }
+    }
+    status!("Stopping the daemon");
+    Ok(())
+}
+
+/// Answers the messages a daemon client sends until it disconnects, or until it has been
+/// idle for `CLIENT_TIMEOUT`.
+async fn serve_client(chatbot: &mut Chatbot, stream: Box<dyn daemon::Stream>) -> Result<(), Box<dyn std::error::Error>> {
+    use tokio::io::{AsyncBufReadExt, AsyncWriteExt, BufReader};
+
+    let mut stream = BufReader::new(stream);
+    loop {
+        let mut line = String::new();
+        if tokio::time::timeout(CLIENT_TIMEOUT, stream.read_line(&mut line)).await?? == 0 {
+            return Ok(());
+        }
+        let answer = match serde_json::from_str::<daemon::Request>(&line) {
+            Ok(request) => match chatbot.respond(DEFAULT_SESSION, &request.message, None).await {
+                Ok(Some(reply)) => {
+                    chatbot.save_session(DEFAULT_SESSION).await?;
+                    json!(reply)
+                }
+                Ok(None) => json!({ "error": "no reply" }),
+                Err(e) => json!({ "error": e.to_string() }),
+            },
+            Err(e) => json!({ "error": format!("not a request: {}", e) }),
+        };
+        stream.write_all(format!("{}\n", answer).as_bytes()).await?;
+    }
+}
+
+/// `alya send <message>`: has the running daemon answer a message and prints the reply.
+async fn run_send(config: &ChatbotConfig, message: &str, format: OutputFormat) -> Result<(), Box<dyn std::error::Error>> {
+    use tokio::io::{AsyncBufReadExt, AsyncWriteExt, BufReader};
+
+    let mut stream = daemon::connect()
+        .await
+        .map_err(|e| format!("No daemon is listening on {} ({}); start one with `alya daemon`", daemon::address(), e))?;
+    let request = json!(daemon::Request { message: message.to_string() });
+    stream.write_all(format!("{}\n", request).as_bytes()).await?;
+
+    let mut line = String::new();
+    BufReader::new(stream).read_line(&mut line).await?;
+    let answer: Value = serde_json::from_str(&line)?;
+    if let Some(error) = answer["error"].as_str() {
+        return Err(format!("The daemon could not answer: {}", error).into());
+    }
+    output::set_quiet(true);
+    print_reply(&config.character.name, Some(&serde_json::from_value(answer)?), format);
+    Ok(())
+}
+
+/// `alya serve`: answers the HTTP API on `bind`, running the scheduled jobs in between,
+/// until interrupted.
+async fn run_serve(
+    config: ChatbotConfig,
+    bind: &str,
+    grpc: Option<std::net::SocketAddr>,
+    no_auth: bool,
+    no_initial_learn: bool,
+) -> Result<(), Box<dyn std::error::Error>> {
+    if config.character.name.is_empty() {
+        return Err("No character is set up yet; run `alya chat` first".into());
+    }
+    secrets::require("GEMINI_API_KEY")?;
+    let auth = Auth::new(&ApiKeys::load(paths::data_dir())?, secrets::get(JWT_SECRET_NAME));
+    let auth = match (no_auth, auth.is_configured()) {
+        (true, _) => {
+            status!("Serving without authentication; anyone who can reach the API may use it");
+            None
+        }
+        (false, true) => Some(Arc::new(auth)),
+        (false, false) => {
+            return Err(format!(
+                "The API needs a key; create one with `alya api-keys create <name>`, set {} for JWTs, or use --no-auth",
+                JWT_SECRET_NAME
+            )
+            .into())
+        }
+    };
+    let listener = tokio::net::TcpListener::bind(bind).await?;
+    let mut chatbot = Chatbot::new(config).await?;
+    chatbot.start(no_initial_learn).await?;
+
+    let (calls, mut incoming) = tokio::sync::mpsc::channel(16);
+    status!("Serving the API on http://{}", listener.local_addr()?);
+    if chatbot.config.server.web_ui {
+        status!("Chat in a browser at http://{}/", listener.local_addr()?);
+    }
+    let name = chatbot.config.character.name.clone();
+    let limiter = Arc::new(Mutex::new(RateLimiter::new(chatbot.config.server.clone())));
+    let grpc = grpc.map(|address| {
+        status!("Serving the gRPC API on {}", address);
+        grpc::serve(address, calls.clone(), auth.clone(), limiter.clone(), name.clone())
+    });
+    let http = server::serve(listener, calls, auth, limiter, chatbot.config.server.web_ui, name);
+    // Both APIs run until one of them fails
+    let server = tokio::spawn(async move {
+        let http = async { http.await.map_err(Box::<dyn std::error::Error + Send + Sync>::from) };
+        match grpc {
+            Some(grpc) => tokio::try_join!(http, async { grpc.await.map_err(Box::from) }).map(|_| ()),
+            None => http.await,
+        }
+    });
+    loop {
+        let next_due = chatbot.scheduler.lock().unwrap().next_due();
+        let wait = next_due.map(|due| (due - chrono::Utc::now()).to_std().unwrap_or_default());
+        tokio::select! {
+            call = incoming.recv() => {
+                // The server only stops taking calls when it failed
+                let Some((call, answer)) = call else {
+                    break;
+                };
+                let _ = answer.send(chatbot.handle_api(call).await);
+            }
+            _ = tokio::time::sleep(wait.unwrap_or_default()), if wait.is_some() => chatbot.run_due_jobs().await,
+            _ = tokio::signal::ctrl_c() => {
+                server.abort();
+                status!("Stopping the server");
+                return Ok(());
+            }
+        }
+    }
+    server.await?.map_err(|e| e as Box<dyn std::error::Error>)
+}
+
+/// The chat platforms the gateway can answer on, by the names of their config sections.
+const CONNECTORS: [&str; 9] = ["discord", "telegram", "matrix", "slack", "irc", "whatsapp", "email", "xmpp", "mastodon"];
+
+/// The platforms set up in the config: those with a section, and Discord, Telegram and Slack,
+/// which need none, when their tokens are set.
+fn configured(config: &ChatbotConfig) -> Vec<&'static str> {
+    let set_up = [
+        config.discord.is_some() || secrets::get(discord::TOKEN_NAME).is_some(),
+        config.telegram.is_some() || secrets::get(telegram::TOKEN_NAME).is_some(),
+        config.matrix.is_some(),
+        secrets::get(slack::APP_TOKEN_NAME).is_some(),
+        config.irc.is_some(),
+        config.whatsapp.is_some(),
+        config.email.is_some(),
+        config.xmpp.is_some(),
+        config.mastodon.is_some(),
+    ];
+    CONNECTORS.into_iter().zip(set_up).filter_map(|(name, set_up)| set_up.then_some(name)).collect()
+}
+
+/// Adds the connector of the platform `name` to the gateway, with its settings and secrets.
+fn add_connector(gateway: &mut Gateway, name: &str, config: &ChatbotConfig) -> Result<(), Box<dyn std::error::Error>> {
+    match name {
+        "discord" => {
+            let token = secrets::require(discord::TOKEN_NAME)?;
+            let settings = config.discord.clone().unwrap_or_default();
+            if settings.admin_roles.is_empty() {
+                status!("No discord.admin_roles are set, so nobody may use the slash commands");
+            }
+            gateway.add(discord::Bot::new(token, settings));
+        }
+        "telegram" => {
+            let token = secrets::require(telegram::TOKEN_NAME)?;
+            let settings = config.telegram.clone().unwrap_or_default();
+            if settings.allowed_chats.is_empty() {
+                status!("No telegram.allowed_chats are set, so the bot answers nobody; the IDs of the chats it ignores are shown");
+            }
+            gateway.add(telegram::Bot::new(token, settings)?);
+        }
+        "matrix" => {
+            let Some(settings) = config.matrix.clone() else {
+                return Err("No Matrix homeserver is set; add a matrix section to the config".into());
+            };
+            let token = secrets::require(matrix::TOKEN_NAME)?;
+            let passphrase = secrets::get(matrix::STORE_PASSPHRASE_NAME);
+            gateway.add(matrix::Bot::new(settings, token, paths::data_path("matrix"), passphrase));
+        }
+        "slack" => {
+            let app_token = secrets::require(slack::APP_TOKEN_NAME)?;
+            let bot_token = secrets::require(slack::BOT_TOKEN_NAME)?;
+            gateway.add(slack::Bot::new(app_token, bot_token)?);
         }
+        "irc" => {
+            let Some(settings) = config.irc.clone() else {
+                return Err("No IRC network is set; add an irc section to the config".into());
+            };
+            let password = match settings.sasl_account {
+                Some(_) => Some(secrets::require(irc::PASSWORD_NAME)?),
+                None => None,
+            };
+            gateway.add(irc::Bot::new(settings, password));
+        }
+        "whatsapp" => {
+            let Some(settings) = config.whatsapp.clone() else {
+                return Err("No WhatsApp phone number is set; add a whatsapp section to the config".into());
+            };
+            let credentials = whatsapp::Credentials {
+                access_token: secrets::require(whatsapp::ACCESS_TOKEN_NAME)?,
+                app_secret: secrets::require(whatsapp::APP_SECRET_NAME)?,
+                verify_token: secrets::require(whatsapp::VERIFY_TOKEN_NAME)?,
+            };
+            gateway.add(whatsapp::Bot::new(settings, credentials));
+        }
+        "email" => {
+            let Some(settings) = config.email.clone() else {
+                return Err("No mailbox is set; add an email section to the config".into());
+            };
+            let password = secrets::require(email::PASSWORD_NAME)?;
+            gateway.add(email::Bot::new(settings, password, &config.character.name).map_err(|e| e as Box<dyn std::error::Error>)?);
+        }
+        "xmpp" => {
+            let Some(settings) = config.xmpp.clone() else {
+                return Err("No XMPP account is set; add an xmpp section to the config".into());
+            };
+            let password = secrets::require(xmpp::PASSWORD_NAME)?;
+            gateway.add(xmpp::Bot::new(settings, password));
+        }
+        "mastodon" => {
+            let Some(settings) = config.mastodon.clone() else {
+                return Err("No Mastodon account is set; add a mastodon section to the config".into());
+            };
+            let token = secrets::require(mastodon::TOKEN_NAME)?;
+            gateway.add(mastodon::Bot::new(settings, &token).map_err(|e| e as Box<dyn std::error::Error>)?);
+        }
+        _ => return Err(format!("Unknown chat platform \"{}\"; use {}", name, CONNECTORS.join(", ")).into()),
+    }
+    Ok(())
+}
 
-        let search_results: Value = response.json().await?;
-        let mut content = String::new();
+/// `alya gateway`, and `alya discord` and the other platforms' commands: answers on the chat
+/// platforms `names`, or on every one set up in the config if none are named, until stopped.
+/// They all share the one chatbot, and the scheduled jobs run in between. When a platform's
+/// connection fails for good the others go on, and the gateway stops with the last one.
+async fn run_gateway(config: ChatbotConfig, names: &[&str], no_initial_learn: bool) -> Result<(), Box<dyn std::error::Error>> {
+    if config.character.name.is_empty() {
+        return Err("No character is set up yet; run `alya chat` first".into());
+    }
+    secrets::require("GEMINI_API_KEY")?;
+    let names = if names.is_empty() { configured(&config) } else { names.to_vec() };
+    if names.is_empty() {
+        return Err(format!("No chat platform is set up; add a section to the config for one of {}", CONNECTORS.join(", ")).into());
+    }
+    let mut gateway = Gateway::default();
+    for name in names {
+        add_connector(&mut gateway, name, &config)?;
+    }
+    let titles = gateway.titles().join(", ");
+    let mut chatbot = Chatbot::new(config).await?;
+    chatbot.start(no_initial_learn).await?;
 
-        if let Some(items) = search_results.get("items") {
-            let empty_vec: Vec<Value> = Vec::new();
-            let array = items.as_array().unwrap_or(&empty_vec);
-            println!("Found {} search results", array.len());
-            
-            // Process only the first result for now
-            if let Some(first_item) = array.first() {
-                println!("Processing first search result");
-                
-                if let Some(snippet) = first_item.get("snippet") {
-                    if let Some(text) = snippet.as_str() {
-                        content.push_str(text);
-                        content.push_str("\n\n");
-                    }
-                }
-                
-                if let Some(link) = first_item.get("link") {
-                    if let Some(url) = link.as_str() {
-                        if !url.contains("pinterest.com") {
-                            println!("Processing URL: {}", url);
-                            if let Err(e) = self.learn_from_url(url).await {
-                                println!("Error processing URL: {}", e);
-                            }
-                        }
-                    }
+    let (calls, mut incoming) = mpsc::channel(16);
+    status!("Connecting to {}", titles);
+    let limits = Limits {
+        limiter: Arc::new(Mutex::new(RateLimiter::new(chatbot.config.server.clone()))),
+        name: chatbot.config.character.name.clone(),
+    };
+    let mut running = gateway.start(calls, limits);
+    loop {
+        let next_due = chatbot.scheduler.lock().unwrap().next_due();
+        let wait = next_due.map(|due| (due - chrono::Utc::now()).to_std().unwrap_or_default());
+        tokio::select! {
+            Some((call, answer)) = incoming.recv() => {
+                let _ = answer.send(chatbot.handle_api(call).await);
+            }
+            _ = tokio::time::sleep(wait.unwrap_or_default()), if wait.is_some() => chatbot.run_due_jobs().await,
+            stopped = running.join_next() => {
+                let Some(stopped) = stopped else {
+                    return Ok(());
+                };
+                let (title, result) = stopped?;
+                match result {
+                    Err(e) if running.is_empty() => return Err(e as Box<dyn std::error::Error>),
+                    Err(e) => status!("Stopped answering on {}: {}", title, e),
+                    Ok(()) => status!("Stopped answering on {}", title),
                 }
             }
+            _ = tokio::signal::ctrl_c() => {
+                running.abort_all();
+                status!("Disconnecting from {}", titles);
+                return Ok(());
+            }
         }
+    }
+}
 
-        // Process search content with AI
-        println!("Processing search results with AI...");
-        let processed_content = self.process_with_ai(&content).await?;
-        
-        Ok(processed_content)
+/// `alya --stdio`: answers JSON-RPC requests on stdin, as a language server does, until
+/// `shutdown` or the end of input. The scheduled jobs run in between.
+async fn run_stdio(config: ChatbotConfig, no_initial_learn: bool) -> Result<(), Box<dyn std::error::Error>> {
+    if config.character.name.is_empty() {
+        return Err("No character is set up yet; run `alya chat` first".into());
     }
+    secrets::require("GEMINI_API_KEY")?;
+    // stdout carries the protocol alone
+    output::set_quiet(true);
+    let mut messages = stdio::read_stdin();
+    let mut chatbot = Chatbot::new(config).await?;
+    chatbot.start(no_initial_learn).await?;
 
-    fn get_context(&self) -> String {
-        let mut context = format!(
-            "You are a chatbot named {}. Your personality: {}. Description: {}. Traits: {}. Interests: {}.\n",
-            self.config.character.name,
-            self.config.character.personality,
-            self.config.character.description,
-            self.config.character.traits.join(", "),
-            self.config.character.interests.join(", ")
-        );
-        
-        context.push_str(&format!("Additional context: {}\n", self.config.knowledge_sources.additional_context));
-        
-        if let Ok(knowledge) = self.knowledge.read() {
-            // Add learned facts
-            for (key, value) in &knowledge.facts {
-                context.push_str(&format!("\nKnowledge from {}:\n{}\n", key, value));
+    let mut stdout = tokio::io::stdout();
+    let mut initialized = false;
+    loop {
+        let next_due = chatbot.scheduler.lock().unwrap().next_due();
+        let wait = next_due.map(|due| (due - chrono::Utc::now()).to_std().unwrap_or_default());
+        let (message, framing) = tokio::select! {
+            message = messages.recv() => match message {
+                Some(message) => message?,
+                None => break,
+            },
+            _ = tokio::time::sleep(wait.unwrap_or_default()), if wait.is_some() => {
+                chatbot.run_due_jobs().await;
+                continue;
             }
-        }
-        
-        if !self.conversation_history.is_empty() {
-            context.push_str("\nPrevious conversation context:\n");
-            for msg in &self.conversation_history {
-                context.push_str(&format!("{}\n", msg));
+        };
+        let request: Value = match serde_json::from_str(&message) {
+            Ok(request) => request,
+            Err(e) => {
+                stdio::write_message(&mut stdout, &stdio::error(&Value::Null, stdio::PARSE_ERROR, &e.to_string()), framing).await?;
+                continue;
+            }
+        };
+        // Requests have an ID to answer to; notifications are not answered
+        let id = request.get("id").cloned();
+        let Some(method) = request["method"].as_str() else {
+            if let Some(id) = &id {
+                stdio::write_message(&mut stdout, &stdio::error(id, stdio::INVALID_REQUEST, "The request has no method"), framing).await?;
+            }
+            continue;
+        };
+        let params = &request["params"];
+        let answer = match method {
+            "initialize" => {
+                initialized = true;
+                Ok(json!({
+                    "serverInfo": { "name": "alya", "version": env!("CARGO_PKG_VERSION") },
+                    "character": chatbot.config.character,
+                    "methods": ["initialize", "chat", "learn", "shutdown"],
+                }))
+            }
+            "exit" => break,
+            _ if !initialized => Err((stdio::NOT_INITIALIZED, "Send initialize first".to_string())),
+            "chat" => match params["message"].as_str().filter(|message| !message.trim().is_empty()) {
+                Some(message) => {
+                    let stream = params["stream"].as_bool().unwrap_or(false);
+                    let session = params["session"].as_str().filter(|session| !session.is_empty()).unwrap_or(DEFAULT_SESSION);
+                    stdio_chat(&mut chatbot, session, message, stream, &mut stdout, framing).await?
+                }
+                None => Err((stdio::INVALID_PARAMS, "chat needs a message".to_string())),
+            },
+            "learn" => match chatbot.learn_about_self().await {
+                Ok(()) => {
+                    chatbot.scheduler.lock().unwrap().record("learn", None)?;
+                    Ok(json!(*chatbot.report.lock().unwrap()))
+                }
+                Err(e) => Err((stdio::INTERNAL_ERROR, e.to_string())),
+            },
+            "shutdown" => {
+                if let Some(id) = &id {
+                    stdio::write_message(&mut stdout, &stdio::result(id, Value::Null), framing).await?;
+                }
+                break;
             }
+            _ => Err((stdio::METHOD_NOT_FOUND, format!("There is no method {}", method))),
+        };
+        if let Some(id) = &id {
+            let answer = match answer {
+                Ok(result) => stdio::result(id, result),
+                Err((code, message)) => stdio::error(id, code, &message),
+            };
+            stdio::write_message(&mut stdout, &answer, framing).await?;
         }
-        
-        // Add personality guidance
-        context.push_str("\nRemember to stay in character and respond according to your personality traits. ");
-        context.push_str("If you're asked about something you don't know, be honest about it. ");
-        context.push_str("Use your learned knowledge to provide detailed and accurate responses.\n");
-        
-        context
     }
+    Ok(())
+}
 
-    fn save_config(&self) -> Result<(), Box<dyn std::error::Error>> {
-        let config_str = serde_json::to_string_pretty(&self.config)?;
-        fs::write("config/chatbot_config.json", config_str)?;
-        Ok(())
+/// `alya mcp`: serves an MCP client on stdin and stdout, until the end of input. The
+/// scheduled jobs run in between.
+async fn run_mcp(config: ChatbotConfig, no_initial_learn: bool) -> Result<(), Box<dyn std::error::Error>> {
+    if config.character.name.is_empty() {
+        return Err("No character is set up yet; run `alya chat` first".into());
     }
+    secrets::require("GEMINI_API_KEY")?;
+    // stdout carries the protocol alone
+    output::set_quiet(true);
+    let mut messages = stdio::read_stdin();
+    let mut chatbot = Chatbot::new(config).await?;
+    chatbot.start(no_initial_learn).await?;
 
-    async fn train_with_text(&self, text: &str) -> Result<(), Box<dyn std::error::Error>> {
-        println!("Training with provided text...");
-        
-        // Process the text with AI to make it more personal and relevant
-        let processed_content = self.process_with_ai(text).await?;
-        
-        if !processed_content.is_empty() {
-            let mut knowledge = self.knowledge.write().unwrap();
-            knowledge.facts.insert(format!("trained_knowledge_{}", chrono::Utc::now().timestamp()), processed_content);
-            
-            // Save the updated knowledge
-            drop(knowledge);
-            self.save_knowledge()?;
-            println!("Successfully trained with new text!");
+    // The client is served in a task of its own, which sends its calls to the chatbot
+    let (calls, mut incoming) = mpsc::channel(16);
+    let client = tokio::spawn(async move {
+        let mcp = mcp::Session {
+            calls,
+            session: MCP_SESSION.to_string(),
+            limiter: None,
+        };
+        let mut stdout = tokio::io::stdout();
+        while let Some(message) = messages.recv().await {
+            let (message, framing) = message?;
+            let answer = match serde_json::from_str(&message) {
+                Ok(message) => mcp.handle(&message).await,
+                Err(e) => Some(stdio::error(&Value::Null, stdio::PARSE_ERROR, &e.to_string())),
+            };
+            if let Some(answer) = answer {
+                stdio::write_message(&mut stdout, &answer, framing).await?;
+            }
+        }
+        Ok::<(), std::io::Error>(())
+    });
+    loop {
+        let next_due = chatbot.scheduler.lock().unwrap().next_due();
+        let wait = next_due.map(|due| (due - chrono::Utc::now()).to_std().unwrap_or_default());
+        tokio::select! {
+            call = incoming.recv() => {
+                // The client task only stops calling at the end of input
+                let Some((call, answer)) = call else {
+                    break;
+                };
+                let _ = answer.send(chatbot.handle_api(call).await);
+            }
+            _ = tokio::time::sleep(wait.unwrap_or_default()), if wait.is_some() => chatbot.run_due_jobs().await,
         }
-        
-        Ok(())
     }
+    client.await??;
+    Ok(())
 }
 
-impl Knowledge {
-    fn merge(&mut self, other: Knowledge) {
-        self.facts.extend(other.facts);
-        self.search_history.extend(other.search_history);
-        self.learned_urls.extend(other.learned_urls);
-        self.cached_content.extend(other.cached_content);
-        self.external_url_count = other.external_url_count;
-    }
+/// Answers a `chat` request in `session`, sending `chat/chunk` notifications with the reply
+/// as it is written when `stream` is set.
+async fn stdio_chat(
+    chatbot: &mut Chatbot,
+    session: &str,
+    message: &str,
+    stream: bool,
+    stdout: &mut tokio::io::Stdout,
+    framing: stdio::Framing,
+) -> Result<Result<Value, (i64, String)>, Box<dyn std::error::Error>> {
+    chatbot.open_session(session).await?;
+    let (chunks, mut written) = mpsc::unbounded_channel();
+    let reply = async {
+        let reply = chatbot.respond(session, message, stream.then_some(&chunks)).await;
+        drop(chunks);
+        reply
+    };
+    let forward = async {
+        while let Some(chunk) = written.recv().await {
+            stdio::write_message(stdout, &stdio::notification("chat/chunk", json!({ "text": chunk })), framing).await?;
+        }
+        Ok::<(), std::io::Error>(())
+    };
+    let (reply, forwarded) = tokio::join!(reply, forward);
+    forwarded?;
+    Ok(match reply {
+        Ok(Some(reply)) => {
+            chatbot.save_session(session).await?;
+            Ok(json!(reply))
+        }
+        Ok(None) => Err((stdio::INTERNAL_ERROR, "The model gave no reply".to_string())),
+        Err(e) => Err((stdio::INTERNAL_ERROR, e.to_string())),
+    })
 }
 
 #[tokio::main]
 async fn main() -> Result<(), Box<dyn std::error::Error>> {
+    let result = run(Cli::parse()).await;
+    // Events still on their way to webhooks would be dropped with the runtime
+    webhooks::flush().await;
+    result
+}
+
+async fn run(cli: Cli) -> Result<(), Box<dyn std::error::Error>> {
+    paths::init(&cli);
+    if let Some(name) = cli.character.as_deref().filter(|_| cli.config.is_none()) {
+        if !paths::character_config(name).exists() {
+            return Err(format!("No character is installed as {}; see `alya character list`", name).into());
+        }
+    }
+    if let Some(name) = cli.profile.as_deref().filter(|_| cli.config.is_none()) {
+        if !paths::profile_dir(name).is_dir() {
+            return Err(format!("There is no profile {}; make it with `alya profile create {}`", name, name).into());
+        }
+    }
+    // JSON output keeps stdout for the replies too
+    output::set_quiet(cli.quiet || cli.output == OutputFormat::Json);
     dotenv().ok();
-    let api_key = env::var("GEMINI_API_KEY").expect("GEMINI_API_KEY not set");
-    
+    // So the keys are found from any working directory
+    dotenv::from_path(paths::config_dir().join(".env")).ok();
+
     // Load or create configuration
-    let config_path = Path::new("config/chatbot_config.json");
+    let config_path = paths::config_file();
     let config: ChatbotConfig = if config_path.exists() {
-        let config_str = fs::read_to_string(config_path)?;
-        serde_json::from_str(&config_str)?
+        let config: ChatbotConfig = config_format::read_with_env(config_path)?;
+        config.validate(config_path)?;
+        config
     } else {
         // Create default config if it doesn't exist
-        let default_config = ChatbotConfig {
+        let config = ChatbotConfig {
             character: CharacterConfig {
                 name: String::new(),
                 personality: String::new(),
                 description: String::new(),
                 traits: Vec::new(),
                 interests: Vec::new(),
+                examples: Vec::new(),
+                greeting: None,
             },
             knowledge_sources: KnowledgeSources {
                 self_learning_urls: Vec::new(),
                 additional_context: String::new(),
+                lore_dir: default_lore_dir(),
+                feeds: Vec::new(),
             },
             conversation_settings: ConversationSettings {
                 max_history: 5,
                 learning_frequency: "daily".to_string(),
+                include_tags: Vec::new(),
+                exclude_tags: Vec::new(),
+                citations: false,
+                recent_news: default_recent_news(),
+                live_search: false,
+                user_memory: default_user_memory(),
             },
+            learning: LearningSettings::default(),
+            retrieval: RetrievalSettings::default(),
+            storage: StorageSettings::default(),
+            search: SearchSettings::default(),
+            sync: None,
+            jobs: HashMap::new(),
+            server: ServerSettings::default(),
+            webhooks: Vec::new(),
+            mcp_servers: HashMap::new(),
+            discord: None,
+            telegram: None,
+            matrix: None,
+            irc: None,
+            whatsapp: None,
+            email: None,
+            xmpp: None,
+            mastodon: None,
         };
-        default_config
+        // A deployment may be configured by its environment alone
+        let config = config_format::with_env(config)?;
+        config.validate(config_path)?;
+        config
     };
-    
-    let mut chatbot = Chatbot::new(config);
-    
-    println!("Welcome to the Self-Learning Rust Chatbot!");
-    
+
+    if cli.stdio && !matches!(cli.command, None | Some(Command::Chat)) {
+        return Err("--stdio takes the place of the chat; it can't be used with another command".into());
+    }
+    match cli.command.unwrap_or(Command::Chat) {
+        Command::Chat if cli.stdio => return run_stdio(config, cli.no_initial_learn).await,
+        Command::Chat => {}
+        Command::Ask { question } => return run_ask(config, &question.join(" "), cli.output).await,
+        Command::Daemon => return run_daemon(config, cli.no_initial_learn).await,
+        Command::Send { message } => return run_send(&config, &message.join(" "), cli.output).await,
+        Command::Serve { bind, no_auth, grpc } => return run_serve(config, &bind, grpc, no_auth, cli.no_initial_learn).await,
+        Command::Mcp => return run_mcp(config, cli.no_initial_learn).await,
+        Command::Gateway { names } => {
+            let names: Vec<&str> = names.iter().map(String::as_str).collect();
+            return run_gateway(config, &names, cli.no_initial_learn).await;
+        }
+        Command::Discord => return run_gateway(config, &["discord"], cli.no_initial_learn).await,
+        Command::Telegram => return run_gateway(config, &["telegram"], cli.no_initial_learn).await,
+        Command::Matrix => return run_gateway(config, &["matrix"], cli.no_initial_learn).await,
+        Command::Slack => return run_gateway(config, &["slack"], cli.no_initial_learn).await,
+        Command::Irc => return run_gateway(config, &["irc"], cli.no_initial_learn).await,
+        Command::Whatsapp { bind } => {
+            let mut config = config;
+            if let (Some(bind), Some(settings)) = (bind, config.whatsapp.as_mut()) {
+                settings.bind = bind;
+            }
+            return run_gateway(config, &["whatsapp"], cli.no_initial_learn).await;
+        }
+        Command::Email => return run_gateway(config, &["email"], cli.no_initial_learn).await,
+        Command::Xmpp => return run_gateway(config, &["xmpp"], cli.no_initial_learn).await,
+        Command::Mastodon => return run_gateway(config, &["mastodon"], cli.no_initial_learn).await,
+        Command::ApiKeys { action, name, scope } => return run_api_keys(&action, name.as_deref(), &scope),
+        Command::Learn { dry_run } => return run_learn(config, dry_run).await,
+        Command::Train { path } => return run_train(config, &path).await,
+        Command::Migrate { dry_run } => return run_migration(&config, dry_run).await,
+        Command::Export { file } => return run_export(&config, &file).await,
+        Command::Import { file, keep_character, dry_run } => return run_import(config, &file, keep_character, dry_run).await,
+        Command::Card { action, file } => return run_card(config, &action, &file),
+        Command::Character { action, url, name, yes } => return run_character(config, &action, url.as_deref(), name.as_deref(), yes).await,
+        Command::Profile { action, name } => return run_profile(&action, name.as_deref()),
+        Command::Group { characters, topic, rounds, join, transcript } => {
+            return run_group(&config, &characters, topic.as_deref(), rounds, join, transcript).await;
+        }
+        Command::ExportCharacter { file, image, with_knowledge } => {
+            return run_export_character(config, &file, image.as_deref(), with_knowledge).await;
+        }
+        Command::Sync { action, force, merge } => return run_sync(config, &action, force, merge).await,
+        Command::Encryption { action } => return run_encryption(&action),
+        Command::Keys { action, name } => return run_keys(&action, name.as_deref()).await,
+        Command::Setup => return run_setup(config).await,
+        Command::Cookies { action, domain } => return run_cookies(&config, &action, domain.as_deref()).await,
+        Command::Knowledge { action, id } => return run_knowledge(&config, &action, id.as_deref()).await,
+    }
+
+    if output::is_quiet() && config.character.name.is_empty() {
+        return Err("No character is set up yet; run `alya chat` without --quiet first".into());
+    }
+
+    if !output::is_quiet() {
+        println!("Welcome to the Self-Learning Rust Chatbot!");
+    }
+
     // If character is not configured, ask for configuration
-    if chatbot.config.character.name.is_empty() {
-        println!("Let's set up your chatbot's character.");
-        
-        println!("\nEnter character name: ");
-        let mut character_name = String::new();
-        std::io::stdin().read_line(&mut character_name)?;
-        chatbot.config.character.name = character_name.trim().to_string();
-        
-        println!("Enter character personality: ");
-        let mut personality = String::new();
-        std::io::stdin().read_line(&mut personality)?;
-        chatbot.config.character.personality = personality.trim().to_string();
-        
-        println!("Enter character description: ");
-        let mut description = String::new();
-        std::io::stdin().read_line(&mut description)?;
-        chatbot.config.character.description = description.trim().to_string();
-        
-        println!("Enter character traits (comma-separated): ");
-        let mut traits = String::new();
-        std::io::stdin().read_line(&mut traits)?;
-        chatbot.config.character.traits = traits.trim().split(',').map(|s| s.trim().to_string()).collect();
-        
-        println!("Enter character interests (comma-separated): ");
-        let mut interests = String::new();
-        std::io::stdin().read_line(&mut interests)?;
-        chatbot.config.character.interests = interests.trim().split(',').map(|s| s.trim().to_string()).collect();
-        
-        chatbot.save_config()?;
-    }
-    
-    println!("\nChatbot initialized as: {}", chatbot.config.character.name);
-    println!("Personality: {}", chatbot.config.character.personality);
-    println!("\nAvailable commands:");
-    println!("- Type 'exit' to quit the chat");
-    println!("- Type 'learn' to make the chatbot search and learn about itself");
-    println!("- Type 'train' to train the chatbot with custom text");
-    println!("- Type 'add_url <url>' to add a new learning source");
-    println!("- Type 'save' to save the current configuration");
-    println!("- Type anything else to chat with the AI");
-    
-    // Initial self-learning
-    println!("\nPerforming initial self-learning...");
-    chatbot.learn_about_self().await?;
-    
-    let client = reqwest::Client::new();
-    
+    let config = if config.character.name.is_empty() {
+        println!("Let's set up your chatbot.");
+        let config = setup_wizard(config).await?;
+        config.save()?;
+        config
+    } else {
+        config
+    };
+
+    secrets::require("GEMINI_API_KEY")?;
+
+    let mut chatbot = Chatbot::new(config).await?;
+
+    if !output::is_quiet() {
+        println!("\nChatbot initialized as: {}", chatbot.config.character.name);
+        println!("Personality: {}", chatbot.config.character.personality);
+        println!("\nAvailable commands:");
+        println!("- Type 'exit' to quit the chat");
+        println!("- Type 'learn' to make the chatbot search and learn about itself, or 'learn --dry-run' to see what it would do");
+        println!("- Type 'refresh' to learn again from learned pages that changed");
+        println!("- Type 'jobs' to list the scheduled background jobs, or 'jobs run <name>' to run one now");
+        println!("- Type 'train' to train the chatbot with custom text");
+        println!("- Type 'train_file <path>' to train the chatbot with a book or document (.epub, .docx, .txt, .md)");
+        println!("- Type 'train_dir <path>' to train the chatbot with the new and changed files in a directory");
+        println!("- Type 'ocr <path or url>' to read the text in an image, such as a profile screenshot or manga page, and learn from it");
+        println!("- Type 'lore' to reload the lorebook entries in the lore directory");
+        println!("- Type 'import_qa <path> [examples]' to import a JSONL or CSV Q&A dataset, optionally as example replies too");
+        println!("- Type 'add_url <url> [depth]' to add a new learning source, following its links up to depth");
+        println!("- Type 'add_sitemap <url> [pattern]' to add the pages of a sitemap as learning sources");
+        println!("- Type 'wiki [language:]<topic>' to look up a background topic on Wikipedia and learn its summary");
+        println!("- Type 'anilist [name]' to learn a character's AniList profile, appearances and voice actors (the chatbot's own by default)");
+        println!("- Type 'vndb [name]' to learn a visual-novel character's VNDB profile, traits and routes (the chatbot's own by default)");
+        println!("- Type 'add_fandom <wiki> <page title>' to add a Fandom wiki article and its infobox as a learning source");
+        println!("- Type 'add_feed <url>' to subscribe to an RSS or Atom feed and learn from its new entries");
+        println!("- Type 'feeds' to check the subscribed feeds for new entries now");
+        println!("- Type 'facts [tag|text]' to list learned facts and where they came from");
+        println!("- Type 'citations on|off' to toggle source citations after factual replies");
+        println!("- Type 'live_search on|off' to toggle searching the web when a question isn't answered by the knowledge");
+        println!("- Type 'conflicts' to list contradictions between learned facts");
+        println!("- Type 'resolve <n> existing|new|both' to settle a contradiction");
+        println!("- Type 'export_json <path>' to export the learned knowledge as JSON");
+        println!("- Type 'knowledge history' to list saved versions of the knowledge");
+        println!("- Type 'knowledge rollback <n>' to revert the knowledge to version n");
+        println!("- Type 'knowledge verify' to check learned pages for dead links and clean up after them");
+        println!("- Type 'save' to save the current configuration");
+        println!("- Type anything else to chat with the AI");
+    }
+
+    chatbot.start(cli.no_initial_learn).await?;
+
+    if let Some(greeting) = chatbot.config.character.greeting.clone() {
+        if !output::is_quiet() && chatbot.conversation(DEFAULT_SESSION).next().is_none() {
+            println!("\n{}: {}", chatbot.config.character.name, greeting);
+            chatbot.add_to_history(DEFAULT_SESSION, &format!("{}: {}", chatbot.config.character.name, greeting));
+        }
+    }
+
+    // The character and prompt settings can be tuned while chatting
+    let mut watcher = match reload::ConfigWatcher::new(paths::config_file()) {
+        Ok(watcher) => Some(watcher),
+        Err(e) => {
+            status!("Not watching {} for changes: {}", paths::config_file().display(), e);
+            None
+        }
+    };
+
     loop {
-        println!("\nYou: ");
-        let mut input = String::new();
-        std::io::stdin().read_line(&mut input)?;
-        
-        let input = input.trim();
-        
+        if !output::is_quiet() {
+            println!("\nYou: ");
+        }
+        // Scheduled jobs run and config changes are taken up while waiting for the line
+        let line = loop {
+            let next_due = chatbot.scheduler.lock().unwrap().next_due();
+            let wait = next_due.map(|due| (due - chrono::Utc::now()).to_std().unwrap_or_default());
+            tokio::select! {
+                line = input::read_message() => break line?,
+                _ = tokio::time::sleep(wait.unwrap_or_default()), if wait.is_some() => {
+                    chatbot.run_due_jobs().await;
+                    if !output::is_quiet() && !input::is_editing() {
+                        println!("\nYou: ");
+                    }
+                }
+                _ = async { watcher.as_mut().unwrap().changed().await }, if watcher.is_some() => {
+                    match chatbot.reload_config() {
+                        Ok((live, restart)) => {
+                            if !live.is_empty() {
+                                status!("\n[{} changed: {} reloaded]", paths::config_file().display(), live.join(", "));
+                            }
+                            if !restart.is_empty() {
+                                status!("\n[{} changed: restart alya for {}]", paths::config_file().display(), restart.join(", "));
+                            }
+                            if !output::is_quiet() && !input::is_editing() && (!live.is_empty() || !restart.is_empty()) {
+                                println!("\nYou: ");
+                            }
+                        }
+                        Err(e) => status!("\n[Could not reload {}, keeping the current settings: {}]", paths::config_file().display(), e),
+                    }
+                }
+            }
+        };
+        let Some(line) = line else {
+            // stdin was closed, as at the end of piped input
+            break;
+        };
+
+        let input = line.trim();
+        if input.is_empty() {
+            continue;
+        }
+
         if input.to_lowercase() == "exit" {
-            println!("Goodbye!");
+            if !output::is_quiet() {
+                println!("Goodbye!");
+            }
             break;
         }
-        
+
+        if input.to_lowercase() == "learn --dry-run" {
+            chatbot.print_learning_plan().await?;
+            continue;
+        }
+
         if input.to_lowercase() == "learn" {
             println!("Searching and learning about myself...");
             chatbot.learn_about_self().await?;
+            chatbot.scheduler.lock().unwrap().record("learn", None)?;
+            continue;
+        }
+
+        if input.to_lowercase() == "jobs" {
+            chatbot.print_jobs();
+            continue;
+        }
+
+        if let Some(name) = input.strip_prefix("jobs run ") {
+            let name = name.trim();
+            if JOBS.iter().any(|(job, _)| *job == name) {
+                chatbot.run_job(name).await;
+            } else {
+                let names: Vec<&str> = JOBS.iter().map(|(job, _)| *job).collect();
+                println!("There is no job called {}; the jobs are {}", name, names.join(", "));
+            }
+            continue;
+        }
+
+        if input.to_lowercase() == "refresh" {
+            println!("Checking learned pages for changes...");
+            chatbot.refresh_pages().await?;
             continue;
         }
-        
+
         if input.to_lowercase() == "train" {
             println!("Enter the training text (type 'END' on a new line when finished):");
             let mut training_text = String::new();
-            loop {
-                let mut line = String::new();
-                std::io::stdin().read_line(&mut line)?;
+            while let Some(line) = input::read_line().await? {
                 if line.trim() == "END" {
                     break;
                 }
@@ -524,66 +5916,255 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
             chatbot.train_with_text(&training_text).await?;
             continue;
         }
-        
+
+        if let Some(path) = input.strip_prefix("train_file ") {
+            if let Err(e) = chatbot.train_file(Path::new(path.trim())).await {
+                println!("Error training with {}: {}", path.trim(), e);
+            }
+            continue;
+        }
+
+        if let Some(source) = input.strip_prefix("ocr ") {
+            match chatbot.learn_from_image(source.trim()).await {
+                Ok(text) if !text.is_empty() => println!("\n{}", text),
+                Ok(_) => {}
+                Err(e) => println!("Error reading {}: {}", source.trim(), e),
+            }
+            continue;
+        }
+
+        if input.to_lowercase() == "lore" {
+            chatbot.sync_lore().await?;
+            continue;
+        }
+
+        if let Some(args) = input.strip_prefix("import_qa ") {
+            let (path, as_examples) = match args.trim().strip_suffix(" examples") {
+                Some(path) => (path.trim(), true),
+                None => (args.trim(), false),
+            };
+            if let Err(e) = chatbot.import_qa(Path::new(path), as_examples).await {
+                println!("Error importing {}: {}", path, e);
+            }
+            continue;
+        }
+
+        if let Some(path) = input.strip_prefix("train_dir ") {
+            if let Err(e) = chatbot.train_dir(Path::new(path.trim())).await {
+                println!("Error training with {}: {}", path.trim(), e);
+            }
+            continue;
+        }
+
+        if input.to_lowercase() == "facts" || input.starts_with("facts ") {
+            chatbot.print_facts(input["facts".len()..].trim()).await?;
+            continue;
+        }
+
+        if let Some(path) = input.strip_prefix("export_json ") {
+            let path = path.trim();
+            chatbot.export_knowledge(path).await?;
+            println!("Knowledge exported to {}", path);
+            continue;
+        }
+
+        if input.to_lowercase() == "knowledge verify" {
+            chatbot.load_knowledge().await?;
+            let gone = chatbot.check_sources(None).await?;
+            println!("{} learned source(s) no longer exist", gone);
+            chatbot.review_gone_sources().await?;
+            continue;
+        }
+
+        if input.to_lowercase() == "knowledge history" {
+            print_history(&chatbot.history)?;
+            continue;
+        }
+
+        if let Some(id) = input.strip_prefix("knowledge rollback ") {
+            match id.trim().trim_start_matches('#').parse::<u64>() {
+                Ok(id) => match chatbot.rollback_knowledge(id).await {
+                    Ok(()) => println!("Knowledge rolled back to snapshot #{}", id),
+                    Err(e) => println!("Error rolling back knowledge: {}", e),
+                },
+                Err(_) => println!("Usage: knowledge rollback <n> (see 'knowledge history')"),
+            }
+            continue;
+        }
+
+        if let Some(mode) = input.strip_prefix("citations ") {
+            match mode.trim().to_lowercase().as_str() {
+                "on" => chatbot.config.conversation_settings.citations = true,
+                "off" => chatbot.config.conversation_settings.citations = false,
+                _ => {
+                    println!("Usage: citations on|off");
+                    continue;
+                }
+            }
+            println!("Citation mode {}", if chatbot.config.conversation_settings.citations { "enabled" } else { "disabled" });
+            chatbot.save_config()?;
+            continue;
+        }
+
+        if let Some(mode) = input.strip_prefix("live_search ") {
+            match mode.trim().to_lowercase().as_str() {
+                "on" => chatbot.config.conversation_settings.live_search = true,
+                "off" => chatbot.config.conversation_settings.live_search = false,
+                _ => {
+                    println!("Usage: live_search on|off");
+                    continue;
+                }
+            }
+            println!("Live search {}", if chatbot.config.conversation_settings.live_search { "enabled" } else { "disabled" });
+            chatbot.save_config()?;
+            continue;
+        }
+
+        if input.to_lowercase() == "conflicts" {
+            chatbot.print_contradictions();
+            continue;
+        }
+
+        if let Some(args) = input.strip_prefix("resolve ") {
+            let mut parts = args.split_whitespace();
+            let index = parts.next().and_then(|n| n.parse::<usize>().ok()).and_then(|n| n.checked_sub(1));
+            let verdict = parts.next().and_then(Verdict::parse);
+            match (index, verdict) {
+                (Some(index), Some(verdict)) if chatbot.knowledge.read().unwrap().pending_contradictions().contains(&index) => {
+                    chatbot.resolve_contradiction(index, verdict, "user", String::new()).await?;
+                    chatbot.save_knowledge().await?;
+                    println!("Contradiction {} resolved.", index + 1);
+                }
+                _ => println!("Usage: resolve <number> existing|new|both (see 'conflicts')"),
+            }
+            continue;
+        }
+
         if input.to_lowercase() == "save" {
             chatbot.save_config()?;
             println!("Configuration saved!");
             continue;
         }
-        
-        if input.starts_with("add_url ") {
-            let url = input[8..].trim();
-            chatbot.config.knowledge_sources.self_learning_urls.push(url.to_string());
+
+        if let Some(args) = input.strip_prefix("add_url ") {
+            let mut args = args.split_whitespace();
+            let Some(url) = args.next() else {
+                println!("Usage: add_url <url> [depth]");
+                continue;
+            };
+            if !chatbot.config.learning.domains.allows(url) {
+                println!("Not adding {}: it is outside the allowed domains in learning.domains", url);
+                continue;
+            }
+            let source = match args.next().and_then(|depth| depth.parse().ok()) {
+                Some(depth) if depth > 0 => LearningSource::Crawl {
+                    url: url.to_string(),
+                    depth,
+                    max_pages: default_max_pages(),
+                },
+                _ => LearningSource::Url(url.to_string()),
+            };
+            chatbot.config.knowledge_sources.self_learning_urls.push(source);
             println!("Added new learning source: {}", url);
             chatbot.save_config()?;
             continue;
         }
-        
-        // Add user input to history
-        chatbot.add_to_history(&format!("User: {}", input));
-        
-        // Prepare the prompt with context
-        let context = chatbot.get_context();
-        let prompt = format!("{}\n\nUser: {}\n{}: ", context, input, chatbot.config.character.name);
-        
-        // Prepare the request to Gemini API
-        let response = client
-            .post(format!(
-                "https://generativelanguage.googleapis.com/v1beta/models/gemini-2.0-flash:generateContent?key={}",
-                api_key
-            ))
-            .json(&json!({
-                "contents": [{
-                    "parts": [{
-                        "text": prompt
-                    }]
-                }]
-            }))
-            .send()
-            .await?;
-            
-        let response_json: Value = response.json().await?;
-        
-        // Extract and print the response
-        if let Some(candidates) = response_json.get("candidates") {
-            if let Some(first_candidate) = candidates[0].as_object() {
-                if let Some(content) = first_candidate.get("content") {
-                    if let Some(parts) = content.get("parts") {
-                        if let Some(first_part) = parts[0].as_object() {
-                            if let Some(text) = first_part.get("text") {
-                                let bot_response = text.as_str().unwrap_or("No response");
-                                println!("\n{}: {}", chatbot.config.character.name, bot_response);
-                                chatbot.add_to_history(&format!("{}: {}", chatbot.config.character.name, bot_response));
-                                continue;
-                            }
-                        }
-                    }
+
+        if let Some(topic) = input.strip_prefix("wiki ") {
+            // `wiki ja:Topic` looks the topic up on another Wikipedia
+            let (language, topic) = match topic.trim().split_once(':') {
+                Some((language, topic)) if (2..=3).contains(&language.len()) && language.chars().all(|c| c.is_ascii_lowercase()) => {
+                    (language, topic.trim())
+                }
+                _ => (DEFAULT_WIKIPEDIA, topic.trim()),
+            };
+            match chatbot.learn_wikipedia(language, topic, &[], true).await {
+                Ok(text) => println!("\n{}", text),
+                Err(e) => println!("Error looking up {}: {}", topic, e),
+            }
+            continue;
+        }
+
+        if input == "anilist" || input.starts_with("anilist ") {
+            let name = input.strip_prefix("anilist").unwrap_or_default().trim();
+            let name = if name.is_empty() { chatbot.config.character.name.clone() } else { name.to_string() };
+            match chatbot.learn_anilist(&name, true).await {
+                Ok(profile) => println!("\n{}", profile),
+                Err(e) => println!("Error looking up {} on AniList: {}", name, e),
+            }
+            continue;
+        }
+
+        if input == "vndb" || input.starts_with("vndb ") {
+            let name = input.strip_prefix("vndb").unwrap_or_default().trim();
+            let name = if name.is_empty() { chatbot.config.character.name.clone() } else { name.to_string() };
+            match chatbot.learn_vndb(&name, true).await {
+                Ok(profile) => println!("\n{}", profile),
+                Err(e) => println!("Error looking up {} on VNDB: {}", name, e),
+            }
+            continue;
+        }
+
+        if let Some(args) = input.strip_prefix("add_fandom ") {
+            let Some((fandom, page)) = args.trim().split_once(' ') else {
+                println!("Usage: add_fandom <wiki> <page title>");
+                continue;
+            };
+            let source = LearningSource::Fandom {
+                fandom: fandom.to_string(),
+                page: page.trim().to_string(),
+                language: None,
+            };
+            let url = source.url();
+            if !chatbot.config.learning.domains.allows(&url) {
+                println!("Not adding {}: it is outside the allowed domains in learning.domains", url);
+                continue;
+            }
+            chatbot.config.knowledge_sources.self_learning_urls.push(source);
+            println!("Added new learning source: {}", url);
+            chatbot.save_config()?;
+            continue;
+        }
+
+        if let Some(url) = input.strip_prefix("add_feed ") {
+            match chatbot.add_feed(url.trim()).await {
+                Ok(()) => println!("Type 'feeds' to learn from its newest entries"),
+                Err(e) => println!("Error adding feed: {}", e),
+            }
+            continue;
+        }
+
+        if input.to_lowercase() == "feeds" {
+            if chatbot.config.knowledge_sources.feeds.is_empty() {
+                println!("No feeds subscribed; add one with 'add_feed <url>'");
+            } else {
+                chatbot.learn_from_feeds(true).await?;
+            }
+            continue;
+        }
+
+        if let Some(args) = input.strip_prefix("add_sitemap ") {
+            let mut args = args.split_whitespace();
+            let Some(url) = args.next() else {
+                println!("Usage: add_sitemap <url> [pattern]");
+                continue;
+            };
+            match chatbot.add_sitemap(url, args.next()).await {
+                Ok((found, added)) => {
+                    println!("Found {} matching page(s), added {} new learning source(s)", found, added);
+                    println!("Type 'learn' to learn from them");
                 }
+                Err(e) => println!("Error reading sitemap: {}", e),
             }
+            continue;
+        }
+
+        let reply = chatbot.respond(DEFAULT_SESSION, input, None).await?;
+        if reply.is_some() {
+            chatbot.save_session(DEFAULT_SESSION).await?;
         }
-        
-        println!("\n{}: Sorry, I couldn't process that request.", chatbot.config.character.name);
+        print_reply(&chatbot.config.character.name, reply.as_ref(), cli.output);
     }
-    
+
     Ok(())
 } 
\ No newline at end of file
@@ -0,0 +1,213 @@
+use crate::gateway::{split_message, Connector, Incoming};
+use crate::server::Calls;
+use async_trait::async_trait;
+use scraper::Html;
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::sync::mpsc;
+
+/// The secret holding the access token of the bot's account, with the `read` and `write`
+/// scopes.
+pub const TOKEN_NAME: &str = "MASTODON_ACCESS_TOKEN";
+/// The visibilities of a status, from the most to the least visible.
+const VISIBILITIES: [&str; 4] = ["public", "unlisted", "private", "direct"];
+
+/// The account the character posts from.
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct MastodonSettings {
+    /// Such as `https://mastodon.social`.
+    pub instance: String,
+    /// Who sees the scheduled statuses: public, unlisted, private (followers only) or direct.
+    /// Replies are no more visible than this, nor than the status they answer.
+    #[serde(default = "default_visibility")]
+    pub visibility: String,
+    /// The content warning the scheduled statuses are posted behind.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub content_warning: Option<String>,
+    /// The most characters the instance allows in a status.
+    #[serde(default = "default_max_characters")]
+    pub max_characters: usize,
+    /// How often to check for new mentions, in seconds.
+    #[serde(default = "default_poll_seconds")]
+    pub poll_seconds: u64,
+}
+
+fn default_visibility() -> String {
+    "public".to_string()
+}
+
+fn default_max_characters() -> usize {
+    500
+}
+
+fn default_poll_seconds() -> u64 {
+    30
+}
+
+/// A client of the account's Mastodon API.
+pub struct Account {
+    client: reqwest::Client,
+    instance: String,
+    token: String,
+}
+
+impl Account {
+    pub fn new(settings: &MastodonSettings, token: &str) -> Result<Self, Box<dyn std::error::Error + Send + Sync>> {
+        if !VISIBILITIES.contains(&settings.visibility.as_str()) {
+            return Err(format!("Unknown mastodon.visibility \"{}\"; use {}", settings.visibility, VISIBILITIES.join(", ")).into());
+        }
+        Ok(Account {
+            client: reqwest::Client::builder().timeout(Duration::from_secs(30)).build()?,
+            instance: settings.instance.trim_end_matches('/').to_string(),
+            token: token.to_string(),
+        })
+    }
+
+    async fn get(&self, path: &str) -> Result<Value, Box<dyn std::error::Error + Send + Sync>> {
+        let response = self.client.get(format!("{}/api/v1/{}", self.instance, path)).bearer_auth(&self.token).send().await?;
+        Ok(response.error_for_status()?.json().await?)
+    }
+
+    /// Posts a status and gives it back.
+    async fn post_status(&self, params: Value) -> Result<Value, Box<dyn std::error::Error + Send + Sync>> {
+        let response = self.client.post(format!("{}/api/v1/statuses", self.instance)).bearer_auth(&self.token).json(&params).send().await?;
+        Ok(response.error_for_status()?.json().await?)
+    }
+
+    /// The text of the account's latest statuses, without replies and boosts.
+    pub async fn recent_statuses(&self, limit: usize) -> Result<Vec<String>, Box<dyn std::error::Error + Send + Sync>> {
+        let me = self.get("accounts/verify_credentials").await?;
+        let id = me["id"].as_str().unwrap_or_default();
+        let statuses = self.get(&format!("accounts/{}/statuses?limit={}&exclude_replies=true&exclude_reblogs=true", id, limit)).await?;
+        Ok(statuses.as_array().into_iter().flatten().map(|status| plain_text(status["content"].as_str().unwrap_or_default())).collect())
+    }
+
+    /// Posts a status with the visibility and content warning of the settings, and gives its
+    /// address.
+    pub async fn post(&self, settings: &MastodonSettings, text: &str) -> Result<String, Box<dyn std::error::Error + Send + Sync>> {
+        let mut params = json!({ "status": text, "visibility": settings.visibility });
+        if let Some(warning) = &settings.content_warning {
+            params["spoiler_text"] = json!(warning);
+        }
+        let status = self.post_status(params).await?;
+        Ok(status["url"].as_str().unwrap_or_default().to_string())
+    }
+}
+
+/// The account, answering the statuses that mention it, which it checks for every
+/// `poll_seconds`. Mentions made while the bot was away, and those by other bots, are not
+/// answered. Each account it talks with has a conversation of its own.
+pub struct Bot {
+    account: Account,
+    settings: MastodonSettings,
+}
+
+impl Bot {
+    pub fn new(settings: MastodonSettings, token: &str) -> Result<Self, Box<dyn std::error::Error + Send + Sync>> {
+        Ok(Bot { account: Account::new(&settings, token)?, settings })
+    }
+}
+
+#[async_trait]
+impl Connector for Bot {
+    /// The status to answer.
+    type ReplyTo = Value;
+
+    const NAME: &'static str = "mastodon";
+    const TITLE: &'static str = "Mastodon";
+    const PER_USER: bool = true;
+
+    /// Fails only if the token is refused at first.
+    async fn receive(self: Arc<Self>, incoming: mpsc::Sender<Incoming<Value>>, _calls: Calls) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+        let account = &self.account;
+        let me = account.get("accounts/verify_credentials").await?;
+        let username = me["username"].as_str().unwrap_or_default().to_string();
+        status!("Connected to {} as @{}", account.instance, username);
+        let latest = account.get("notifications?types[]=mention&limit=1").await?;
+        let mut since = latest[0]["id"].as_str().map(str::to_string);
+        loop {
+            tokio::time::sleep(Duration::from_secs(self.settings.poll_seconds)).await;
+            let path = match &since {
+                Some(id) => format!("notifications?types[]=mention&since_id={}", id),
+                None => "notifications?types[]=mention".to_string(),
+            };
+            let notifications = match account.get(&path).await {
+                Ok(notifications) => notifications,
+                Err(e) => {
+                    status!("Could not check the Mastodon mentions: {}", e);
+                    continue;
+                }
+            };
+            // They come newest first
+            for notification in notifications.as_array().into_iter().flatten().rev() {
+                since = notification["id"].as_str().map(str::to_string).or(since);
+                let status = &notification["status"];
+                if notification["account"]["bot"] == true || status.is_null() {
+                    continue;
+                }
+                let Some(author) = status["account"]["acct"].as_str() else {
+                    continue;
+                };
+                let text = without_mentions(&plain_text(status["content"].as_str().unwrap_or_default()), &username);
+                if text.is_empty() {
+                    continue;
+                }
+                let message = Incoming {
+                    user: author.to_string(),
+                    channel: author.to_string(),
+                    text,
+                    reply_to: status.clone(),
+                };
+                let _ = incoming.send(message).await;
+            }
+        }
+    }
+
+    async fn send(&self, message: &Incoming<Value>, reply: &str) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+        let status = &message.reply_to;
+        let id = status["id"].as_str().ok_or("The status has no ID")?;
+        // A reply is as private as the status it answers, or more, and keeps its content warning
+        let visibility = narrowest(status["visibility"].as_str().unwrap_or("direct"), &self.settings.visibility);
+        let mention = format!("@{} ", message.user);
+        let mut in_reply_to = id.to_string();
+        for part in split_message(reply, self.settings.max_characters.saturating_sub(mention.len()).max(1)) {
+            let params = json!({
+                "status": format!("{}{}", mention, part),
+                "in_reply_to_id": in_reply_to,
+                "visibility": visibility,
+                "spoiler_text": status["spoiler_text"],
+            });
+            // A long reply goes on as a thread
+            let posted = self.account.post_status(params).await?;
+            in_reply_to = posted["id"].as_str().unwrap_or(id).to_string();
+        }
+        Ok(())
+    }
+}
+
+/// The less visible of two visibilities.
+fn narrowest<'a>(a: &'a str, b: &'a str) -> &'a str {
+    let rank = |visibility: &str| VISIBILITIES.iter().position(|known| *known == visibility).unwrap_or(VISIBILITIES.len() - 1);
+    if rank(a) >= rank(b) {
+        a
+    } else {
+        b
+    }
+}
+
+/// The text of a status, whose content is HTML.
+fn plain_text(content: &str) -> String {
+    let content = content.replace("<br>", "\n").replace("<br />", "\n").replace("</p><p>", "\n\n");
+    Html::parse_fragment(&content).root_element().text().collect::<String>().trim().to_string()
+}
+
+/// A status's text without the mentions it starts with, and without those of the account
+/// anywhere.
+fn without_mentions(text: &str, username: &str) -> String {
+    let own = format!("@{}", username);
+    let is_own = |word: &str| word == own || word.starts_with(&format!("{}@", own));
+    let words: Vec<&str> = text.split(' ').skip_while(|word| word.starts_with('@')).filter(|word| !is_own(word)).collect();
+    words.join(" ").trim().to_string()
+}
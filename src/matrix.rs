@@ -0,0 +1,163 @@
+use crate::gateway::{split_message, Connector, Incoming};
+use crate::server::Calls;
+use async_trait::async_trait;
+use matrix_sdk::authentication::matrix::MatrixSession;
+use matrix_sdk::config::SyncSettings;
+use matrix_sdk::event_handler::Ctx;
+use matrix_sdk::ruma::events::room::member::StrippedRoomMemberEvent;
+use matrix_sdk::ruma::events::room::message::{
+    AddMentions, ForwardThread, MessageType, OriginalSyncRoomMessageEvent, RoomMessageEventContent,
+};
+use matrix_sdk::ruma::{OwnedDeviceId, OwnedUserId};
+use matrix_sdk::{Client, Room, RoomState, SessionMeta, SessionTokens};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::path::PathBuf;
+use std::sync::Arc;
+use tokio::sync::mpsc;
+
+/// The secret holding the bot account's access token.
+pub const TOKEN_NAME: &str = "MATRIX_ACCESS_TOKEN";
+/// The secret the encryption keys kept in the data directory are encrypted with, if set.
+pub const STORE_PASSPHRASE_NAME: &str = "MATRIX_STORE_PASSPHRASE";
+/// Matrix has no hard limit, but clients show very long messages poorly.
+const MESSAGE_LIMIT: usize = 4000;
+
+/// The homeserver the bot's account is on.
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct MatrixSettings {
+    /// Such as `https://matrix.org`.
+    pub homeserver: String,
+}
+
+/// The Matrix bot. It joins the rooms it is invited to, and answers every message in a room
+/// with one other member and the messages that mention it elsewhere, encrypted rooms
+/// included, with a conversation for each room.
+pub struct Bot {
+    settings: MatrixSettings,
+    token: String,
+    /// Where the encryption keys are kept, and what they are encrypted with there.
+    store: PathBuf,
+    passphrase: Option<String>,
+}
+
+impl Bot {
+    pub fn new(settings: MatrixSettings, token: String, store: PathBuf, passphrase: Option<String>) -> Self {
+        Bot { settings, token, store, passphrase }
+    }
+}
+
+/// A message to answer, and its room.
+pub struct ReplyTo {
+    room: Room,
+    event: OriginalSyncRoomMessageEvent,
+}
+
+/// What the bot's event handlers need.
+#[derive(Clone)]
+struct Handler {
+    incoming: mpsc::Sender<Incoming<ReplyTo>>,
+    me: OwnedUserId,
+}
+
+#[async_trait]
+impl Connector for Bot {
+    type ReplyTo = ReplyTo;
+
+    const NAME: &'static str = "matrix";
+    const TITLE: &'static str = "Matrix";
+    const PER_USER: bool = false;
+
+    /// Logs in with the access token of the bot's account and passes on the messages until
+    /// syncing fails.
+    async fn receive(self: Arc<Self>, incoming: mpsc::Sender<Incoming<ReplyTo>>, _calls: Calls) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+        let (user_id, device_id) = whoami(&self.settings.homeserver, &self.token).await?;
+        let client = Client::builder()
+            .homeserver_url(&self.settings.homeserver)
+            .sqlite_store(&self.store, self.passphrase.as_deref())
+            .build()
+            .await?;
+        let session = MatrixSession {
+            meta: SessionMeta { user_id: user_id.clone(), device_id },
+            tokens: SessionTokens { access_token: self.token.clone(), refresh_token: None },
+        };
+        client.restore_session(session).await?;
+        status!("Connected to Matrix as {}", user_id);
+
+        // Messages sent while the bot was away are not answered
+        let response = client.sync_once(SyncSettings::default()).await?;
+        client.add_event_handler_context(Handler { incoming, me: user_id });
+        client.add_event_handler(join_on_invite);
+        client.add_event_handler(pass_on);
+        client.sync(SyncSettings::default().token(response.next_batch)).await?;
+        Ok(())
+    }
+
+    async fn typing(&self, message: &Incoming<ReplyTo>) {
+        let _ = message.reply_to.room.typing_notice(true).await;
+    }
+
+    async fn send(&self, message: &Incoming<ReplyTo>, reply: &str) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+        let ReplyTo { room, event } = &message.reply_to;
+        let _ = room.typing_notice(false).await;
+        for part in split_message(reply, MESSAGE_LIMIT) {
+            // Replies to the message it answers, in its thread if it is in one
+            let content = RoomMessageEventContent::text_markdown(part).make_reply_to(event, ForwardThread::Yes, AddMentions::No);
+            room.send(content).await?;
+        }
+        Ok(())
+    }
+}
+
+/// The account and the device an access token belongs to. The device is needed to take part
+/// in encrypted rooms.
+async fn whoami(homeserver: &str, token: &str) -> Result<(OwnedUserId, OwnedDeviceId), Box<dyn std::error::Error + Send + Sync>> {
+    let response: Value = reqwest::Client::new()
+        .get(format!("{}/_matrix/client/v3/account/whoami", homeserver.trim_end_matches('/')))
+        .bearer_auth(token)
+        .send()
+        .await?
+        .error_for_status()?
+        .json()
+        .await?;
+    let user_id = response["user_id"].as_str().ok_or("The homeserver did not say whose the access token is")?;
+    let device_id = response["device_id"]
+        .as_str()
+        .ok_or("The access token belongs to no device; log the bot's account in to get one that does")?;
+    Ok((user_id.try_into()?, device_id.into()))
+}
+
+async fn join_on_invite(event: StrippedRoomMemberEvent, room: Room, Ctx(bot): Ctx<Handler>) {
+    if event.state_key != bot.me {
+        return;
+    }
+    status!("Joining the Matrix room {}", room.room_id());
+    if let Err(e) = room.join().await {
+        status!("Could not join the Matrix room {}: {}", room.room_id(), e);
+    }
+}
+
+async fn pass_on(event: OriginalSyncRoomMessageEvent, room: Room, Ctx(bot): Ctx<Handler>) {
+    if room.state() != RoomState::Joined || event.sender == bot.me {
+        return;
+    }
+    let MessageType::Text(text) = &event.content.msgtype else {
+        return;
+    };
+    let mentioned = event.content.mentions.as_ref().is_some_and(|mentions| mentions.user_ids.contains(&bot.me))
+        || text.body.contains(bot.me.as_str());
+    if room.joined_members_count() > 2 && !mentioned {
+        return;
+    }
+    let text = text.body.replace(bot.me.as_str(), "").trim().to_string();
+    if text.is_empty() {
+        return;
+    }
+    let message = Incoming {
+        user: event.sender.to_string(),
+        channel: room.room_id().to_string(),
+        text,
+        reply_to: ReplyTo { room, event },
+    };
+    let _ = bot.incoming.send(message).await;
+}
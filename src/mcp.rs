@@ -0,0 +1,194 @@
+use crate::server::{self, ApiCall, Calls, Limiter};
+use crate::stdio::{self, INTERNAL_ERROR, INVALID_PARAMS, INVALID_REQUEST, METHOD_NOT_FOUND};
+use percent_encoding::{percent_decode_str, utf8_percent_encode, NON_ALPHANUMERIC};
+use serde_json::{json, Value};
+
+/// The MCP versions spoken, newest first. A client asking for another one is offered the
+/// newest.
+const PROTOCOL_VERSIONS: [&str; 3] = ["2025-06-18", "2025-03-26", "2024-11-05"];
+const CHARACTER_URI: &str = "alya://character";
+const KNOWLEDGE_URI: &str = "alya://knowledge/";
+/// MCP's error code for reading a resource that does not exist.
+const RESOURCE_NOT_FOUND: i64 = -32002;
+/// How many facts `search_knowledge` returns unless asked for another number.
+const DEFAULT_SEARCH_LIMIT: usize = 5;
+
+/// A connection of an MCP client, which chats in `session`. Its calls go to the chatbot as
+/// those of the HTTP API do.
+pub struct Session {
+    pub calls: Calls,
+    pub session: String,
+    /// The rate limits the chats count against, with the character's name for the throttle
+    /// message.
+    pub limiter: Option<(Limiter, String)>,
+}
+
+impl Session {
+    /// The answer to a JSON-RPC message from the client; `None` for notifications.
+    pub async fn handle(&self, message: &Value) -> Option<Value> {
+        let id = message.get("id")?;
+        let Some(method) = message["method"].as_str() else {
+            return Some(stdio::error(id, INVALID_REQUEST, "The request has no method"));
+        };
+        let params = &message["params"];
+        let answer = match method {
+            "initialize" => self.initialize(params).await,
+            "ping" => Ok(json!({})),
+            "tools/list" => Ok(json!({ "tools": tools() })),
+            "tools/call" => self.call_tool(params).await,
+            "resources/list" => Ok(json!({
+                "resources": [{ "uri": CHARACTER_URI, "name": "character", "description": "The character's profile", "mimeType": "application/json" }],
+            })),
+            "resources/templates/list" => Ok(json!({
+                "resourceTemplates": [{
+                    "uriTemplate": format!("{}{{key}}", KNOWLEDGE_URI),
+                    "name": "fact",
+                    "description": "A learned fact, by its key",
+                    "mimeType": "application/json",
+                }],
+            })),
+            "resources/read" => self.read_resource(params).await,
+            _ => Err((METHOD_NOT_FOUND, format!("There is no method {}", method))),
+        };
+        Some(match answer {
+            Ok(result) => stdio::result(id, result),
+            Err((code, message)) => stdio::error(id, code, &message),
+        })
+    }
+
+    async fn call(&self, call: ApiCall) -> Result<Value, (i64, String)> {
+        server::call(&self.calls, call).await.map_err(|e| (INTERNAL_ERROR, e.to_string()))
+    }
+
+    async fn initialize(&self, params: &Value) -> Result<Value, (i64, String)> {
+        let asked = params["protocolVersion"].as_str().unwrap_or_default();
+        let version = PROTOCOL_VERSIONS.iter().find(|version| **version == asked).unwrap_or(&PROTOCOL_VERSIONS[0]);
+        let character = self.call(ApiCall::Character).await?;
+        let name = character["name"].as_str().unwrap_or_default();
+        Ok(json!({
+            "protocolVersion": version,
+            "capabilities": { "tools": {}, "resources": {} },
+            "serverInfo": { "name": "alya", "version": env!("CARGO_PKG_VERSION") },
+            "instructions": format!(
+                "Chat with {} through the chat tool, and look up what {} knows with search_knowledge.",
+                name, name
+            ),
+        }))
+    }
+
+    async fn call_tool(&self, params: &Value) -> Result<Value, (i64, String)> {
+        let arguments = &params["arguments"];
+        let text = match params["name"].as_str() {
+            Some("chat") => {
+                let Some(message) = arguments["message"].as_str().filter(|message| !message.trim().is_empty()) else {
+                    return Err((INVALID_PARAMS, "chat needs a message".to_string()));
+                };
+                self.chat(message).await
+            }
+            Some("search_knowledge") => {
+                let Some(query) = arguments["query"].as_str().filter(|query| !query.trim().is_empty()) else {
+                    return Err((INVALID_PARAMS, "search_knowledge needs a query".to_string()));
+                };
+                let limit = arguments["limit"].as_u64().map_or(DEFAULT_SEARCH_LIMIT, |limit| limit as usize);
+                self.search(query, limit).await
+            }
+            Some(name) => return Err((INVALID_PARAMS, format!("There is no tool {}", name))),
+            None => return Err((INVALID_PARAMS, "tools/call needs the name of a tool".to_string())),
+        };
+        // Failures of the tool itself are for the model to see, not protocol errors
+        Ok(match text {
+            Ok(text) => json!({ "content": [{ "type": "text", "text": text }], "isError": false }),
+            Err(error) => json!({ "content": [{ "type": "text", "text": error }], "isError": true }),
+        })
+    }
+
+    async fn chat(&self, message: &str) -> Result<String, String> {
+        if let Some((limiter, name)) = &self.limiter {
+            let mut limiter = limiter.lock().unwrap();
+            if let Err(wait) = limiter.admit(&self.session) {
+                return Err(limiter.throttled(name, wait).to_string());
+            }
+        }
+        let chat = ApiCall::Chat {
+            session: self.session.clone(),
+            message: message.to_string(),
+            stream: None,
+        };
+        let reply = server::call(&self.calls, chat).await.map_err(|e| e.to_string())?;
+        if let Some((limiter, _)) = &self.limiter {
+            limiter.lock().unwrap().record_tokens(reply["tokens"].as_u64().unwrap_or_default());
+        }
+        let mut text = reply["text"].as_str().unwrap_or_default().to_string();
+        let sources: Vec<&str> = reply["sources"].as_array().into_iter().flatten().filter_map(Value::as_str).collect();
+        if !sources.is_empty() {
+            text.push_str(&format!("\n\nSources: {}", sources.join(", ")));
+        }
+        Ok(text)
+    }
+
+    async fn search(&self, query: &str, limit: usize) -> Result<String, String> {
+        let retrieve = ApiCall::Retrieve {
+            session: self.session.clone(),
+            query: query.to_string(),
+            limit,
+        };
+        let facts = server::call(&self.calls, retrieve).await.map_err(|e| e.to_string())?;
+        let facts: Vec<String> = facts
+            .as_array()
+            .into_iter()
+            .flatten()
+            .map(|fact| {
+                let key = fact["key"].as_str().unwrap_or_default();
+                let mut line = format!("- {} ({}{})", fact["text"].as_str().unwrap_or_default(), KNOWLEDGE_URI, utf8_percent_encode(key, NON_ALPHANUMERIC));
+                if let Some(url) = fact["source_url"].as_str() {
+                    line.push_str(&format!(", from {}", url));
+                }
+                line
+            })
+            .collect();
+        Ok(if facts.is_empty() { "Nothing known about that.".to_string() } else { facts.join("\n") })
+    }
+
+    async fn read_resource(&self, params: &Value) -> Result<Value, (i64, String)> {
+        let Some(uri) = params["uri"].as_str() else {
+            return Err((INVALID_PARAMS, "resources/read needs a uri".to_string()));
+        };
+        let resource = if uri == CHARACTER_URI {
+            self.call(ApiCall::Character).await?
+        } else if let Some(key) = uri.strip_prefix(KNOWLEDGE_URI) {
+            let key = percent_decode_str(key).decode_utf8_lossy().to_string();
+            server::call(&self.calls, ApiCall::Fact(key)).await.map_err(|_| (RESOURCE_NOT_FOUND, format!("There is no resource {}", uri)))?
+        } else {
+            return Err((RESOURCE_NOT_FOUND, format!("There is no resource {}", uri)));
+        };
+        Ok(json!({
+            "contents": [{ "uri": uri, "mimeType": "application/json", "text": resource.to_string() }],
+        }))
+    }
+}
+
+fn tools() -> Value {
+    json!([
+        {
+            "name": "chat",
+            "description": "Send the character a message and get their reply, in their voice. The conversation carries on between calls.",
+            "inputSchema": {
+                "type": "object",
+                "properties": { "message": { "type": "string", "description": "What to say to the character" } },
+                "required": ["message"],
+            },
+        },
+        {
+            "name": "search_knowledge",
+            "description": "Look up the facts the character has learned that are most relevant to a query, with their sources.",
+            "inputSchema": {
+                "type": "object",
+                "properties": {
+                    "query": { "type": "string", "description": "What to look up" },
+                    "limit": { "type": "integer", "description": "How many facts to return at most", "minimum": 1 },
+                },
+                "required": ["query"],
+            },
+        },
+    ])
+}
@@ -0,0 +1,303 @@
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+use std::collections::HashMap;
+use std::process::Stdio;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::Duration;
+use tokio::io::{AsyncBufReadExt, AsyncWriteExt, BufReader};
+use tokio::process::{Child, ChildStdin, ChildStdout, Command};
+use tokio::sync::Mutex;
+
+const PROTOCOL_VERSION: &str = "2025-06-18";
+/// How long a server has to answer a request, tool calls included.
+const TIMEOUT: Duration = Duration::from_secs(60);
+
+/// An external MCP server whose tools the model may use during the chat: a command to run,
+/// which speaks MCP on its stdin and stdout, or the URL of a server speaking MCP over HTTP.
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct McpServerSettings {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub command: Option<String>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub args: Vec<String>,
+    /// Environment variables for the command.
+    #[serde(default, skip_serializing_if = "HashMap::is_empty")]
+    pub env: HashMap<String, String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub url: Option<String>,
+    /// Extra headers to send to the URL, such as an `Authorization` it expects.
+    #[serde(default, skip_serializing_if = "HashMap::is_empty")]
+    pub headers: HashMap<String, String>,
+    /// The tools the model may use; all of the server's if empty.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub tools: Vec<String>,
+}
+
+/// A tool of an MCP server.
+#[derive(Debug, Clone)]
+pub struct Tool {
+    pub name: String,
+    pub description: String,
+    /// The JSON Schema of the tool's arguments.
+    pub input_schema: Value,
+}
+
+enum Transport {
+    Stdio {
+        // Kept so the process is killed with the client
+        _child: Box<Child>,
+        stdin: Mutex<ChildStdin>,
+        stdout: Mutex<BufReader<ChildStdout>>,
+    },
+    Http {
+        client: reqwest::Client,
+        url: String,
+        headers: HashMap<String, String>,
+        /// The session the server gave at initialization, if any.
+        session: Mutex<Option<String>>,
+    },
+}
+
+/// A connection to an MCP server.
+pub struct McpClient {
+    pub name: String,
+    pub tools: Vec<Tool>,
+    transport: Transport,
+    next_id: AtomicU64,
+}
+
+impl McpClient {
+    /// Starts or connects to a server, and lists its tools.
+    pub async fn connect(name: &str, settings: &McpServerSettings) -> Result<Self, Box<dyn std::error::Error>> {
+        let transport = match (&settings.command, &settings.url) {
+            (Some(command), _) => {
+                let mut child = Command::new(command)
+                    .args(&settings.args)
+                    .envs(&settings.env)
+                    .stdin(Stdio::piped())
+                    .stdout(Stdio::piped())
+                    .stderr(Stdio::null())
+                    .kill_on_drop(true)
+                    .spawn()
+                    .map_err(|e| format!("Could not run {}: {}", command, e))?;
+                let stdin = child.stdin.take().ok_or("The server has no stdin")?;
+                let stdout = child.stdout.take().ok_or("The server has no stdout")?;
+                Transport::Stdio {
+                    _child: Box::new(child),
+                    stdin: Mutex::new(stdin),
+                    stdout: Mutex::new(BufReader::new(stdout)),
+                }
+            }
+            (None, Some(url)) => Transport::Http {
+                client: reqwest::Client::builder().timeout(TIMEOUT).build()?,
+                url: url.clone(),
+                headers: settings.headers.clone(),
+                session: Mutex::new(None),
+            },
+            (None, None) => return Err("An MCP server needs a command or a url".into()),
+        };
+        let mut client = McpClient {
+            name: name.to_string(),
+            tools: Vec::new(),
+            transport,
+            next_id: AtomicU64::new(1),
+        };
+
+        let params = json!({
+            "protocolVersion": PROTOCOL_VERSION,
+            "capabilities": {},
+            "clientInfo": { "name": "alya", "version": env!("CARGO_PKG_VERSION") },
+        });
+        client.request("initialize", params).await?;
+        client.send(&json!({ "jsonrpc": "2.0", "method": "notifications/initialized" })).await?;
+
+        let mut cursor: Option<String> = None;
+        loop {
+            let params = match &cursor {
+                Some(cursor) => json!({ "cursor": cursor }),
+                None => json!({}),
+            };
+            let listed = client.request("tools/list", params).await?;
+            for tool in listed["tools"].as_array().into_iter().flatten() {
+                let tool = Tool {
+                    name: tool["name"].as_str().unwrap_or_default().to_string(),
+                    description: tool["description"].as_str().unwrap_or_default().to_string(),
+                    input_schema: tool["inputSchema"].clone(),
+                };
+                if settings.tools.is_empty() || settings.tools.contains(&tool.name) {
+                    client.tools.push(tool);
+                }
+            }
+            cursor = listed["nextCursor"].as_str().map(str::to_string);
+            if cursor.is_none() {
+                break;
+            }
+        }
+        Ok(client)
+    }
+
+    /// Calls one of the server's tools and returns the text it answered with. A tool that
+    /// reports a failure gives its message as the text, for the model to see.
+    pub async fn call_tool(&self, name: &str, arguments: Value) -> Result<String, Box<dyn std::error::Error>> {
+        let result = self.request("tools/call", json!({ "name": name, "arguments": arguments })).await?;
+        let text: Vec<&str> = result["content"]
+            .as_array()
+            .into_iter()
+            .flatten()
+            .filter(|content| content["type"] == "text")
+            .filter_map(|content| content["text"].as_str())
+            .collect();
+        let text = text.join("\n");
+        Ok(if result["isError"] == true { format!("Error: {}", text) } else { text })
+    }
+
+    async fn request(&self, method: &str, params: Value) -> Result<Value, Box<dyn std::error::Error>> {
+        let id = self.next_id.fetch_add(1, Ordering::Relaxed);
+        let request = json!({ "jsonrpc": "2.0", "id": id, "method": method, "params": params });
+        let answer = tokio::time::timeout(TIMEOUT, self.exchange(&request, id))
+            .await
+            .map_err(|_| format!("{} did not answer {} in time", self.name, method))??;
+        if let Some(error) = answer.get("error") {
+            return Err(format!("{} failed {}: {}", self.name, method, error["message"].as_str().unwrap_or_default()).into());
+        }
+        Ok(answer["result"].clone())
+    }
+
+    /// Sends a request and waits for the answer with its `id`.
+    async fn exchange(&self, request: &Value, id: u64) -> Result<Value, Box<dyn std::error::Error>> {
+        match &self.transport {
+            Transport::Stdio { stdin, stdout, .. } => {
+                // Holding stdout keeps concurrent requests from reading each other's answers
+                let mut stdout = stdout.lock().await;
+                self.send(request).await?;
+                loop {
+                    let mut line = String::new();
+                    if stdout.read_line(&mut line).await? == 0 {
+                        return Err(format!("{} exited", self.name).into());
+                    }
+                    let Ok(message) = serde_json::from_str::<Value>(&line) else {
+                        continue;
+                    };
+                    if message["id"] == id && message.get("method").is_none() {
+                        return Ok(message);
+                    }
+                    // Requests from the server, such as pings, get an empty answer
+                    if let (Some(id), Some(_)) = (message.get("id"), message.get("method")) {
+                        let answer = json!({ "jsonrpc": "2.0", "id": id, "result": {} });
+                        stdin.lock().await.write_all(format!("{}\n", answer).as_bytes()).await?;
+                    }
+                }
+            }
+            Transport::Http { client, url, headers, session } => {
+                let response = self.post(client, url, headers, session, request).await?;
+                let is_stream = response
+                    .headers()
+                    .get(reqwest::header::CONTENT_TYPE)
+                    .and_then(|value| value.to_str().ok())
+                    .is_some_and(|value| value.starts_with("text/event-stream"));
+                if !is_stream {
+                    return Ok(response.json().await?);
+                }
+                // The answer is one of the events
+                let mut response = response;
+                let mut buffer = Vec::new();
+                while let Some(bytes) = response.chunk().await? {
+                    buffer.extend_from_slice(&bytes);
+                    while let Some(end) = buffer.iter().position(|&byte| byte == b'\n') {
+                        let line: Vec<u8> = buffer.drain(..=end).collect();
+                        let Some(data) = String::from_utf8_lossy(&line).trim().strip_prefix("data:").map(str::to_string) else {
+                            continue;
+                        };
+                        let Ok(message) = serde_json::from_str::<Value>(&data) else {
+                            continue;
+                        };
+                        if message["id"] == id && message.get("method").is_none() {
+                            return Ok(message);
+                        }
+                    }
+                }
+                Err(format!("{} closed the stream without answering", self.name).into())
+            }
+        }
+    }
+
+    /// Sends a message that needs no answer.
+    async fn send(&self, message: &Value) -> Result<(), Box<dyn std::error::Error>> {
+        match &self.transport {
+            Transport::Stdio { stdin, .. } => {
+                let mut stdin = stdin.lock().await;
+                stdin.write_all(format!("{}\n", message).as_bytes()).await?;
+                stdin.flush().await?;
+            }
+            Transport::Http { client, url, headers, session } if message.get("id").is_none() => {
+                self.post(client, url, headers, session, message).await?;
+            }
+            // Requests over HTTP are sent by `exchange`, which reads the answer
+            Transport::Http { .. } => {}
+        }
+        Ok(())
+    }
+
+    async fn post(
+        &self,
+        client: &reqwest::Client,
+        url: &str,
+        headers: &HashMap<String, String>,
+        session: &Mutex<Option<String>>,
+        message: &Value,
+    ) -> Result<reqwest::Response, Box<dyn std::error::Error>> {
+        let mut request = client
+            .post(url)
+            .header(reqwest::header::ACCEPT, "application/json, text/event-stream")
+            .header("mcp-protocol-version", PROTOCOL_VERSION)
+            .json(message);
+        for (name, value) in headers {
+            request = request.header(name, value);
+        }
+        if let Some(session) = session.lock().await.as_deref() {
+            request = request.header("mcp-session-id", session);
+        }
+        let response = request.send().await?.error_for_status()?;
+        if let Some(id) = response.headers().get("mcp-session-id").and_then(|value| value.to_str().ok()) {
+            *session.lock().await = Some(id.to_string());
+        }
+        Ok(response)
+    }
+}
+
+/// A JSON Schema cut down to what Gemini's function declarations accept.
+pub fn gemini_schema(schema: &Value) -> Value {
+    let Some(schema) = schema.as_object() else {
+        return json!({ "type": "object" });
+    };
+    let mut cut = serde_json::Map::new();
+    for (key, value) in schema {
+        let value = match key.as_str() {
+            "type" | "description" | "enum" | "required" | "nullable" => value.clone(),
+            // The only formats it knows
+            "format" if value == "date-time" || value == "enum" => value.clone(),
+            "items" => gemini_schema(value),
+            "properties" => Value::Object(
+                value
+                    .as_object()
+                    .into_iter()
+                    .flatten()
+                    .map(|(name, property)| (name.clone(), gemini_schema(property)))
+                    .collect(),
+            ),
+            _ => continue,
+        };
+        cut.insert(key.clone(), value);
+    }
+    // A type given as a list, such as ["string", "null"], becomes the first non-null one
+    if let Some(types) = cut.get("type").and_then(Value::as_array) {
+        let first = types.iter().find(|kind| *kind != "null").cloned().unwrap_or(json!("string"));
+        cut.insert("type".to_string(), first);
+    }
+    // Objects without properties are not accepted
+    if cut.get("type") == Some(&json!("object")) && cut.get("properties").and_then(Value::as_object).is_none_or(|properties| properties.is_empty()) {
+        cut.remove("properties");
+        cut.remove("required");
+    }
+    Value::Object(cut)
+}
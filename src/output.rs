@@ -0,0 +1,25 @@
+use std::sync::atomic::{AtomicBool, Ordering};
+
+static QUIET: AtomicBool = AtomicBool::new(false);
+
+/// Keeps stdout for replies only: prompts and banners are left out, and progress messages
+/// go to stderr.
+pub fn set_quiet(quiet: bool) {
+    QUIET.store(quiet, Ordering::Relaxed);
+}
+
+pub fn is_quiet() -> bool {
+    QUIET.load(Ordering::Relaxed)
+}
+
+/// Prints a progress message like `println!`, above the line being typed if there is one,
+/// or to stderr when quiet.
+macro_rules! status {
+    ($($arg:tt)*) => {
+        if $crate::output::is_quiet() {
+            eprintln!($($arg)*);
+        } else {
+            $crate::input::println(format!($($arg)*));
+        }
+    };
+}
@@ -0,0 +1,152 @@
+use crate::cli::Cli;
+use crate::config_format;
+use std::path::{Path, PathBuf};
+use std::sync::OnceLock;
+
+/// The configuration file in the config directory, and in an installed character's or a
+/// profile's directory; it may be TOML or YAML too, as `chatbot_config.toml` or
+/// `chatbot_config.yaml`.
+pub const CHARACTER_CONFIG_FILE: &str = "chatbot_config.json";
+/// The config and data directories of a checkout, which are used when they are in the
+/// working directory, as they were before the platform's directories were.
+const LOCAL_CONFIG_DIR: &str = "config";
+const LOCAL_DATA_DIR: &str = "data";
+/// The directory alya's own is called in the platform's config and data directories.
+const APP_DIR: &str = "alya";
+/// A file of this name next to the executable turns portable mode on, as `--portable` does.
+const PORTABLE_MARKER: &str = "portable";
+/// Where `character install` puts the characters, each in a directory of its own, in the
+/// config directory.
+const CHARACTERS_DIR: &str = "characters";
+/// Where `profile create` puts the profiles' configurations, each in a directory of its
+/// own, in the config directory.
+const PROFILES_DIR: &str = "profiles";
+
+static CONFIG_DIR: OnceLock<PathBuf> = OnceLock::new();
+static DEFAULT_DATA_DIR: OnceLock<PathBuf> = OnceLock::new();
+static CONFIG_FILE: OnceLock<PathBuf> = OnceLock::new();
+static DATA_DIR: OnceLock<PathBuf> = OnceLock::new();
+
+/// Sets where the config and the data live for the rest of the run, from `--config-dir`,
+/// `--config` and `--data-dir`, or by default those of the installed character given with
+/// `--character` or of the profile given with `--profile`. In portable mode the config and
+/// data directories are next to the executable. Must be called before any of them is used;
+/// later calls have no effect.
+pub fn init(cli: &Cli) {
+    if let Some(dir) = portable_dir(cli.portable) {
+        let _ = CONFIG_DIR.set(cli.config_dir.clone().unwrap_or_else(|| dir.join(LOCAL_CONFIG_DIR)));
+        let _ = DEFAULT_DATA_DIR.set(dir.join(LOCAL_DATA_DIR));
+    } else if let Some(dir) = &cli.config_dir {
+        let _ = CONFIG_DIR.set(dir.clone());
+    }
+    let (default_config, default_data) = match (cli.character.as_deref(), cli.profile.as_deref()) {
+        (Some(name), _) => (character_config(name), character_data_dir(name)),
+        (None, Some(name)) => (profile_config(name), profile_data_dir(name)),
+        (None, None) => (main_config(), default_data_dir().to_path_buf()),
+    };
+    let _ = CONFIG_FILE.set(cli.config.clone().unwrap_or(default_config));
+    let _ = DATA_DIR.set(cli.data_dir.clone().unwrap_or(default_data));
+}
+
+/// The directory of the executable when it runs portably: with `--portable`, or when the
+/// `PORTABLE_MARKER` file is next to it.
+fn portable_dir(portable: bool) -> Option<PathBuf> {
+    let exe = std::env::current_exe().ok()?;
+    let dir = exe.parent()?;
+    (portable || dir.join(PORTABLE_MARKER).exists()).then(|| dir.to_path_buf())
+}
+
+/// Whether the working directory has the `config` or `data` directory of a checkout.
+fn is_local() -> bool {
+    config_format::find(&Path::new(LOCAL_CONFIG_DIR).join(CHARACTER_CONFIG_FILE)).exists() || Path::new(LOCAL_DATA_DIR).is_dir()
+}
+
+/// The directory the configuration, the installed characters and the profiles are kept in:
+/// the one given with `--config-dir`, `config` in the working directory when it is there,
+/// or else `alya` in the platform's config directory, such as `~/.config/alya`.
+pub fn config_dir() -> &'static Path {
+    CONFIG_DIR.get_or_init(|| match dirs::config_dir().filter(|_| !is_local()) {
+        Some(dir) => dir.join(APP_DIR),
+        None => PathBuf::from(LOCAL_CONFIG_DIR),
+    })
+}
+
+/// The data directory without `--data-dir`: `data` in the working directory when it is
+/// there, or else `alya` in the platform's data directory, such as `~/.local/share/alya`.
+fn default_data_dir() -> &'static Path {
+    DEFAULT_DATA_DIR.get_or_init(|| match dirs::data_dir().filter(|_| !is_local()) {
+        Some(dir) => dir.join(APP_DIR),
+        None => PathBuf::from(LOCAL_DATA_DIR),
+    })
+}
+
+/// The configuration file in the config directory, in whichever format it is.
+fn main_config() -> PathBuf {
+    config_format::find(&config_dir().join(CHARACTER_CONFIG_FILE))
+}
+
+/// Where a relative path in the configuration, such as `lore_dir`, is: in the config
+/// directory, or in the working directory when that has the checkout's config.
+pub fn resolve(path: &str) -> PathBuf {
+    match config_dir() == Path::new(LOCAL_CONFIG_DIR) {
+        true => PathBuf::from(path),
+        false => config_dir().join(path),
+    }
+}
+
+/// The character configuration file; JSON, TOML or YAML going by its extension.
+pub fn config_file() -> &'static Path {
+    CONFIG_FILE.get_or_init(main_config)
+}
+
+/// The directory the knowledge, sessions, indexes and other state are kept in.
+pub fn data_dir() -> &'static Path {
+    DATA_DIR.get_or_init(|| default_data_dir().to_path_buf())
+}
+
+/// A file or directory in the data directory.
+pub fn data_path(name: &str) -> PathBuf {
+    data_dir().join(name)
+}
+
+/// The directory the installed characters are kept in.
+pub fn characters_dir() -> PathBuf {
+    config_dir().join(CHARACTERS_DIR)
+}
+
+/// The directory of the installed character `name`, with its configuration and lorebook.
+pub fn character_dir(name: &str) -> PathBuf {
+    characters_dir().join(name)
+}
+
+/// The configuration file of the installed character `name`, in whichever format it is.
+pub fn character_config(name: &str) -> PathBuf {
+    config_format::find(&character_dir(name).join(CHARACTER_CONFIG_FILE))
+}
+
+/// The data directory of the installed character `name`, so it learns and remembers apart
+/// from the others.
+pub fn character_data_dir(name: &str) -> PathBuf {
+    default_data_dir().join("characters").join(name)
+}
+
+/// The directory the profiles are kept in.
+pub fn profiles_dir() -> PathBuf {
+    config_dir().join(PROFILES_DIR)
+}
+
+/// The directory of the profile `name`, with its configuration.
+pub fn profile_dir(name: &str) -> PathBuf {
+    profiles_dir().join(name)
+}
+
+/// The configuration file of the profile `name`, in whichever format it is.
+pub fn profile_config(name: &str) -> PathBuf {
+    config_format::find(&profile_dir(name).join(CHARACTER_CONFIG_FILE))
+}
+
+/// The data directory of the profile `name`, so what is learned and remembered with it
+/// stays out of the main knowledge.
+pub fn profile_data_dir(name: &str) -> PathBuf {
+    default_data_dir().join("profiles").join(name)
+}
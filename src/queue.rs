@@ -0,0 +1,155 @@
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::collections::HashMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+const QUEUE_FILE: &str = "learning_queue.json";
+
+/// How many times an item is started before it is given up on for the run.
+pub const MAX_ATTEMPTS: u32 = 3;
+
+/// One step of a learning run still to be done, such as a source to learn from.
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct QueueItem {
+    pub id: String,
+    /// How many times the item was started, counting starts cut short by a crash.
+    #[serde(default)]
+    pub attempts: u32,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub last_error: Option<String>,
+}
+
+#[derive(Debug, Serialize, Deserialize, Default)]
+struct QueueState {
+    pending: Vec<QueueItem>,
+    /// What the model wrote from the chunks of the sources in progress, by the chunk's hash.
+    #[serde(default, skip_serializing_if = "HashMap::is_empty")]
+    chunks: HashMap<String, String>,
+}
+
+/// The steps of a learning run that are not done yet, persisted after every change so
+/// that a run cut short resumes where it stopped instead of starting over. The chunks of a
+/// long source already processed are kept too, so the sources in progress are not sent to
+/// the model again from the start.
+pub struct LearningQueue {
+    path: PathBuf,
+    state: QueueState,
+}
+
+impl LearningQueue {
+    pub fn load(dir: &Path) -> Result<Self, Box<dyn std::error::Error>> {
+        let path = dir.join(QUEUE_FILE);
+        let state = match fs::read_to_string(&path) {
+            Ok(text) => serde_json::from_str(&text)?,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => QueueState::default(),
+            Err(e) => return Err(e.into()),
+        };
+        Ok(LearningQueue { path, state })
+    }
+
+    /// Queues the steps of a new run, unless a run was cut short, in which case its
+    /// remaining steps that are still among `ids` are kept instead. Returns how many steps
+    /// were resumed, 0 for a new run.
+    pub fn start(&mut self, ids: Vec<String>) -> Result<usize, Box<dyn std::error::Error>> {
+        self.state.pending.retain(|item| ids.contains(&item.id));
+        if !self.state.pending.is_empty() {
+            return Ok(self.state.pending.len());
+        }
+        self.state.pending = ids
+            .into_iter()
+            .map(|id| QueueItem {
+                id,
+                attempts: 0,
+                last_error: None,
+            })
+            .collect();
+        self.save()?;
+        Ok(0)
+    }
+
+    /// The next step to do, with its attempt count raised and saved before it starts.
+    pub fn next(&mut self) -> Result<Option<QueueItem>, Box<dyn std::error::Error>> {
+        let Some(item) = self.state.pending.first_mut() else {
+            return Ok(None);
+        };
+        item.attempts += 1;
+        let item = item.clone();
+        self.save()?;
+        Ok(Some(item))
+    }
+
+    /// Removes a finished step, along with the chunks processed for it.
+    pub fn complete(&mut self, id: &str) -> Result<(), Box<dyn std::error::Error>> {
+        self.state.pending.retain(|item| item.id != id);
+        self.state.chunks.clear();
+        self.save()
+    }
+
+    /// Puts a failed step at the back of the queue to be tried again, or drops it once it
+    /// was tried `MAX_ATTEMPTS` times. Returns whether it will be tried again.
+    pub fn fail(&mut self, id: &str, error: &str) -> Result<bool, Box<dyn std::error::Error>> {
+        let Some(index) = self.state.pending.iter().position(|item| item.id == id) else {
+            return Ok(false);
+        };
+        let mut item = self.state.pending.remove(index);
+        let retry = item.attempts < MAX_ATTEMPTS;
+        if retry {
+            item.last_error = Some(error.to_string());
+            self.state.pending.push(item);
+        }
+        self.save()?;
+        Ok(retry)
+    }
+
+    /// What the model wrote from `chunk` before the run was cut short.
+    pub fn processed(&self, chunk: &str) -> Option<String> {
+        self.state.chunks.get(&chunk_key(chunk)).cloned()
+    }
+
+    /// Keeps what the model wrote from `chunk` until the step it belongs to is done.
+    pub fn remember(&mut self, chunk: &str, processed: &str) -> Result<(), Box<dyn std::error::Error>> {
+        self.state.chunks.insert(chunk_key(chunk), processed.to_string());
+        self.save()
+    }
+
+    /// Drops the processed chunks, once what was written from them is stored.
+    pub fn forget_chunks(&mut self) -> Result<(), Box<dyn std::error::Error>> {
+        if self.state.chunks.is_empty() {
+            return Ok(());
+        }
+        self.state.chunks.clear();
+        self.save()
+    }
+
+    /// Drops what was written from `chunks` only, for a source finished while others that
+    /// are learned at the same time still need theirs.
+    pub fn forget_chunks_of(&mut self, chunks: &[String]) -> Result<(), Box<dyn std::error::Error>> {
+        let before = self.state.chunks.len();
+        for chunk in chunks {
+            self.state.chunks.remove(&chunk_key(chunk));
+        }
+        if self.state.chunks.len() == before {
+            return Ok(());
+        }
+        self.save()
+    }
+
+    fn save(&self) -> Result<(), Box<dyn std::error::Error>> {
+        if self.state.pending.is_empty() && self.state.chunks.is_empty() {
+            return match fs::remove_file(&self.path) {
+                Err(e) if e.kind() != std::io::ErrorKind::NotFound => Err(e.into()),
+                _ => Ok(()),
+            };
+        }
+        if let Some(dir) = self.path.parent() {
+            fs::create_dir_all(dir)?;
+        }
+        fs::write(&self.path, serde_json::to_string_pretty(&self.state)?)?;
+        Ok(())
+    }
+}
+
+fn chunk_key(chunk: &str) -> String {
+    format!("{:x}", Sha256::digest(chunk.as_bytes()))
+}
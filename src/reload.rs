@@ -0,0 +1,46 @@
+use notify::{EventKind, RecommendedWatcher, RecursiveMode, Watcher};
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+use tokio::sync::mpsc;
+
+/// How long to wait for an editor to finish saving before reading the file; saves often
+/// come as several changes.
+const SETTLE: Duration = Duration::from_millis(300);
+
+/// Watches a configuration file for changes while the chat runs.
+pub struct ConfigWatcher {
+    // Watching stops when it is dropped
+    _watcher: RecommendedWatcher,
+    changes: mpsc::UnboundedReceiver<()>,
+}
+
+impl ConfigWatcher {
+    /// Watches the directory the file is in, since editors often save by replacing the file
+    /// rather than writing to it.
+    pub fn new(file: &Path) -> Result<Self, Box<dyn std::error::Error>> {
+        let dir = file.parent().filter(|dir| !dir.as_os_str().is_empty()).unwrap_or(Path::new("."));
+        let name = file.file_name().ok_or("The configuration file has no name")?.to_os_string();
+        let (sender, changes) = mpsc::unbounded_channel();
+        let mut watcher = notify::recommended_watcher(move |event: notify::Result<notify::Event>| {
+            let Ok(event) = event else {
+                return;
+            };
+            let written = matches!(event.kind, EventKind::Create(_) | EventKind::Modify(_));
+            if written && event.paths.iter().any(|path: &PathBuf| path.file_name() == Some(name.as_os_str())) {
+                let _ = sender.send(());
+            }
+        })?;
+        watcher.watch(dir, RecursiveMode::NonRecursive)?;
+        Ok(ConfigWatcher { _watcher: watcher, changes })
+    }
+
+    /// Waits for the file to change, then for the changes to settle.
+    pub async fn changed(&mut self) {
+        if self.changes.recv().await.is_none() {
+            // The watcher stopped; the file is not watched anymore
+            std::future::pending::<()>().await;
+        }
+        tokio::time::sleep(SETTLE).await;
+        while self.changes.try_recv().is_ok() {}
+    }
+}
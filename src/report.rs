@@ -0,0 +1,60 @@
+use serde::Serialize;
+use std::time::Instant;
+
+/// What a learning run did, summed up when it ends.
+#[derive(Debug, Serialize)]
+pub struct LearnReport {
+    #[serde(skip)]
+    started: Instant,
+    pub pages_fetched: usize,
+    /// Pages left out by the domain filter or robots.txt.
+    pub pages_skipped: usize,
+    pub facts_added: usize,
+    /// New facts that repeated one already known.
+    pub duplicates: usize,
+    pub model_requests: usize,
+    /// Tokens of the prompts and replies, as the model counts them.
+    pub tokens: u64,
+    /// Sources that could not be learned from, with why.
+    pub failures: Vec<(String, String)>,
+}
+
+impl LearnReport {
+    pub fn new() -> Self {
+        LearnReport {
+            started: Instant::now(),
+            pages_fetched: 0,
+            pages_skipped: 0,
+            facts_added: 0,
+            duplicates: 0,
+            model_requests: 0,
+            tokens: 0,
+            failures: Vec::new(),
+        }
+    }
+
+    pub fn fail(&mut self, source: &str, reason: impl ToString) {
+        self.failures.push((source.to_string(), reason.to_string()));
+    }
+
+    pub fn print(&self) {
+        status!("\nLearning report ({}s):", self.started.elapsed().as_secs());
+        status!("- Pages fetched: {} ({} skipped by the domain filter or robots.txt)", self.pages_fetched, self.pages_skipped);
+        status!("- Facts added: {} ({} duplicates skipped)", self.facts_added, self.duplicates);
+        status!("- Model requests: {} ({} tokens)", self.model_requests, self.tokens);
+        if self.failures.is_empty() {
+            status!("- Failures: none");
+        } else {
+            status!("- Failures: {}", self.failures.len());
+            for (source, reason) in &self.failures {
+                status!("  - {}: {}", source, reason);
+            }
+        }
+    }
+}
+
+impl Default for LearnReport {
+    fn default() -> Self {
+        Self::new()
+    }
+}
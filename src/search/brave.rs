@@ -0,0 +1,75 @@
+use super::{SearchProvider, SearchResult};
+use crate::secrets;
+use async_trait::async_trait;
+use scraper::Html;
+use serde_json::Value;
+
+const ENDPOINT: &str = "https://api.search.brave.com/res/v1/web/search";
+/// Brave returns at most this many web results per request.
+const MAX_RESULTS: usize = 20;
+
+pub struct BraveSearch {
+    /// Language results are asked for in, such as `en`.
+    language: Option<String>,
+    client: reqwest::Client,
+}
+
+impl BraveSearch {
+    pub fn new(language: Option<String>) -> Self {
+        BraveSearch {
+            language,
+            client: reqwest::Client::builder()
+                .timeout(std::time::Duration::from_secs(10))
+                .build()
+                .unwrap_or_default(),
+        }
+    }
+}
+
+#[async_trait]
+impl SearchProvider for BraveSearch {
+    fn name(&self) -> &str {
+        "Brave Search"
+    }
+
+    async fn search(&self, query: &str, count: usize) -> Result<Vec<SearchResult>, Box<dyn std::error::Error>> {
+        let api_key = secrets::require("BRAVE_SEARCH_API_KEY")?;
+        let count = count.clamp(1, MAX_RESULTS).to_string();
+        let mut params = vec![("q", query), ("count", count.as_str()), ("result_filter", "web")];
+        if let Some(language) = &self.language {
+            params.push(("search_lang", language));
+        }
+        let response = self
+            .client
+            .get(ENDPOINT)
+            .header("Accept", "application/json")
+            .header("X-Subscription-Token", api_key)
+            .query(&params)
+            .send()
+            .await?;
+        if !response.status().is_success() {
+            return Err(format!("Brave Search API request failed: {}", response.status()).into());
+        }
+
+        let body: Value = response.json().await?;
+        let results = body["web"]["results"]
+            .as_array()
+            .into_iter()
+            .flatten()
+            .filter_map(|item| {
+                Some(SearchResult {
+                    title: plain_text(item["title"].as_str().unwrap_or_default()),
+                    url: item["url"].as_str()?.to_string(),
+                    snippet: plain_text(item["description"].as_str().unwrap_or_default()),
+                    language: item["language"].as_str().map(str::to_string),
+                })
+            })
+            .collect();
+        Ok(results)
+    }
+}
+
+/// Brave highlights the query words in titles and descriptions with `<strong>` tags.
+fn plain_text(html: &str) -> String {
+    Html::parse_fragment(html).root_element().text().collect()
+}
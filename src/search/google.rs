@@ -0,0 +1,64 @@
+use super::{SearchProvider, SearchResult};
+use crate::secrets;
+use async_trait::async_trait;
+use serde_json::Value;
+
+const ENDPOINT: &str = "https://www.googleapis.com/customsearch/v1";
+/// Google Custom Search returns at most this many results per request.
+const MAX_RESULTS: usize = 10;
+
+pub struct GoogleSearch {
+    /// Language results are restricted to, such as `en`.
+    language: Option<String>,
+    client: reqwest::Client,
+}
+
+impl GoogleSearch {
+    pub fn new(language: Option<String>) -> Self {
+        GoogleSearch {
+            language,
+            client: reqwest::Client::builder()
+                .timeout(std::time::Duration::from_secs(10))
+                .build()
+                .unwrap_or_default(),
+        }
+    }
+}
+
+#[async_trait]
+impl SearchProvider for GoogleSearch {
+    fn name(&self) -> &str {
+        "Google Custom Search"
+    }
+
+    async fn search(&self, query: &str, count: usize) -> Result<Vec<SearchResult>, Box<dyn std::error::Error>> {
+        let api_key = secrets::require("GOOGLE_SEARCH_API_KEY")?;
+        let engine_id = secrets::require("GOOGLE_SEARCH_ENGINE_ID")?;
+        let num = count.clamp(1, MAX_RESULTS).to_string();
+        let mut params = vec![("key", api_key), ("cx", engine_id), ("q", query.to_string()), ("num", num)];
+        if let Some(language) = &self.language {
+            params.push(("lr", format!("lang_{}", language)));
+        }
+        let response = self.client.get(ENDPOINT).query(&params).send().await?;
+        if !response.status().is_success() {
+            return Err(format!("Google Search API request failed: {}", response.status()).into());
+        }
+
+        let body: Value = response.json().await?;
+        let results = body["items"]
+            .as_array()
+            .into_iter()
+            .flatten()
+            .filter_map(|item| {
+                Some(SearchResult {
+                    title: item["title"].as_str().unwrap_or_default().to_string(),
+                    url: item["link"].as_str()?.to_string(),
+                    snippet: item["snippet"].as_str().unwrap_or_default().to_string(),
+                    language: None,
+                })
+            })
+            .take(count)
+            .collect();
+        Ok(results)
+    }
+}
@@ -0,0 +1,109 @@
+use async_trait::async_trait;
+use serde::{Deserialize, Serialize};
+
+mod brave;
+mod duckduckgo;
+mod google;
+mod rules;
+mod searxng;
+
+pub use brave::BraveSearch;
+pub use duckduckgo::DuckDuckGoSearch;
+pub use google::GoogleSearch;
+pub use rules::SearchRules;
+pub use searxng::SearxngSearch;
+
+/// One hit of a web search.
+#[derive(Debug, Clone)]
+pub struct SearchResult {
+    pub title: String,
+    pub url: String,
+    /// The excerpt the search engine shows under the title.
+    pub snippet: String,
+    /// The language of the page, when the search engine tells.
+    pub language: Option<String>,
+}
+
+/// A web search engine the chatbot finds pages to learn from with.
+#[async_trait]
+pub trait SearchProvider: Send + Sync {
+    /// Shown in progress messages.
+    fn name(&self) -> &str;
+
+    /// Returns up to `count` results for `query`, best first. Credentials are only read
+    /// here, so a provider that is never searched with needs none.
+    async fn search(&self, query: &str, count: usize) -> Result<Vec<SearchResult>, Box<dyn std::error::Error>>;
+}
+
+/// Which search engine to use.
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Eq, Default)]
+#[serde(rename_all = "lowercase")]
+pub enum SearchProviderKind {
+    /// Google Custom Search. Needs `GOOGLE_SEARCH_API_KEY` and `GOOGLE_SEARCH_ENGINE_ID`.
+    #[default]
+    Google,
+    /// DuckDuckGo's HTML results page; no API key needed.
+    DuckDuckGo,
+    /// The Brave Search API. Needs `BRAVE_SEARCH_API_KEY`.
+    Brave,
+    /// A SearxNG instance, such as a self-hosted one, at `searxng_url`; no API key needed.
+    Searxng,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct SearchSettings {
+    #[serde(default)]
+    pub provider: SearchProviderKind,
+    /// How many results of a search are learned from.
+    #[serde(default = "default_results")]
+    pub results: usize,
+    /// How many of those pages are fetched and processed at the same time.
+    #[serde(default = "default_concurrency")]
+    pub concurrency: usize,
+    /// How many more queries, on topics such as relationships, story arcs, quotes and news,
+    /// the model writes for each `learn` besides the search for the character itself.
+    #[serde(default = "default_queries")]
+    pub queries: usize,
+    /// Address of the SearxNG instance used when `provider` is "searxng".
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub searxng_url: Option<String>,
+    /// Which results are learned from, applied before any page is fetched.
+    #[serde(default)]
+    pub rules: SearchRules,
+}
+
+fn default_results() -> usize {
+    3
+}
+
+fn default_concurrency() -> usize {
+    3
+}
+
+fn default_queries() -> usize {
+    4
+}
+
+impl Default for SearchSettings {
+    fn default() -> Self {
+        SearchSettings {
+            provider: SearchProviderKind::default(),
+            results: default_results(),
+            concurrency: default_concurrency(),
+            queries: default_queries(),
+            searxng_url: None,
+            rules: SearchRules::default(),
+        }
+    }
+}
+
+/// Creates the configured search provider.
+pub fn create_search_provider(settings: &SearchSettings) -> Box<dyn SearchProvider> {
+    let language = settings.rules.language();
+    match settings.provider {
+        SearchProviderKind::Google => Box::new(GoogleSearch::new(language)),
+        SearchProviderKind::DuckDuckGo => Box::new(DuckDuckGoSearch::new(language)),
+        SearchProviderKind::Brave => Box::new(BraveSearch::new(language)),
+        SearchProviderKind::Searxng => Box::new(SearxngSearch::new(settings.searxng_url.as_deref(), language)),
+    }
+}
@@ -0,0 +1,58 @@
+use super::SearchResult;
+use crate::ingest::url_matches;
+use serde::{Deserialize, Serialize};
+
+/// Which search results are learned from and in what order, decided from the results
+/// alone before any page is fetched. Domains are written as in `learning.domains`.
+#[derive(Debug, Serialize, Deserialize, Clone, Default)]
+pub struct SearchRules {
+    /// Results from these sites come first, in the order listed, such as `fandom.com` and
+    /// the official site.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub preferred_domains: Vec<String>,
+    /// Results from these sites are dropped.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub blocked_domains: Vec<String>,
+    /// Results whose snippet is shorter than this many characters are dropped.
+    #[serde(default)]
+    pub min_snippet_length: usize,
+    /// Language codes, such as `en` or `ja`, results must be in. Providers that can are
+    /// asked for results in the first one; results the provider reports in another
+    /// language are dropped.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub languages: Vec<String>,
+}
+
+impl SearchRules {
+    /// Drops the results the rules exclude and puts the preferred ones first, keeping the
+    /// search engine's order otherwise.
+    pub fn apply(&self, results: Vec<SearchResult>) -> Vec<SearchResult> {
+        let mut kept: Vec<SearchResult> = results
+            .into_iter()
+            .filter(|result| !url_matches(&self.blocked_domains, &result.url))
+            .filter(|result| result.snippet.trim().chars().count() >= self.min_snippet_length)
+            .filter(|result| match &result.language {
+                Some(language) if !self.languages.is_empty() => self.languages.iter().any(|wanted| same_language(wanted, language)),
+                _ => true,
+            })
+            .collect();
+        kept.sort_by_key(|result| {
+            self.preferred_domains
+                .iter()
+                .position(|domain| url_matches(std::slice::from_ref(domain), &result.url))
+                .unwrap_or(self.preferred_domains.len())
+        });
+        kept
+    }
+
+    /// The language providers are asked for results in.
+    pub fn language(&self) -> Option<String> {
+        self.languages.first().map(|language| language.trim().to_ascii_lowercase())
+    }
+}
+
+/// Whether two language codes name the same language, ignoring regions: `en` and `en-US`.
+fn same_language(a: &str, b: &str) -> bool {
+    let base = |code: &str| code.trim().split(['-', '_']).next().unwrap_or_default().to_ascii_lowercase();
+    base(a) == base(b)
+}
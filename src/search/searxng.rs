@@ -0,0 +1,66 @@
+use super::{SearchProvider, SearchResult};
+use crate::paths;
+use async_trait::async_trait;
+use serde_json::Value;
+
+pub struct SearxngSearch {
+    /// Address of the instance, such as `http://localhost:8888`.
+    base_url: Option<String>,
+    /// Language results are asked for in, such as `en`.
+    language: Option<String>,
+    client: reqwest::Client,
+}
+
+impl SearxngSearch {
+    pub fn new(base_url: Option<&str>, language: Option<String>) -> Self {
+        SearxngSearch {
+            base_url: base_url.map(|url| url.trim_end_matches('/').to_string()),
+            language,
+            client: reqwest::Client::builder()
+                .timeout(std::time::Duration::from_secs(15))
+                .build()
+                .unwrap_or_default(),
+        }
+    }
+}
+
+#[async_trait]
+impl SearchProvider for SearxngSearch {
+    fn name(&self) -> &str {
+        "SearxNG"
+    }
+
+    async fn search(&self, query: &str, count: usize) -> Result<Vec<SearchResult>, Box<dyn std::error::Error>> {
+        let base_url = self.base_url.as_deref().ok_or_else(|| format!("search.searxng_url is not set in {}", paths::config_file().display()))?;
+        let response = self
+            .client
+            .get(format!("{}/search", base_url))
+            .header("Accept", "application/json")
+            .query(&[("q", query), ("format", "json"), ("safesearch", "0"), ("language", self.language.as_deref().unwrap_or("all"))])
+            .send()
+            .await?;
+        if response.status() == reqwest::StatusCode::FORBIDDEN {
+            return Err(format!("{} refused the JSON format; add `json` to search.formats in its settings.yml", base_url).into());
+        }
+        if !response.status().is_success() {
+            return Err(format!("SearxNG search failed: {}", response.status()).into());
+        }
+
+        let body: Value = response.json().await?;
+        let results = body["results"]
+            .as_array()
+            .into_iter()
+            .flatten()
+            .filter_map(|item| {
+                Some(SearchResult {
+                    title: item["title"].as_str().unwrap_or_default().to_string(),
+                    url: item["url"].as_str()?.to_string(),
+                    snippet: item["content"].as_str().unwrap_or_default().to_string(),
+                    language: None,
+                })
+            })
+            .take(count)
+            .collect();
+        Ok(results)
+    }
+}
@@ -0,0 +1,103 @@
+use std::collections::HashMap;
+use std::env;
+use std::sync::Mutex;
+
+/// Service name the secrets are filed under in the OS keyring.
+const KEYRING_SERVICE: &str = "alya-chatbot";
+
+/// The secrets the chatbot reads, listed by `keys list`.
+pub const KNOWN: &[&str] = &[
+    "GEMINI_API_KEY",
+    "GOOGLE_SEARCH_API_KEY",
+    "GOOGLE_SEARCH_ENGINE_ID",
+    "BRAVE_SEARCH_API_KEY",
+    "AWS_ACCESS_KEY_ID",
+    "AWS_SECRET_ACCESS_KEY",
+    "WEBDAV_PASSWORD",
+    "ALYA_ENCRYPTION_KEY",
+    "ALYA_JWT_SECRET",
+    "DISCORD_BOT_TOKEN",
+    "TELEGRAM_BOT_TOKEN",
+    "MATRIX_ACCESS_TOKEN",
+    "MATRIX_STORE_PASSPHRASE",
+    "SLACK_APP_TOKEN",
+    "SLACK_BOT_TOKEN",
+    "IRC_PASSWORD",
+    "WHATSAPP_ACCESS_TOKEN",
+    "WHATSAPP_APP_SECRET",
+    "WHATSAPP_VERIFY_TOKEN",
+    "EMAIL_PASSWORD",
+    "XMPP_PASSWORD",
+    "MASTODON_ACCESS_TOKEN",
+];
+
+/// Where a secret was found.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Source {
+    Keyring,
+    Environment,
+}
+
+/// Keyring lookups can be slow or ask to unlock the keyring, so each secret is read once
+/// per run.
+static CACHE: Mutex<Option<HashMap<String, Found>>> = Mutex::new(None);
+
+type Found = Option<(String, Source)>;
+
+/// Looks up a secret by its environment variable name, in the OS keyring first and then in
+/// the environment (which includes `.env`).
+pub fn get(name: &str) -> Option<String> {
+    lookup(name).map(|(value, _)| value)
+}
+
+/// Like `get`, but also tells where the secret came from.
+pub fn lookup(name: &str) -> Found {
+    let mut cache = CACHE.lock().unwrap();
+    let cache = cache.get_or_insert_with(HashMap::new);
+    if let Some(found) = cache.get(name) {
+        return found.clone();
+    }
+    let stored = keyring::Entry::new(KEYRING_SERVICE, name).and_then(|entry| entry.get_password());
+    let found = match stored {
+        Ok(value) => Some((value, Source::Keyring)),
+        Err(_) => env::var(name).ok().filter(|value| !value.is_empty()).map(|value| (value, Source::Environment)),
+    };
+    cache.insert(name.to_string(), found.clone());
+    found
+}
+
+/// Like `get`, for secrets the chatbot cannot do without.
+pub fn require(name: &str) -> Result<String, String> {
+    get(name).ok_or_else(|| format!("{} not set; run `chatbot keys set {}` or add it to .env", name, name))
+}
+
+/// Stores a secret in the OS keyring.
+pub fn set(name: &str, value: &str) -> Result<(), Box<dyn std::error::Error>> {
+    keyring::Entry::new(KEYRING_SERVICE, name)?.set_password(value)?;
+    forget(name);
+    Ok(())
+}
+
+/// Sets a secret for the rest of the run only, as an environment variable, where the OS
+/// keyring can't store it.
+pub fn set_for_run(name: &str, value: &str) {
+    env::set_var(name, value);
+    forget(name);
+}
+
+/// Removes a secret from the OS keyring. Returns false if it was not stored there.
+pub fn delete(name: &str) -> Result<bool, Box<dyn std::error::Error>> {
+    let deleted = match keyring::Entry::new(KEYRING_SERVICE, name)?.delete_credential() {
+        Ok(()) => true,
+        Err(keyring::Error::NoEntry) => false,
+        Err(e) => return Err(e.into()),
+    };
+    forget(name);
+    Ok(deleted)
+}
+
+fn forget(name: &str) {
+    if let Some(cache) = CACHE.lock().unwrap().as_mut() {
+        cache.remove(name);
+    }
+}
@@ -0,0 +1,662 @@
+use crate::auth::{Auth, Caller, Scope};
+use axum::extract::{FromRef, Path, Query, Request, State};
+use axum::Extension;
+use axum::http::{header, HeaderMap, HeaderValue, Method, StatusCode};
+use axum::middleware::{self, Next};
+use axum::response::sse::{Event, KeepAlive, Sse};
+use axum::response::{Html, IntoResponse, Response};
+use axum::routing::{get, post};
+use axum::{Json, Router};
+use futures::stream::{self, Stream, StreamExt};
+use serde::{Deserialize, Serialize};
+use std::convert::Infallible;
+use serde_json::{json, Value};
+use std::collections::{HashMap, VecDeque};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+use tokio::net::TcpListener;
+use tokio::sync::{mpsc, oneshot};
+
+/// What an API request asks of the chatbot. The handlers don't share the chatbot; they
+/// send it a call and wait for the answer, so calls are handled one at a time, between
+/// the scheduled jobs.
+#[derive(Debug)]
+pub enum ApiCall {
+    /// With `stream`, the reply is also sent there piece by piece as it is written.
+    Chat {
+        session: String,
+        message: String,
+        stream: Option<mpsc::UnboundedSender<String>>,
+    },
+    Character,
+    /// Changes the character's personality or description, and saves the config.
+    UpdateCharacter { personality: Option<String>, description: Option<String> },
+    /// What was remembered about the user of a session.
+    Memories(String),
+    /// Forgets the memories and conversation of a session.
+    Forget(String),
+    Facts { filter: String, limit: Option<usize> },
+    /// The facts most relevant to `query`, as they would be picked for a chat message of
+    /// the session.
+    Retrieve { session: String, query: String, limit: usize },
+    Fact(String),
+    DeleteFact(String),
+    /// Replaces the text of a fact.
+    UpdateFact { key: String, text: String },
+    Train(String),
+    Learn,
+    RunJob(String),
+}
+
+/// Limits on the HTTP API, so its users cannot run through the model's quota.
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct ServerSettings {
+    /// How many chat messages each session may send a minute; 0 for no limit.
+    #[serde(default = "default_messages_per_minute")]
+    pub messages_per_minute: usize,
+    /// How many model tokens the chats of all sessions together may use an hour; 0 for no
+    /// limit.
+    #[serde(default)]
+    pub tokens_per_hour: u64,
+    /// What the character says when a message is over a limit, with `{name}` for its name
+    /// and `{seconds}` for how long to wait.
+    #[serde(default = "default_throttle_message")]
+    pub throttle_message: String,
+    /// Whether to serve the chat page at `/`.
+    #[serde(default = "default_web_ui")]
+    pub web_ui: bool,
+}
+
+fn default_messages_per_minute() -> usize {
+    10
+}
+
+fn default_throttle_message() -> String {
+    "Hey, slow down a little, I can't keep up! Give me {seconds} seconds and ask me again.".to_string()
+}
+
+fn default_web_ui() -> bool {
+    true
+}
+
+impl Default for ServerSettings {
+    fn default() -> Self {
+        ServerSettings {
+            messages_per_minute: default_messages_per_minute(),
+            tokens_per_hour: 0,
+            throttle_message: default_throttle_message(),
+            web_ui: default_web_ui(),
+        }
+    }
+}
+
+const MINUTE: Duration = Duration::from_secs(60);
+const HOUR: Duration = Duration::from_secs(3600);
+
+/// Counts the messages of each session in the last minute and the tokens of all chats in
+/// the last hour, for the HTTP and gRPC APIs together.
+pub struct RateLimiter {
+    settings: ServerSettings,
+    messages: HashMap<String, VecDeque<Instant>>,
+    tokens: VecDeque<(Instant, u64)>,
+}
+
+/// The rate limiter, as the APIs share it.
+pub type Limiter = Arc<Mutex<RateLimiter>>;
+
+impl RateLimiter {
+    pub fn new(settings: ServerSettings) -> Self {
+        RateLimiter {
+            settings,
+            messages: HashMap::new(),
+            tokens: VecDeque::new(),
+        }
+    }
+
+    /// Counts a message of `session`, or returns how long it has to wait if it is over a
+    /// limit.
+    pub fn admit(&mut self, session: &str) -> Result<(), Duration> {
+        let now = Instant::now();
+        while self.tokens.front().is_some_and(|(at, _)| now.duration_since(*at) >= HOUR) {
+            self.tokens.pop_front();
+        }
+        let limit = self.settings.tokens_per_hour;
+        if limit > 0 && self.tokens.iter().map(|(_, tokens)| tokens).sum::<u64>() >= limit {
+            // Wait for the oldest chat to drop out of the hour
+            let (oldest, _) = self.tokens.front().copied().unwrap_or((now, 0));
+            return Err(HOUR.saturating_sub(now.duration_since(oldest)));
+        }
+
+        let sent = self.messages.entry(session.to_string()).or_default();
+        while sent.front().is_some_and(|at| now.duration_since(*at) >= MINUTE) {
+            sent.pop_front();
+        }
+        let limit = self.settings.messages_per_minute;
+        if limit > 0 && sent.len() >= limit {
+            return Err(MINUTE.saturating_sub(now.duration_since(sent[0])));
+        }
+        sent.push_back(now);
+        // Sessions that went quiet are not kept around
+        self.messages.retain(|_, sent| sent.back().is_some_and(|at| now.duration_since(*at) < MINUTE));
+        Ok(())
+    }
+
+    /// The throttle message for waiting `wait`, as an error.
+    pub fn throttled(&self, name: &str, wait: Duration) -> ApiError {
+        ApiError::Throttled(self.throttle_message(name, wait), wait.as_secs().max(1))
+    }
+
+    /// The throttle message for waiting `wait`, for the character to send on a chat platform.
+    pub fn throttle_message(&self, name: &str, wait: Duration) -> String {
+        self.settings
+            .throttle_message
+            .replace("{name}", name)
+            .replace("{seconds}", &wait.as_secs().max(1).to_string())
+    }
+
+    pub fn record_tokens(&mut self, tokens: u64) {
+        if tokens > 0 {
+            self.tokens.push_back((Instant::now(), tokens));
+        }
+    }
+}
+
+/// What the handlers share.
+#[derive(Clone)]
+struct AppState {
+    calls: Calls,
+    limiter: Limiter,
+    /// The character's name, for the throttle message.
+    name: String,
+    /// The event streams of the connected MCP clients, by their ID.
+    mcp: Arc<Mutex<HashMap<String, McpStream>>>,
+}
+
+/// Where the answers to an MCP client's messages are sent, and whose session it chats in.
+struct McpStream {
+    session: String,
+    messages: mpsc::UnboundedSender<Value>,
+}
+
+/// The receiving end of an MCP client's event stream, which forgets the stream when the
+/// client disconnects.
+struct McpOutbox {
+    id: String,
+    messages: mpsc::UnboundedReceiver<Value>,
+    streams: Arc<Mutex<HashMap<String, McpStream>>>,
+}
+
+impl Drop for McpOutbox {
+    fn drop(&mut self) {
+        self.streams.lock().unwrap().remove(&self.id);
+    }
+}
+
+impl FromRef<AppState> for Calls {
+    fn from_ref(state: &AppState) -> Calls {
+        state.calls.clone()
+    }
+}
+
+#[derive(Debug)]
+pub enum ApiError {
+    BadRequest(String),
+    Unauthorized(String),
+    Forbidden(String),
+    NotFound(String),
+    /// Over a rate limit: what the character says about it, and how many seconds to wait.
+    Throttled(String, u64),
+    Internal(String),
+}
+
+impl std::fmt::Display for ApiError {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            ApiError::BadRequest(message)
+            | ApiError::Unauthorized(message)
+            | ApiError::Forbidden(message)
+            | ApiError::NotFound(message)
+            | ApiError::Throttled(message, _)
+            | ApiError::Internal(message) => write!(f, "{}", message),
+        }
+    }
+}
+
+impl IntoResponse for ApiError {
+    fn into_response(self) -> Response {
+        let (status, message) = match self {
+            ApiError::BadRequest(message) => (StatusCode::BAD_REQUEST, message),
+            ApiError::Unauthorized(message) => (StatusCode::UNAUTHORIZED, message),
+            ApiError::Forbidden(message) => (StatusCode::FORBIDDEN, message),
+            ApiError::NotFound(message) => (StatusCode::NOT_FOUND, message),
+            ApiError::Throttled(text, retry_after) => {
+                let body = json!({ "error": "Too many requests", "text": text, "retry_after": retry_after });
+                let mut response = (StatusCode::TOO_MANY_REQUESTS, Json(body)).into_response();
+                response.headers_mut().insert(header::RETRY_AFTER, HeaderValue::from(retry_after));
+                return response;
+            }
+            ApiError::Internal(message) => (StatusCode::INTERNAL_SERVER_ERROR, message),
+        };
+        (status, Json(json!({ "error": message }))).into_response()
+    }
+}
+
+pub type ApiResult = Result<Value, ApiError>;
+
+/// Where the handlers send their calls, each with where to send the answer.
+pub type Calls = mpsc::Sender<(ApiCall, oneshot::Sender<ApiResult>)>;
+
+#[derive(Debug, Deserialize)]
+struct ChatRequest {
+    message: String,
+    /// Who is chatting, for callers that serve several people with one key.
+    #[serde(default)]
+    user: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+struct UserQuery {
+    user: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+struct McpQuery {
+    #[serde(rename = "sessionId")]
+    session_id: String,
+}
+
+#[derive(Debug, Deserialize)]
+struct TrainRequest {
+    text: String,
+}
+
+#[derive(Debug, Deserialize)]
+struct FactsQuery {
+    /// A tag name, or text to search for.
+    #[serde(default)]
+    q: String,
+    limit: Option<usize>,
+}
+
+/// Serves the API on `listener` until the server fails. Without `auth`, anyone who can
+/// reach it may do anything. `name` is the character's.
+pub async fn serve(
+    listener: TcpListener,
+    calls: Calls,
+    auth: Option<Arc<Auth>>,
+    limiter: Limiter,
+    web_ui: bool,
+    name: String,
+) -> std::io::Result<()> {
+    let state = AppState {
+        calls,
+        limiter,
+        name,
+        mcp: Arc::new(Mutex::new(HashMap::new())),
+    };
+    let mut app = Router::new()
+        .route("/chat", post(chat))
+        .route("/chat/stream", post(chat_stream))
+        .route("/character", get(character))
+        .route("/memories", get(memories).delete(forget))
+        .route("/knowledge", get(facts).post(train))
+        .route("/knowledge/{key}", get(fact).put(update_fact).delete(delete_fact))
+        .route("/learn", post(learn))
+        .route("/jobs/{name}", post(run_job))
+        .route("/mcp/sse", get(mcp_connect))
+        .route("/mcp/messages", post(mcp_message))
+        .with_state(state);
+    if let Some(auth) = auth {
+        app = app.layer(middleware::from_fn_with_state(auth, authenticate));
+    }
+    if web_ui {
+        // The page itself holds nothing private; it asks for the API key when it needs one
+        app = app.merge(
+            Router::new()
+                .route("/", get(|| async { Html(include_str!("../web/index.html")) }))
+                .route("/app.js", get(|| async { ([(header::CONTENT_TYPE, "text/javascript")], include_str!("../web/app.js")) }))
+                .route("/style.css", get(|| async { ([(header::CONTENT_TYPE, "text/css")], include_str!("../web/style.css")) })),
+        );
+    }
+    axum::serve(listener, app).await
+}
+
+/// What a request needs to be allowed: chatting, reading the character, browsing the
+/// knowledge and MCP need the chat scope, everything else the admin scope.
+fn required_scope(method: &Method, path: &str) -> Scope {
+    match path {
+        "/chat" | "/chat/stream" | "/character" | "/memories" | "/mcp/sse" | "/mcp/messages" => Scope::Chat,
+        _ if *method == Method::GET && (path == "/knowledge" || path.starts_with("/knowledge/")) => Scope::Chat,
+        _ => Scope::Admin,
+    }
+}
+
+/// Who sent a request with `headers`, if they carry an API key or JWT, as `Authorization:
+/// Bearer <token>` or `X-API-Key: <key>`, whose scope allows `needed`.
+pub fn authorize(auth: &Auth, headers: &HeaderMap, needed: Scope) -> Result<Caller, ApiError> {
+    let token = headers
+        .get(header::AUTHORIZATION)
+        .and_then(|value| value.to_str().ok())
+        .and_then(|value| value.strip_prefix("Bearer "))
+        .or_else(|| headers.get("x-api-key").and_then(|value| value.to_str().ok()))
+        .map(str::trim)
+        .ok_or_else(|| ApiError::Unauthorized("An API key or token is needed".to_string()))?;
+    let caller = auth
+        .authenticate(token)
+        .ok_or_else(|| ApiError::Unauthorized("The API key or token is not valid".to_string()))?;
+    if !caller.scope.allows(needed) {
+        return Err(ApiError::Forbidden(format!("{} may only chat", caller.name)));
+    }
+    Ok(caller)
+}
+
+/// Lets a request through if `authorize` does, adding the caller to its extensions.
+async fn authenticate(State(auth): State<Arc<Auth>>, mut request: Request, next: Next) -> Result<Response, ApiError> {
+    let needed = required_scope(request.method(), request.uri().path());
+    let caller = authorize(&auth, request.headers(), needed)?;
+    request.extensions_mut().insert(caller);
+    Ok(next.run(request).await)
+}
+
+pub async fn call(calls: &Calls, call: ApiCall) -> ApiResult {
+    let (answer, answered) = oneshot::channel();
+    calls
+        .send((call, answer))
+        .await
+        .map_err(|_| ApiError::Internal("The chatbot has stopped".to_string()))?;
+    answered
+        .await
+        .map_err(|_| ApiError::Internal("The chatbot has stopped".to_string()))?
+}
+
+/// The session of a user: each caller has their own, and a caller chatting for several
+/// people has one for each of them. Without authentication, the user alone picks it.
+pub fn session(caller: Option<&Caller>, user: Option<&str>) -> Result<String, ApiError> {
+    let user = user.map(str::trim).filter(|user| !user.is_empty());
+    if user.is_some_and(|user| user.contains('/')) {
+        return Err(ApiError::BadRequest("The user may not contain '/'".to_string()));
+    }
+    Ok(match (caller, user) {
+        (Some(caller), Some(user)) => format!("api/{}/{}", caller.name, user),
+        (Some(caller), None) => format!("api/{}", caller.name),
+        (None, Some(user)) => format!("api/anonymous/{}", user),
+        (None, None) => "api/anonymous".to_string(),
+    })
+}
+
+async fn chat(
+    State(state): State<AppState>,
+    caller: Option<Extension<Caller>>,
+    Json(request): Json<ChatRequest>,
+) -> Result<Json<Value>, ApiError> {
+    if request.message.trim().is_empty() {
+        return Err(ApiError::BadRequest("The message is empty".to_string()));
+    }
+    let session = session(caller.as_deref(), request.user.as_deref())?;
+    {
+        let mut limiter = state.limiter.lock().unwrap();
+        if let Err(wait) = limiter.admit(&session) {
+            return Err(limiter.throttled(&state.name, wait));
+        }
+    }
+    let reply = call(&state.calls, ApiCall::Chat { session, message: request.message, stream: None }).await?;
+    state.limiter.lock().unwrap().record_tokens(reply["tokens"].as_u64().unwrap_or_default());
+    Ok(Json(reply))
+}
+
+/// Like `chat`, but answers with server-sent events: a `chunk` event with each piece of the
+/// reply as a JSON string while it is written, then a `reply` event with the whole reply,
+/// or an `error` event.
+async fn chat_stream(
+    State(state): State<AppState>,
+    caller: Option<Extension<Caller>>,
+    Json(request): Json<ChatRequest>,
+) -> Result<Sse<impl Stream<Item = Result<Event, Infallible>>>, ApiError> {
+    if request.message.trim().is_empty() {
+        return Err(ApiError::BadRequest("The message is empty".to_string()));
+    }
+    let session = session(caller.as_deref(), request.user.as_deref())?;
+    {
+        let mut limiter = state.limiter.lock().unwrap();
+        if let Err(wait) = limiter.admit(&session) {
+            return Err(limiter.throttled(&state.name, wait));
+        }
+    }
+    let (chunks, written) = mpsc::unbounded_channel();
+    let (answer, answered) = oneshot::channel();
+    let chat = ApiCall::Chat { session, message: request.message, stream: Some(chunks) };
+    state
+        .calls
+        .send((chat, answer))
+        .await
+        .map_err(|_| ApiError::Internal("The chatbot has stopped".to_string()))?;
+
+    // The chunks end when the chatbot is done with the call and drops its sender
+    let chunks = stream::unfold(written, |mut written| async move {
+        let chunk = written.recv().await?;
+        Some((Ok(Event::default().event("chunk").data(json!(chunk).to_string())), written))
+    });
+    let limiter = state.limiter.clone();
+    let end = stream::once(async move {
+        let event = match answered.await {
+            Ok(Ok(reply)) => {
+                limiter.lock().unwrap().record_tokens(reply["tokens"].as_u64().unwrap_or_default());
+                Event::default().event("reply").data(reply.to_string())
+            }
+            Ok(Err(e)) => Event::default().event("error").data(json!({ "error": e.to_string() }).to_string()),
+            Err(_) => Event::default().event("error").data(json!({ "error": "The chatbot has stopped" }).to_string()),
+        };
+        Ok(event)
+    });
+    Ok(Sse::new(chunks.chain(end)))
+}
+
+async fn memories(State(calls): State<Calls>, caller: Option<Extension<Caller>>, Query(query): Query<UserQuery>) -> Result<Json<Value>, ApiError> {
+    let session = session(caller.as_deref(), query.user.as_deref())?;
+    call(&calls, ApiCall::Memories(session)).await.map(Json)
+}
+
+async fn forget(State(calls): State<Calls>, caller: Option<Extension<Caller>>, Query(query): Query<UserQuery>) -> Result<Json<Value>, ApiError> {
+    let session = session(caller.as_deref(), query.user.as_deref())?;
+    call(&calls, ApiCall::Forget(session)).await.map(Json)
+}
+
+async fn character(State(calls): State<Calls>) -> Result<Json<Value>, ApiError> {
+    call(&calls, ApiCall::Character).await.map(Json)
+}
+
+async fn facts(State(calls): State<Calls>, Query(query): Query<FactsQuery>) -> Result<Json<Value>, ApiError> {
+    call(&calls, ApiCall::Facts { filter: query.q, limit: query.limit }).await.map(Json)
+}
+
+async fn fact(State(calls): State<Calls>, Path(key): Path<String>) -> Result<Json<Value>, ApiError> {
+    call(&calls, ApiCall::Fact(key)).await.map(Json)
+}
+
+async fn delete_fact(State(calls): State<Calls>, Path(key): Path<String>) -> Result<Json<Value>, ApiError> {
+    call(&calls, ApiCall::DeleteFact(key)).await.map(Json)
+}
+
+async fn update_fact(State(calls): State<Calls>, Path(key): Path<String>, Json(request): Json<TrainRequest>) -> Result<Json<Value>, ApiError> {
+    if request.text.trim().is_empty() {
+        return Err(ApiError::BadRequest("The text is empty".to_string()));
+    }
+    call(&calls, ApiCall::UpdateFact { key, text: request.text }).await.map(Json)
+}
+
+async fn train(State(calls): State<Calls>, Json(request): Json<TrainRequest>) -> Result<Json<Value>, ApiError> {
+    if request.text.trim().is_empty() {
+        return Err(ApiError::BadRequest("The text is empty".to_string()));
+    }
+    call(&calls, ApiCall::Train(request.text)).await.map(Json)
+}
+
+async fn learn(State(calls): State<Calls>) -> Result<Json<Value>, ApiError> {
+    call(&calls, ApiCall::Learn).await.map(Json)
+}
+
+async fn run_job(State(calls): State<Calls>, Path(name): Path<String>) -> Result<Json<Value>, ApiError> {
+    call(&calls, ApiCall::RunJob(name)).await.map(Json)
+}
+
+/// Opens the event stream of an MCP client, in MCP's HTTP with SSE transport. The first
+/// event tells the client where to post its messages; the answers come as `message`
+/// events.
+async fn mcp_connect(
+    State(state): State<AppState>,
+    caller: Option<Extension<Caller>>,
+) -> Result<Sse<impl Stream<Item = Result<Event, Infallible>>>, ApiError> {
+    let session = session(caller.as_deref(), Some("mcp"))?;
+    let id = crate::storage::generate_key();
+    let (messages, received) = mpsc::unbounded_channel();
+    state.mcp.lock().unwrap().insert(id.clone(), McpStream { session, messages });
+
+    let endpoint = Event::default().event("endpoint").data(format!("/mcp/messages?sessionId={}", id));
+    let outbox = McpOutbox {
+        id,
+        messages: received,
+        streams: state.mcp.clone(),
+    };
+    let messages = stream::unfold(outbox, |mut outbox| async move {
+        let message = outbox.messages.recv().await?;
+        Some((Ok(Event::default().event("message").data(message.to_string())), outbox))
+    });
+    Ok(Sse::new(stream::once(async { Ok(endpoint) }).chain(messages)).keep_alive(KeepAlive::default()))
+}
+
+/// Takes a message of an MCP client, whose answer goes to its event stream.
+async fn mcp_message(
+    State(state): State<AppState>,
+    caller: Option<Extension<Caller>>,
+    Query(query): Query<McpQuery>,
+    Json(message): Json<Value>,
+) -> Result<StatusCode, ApiError> {
+    let session = session(caller.as_deref(), Some("mcp"))?;
+    let messages = match state.mcp.lock().unwrap().get(&query.session_id) {
+        // A caller may only post to their own streams
+        Some(stream) if stream.session == session => stream.messages.clone(),
+        _ => return Err(ApiError::NotFound("There is no such MCP session".to_string())),
+    };
+    let mcp = crate::mcp::Session {
+        calls: state.calls,
+        session,
+        limiter: Some((state.limiter, state.name)),
+    };
+    tokio::spawn(async move {
+        if let Some(answer) = mcp.handle(&message).await {
+            let _ = messages.send(answer);
+        }
+    });
+    Ok(StatusCode::ACCEPTED)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn limiter(messages_per_minute: usize, tokens_per_hour: u64) -> RateLimiter {
+        RateLimiter::new(ServerSettings {
+            messages_per_minute,
+            tokens_per_hour,
+            ..ServerSettings::default()
+        })
+    }
+
+    fn ago(seconds: u64) -> Instant {
+        Instant::now() - Duration::from_secs(seconds)
+    }
+
+    #[test]
+    fn messages_over_the_limit_wait_for_the_minute() {
+        let mut limiter = limiter(2, 0);
+        assert!(limiter.admit("a").is_ok());
+        assert!(limiter.admit("a").is_ok());
+        let wait = limiter.admit("a").unwrap_err();
+        assert!(wait > Duration::from_secs(58) && wait <= MINUTE, "{:?}", wait);
+    }
+
+    #[test]
+    fn sessions_are_limited_separately() {
+        let mut limiter = limiter(1, 0);
+        assert!(limiter.admit("a").is_ok());
+        assert!(limiter.admit("a").is_err());
+        assert!(limiter.admit("b").is_ok());
+    }
+
+    #[test]
+    fn messages_older_than_a_minute_no_longer_count() {
+        let mut limiter = limiter(2, 0);
+        limiter.messages.insert("a".to_string(), VecDeque::from([ago(70), ago(30)]));
+        assert!(limiter.admit("a").is_ok());
+        let wait = limiter.admit("a").unwrap_err();
+        // The message from 30 seconds ago is the oldest left in the window
+        assert!(wait > Duration::from_secs(28) && wait <= Duration::from_secs(30), "{:?}", wait);
+    }
+
+    #[test]
+    fn refused_messages_are_not_counted() {
+        let mut limiter = limiter(1, 0);
+        limiter.messages.insert("a".to_string(), VecDeque::from([ago(59)]));
+        assert!(limiter.admit("a").is_err());
+        assert_eq!(limiter.messages["a"].len(), 1);
+    }
+
+    #[test]
+    fn zero_turns_the_message_limit_off() {
+        let mut limiter = limiter(0, 0);
+        for _ in 0..100 {
+            assert!(limiter.admit("a").is_ok());
+        }
+    }
+
+    #[test]
+    fn tokens_over_the_hourly_limit_stop_every_session() {
+        let mut limiter = limiter(0, 1000);
+        assert!(limiter.admit("a").is_ok());
+        limiter.record_tokens(600);
+        assert!(limiter.admit("b").is_ok());
+        limiter.record_tokens(400);
+        let wait = limiter.admit("c").unwrap_err();
+        assert!(wait > Duration::from_secs(3598) && wait <= HOUR, "{:?}", wait);
+    }
+
+    #[test]
+    fn tokens_older_than_an_hour_no_longer_count() {
+        let mut limiter = limiter(0, 1000);
+        limiter.tokens = VecDeque::from([(ago(3700), 900), (ago(600), 200)]);
+        assert!(limiter.admit("a").is_ok());
+        assert_eq!(limiter.tokens.len(), 1);
+        limiter.record_tokens(800);
+        let wait = limiter.admit("a").unwrap_err();
+        // Until the chat from ten minutes ago drops out of the hour
+        assert!(wait > Duration::from_secs(2998) && wait <= Duration::from_secs(3000), "{:?}", wait);
+    }
+
+    #[test]
+    fn throttle_message_names_the_character_and_the_wait() {
+        let limiter = RateLimiter::new(ServerSettings {
+            throttle_message: "{name} needs {seconds}s".to_string(),
+            ..ServerSettings::default()
+        });
+        assert_eq!(limiter.throttle_message("Alya", Duration::from_millis(300)), "Alya needs 1s");
+        assert_eq!(limiter.throttle_message("Alya", Duration::from_secs(42)), "Alya needs 42s");
+    }
+
+    #[test]
+    fn chat_and_reading_need_the_chat_scope() {
+        for path in ["/chat", "/chat/stream", "/character", "/memories", "/mcp/sse", "/mcp/messages"] {
+            assert_eq!(required_scope(&Method::POST, path), Scope::Chat, "{}", path);
+        }
+        assert_eq!(required_scope(&Method::GET, "/knowledge"), Scope::Chat);
+        assert_eq!(required_scope(&Method::GET, "/knowledge/some_fact"), Scope::Chat);
+    }
+
+    #[test]
+    fn changing_the_knowledge_needs_the_admin_scope() {
+        assert_eq!(required_scope(&Method::POST, "/knowledge"), Scope::Admin);
+        assert_eq!(required_scope(&Method::DELETE, "/knowledge/some_fact"), Scope::Admin);
+        assert_eq!(required_scope(&Method::GET, "/knowledgebase"), Scope::Admin);
+        assert_eq!(required_scope(&Method::POST, "/learn"), Scope::Admin);
+        assert_eq!(required_scope(&Method::POST, "/jobs/learn"), Scope::Admin);
+    }
+}
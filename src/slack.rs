@@ -0,0 +1,166 @@
+use crate::gateway::{split_message, Connector, Incoming};
+use crate::server::Calls;
+use async_trait::async_trait;
+use futures::{SinkExt, StreamExt};
+use serde_json::{json, Value};
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::sync::mpsc;
+use tokio_tungstenite::tungstenite::Message;
+
+/// The secret holding the app-level token, with the `connections:write` scope, that Socket
+/// Mode connects with.
+pub const APP_TOKEN_NAME: &str = "SLACK_APP_TOKEN";
+/// The secret holding the bot token the replies are posted with.
+pub const BOT_TOKEN_NAME: &str = "SLACK_BOT_TOKEN";
+/// Slack cuts messages longer than this into several.
+const MESSAGE_LIMIT: usize = 4000;
+/// How long to wait before connecting again after the connection failed.
+const RETRY_AFTER: Duration = Duration::from_secs(5);
+
+/// A client of the Slack Web API.
+#[derive(Clone)]
+struct Api {
+    client: reqwest::Client,
+    bot_token: String,
+}
+
+impl Api {
+    async fn call(&self, method: &str, token: &str, params: Value) -> Result<Value, Box<dyn std::error::Error + Send + Sync>> {
+        let response: Value = self
+            .client
+            .post(format!("https://slack.com/api/{}", method))
+            .bearer_auth(token)
+            .json(&params)
+            .send()
+            .await?
+            .json()
+            .await?;
+        if response["ok"] != true {
+            return Err(format!("Slack refused {}: {}", method, response["error"].as_str().unwrap_or_default()).into());
+        }
+        Ok(response)
+    }
+}
+
+/// The Slack app. It answers mentions and direct messages in the workspace over Socket
+/// Mode, so no public address is needed, with a conversation for each Slack user. Replies
+/// go in the thread of the message they answer.
+pub struct Bot {
+    api: Api,
+    app_token: String,
+}
+
+impl Bot {
+    pub fn new(app_token: String, bot_token: String) -> Result<Self, reqwest::Error> {
+        let api = Api {
+            client: reqwest::Client::builder().timeout(Duration::from_secs(30)).build()?,
+            bot_token,
+        };
+        Ok(Bot { api, app_token })
+    }
+}
+
+/// Where to post a reply: the channel, and the thread if there is one.
+pub struct ReplyTo {
+    channel: String,
+    thread: Option<String>,
+}
+
+#[async_trait]
+impl Connector for Bot {
+    type ReplyTo = ReplyTo;
+
+    const NAME: &'static str = "slack";
+    const TITLE: &'static str = "Slack";
+    const PER_USER: bool = true;
+
+    /// Fails only if the tokens are refused at first.
+    async fn receive(self: Arc<Self>, incoming: mpsc::Sender<Incoming<ReplyTo>>, _calls: Calls) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+        let me = self.api.call("auth.test", &self.api.bot_token, json!({})).await?;
+        let me = me["user_id"].as_str().unwrap_or_default().to_string();
+        // Checks the app token too
+        self.api.call("apps.connections.open", &self.app_token, json!({})).await?;
+        loop {
+            match connect(&self.api, &self.app_token, &me, &incoming).await {
+                // Slack asks to reconnect every few hours
+                Ok(()) => status!("Reconnecting to Slack"),
+                Err(e) => {
+                    status!("Lost the connection to Slack, reconnecting: {}", e);
+                    tokio::time::sleep(RETRY_AFTER).await;
+                }
+            }
+        }
+    }
+
+    async fn send(&self, message: &Incoming<ReplyTo>, reply: &str) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+        for part in split_message(reply, MESSAGE_LIMIT) {
+            let mut params = json!({ "channel": message.reply_to.channel, "text": part });
+            if let Some(thread) = &message.reply_to.thread {
+                params["thread_ts"] = json!(thread);
+            }
+            self.api.call("chat.postMessage", &self.api.bot_token, params).await?;
+        }
+        Ok(())
+    }
+}
+
+/// Passes on the events of one Socket Mode connection until Slack closes it.
+async fn connect(api: &Api, app_token: &str, me: &str, incoming: &mpsc::Sender<Incoming<ReplyTo>>) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+    let opened = api.call("apps.connections.open", app_token, json!({})).await?;
+    let url = opened["url"].as_str().ok_or("Slack gave no Socket Mode URL")?;
+    let (mut socket, _) = tokio_tungstenite::connect_async(url).await?;
+    while let Some(message) = socket.next().await {
+        let envelope: Value = match message? {
+            Message::Text(text) => serde_json::from_str(&text)?,
+            Message::Ping(data) => {
+                socket.send(Message::Pong(data)).await?;
+                continue;
+            }
+            Message::Close(_) => break,
+            _ => continue,
+        };
+        match envelope["type"].as_str() {
+            Some("hello") => status!("Connected to Slack"),
+            Some("disconnect") => break,
+            Some("events_api") => {
+                // Slack sends an event again unless it is acknowledged within 3 seconds
+                if let Some(id) = envelope["envelope_id"].as_str() {
+                    socket.send(Message::Text(json!({ "envelope_id": id }).to_string())).await?;
+                }
+                let event = &envelope["payload"]["event"];
+                let direct = event["type"] == "message" && event["channel_type"] == "im";
+                if event["type"] != "app_mention" && !direct {
+                    continue;
+                }
+                // Messages from bots, the character's own included, and edits are left alone
+                if event.get("bot_id").is_some() || event.get("subtype").is_some() {
+                    continue;
+                }
+                if let Some(message) = incoming_message(event, me, direct) {
+                    let _ = incoming.send(message).await;
+                }
+            }
+            _ => {}
+        }
+    }
+    Ok(())
+}
+
+fn incoming_message(event: &Value, me: &str, direct: bool) -> Option<Incoming<ReplyTo>> {
+    let (Some(user), Some(channel), Some(ts)) = (event["user"].as_str(), event["channel"].as_str(), event["ts"].as_str()) else {
+        return None;
+    };
+    let text = event["text"].as_str().unwrap_or_default().replace(&format!("<@{}>", me), "").trim().to_string();
+    if text.is_empty() {
+        return None;
+    }
+    // A message already in a thread is answered there, and a mention in a channel starts one
+    let thread = event["thread_ts"].as_str().or((!direct).then_some(ts)).map(str::to_string);
+    Some(Incoming {
+        user: user.to_string(),
+        channel: channel.to_string(),
+        text,
+        reply_to: ReplyTo { channel: channel.to_string(), thread },
+    })
+}
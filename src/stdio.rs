@@ -0,0 +1,99 @@
+use serde_json::{json, Value};
+use std::io;
+use tokio::io::{AsyncBufReadExt, AsyncReadExt, AsyncWrite, AsyncWriteExt, BufReader, Stdin};
+use tokio::sync::mpsc;
+
+/// JSON-RPC error codes.
+pub const PARSE_ERROR: i64 = -32700;
+pub const INVALID_REQUEST: i64 = -32600;
+pub const METHOD_NOT_FOUND: i64 = -32601;
+pub const INVALID_PARAMS: i64 = -32602;
+pub const INTERNAL_ERROR: i64 = -32603;
+/// A request sent before `initialize`, as language servers answer it.
+pub const NOT_INITIALIZED: i64 = -32002;
+
+/// How a message is delimited: with a `Content-Length` header as language servers do, or
+/// as one line of JSON. Answers are framed like the request they answer.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Framing {
+    Headers,
+    Lines,
+}
+
+/// Reads the messages on stdin in the background and sends them to the returned channel,
+/// which closes at the end of input. Reading in a task of its own keeps a message from
+/// being cut off when the caller stops waiting for it to run a job.
+pub fn read_stdin() -> mpsc::Receiver<io::Result<(String, Framing)>> {
+    let (messages, received) = mpsc::channel(16);
+    tokio::spawn(async move {
+        let mut stdin = BufReader::new(tokio::io::stdin());
+        loop {
+            let message = match read_message(&mut stdin).await {
+                Ok(Some(message)) => Ok(message),
+                Ok(None) => break,
+                Err(e) => Err(e),
+            };
+            let failed = message.is_err();
+            if messages.send(message).await.is_err() || failed {
+                break;
+            }
+        }
+    });
+    received
+}
+
+async fn read_message(stdin: &mut BufReader<Stdin>) -> io::Result<Option<(String, Framing)>> {
+    let mut length = None;
+    loop {
+        let mut line = String::new();
+        if stdin.read_line(&mut line).await? == 0 {
+            return Ok(None);
+        }
+        let line = line.trim();
+        if line.starts_with('{') && length.is_none() {
+            return Ok(Some((line.to_string(), Framing::Lines)));
+        }
+        if line.is_empty() {
+            match length {
+                Some(length) => return read_body(stdin, length).await.map(|body| Some((body, Framing::Headers))),
+                // Blank lines between messages
+                None => continue,
+            }
+        }
+        if let Some((name, value)) = line.split_once(':') {
+            if name.trim().eq_ignore_ascii_case("content-length") {
+                length = Some(value.trim().parse::<usize>().map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?);
+            }
+        }
+    }
+}
+
+async fn read_body(stdin: &mut BufReader<Stdin>, length: usize) -> io::Result<String> {
+    let mut body = vec![0; length];
+    stdin.read_exact(&mut body).await?;
+    String::from_utf8(body).map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))
+}
+
+/// Writes a message framed as `framing`.
+pub async fn write_message<W: AsyncWrite + Unpin>(out: &mut W, message: &Value, framing: Framing) -> io::Result<()> {
+    let body = message.to_string();
+    match framing {
+        Framing::Headers => out.write_all(format!("Content-Length: {}\r\n\r\n{}", body.len(), body).as_bytes()).await?,
+        Framing::Lines => out.write_all(format!("{}\n", body).as_bytes()).await?,
+    }
+    out.flush().await
+}
+
+/// The answer to the request `id`.
+pub fn result(id: &Value, result: Value) -> Value {
+    json!({ "jsonrpc": "2.0", "id": id, "result": result })
+}
+
+/// The error answer to the request `id`.
+pub fn error(id: &Value, code: i64, message: &str) -> Value {
+    json!({ "jsonrpc": "2.0", "id": id, "error": { "code": code, "message": message } })
+}
+
+pub fn notification(method: &str, params: Value) -> Value {
+    json!({ "jsonrpc": "2.0", "method": method, "params": params })
+}
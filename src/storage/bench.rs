@@ -0,0 +1,81 @@
+use super::{Codec, JsonStore, KnowledgeStore, RedbStore, SqliteStore, StorageBackend};
+use crate::knowledge::Knowledge;
+use std::fmt;
+use std::fs;
+use std::path::Path;
+use std::time::{Duration, Instant};
+
+/// Size and speed of one backend holding the same knowledge.
+#[derive(Debug)]
+pub struct BenchResult {
+    pub backend: StorageBackend,
+    pub compression: bool,
+    pub size: u64,
+    pub save: Duration,
+    /// Average time to open the store and load everything, as on startup.
+    pub load: Duration,
+}
+
+impl fmt::Display for BenchResult {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{:<8} {:<6} {:>10.1} KiB {:>9.1} ms {:>9.1} ms",
+            format!("{:?}", self.backend).to_lowercase(),
+            if self.compression { "zstd" } else { "none" },
+            self.size as f64 / 1024.0,
+            self.save.as_secs_f64() * 1000.0,
+            self.load.as_secs_f64() * 1000.0
+        )
+    }
+}
+
+fn open(backend: StorageBackend, dir: &Path, compression: bool) -> Result<Box<dyn KnowledgeStore>, Box<dyn std::error::Error>> {
+    let codec = Codec::new(compression, None);
+    Ok(match backend {
+        StorageBackend::Sqlite => Box::new(SqliteStore::open(&dir.join("knowledge.db"), codec)?),
+        StorageBackend::Redb => Box::new(RedbStore::open(&dir.join("knowledge.redb"), codec)?),
+        _ => Box::new(JsonStore::new(dir, codec)),
+    })
+}
+
+fn dir_size(dir: &Path) -> Result<u64, std::io::Error> {
+    let mut size = 0;
+    for entry in fs::read_dir(dir)? {
+        size += entry?.metadata()?.len();
+    }
+    Ok(size)
+}
+
+/// Writes `knowledge` into scratch copies of the local backends, with and without
+/// compression, and measures their size on disk and how long they take to load.
+pub async fn compare_backends(knowledge: &Knowledge, rounds: usize) -> Result<Vec<BenchResult>, Box<dyn std::error::Error>> {
+    let root = std::env::temp_dir().join(format!("alya-storage-bench-{}", std::process::id()));
+    let mut results = Vec::new();
+    for backend in [StorageBackend::Json, StorageBackend::Sqlite, StorageBackend::Redb] {
+        for compression in [false, true] {
+            let dir = root.join(format!("{:?}-{}", backend, compression));
+            fs::create_dir_all(&dir)?;
+
+            let started = Instant::now();
+            open(backend, &dir, compression)?.save(knowledge).await?;
+            let save = started.elapsed();
+
+            let started = Instant::now();
+            for _ in 0..rounds.max(1) {
+                open(backend, &dir, compression)?.load().await?;
+            }
+            let load = started.elapsed() / rounds.max(1) as u32;
+
+            results.push(BenchResult {
+                backend,
+                compression,
+                size: dir_size(&dir)?,
+                save,
+                load,
+            });
+        }
+    }
+    fs::remove_dir_all(&root)?;
+    Ok(results)
+}
@@ -0,0 +1,130 @@
+use super::{FactQuery, KnowledgeStore};
+use crate::knowledge::{Fact, Knowledge};
+use crate::vector_index::VectorIndex;
+use async_trait::async_trait;
+use std::collections::HashMap;
+use std::sync::Mutex;
+
+/// Least recently used facts, evicted by scanning for the oldest entry. Linear, but the
+/// cache only holds a few hundred facts.
+struct Lru {
+    capacity: usize,
+    facts: HashMap<String, (Fact, u64)>,
+    clock: u64,
+}
+
+impl Lru {
+    fn get(&mut self, key: &str) -> Option<Fact> {
+        self.clock += 1;
+        let (fact, used) = self.facts.get_mut(key)?;
+        *used = self.clock;
+        Some(fact.clone())
+    }
+
+    fn insert(&mut self, key: &str, fact: &Fact) {
+        if self.capacity == 0 {
+            return;
+        }
+        self.clock += 1;
+        if !self.facts.contains_key(key) && self.facts.len() >= self.capacity {
+            if let Some(oldest) = self.facts.iter().min_by_key(|(_, (_, used))| *used).map(|(key, _)| key.clone()) {
+                self.facts.remove(&oldest);
+            }
+        }
+        self.facts.insert(key.to_string(), (fact.clone(), self.clock));
+    }
+}
+
+/// Keeps the most recently fetched facts of another store in memory, so facts loaded as
+/// stubs do not hit the database every time they are used.
+pub struct CachedStore {
+    inner: Box<dyn KnowledgeStore>,
+    cache: Mutex<Lru>,
+}
+
+impl CachedStore {
+    pub fn new(inner: Box<dyn KnowledgeStore>, capacity: usize) -> Self {
+        CachedStore {
+            inner,
+            cache: Mutex::new(Lru {
+                capacity,
+                facts: HashMap::new(),
+                clock: 0,
+            }),
+        }
+    }
+}
+
+#[async_trait]
+impl KnowledgeStore for CachedStore {
+    async fn load(&self) -> Result<Option<Knowledge>, Box<dyn std::error::Error>> {
+        self.inner.load().await
+    }
+
+    async fn load_metadata(&self) -> Result<Option<Knowledge>, Box<dyn std::error::Error>> {
+        self.inner.load_metadata().await
+    }
+
+    async fn save(&self, knowledge: &Knowledge) -> Result<(), Box<dyn std::error::Error>> {
+        self.inner.save(knowledge).await?;
+        let mut cache = self.cache.lock().unwrap();
+        cache.facts.retain(|key, (fact, _)| match knowledge.facts.get(key) {
+            Some(saved) => {
+                if !saved.is_stub() {
+                    *fact = saved.clone();
+                }
+                true
+            }
+            None => false,
+        });
+        Ok(())
+    }
+
+    async fn load_fact(&self, key: &str) -> Result<Option<Fact>, Box<dyn std::error::Error>> {
+        let cached = self.cache.lock().unwrap().get(key);
+        if cached.is_some() {
+            return Ok(cached);
+        }
+        let fact = self.inner.load_fact(key).await?;
+        if let Some(fact) = &fact {
+            self.cache.lock().unwrap().insert(key, fact);
+        }
+        Ok(fact)
+    }
+
+    async fn save_fact(&self, key: &str, fact: &Fact) -> Result<(), Box<dyn std::error::Error>> {
+        self.inner.save_fact(key, fact).await?;
+        self.cache.lock().unwrap().insert(key, fact);
+        Ok(())
+    }
+
+    async fn delete_fact(&self, key: &str) -> Result<(), Box<dyn std::error::Error>> {
+        self.inner.delete_fact(key).await?;
+        self.cache.lock().unwrap().facts.remove(key);
+        Ok(())
+    }
+
+    async fn iterate(&self, visit: &mut (dyn for<'f> FnMut(&'f str, &'f Fact) + Send)) -> Result<(), Box<dyn std::error::Error>> {
+        self.inner.iterate(visit).await
+    }
+
+    async fn query(&self, query: &FactQuery) -> Result<Vec<(String, Fact)>, Box<dyn std::error::Error>> {
+        self.inner.query(query).await
+    }
+
+    async fn load_session(&self, session_id: &str) -> Result<Vec<String>, Box<dyn std::error::Error>> {
+        self.inner.load_session(session_id).await
+    }
+
+    async fn save_session(&self, session_id: &str, history: &[String]) -> Result<(), Box<dyn std::error::Error>> {
+        self.inner.save_session(session_id, history).await
+    }
+
+    async fn list_sessions(&self) -> Result<Vec<String>, Box<dyn std::error::Error>> {
+        self.inner.list_sessions().await
+    }
+
+    fn vector_index(&self, model: &str) -> Option<Box<dyn VectorIndex>> {
+        self.inner.vector_index(model)
+    }
+}
@@ -0,0 +1,59 @@
+use super::compression;
+use super::encryption::{self, Cipher};
+use std::io;
+use std::sync::Arc;
+
+/// How stored values are written: zstd-compressed when worthwhile, then encrypted when a
+/// cipher is set. Values are read back in whatever form they were written in.
+#[derive(Clone, Default)]
+pub struct Codec {
+    pub compress: bool,
+    pub cipher: Option<Arc<Cipher>>,
+}
+
+impl Codec {
+    pub fn new(compress: bool, cipher: Option<Arc<Cipher>>) -> Self {
+        Codec { compress, cipher }
+    }
+
+    /// The same encryption, without compression; for small values.
+    pub fn uncompressed(&self) -> Self {
+        Codec::new(false, self.cipher.clone())
+    }
+
+    pub fn encode(&self, text: &str) -> io::Result<Vec<u8>> {
+        self.seal(compression::encode(text, self.compress)?)
+    }
+
+    /// Reads a stored value back, together with whether it is in the form `encode` would
+    /// write now; values in another form are rewritten on the next save.
+    pub fn decode(&self, bytes: &[u8]) -> io::Result<(String, bool)> {
+        let encrypted = encryption::is_encrypted(bytes);
+        let inner = self.unseal(bytes)?;
+        let text = compression::decode(&inner)?;
+        let current = encrypted == self.cipher.is_some() && compression::stored_as_configured(&inner, &text, self.compress);
+        Ok((text, current))
+    }
+
+    /// Encrypts already encoded bytes, if encryption is on.
+    pub fn seal(&self, bytes: Vec<u8>) -> io::Result<Vec<u8>> {
+        match &self.cipher {
+            Some(cipher) => cipher.encrypt(&bytes),
+            None => Ok(bytes),
+        }
+    }
+
+    /// Decrypts bytes written by `seal`; unencrypted bytes are returned as they are.
+    pub fn unseal(&self, bytes: &[u8]) -> io::Result<Vec<u8>> {
+        if !encryption::is_encrypted(bytes) {
+            return Ok(bytes.to_vec());
+        }
+        match &self.cipher {
+            Some(cipher) => cipher.decrypt(bytes),
+            None => Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                "The data is encrypted; turn on storage.encryption and provide the key",
+            )),
+        }
+    }
+}
@@ -0,0 +1,95 @@
+use std::io;
+
+/// Every zstd frame starts with these bytes; valid UTF-8 text never does, so stored values
+/// can be told apart without a flag.
+const ZSTD_MAGIC: [u8; 4] = [0x28, 0xB5, 0x2F, 0xFD];
+const LEVEL: i32 = 3;
+/// Shorter values are kept as plain text, where the frame header would eat the savings.
+const MIN_LEN: usize = 256;
+
+/// Whether `text` is stored compressed when compression is on.
+pub fn should_compress(text: &str) -> bool {
+    text.len() >= MIN_LEN
+}
+
+pub fn compress(text: &str) -> io::Result<Vec<u8>> {
+    zstd::encode_all(text.as_bytes(), LEVEL)
+}
+
+pub fn is_compressed(bytes: &[u8]) -> bool {
+    bytes.starts_with(&ZSTD_MAGIC)
+}
+
+/// The form `text` is stored in: compressed, or its plain UTF-8 bytes.
+pub fn encode(text: &str, enabled: bool) -> io::Result<Vec<u8>> {
+    if enabled && should_compress(text) {
+        compress(text)
+    } else {
+        Ok(text.as_bytes().to_vec())
+    }
+}
+
+/// Reads a stored value back, whichever form it was written in.
+pub fn decode(bytes: &[u8]) -> io::Result<String> {
+    let bytes = if is_compressed(bytes) {
+        zstd::decode_all(bytes)?
+    } else {
+        bytes.to_vec()
+    };
+    String::from_utf8(bytes).map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))
+}
+
+/// Whether a value read back as `text` is stored in the form `encode` would write now.
+pub fn stored_as_configured(bytes: &[u8], text: &str, enabled: bool) -> bool {
+    is_compressed(bytes) == (enabled && should_compress(text))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn plain_text_is_read_as_it_is() {
+        assert_eq!(decode(b"Alya likes sweets").unwrap(), "Alya likes sweets");
+        assert_eq!(decode(b"").unwrap(), "");
+    }
+
+    #[test]
+    fn compressed_text_is_read_back() {
+        let text = "Alisa Mikhailovna Kujou is the treasurer of the student council. ".repeat(10);
+        let stored = encode(&text, true).unwrap();
+        assert!(is_compressed(&stored));
+        assert!(stored.len() < text.len());
+        assert_eq!(decode(&stored).unwrap(), text);
+    }
+
+    #[test]
+    fn short_text_is_not_compressed() {
+        let stored = encode("short", true).unwrap();
+        assert_eq!(stored, b"short");
+        assert!(stored_as_configured(&stored, "short", true));
+    }
+
+    #[test]
+    fn invalid_utf8_is_an_error() {
+        let error = decode(&[0xff, 0xfe, 0x00]).unwrap_err();
+        assert_eq!(error.kind(), io::ErrorKind::InvalidData);
+    }
+
+    #[test]
+    fn corrupted_frame_is_an_error() {
+        let mut stored = compress(&"x".repeat(1000)).unwrap();
+        stored.truncate(ZSTD_MAGIC.len() + 2);
+        assert!(decode(&stored).is_err());
+    }
+
+    #[test]
+    fn values_in_the_other_form_are_rewritten() {
+        let text = "a".repeat(MIN_LEN);
+        let plain = encode(&text, false).unwrap();
+        let compressed = encode(&text, true).unwrap();
+        assert!(!stored_as_configured(&plain, &text, true));
+        assert!(!stored_as_configured(&compressed, &text, false));
+        assert!(stored_as_configured(&compressed, &text, true));
+    }
+}
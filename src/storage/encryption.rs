@@ -0,0 +1,133 @@
+use crate::secrets;
+use argon2::Argon2;
+use chacha20poly1305::aead::rand_core::RngCore;
+use chacha20poly1305::aead::{Aead, AeadCore, KeyInit, OsRng};
+use chacha20poly1305::{XChaCha20Poly1305, XNonce};
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::io;
+use std::path::Path;
+use std::sync::{Arc, Mutex};
+
+/// Every encrypted value starts with these bytes. 0xA1 never starts valid UTF-8 and zstd
+/// frames start differently, so plain, compressed and encrypted values can be told apart.
+const MAGIC: [u8; 4] = [0xA1, 0xC3, 0x5E, 0x01];
+const NONCE_LEN: usize = 24;
+/// Name of the passphrase in the OS keyring and the environment.
+pub const KEY_NAME: &str = "ALYA_ENCRYPTION_KEY";
+/// Salt of the key derivation, and a value to recognise a wrong passphrase by.
+const PARAMS_FILE: &str = "encryption.json";
+const CHECK_TEXT: &[u8] = b"alya-chatbot";
+
+#[derive(Serialize, Deserialize)]
+struct Params {
+    salt: String,
+    check: String,
+}
+
+/// XChaCha20-Poly1305 with a key derived from the passphrase by Argon2id. Each value gets
+/// a random nonce, stored in front of the ciphertext.
+pub struct Cipher {
+    aead: XChaCha20Poly1305,
+}
+
+impl Cipher {
+    fn derive(passphrase: &str, salt: &[u8]) -> Result<Self, Box<dyn std::error::Error>> {
+        let mut key = [0u8; 32];
+        Argon2::default()
+            .hash_password_into(passphrase.as_bytes(), salt, &mut key)
+            .map_err(|e| format!("Could not derive the encryption key: {}", e))?;
+        Ok(Cipher {
+            aead: XChaCha20Poly1305::new(&key.into()),
+        })
+    }
+
+    pub fn encrypt(&self, plaintext: &[u8]) -> io::Result<Vec<u8>> {
+        let nonce = XChaCha20Poly1305::generate_nonce(&mut OsRng);
+        let ciphertext = self
+            .aead
+            .encrypt(&nonce, plaintext)
+            .map_err(|_| invalid_data("Encryption failed"))?;
+        let mut sealed = Vec::with_capacity(MAGIC.len() + NONCE_LEN + ciphertext.len());
+        sealed.extend_from_slice(&MAGIC);
+        sealed.extend_from_slice(&nonce);
+        sealed.extend_from_slice(&ciphertext);
+        Ok(sealed)
+    }
+
+    pub fn decrypt(&self, bytes: &[u8]) -> io::Result<Vec<u8>> {
+        let sealed = bytes
+            .strip_prefix(&MAGIC)
+            .filter(|sealed| sealed.len() >= NONCE_LEN)
+            .ok_or_else(|| invalid_data("Not an encrypted value"))?;
+        let (nonce, ciphertext) = sealed.split_at(NONCE_LEN);
+        self.aead
+            .decrypt(XNonce::from_slice(nonce), ciphertext)
+            .map_err(|_| invalid_data("Could not decrypt stored data, was it written with another key?"))
+    }
+}
+
+pub fn is_encrypted(bytes: &[u8]) -> bool {
+    bytes.starts_with(&MAGIC)
+}
+
+fn invalid_data(message: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, message.to_string())
+}
+
+fn to_hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|byte| format!("{:02x}", byte)).collect()
+}
+
+fn from_hex(hex: &str) -> Result<Vec<u8>, std::num::ParseIntError> {
+    (0..hex.len())
+        .step_by(2)
+        .map(|i| u8::from_str_radix(hex.get(i..i + 2).unwrap_or("?"), 16))
+        .collect()
+}
+
+/// Deriving the key is slow on purpose, so it only happens once per run.
+static CIPHER: Mutex<Option<Arc<Cipher>>> = Mutex::new(None);
+
+/// The cipher for the data in `data_dir`, keyed with the passphrase from the OS keyring or
+/// `ALYA_ENCRYPTION_KEY`. The salt is created on first use.
+pub fn open(data_dir: &Path) -> Result<Arc<Cipher>, Box<dyn std::error::Error>> {
+    let mut cached = CIPHER.lock().unwrap();
+    if let Some(cipher) = cached.as_ref() {
+        return Ok(Arc::clone(cipher));
+    }
+    let passphrase = secrets::get(KEY_NAME)
+        .ok_or("There is no encryption key; run `chatbot encryption init` or set ALYA_ENCRYPTION_KEY")?;
+
+    let path = data_dir.join(PARAMS_FILE);
+    let cipher = if path.exists() {
+        let params: Params = serde_json::from_str(&fs::read_to_string(&path)?)?;
+        let cipher = Cipher::derive(&passphrase, &from_hex(&params.salt)?)?;
+        if cipher.decrypt(&from_hex(&params.check)?).ok().as_deref() != Some(CHECK_TEXT) {
+            return Err("The encryption key is not the one the data was encrypted with".into());
+        }
+        cipher
+    } else {
+        let mut salt = [0u8; 16];
+        OsRng.fill_bytes(&mut salt);
+        let cipher = Cipher::derive(&passphrase, &salt)?;
+        let params = Params {
+            salt: to_hex(&salt),
+            check: to_hex(&cipher.encrypt(CHECK_TEXT)?),
+        };
+        fs::create_dir_all(data_dir)?;
+        fs::write(path, serde_json::to_string_pretty(&params)?)?;
+        cipher
+    };
+
+    let cipher = Arc::new(cipher);
+    *cached = Some(Arc::clone(&cipher));
+    Ok(cipher)
+}
+
+/// A random passphrase for `encryption init`.
+pub fn generate_key() -> String {
+    let mut key = [0u8; 32];
+    OsRng.fill_bytes(&mut key);
+    to_hex(&key)
+}
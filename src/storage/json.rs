@@ -0,0 +1,206 @@
+use super::{compression, Codec, KnowledgeStore};
+use crate::knowledge::{Fact, Knowledge};
+use async_trait::async_trait;
+use std::collections::HashMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+
+/// Stores everything as pretty-printed JSON files in the data directory. With compression
+/// on, the knowledge file is written zstd-compressed as `learned_knowledge.json.zst`; with
+/// encryption on, both files are encrypted and get an `.enc` extension.
+pub struct JsonStore {
+    data_dir: PathBuf,
+    codec: Codec,
+    /// Facts saved, or deleted as `None`, since the knowledge file was last written. The
+    /// file can only be rewritten whole, so they are written with the next `save`, or when
+    /// the store is dropped, rather than one at a time.
+    pending: Mutex<HashMap<String, Option<Fact>>>,
+}
+
+impl JsonStore {
+    pub fn new(data_dir: &Path, codec: Codec) -> Self {
+        JsonStore {
+            data_dir: data_dir.to_path_buf(),
+            codec,
+            pending: Mutex::new(HashMap::new()),
+        }
+    }
+
+    /// The knowledge file in the configured form, followed by the ones in other forms.
+    fn knowledge_paths(&self) -> [PathBuf; 3] {
+        let [plain, compressed, encrypted] =
+            ["learned_knowledge.json", "learned_knowledge.json.zst", "learned_knowledge.json.enc"]
+                .map(|name| self.data_dir.join(name));
+        match (&self.codec.cipher, self.codec.compress) {
+            (Some(_), _) => [encrypted, compressed, plain],
+            (None, true) => [compressed, plain, encrypted],
+            (None, false) => [plain, compressed, encrypted],
+        }
+    }
+
+    /// The knowledge file as written, in whichever form it is in.
+    fn read_knowledge(&self) -> Result<Option<Knowledge>, Box<dyn std::error::Error>> {
+        // Any form is read, so switching compression or encryption needs no conversion step
+        let Some(path) = self.knowledge_paths().into_iter().find(|path| path.exists()) else {
+            return Ok(None);
+        };
+        let knowledge_str = compression::decode(&self.codec.unseal(&fs::read(path)?)?)?;
+        Ok(Some(Knowledge::from_json(serde_json::from_str(&knowledge_str)?)?))
+    }
+
+    /// The knowledge file with the pending facts applied.
+    fn current(&self) -> Result<Option<Knowledge>, Box<dyn std::error::Error>> {
+        let mut knowledge = self.read_knowledge()?;
+        for (key, fact) in self.pending.lock().unwrap().iter() {
+            match fact {
+                Some(fact) => {
+                    knowledge.get_or_insert_with(Knowledge::default).facts.insert(key.clone(), fact.clone());
+                }
+                None => {
+                    if let Some(knowledge) = knowledge.as_mut() {
+                        knowledge.facts.remove(key);
+                    }
+                }
+            }
+        }
+        Ok(knowledge)
+    }
+
+    fn write_knowledge(&self, knowledge: &Knowledge) -> Result<(), Box<dyn std::error::Error>> {
+        let knowledge_str = serde_json::to_string_pretty(knowledge)?;
+        let encoded = if self.codec.compress {
+            compression::compress(&knowledge_str)?
+        } else {
+            knowledge_str.into_bytes()
+        };
+        write_file(&self.knowledge_paths(), self.codec.seal(encoded)?)?;
+        self.pending.lock().unwrap().clear();
+        Ok(())
+    }
+
+    /// The sessions file in the configured form, and the one in the other form.
+    fn sessions_paths(&self) -> [PathBuf; 2] {
+        let [plain, encrypted] = ["sessions.json", "sessions.json.enc"].map(|name| self.data_dir.join(name));
+        match self.codec.cipher {
+            Some(_) => [encrypted, plain],
+            None => [plain, encrypted],
+        }
+    }
+
+    /// All stored sessions by id.
+    pub fn sessions(&self) -> Result<HashMap<String, Vec<String>>, Box<dyn std::error::Error>> {
+        let Some(path) = self.sessions_paths().into_iter().find(|path| path.exists()) else {
+            return Ok(HashMap::new());
+        };
+        let sessions_str = String::from_utf8(self.codec.unseal(&fs::read(path)?)?)?;
+        Ok(serde_json::from_str(&sessions_str)?)
+    }
+}
+
+/// Writes the first of `paths` and removes the others, which hold the same data in another form.
+fn write_file(paths: &[PathBuf], contents: impl AsRef<[u8]>) -> Result<(), Box<dyn std::error::Error>> {
+    let (path, others) = paths.split_first().ok_or("No path to write to")?;
+    // Ensure the data directory exists
+    if let Some(parent) = path.parent() {
+        fs::create_dir_all(parent)?;
+    }
+    fs::write(path, contents)?;
+    for other in others.iter().filter(|other| other.exists()) {
+        fs::remove_file(other)?;
+    }
+    Ok(())
+}
+
+impl Drop for JsonStore {
+    /// Writes the facts saved since the last `save`, so none are lost on exit.
+    fn drop(&mut self) {
+        if self.pending.get_mut().unwrap().is_empty() {
+            return;
+        }
+        let written = self.current().and_then(|knowledge| match knowledge {
+            Some(knowledge) => self.write_knowledge(&knowledge),
+            None => Ok(()),
+        });
+        if let Err(e) = written {
+            status!("Error writing learned facts: {}", e);
+        }
+    }
+}
+
+#[async_trait]
+impl KnowledgeStore for JsonStore {
+    async fn load(&self) -> Result<Option<Knowledge>, Box<dyn std::error::Error>> {
+        self.current()
+    }
+
+    /// Writes `knowledge` as it is; pending facts it does not have are dropped, as with any
+    /// save replacing what is stored.
+    async fn save(&self, knowledge: &Knowledge) -> Result<(), Box<dyn std::error::Error>> {
+        self.write_knowledge(knowledge)
+    }
+
+    async fn save_fact(&self, key: &str, fact: &Fact) -> Result<(), Box<dyn std::error::Error>> {
+        self.pending.lock().unwrap().insert(key.to_string(), Some(fact.clone()));
+        Ok(())
+    }
+
+    async fn delete_fact(&self, key: &str) -> Result<(), Box<dyn std::error::Error>> {
+        self.pending.lock().unwrap().insert(key.to_string(), None);
+        Ok(())
+    }
+
+    async fn iterate(&self, visit: &mut (dyn for<'f> FnMut(&'f str, &'f Fact) + Send)) -> Result<(), Box<dyn std::error::Error>> {
+        if let Some(knowledge) = self.load().await? {
+            for (key, fact) in &knowledge.facts {
+                visit(key, fact);
+            }
+        }
+        Ok(())
+    }
+
+    async fn load_session(&self, session_id: &str) -> Result<Vec<String>, Box<dyn std::error::Error>> {
+        Ok(self.sessions()?.remove(session_id).unwrap_or_default())
+    }
+
+    async fn save_session(&self, session_id: &str, history: &[String]) -> Result<(), Box<dyn std::error::Error>> {
+        let mut sessions = self.sessions()?;
+        sessions.insert(session_id.to_string(), history.to_vec());
+        let sessions_str = serde_json::to_string_pretty(&sessions)?;
+        write_file(&self.sessions_paths(), self.codec.seal(sessions_str.into_bytes())?)
+    }
+
+    async fn list_sessions(&self) -> Result<Vec<String>, Box<dyn std::error::Error>> {
+        Ok(self.sessions()?.into_keys().collect())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::knowledge::LearnMethod;
+
+    #[tokio::test]
+    async fn facts_are_written_with_the_next_save_or_on_drop() {
+        let dir = std::env::temp_dir().join(format!("alya-json-{}", std::process::id()));
+        let fact = |text: &str| Fact::new(text.to_string(), None, LearnMethod::Training, Vec::new());
+        let path = dir.join("learned_knowledge.json");
+        {
+            let store = JsonStore::new(&dir, Codec::default());
+            let mut knowledge = Knowledge::default();
+            knowledge.facts.insert("old".to_string(), fact("Alya is proud"));
+            store.save(&knowledge).await.unwrap();
+
+            store.save_fact("new", &fact("Alya plays the piano")).await.unwrap();
+            store.delete_fact("old").await.unwrap();
+            let written = fs::read_to_string(&path).unwrap();
+            assert!(written.contains("Alya is proud") && !written.contains("Alya plays the piano"));
+            let loaded = store.load().await.unwrap().unwrap();
+            assert_eq!(loaded.facts.keys().collect::<Vec<_>>(), ["new"]);
+        }
+
+        let loaded = JsonStore::new(&dir, Codec::default()).load().await.unwrap().unwrap();
+        fs::remove_dir_all(&dir).unwrap();
+        assert_eq!(loaded.facts.keys().collect::<Vec<_>>(), ["new"]);
+    }
+}
@@ -0,0 +1,59 @@
+use super::KnowledgeStore;
+use crate::knowledge::{Fact, Knowledge};
+use async_trait::async_trait;
+use std::collections::HashMap;
+use std::sync::Mutex;
+
+/// Store that only lives as long as the process.
+#[derive(Default)]
+pub struct MemoryStore {
+    knowledge: Mutex<Option<Knowledge>>,
+    sessions: Mutex<HashMap<String, Vec<String>>>,
+}
+
+#[async_trait]
+impl KnowledgeStore for MemoryStore {
+    async fn load(&self) -> Result<Option<Knowledge>, Box<dyn std::error::Error>> {
+        Ok(self.knowledge.lock().unwrap().clone())
+    }
+
+    async fn save(&self, knowledge: &Knowledge) -> Result<(), Box<dyn std::error::Error>> {
+        *self.knowledge.lock().unwrap() = Some(knowledge.clone());
+        Ok(())
+    }
+
+    async fn save_fact(&self, key: &str, fact: &Fact) -> Result<(), Box<dyn std::error::Error>> {
+        let mut knowledge = self.knowledge.lock().unwrap();
+        knowledge.get_or_insert_with(Knowledge::default).facts.insert(key.to_string(), fact.clone());
+        Ok(())
+    }
+
+    async fn delete_fact(&self, key: &str) -> Result<(), Box<dyn std::error::Error>> {
+        if let Some(knowledge) = self.knowledge.lock().unwrap().as_mut() {
+            knowledge.facts.remove(key);
+        }
+        Ok(())
+    }
+
+    async fn iterate(&self, visit: &mut (dyn for<'f> FnMut(&'f str, &'f Fact) + Send)) -> Result<(), Box<dyn std::error::Error>> {
+        if let Some(knowledge) = self.knowledge.lock().unwrap().as_ref() {
+            for (key, fact) in &knowledge.facts {
+                visit(key, fact);
+            }
+        }
+        Ok(())
+    }
+
+    async fn load_session(&self, session_id: &str) -> Result<Vec<String>, Box<dyn std::error::Error>> {
+        Ok(self.sessions.lock().unwrap().get(session_id).cloned().unwrap_or_default())
+    }
+
+    async fn save_session(&self, session_id: &str, history: &[String]) -> Result<(), Box<dyn std::error::Error>> {
+        self.sessions.lock().unwrap().insert(session_id.to_string(), history.to_vec());
+        Ok(())
+    }
+
+    async fn list_sessions(&self) -> Result<Vec<String>, Box<dyn std::error::Error>> {
+        Ok(self.sessions.lock().unwrap().keys().cloned().collect())
+    }
+}
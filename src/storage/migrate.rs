@@ -0,0 +1,101 @@
+use super::{Codec, JsonStore, KnowledgeStore};
+use crate::knowledge::Knowledge;
+use std::collections::HashMap;
+use std::fmt;
+use std::path::Path;
+
+/// What a migration or archive import added, or would add on a dry run.
+#[derive(Debug, Default)]
+pub struct ImportReport {
+    pub dry_run: bool,
+    pub facts_added: usize,
+    /// Facts whose key already exists in the target; the target's version is kept.
+    pub facts_present: usize,
+    /// Facts left out because the target already holds the same content under another key.
+    pub facts_duplicate: usize,
+    pub urls_added: usize,
+    pub searches_added: usize,
+    pub contradictions_added: usize,
+    pub sessions_added: usize,
+}
+
+impl fmt::Display for ImportReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let verb = if self.dry_run { "Would import" } else { "Imported" };
+        writeln!(f, "{} {} fact(s)", verb, self.facts_added)?;
+        writeln!(f, "  {} already present, {} duplicate(s) skipped", self.facts_present, self.facts_duplicate)?;
+        writeln!(f, "{} {} learned URL(s)", verb, self.urls_added)?;
+        writeln!(f, "{} {} past search(es)", verb, self.searches_added)?;
+        writeln!(f, "{} {} contradiction(s)", verb, self.contradictions_added)?;
+        write!(f, "{} {} session(s)", verb, self.sessions_added)
+    }
+}
+
+/// Copies the JSON files in `data_dir`, written with `codec`, into `target`. Entries the
+/// target already has are left alone, so running it again imports nothing new.
+pub async fn migrate_from_json(
+    data_dir: &Path,
+    codec: Codec,
+    target: &dyn KnowledgeStore,
+    dry_run: bool,
+) -> Result<ImportReport, Box<dyn std::error::Error>> {
+    let source = JsonStore::new(data_dir, codec);
+    import_into(target, source.load().await?, source.sessions()?, dry_run).await
+}
+
+/// Adds knowledge and sessions from elsewhere to `target`, keeping whatever the target
+/// already has for a fact key or session id.
+pub async fn import_into(
+    target: &dyn KnowledgeStore,
+    knowledge: Option<Knowledge>,
+    sessions: HashMap<String, Vec<String>>,
+    dry_run: bool,
+) -> Result<ImportReport, Box<dyn std::error::Error>> {
+    let mut report = ImportReport {
+        dry_run,
+        ..ImportReport::default()
+    };
+
+    let existing = target.load().await?.unwrap_or_default();
+    if let Some(mut incoming_knowledge) = knowledge {
+        let incoming = incoming_knowledge.facts.len();
+        incoming_knowledge.facts.retain(|key, _| !existing.facts.contains_key(key));
+        report.facts_present = incoming - incoming_knowledge.facts.len();
+
+        let mut merged = existing.clone();
+        merged.merge(incoming_knowledge);
+        report.facts_added = merged.facts.len() - existing.facts.len();
+        report.facts_duplicate = incoming - report.facts_present - report.facts_added;
+        report.urls_added = merged.learned_urls.len() - existing.learned_urls.len();
+        report.searches_added = merged.search_history.len() - existing.search_history.len();
+        report.contradictions_added = merged.contradictions.len() - existing.contradictions.len();
+
+        if !dry_run && changed(&existing, &merged) {
+            target.save(&merged).await?;
+        }
+    }
+
+    for (session_id, history) in sessions {
+        if history.is_empty() || !target.load_session(&session_id).await?.is_empty() {
+            continue;
+        }
+        report.sessions_added += 1;
+        if !dry_run {
+            target.save_session(&session_id, &history).await?;
+        }
+    }
+
+    Ok(report)
+}
+
+fn changed(before: &Knowledge, after: &Knowledge) -> bool {
+    before.facts.len() != after.facts.len()
+        || before.learned_urls.len() != after.learned_urls.len()
+        || before.search_history.len() != after.search_history.len()
+        || before.contradictions.len() != after.contradictions.len()
+        || before.cached_content.len() != after.cached_content.len()
+        || before.pages != after.pages
+        || before.files != after.files
+        || before.feeds != after.feeds
+        || before.external_url_count != after.external_url_count
+}
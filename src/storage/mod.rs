@@ -0,0 +1,335 @@
+use crate::knowledge::{Fact, FactStub, Knowledge, LearnMethod, SCHEMA_VERSION};
+use crate::paths;
+use crate::vector_index::{TagFilter, VectorIndex};
+use async_trait::async_trait;
+use serde::{Deserialize, Serialize};
+use std::collections::hash_map::DefaultHasher;
+use std::hash::{Hash, Hasher};
+
+mod bench;
+mod cache;
+mod codec;
+mod compression;
+mod encryption;
+mod json;
+mod memory;
+mod migrate;
+mod redb;
+mod sqlite;
+#[cfg(feature = "postgres")]
+mod postgres;
+
+pub use self::redb::RedbStore;
+pub use bench::compare_backends;
+pub use cache::CachedStore;
+pub use codec::Codec;
+pub use encryption::{generate_key, KEY_NAME as ENCRYPTION_KEY_NAME};
+pub use json::JsonStore;
+pub use memory::MemoryStore;
+pub use migrate::{import_into, migrate_from_json};
+pub use sqlite::SqliteStore;
+#[cfg(feature = "postgres")]
+pub use postgres::PostgresStore;
+
+/// Persistence for learned knowledge and conversation sessions.
+#[async_trait]
+pub trait KnowledgeStore: Send + Sync {
+    /// Loads the stored knowledge, or `None` when nothing has been saved yet.
+    async fn load(&self) -> Result<Option<Knowledge>, Box<dyn std::error::Error>>;
+
+    /// Like `load`, but facts may come back as stubs without their text and embedding, to
+    /// be fetched with `load_fact` when needed. Backends that do not keep fact metadata
+    /// separately load everything.
+    async fn load_metadata(&self) -> Result<Option<Knowledge>, Box<dyn std::error::Error>> {
+        self.load().await
+    }
+
+    /// Replaces the stored knowledge with `knowledge`. Stubs are left as stored.
+    async fn save(&self, knowledge: &Knowledge) -> Result<(), Box<dyn std::error::Error>>;
+
+    /// Loads a single fact with its text and embedding.
+    async fn load_fact(&self, key: &str) -> Result<Option<Fact>, Box<dyn std::error::Error>> {
+        let mut found = None;
+        self.iterate(&mut |stored_key, fact| {
+            if stored_key == key {
+                found = Some(fact.clone());
+            }
+        })
+        .await?;
+        Ok(found)
+    }
+
+    /// Adds or replaces a single fact without writing the rest of the knowledge.
+    async fn save_fact(&self, key: &str, fact: &Fact) -> Result<(), Box<dyn std::error::Error>>;
+
+    async fn delete_fact(&self, key: &str) -> Result<(), Box<dyn std::error::Error>>;
+
+    /// Calls `visit` with every stored fact, in no particular order.
+    async fn iterate(&self, visit: &mut (dyn for<'f> FnMut(&'f str, &'f Fact) + Send)) -> Result<(), Box<dyn std::error::Error>>;
+
+    /// Returns the stored facts matching `query`, sorted by key.
+    async fn query(&self, query: &FactQuery) -> Result<Vec<(String, Fact)>, Box<dyn std::error::Error>> {
+        let mut facts = Vec::new();
+        self.iterate(&mut |key, fact| {
+            if query.matches(fact) {
+                facts.push((key.to_string(), fact.clone()));
+            }
+        })
+        .await?;
+        facts.sort_by(|a, b| a.0.cmp(&b.0));
+        if let Some(limit) = query.limit {
+            facts.truncate(limit);
+        }
+        Ok(facts)
+    }
+
+    /// Returns the stored conversation history of a session, oldest message first.
+    async fn load_session(&self, session_id: &str) -> Result<Vec<String>, Box<dyn std::error::Error>>;
+
+    async fn save_session(&self, session_id: &str, history: &[String]) -> Result<(), Box<dyn std::error::Error>>;
+
+    /// Ids of all stored sessions.
+    async fn list_sessions(&self) -> Result<Vec<String>, Box<dyn std::error::Error>>;
+
+    /// A vector index backed by the same storage, for backends that can search embeddings
+    /// produced by `model`.
+    fn vector_index(&self, _model: &str) -> Option<Box<dyn VectorIndex>> {
+        None
+    }
+}
+
+/// Restrictions for `KnowledgeStore::query`; the default matches every fact.
+#[derive(Debug, Clone, Default)]
+pub struct FactQuery {
+    pub tags: TagFilter,
+    /// Case-insensitive substring of the fact text.
+    pub text: Option<String>,
+    pub method: Option<LearnMethod>,
+    pub limit: Option<usize>,
+}
+
+impl FactQuery {
+    pub fn matches(&self, fact: &Fact) -> bool {
+        if !self.tags.matches(&fact.tags) {
+            return false;
+        }
+        if self.method.is_some_and(|method| method != fact.method) {
+            return false;
+        }
+        match &self.text {
+            Some(text) => fact.text.to_lowercase().contains(&text.to_lowercase()),
+            None => true,
+        }
+    }
+}
+
+/// Backends that store facts row by row record the schema version they were written with;
+/// refuse databases from a newer build instead of misreading them.
+fn check_schema_version(stored: Option<&str>) -> Result<(), Box<dyn std::error::Error>> {
+    let version: u32 = stored.map(str::parse).transpose()?.unwrap_or(SCHEMA_VERSION);
+    if version > SCHEMA_VERSION {
+        return Err(format!(
+            "The knowledge store uses schema version {}, but this build only understands up to {}; please update the chatbot",
+            version, SCHEMA_VERSION
+        )
+        .into());
+    }
+    Ok(())
+}
+
+/// A fact's stub as stored next to the full fact by backends that support `load_metadata`.
+#[derive(Serialize, Deserialize)]
+struct StoredStub {
+    fact: Fact,
+    stub: FactStub,
+}
+
+fn stub_json(fact: &Fact) -> Result<String, serde_json::Error> {
+    serde_json::to_string(&StoredStub {
+        fact: fact.to_stub(),
+        stub: fact.stub_info(),
+    })
+}
+
+fn stub_from_json(json: &str) -> Result<Fact, serde_json::Error> {
+    let stored: StoredStub = serde_json::from_str(json)?;
+    Ok(Fact {
+        stub: Some(stored.stub),
+        ..stored.fact
+    })
+}
+
+/// Cheap change detection for backends that only write rows that changed.
+fn fingerprint(value: &str) -> u64 {
+    let mut hasher = DefaultHasher::new();
+    value.hash(&mut hasher);
+    hasher.finish()
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Eq, Default)]
+#[serde(rename_all = "lowercase")]
+pub enum StorageBackend {
+    /// `data/learned_knowledge.json` and `data/sessions.json`.
+    #[default]
+    Json,
+    /// Keeps everything in memory and persists nothing; for tests and throwaway sessions.
+    Memory,
+    /// `data/knowledge.db`, written incrementally in transactions.
+    Sqlite,
+    /// `data/knowledge.redb`, an embedded key-value database.
+    Redb,
+    /// A Postgres database with the pgvector extension; requires the `postgres` feature.
+    Postgres,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct StorageSettings {
+    #[serde(default)]
+    pub backend: StorageBackend,
+    /// Connection string used by the Postgres backend, e.g.
+    /// `host=localhost user=alya password=secret dbname=alya`.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub postgres_url: Option<String>,
+    /// Knowledge snapshots kept in `data/history/` for `knowledge rollback`; 0 disables them.
+    #[serde(default = "default_history_snapshots")]
+    pub history_snapshots: usize,
+    /// zstd-compress fact data and cached pages in the json, sqlite and redb backends.
+    #[serde(default)]
+    pub compression: bool,
+    /// Encrypt the values the json, sqlite and redb backends and the knowledge history write
+    /// to `data/`, with the key from the OS keyring or `ALYA_ENCRYPTION_KEY`. The keys they
+    /// are stored under, such as fact keys, page URLs and session IDs, stay readable.
+    #[serde(default)]
+    pub encryption: bool,
+    /// Load only fact metadata on startup and fetch fact text and embeddings when they are
+    /// used; supported by the sqlite, redb and postgres backends. A knowledge snapshot then
+    /// reads every fact from the store, so one is only taken every few saves.
+    #[serde(default)]
+    pub lazy_loading: bool,
+    /// Fetched facts kept in memory when `lazy_loading` is on.
+    #[serde(default = "default_fact_cache_size")]
+    pub fact_cache_size: usize,
+}
+
+fn default_history_snapshots() -> usize {
+    20
+}
+
+fn default_fact_cache_size() -> usize {
+    256
+}
+
+impl Default for StorageSettings {
+    fn default() -> Self {
+        StorageSettings {
+            backend: StorageBackend::default(),
+            postgres_url: None,
+            history_snapshots: default_history_snapshots(),
+            compression: false,
+            encryption: false,
+            lazy_loading: false,
+            fact_cache_size: default_fact_cache_size(),
+        }
+    }
+}
+
+/// How the local backends write values under the configured compression and encryption.
+pub fn create_codec(settings: &StorageSettings) -> Result<Codec, Box<dyn std::error::Error>> {
+    let cipher = match settings.encryption {
+        true => Some(encryption::open(paths::data_dir())?),
+        false => None,
+    };
+    Ok(Codec::new(settings.compression, cipher))
+}
+
+/// A codec that always encrypts, for data such as login cookies that is never stored in
+/// the clear, whatever `storage.encryption` says.
+pub fn secret_codec() -> Result<Codec, Box<dyn std::error::Error>> {
+    Ok(Codec::new(false, Some(encryption::open(paths::data_dir())?)))
+}
+
+/// Opens the configured store. Knowledge is scoped to `character` on shared backends.
+pub async fn create_store(
+    settings: &StorageSettings,
+    character: &str,
+) -> Result<Box<dyn KnowledgeStore>, Box<dyn std::error::Error>> {
+    match settings.backend {
+        StorageBackend::Json => Ok(Box::new(JsonStore::new(paths::data_dir(), create_codec(settings)?))),
+        StorageBackend::Memory => Ok(Box::new(MemoryStore::default())),
+        StorageBackend::Sqlite => Ok(Box::new(SqliteStore::open(&paths::data_path("knowledge.db"), create_codec(settings)?)?)),
+        StorageBackend::Redb => Ok(Box::new(RedbStore::open(&paths::data_path("knowledge.redb"), create_codec(settings)?)?)),
+        StorageBackend::Postgres if settings.encryption => {
+            Err("storage.encryption is not supported by the postgres backend; use the database's own encryption at rest".into())
+        }
+        #[cfg(feature = "postgres")]
+        StorageBackend::Postgres => {
+            let url = settings
+                .postgres_url
+                .as_deref()
+                .ok_or("storage.postgres_url must be set for the postgres backend")?;
+            Ok(Box::new(PostgresStore::connect(url, character).await?))
+        }
+        #[cfg(not(feature = "postgres"))]
+        StorageBackend::Postgres => {
+            let _ = character;
+            Err("The postgres storage backend needs a build with `--features postgres`".into())
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::knowledge::FactTag;
+
+    async fn store_with(facts: &[(&str, &str, LearnMethod, Vec<FactTag>)]) -> MemoryStore {
+        let store = MemoryStore::default();
+        for (key, text, method, tags) in facts {
+            store.save_fact(key, &Fact::new(text.to_string(), None, *method, tags.clone())).await.unwrap();
+        }
+        store
+    }
+
+    #[tokio::test]
+    async fn load_fact_finds_the_fact_by_key() {
+        let store = store_with(&[("a", "Alya is proud", LearnMethod::Training, vec![FactTag::Personality])]).await;
+        assert_eq!(store.load_fact("a").await.unwrap().unwrap().text, "Alya is proud");
+        assert!(store.load_fact("b").await.unwrap().is_none());
+        store.delete_fact("a").await.unwrap();
+        assert!(store.load_fact("a").await.unwrap().is_none());
+    }
+
+    #[tokio::test]
+    async fn query_filters_sorts_and_limits() {
+        let store = store_with(&[
+            ("c", "Alya plays the piano", LearnMethod::Url, vec![FactTag::Background]),
+            ("a", "Alya is proud", LearnMethod::Training, vec![FactTag::Personality]),
+            ("b", "Alya is Masha's sister", LearnMethod::Training, vec![FactTag::Relationships, FactTag::Personality]),
+        ])
+        .await;
+        let keys = |facts: Vec<(String, Fact)>| facts.into_iter().map(|(key, _)| key).collect::<Vec<_>>();
+
+        assert_eq!(keys(store.query(&FactQuery::default()).await.unwrap()), ["a", "b", "c"]);
+        let query = FactQuery { limit: Some(2), ..Default::default() };
+        assert_eq!(keys(store.query(&query).await.unwrap()), ["a", "b"]);
+        let query = FactQuery { text: Some("PIANO".to_string()), ..Default::default() };
+        assert_eq!(keys(store.query(&query).await.unwrap()), ["c"]);
+        let query = FactQuery { method: Some(LearnMethod::Training), ..Default::default() };
+        assert_eq!(keys(store.query(&query).await.unwrap()), ["a", "b"]);
+        let tags = TagFilter { include: vec![FactTag::Personality], exclude: vec![FactTag::Relationships] };
+        let query = FactQuery { tags, ..Default::default() };
+        assert_eq!(keys(store.query(&query).await.unwrap()), ["a"]);
+    }
+
+    #[tokio::test]
+    async fn sessions_are_kept_apart() {
+        let store = MemoryStore::default();
+        store.save_session("one", &["hello".to_string()]).await.unwrap();
+        store.save_session("two", &[]).await.unwrap();
+        assert_eq!(store.load_session("one").await.unwrap(), ["hello"]);
+        assert!(store.load_session("three").await.unwrap().is_empty());
+        let mut sessions = store.list_sessions().await.unwrap();
+        sessions.sort();
+        assert_eq!(sessions, ["one", "two"]);
+    }
+}
@@ -0,0 +1,421 @@
+use super::KnowledgeStore;
+use crate::knowledge::{Fact, FactTag, Knowledge, LearnMethod};
+use crate::vector_index::{TagFilter, VectorIndex};
+use async_trait::async_trait;
+use chrono::{DateTime, Utc};
+use serde_json::Value;
+use std::collections::HashSet;
+use std::sync::Arc;
+use tokio::sync::Mutex;
+use tokio_postgres::{Client, GenericClient, Row, Statement};
+
+const SCHEMA: &str = "
+    CREATE EXTENSION IF NOT EXISTS vector;
+    CREATE TABLE IF NOT EXISTS facts (
+        character TEXT NOT NULL,
+        key TEXT NOT NULL,
+        text TEXT NOT NULL,
+        source_url TEXT,
+        learned_at TIMESTAMPTZ NOT NULL,
+        method TEXT NOT NULL,
+        tags TEXT[] NOT NULL DEFAULT '{}',
+        confidence DOUBLE PRECISION NOT NULL,
+        verified_at TIMESTAMPTZ NOT NULL,
+        embedding_model TEXT,
+        embedding vector,
+        keywords TEXT[] NOT NULL DEFAULT '{}',
+        user_id TEXT,
+        PRIMARY KEY (character, key)
+    );
+    ALTER TABLE facts ADD COLUMN IF NOT EXISTS keywords TEXT[] NOT NULL DEFAULT '{}';
+    ALTER TABLE facts ADD COLUMN IF NOT EXISTS user_id TEXT;
+    CREATE TABLE IF NOT EXISTS knowledge_meta (
+        character TEXT PRIMARY KEY,
+        data JSONB NOT NULL
+    );
+    CREATE TABLE IF NOT EXISTS sessions (
+        character TEXT NOT NULL,
+        session_id TEXT NOT NULL,
+        position INTEGER NOT NULL,
+        message TEXT NOT NULL,
+        PRIMARY KEY (character, session_id, position)
+    );
+";
+
+const FACT_COLUMNS: &str = "key, text, source_url, learned_at, method, tags, confidence, verified_at, \
+    embedding_model, embedding::text, keywords, user_id";
+
+/// `FACT_COLUMNS` without the embedding, for `load_metadata`.
+const STUB_COLUMNS: &str = "key, text, source_url, learned_at, method, tags, confidence, verified_at, \
+    embedding_model, NULL::text, keywords, user_id";
+
+const UPSERT_FACT: &str = "
+    INSERT INTO facts (character, key, text, source_url, learned_at, method, tags, confidence,
+                       verified_at, embedding_model, embedding, keywords, user_id)
+    VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9, $10, $11::text::vector, $12, $13)
+    ON CONFLICT (character, key) DO UPDATE SET
+        text = EXCLUDED.text, source_url = EXCLUDED.source_url, learned_at = EXCLUDED.learned_at,
+        method = EXCLUDED.method, tags = EXCLUDED.tags, confidence = EXCLUDED.confidence,
+        verified_at = EXCLUDED.verified_at, embedding_model = EXCLUDED.embedding_model,
+        embedding = EXCLUDED.embedding, keywords = EXCLUDED.keywords, user_id = EXCLUDED.user_id
+";
+
+/// Knowledge store in Postgres, shareable between several bot instances. Facts live in
+/// their own table with pgvector embeddings; everything else is kept as JSONB.
+pub struct PostgresStore {
+    client: Arc<Mutex<Client>>,
+    character: String,
+    /// The facts this instance has loaded or written. A save deletes only those of them
+    /// that are gone from the knowledge, so facts other instances added meanwhile stay.
+    known: Mutex<HashSet<String>>,
+}
+
+impl PostgresStore {
+    pub async fn connect(url: &str, character: &str) -> Result<Self, Box<dyn std::error::Error>> {
+        let connector = postgres_native_tls::MakeTlsConnector::new(native_tls::TlsConnector::new()?);
+        let (client, connection) = tokio_postgres::connect(url, connector).await?;
+        tokio::spawn(async move {
+            if let Err(e) = connection.await {
+                status!("Postgres connection error: {}", e);
+            }
+        });
+
+        client.batch_execute(SCHEMA).await?;
+        Ok(PostgresStore {
+            client: Arc::new(Mutex::new(client)),
+            character: character.to_string(),
+            known: Mutex::new(HashSet::new()),
+        })
+    }
+}
+
+fn method_name(method: LearnMethod) -> String {
+    serde_json::to_value(method)
+        .ok()
+        .and_then(|value| value.as_str().map(str::to_string))
+        .unwrap_or_default()
+}
+
+fn tag_names(tags: &[FactTag]) -> Vec<String> {
+    tags.iter().map(|tag| tag.as_str().to_string()).collect()
+}
+
+/// pgvector accepts and prints vectors as `[1,2,3]`.
+fn vector_literal(vector: &[f32]) -> String {
+    let values: Vec<String> = vector.iter().map(|v| v.to_string()).collect();
+    format!("[{}]", values.join(","))
+}
+
+fn parse_vector(literal: &str) -> Option<Vec<f32>> {
+    literal
+        .trim()
+        .trim_start_matches('[')
+        .trim_end_matches(']')
+        .split(',')
+        .map(|v| v.trim().parse().ok())
+        .collect()
+}
+
+/// Reads a row selected with `FACT_COLUMNS`.
+fn fact_from_row(row: &Row) -> (String, Fact) {
+    let method: String = row.get(4);
+    let tags: Vec<String> = row.get(5);
+    let embedding: Option<String> = row.get(9);
+    let fact = Fact {
+        text: row.get(1),
+        source_url: row.get(2),
+        learned_at: row.get::<_, DateTime<Utc>>(3),
+        method: serde_json::from_value(Value::String(method)).unwrap_or(LearnMethod::Unknown),
+        tags: tags.iter().filter_map(|tag| FactTag::parse(tag)).collect(),
+        keywords: row.get(10),
+        confidence: row.get(6),
+        verified_at: row.get::<_, DateTime<Utc>>(7),
+        embedding_model: row.get(8),
+        embedding: embedding.as_deref().and_then(parse_vector),
+        user: row.get(11),
+        stub: None,
+    };
+    (row.get(0), fact)
+}
+
+async fn upsert_fact<C: GenericClient>(
+    client: &C,
+    statement: &Statement,
+    character: &str,
+    key: &str,
+    fact: &Fact,
+) -> Result<(), tokio_postgres::Error> {
+    let embedding = fact.embedding.as_deref().map(vector_literal);
+    client
+        .execute(
+            statement,
+            &[
+                &character,
+                &key,
+                &fact.text,
+                &fact.source_url,
+                &fact.learned_at,
+                &method_name(fact.method),
+                &tag_names(&fact.tags),
+                &fact.confidence,
+                &fact.verified_at,
+                &fact.embedding_model,
+                &embedding,
+                &fact.keywords,
+                &fact.user,
+            ],
+        )
+        .await?;
+    Ok(())
+}
+
+#[async_trait]
+impl KnowledgeStore for PostgresStore {
+    async fn load(&self) -> Result<Option<Knowledge>, Box<dyn std::error::Error>> {
+        let client = self.client.lock().await;
+        let Some(meta) = client
+            .query_opt("SELECT data FROM knowledge_meta WHERE character = $1", &[&self.character])
+            .await?
+        else {
+            return Ok(None);
+        };
+        let mut knowledge = Knowledge::from_json(meta.get::<_, Value>(0))?;
+
+        let rows = client
+            .query(
+                &format!("SELECT {} FROM facts WHERE character = $1", FACT_COLUMNS),
+                &[&self.character],
+            )
+            .await?;
+        knowledge.facts.extend(rows.iter().map(fact_from_row));
+        *self.known.lock().await = knowledge.facts.keys().cloned().collect();
+        Ok(Some(knowledge))
+    }
+
+    async fn save(&self, knowledge: &Knowledge) -> Result<(), Box<dyn std::error::Error>> {
+        let mut meta = knowledge.clone();
+        meta.facts.clear();
+        let meta = serde_json::to_value(&meta)?;
+
+        let mut client = self.client.lock().await;
+        let transaction = client.transaction().await?;
+        transaction
+            .execute(
+                "INSERT INTO knowledge_meta (character, data) VALUES ($1, $2)
+                 ON CONFLICT (character) DO UPDATE SET data = EXCLUDED.data",
+                &[&self.character, &meta],
+            )
+            .await?;
+
+        let upsert = transaction.prepare(UPSERT_FACT).await?;
+        for (key, fact) in knowledge.facts.iter().filter(|(_, fact)| !fact.is_stub()) {
+            upsert_fact(&transaction, &upsert, &self.character, key, fact).await?;
+        }
+
+        let mut known = self.known.lock().await;
+        let deleted: Vec<&String> = known.iter().filter(|key| !knowledge.facts.contains_key(*key)).collect();
+        transaction
+            .execute(
+                "DELETE FROM facts WHERE character = $1 AND key = ANY($2)",
+                &[&self.character, &deleted],
+            )
+            .await?;
+        transaction.commit().await?;
+        *known = knowledge.facts.keys().cloned().collect();
+        Ok(())
+    }
+
+    /// Leaves the embeddings in the database; the text is still read to hash it.
+    async fn load_metadata(&self) -> Result<Option<Knowledge>, Box<dyn std::error::Error>> {
+        let client = self.client.lock().await;
+        let Some(meta) = client
+            .query_opt("SELECT data FROM knowledge_meta WHERE character = $1", &[&self.character])
+            .await?
+        else {
+            return Ok(None);
+        };
+        let mut knowledge = Knowledge::from_json(meta.get::<_, Value>(0))?;
+
+        let rows = client
+            .query(
+                &format!("SELECT {}, embedding IS NOT NULL FROM facts WHERE character = $1", STUB_COLUMNS),
+                &[&self.character],
+            )
+            .await?;
+        for row in &rows {
+            let (key, fact) = fact_from_row(row);
+            let mut stub = fact.to_stub();
+            if let Some(info) = stub.stub.as_mut() {
+                info.embedded = row.get(10);
+            }
+            knowledge.facts.insert(key, stub);
+        }
+        *self.known.lock().await = knowledge.facts.keys().cloned().collect();
+        Ok(Some(knowledge))
+    }
+
+    async fn load_fact(&self, key: &str) -> Result<Option<Fact>, Box<dyn std::error::Error>> {
+        let client = self.client.lock().await;
+        let row = client
+            .query_opt(
+                &format!("SELECT {} FROM facts WHERE character = $1 AND key = $2", FACT_COLUMNS),
+                &[&self.character, &key],
+            )
+            .await?;
+        Ok(row.map(|row| fact_from_row(&row).1))
+    }
+
+    async fn save_fact(&self, key: &str, fact: &Fact) -> Result<(), Box<dyn std::error::Error>> {
+        let client = self.client.lock().await;
+        let upsert = client.prepare(UPSERT_FACT).await?;
+        upsert_fact(&*client, &upsert, &self.character, key, fact).await?;
+        self.known.lock().await.insert(key.to_string());
+        Ok(())
+    }
+
+    async fn delete_fact(&self, key: &str) -> Result<(), Box<dyn std::error::Error>> {
+        let client = self.client.lock().await;
+        client
+            .execute("DELETE FROM facts WHERE character = $1 AND key = $2", &[&self.character, &key])
+            .await?;
+        self.known.lock().await.remove(key);
+        Ok(())
+    }
+
+    async fn iterate(&self, visit: &mut (dyn for<'f> FnMut(&'f str, &'f Fact) + Send)) -> Result<(), Box<dyn std::error::Error>> {
+        let client = self.client.lock().await;
+        let rows = client
+            .query(
+                &format!("SELECT {} FROM facts WHERE character = $1", FACT_COLUMNS),
+                &[&self.character],
+            )
+            .await?;
+        for row in &rows {
+            let (key, fact) = fact_from_row(row);
+            visit(&key, &fact);
+        }
+        Ok(())
+    }
+
+    async fn load_session(&self, session_id: &str) -> Result<Vec<String>, Box<dyn std::error::Error>> {
+        let client = self.client.lock().await;
+        let rows = client
+            .query(
+                "SELECT message FROM sessions WHERE character = $1 AND session_id = $2 ORDER BY position",
+                &[&self.character, &session_id],
+            )
+            .await?;
+        Ok(rows.iter().map(|row| row.get(0)).collect())
+    }
+
+    async fn save_session(&self, session_id: &str, history: &[String]) -> Result<(), Box<dyn std::error::Error>> {
+        let mut client = self.client.lock().await;
+        let transaction = client.transaction().await?;
+        transaction
+            .execute(
+                "DELETE FROM sessions WHERE character = $1 AND session_id = $2",
+                &[&self.character, &session_id],
+            )
+            .await?;
+        for (position, message) in history.iter().enumerate() {
+            transaction
+                .execute(
+                    "INSERT INTO sessions (character, session_id, position, message) VALUES ($1, $2, $3, $4)",
+                    &[&self.character, &session_id, &(position as i32), message],
+                )
+                .await?;
+        }
+        transaction.commit().await?;
+        Ok(())
+    }
+
+    async fn list_sessions(&self) -> Result<Vec<String>, Box<dyn std::error::Error>> {
+        let client = self.client.lock().await;
+        let rows = client
+            .query("SELECT DISTINCT session_id FROM sessions WHERE character = $1", &[&self.character])
+            .await?;
+        Ok(rows.iter().map(|row| row.get(0)).collect())
+    }
+
+    fn vector_index(&self, model: &str) -> Option<Box<dyn VectorIndex>> {
+        Some(Box::new(PgVectorIndex {
+            client: Arc::clone(&self.client),
+            character: self.character.clone(),
+            model: model.to_string(),
+        }))
+    }
+}
+
+/// Similarity search over the embeddings in the `facts` table. The embeddings themselves
+/// are written by `PostgresStore::save`, so this index only has to query them.
+pub struct PgVectorIndex {
+    client: Arc<Mutex<Client>>,
+    character: String,
+    model: String,
+}
+
+#[async_trait]
+impl VectorIndex for PgVectorIndex {
+    async fn insert(&mut self, id: &str, vector: &[f32], _tags: &[FactTag]) -> Result<(), Box<dyn std::error::Error>> {
+        // Facts that are not saved yet get their embedding with the next save
+        let client = self.client.lock().await;
+        client
+            .execute(
+                "UPDATE facts SET embedding = $3::text::vector, embedding_model = $4
+                 WHERE character = $1 AND key = $2",
+                &[&self.character, &id, &vector_literal(vector), &self.model],
+            )
+            .await?;
+        Ok(())
+    }
+
+    async fn remove(&mut self, _id: &str) -> Result<(), Box<dyn std::error::Error>> {
+        // Removed facts disappear from the table with the next save
+        Ok(())
+    }
+
+    async fn search(
+        &self,
+        query: &[f32],
+        k: usize,
+        filter: &TagFilter,
+    ) -> Result<Vec<(String, f32)>, Box<dyn std::error::Error>> {
+        let include = tag_names(&filter.include);
+        let exclude = tag_names(&filter.exclude);
+        let client = self.client.lock().await;
+        let rows = client
+            .query(
+                "SELECT key, (1 - (embedding <=> $2::text::vector))::real AS score
+                 FROM facts
+                 WHERE character = $1 AND embedding_model = $3 AND embedding IS NOT NULL
+                   AND (cardinality($4::text[]) = 0 OR tags && $4)
+                   AND NOT (tags && $5)
+                 ORDER BY embedding <=> $2::text::vector
+                 LIMIT $6",
+                &[&self.character, &vector_literal(query), &self.model, &include, &exclude, &(k as i64)],
+            )
+            .await?;
+        Ok(rows.iter().map(|row| (row.get(0), row.get(1))).collect())
+    }
+
+    async fn is_consistent(&self, model: &str, ids: &HashSet<String>) -> Result<bool, Box<dyn std::error::Error>> {
+        let client = self.client.lock().await;
+        let rows = client
+            .query(
+                "SELECT key FROM facts WHERE character = $1 AND embedding_model = $2 AND embedding IS NOT NULL",
+                &[&self.character, &model],
+            )
+            .await?;
+        let stored: HashSet<String> = rows.iter().map(|row| row.get(0)).collect();
+        Ok(stored == *ids)
+    }
+
+    async fn reset(&mut self, model: &str) -> Result<(), Box<dyn std::error::Error>> {
+        self.model = model.to_string();
+        Ok(())
+    }
+
+    async fn flush(&self) -> Result<(), Box<dyn std::error::Error>> {
+        Ok(())
+    }
+}
@@ -0,0 +1,345 @@
+use super::{check_schema_version, fingerprint, stub_from_json, stub_json, Codec, KnowledgeStore};
+use crate::knowledge::{Fact, Knowledge, SCHEMA_VERSION};
+use async_trait::async_trait;
+use redb::{Database, ReadableTable, Table, TableDefinition, TableHandle, WriteTransaction};
+use std::collections::{HashMap, HashSet};
+use std::fs;
+use std::path::Path;
+use std::sync::Mutex;
+
+// Values are UTF-8 text or, with compression or encryption on, encoded by the codec.
+const FACTS: TableDefinition<&str, &[u8]> = TableDefinition::new("fact_data");
+/// Stubs of the facts for `load_metadata`, as JSON that is never compressed.
+const FACT_META: TableDefinition<&str, &[u8]> = TableDefinition::new("fact_meta");
+const CACHE: TableDefinition<&str, &[u8]> = TableDefinition::new("cache_data");
+/// Search history, learned URLs, contradictions and counters, each as one JSON value.
+const META: TableDefinition<&str, &[u8]> = TableDefinition::new("meta_data");
+/// Conversation history per session id, as a JSON array.
+const SESSIONS: TableDefinition<&str, &[u8]> = TableDefinition::new("session_data");
+/// Text-valued tables written before compression and encryption support, copied over on open.
+const LEGACY_TABLES: [(&str, TableDefinition<&str, &[u8]>); 4] =
+    [("facts", FACTS), ("cache", CACHE), ("meta", META), ("sessions", SESSIONS)];
+
+/// Knowledge store in an embedded redb key-value database. Every save is one
+/// transaction that only writes the entries that changed.
+pub struct RedbStore {
+    database: Database,
+    /// Fingerprints of what the database currently holds, per table and key.
+    written: Mutex<HashMap<&'static str, HashMap<String, u64>>>,
+    /// How values are written.
+    codec: Codec,
+}
+
+impl RedbStore {
+    pub fn open(path: &Path, codec: Codec) -> Result<Self, Box<dyn std::error::Error>> {
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        let database = Database::create(path)?;
+
+        // Create the tables up front so read transactions can always open them
+        let transaction = database.begin_write()?;
+        for table in [FACTS, FACT_META, CACHE, META, SESSIONS] {
+            transaction.open_table(table)?;
+        }
+        upgrade_legacy_tables(&transaction)?;
+        backfill_fact_meta(&transaction, &codec)?;
+        transaction.commit()?;
+
+        Ok(RedbStore {
+            database,
+            written: Mutex::new(HashMap::new()),
+            codec,
+        })
+    }
+
+    /// Reads the whole knowledge, or only the fact stubs from `fact_meta` when `lazy` is set.
+    fn read_knowledge(&self, lazy: bool) -> Result<Option<Knowledge>, Box<dyn std::error::Error>> {
+        let transaction = self.database.begin_read()?;
+        let meta = transaction.open_table(META)?;
+        let mut meta_values = HashMap::new();
+        for entry in meta.iter()? {
+            let (key, value) = entry?;
+            meta_values.insert(key.value().to_string(), read_value(value.value(), &self.codec.uncompressed())?);
+        }
+        let Some((external_url_count, _)) = meta_values.get("external_url_count") else {
+            return Ok(None);
+        };
+        check_schema_version(meta_values.get("schema_version").map(|(version, _)| version.as_str()))?;
+
+        let mut knowledge = Knowledge {
+            external_url_count: external_url_count.parse()?,
+            ..Knowledge::default()
+        };
+        let mut written = self.written.lock().unwrap();
+        written.clear();
+
+        let read_meta = |key: &str| meta_values.get(key).map_or("[]", |(value, _)| value.as_str());
+        knowledge.search_history = serde_json::from_str(read_meta("search_history"))?;
+        knowledge.learned_urls = serde_json::from_str(read_meta("learned_urls"))?;
+        knowledge.contradictions = serde_json::from_str(read_meta("contradictions"))?;
+        knowledge.pages = serde_json::from_str(meta_values.get("pages").map_or("{}", |(value, _)| value.as_str()))?;
+        knowledge.files = serde_json::from_str(meta_values.get("files").map_or("{}", |(value, _)| value.as_str()))?;
+        knowledge.feeds = serde_json::from_str(meta_values.get("feeds").map_or("{}", |(value, _)| value.as_str()))?;
+        let prints = written.entry("meta").or_default();
+        for (key, (_, print)) in &meta_values {
+            prints.insert(key.clone(), *print);
+        }
+
+        let fact_meta = written.entry("fact_meta").or_default();
+        for entry in transaction.open_table(FACT_META)?.iter()? {
+            let (key, meta) = entry?;
+            let (meta, print) = read_value(meta.value(), &self.codec.uncompressed())?;
+            fact_meta.insert(key.value().to_string(), print);
+            if lazy {
+                knowledge.facts.insert(key.value().to_string(), stub_from_json(&meta)?);
+            }
+        }
+        let facts = written.entry("facts").or_default();
+        if lazy {
+            // The stored data is unknown until the fact is fetched, so the next save of a
+            // loaded copy always writes it
+            facts.extend(knowledge.facts.keys().map(|key| (key.clone(), 0)));
+        } else {
+            for entry in transaction.open_table(FACTS)?.iter()? {
+                let (key, data) = entry?;
+                let (data, print) = read_value(data.value(), &self.codec)?;
+                facts.insert(key.value().to_string(), print);
+                knowledge.facts.insert(key.value().to_string(), serde_json::from_str(&data)?);
+            }
+        }
+        let cache = written.entry("cache").or_default();
+        for entry in transaction.open_table(CACHE)?.iter()? {
+            let (url, content) = entry?;
+            let (content, print) = read_value(content.value(), &self.codec)?;
+            cache.insert(url.value().to_string(), print);
+            knowledge.cached_content.insert(url.value().to_string(), content);
+        }
+
+        Ok(Some(knowledge))
+    }
+}
+
+fn upgrade_legacy_tables(transaction: &WriteTransaction) -> Result<(), Box<dyn std::error::Error>> {
+    let existing: Vec<String> = transaction.list_tables()?.map(|table| table.name().to_string()).collect();
+    for (legacy_name, current) in LEGACY_TABLES {
+        if !existing.iter().any(|name| name == legacy_name) {
+            continue;
+        }
+        let legacy: TableDefinition<&str, &str> = TableDefinition::new(legacy_name);
+        {
+            let old = transaction.open_table(legacy)?;
+            let mut new = transaction.open_table(current)?;
+            for entry in old.iter()? {
+                let (key, value) = entry?;
+                new.insert(key.value(), value.value().as_bytes())?;
+            }
+        }
+        transaction.delete_table(legacy)?;
+    }
+    Ok(())
+}
+
+/// Writes the stubs of facts stored before `fact_meta` existed.
+fn backfill_fact_meta(transaction: &WriteTransaction, codec: &Codec) -> Result<(), Box<dyn std::error::Error>> {
+    let facts = transaction.open_table(FACTS)?;
+    let mut fact_meta = transaction.open_table(FACT_META)?;
+    for entry in facts.iter()? {
+        let (key, data) = entry?;
+        if fact_meta.get(key.value())?.is_some() {
+            continue;
+        }
+        let (data, _) = codec.decode(data.value())?;
+        let fact: Fact = serde_json::from_str(&data)?;
+        fact_meta.insert(key.value(), codec.uncompressed().encode(&stub_json(&fact)?)?.as_slice())?;
+    }
+    Ok(())
+}
+
+/// Reads a stored value back. Values not in the form the store would write now get a
+/// fingerprint that never matches, so the next save rewrites them after compression is
+/// or encryption is switched on or off.
+fn read_value(bytes: &[u8], codec: &Codec) -> Result<(String, u64), std::io::Error> {
+    let (text, current) = codec.decode(bytes)?;
+    let print = fingerprint(&text);
+    if current {
+        Ok((text, print))
+    } else {
+        Ok((text, !print))
+    }
+}
+
+/// Adds the keys stored in `table` to `written`, for a handle that has not read them, so
+/// `sync_table` removes the entries that are gone. Their fingerprint is unknown, so the
+/// entries that stay are written again.
+fn add_stored_keys(table: &Table<&str, &[u8]>, written: &mut HashMap<String, u64>) -> Result<(), Box<dyn std::error::Error>> {
+    for entry in table.iter()? {
+        let (key, _) = entry?;
+        written.entry(key.value().to_string()).or_insert(0);
+    }
+    Ok(())
+}
+
+/// Writes the entries whose value changed and removes the ones that are gone, except for
+/// the keys in `keep`.
+fn sync_table(
+    table: &mut Table<&str, &[u8]>,
+    entries: &HashMap<&str, String>,
+    keep: &HashSet<&str>,
+    written: &mut HashMap<String, u64>,
+    codec: &Codec,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let stale: Vec<String> = written
+        .keys()
+        .filter(|key| !entries.contains_key(key.as_str()) && !keep.contains(key.as_str()))
+        .cloned()
+        .collect();
+    for key in stale {
+        table.remove(key.as_str())?;
+        written.remove(&key);
+    }
+
+    for (key, value) in entries {
+        let print = fingerprint(value);
+        if written.get(*key) == Some(&print) {
+            continue;
+        }
+        table.insert(*key, codec.encode(value)?.as_slice())?;
+        written.insert(key.to_string(), print);
+    }
+    Ok(())
+}
+
+#[async_trait]
+impl KnowledgeStore for RedbStore {
+    async fn load(&self) -> Result<Option<Knowledge>, Box<dyn std::error::Error>> {
+        self.read_knowledge(false)
+    }
+
+    async fn load_metadata(&self) -> Result<Option<Knowledge>, Box<dyn std::error::Error>> {
+        self.read_knowledge(true)
+    }
+
+    async fn save(&self, knowledge: &Knowledge) -> Result<(), Box<dyn std::error::Error>> {
+        let loaded = knowledge.facts.iter().filter(|(_, fact)| !fact.is_stub());
+        let facts = loaded
+            .clone()
+            .map(|(key, fact)| Ok((key.as_str(), serde_json::to_string(fact)?)))
+            .collect::<Result<HashMap<_, _>, serde_json::Error>>()?;
+        let fact_meta = loaded
+            .map(|(key, fact)| Ok((key.as_str(), stub_json(fact)?)))
+            .collect::<Result<HashMap<_, _>, serde_json::Error>>()?;
+        let stubs: HashSet<&str> = knowledge
+            .facts
+            .iter()
+            .filter(|(_, fact)| fact.is_stub())
+            .map(|(key, _)| key.as_str())
+            .collect();
+        let cache = knowledge
+            .cached_content
+            .iter()
+            .map(|(url, content)| (url.as_str(), content.clone()))
+            .collect();
+        let meta = HashMap::from([
+            ("search_history", serde_json::to_string(&knowledge.search_history)?),
+            ("learned_urls", serde_json::to_string(&knowledge.learned_urls)?),
+            ("contradictions", serde_json::to_string(&knowledge.contradictions)?),
+            ("pages", serde_json::to_string(&knowledge.pages)?),
+            ("files", serde_json::to_string(&knowledge.files)?),
+            ("feeds", serde_json::to_string(&knowledge.feeds)?),
+            ("external_url_count", knowledge.external_url_count.to_string()),
+            ("schema_version", SCHEMA_VERSION.to_string()),
+        ]);
+
+        let mut written = self.written.lock().unwrap();
+        // Work on a copy so a failed transaction does not leave stale fingerprints behind
+        let mut pending = written.clone();
+        let transaction = self.database.begin_write()?;
+        let codec = &self.codec;
+        let small = codec.uncompressed();
+        let none = HashSet::new();
+        // A handle that never loaded does not know which entries a full save must remove
+        if !pending.contains_key("cache") {
+            if let Some(version) = transaction.open_table(META)?.get("schema_version")? {
+                check_schema_version(Some(&read_value(version.value(), &small)?.0))?;
+            }
+            for (name, table) in [("facts", FACTS), ("fact_meta", FACT_META), ("cache", CACHE), ("meta", META)] {
+                add_stored_keys(&transaction.open_table(table)?, pending.entry(name).or_default())?;
+            }
+        }
+        sync_table(&mut transaction.open_table(FACTS)?, &facts, &stubs, pending.entry("facts").or_default(), codec)?;
+        sync_table(&mut transaction.open_table(FACT_META)?, &fact_meta, &stubs, pending.entry("fact_meta").or_default(), &small)?;
+        sync_table(&mut transaction.open_table(CACHE)?, &cache, &none, pending.entry("cache").or_default(), codec)?;
+        sync_table(&mut transaction.open_table(META)?, &meta, &none, pending.entry("meta").or_default(), &small)?;
+        transaction.commit()?;
+        *written = pending;
+        Ok(())
+    }
+
+    async fn load_fact(&self, key: &str) -> Result<Option<Fact>, Box<dyn std::error::Error>> {
+        let transaction = self.database.begin_read()?;
+        match transaction.open_table(FACTS)?.get(key)? {
+            Some(data) => Ok(Some(serde_json::from_str(&self.codec.decode(data.value())?.0)?)),
+            None => Ok(None),
+        }
+    }
+
+    async fn save_fact(&self, key: &str, fact: &Fact) -> Result<(), Box<dyn std::error::Error>> {
+        let data = serde_json::to_string(fact)?;
+        let meta = stub_json(fact)?;
+        let transaction = self.database.begin_write()?;
+        transaction.open_table(FACTS)?.insert(key, self.codec.encode(&data)?.as_slice())?;
+        transaction.open_table(FACT_META)?.insert(key, self.codec.uncompressed().encode(&meta)?.as_slice())?;
+        transaction.commit()?;
+        let mut written = self.written.lock().unwrap();
+        written.entry("facts").or_default().insert(key.to_string(), fingerprint(&data));
+        written.entry("fact_meta").or_default().insert(key.to_string(), fingerprint(&meta));
+        Ok(())
+    }
+
+    async fn delete_fact(&self, key: &str) -> Result<(), Box<dyn std::error::Error>> {
+        let transaction = self.database.begin_write()?;
+        transaction.open_table(FACTS)?.remove(key)?;
+        transaction.open_table(FACT_META)?.remove(key)?;
+        transaction.commit()?;
+        let mut written = self.written.lock().unwrap();
+        written.entry("facts").or_default().remove(key);
+        written.entry("fact_meta").or_default().remove(key);
+        Ok(())
+    }
+
+    async fn iterate(&self, visit: &mut (dyn for<'f> FnMut(&'f str, &'f Fact) + Send)) -> Result<(), Box<dyn std::error::Error>> {
+        let transaction = self.database.begin_read()?;
+        for entry in transaction.open_table(FACTS)?.iter()? {
+            let (key, data) = entry?;
+            visit(key.value(), &serde_json::from_str(&self.codec.decode(data.value())?.0)?);
+        }
+        Ok(())
+    }
+
+    async fn load_session(&self, session_id: &str) -> Result<Vec<String>, Box<dyn std::error::Error>> {
+        let transaction = self.database.begin_read()?;
+        let sessions = transaction.open_table(SESSIONS)?;
+        match sessions.get(session_id)? {
+            Some(history) => Ok(serde_json::from_str(&self.codec.decode(history.value())?.0)?),
+            None => Ok(Vec::new()),
+        }
+    }
+
+    async fn save_session(&self, session_id: &str, history: &[String]) -> Result<(), Box<dyn std::error::Error>> {
+        let history = serde_json::to_string(history)?;
+        let transaction = self.database.begin_write()?;
+        transaction.open_table(SESSIONS)?.insert(session_id, self.codec.encode(&history)?.as_slice())?;
+        transaction.commit()?;
+        Ok(())
+    }
+
+    async fn list_sessions(&self) -> Result<Vec<String>, Box<dyn std::error::Error>> {
+        let transaction = self.database.begin_read()?;
+        let mut ids = Vec::new();
+        for entry in transaction.open_table(SESSIONS)?.iter()? {
+            ids.push(entry?.0.value().to_string());
+        }
+        Ok(ids)
+    }
+}
@@ -0,0 +1,500 @@
+use super::{check_schema_version, fingerprint, stub_from_json, stub_json, Codec, KnowledgeStore};
+use crate::knowledge::{Fact, Knowledge, SCHEMA_VERSION};
+use async_trait::async_trait;
+use rusqlite::types::{Type, Value, ValueRef};
+use rusqlite::{params, Connection, OptionalExtension, Row, Transaction};
+use serde::Serialize;
+use std::collections::{HashMap, HashSet};
+use std::fs;
+use std::path::Path;
+use std::sync::Mutex;
+
+const SCHEMA: &str = "
+    CREATE TABLE IF NOT EXISTS facts (key TEXT PRIMARY KEY, data TEXT NOT NULL);
+    CREATE TABLE IF NOT EXISTS fact_meta (key TEXT PRIMARY KEY, meta TEXT NOT NULL);
+    CREATE TABLE IF NOT EXISTS cache (url TEXT PRIMARY KEY, content TEXT NOT NULL);
+    CREATE TABLE IF NOT EXISTS pages (url TEXT PRIMARY KEY, data TEXT NOT NULL);
+    CREATE TABLE IF NOT EXISTS files (url TEXT PRIMARY KEY, data TEXT NOT NULL);
+    CREATE TABLE IF NOT EXISTS feeds (url TEXT PRIMARY KEY, data TEXT NOT NULL);
+    CREATE TABLE IF NOT EXISTS learned_urls (position INTEGER PRIMARY KEY, url TEXT NOT NULL);
+    CREATE TABLE IF NOT EXISTS search_history (position INTEGER PRIMARY KEY, query TEXT NOT NULL);
+    CREATE TABLE IF NOT EXISTS contradictions (position INTEGER PRIMARY KEY, data TEXT NOT NULL);
+    CREATE TABLE IF NOT EXISTS meta (key TEXT PRIMARY KEY, value TEXT NOT NULL);
+    CREATE TABLE IF NOT EXISTS sessions (
+        session_id TEXT NOT NULL,
+        position INTEGER NOT NULL,
+        message TEXT NOT NULL,
+        PRIMARY KEY (session_id, position)
+    );
+";
+
+/// Knowledge store in a single SQLite database. Saves run in a transaction and only
+/// write the rows that changed since the last load or save.
+pub struct SqliteStore {
+    connection: Mutex<Connection>,
+    /// Fingerprints of what the database currently holds, per table and row key.
+    written: Mutex<HashMap<&'static str, HashMap<String, u64>>>,
+    /// How values are written; compressed and encrypted values are stored as blobs.
+    codec: Codec,
+}
+
+impl SqliteStore {
+    pub fn open(path: &Path, codec: Codec) -> Result<Self, Box<dyn std::error::Error>> {
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        let connection = Connection::open(path)?;
+        connection.pragma_update(None, "journal_mode", "WAL")?;
+        connection.execute_batch(SCHEMA)?;
+        backfill_fact_meta(&connection, &codec)?;
+        Ok(SqliteStore {
+            connection: Mutex::new(connection),
+            written: Mutex::new(HashMap::new()),
+            codec,
+        })
+    }
+
+    /// Reads the whole knowledge, or only the fact stubs from `fact_meta` when `lazy` is set.
+    fn read_knowledge(&self, lazy: bool) -> Result<Option<Knowledge>, Box<dyn std::error::Error>> {
+        let connection = self.connection.lock().unwrap();
+        let external_url_count: Option<String> = connection
+            .query_row("SELECT value FROM meta WHERE key = 'external_url_count'", [], |row| row.get(0))
+            .optional()?;
+        let Some(external_url_count) = external_url_count else {
+            return Ok(None);
+        };
+        let schema_version: Option<String> = connection
+            .query_row("SELECT value FROM meta WHERE key = 'schema_version'", [], |row| row.get(0))
+            .optional()?;
+        check_schema_version(schema_version.as_deref())?;
+
+        let mut knowledge = Knowledge {
+            external_url_count: external_url_count.parse()?,
+            ..Knowledge::default()
+        };
+        let mut written = self.written.lock().unwrap();
+        written.clear();
+
+        let metas = read_pairs(&connection, "SELECT key, meta FROM fact_meta", &self.codec.uncompressed())?;
+        let facts = written.entry("facts").or_default();
+        if lazy {
+            for (key, meta) in &metas {
+                // The stored data is unknown until the fact is fetched, so the next save of
+                // a loaded copy always writes it
+                facts.insert(key.clone(), 0);
+                knowledge.facts.insert(key.clone(), stub_from_json(&meta.text)?);
+            }
+        } else {
+            for (key, data) in read_pairs(&connection, "SELECT key, data FROM facts", &self.codec)? {
+                facts.insert(key.clone(), data.fingerprint());
+                knowledge.facts.insert(key, serde_json::from_str(&data.text)?);
+            }
+        }
+        let fact_meta = written.entry("fact_meta").or_default();
+        for (key, meta) in metas {
+            fact_meta.insert(key, meta.fingerprint());
+        }
+        let cache = written.entry("cache").or_default();
+        for (url, content) in read_pairs(&connection, "SELECT url, content FROM cache", &self.codec)? {
+            cache.insert(url.clone(), content.fingerprint());
+            knowledge.cached_content.insert(url, content.text);
+        }
+        let pages = written.entry("pages").or_default();
+        for (url, data) in read_pairs(&connection, "SELECT url, data FROM pages", &self.codec.uncompressed())? {
+            pages.insert(url.clone(), data.fingerprint());
+            knowledge.pages.insert(url, serde_json::from_str(&data.text)?);
+        }
+        let files = written.entry("files").or_default();
+        for (url, data) in read_pairs(&connection, "SELECT url, data FROM files", &self.codec.uncompressed())? {
+            files.insert(url.clone(), data.fingerprint());
+            knowledge.files.insert(url, serde_json::from_str(&data.text)?);
+        }
+        let feeds = written.entry("feeds").or_default();
+        for (url, data) in read_pairs(&connection, "SELECT url, data FROM feeds", &self.codec.uncompressed())? {
+            feeds.insert(url.clone(), data.fingerprint());
+            knowledge.feeds.insert(url, serde_json::from_str(&data.text)?);
+        }
+
+        let lists = written.entry("lists").or_default();
+        let (learned_urls, print) = read_values(&connection, "SELECT url FROM learned_urls ORDER BY position", &self.codec)?;
+        knowledge.learned_urls = learned_urls;
+        lists.insert("learned_urls".to_string(), print);
+        let (search_history, print) = read_values(&connection, "SELECT query FROM search_history ORDER BY position", &self.codec)?;
+        knowledge.search_history = search_history;
+        lists.insert("search_history".to_string(), print);
+        let (contradictions, print) = read_values(&connection, "SELECT data FROM contradictions ORDER BY position", &self.codec)?;
+        lists.insert("contradictions".to_string(), print);
+        for data in contradictions {
+            knowledge.contradictions.push(serde_json::from_str(&data)?);
+        }
+
+        Ok(Some(knowledge))
+    }
+}
+
+/// Writes the stubs of facts stored before `fact_meta` existed.
+fn backfill_fact_meta(connection: &Connection, codec: &Codec) -> Result<(), Box<dyn std::error::Error>> {
+    let missing = read_pairs(
+        connection,
+        "SELECT key, data FROM facts WHERE key NOT IN (SELECT key FROM fact_meta)",
+        codec,
+    )?;
+    for (key, data) in missing {
+        let fact: Fact = serde_json::from_str(&data.text)?;
+        connection.execute(
+            "INSERT INTO fact_meta (key, meta) VALUES (?1, ?2)",
+            params![key, stored_value(&stub_json(&fact)?, &codec.uncompressed())?],
+        )?;
+    }
+    Ok(())
+}
+
+/// The tables `sync_keyed` writes, with the column their rows are keyed by.
+const KEYED_TABLES: [(&str, &str); 6] =
+    [("facts", "key"), ("fact_meta", "key"), ("cache", "url"), ("pages", "url"), ("files", "url"), ("feeds", "url")];
+
+/// Adds the keys stored in `table` to `written`, for a handle that has not read them, so
+/// `sync_keyed` removes the rows that are gone. Their fingerprint is unknown, so the rows
+/// that stay are written again.
+fn add_stored_keys(
+    transaction: &Transaction,
+    table: &str,
+    key_column: &str,
+    written: &mut HashMap<String, u64>,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let mut statement = transaction.prepare(&format!("SELECT {} FROM {}", key_column, table))?;
+    let keys = statement.query_map([], |row| row.get::<_, String>(0))?;
+    for key in keys {
+        written.entry(key?).or_insert(0);
+    }
+    Ok(())
+}
+
+/// Upserts the rows of a keyed table whose value changed and deletes the ones that are gone,
+/// except for the keys in `keep`.
+fn sync_keyed(
+    transaction: &Transaction,
+    (table, key_column, value_column): (&str, &str, &str),
+    rows: HashMap<&String, String>,
+    keep: &HashSet<&String>,
+    written: &mut HashMap<String, u64>,
+    codec: &Codec,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let stale: Vec<String> = written
+        .keys()
+        .filter(|key| !rows.contains_key(key) && !keep.contains(key))
+        .cloned()
+        .collect();
+    for key in stale {
+        transaction.execute(&format!("DELETE FROM {} WHERE {} = ?1", table, key_column), params![key])?;
+        written.remove(&key);
+    }
+
+    for (key, value) in rows {
+        let print = fingerprint(&value);
+        if written.get(key) == Some(&print) {
+            continue;
+        }
+        transaction.execute(
+            &format!(
+                "INSERT INTO {0} ({1}, {2}) VALUES (?1, ?2) ON CONFLICT ({1}) DO UPDATE SET {2} = excluded.{2}",
+                table, key_column, value_column
+            ),
+            params![key, stored_value(&value, codec)?],
+        )?;
+        written.insert(key.clone(), print);
+    }
+    Ok(())
+}
+
+/// Rewrites an ordered table, but only when its contents changed.
+fn sync_list(
+    transaction: &Transaction,
+    table: &str,
+    column: &str,
+    values: &[String],
+    written: &mut HashMap<String, u64>,
+    codec: &Codec,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let print = fingerprint(&values.join("\n"));
+    if written.get(table) == Some(&print) {
+        return Ok(());
+    }
+    transaction.execute(&format!("DELETE FROM {}", table), [])?;
+    for (position, value) in values.iter().enumerate() {
+        transaction.execute(
+            &format!("INSERT INTO {} (position, {}) VALUES (?1, ?2)", table, column),
+            params![position as i64, stored_value(value, codec)?],
+        )?;
+    }
+    written.insert(table.to_string(), print);
+    Ok(())
+}
+
+fn to_json<T: Serialize>(value: &T) -> Result<String, serde_json::Error> {
+    serde_json::to_string(value)
+}
+
+/// Plain text, or a blob with the compressed or encrypted text.
+fn stored_value(text: &str, codec: &Codec) -> std::io::Result<Value> {
+    // Neither encoded form is valid UTF-8
+    Ok(match String::from_utf8(codec.encode(text)?) {
+        Ok(text) => Value::Text(text),
+        Err(e) => Value::Blob(e.into_bytes()),
+    })
+}
+
+/// A text column that may hold an encoded blob.
+struct Stored {
+    text: String,
+    /// Whether the row is in the form the store would write it in now.
+    current: bool,
+}
+
+impl Stored {
+    /// Rows in the other form get a fingerprint that never matches, so the next save
+    /// rewrites them after compression or encryption is switched on or off.
+    fn fingerprint(&self) -> u64 {
+        let print = fingerprint(&self.text);
+        if self.current {
+            print
+        } else {
+            !print
+        }
+    }
+}
+
+fn decode_column(row: &Row, index: usize, codec: &Codec) -> rusqlite::Result<Stored> {
+    let bytes = match row.get_ref(index)? {
+        ValueRef::Text(bytes) | ValueRef::Blob(bytes) => bytes,
+        other => return Err(rusqlite::Error::InvalidColumnType(index, "value".to_string(), other.data_type())),
+    };
+    let (text, current) = codec
+        .decode(bytes)
+        .map_err(|e| rusqlite::Error::FromSqlConversionFailure(index, Type::Blob, Box::new(e)))?;
+    Ok(Stored { text, current })
+}
+
+fn read_pairs(connection: &Connection, query: &str, codec: &Codec) -> rusqlite::Result<Vec<(String, Stored)>> {
+    let mut statement = connection.prepare(query)?;
+    let rows = statement.query_map([], |row| Ok((row.get(0)?, decode_column(row, 1, codec)?)))?;
+    rows.collect()
+}
+
+/// The values of an ordered table, with the fingerprint `sync_list` compares against.
+fn read_values(connection: &Connection, query: &str, codec: &Codec) -> rusqlite::Result<(Vec<String>, u64)> {
+    let mut statement = connection.prepare(query)?;
+    let stored = statement
+        .query_map([], |row| decode_column(row, 0, codec))?
+        .collect::<rusqlite::Result<Vec<_>>>()?;
+    let current = stored.iter().all(|value| value.current);
+    let values: Vec<String> = stored.into_iter().map(|value| value.text).collect();
+    let print = fingerprint(&values.join("\n"));
+    Ok((values, if current { print } else { !print }))
+}
+
+fn read_list(connection: &Connection, query: &str) -> rusqlite::Result<Vec<String>> {
+    let mut statement = connection.prepare(query)?;
+    let rows = statement.query_map([], |row| row.get(0))?;
+    rows.collect()
+}
+
+#[async_trait]
+impl KnowledgeStore for SqliteStore {
+    async fn load(&self) -> Result<Option<Knowledge>, Box<dyn std::error::Error>> {
+        self.read_knowledge(false)
+    }
+
+    async fn load_metadata(&self) -> Result<Option<Knowledge>, Box<dyn std::error::Error>> {
+        self.read_knowledge(true)
+    }
+
+    async fn save(&self, knowledge: &Knowledge) -> Result<(), Box<dyn std::error::Error>> {
+        let loaded = knowledge.facts.iter().filter(|(_, fact)| !fact.is_stub());
+        let facts = loaded
+            .clone()
+            .map(|(key, fact)| Ok((key, to_json(fact)?)))
+            .collect::<Result<HashMap<_, _>, serde_json::Error>>()?;
+        let metas = loaded
+            .map(|(key, fact)| Ok((key, stub_json(fact)?)))
+            .collect::<Result<HashMap<_, _>, serde_json::Error>>()?;
+        let stubs: HashSet<&String> = knowledge.facts.iter().filter(|(_, fact)| fact.is_stub()).map(|(key, _)| key).collect();
+        let cache = knowledge.cached_content.iter().map(|(url, content)| (url, content.clone())).collect();
+        let pages = knowledge
+            .pages
+            .iter()
+            .map(|(url, page)| Ok((url, to_json(page)?)))
+            .collect::<Result<HashMap<_, _>, serde_json::Error>>()?;
+        let files = knowledge
+            .files
+            .iter()
+            .map(|(url, file)| Ok((url, to_json(file)?)))
+            .collect::<Result<HashMap<_, _>, serde_json::Error>>()?;
+        let feeds = knowledge
+            .feeds
+            .iter()
+            .map(|(url, feed)| Ok((url, to_json(feed)?)))
+            .collect::<Result<HashMap<_, _>, serde_json::Error>>()?;
+        let contradictions = knowledge
+            .contradictions
+            .iter()
+            .map(to_json)
+            .collect::<Result<Vec<_>, _>>()?;
+
+        let mut connection = self.connection.lock().unwrap();
+        let mut written = self.written.lock().unwrap();
+        // Work on a copy so a failed transaction does not leave stale fingerprints behind
+        let mut pending = written.clone();
+        let transaction = connection.transaction()?;
+        // A handle that never loaded does not know which rows a full save must remove
+        if !pending.contains_key("cache") {
+            let schema_version: Option<String> = transaction
+                .query_row("SELECT value FROM meta WHERE key = 'schema_version'", [], |row| row.get(0))
+                .optional()?;
+            check_schema_version(schema_version.as_deref())?;
+            for (table, key_column) in KEYED_TABLES {
+                add_stored_keys(&transaction, table, key_column, pending.entry(table).or_default())?;
+            }
+        }
+        let codec = &self.codec;
+        sync_keyed(&transaction, ("facts", "key", "data"), facts, &stubs, pending.entry("facts").or_default(), codec)?;
+        let meta_codec = codec.uncompressed();
+        sync_keyed(&transaction, ("fact_meta", "key", "meta"), metas, &stubs, pending.entry("fact_meta").or_default(), &meta_codec)?;
+        let none = HashSet::new();
+        sync_keyed(&transaction, ("cache", "url", "content"), cache, &none, pending.entry("cache").or_default(), codec)?;
+        sync_keyed(&transaction, ("pages", "url", "data"), pages, &none, pending.entry("pages").or_default(), &meta_codec)?;
+        sync_keyed(&transaction, ("files", "url", "data"), files, &none, pending.entry("files").or_default(), &meta_codec)?;
+        sync_keyed(&transaction, ("feeds", "url", "data"), feeds, &none, pending.entry("feeds").or_default(), &meta_codec)?;
+        let lists = pending.entry("lists").or_default();
+        sync_list(&transaction, "learned_urls", "url", &knowledge.learned_urls, lists, codec)?;
+        sync_list(&transaction, "search_history", "query", &knowledge.search_history, lists, codec)?;
+        sync_list(&transaction, "contradictions", "data", &contradictions, lists, codec)?;
+        for (key, value) in [
+            ("external_url_count", knowledge.external_url_count.to_string()),
+            ("schema_version", SCHEMA_VERSION.to_string()),
+        ] {
+            transaction.execute(
+                "INSERT INTO meta (key, value) VALUES (?1, ?2) ON CONFLICT (key) DO UPDATE SET value = excluded.value",
+                params![key, value],
+            )?;
+        }
+        transaction.commit()?;
+        *written = pending;
+        Ok(())
+    }
+
+    async fn load_fact(&self, key: &str) -> Result<Option<Fact>, Box<dyn std::error::Error>> {
+        let connection = self.connection.lock().unwrap();
+        let data = connection
+            .query_row("SELECT data FROM facts WHERE key = ?1", params![key], |row| decode_column(row, 0, &self.codec))
+            .optional()?;
+        match data {
+            Some(data) => Ok(Some(serde_json::from_str(&data.text)?)),
+            None => Ok(None),
+        }
+    }
+
+    async fn save_fact(&self, key: &str, fact: &Fact) -> Result<(), Box<dyn std::error::Error>> {
+        let data = to_json(fact)?;
+        let meta = stub_json(fact)?;
+        let mut connection = self.connection.lock().unwrap();
+        let transaction = connection.transaction()?;
+        transaction.execute(
+            "INSERT INTO facts (key, data) VALUES (?1, ?2) ON CONFLICT (key) DO UPDATE SET data = excluded.data",
+            params![key, stored_value(&data, &self.codec)?],
+        )?;
+        transaction.execute(
+            "INSERT INTO fact_meta (key, meta) VALUES (?1, ?2) ON CONFLICT (key) DO UPDATE SET meta = excluded.meta",
+            params![key, stored_value(&meta, &self.codec.uncompressed())?],
+        )?;
+        transaction.commit()?;
+        let mut written = self.written.lock().unwrap();
+        written.entry("facts").or_default().insert(key.to_string(), fingerprint(&data));
+        written.entry("fact_meta").or_default().insert(key.to_string(), fingerprint(&meta));
+        Ok(())
+    }
+
+    async fn delete_fact(&self, key: &str) -> Result<(), Box<dyn std::error::Error>> {
+        let mut connection = self.connection.lock().unwrap();
+        let transaction = connection.transaction()?;
+        transaction.execute("DELETE FROM facts WHERE key = ?1", params![key])?;
+        transaction.execute("DELETE FROM fact_meta WHERE key = ?1", params![key])?;
+        transaction.commit()?;
+        let mut written = self.written.lock().unwrap();
+        written.entry("facts").or_default().remove(key);
+        written.entry("fact_meta").or_default().remove(key);
+        Ok(())
+    }
+
+    async fn iterate(&self, visit: &mut (dyn for<'f> FnMut(&'f str, &'f Fact) + Send)) -> Result<(), Box<dyn std::error::Error>> {
+        let connection = self.connection.lock().unwrap();
+        for (key, data) in read_pairs(&connection, "SELECT key, data FROM facts", &self.codec)? {
+            visit(&key, &serde_json::from_str(&data.text)?);
+        }
+        Ok(())
+    }
+
+    async fn load_session(&self, session_id: &str) -> Result<Vec<String>, Box<dyn std::error::Error>> {
+        let connection = self.connection.lock().unwrap();
+        let mut statement =
+            connection.prepare("SELECT message FROM sessions WHERE session_id = ?1 ORDER BY position")?;
+        let messages = statement.query_map(params![session_id], |row| Ok(decode_column(row, 0, &self.codec)?.text))?;
+        Ok(messages.collect::<rusqlite::Result<_>>()?)
+    }
+
+    async fn save_session(&self, session_id: &str, history: &[String]) -> Result<(), Box<dyn std::error::Error>> {
+        let mut connection = self.connection.lock().unwrap();
+        let transaction = connection.transaction()?;
+        transaction.execute("DELETE FROM sessions WHERE session_id = ?1", params![session_id])?;
+        for (position, message) in history.iter().enumerate() {
+            transaction.execute(
+                "INSERT INTO sessions (session_id, position, message) VALUES (?1, ?2, ?3)",
+                params![session_id, position as i64, stored_value(message, &self.codec)?],
+            )?;
+        }
+        transaction.commit()?;
+        Ok(())
+    }
+
+    async fn list_sessions(&self) -> Result<Vec<String>, Box<dyn std::error::Error>> {
+        let connection = self.connection.lock().unwrap();
+        Ok(read_list(&connection, "SELECT DISTINCT session_id FROM sessions")?)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::knowledge::LearnMethod;
+
+    fn remove(path: &Path) {
+        for suffix in ["", "-wal", "-shm"] {
+            let _ = fs::remove_file(format!("{}{}", path.display(), suffix));
+        }
+    }
+
+    #[tokio::test]
+    async fn saving_without_loading_removes_the_facts_that_are_gone() {
+        let path = std::env::temp_dir().join(format!("alya-sqlite-{}.db", std::process::id()));
+        remove(&path);
+        let fact = |text: &str| Fact::new(text.to_string(), None, LearnMethod::Training, Vec::new());
+        {
+            let store = SqliteStore::open(&path, Codec::default()).unwrap();
+            store.save_fact("old", &fact("Alya is proud")).await.unwrap();
+            store.save_fact("kept", &fact("Alya plays the piano")).await.unwrap();
+        }
+
+        // As a migration or import does: a new handle saves the whole knowledge
+        let store = SqliteStore::open(&path, Codec::default()).unwrap();
+        let mut knowledge = Knowledge::default();
+        knowledge.facts.insert("kept".to_string(), fact("Alya plays the piano"));
+        knowledge.facts.insert("new".to_string(), fact("Alya is Masha's sister"));
+        store.save(&knowledge).await.unwrap();
+
+        let loaded = SqliteStore::open(&path, Codec::default()).unwrap().load().await.unwrap().unwrap();
+        let mut keys: Vec<String> = loaded.facts.into_keys().collect();
+        keys.sort();
+        remove(&path);
+        assert_eq!(keys, ["kept", "new"]);
+    }
+}
@@ -0,0 +1,169 @@
+use crate::knowledge::Knowledge;
+use crate::paths;
+use async_trait::async_trait;
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use std::fs;
+
+mod s3;
+mod webdav;
+
+pub use s3::S3Remote;
+pub use webdav::WebDavRemote;
+
+/// Where the last successful sync is recorded, in the data directory.
+const STATE_FILE: &str = "sync_state.json";
+
+/// A single remote copy of the character archive.
+#[async_trait]
+pub trait Remote: Send + Sync {
+    /// Version tag of the remote copy, or `None` when nothing has been pushed yet.
+    async fn head(&self) -> Result<Option<String>, Box<dyn std::error::Error>>;
+
+    /// Downloads the remote copy together with its version tag.
+    async fn get(&self) -> Result<Option<(Vec<u8>, String)>, Box<dyn std::error::Error>>;
+
+    /// Uploads a new copy and returns its version tag, or `None` when the remote copy is no
+    /// longer what `expect` says, as when another machine pushed in the meantime.
+    async fn put(&self, data: Vec<u8>, expect: Expect<'_>) -> Result<Option<String>, Box<dyn std::error::Error>>;
+}
+
+/// What the remote copy has to be for an upload to replace it. The server checks this, so
+/// two machines pushing at once cannot both succeed.
+#[derive(Debug, Clone, Copy)]
+pub enum Expect<'a> {
+    /// Whatever is there is overwritten.
+    Anything,
+    /// Nothing may have been pushed yet.
+    Nothing,
+    /// The copy with this version tag.
+    Version(&'a str),
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum SyncBackend {
+    /// Any S3-compatible object store (AWS, MinIO, R2, ...). Credentials are read from
+    /// `AWS_ACCESS_KEY_ID` and `AWS_SECRET_ACCESS_KEY`.
+    S3,
+    /// A WebDAV share. The password is read from `WEBDAV_PASSWORD`.
+    Webdav,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct SyncSettings {
+    pub backend: SyncBackend,
+    /// S3 endpoint (e.g. `https://s3.eu-central-1.amazonaws.com`) or WebDAV folder URL.
+    pub url: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub bucket: Option<String>,
+    #[serde(default = "default_region")]
+    pub region: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub username: Option<String>,
+    /// Folder or key prefix the archive is stored under.
+    #[serde(default)]
+    pub path: String,
+}
+
+fn default_region() -> String {
+    "us-east-1".to_string()
+}
+
+/// Connects to the configured remote. Each character is stored as its own archive.
+pub fn create_remote(settings: &SyncSettings, character: &str) -> Result<Box<dyn Remote>, Box<dyn std::error::Error>> {
+    let file_name: String = character
+        .chars()
+        .map(|c| if c.is_alphanumeric() || c == '-' { c } else { '_' })
+        .collect();
+    let prefix = settings.path.trim_matches('/');
+    let key = if prefix.is_empty() {
+        format!("{}.tar.gz", file_name)
+    } else {
+        format!("{}/{}.tar.gz", prefix, file_name)
+    };
+
+    match settings.backend {
+        SyncBackend::S3 => Ok(Box::new(S3Remote::new(settings, key)?)),
+        SyncBackend::Webdav => Ok(Box::new(WebDavRemote::new(settings, &key))),
+    }
+}
+
+/// What both sides looked like after the last successful push or pull.
+#[derive(Debug, Serialize, Deserialize, Default)]
+pub struct SyncState {
+    pub remote_version: Option<String>,
+    pub local_fingerprint: Option<String>,
+    pub synced_at: Option<DateTime<Utc>>,
+}
+
+impl SyncState {
+    pub fn load() -> Result<Self, Box<dyn std::error::Error>> {
+        let path = paths::data_path(STATE_FILE);
+        if !path.exists() {
+            return Ok(SyncState::default());
+        }
+        Ok(serde_json::from_str(&fs::read_to_string(path)?)?)
+    }
+
+    pub fn save(&self) -> Result<(), Box<dyn std::error::Error>> {
+        fs::create_dir_all(paths::data_dir())?;
+        fs::write(paths::data_path(STATE_FILE), serde_json::to_string_pretty(self)?)?;
+        Ok(())
+    }
+
+    pub fn record(remote_version: String, knowledge: &Knowledge) -> Self {
+        SyncState {
+            remote_version: Some(remote_version),
+            local_fingerprint: Some(knowledge.fingerprint()),
+            synced_at: Some(Utc::now()),
+        }
+    }
+
+    /// Whether the local knowledge changed since the last sync.
+    pub fn local_changed(&self, knowledge: &Knowledge) -> bool {
+        self.local_fingerprint.as_deref() != Some(knowledge.fingerprint().as_str())
+    }
+
+    /// Whether someone else pushed since the last sync.
+    pub fn remote_changed(&self, remote_version: Option<&str>) -> bool {
+        remote_version.is_some() && self.remote_version.as_deref() != remote_version
+    }
+}
+
+async fn check(response: reqwest::Response) -> Result<reqwest::Response, Box<dyn std::error::Error>> {
+    if response.status().is_success() {
+        return Ok(response);
+    }
+    let status = response.status();
+    let body = response.text().await.unwrap_or_default();
+    Err(format!("Sync request failed ({}): {}", status, body).into())
+}
+
+/// Makes an upload conditional on `expect`: `If-Match` with the ETag, or
+/// `If-Unmodified-Since` for WebDAV servers whose versions are modification times.
+fn expecting(request: reqwest::RequestBuilder, expect: Expect) -> reqwest::RequestBuilder {
+    match expect {
+        Expect::Anything => request,
+        Expect::Nothing => request.header(reqwest::header::IF_NONE_MATCH, "*"),
+        Expect::Version(modified) if DateTime::parse_from_rfc2822(modified).is_ok() => {
+            request.header(reqwest::header::IF_UNMODIFIED_SINCE, modified)
+        }
+        // `etag` strips the quotes of strong tags
+        Expect::Version(tag) if tag.starts_with("W/") => request.header(reqwest::header::IF_MATCH, tag),
+        Expect::Version(tag) => request.header(reqwest::header::IF_MATCH, format!("\"{}\"", tag)),
+    }
+}
+
+/// Whether the server refused an upload because its precondition failed.
+fn conflicted(response: &reqwest::Response) -> bool {
+    response.status() == reqwest::StatusCode::PRECONDITION_FAILED
+}
+
+fn etag(response: &reqwest::Response) -> Option<String> {
+    response
+        .headers()
+        .get(reqwest::header::ETAG)
+        .and_then(|etag| etag.to_str().ok())
+        .map(|etag| etag.trim_matches('"').to_string())
+}
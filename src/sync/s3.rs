@@ -0,0 +1,138 @@
+use super::{check, conflicted, etag, expecting, Expect, Remote, SyncSettings};
+use crate::secrets;
+use async_trait::async_trait;
+use chrono::Utc;
+use hmac::{Hmac, Mac};
+use sha2::{Digest, Sha256};
+
+type HmacSha256 = Hmac<Sha256>;
+
+/// Archive stored as an object in an S3-compatible bucket, addressed path-style and
+/// signed with AWS Signature Version 4.
+pub struct S3Remote {
+    endpoint: url::Url,
+    /// `/<bucket>/<key>`, already URI-encoded.
+    path: String,
+    region: String,
+    access_key_id: String,
+    secret_access_key: String,
+    client: reqwest::Client,
+}
+
+impl S3Remote {
+    pub fn new(settings: &SyncSettings, key: String) -> Result<Self, Box<dyn std::error::Error>> {
+        let bucket = settings.bucket.as_deref().ok_or("sync.bucket must be set for the s3 backend")?;
+        let path = std::iter::once(bucket)
+            .chain(key.split('/'))
+            .map(uri_encode)
+            .collect::<Vec<_>>()
+            .join("/");
+        Ok(S3Remote {
+            endpoint: url::Url::parse(&settings.url)?,
+            path: format!("/{}", path),
+            region: settings.region.clone(),
+            access_key_id: secrets::require("AWS_ACCESS_KEY_ID")?,
+            secret_access_key: secrets::require("AWS_SECRET_ACCESS_KEY")?,
+            client: reqwest::Client::builder()
+                .timeout(std::time::Duration::from_secs(120))
+                .build()
+                .unwrap_or_default(),
+        })
+    }
+
+    fn request(&self, method: reqwest::Method, body: Vec<u8>) -> reqwest::RequestBuilder {
+        let now = Utc::now();
+        let amz_date = now.format("%Y%m%dT%H%M%SZ").to_string();
+        let date = now.format("%Y%m%d").to_string();
+        let payload_hash = format!("{:x}", Sha256::digest(&body));
+        let host = match self.endpoint.port() {
+            Some(port) => format!("{}:{}", self.endpoint.host_str().unwrap_or_default(), port),
+            None => self.endpoint.host_str().unwrap_or_default().to_string(),
+        };
+
+        let canonical_request = format!(
+            "{}\n{}\n\nhost:{}\nx-amz-content-sha256:{}\nx-amz-date:{}\n\nhost;x-amz-content-sha256;x-amz-date\n{}",
+            method, self.path, host, payload_hash, amz_date, payload_hash
+        );
+        let scope = format!("{}/{}/s3/aws4_request", date, self.region);
+        let string_to_sign = format!(
+            "AWS4-HMAC-SHA256\n{}\n{}\n{:x}",
+            amz_date,
+            scope,
+            Sha256::digest(canonical_request.as_bytes())
+        );
+
+        let mut key = hmac(format!("AWS4{}", self.secret_access_key).as_bytes(), &date);
+        for part in [self.region.as_str(), "s3", "aws4_request"] {
+            key = hmac(&key, part);
+        }
+        let signature: String = hmac(&key, &string_to_sign).iter().map(|byte| format!("{:02x}", byte)).collect();
+
+        let mut url = self.endpoint.clone();
+        url.set_path(&self.path);
+        self.client
+            .request(method, url)
+            .header("x-amz-date", amz_date)
+            .header("x-amz-content-sha256", payload_hash)
+            .header(
+                reqwest::header::AUTHORIZATION,
+                format!(
+                    "AWS4-HMAC-SHA256 Credential={}/{}, SignedHeaders=host;x-amz-content-sha256;x-amz-date, Signature={}",
+                    self.access_key_id, scope, signature
+                ),
+            )
+            .body(body)
+    }
+}
+
+fn hmac(key: &[u8], data: &str) -> Vec<u8> {
+    let mut mac = HmacSha256::new_from_slice(key).expect("HMAC accepts keys of any length");
+    mac.update(data.as_bytes());
+    mac.finalize().into_bytes().to_vec()
+}
+
+/// Percent-encodes everything except RFC 3986 unreserved characters.
+fn uri_encode(segment: &str) -> String {
+    segment
+        .bytes()
+        .map(|byte| match byte {
+            b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'_' | b'.' | b'~' => (byte as char).to_string(),
+            _ => format!("%{:02X}", byte),
+        })
+        .collect()
+}
+
+#[async_trait]
+impl Remote for S3Remote {
+    async fn head(&self) -> Result<Option<String>, Box<dyn std::error::Error>> {
+        let response = self.request(reqwest::Method::HEAD, Vec::new()).send().await?;
+        if response.status() == reqwest::StatusCode::NOT_FOUND {
+            return Ok(None);
+        }
+        let response = check(response).await?;
+        Ok(etag(&response))
+    }
+
+    async fn get(&self) -> Result<Option<(Vec<u8>, String)>, Box<dyn std::error::Error>> {
+        let response = self.request(reqwest::Method::GET, Vec::new()).send().await?;
+        if response.status() == reqwest::StatusCode::NOT_FOUND {
+            return Ok(None);
+        }
+        let response = check(response).await?;
+        let version = etag(&response).unwrap_or_default();
+        Ok(Some((response.bytes().await?.to_vec(), version)))
+    }
+
+    async fn put(&self, data: Vec<u8>, expect: Expect<'_>) -> Result<Option<String>, Box<dyn std::error::Error>> {
+        // The conditional headers are left unsigned, which SigV4 allows
+        let response = expecting(self.request(reqwest::Method::PUT, data), expect).send().await?;
+        if conflicted(&response) {
+            return Ok(None);
+        }
+        let response = check(response).await?;
+        match etag(&response) {
+            Some(version) => Ok(Some(version)),
+            None => Ok(Some(self.head().await?.ok_or("Uploaded archive is missing from the bucket")?)),
+        }
+    }
+}
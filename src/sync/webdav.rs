@@ -0,0 +1,91 @@
+use super::{check, conflicted, etag, expecting, Expect, Remote, SyncSettings};
+use crate::secrets;
+use async_trait::async_trait;
+
+/// Archive stored as a file on a WebDAV share.
+pub struct WebDavRemote {
+    url: String,
+    /// Folders between the share root and the file, created before the first upload.
+    folders: Vec<String>,
+    username: Option<String>,
+    password: Option<String>,
+    client: reqwest::Client,
+}
+
+impl WebDavRemote {
+    pub fn new(settings: &SyncSettings, key: &str) -> Self {
+        let base = settings.url.trim_end_matches('/');
+        let segments: Vec<&str> = key.split('/').collect();
+        let folders = (1..segments.len())
+            .map(|depth| format!("{}/{}/", base, segments[..depth].join("/")))
+            .collect();
+        WebDavRemote {
+            url: format!("{}/{}", base, key),
+            folders,
+            username: settings.username.clone(),
+            password: secrets::get("WEBDAV_PASSWORD"),
+            client: reqwest::Client::builder()
+                .timeout(std::time::Duration::from_secs(120))
+                .build()
+                .unwrap_or_default(),
+        }
+    }
+
+    fn request(&self, method: reqwest::Method, url: &str) -> reqwest::RequestBuilder {
+        let request = self.client.request(method, url);
+        match &self.username {
+            Some(username) => request.basic_auth(username, self.password.as_deref()),
+            None => request,
+        }
+    }
+}
+
+#[async_trait]
+impl Remote for WebDavRemote {
+    async fn head(&self) -> Result<Option<String>, Box<dyn std::error::Error>> {
+        let response = self.request(reqwest::Method::HEAD, &self.url).send().await?;
+        if response.status() == reqwest::StatusCode::NOT_FOUND {
+            return Ok(None);
+        }
+        let response = check(response).await?;
+        Ok(Some(version(&response)))
+    }
+
+    async fn get(&self) -> Result<Option<(Vec<u8>, String)>, Box<dyn std::error::Error>> {
+        let response = self.request(reqwest::Method::GET, &self.url).send().await?;
+        if response.status() == reqwest::StatusCode::NOT_FOUND {
+            return Ok(None);
+        }
+        let response = check(response).await?;
+        let version = version(&response);
+        Ok(Some((response.bytes().await?.to_vec(), version)))
+    }
+
+    async fn put(&self, data: Vec<u8>, expect: Expect<'_>) -> Result<Option<String>, Box<dyn std::error::Error>> {
+        let mkcol = reqwest::Method::from_bytes(b"MKCOL")?;
+        for folder in &self.folders {
+            // Fails harmlessly with 405 when the folder already exists
+            self.request(mkcol.clone(), folder).send().await?;
+        }
+        let response = expecting(self.request(reqwest::Method::PUT, &self.url), expect).body(data).send().await?;
+        if conflicted(&response) {
+            return Ok(None);
+        }
+        check(response).await?;
+        // Not every server returns the new ETag from PUT, so ask for it
+        Ok(Some(self.head().await?.ok_or("Uploaded archive is missing on the WebDAV share")?))
+    }
+}
+
+/// Servers without ETags still report the modification time.
+fn version(response: &reqwest::Response) -> String {
+    etag(response)
+        .or_else(|| {
+            response
+                .headers()
+                .get(reqwest::header::LAST_MODIFIED)
+                .and_then(|modified| modified.to_str().ok())
+                .map(str::to_string)
+        })
+        .unwrap_or_default()
+}
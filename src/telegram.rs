@@ -0,0 +1,148 @@
+use crate::gateway::{split_message, Connector, Incoming};
+use crate::server::Calls;
+use async_trait::async_trait;
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::sync::mpsc;
+
+/// The secret holding the bot's token.
+pub const TOKEN_NAME: &str = "TELEGRAM_BOT_TOKEN";
+/// The most characters Telegram allows in a message.
+const MESSAGE_LIMIT: usize = 4096;
+/// How long a poll waits for messages before asking again.
+const POLL_SECONDS: u64 = 50;
+/// How long to wait after a failed poll.
+const RETRY_AFTER: Duration = Duration::from_secs(5);
+
+/// Which chats the bot answers.
+#[derive(Debug, Serialize, Deserialize, Clone, Default)]
+pub struct TelegramSettings {
+    /// The IDs of the chats the bot may answer; none without any.
+    #[serde(default)]
+    pub allowed_chats: Vec<i64>,
+}
+
+/// The Telegram bot. It answers the allowed chats, polling for messages. In groups, it
+/// answers the messages that mention it or reply to it.
+pub struct Bot {
+    client: reqwest::Client,
+    token: String,
+    settings: TelegramSettings,
+}
+
+/// A message to answer: its chat and ID.
+pub struct ReplyTo {
+    chat: i64,
+    message_id: Option<i64>,
+}
+
+impl Bot {
+    pub fn new(token: String, settings: TelegramSettings) -> Result<Self, reqwest::Error> {
+        Ok(Bot {
+            client: reqwest::Client::builder().timeout(Duration::from_secs(POLL_SECONDS + 10)).build()?,
+            token,
+            settings,
+        })
+    }
+
+    async fn call(&self, method: &str, params: Value) -> Result<Value, Box<dyn std::error::Error + Send + Sync>> {
+        // The token is part of the URL, so it is left out of errors
+        let response: Value = self
+            .client
+            .post(format!("https://api.telegram.org/bot{}/{}", self.token, method))
+            .json(&params)
+            .send()
+            .await
+            .map_err(|e| e.without_url())?
+            .json()
+            .await
+            .map_err(|e| e.without_url())?;
+        if response["ok"] != true {
+            return Err(format!("Telegram refused {}: {}", method, response["description"].as_str().unwrap_or_default()).into());
+        }
+        Ok(response["result"].clone())
+    }
+}
+
+#[async_trait]
+impl Connector for Bot {
+    type ReplyTo = ReplyTo;
+
+    const NAME: &'static str = "telegram";
+    const TITLE: &'static str = "Telegram";
+    const PER_USER: bool = false;
+    /// Telegram shows "typing" for 5 seconds.
+    const TYPING_EVERY: Duration = Duration::from_secs(4);
+
+    /// Fails only if the bot cannot connect at first.
+    async fn receive(self: Arc<Self>, incoming: mpsc::Sender<Incoming<ReplyTo>>, _calls: Calls) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+        let me = self.call("getMe", json!({})).await?;
+        let username = me["username"].as_str().unwrap_or_default().to_string();
+        status!("Connected to Telegram as @{}", username);
+
+        let mut offset = 0;
+        loop {
+            let params = json!({ "offset": offset, "timeout": POLL_SECONDS, "allowed_updates": ["message"] });
+            let updates = match self.call("getUpdates", params).await {
+                Ok(updates) => updates,
+                // The connection dropping now and then is no reason to stop
+                Err(e) => {
+                    status!("Could not poll Telegram, trying again: {}", e);
+                    tokio::time::sleep(RETRY_AFTER).await;
+                    continue;
+                }
+            };
+            for update in updates.as_array().into_iter().flatten() {
+                offset = offset.max(update["update_id"].as_i64().unwrap_or_default() + 1);
+                let message = &update["message"];
+                let (Some(chat), Some(text)) = (message["chat"]["id"].as_i64(), message["text"].as_str()) else {
+                    continue;
+                };
+                if message["from"]["is_bot"] == true {
+                    continue;
+                }
+                let mention = format!("@{}", username);
+                let private = message["chat"]["type"] == "private";
+                let replied_to = message["reply_to_message"]["from"]["username"].as_str() == Some(username.as_str());
+                if !private && !replied_to && !text.contains(&mention) {
+                    continue;
+                }
+                if !self.settings.allowed_chats.contains(&chat) {
+                    status!("Ignoring a Telegram message from chat {}, which is not in telegram.allowed_chats", chat);
+                    continue;
+                }
+                let text = text.replace(&mention, "").trim().to_string();
+                if text.is_empty() {
+                    continue;
+                }
+                let message = Incoming {
+                    user: message["from"]["id"].to_string(),
+                    channel: chat.to_string(),
+                    text,
+                    reply_to: ReplyTo { chat, message_id: message["message_id"].as_i64() },
+                };
+                if incoming.send(message).await.is_err() {
+                    return Ok(());
+                }
+            }
+        }
+    }
+
+    async fn typing(&self, message: &Incoming<ReplyTo>) {
+        let _ = self.call("sendChatAction", json!({ "chat_id": message.reply_to.chat, "action": "typing" })).await;
+    }
+
+    async fn send(&self, message: &Incoming<ReplyTo>, reply: &str) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+        for (index, part) in split_message(reply, MESSAGE_LIMIT).into_iter().enumerate() {
+            let mut params = json!({ "chat_id": message.reply_to.chat, "text": part });
+            // In a busy group, the first part replies to the message it answers
+            if let (0, Some(message_id)) = (index, message.reply_to.message_id) {
+                params["reply_parameters"] = json!({ "message_id": message_id, "allow_sending_without_reply": true });
+            }
+            self.call("sendMessage", params).await?;
+        }
+        Ok(())
+    }
+}
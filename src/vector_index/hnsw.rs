@@ -0,0 +1,338 @@
+use super::{TagFilter, VectorIndex};
+use crate::knowledge::FactTag;
+use async_trait::async_trait;
+use serde::{Deserialize, Serialize};
+use std::cmp::{Ordering, Reverse};
+use std::collections::{BinaryHeap, HashMap, HashSet};
+use std::fs;
+use std::path::{Path, PathBuf};
+
+/// Maximum neighbours per node on the upper layers (`M` in the HNSW paper).
+const MAX_NEIGHBORS: usize = 16;
+/// Layer 0 keeps twice as many links, as recommended by the paper.
+const MAX_NEIGHBORS_LAYER0: usize = MAX_NEIGHBORS * 2;
+const EF_CONSTRUCTION: usize = 100;
+const EF_SEARCH: usize = 64;
+
+#[derive(Debug, Serialize, Deserialize)]
+struct Node {
+    id: String,
+    /// Normalised so that the dot product equals the cosine similarity.
+    vector: Vec<f32>,
+    /// Neighbour lists, one per layer the node lives on.
+    links: Vec<Vec<usize>>,
+    deleted: bool,
+    #[serde(default)]
+    tags: Vec<FactTag>,
+}
+
+#[derive(Debug, Serialize, Deserialize, Default)]
+struct Graph {
+    model: String,
+    nodes: Vec<Node>,
+    entry_point: Option<usize>,
+    rng_state: u64,
+}
+
+#[derive(Clone, Copy, PartialEq)]
+struct Candidate {
+    distance: f32,
+    node: usize,
+}
+
+impl Eq for Candidate {}
+
+impl PartialOrd for Candidate {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for Candidate {
+    fn cmp(&self, other: &Self) -> Ordering {
+        self.distance.total_cmp(&other.distance).then(self.node.cmp(&other.node))
+    }
+}
+
+/// Hierarchical navigable small world graph persisted as JSON under `data/`.
+pub struct HnswIndex {
+    path: PathBuf,
+    graph: Graph,
+    ids: HashMap<String, usize>,
+}
+
+impl HnswIndex {
+    /// Loads the index from `path`. A missing or unreadable file yields an empty index,
+    /// which the caller then rebuilds from the stored embeddings.
+    pub fn open(path: &Path) -> Self {
+        let graph = match fs::read_to_string(path) {
+            Ok(contents) => match serde_json::from_str::<Graph>(&contents) {
+                Ok(graph) if graph.is_valid() => graph,
+                _ => {
+                    status!("Vector index at {} is corrupted, it will be rebuilt", path.display());
+                    Graph::default()
+                }
+            },
+            Err(_) => Graph::default(),
+        };
+        let ids = graph
+            .nodes
+            .iter()
+            .enumerate()
+            .filter(|(_, node)| !node.deleted)
+            .map(|(i, node)| (node.id.clone(), i))
+            .collect();
+        HnswIndex {
+            path: path.to_path_buf(),
+            graph,
+            ids,
+        }
+    }
+
+    fn insert_node(&mut self, id: &str, vector: &[f32], tags: &[FactTag]) {
+        if let Some(old) = self.ids.remove(id) {
+            self.graph.nodes[old].deleted = true;
+        }
+
+        let vector = normalize(vector);
+        let level = self.graph.random_level();
+        let node = self.graph.nodes.len();
+        self.graph.nodes.push(Node {
+            id: id.to_string(),
+            vector,
+            links: vec![Vec::new(); level + 1],
+            deleted: false,
+            tags: tags.to_vec(),
+        });
+        self.ids.insert(id.to_string(), node);
+
+        let Some(entry) = self.graph.entry_point else {
+            self.graph.entry_point = Some(node);
+            return;
+        };
+
+        let query = self.graph.nodes[node].vector.clone();
+        let top_level = self.graph.nodes[entry].links.len() - 1;
+        let mut current = entry;
+
+        // Greedy descent through the layers above the new node
+        for layer in (level + 1..=top_level).rev() {
+            current = self.graph.greedy_closest(&query, current, layer);
+        }
+
+        // Connect the node on each layer it shares with the graph
+        let mut entry_points = vec![current];
+        for layer in (0..=level.min(top_level)).rev() {
+            let candidates = self.graph.search_layer(&query, &entry_points, EF_CONSTRUCTION, layer);
+            let max_links = if layer == 0 { MAX_NEIGHBORS_LAYER0 } else { MAX_NEIGHBORS };
+            let neighbors: Vec<usize> = candidates.iter().take(max_links).map(|c| c.node).collect();
+
+            self.graph.nodes[node].links[layer] = neighbors.clone();
+            for &neighbor in &neighbors {
+                self.graph.nodes[neighbor].links[layer].push(node);
+                if self.graph.nodes[neighbor].links[layer].len() > max_links {
+                    self.graph.prune(neighbor, layer, max_links);
+                }
+            }
+            entry_points = candidates.into_iter().map(|c| c.node).collect();
+        }
+
+        if level > top_level {
+            self.graph.entry_point = Some(node);
+        }
+    }
+}
+
+impl Graph {
+    /// Checks that every link points at an existing node on a layer it lives on.
+    fn is_valid(&self) -> bool {
+        if let Some(entry) = self.entry_point {
+            if entry >= self.nodes.len() {
+                return false;
+            }
+        }
+        self.nodes.iter().all(|node| {
+            !node.links.is_empty()
+                && node.links.iter().enumerate().all(|(layer, links)| {
+                    links
+                        .iter()
+                        .all(|&n| n < self.nodes.len() && self.nodes[n].links.len() > layer)
+                })
+        })
+    }
+
+    /// Draws a level from the exponential distribution used by HNSW (xorshift, no extra deps).
+    fn random_level(&mut self) -> usize {
+        if self.rng_state == 0 {
+            self.rng_state = 0x9E37_79B9_7F4A_7C15;
+        }
+        self.rng_state ^= self.rng_state << 13;
+        self.rng_state ^= self.rng_state >> 7;
+        self.rng_state ^= self.rng_state << 17;
+        let uniform = (self.rng_state >> 11) as f64 / (1u64 << 53) as f64;
+        let level_multiplier = 1.0 / (MAX_NEIGHBORS as f64).ln();
+        (-(uniform.max(f64::MIN_POSITIVE)).ln() * level_multiplier) as usize
+    }
+
+    fn distance(&self, query: &[f32], node: usize) -> f32 {
+        1.0 - dot(query, &self.nodes[node].vector)
+    }
+
+    fn greedy_closest(&self, query: &[f32], start: usize, layer: usize) -> usize {
+        let mut current = start;
+        let mut current_distance = self.distance(query, current);
+        loop {
+            let mut improved = false;
+            for &neighbor in &self.nodes[current].links[layer] {
+                let distance = self.distance(query, neighbor);
+                if distance < current_distance {
+                    current = neighbor;
+                    current_distance = distance;
+                    improved = true;
+                }
+            }
+            if !improved {
+                return current;
+            }
+        }
+    }
+
+    /// Beam search on one layer; returns up to `ef` candidates, closest first.
+    fn search_layer(&self, query: &[f32], entry_points: &[usize], ef: usize, layer: usize) -> Vec<Candidate> {
+        let mut visited: HashSet<usize> = entry_points.iter().copied().collect();
+        let mut to_visit: BinaryHeap<Reverse<Candidate>> = BinaryHeap::new();
+        let mut found: BinaryHeap<Candidate> = BinaryHeap::new();
+
+        for &node in entry_points {
+            let candidate = Candidate {
+                distance: self.distance(query, node),
+                node,
+            };
+            to_visit.push(Reverse(candidate));
+            found.push(candidate);
+        }
+
+        while let Some(Reverse(closest)) = to_visit.pop() {
+            if let Some(furthest) = found.peek() {
+                if closest.distance > furthest.distance && found.len() >= ef {
+                    break;
+                }
+            }
+            for &neighbor in &self.nodes[closest.node].links[layer] {
+                if !visited.insert(neighbor) {
+                    continue;
+                }
+                let candidate = Candidate {
+                    distance: self.distance(query, neighbor),
+                    node: neighbor,
+                };
+                let furthest = found.peek().map(|c| c.distance).unwrap_or(f32::MAX);
+                if found.len() < ef || candidate.distance < furthest {
+                    to_visit.push(Reverse(candidate));
+                    found.push(candidate);
+                    if found.len() > ef {
+                        found.pop();
+                    }
+                }
+            }
+        }
+
+        found.into_sorted_vec()
+    }
+
+    /// Keeps only the closest `max_links` neighbours of `node` on `layer`.
+    fn prune(&mut self, node: usize, layer: usize, max_links: usize) {
+        let vector = self.nodes[node].vector.clone();
+        let mut links: Vec<Candidate> = self.nodes[node].links[layer]
+            .iter()
+            .map(|&neighbor| Candidate {
+                distance: self.distance(&vector, neighbor),
+                node: neighbor,
+            })
+            .collect();
+        links.sort();
+        links.truncate(max_links);
+        self.nodes[node].links[layer] = links.into_iter().map(|c| c.node).collect();
+    }
+}
+
+#[async_trait]
+impl VectorIndex for HnswIndex {
+    async fn insert(&mut self, id: &str, vector: &[f32], tags: &[FactTag]) -> Result<(), Box<dyn std::error::Error>> {
+        self.insert_node(id, vector, tags);
+        Ok(())
+    }
+
+    async fn remove(&mut self, id: &str) -> Result<(), Box<dyn std::error::Error>> {
+        // Tombstone the node; its links still help navigation until the next rebuild
+        if let Some(node) = self.ids.remove(id) {
+            self.graph.nodes[node].deleted = true;
+        }
+        Ok(())
+    }
+
+    async fn search(
+        &self,
+        query: &[f32],
+        k: usize,
+        filter: &TagFilter,
+    ) -> Result<Vec<(String, f32)>, Box<dyn std::error::Error>> {
+        let Some(entry) = self.graph.entry_point else {
+            return Ok(Vec::new());
+        };
+        let query = normalize(query);
+        if query.len() != self.graph.nodes[entry].vector.len() {
+            return Err("Query embedding dimension does not match the vector index".into());
+        }
+
+        let mut current = entry;
+        for layer in (1..self.graph.nodes[entry].links.len()).rev() {
+            current = self.graph.greedy_closest(&query, current, layer);
+        }
+        // Widen the beam so enough candidates survive the tag filter
+        let candidates = self.graph.search_layer(&query, &[current], EF_SEARCH.max(k * 4), 0);
+
+        Ok(candidates
+            .into_iter()
+            .filter(|c| !self.graph.nodes[c.node].deleted && filter.matches(&self.graph.nodes[c.node].tags))
+            .take(k)
+            .map(|c| (self.graph.nodes[c.node].id.clone(), 1.0 - c.distance))
+            .collect())
+    }
+
+    async fn is_consistent(&self, model: &str, ids: &HashSet<String>) -> Result<bool, Box<dyn std::error::Error>> {
+        Ok(self.graph.model == model && self.ids.len() == ids.len() && ids.iter().all(|id| self.ids.contains_key(id)))
+    }
+
+    async fn reset(&mut self, model: &str) -> Result<(), Box<dyn std::error::Error>> {
+        self.graph = Graph {
+            model: model.to_string(),
+            ..Graph::default()
+        };
+        self.ids.clear();
+        Ok(())
+    }
+
+    async fn flush(&self) -> Result<(), Box<dyn std::error::Error>> {
+        if let Some(parent) = self.path.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        // Write to a temporary file first so a crash never leaves a half-written index
+        let tmp_path = self.path.with_extension("json.tmp");
+        fs::write(&tmp_path, serde_json::to_string(&self.graph)?)?;
+        fs::rename(&tmp_path, &self.path)?;
+        Ok(())
+    }
+}
+
+fn dot(a: &[f32], b: &[f32]) -> f32 {
+    a.iter().zip(b).map(|(x, y)| x * y).sum()
+}
+
+fn normalize(vector: &[f32]) -> Vec<f32> {
+    let norm = dot(vector, vector).sqrt();
+    if norm == 0.0 {
+        return vector.to_vec();
+    }
+    vector.iter().map(|x| x / norm).collect()
+}
@@ -0,0 +1,90 @@
+use crate::knowledge::FactTag;
+use crate::paths;
+use async_trait::async_trait;
+use serde::{Deserialize, Serialize};
+use std::collections::HashSet;
+
+mod hnsw;
+mod qdrant;
+
+pub use hnsw::HnswIndex;
+pub use qdrant::{QdrantIndex, QdrantSettings};
+
+/// Nearest-neighbour index over fact embeddings, keyed by fact key.
+#[async_trait]
+pub trait VectorIndex: Send + Sync {
+    /// Adds or replaces the vector stored for `id`.
+    async fn insert(&mut self, id: &str, vector: &[f32], tags: &[FactTag]) -> Result<(), Box<dyn std::error::Error>>;
+
+    async fn remove(&mut self, id: &str) -> Result<(), Box<dyn std::error::Error>>;
+
+    /// Returns up to `k` ids matching `filter` with their cosine similarity to `query`,
+    /// most similar first.
+    async fn search(
+        &self,
+        query: &[f32],
+        k: usize,
+        filter: &TagFilter,
+    ) -> Result<Vec<(String, f32)>, Box<dyn std::error::Error>>;
+
+    /// Whether the index holds vectors from `model` for exactly these ids; otherwise it
+    /// has to be rebuilt from the stored fact embeddings.
+    async fn is_consistent(&self, model: &str, ids: &HashSet<String>) -> Result<bool, Box<dyn std::error::Error>>;
+
+    /// Drops everything and starts over for vectors produced by `model`.
+    async fn reset(&mut self, model: &str) -> Result<(), Box<dyn std::error::Error>>;
+
+    /// Persists pending changes.
+    async fn flush(&self) -> Result<(), Box<dyn std::error::Error>>;
+}
+
+/// Tag restrictions applied to retrieved facts.
+#[derive(Debug, Clone, Default)]
+pub struct TagFilter {
+    /// Facts need at least one of these tags (empty = no restriction).
+    pub include: Vec<FactTag>,
+    /// Facts with any of these tags are left out.
+    pub exclude: Vec<FactTag>,
+}
+
+impl TagFilter {
+    pub fn matches(&self, tags: &[FactTag]) -> bool {
+        if tags.iter().any(|tag| self.exclude.contains(tag)) {
+            return false;
+        }
+        self.include.is_empty() || tags.iter().any(|tag| self.include.contains(tag))
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Eq, Default)]
+#[serde(rename_all = "lowercase")]
+pub enum VectorIndexKind {
+    /// In-process HNSW graph stored under `data/`.
+    #[default]
+    Hnsw,
+    Qdrant,
+    /// pgvector search in the Postgres storage backend.
+    Postgres,
+}
+
+/// Creates the configured vector index. `character` scopes shared backends so several
+/// characters can live in one collection; `model` names the embedding model of new vectors.
+pub fn create_vector_index(
+    kind: VectorIndexKind,
+    qdrant: Option<&QdrantSettings>,
+    character: &str,
+    model: &str,
+) -> Box<dyn VectorIndex> {
+    match (kind, qdrant) {
+        (VectorIndexKind::Qdrant, Some(settings)) => Box::new(QdrantIndex::new(settings, character, model)),
+        (VectorIndexKind::Qdrant, None) => {
+            status!("Qdrant selected but no `retrieval.qdrant` settings given, using the local index");
+            Box::new(HnswIndex::open(&paths::data_path("vector_index.json")))
+        }
+        (VectorIndexKind::Postgres, _) => {
+            status!("pgvector search needs the postgres storage backend, using the local index");
+            Box::new(HnswIndex::open(&paths::data_path("vector_index.json")))
+        }
+        (VectorIndexKind::Hnsw, _) => Box::new(HnswIndex::open(&paths::data_path("vector_index.json"))),
+    }
+}
@@ -0,0 +1,272 @@
+use super::{TagFilter, VectorIndex};
+use crate::knowledge::FactTag;
+use async_trait::async_trait;
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+use sha2::{Digest, Sha256};
+use std::collections::HashSet;
+
+/// Points fetched per scroll request when checking consistency.
+const SCROLL_PAGE_SIZE: usize = 256;
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct QdrantSettings {
+    /// Base URL of the Qdrant REST API, e.g. `http://localhost:6333`.
+    pub url: String,
+    #[serde(default)]
+    pub api_key: Option<String>,
+    #[serde(default = "default_collection")]
+    pub collection: String,
+}
+
+fn default_collection() -> String {
+    "alya_facts".to_string()
+}
+
+/// Vector index stored in a Qdrant collection, talking to its REST API. Points carry the
+/// fact key, tags, character name and embedding model as payload.
+pub struct QdrantIndex {
+    base_url: String,
+    api_key: Option<String>,
+    collection: String,
+    character: String,
+    /// Embedding model written into the payload of new points.
+    model: String,
+    client: reqwest::Client,
+    collection_ready: bool,
+}
+
+impl QdrantIndex {
+    pub fn new(settings: &QdrantSettings, character: &str, model: &str) -> Self {
+        QdrantIndex {
+            base_url: settings.url.trim_end_matches('/').to_string(),
+            api_key: settings.api_key.clone(),
+            collection: settings.collection.clone(),
+            character: character.to_string(),
+            model: model.to_string(),
+            client: reqwest::Client::builder()
+                .timeout(std::time::Duration::from_secs(30))
+                .build()
+                .unwrap_or_default(),
+            collection_ready: false,
+        }
+    }
+
+    fn request(&self, method: reqwest::Method, path: &str) -> reqwest::RequestBuilder {
+        let request = self
+            .client
+            .request(method, format!("{}/collections/{}{}", self.base_url, self.collection, path));
+        match &self.api_key {
+            Some(api_key) => request.header("api-key", api_key),
+            None => request,
+        }
+    }
+
+    /// Qdrant ids must be integers or UUIDs, so fact keys are hashed into a UUID.
+    fn point_id(&self, key: &str) -> String {
+        let hash = Sha256::digest(format!("{}\0{}", self.character, key).as_bytes());
+        let hex = format!("{:x}", hash);
+        format!("{}-{}-{}-{}-{}", &hex[0..8], &hex[8..12], &hex[12..16], &hex[16..20], &hex[20..32])
+    }
+
+    fn character_condition(&self) -> Value {
+        json!({ "key": "character", "match": { "value": self.character } })
+    }
+
+    /// Creates the collection on first use, or checks that an existing one fits `dimension`.
+    async fn ensure_collection(&mut self, dimension: usize) -> Result<(), Box<dyn std::error::Error>> {
+        if self.collection_ready {
+            return Ok(());
+        }
+
+        let response = self.request(reqwest::Method::GET, "").send().await?;
+        if response.status() == reqwest::StatusCode::NOT_FOUND {
+            status!("Creating Qdrant collection {}...", self.collection);
+            check(
+                self.request(reqwest::Method::PUT, "")
+                    .json(&json!({ "vectors": { "size": dimension, "distance": "Cosine" } }))
+                    .send()
+                    .await?,
+            )
+            .await?;
+            for field in ["character", "tags", "model"] {
+                check(
+                    self.request(reqwest::Method::PUT, "/index?wait=true")
+                        .json(&json!({ "field_name": field, "field_schema": "keyword" }))
+                        .send()
+                        .await?,
+                )
+                .await?;
+            }
+        } else {
+            let response = check(response).await?;
+            let info: Value = response.json().await?;
+            let size = info
+                .pointer("/result/config/params/vectors/size")
+                .and_then(|size| size.as_u64());
+            if let Some(size) = size {
+                if size as usize != dimension {
+                    return Err(format!(
+                        "Qdrant collection {} stores {}-dimensional vectors but the embedder produces {}; \
+                        configure a different collection",
+                        self.collection, size, dimension
+                    )
+                    .into());
+                }
+            }
+        }
+
+        self.collection_ready = true;
+        Ok(())
+    }
+}
+
+async fn check(response: reqwest::Response) -> Result<reqwest::Response, Box<dyn std::error::Error>> {
+    if response.status().is_success() {
+        return Ok(response);
+    }
+    let status = response.status();
+    let body = response.text().await.unwrap_or_default();
+    Err(format!("Qdrant request failed ({}): {}", status, body).into())
+}
+
+#[async_trait]
+impl VectorIndex for QdrantIndex {
+    async fn insert(&mut self, id: &str, vector: &[f32], tags: &[FactTag]) -> Result<(), Box<dyn std::error::Error>> {
+        self.ensure_collection(vector.len()).await?;
+        let point = json!({
+            "id": self.point_id(id),
+            "vector": vector,
+            "payload": {
+                "fact_key": id,
+                "character": self.character,
+                "tags": tags,
+                "model": self.model,
+            }
+        });
+        check(
+            self.request(reqwest::Method::PUT, "/points?wait=true")
+                .json(&json!({ "points": [point] }))
+                .send()
+                .await?,
+        )
+        .await?;
+        Ok(())
+    }
+
+    async fn remove(&mut self, id: &str) -> Result<(), Box<dyn std::error::Error>> {
+        let response = self
+            .request(reqwest::Method::POST, "/points/delete?wait=true")
+            .json(&json!({ "points": [self.point_id(id)] }))
+            .send()
+            .await?;
+        if response.status() != reqwest::StatusCode::NOT_FOUND {
+            check(response).await?;
+        }
+        Ok(())
+    }
+
+    async fn search(
+        &self,
+        query: &[f32],
+        k: usize,
+        filter: &TagFilter,
+    ) -> Result<Vec<(String, f32)>, Box<dyn std::error::Error>> {
+        let mut must = vec![self.character_condition()];
+        if !filter.include.is_empty() {
+            must.push(json!({ "key": "tags", "match": { "any": filter.include } }));
+        }
+        let mut must_not = Vec::new();
+        if !filter.exclude.is_empty() {
+            must_not.push(json!({ "key": "tags", "match": { "any": filter.exclude } }));
+        }
+
+        let response = self
+            .request(reqwest::Method::POST, "/points/search")
+            .json(&json!({
+                "vector": query,
+                "limit": k,
+                "with_payload": ["fact_key"],
+                "filter": { "must": must, "must_not": must_not },
+            }))
+            .send()
+            .await?;
+        if response.status() == reqwest::StatusCode::NOT_FOUND {
+            return Ok(Vec::new());
+        }
+        let response = check(response).await?;
+        let results: Value = response.json().await?;
+
+        Ok(results
+            .get("result")
+            .and_then(|result| result.as_array())
+            .map(|points| {
+                points
+                    .iter()
+                    .filter_map(|point| {
+                        let key = point.pointer("/payload/fact_key")?.as_str()?;
+                        let score = point.get("score")?.as_f64()?;
+                        Some((key.to_string(), score as f32))
+                    })
+                    .collect()
+            })
+            .unwrap_or_default())
+    }
+
+    async fn is_consistent(&self, model: &str, ids: &HashSet<String>) -> Result<bool, Box<dyn std::error::Error>> {
+        let mut stored = HashSet::new();
+        let mut offset = Value::Null;
+        loop {
+            let response = self
+                .request(reqwest::Method::POST, "/points/scroll")
+                .json(&json!({
+                    "limit": SCROLL_PAGE_SIZE,
+                    "offset": offset,
+                    "with_payload": ["fact_key", "model"],
+                    "with_vector": false,
+                    "filter": { "must": [self.character_condition()] },
+                }))
+                .send()
+                .await?;
+            if response.status() == reqwest::StatusCode::NOT_FOUND {
+                return Ok(ids.is_empty());
+            }
+            let response = check(response).await?;
+            let page: Value = response.json().await?;
+
+            for point in page.pointer("/result/points").and_then(|p| p.as_array()).into_iter().flatten() {
+                if point.pointer("/payload/model").and_then(|m| m.as_str()) != Some(model) {
+                    return Ok(false);
+                }
+                if let Some(key) = point.pointer("/payload/fact_key").and_then(|k| k.as_str()) {
+                    stored.insert(key.to_string());
+                }
+            }
+
+            offset = page.pointer("/result/next_page_offset").cloned().unwrap_or(Value::Null);
+            if offset.is_null() {
+                break;
+            }
+        }
+        Ok(stored == *ids)
+    }
+
+    async fn reset(&mut self, model: &str) -> Result<(), Box<dyn std::error::Error>> {
+        // Only this character's points are dropped; the collection may be shared
+        let response = self
+            .request(reqwest::Method::POST, "/points/delete?wait=true")
+            .json(&json!({ "filter": { "must": [self.character_condition()] } }))
+            .send()
+            .await?;
+        if response.status() != reqwest::StatusCode::NOT_FOUND {
+            check(response).await?;
+        }
+        self.model = model.to_string();
+        Ok(())
+    }
+
+    async fn flush(&self) -> Result<(), Box<dyn std::error::Error>> {
+        // Every write already waits for Qdrant to apply it
+        Ok(())
+    }
+}
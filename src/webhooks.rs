@@ -0,0 +1,96 @@
+use chrono::Utc;
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+use std::collections::HashMap;
+use std::sync::Mutex;
+use std::time::Duration;
+use tokio::task::JoinSet;
+
+/// How long a webhook has to answer, and how long the chatbot waits for deliveries still
+/// in flight when it exits.
+const TIMEOUT: Duration = Duration::from_secs(10);
+
+/// The kinds of events webhooks can receive.
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum EventKind {
+    MessageReceived,
+    ReplyGenerated,
+    FactLearned,
+    Error,
+}
+
+/// A URL that is sent events as JSON.
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct Webhook {
+    pub url: String,
+    /// The events to send; all of them if empty.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub events: Vec<EventKind>,
+    /// Extra headers to send, such as an `Authorization` the receiver expects.
+    #[serde(default, skip_serializing_if = "HashMap::is_empty")]
+    pub headers: HashMap<String, String>,
+}
+
+/// The events on their way to webhooks.
+static DELIVERIES: Mutex<Option<JoinSet<()>>> = Mutex::new(None);
+
+/// Sends events to the configured webhooks in the background, so a slow or failing
+/// receiver never holds up the chat.
+pub struct Webhooks {
+    hooks: Vec<Webhook>,
+    character: String,
+    client: reqwest::Client,
+}
+
+impl Webhooks {
+    pub fn new(hooks: Vec<Webhook>, character: &str) -> Self {
+        Webhooks {
+            hooks,
+            character: character.to_string(),
+            client: reqwest::Client::builder().timeout(TIMEOUT).build().unwrap_or_default(),
+        }
+    }
+
+    /// Sends an event of `kind` to the webhooks that want it. `data` is merged into the
+    /// event, next to its `event`, `character` and `timestamp`.
+    pub fn emit(&self, kind: EventKind, data: Value) {
+        let hooks: Vec<&Webhook> = self.hooks.iter().filter(|hook| hook.events.is_empty() || hook.events.contains(&kind)).collect();
+        if hooks.is_empty() {
+            return;
+        }
+        let mut event = json!({
+            "event": kind,
+            "character": self.character,
+            "timestamp": Utc::now(),
+        });
+        if let (Some(event), Value::Object(data)) = (event.as_object_mut(), data) {
+            event.extend(data);
+        }
+
+        let mut deliveries = DELIVERIES.lock().unwrap();
+        let deliveries = deliveries.get_or_insert_with(JoinSet::new);
+        // Deliveries that are done are not kept around
+        while deliveries.try_join_next().is_some() {}
+        for hook in hooks {
+            let mut request = self.client.post(&hook.url).json(&event);
+            for (name, value) in &hook.headers {
+                request = request.header(name, value);
+            }
+            let url = hook.url.clone();
+            deliveries.spawn(async move {
+                if let Err(e) = request.send().await.and_then(|response| response.error_for_status()) {
+                    status!("Could not send an event to the webhook {}: {}", url, e.without_url());
+                }
+            });
+        }
+    }
+}
+
+/// Waits a little for the events still on their way to webhooks, before exiting.
+pub async fn flush() {
+    let Some(mut deliveries) = DELIVERIES.lock().unwrap().take() else {
+        return;
+    };
+    let _ = tokio::time::timeout(TIMEOUT, async { while deliveries.join_next().await.is_some() {} }).await;
+}
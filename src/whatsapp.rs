@@ -0,0 +1,258 @@
+use crate::gateway::{split_message, Connector, Incoming};
+use crate::server::Calls;
+use async_trait::async_trait;
+use axum::body::Bytes;
+use axum::extract::{Query, State};
+use axum::http::{HeaderMap, StatusCode};
+use axum::routing::get;
+use axum::Router;
+use hmac::{Hmac, Mac};
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+use sha2::Sha256;
+use std::collections::{HashMap, VecDeque};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use tokio::net::TcpListener;
+use tokio::sync::mpsc;
+
+/// The secret holding the access token of the WhatsApp Business account.
+pub const ACCESS_TOKEN_NAME: &str = "WHATSAPP_ACCESS_TOKEN";
+/// The secret holding the Meta app's secret, which signs the webhook's requests.
+pub const APP_SECRET_NAME: &str = "WHATSAPP_APP_SECRET";
+/// The secret holding the token Meta sends back when the webhook is set up.
+pub const VERIFY_TOKEN_NAME: &str = "WHATSAPP_VERIFY_TOKEN";
+const GRAPH_API: &str = "https://graph.facebook.com/v21.0";
+/// The most characters WhatsApp allows in a text message.
+const MESSAGE_LIMIT: usize = 4096;
+/// WhatsApp's error for a free-form message sent more than 24 hours after the user last wrote.
+const OUTSIDE_WINDOW: i64 = 131047;
+/// How many message IDs are remembered to leave out the messages Meta delivers twice.
+const SEEN_MESSAGES: usize = 1000;
+
+/// The WhatsApp Business phone number the character chats from.
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct WhatsAppSettings {
+    /// The ID of the phone number, from the API Setup page of the Meta app.
+    pub phone_number_id: String,
+    /// The address and port the webhook listens on, behind a reverse proxy with HTTPS.
+    #[serde(default = "default_bind")]
+    pub bind: String,
+    /// An approved message template sent instead of a reply that is no longer allowed, when
+    /// more than 24 hours passed since the user's message; such replies are dropped without
+    /// one.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub template: Option<String>,
+    /// The language code of the template.
+    #[serde(default = "default_template_language")]
+    pub template_language: String,
+}
+
+fn default_bind() -> String {
+    "127.0.0.1:8090".to_string()
+}
+
+fn default_template_language() -> String {
+    "en_US".to_string()
+}
+
+/// The credentials of the WhatsApp Business account.
+pub struct Credentials {
+    pub access_token: String,
+    pub app_secret: String,
+    pub verify_token: String,
+}
+
+/// The WhatsApp Business phone number. It receives the messages on the webhook at
+/// `/webhook` and answers them with the Cloud API, with a conversation for each phone number.
+pub struct Bot {
+    settings: WhatsAppSettings,
+    credentials: Credentials,
+    client: reqwest::Client,
+    seen: Mutex<VecDeque<String>>,
+}
+
+impl Bot {
+    pub fn new(settings: WhatsAppSettings, credentials: Credentials) -> Self {
+        Bot {
+            settings,
+            credentials,
+            client: reqwest::Client::new(),
+            seen: Mutex::new(VecDeque::new()),
+        }
+    }
+}
+
+#[derive(Clone)]
+struct Webhook {
+    bot: Arc<Bot>,
+    incoming: mpsc::Sender<Incoming<String>>,
+}
+
+#[async_trait]
+impl Connector for Bot {
+    /// The ID of the message, which is marked as read.
+    type ReplyTo = String;
+
+    const NAME: &'static str = "whatsapp";
+    const TITLE: &'static str = "WhatsApp";
+    const PER_USER: bool = true;
+    const TYPING_EVERY: Duration = Duration::from_secs(20);
+
+    /// Serves the webhook until the server fails.
+    async fn receive(self: Arc<Self>, incoming: mpsc::Sender<Incoming<String>>, _calls: Calls) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+        let listener = TcpListener::bind(&self.settings.bind).await?;
+        status!("Receiving WhatsApp messages on http://{}/webhook", listener.local_addr()?);
+        let app = Router::new().route("/webhook", get(verify).post(receive)).with_state(Webhook { bot: self, incoming });
+        axum::serve(listener, app).await?;
+        Ok(())
+    }
+
+    /// Shows the message as read, with "typing..." until the reply arrives.
+    async fn typing(&self, message: &Incoming<String>) {
+        let read = json!({
+            "messaging_product": "whatsapp",
+            "status": "read",
+            "message_id": message.reply_to,
+            "typing_indicator": { "type": "text" },
+        });
+        let _ = self.post(read).await;
+    }
+
+    /// Failures are reported as they happen, so this never fails.
+    async fn send(&self, message: &Incoming<String>, reply: &str) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+        self.send_text(&message.user, reply).await;
+        Ok(())
+    }
+}
+
+/// Answers Meta's check when the webhook is set up.
+async fn verify(State(Webhook { bot, .. }): State<Webhook>, Query(query): Query<HashMap<String, String>>) -> Result<String, StatusCode> {
+    let token = query.get("hub.verify_token").map(String::as_str);
+    if query.get("hub.mode").map(String::as_str) != Some("subscribe") || token != Some(bot.credentials.verify_token.as_str()) {
+        return Err(StatusCode::FORBIDDEN);
+    }
+    status!("The WhatsApp webhook was verified");
+    Ok(query.get("hub.challenge").cloned().unwrap_or_default())
+}
+
+async fn receive(State(Webhook { bot, incoming }): State<Webhook>, headers: HeaderMap, body: Bytes) -> StatusCode {
+    let signature = headers.get("x-hub-signature-256").and_then(|value| value.to_str().ok()).unwrap_or_default();
+    if !signed(&bot.credentials.app_secret, &body, signature) {
+        return StatusCode::UNAUTHORIZED;
+    }
+    let Ok(update) = serde_json::from_slice::<Value>(&body) else {
+        return StatusCode::BAD_REQUEST;
+    };
+    let changes = update["entry"].as_array().into_iter().flatten().flat_map(|entry| entry["changes"].as_array().into_iter().flatten());
+    for change in changes {
+        for message in change["value"]["messages"].as_array().into_iter().flatten() {
+            let (Some(id), Some(from)) = (message["id"].as_str(), message["from"].as_str()) else {
+                continue;
+            };
+            if !bot.first_time(id) {
+                continue;
+            }
+            let Some(text) = message["text"]["body"].as_str() else {
+                bot.send_text(from, "Sorry, I can only read text messages.").await;
+                continue;
+            };
+            let message = Incoming {
+                user: from.to_string(),
+                channel: from.to_string(),
+                text: text.to_string(),
+                reply_to: id.to_string(),
+            };
+            let _ = incoming.send(message).await;
+        }
+    }
+    StatusCode::OK
+}
+
+/// Whether `signature` is the `sha256=` HMAC of the body with the app secret.
+fn signed(app_secret: &str, body: &[u8], signature: &str) -> bool {
+    let Some(hex) = signature.strip_prefix("sha256=") else {
+        return false;
+    };
+    let Some(signature) = (0..hex.len()).step_by(2).map(|i| hex.get(i..i + 2).and_then(|byte| u8::from_str_radix(byte, 16).ok())).collect::<Option<Vec<u8>>>() else {
+        return false;
+    };
+    let Ok(mut mac) = Hmac::<Sha256>::new_from_slice(app_secret.as_bytes()) else {
+        return false;
+    };
+    mac.update(body);
+    mac.verify_slice(&signature).is_ok()
+}
+
+impl Bot {
+    /// Whether a message is new, rather than one Meta delivered again.
+    fn first_time(&self, id: &str) -> bool {
+        let mut seen = self.seen.lock().unwrap();
+        if seen.iter().any(|seen| seen == id) {
+            return false;
+        }
+        if seen.len() == SEEN_MESSAGES {
+            seen.pop_front();
+        }
+        seen.push_back(id.to_string());
+        true
+    }
+
+    /// Sends a text, or the template when the 24 hours to answer in have passed.
+    async fn send_text(&self, to: &str, text: &str) {
+        for part in split_message(text, MESSAGE_LIMIT) {
+            let message = json!({ "messaging_product": "whatsapp", "to": to, "type": "text", "text": { "body": part } });
+            match self.post(message).await {
+                Ok(()) => {}
+                Err(Some(OUTSIDE_WINDOW)) => {
+                    self.send_template(to).await;
+                    return;
+                }
+                Err(_) => return,
+            }
+        }
+    }
+
+    async fn send_template(&self, to: &str) {
+        let Some(template) = &self.settings.template else {
+            status!("Dropped a WhatsApp reply to {}, who wrote more than 24 hours ago; set whatsapp.template to reach them", to);
+            return;
+        };
+        let message = json!({
+            "messaging_product": "whatsapp",
+            "to": to,
+            "type": "template",
+            "template": { "name": template, "language": { "code": self.settings.template_language } },
+        });
+        let _ = self.post(message).await;
+    }
+
+    /// Posts to the messages endpoint. A failure is reported here, and gives WhatsApp's error
+    /// code if it sent one.
+    async fn post(&self, message: Value) -> Result<(), Option<i64>> {
+        let response = self
+            .client
+            .post(format!("{}/{}/messages", GRAPH_API, self.settings.phone_number_id))
+            .bearer_auth(&self.credentials.access_token)
+            .json(&message)
+            .send()
+            .await;
+        let response: Value = match response {
+            Ok(response) => response.json().await.unwrap_or_default(),
+            Err(e) => {
+                status!("Could not reach WhatsApp: {}", e);
+                return Err(None);
+            }
+        };
+        match response.get("error") {
+            None => Ok(()),
+            Some(error) => {
+                let code = error["code"].as_i64();
+                if code != Some(OUTSIDE_WINDOW) {
+                    status!("WhatsApp refused a message: {}", error["message"].as_str().unwrap_or_default());
+                }
+                Err(code)
+            }
+        }
+    }
+}
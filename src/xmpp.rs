@@ -0,0 +1,218 @@
+use crate::gateway::{Connector, Incoming};
+use crate::server::Calls;
+use async_trait::async_trait;
+use futures::StreamExt;
+use serde::{Deserialize, Serialize};
+use std::sync::{Arc, OnceLock};
+use std::time::Duration;
+use tokio::sync::mpsc;
+use tokio_xmpp::connect::{DnsConfig, StartTlsServerConnector};
+use tokio_xmpp::jid::{BareJid, Jid};
+use tokio_xmpp::parsers::chatstates::ChatState;
+use tokio_xmpp::parsers::message::{Lang, Message, MessageType};
+use tokio_xmpp::parsers::muc::muc::{History, Muc};
+use tokio_xmpp::parsers::ns;
+use tokio_xmpp::parsers::presence::{Presence, Type as PresenceType};
+use tokio_xmpp::{Client, Event, Stanza};
+
+/// The secret holding the password of the bot's account.
+pub const PASSWORD_NAME: &str = "XMPP_PASSWORD";
+
+/// The bot's account and the group chats it joins.
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct XmppSettings {
+    /// The bot's account, such as `alya@example.org`.
+    pub jid: String,
+    /// The server to connect to, when its domain's SRV records do not name it.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub server: Option<String>,
+    #[serde(default = "default_port")]
+    pub port: u16,
+    /// The group chats (MUC rooms) to join, such as `lounge@conference.example.org`.
+    #[serde(default)]
+    pub rooms: Vec<String>,
+    /// The nick in the rooms; the account's name if not given.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub nick: Option<String>,
+}
+
+fn default_port() -> u16 {
+    5222
+}
+
+/// The bot's account. It stays online, as the XMPP library reconnects when the connection
+/// is lost, and answers direct messages and the room messages addressed to its nick, with a
+/// conversation for each account, and for each nick in a room. Subscription requests are
+/// accepted, so anyone can add the bot as a contact.
+pub struct Bot {
+    settings: XmppSettings,
+    password: String,
+    /// The stanzas to send, once connected.
+    outgoing: OnceLock<mpsc::UnboundedSender<Stanza>>,
+}
+
+impl Bot {
+    pub fn new(settings: XmppSettings, password: String) -> Self {
+        Bot { settings, password, outgoing: OnceLock::new() }
+    }
+
+    fn send_stanza(&self, stanza: Stanza) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+        self.outgoing.get().ok_or("Not connected to XMPP")?.send(stanza)?;
+        Ok(())
+    }
+}
+
+/// Who to answer, and in a room, the nick to address the reply to.
+pub struct ReplyTo {
+    to: Jid,
+    sender: Option<String>,
+}
+
+#[async_trait]
+impl Connector for Bot {
+    type ReplyTo = ReplyTo;
+
+    const NAME: &'static str = "xmpp";
+    const TITLE: &'static str = "XMPP";
+    const PER_USER: bool = true;
+    const TYPING_EVERY: Duration = Duration::from_secs(30);
+
+    /// Fails only if the settings are wrong.
+    async fn receive(self: Arc<Self>, incoming: mpsc::Sender<Incoming<ReplyTo>>, _calls: Calls) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+        let settings = &self.settings;
+        let jid = BareJid::new(&settings.jid)?;
+        let nick = match (&settings.nick, jid.node()) {
+            (Some(nick), _) => nick.clone(),
+            (None, Some(node)) => node.as_str().to_string(),
+            (None, None) => return Err("The XMPP jid has no account name; set xmpp.nick".into()),
+        };
+        let rooms = settings.rooms.iter().map(|room| BareJid::new(room)).collect::<Result<Vec<_>, _>>()?;
+        let password = self.password.clone();
+        let mut client = match &settings.server {
+            Some(host) => {
+                let dns = DnsConfig::NoSrv { host: host.clone(), port: settings.port, resolver: None };
+                Client::new_with_connector(jid, password, StartTlsServerConnector::from(dns), Default::default())
+            }
+            None => Client::new(jid, password),
+        };
+
+        // Replies are written in the background, and sent from here
+        let (replies, mut outgoing) = mpsc::unbounded_channel::<Stanza>();
+        let _ = self.outgoing.set(replies);
+        loop {
+            let event = tokio::select! {
+                event = client.next() => event,
+                Some(stanza) = outgoing.recv() => {
+                    if let Err(e) = client.send_stanza(stanza).await {
+                        status!("Could not send an XMPP message: {}", e);
+                    }
+                    continue;
+                }
+            };
+            let stanza = match event {
+                None => return Ok(()),
+                // A resumed stream is still in its rooms; a new one joins them again
+                Some(Event::Online { bound_jid, resumed, .. }) => {
+                    if !resumed {
+                        status!("Connected to XMPP as {}", bound_jid.to_bare());
+                        client.send_stanza(Presence::new(PresenceType::None).into()).await?;
+                        for room in &rooms {
+                            let join = Presence::new(PresenceType::None)
+                                .with_to(room.with_resource_str(&nick)?)
+                                .with_payload(Muc::new().with_history(History::new().with_maxstanzas(0)));
+                            client.send_stanza(join.into()).await?;
+                        }
+                    }
+                    continue;
+                }
+                Some(Event::Disconnected(e)) => {
+                    status!("Lost the connection to XMPP, reconnecting: {}", e);
+                    continue;
+                }
+                Some(Event::Stanza(stanza)) => stanza,
+            };
+            match stanza {
+                Stanza::Presence(presence) => match (presence.type_, presence.from) {
+                    (PresenceType::Subscribe, Some(from)) => {
+                        let _ = client.send_stanza(Presence::new(PresenceType::Subscribed).with_to(from).into()).await;
+                    }
+                    (PresenceType::Error, Some(from)) if rooms.contains(&from.to_bare()) => {
+                        status!("Could not join the XMPP room {}", from.to_bare());
+                    }
+                    _ => {}
+                },
+                Stanza::Message(message) => {
+                    if let Some(message) = incoming_message(&message, &rooms, &nick) {
+                        let _ = incoming.send(message).await;
+                    }
+                }
+                Stanza::Iq(_) => {}
+            }
+        }
+    }
+
+    /// In a direct chat, shows that the character is typing.
+    async fn typing(&self, message: &Incoming<ReplyTo>) {
+        if message.reply_to.sender.is_none() {
+            let _ = self.send_stanza(Message::chat(message.reply_to.to.clone()).with_payload(ChatState::Composing).into());
+        }
+    }
+
+    async fn send(&self, message: &Incoming<ReplyTo>, reply: &str) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+        let to = message.reply_to.to.clone();
+        let message = match &message.reply_to.sender {
+            Some(sender) => Message::groupchat(to).with_body(Lang::default(), format!("{}: {}", sender, reply)),
+            None => Message::chat(to).with_body(Lang::default(), reply.to_string()).with_payload(ChatState::Active),
+        };
+        self.send_stanza(message.into())
+    }
+}
+
+/// What a message asks of the bot, if anything. In a room, the user is the room and the
+/// nick, so each nick has a conversation of its own.
+fn incoming_message(message: &Message, rooms: &[BareJid], nick: &str) -> Option<Incoming<ReplyTo>> {
+    let from = message.from.clone()?;
+    let (_, body) = message.get_best_body(Vec::new())?;
+    // Messages sent while the bot was away, which the server delivers late, are not answered
+    if message.payloads.iter().any(|payload| payload.is("delay", ns::DELAY)) {
+        return None;
+    }
+    let room = from.to_bare();
+    match message.type_ {
+        MessageType::Groupchat => {
+            let sender = from.resource()?.as_str();
+            if sender == nick || !rooms.contains(&room) {
+                return None;
+            }
+            let text = addressed(body, nick).filter(|text| !text.is_empty())?;
+            Some(Incoming {
+                user: format!("{}/{}", room, sender.to_lowercase()),
+                channel: room.to_string(),
+                text,
+                reply_to: ReplyTo { to: room.into(), sender: Some(sender.to_string()) },
+            })
+        }
+        MessageType::Chat | MessageType::Normal => {
+            let text = body.trim().to_string();
+            if text.is_empty() {
+                return None;
+            }
+            // A private message from someone in a room is known by their nick there
+            let user = if rooms.contains(&room) { from.to_string() } else { room.to_string() };
+            Some(Incoming {
+                channel: user.clone(),
+                user,
+                text,
+                reply_to: ReplyTo { to: from, sender: None },
+            })
+        }
+        _ => None,
+    }
+}
+
+/// The text of a room message addressed to `nick`, as in "alya: hi" or "alya, hi".
+fn addressed(text: &str, nick: &str) -> Option<String> {
+    let head = text.get(..nick.len())?;
+    let rest = text[nick.len()..].strip_prefix([':', ','])?;
+    head.eq_ignore_ascii_case(nick).then(|| rest.trim().to_string())
+}
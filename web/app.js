@@ -0,0 +1,156 @@
+// The chat page of `alya serve`. It talks to the HTTP API with the key from the settings,
+// as a user of its own that is made up once per browser.
+
+const $ = (id) => document.getElementById(id);
+
+let user = localStorage.getItem("alya-user");
+if (!user) {
+  user = crypto.randomUUID();
+  localStorage.setItem("alya-user", user);
+}
+$("api-key").value = localStorage.getItem("alya-key") || "";
+$("api-key").addEventListener("change", () => {
+  localStorage.setItem("alya-key", $("api-key").value.trim());
+  loadCharacter();
+});
+
+function headers() {
+  const key = localStorage.getItem("alya-key");
+  const headers = { "Content-Type": "application/json" };
+  if (key) headers["Authorization"] = "Bearer " + key;
+  return headers;
+}
+
+async function api(method, path, body) {
+  const response = await fetch(path, { method, headers: headers(), body: body && JSON.stringify(body) });
+  const json = await response.json();
+  if (!response.ok) throw new Error(json.text || json.error);
+  return json;
+}
+
+for (const button of document.querySelectorAll("nav button")) {
+  button.addEventListener("click", () => {
+    for (const other of document.querySelectorAll("nav button, .tab")) other.classList.remove("active");
+    button.classList.add("active");
+    $(button.dataset.tab).classList.add("active");
+  });
+}
+
+function addMessage(kind, text) {
+  const div = document.createElement("div");
+  div.className = "message " + kind;
+  div.textContent = text;
+  $("messages").appendChild(div);
+  $("messages").scrollTop = $("messages").scrollHeight;
+  return div;
+}
+
+async function loadCharacter() {
+  try {
+    const character = await api("GET", "/character");
+    $("name").textContent = character.name;
+    document.title = character.name;
+  } catch (e) {
+    addMessage("error", e.message + ". Is the API key in the settings right?");
+  }
+}
+
+// Reads the server-sent events of /chat/stream, calling `on` with each event's name and data.
+async function readEvents(response, on) {
+  const reader = response.body.getReader();
+  const decoder = new TextDecoder();
+  let buffer = "";
+  for (;;) {
+    const { done, value } = await reader.read();
+    if (done) break;
+    buffer += decoder.decode(value, { stream: true });
+    let end;
+    while ((end = buffer.indexOf("\n\n")) >= 0) {
+      const block = buffer.slice(0, end);
+      buffer = buffer.slice(end + 2);
+      let event = "message";
+      let data = "";
+      for (const line of block.split("\n")) {
+        if (line.startsWith("event:")) event = line.slice(6).trim();
+        if (line.startsWith("data:")) data += line.slice(5).trim();
+      }
+      on(event, JSON.parse(data));
+    }
+  }
+}
+
+$("send").addEventListener("submit", async (e) => {
+  e.preventDefault();
+  const message = $("message").value.trim();
+  if (!message) return;
+  $("message").value = "";
+  addMessage("user", message);
+  const reply = addMessage("character", "…");
+  let text = "";
+  try {
+    const response = await fetch("/chat/stream", { method: "POST", headers: headers(), body: JSON.stringify({ message, user }) });
+    if (!response.ok) {
+      const json = await response.json();
+      throw new Error(json.text || json.error);
+    }
+    await readEvents(response, (event, data) => {
+      if (event === "chunk") {
+        text += data;
+        reply.textContent = text;
+      } else if (event === "reply") {
+        reply.textContent = data.text;
+        if (data.sources.length) {
+          const sources = document.createElement("div");
+          sources.className = "sources";
+          for (const url of data.sources) {
+            const link = document.createElement("a");
+            link.href = url;
+            link.target = "_blank";
+            link.textContent = url;
+            sources.append(link, document.createElement("br"));
+          }
+          reply.appendChild(sources);
+        }
+      } else if (event === "error") {
+        throw new Error(data.error);
+      }
+      $("messages").scrollTop = $("messages").scrollHeight;
+    });
+  } catch (e) {
+    reply.remove();
+    addMessage("error", e.message);
+  }
+});
+
+$("search").addEventListener("submit", async (e) => {
+  e.preventDefault();
+  const query = encodeURIComponent($("query").value.trim());
+  $("facts").replaceChildren();
+  try {
+    const facts = await api("GET", "/knowledge?limit=100&q=" + query);
+    for (const fact of facts) {
+      const li = document.createElement("li");
+      const tags = document.createElement("div");
+      tags.className = "tags";
+      tags.textContent = fact.tags.join(", ") + (fact.source_url ? " · " + fact.source_url : "");
+      li.append(fact.text, tags);
+      $("facts").appendChild(li);
+    }
+    if (!facts.length) $("facts").textContent = "Nothing found.";
+  } catch (e) {
+    $("facts").textContent = e.message;
+  }
+});
+
+$("forget").addEventListener("click", async () => {
+  if (!confirm("Forget everything the character remembers about you?")) return;
+  try {
+    const result = await api("DELETE", "/memories?user=" + encodeURIComponent(user));
+    alert("Forgot " + result.forgotten + " memories.");
+    $("messages").replaceChildren();
+  } catch (e) {
+    alert(e.message);
+  }
+});
+
+loadCharacter();